@@ -0,0 +1,578 @@
+use klumo_engine::check_syntax;
+use oxc_allocator::Allocator;
+use oxc_parser::Parser;
+use oxc_span::{GetSpan, SourceType};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Passes over the bundle; removing one declaration can leave another unused.
+const MAX_PASSES: usize = 8;
+
+const REGEX_KEYWORDS: &[&str] = &[
+    "return", "typeof", "case", "do", "else", "in", "of", "new", "delete", "void", "throw",
+    "yield", "await",
+];
+
+/// What `fix_bundle` changed.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct BundleFixes {
+    /// Top-level declarations and import bindings removed because nothing referenced them.
+    pub(crate) removed: Vec<String>,
+    /// Import statements dropped as exact duplicates or merged into an earlier import of the
+    /// same module.
+    pub(crate) merged_imports: usize,
+}
+
+impl BundleFixes {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.merged_imports == 0
+    }
+}
+
+/// Lint auto-fixes for a generated bundle: duplicate imports are merged, and top-level
+/// functions, side-effect-free `const`/`let`/`var` declarations and import bindings that
+/// nothing in the bundle refers to are removed, each by its parsed statement span so code
+/// sharing its line stays. Any mention of a name, even in a string, keeps its declaration,
+/// and an import left without bindings stays as `import "module";` so the module still
+/// loads. Output that no longer parses is discarded for the original.
+pub(crate) fn fix_bundle(javascript: &str) -> (String, BundleFixes) {
+    let mut fixes = BundleFixes::default();
+    let mut fixed = javascript.to_string();
+    for _ in 0..MAX_PASSES {
+        match fix_pass(&fixed, &mut fixes) {
+            Some(next) => fixed = next,
+            None => break,
+        }
+    }
+    if fixes.is_empty() || (!check_syntax(&fixed).is_empty() && check_syntax(javascript).is_empty())
+    {
+        return (javascript.to_string(), BundleFixes::default());
+    }
+    (fixed, fixes)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Code,
+    /// Strings, template literals and regular expressions.
+    Literal,
+    Comment,
+}
+
+/// Classifies every byte of `source`. Template substitutions (`${...}`) are code.
+fn classify(source: &str) -> Vec<Class> {
+    let bytes = source.as_bytes();
+    let mut classes = vec![Class::Code; bytes.len()];
+    // Brace depth at each open template substitution.
+    let mut templates: Vec<usize> = Vec::new();
+    let mut depth = 0usize;
+    let mut at = 0;
+    while at < bytes.len() {
+        let next = bytes.get(at + 1).copied();
+        let end = match bytes[at] {
+            b'/' if next == Some(b'/') => {
+                let end = find_from(bytes, at, b"\n").unwrap_or(bytes.len());
+                mark(&mut classes, at, end, Class::Comment);
+                end
+            }
+            b'/' if next == Some(b'*') => {
+                let end = find_from(bytes, at + 2, b"*/").map_or(bytes.len(), |end| end + 2);
+                mark(&mut classes, at, end, Class::Comment);
+                end
+            }
+            quote @ (b'\'' | b'"') => {
+                let end = scan_string(bytes, at, quote);
+                mark(&mut classes, at, end, Class::Literal);
+                end
+            }
+            b'`' => scan_template(bytes, at + 1, &mut classes, &mut templates, depth, at),
+            b'/' if regex_allowed(bytes, &classes, at) => {
+                let end = scan_regex(bytes, at);
+                mark(&mut classes, at, end, Class::Literal);
+                end
+            }
+            b'{' => {
+                depth += 1;
+                at + 1
+            }
+            b'}' if templates.last() == Some(&depth) => {
+                templates.pop();
+                scan_template(bytes, at + 1, &mut classes, &mut templates, depth, at)
+            }
+            b'}' => {
+                depth = depth.saturating_sub(1);
+                at + 1
+            }
+            _ => at + 1,
+        };
+        at = end.max(at + 1);
+    }
+    classes
+}
+
+fn mark(classes: &mut [Class], start: usize, end: usize, class: Class) {
+    let end = end.min(classes.len());
+    for slot in &mut classes[start..end] {
+        *slot = class;
+    }
+}
+
+fn find_from(bytes: &[u8], from: usize, needle: &[u8]) -> Option<usize> {
+    bytes
+        .get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|position| from + position)
+}
+
+fn scan_string(bytes: &[u8], start: usize, quote: u8) -> usize {
+    let mut at = start + 1;
+    while at < bytes.len() {
+        match bytes[at] {
+            b'\\' => at += 2,
+            b'\n' => return at,
+            byte if byte == quote => return at + 1,
+            _ => at += 1,
+        }
+    }
+    bytes.len()
+}
+
+/// Marks template text from `from` (just after a backtick or a substitution's closing brace)
+/// up to and including the closing backtick, or up to a `${`, which opens a substitution.
+fn scan_template(
+    bytes: &[u8],
+    from: usize,
+    classes: &mut [Class],
+    templates: &mut Vec<usize>,
+    depth: usize,
+    opener: usize,
+) -> usize {
+    let mut at = from;
+    while at < bytes.len() {
+        match bytes[at] {
+            b'\\' => at += 2,
+            b'`' => {
+                mark(classes, opener, at + 1, Class::Literal);
+                return at + 1;
+            }
+            b'$' if bytes.get(at + 1) == Some(&b'{') => {
+                mark(classes, opener, at + 2, Class::Literal);
+                templates.push(depth);
+                return at + 2;
+            }
+            _ => at += 1,
+        }
+    }
+    mark(classes, opener, bytes.len(), Class::Literal);
+    bytes.len()
+}
+
+/// A `/` starts a regular expression where an expression may begin, e.g. after `(`, `=` or
+/// `return`, and is division after a value.
+fn regex_allowed(bytes: &[u8], classes: &[Class], at: usize) -> bool {
+    let Some(prev) = (0..at)
+        .rev()
+        .find(|&index| classes[index] != Class::Comment && !bytes[index].is_ascii_whitespace())
+    else {
+        return true;
+    };
+    let byte = bytes[prev];
+    if classes[prev] == Class::Literal {
+        return false;
+    }
+    if is_word_byte(byte) {
+        let start = (0..=prev)
+            .rev()
+            .take_while(|&index| is_word_byte(bytes[index]))
+            .last()
+            .unwrap_or(prev);
+        let word = std::str::from_utf8(&bytes[start..=prev]).unwrap_or_default();
+        return REGEX_KEYWORDS.contains(&word);
+    }
+    !matches!(byte, b')' | b']' | b'}')
+}
+
+fn scan_regex(bytes: &[u8], start: usize) -> usize {
+    let mut at = start + 1;
+    let mut in_class = false;
+    while at < bytes.len() {
+        match bytes[at] {
+            b'\\' => at += 2,
+            b'\n' => return start + 1,
+            b'[' => {
+                in_class = true;
+                at += 1;
+            }
+            b']' => {
+                in_class = false;
+                at += 1;
+            }
+            b'/' if !in_class => {
+                at += 1;
+                while at < bytes.len() && bytes[at].is_ascii_alphabetic() {
+                    at += 1;
+                }
+                return at;
+            }
+            _ => at += 1,
+        }
+    }
+    start + 1
+}
+
+fn is_word_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'$' || !byte.is_ascii()
+}
+
+fn is_identifier(word: &str) -> bool {
+    !word.is_empty() && !word.as_bytes()[0].is_ascii_digit() && word.bytes().all(is_word_byte)
+}
+
+/// Occurrences of every word outside comments. Words in strings count too, so a name that
+/// is only looked up by string is still treated as used.
+fn word_counts<'a>(source: &'a str, classes: &[Class]) -> HashMap<&'a str, usize> {
+    let bytes = source.as_bytes();
+    let mut counts = HashMap::new();
+    let mut at = 0;
+    while at < bytes.len() {
+        if classes[at] == Class::Comment || !is_word_byte(bytes[at]) {
+            at += 1;
+            continue;
+        }
+        let start = at;
+        while at < bytes.len() && classes[at] != Class::Comment && is_word_byte(bytes[at]) {
+            at += 1;
+        }
+        *counts.entry(&source[start..at]).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Byte ranges of the top-level statements, as the parser spans them; `None` when the
+/// bundle does not parse.
+fn top_level_statements(source: &str) -> Option<Vec<(usize, usize)>> {
+    let allocator = Allocator::default();
+    let parsed = Parser::new(&allocator, source, SourceType::unambiguous()).parse();
+    if parsed.panicked || !parsed.diagnostics.is_empty() {
+        return None;
+    }
+    Some(
+        parsed
+            .program
+            .body
+            .iter()
+            .map(|statement| {
+                let span = statement.span();
+                (span.start as usize, span.end as usize)
+            })
+            .collect(),
+    )
+}
+
+/// The range to delete for the statement at `start..end`: the statement with the blanks
+/// after it, and its whole line when nothing else is on it. Code sharing the line stays.
+fn removal_range(source: &str, start: usize, end: usize) -> (usize, usize) {
+    let bytes = source.as_bytes();
+    let mut after = end;
+    while after < bytes.len() && matches!(bytes[after], b' ' | b'\t') {
+        after += 1;
+    }
+    let line_start = source[..start].rfind('\n').map_or(0, |at| at + 1);
+    let alone_before = source[line_start..start].trim().is_empty();
+    match bytes.get(after) {
+        None | Some(b'\n') if alone_before => (line_start, (after + 1).min(bytes.len())),
+        None | Some(b'\n') => {
+            let before = source[..start].trim_end_matches([' ', '\t']).len();
+            (before, after)
+        }
+        _ => (start, after),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ImportDecl {
+    /// The module specifier as written, quotes included.
+    module: String,
+    default: Option<String>,
+    namespace: Option<String>,
+    /// Named imports as written (`a`, `b as c`) with their local names.
+    named: Vec<(String, String)>,
+}
+
+impl ImportDecl {
+    fn parse(statement: &str) -> Option<Self> {
+        let rest = statement.strip_prefix("import")?;
+        if !rest.starts_with(char::is_whitespace) && !rest.starts_with('{') {
+            return None;
+        }
+        let rest = rest.trim().trim_end_matches(';').trim_end();
+        let (clause, module) = rest.rsplit_once(" from ")?;
+        let module = module.trim();
+        let quoted = module.len() >= 2
+            && (module.starts_with('"') && module.ends_with('"')
+                || module.starts_with('\'') && module.ends_with('\''));
+        if !quoted {
+            return None;
+        }
+        let mut decl = Self {
+            module: module.to_string(),
+            default: None,
+            namespace: None,
+            named: Vec::new(),
+        };
+        let mut clause = clause.trim();
+        if let Some(open) = clause.find('{') {
+            let close = clause.rfind('}')?;
+            if clause[close + 1..].trim() != "" {
+                return None;
+            }
+            for item in clause[open + 1..close].split(',') {
+                let item = item.split_whitespace().collect::<Vec<_>>().join(" ");
+                if item.is_empty() {
+                    continue;
+                }
+                let local = item.rsplit(' ').next().unwrap_or_default().to_string();
+                if !is_identifier(&local) {
+                    return None;
+                }
+                decl.named.push((item, local));
+            }
+            clause = clause[..open].trim().trim_end_matches(',').trim();
+        }
+        for part in clause.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            if let Some(namespace) = part.strip_prefix('*') {
+                let name = namespace.trim().strip_prefix("as")?.trim();
+                if !is_identifier(name) {
+                    return None;
+                }
+                decl.namespace = Some(name.to_string());
+            } else if is_identifier(part) && decl.default.is_none() {
+                decl.default = Some(part.to_string());
+            } else {
+                return None;
+            }
+        }
+        Some(decl)
+    }
+
+    fn is_named_only(&self) -> bool {
+        self.default.is_none() && self.namespace.is_none()
+    }
+
+    fn locals(&self) -> impl Iterator<Item = &str> {
+        self.default
+            .iter()
+            .chain(self.namespace.iter())
+            .map(String::as_str)
+            .chain(self.named.iter().map(|(_, local)| local.as_str()))
+    }
+
+    fn render(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(default) = &self.default {
+            parts.push(default.clone());
+        }
+        if let Some(namespace) = &self.namespace {
+            parts.push(format!("* as {namespace}"));
+        }
+        if !self.named.is_empty() {
+            let named: Vec<&str> = self.named.iter().map(|(item, _)| item.as_str()).collect();
+            parts.push(format!("{{ {} }}", named.join(", ")));
+        }
+        if parts.is_empty() {
+            format!("import {};", self.module)
+        } else {
+            format!("import {} from {};", parts.join(", "), self.module)
+        }
+    }
+}
+
+/// The name a removable declaration binds: a function declaration, or a single `const`,
+/// `let` or `var` binding whose initializer has no side effects (a literal or a function).
+fn removable_declaration(statement: &str) -> Option<&str> {
+    let statement = statement.trim_end_matches(';').trim_end();
+    let function = statement
+        .strip_prefix("async function")
+        .or_else(|| statement.strip_prefix("function"));
+    if let Some(rest) = function {
+        let rest = rest.trim_start_matches('*').trim_start();
+        let name_end = rest.find('(')?;
+        let name = rest[..name_end].trim();
+        return (is_identifier(name) && statement.ends_with('}')).then_some(name);
+    }
+    let rest = ["const ", "let ", "var "]
+        .iter()
+        .find_map(|keyword| statement.strip_prefix(keyword))?;
+    let (name, init) = rest.split_once('=')?;
+    let name = name.trim();
+    let init = init.trim();
+    if init.starts_with(['=', '>']) || !is_identifier(name) {
+        return None;
+    }
+    side_effect_free(init).then_some(name)
+}
+
+fn side_effect_free(init: &str) -> bool {
+    if matches!(init, "true" | "false" | "null" | "undefined") || is_number(init) {
+        return true;
+    }
+    if let Some(quote @ ('"' | '\'')) = init.chars().next() {
+        let bytes = init.as_bytes();
+        return init.len() >= 2 && scan_string(bytes, 0, quote as u8) == bytes.len();
+    }
+    let init = init.strip_prefix("async").map_or(init, str::trim_start);
+    if init.starts_with("function") {
+        return init.ends_with('}');
+    }
+    if let Some((params, _)) = init.split_once("=>") {
+        let params = params.trim();
+        return is_identifier(params) || balanced_parens(params);
+    }
+    false
+}
+
+fn is_number(text: &str) -> bool {
+    let digits = text.strip_prefix('-').unwrap_or(text);
+    digits.starts_with(|c: char| c.is_ascii_digit())
+        && digits
+            .chars()
+            .all(|c| c.is_ascii_hexdigit() || matches!(c, '.' | '_' | 'x' | 'X' | 'o' | 'n'))
+}
+
+/// Whether `text` is exactly one parenthesised group, as arrow function parameters are.
+fn balanced_parens(text: &str) -> bool {
+    if !text.starts_with('(') || !text.ends_with(')') {
+        return false;
+    }
+    let mut depth = 0usize;
+    for (at, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 && at + 1 != text.len() {
+                    return false;
+                }
+            }
+            _ => {}
+        }
+    }
+    depth == 0
+}
+
+/// Whether a comma separates bindings at the top of a statement, as in `let a = 1, b = f()`.
+fn declares_several(statement: &str, classes: &[Class]) -> bool {
+    let mut depth = 0usize;
+    for (byte, class) in statement.bytes().zip(classes) {
+        if *class != Class::Code {
+            continue;
+        }
+        match byte {
+            b'{' | b'[' | b'(' => depth += 1,
+            b'}' | b']' | b')' => depth = depth.saturating_sub(1),
+            b',' if depth == 0 => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Whether `line` continues the statement before it, so that statement cannot be dropped.
+fn continues_statement(line: &str) -> bool {
+    line.trim_start().starts_with([
+        '+', '-', '*', '/', '%', '.', ',', '?', ':', '&', '|', '^', '=', '<', '>', '[', '(', '`',
+    ])
+}
+
+enum Edit {
+    Remove,
+    Replace(String),
+}
+
+/// One round of fixes; `None` when nothing changed.
+fn fix_pass(source: &str, fixes: &mut BundleFixes) -> Option<String> {
+    let chunks = top_level_statements(source)?;
+    let classes = classify(source);
+    let counts = word_counts(source, &classes);
+    let mut edits: BTreeMap<usize, Edit> = BTreeMap::new();
+    let mut seen_imports = HashSet::new();
+    // First named-only import of each module: chunk index and the merged declaration.
+    let mut named_imports: HashMap<String, (usize, ImportDecl)> = HashMap::new();
+
+    for (index, &(start, end)) in chunks.iter().enumerate() {
+        let statement = source[start..end].trim();
+        if let Some(mut decl) = ImportDecl::parse(statement) {
+            let normalized = statement.split_whitespace().collect::<Vec<_>>().join(" ");
+            if !seen_imports.insert(normalized) {
+                edits.insert(index, Edit::Remove);
+                fixes.merged_imports += 1;
+                continue;
+            }
+            let unused: Vec<String> = decl
+                .locals()
+                .filter(|local| counts.get(local).copied().unwrap_or(0) <= 1)
+                .map(str::to_string)
+                .collect();
+            if decl.is_named_only()
+                && !decl.named.is_empty()
+                && let Some((first, merged)) = named_imports.get_mut(&decl.module)
+            {
+                for (item, local) in decl.named {
+                    if !merged.named.iter().any(|(_, known)| *known == local) {
+                        merged.named.push((item, local));
+                    }
+                }
+                edits.insert(*first, Edit::Replace(merged.render()));
+                edits.insert(index, Edit::Remove);
+                fixes.merged_imports += 1;
+                continue;
+            }
+            if !unused.is_empty() {
+                decl.default = decl.default.filter(|name| !unused.contains(name));
+                decl.namespace = decl.namespace.filter(|name| !unused.contains(name));
+                decl.named.retain(|(_, local)| !unused.contains(local));
+                fixes.removed.extend(unused);
+                edits.insert(index, Edit::Replace(decl.render()));
+            }
+            if decl.is_named_only() && !decl.named.is_empty() {
+                named_imports.insert(decl.module.clone(), (index, decl));
+            }
+            continue;
+        }
+        let Some(name) = removable_declaration(statement) else {
+            continue;
+        };
+        if declares_several(&source[start..end], &classes[start..end]) {
+            continue;
+        }
+        // Without the declaration in between, automatic semicolon insertion could join the
+        // statements around it.
+        let continued = chunks
+            .get(index + 1)
+            .is_some_and(|&(next, next_end)| continues_statement(&source[next..next_end]));
+        if counts.get(name).copied().unwrap_or(0) == 1 && !continued {
+            edits.insert(index, Edit::Remove);
+            fixes.removed.push(name.to_string());
+        }
+    }
+
+    if edits.is_empty() {
+        return None;
+    }
+    let mut fixed = String::with_capacity(source.len());
+    let mut copied = 0;
+    for (index, edit) in edits {
+        let (start, end) = chunks[index];
+        let (start, end) = match &edit {
+            Edit::Remove => removal_range(source, start, end),
+            Edit::Replace(_) => (start, end),
+        };
+        fixed.push_str(&source[copied..start.max(copied)]);
+        if let Edit::Replace(text) = edit {
+            fixed.push_str(&text);
+        }
+        copied = copied.max(end);
+    }
+    fixed.push_str(&source[copied..]);
+    Some(fixed)
+}
@@ -0,0 +1,208 @@
+use anyhow::{Context, Result, anyhow, bail};
+use klumo_compiler::{ModuleGraph, PROMPT_VERSION, utc_timestamp};
+use klumo_core::STDIN_SOURCE_ID;
+use klumo_llm::estimate_tokens;
+use serde_json::{Value as JsonValue, json};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+/// `dist/app.js` -> `dist/app.js.klumo-meta.json`.
+pub(crate) fn meta_path(bundle: &Path) -> PathBuf {
+    let mut name = bundle.as_os_str().to_owned();
+    name.push(".klumo-meta.json");
+    PathBuf::from(name)
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Records what `bundle` was built from next to it: a hash of every source in `graph` and of
+/// the bundle itself, who translated each source under which prompt version, and an estimate
+/// of the tokens the translations this build paid for used (cache hits cost none). Providers'
+/// own usage counts are not recorded, so the estimate comes from the text sizes. `stdin_source`
+/// is the text of a module read from stdin, which cannot be read again.
+pub(crate) fn write_meta(
+    bundle: &Path,
+    javascript: &str,
+    graph: &ModuleGraph,
+    stdin_source: Option<&str>,
+    built_at: SystemTime,
+) -> Result<PathBuf> {
+    let dir = bundle_dir(bundle);
+    let (mut spent_input, mut spent_output) = (0, 0);
+    let mut modules = Vec::new();
+    for module in &graph.modules {
+        let (source, recorded_path) = match stdin_source {
+            Some(text) if module.path == Path::new(STDIN_SOURCE_ID) => {
+                (text.as_bytes().to_vec(), module.path.clone())
+            }
+            _ => (
+                fs::read(&module.path)
+                    .with_context(|| format!("failed reading {}", module.path.display()))?,
+                relative_to(&dir, &module.path),
+            ),
+        };
+        let metadata = &module.compile.metadata;
+        let tokens = metadata.provider.as_ref().map(|_| {
+            let input = estimate_tokens(&String::from_utf8_lossy(&source));
+            let output = estimate_tokens(&module.compile.javascript);
+            if !metadata.cache_hit {
+                spent_input += input;
+                spent_output += output;
+            }
+            json!({ "input": input, "output": output })
+        });
+        modules.push(json!({
+            "source": recorded_path.to_string_lossy(),
+            "source_sha256": sha256_hex(&source),
+            "source_modified": fs::metadata(&module.path)
+                .and_then(|stat| stat.modified())
+                .ok()
+                .map(utc_timestamp),
+            "provider": metadata.provider.as_ref().map(ToString::to_string),
+            "model": metadata.model,
+            "served_model": metadata.served_model,
+            "prompt_version": metadata.prompt_version,
+            "cache_hit": metadata.cache_hit,
+            "similarity": metadata.similarity,
+            "estimated_tokens": tokens,
+        }));
+    }
+    let meta = json!({
+        "klumo_version": env!("CARGO_PKG_VERSION"),
+        "built_at": utc_timestamp(built_at),
+        "bundle_sha256": sha256_hex(javascript.as_bytes()),
+        "modules": modules,
+        "estimated_tokens_spent": { "input": spent_input, "output": spent_output },
+    });
+    let path = meta_path(bundle);
+    fs::write(&path, format!("{}\n", serde_json::to_string_pretty(&meta)?))
+        .with_context(|| format!("failed writing bundle metadata {}", path.display()))?;
+    Ok(path)
+}
+
+/// `klumo verify`: hashes the bundle and every source its metadata lists again, printing one
+/// line per file, and fails when anything changed since the build or the sources would now
+/// be translated under a different prompt version.
+pub(crate) fn verify_command(bundle: &Path) -> Result<()> {
+    let path = meta_path(bundle);
+    let raw = fs::read_to_string(&path).with_context(|| {
+        format!(
+            "failed reading {} (bundles record it when built with `klumo bundle --output`)",
+            path.display()
+        )
+    })?;
+    let meta: JsonValue = serde_json::from_str(&raw)
+        .with_context(|| format!("failed parsing bundle metadata {}", path.display()))?;
+    let field = |value: &JsonValue, name: &str| -> Result<String> {
+        value[name]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("bundle metadata {} has no {name}", path.display()))
+    };
+
+    let mut stale = 0;
+    let bundle_hash = fs::read(bundle)
+        .map(|bytes| sha256_hex(&bytes))
+        .with_context(|| format!("failed reading bundle {}", bundle.display()))?;
+    if bundle_hash == field(&meta, "bundle_sha256")? {
+        println!("ok       {}", bundle.display());
+    } else {
+        stale += 1;
+        println!("edited   {} (changed since it was built)", bundle.display());
+    }
+
+    let dir = bundle_dir(bundle);
+    let modules = meta["modules"]
+        .as_array()
+        .ok_or_else(|| anyhow!("bundle metadata {} has no modules", path.display()))?;
+    for module in modules {
+        let source = field(module, "source")?;
+        if source == STDIN_SOURCE_ID {
+            println!("skipped  {source} (read from stdin)");
+            continue;
+        }
+        let prompt_version = field(module, "prompt_version")?;
+        let source_path = lexically_normal(&dir.join(&source));
+        match fs::read(&source_path) {
+            Err(_) => {
+                stale += 1;
+                println!("missing  {}", source_path.display());
+            }
+            Ok(bytes) if sha256_hex(&bytes) != field(module, "source_sha256")? => {
+                stale += 1;
+                println!("changed  {}", source_path.display());
+            }
+            Ok(_) if module["provider"].is_string() && prompt_version != PROMPT_VERSION => {
+                stale += 1;
+                println!(
+                    "outdated {} (translated with prompt {prompt_version}, now {PROMPT_VERSION})",
+                    source_path.display()
+                );
+            }
+            Ok(_) => println!("ok       {}", source_path.display()),
+        }
+    }
+    if stale > 0 {
+        bail!(
+            "{} is stale ({stale} of its files changed); rebuild it with `klumo bundle`",
+            bundle.display()
+        );
+    }
+    println!(
+        "{} is up to date ({} sources)",
+        bundle.display(),
+        modules.len()
+    );
+    Ok(())
+}
+
+/// The directory sources are recorded relative to.
+fn bundle_dir(bundle: &Path) -> PathBuf {
+    match bundle.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// `path` with `..` components folded into the directories before them, e.g. `dist/../src/a.js`
+/// -> `src/a.js`, for printing a source recorded relative to the bundle.
+fn lexically_normal(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(normal.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                normal.pop();
+            }
+            component => normal.push(component),
+        }
+    }
+    if normal.as_os_str().is_empty() {
+        normal.push(".");
+    }
+    normal
+}
+
+/// `path` relative to `dir`, so metadata stays valid when the project moves; either one
+/// failing to resolve keeps `path` as given.
+fn relative_to(dir: &Path, path: &Path) -> PathBuf {
+    let (Ok(dir), Ok(path)) = (fs::canonicalize(dir), fs::canonicalize(path)) else {
+        return path.to_path_buf();
+    };
+    let (dir, target): (Vec<_>, Vec<_>) = (dir.components().collect(), path.components().collect());
+    let shared = dir.iter().zip(&target).take_while(|(a, b)| a == b).count();
+    if shared == 0 {
+        return path;
+    }
+    dir[shared..]
+        .iter()
+        .map(|_| Component::ParentDir)
+        .chain(target[shared..].iter().copied())
+        .collect()
+}
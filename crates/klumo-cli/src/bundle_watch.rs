@@ -0,0 +1,192 @@
+use crate::config_reload::{ConfigReloader, FileStamp, stamp};
+use anyhow::{Context, Result, anyhow};
+use klumo_config::{CliRunOverrides, RunDefaults};
+use klumo_core::ProgressMode;
+use notify_debouncer_mini::notify::RecursiveMode;
+use notify_debouncer_mini::{DebounceEventResult, new_debouncer};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// File events arriving this close together, such as an editor's write-then-rename save,
+/// wake the watcher once.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Settings resolved again from an edited `klumo.json`, with the command-line flags they were
+/// resolved from.
+pub(crate) type Reloaded = (RunDefaults, CliRunOverrides);
+
+/// What started a rebuild.
+enum Trigger {
+    File(PathBuf),
+    Config(PathBuf),
+}
+
+/// The files of one build and their stamps when it started.
+#[derive(Debug)]
+pub(crate) struct WatchedFiles {
+    stamps: Vec<(PathBuf, Option<FileStamp>)>,
+}
+
+impl WatchedFiles {
+    pub(crate) fn new(paths: Vec<PathBuf>) -> Self {
+        Self {
+            stamps: paths
+                .into_iter()
+                .map(|path| {
+                    let stamp = stamp(&path);
+                    (path, stamp)
+                })
+                .collect(),
+        }
+    }
+
+    /// Watches `paths` instead, keeping the snapshot of files already watched so an edit
+    /// made while a build ran is not missed.
+    pub(crate) fn retarget(self, paths: Vec<PathBuf>) -> Self {
+        let mut previous = self.stamps;
+        Self {
+            stamps: paths
+                .into_iter()
+                .map(|path| {
+                    let stamp = match previous.iter().position(|(known, _)| *known == path) {
+                        Some(index) => previous.swap_remove(index).1,
+                        None => stamp(&path),
+                    };
+                    (path, stamp)
+                })
+                .collect(),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.stamps.len()
+    }
+
+    /// The directories holding the watched files. Editors often save by replacing a file, so
+    /// its directory is watched rather than the file itself.
+    fn directories(&self) -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = Vec::new();
+        for (path, _) in &self.stamps {
+            if let Some(dir) = parent_dir(path)
+                && !dirs.contains(&dir)
+            {
+                dirs.push(dir);
+            }
+        }
+        dirs
+    }
+
+    /// The first file edited, created or removed since the snapshot.
+    pub(crate) fn changed(&self) -> Option<&Path> {
+        self.stamps
+            .iter()
+            .find(|(path, before)| stamp(path) != *before)
+            .map(|(path, _)| path.as_path())
+    }
+}
+
+/// Runs `build`, then again whenever a file it was built from or the configuration `reloader`
+/// watches changes, until the process is interrupted. `build` returns those files (the module
+/// graph) and is handed the reloaded settings when the configuration changed. A failed build
+/// is reported and the files of the last good build, or `entry`, stay watched; a
+/// configuration that fails to load is reported and the previous one stays in effect. Every
+/// build reuses the compile cache, so only edited modules are translated again.
+pub(crate) fn watch<F>(
+    entry: &Path,
+    progress: ProgressMode,
+    mut reloader: ConfigReloader,
+    mut build: F,
+) -> Result<()>
+where
+    F: FnMut(Option<Reloaded>) -> Result<Vec<PathBuf>>,
+{
+    let mut paths = vec![entry.to_path_buf()];
+    let mut trigger: Option<Trigger> = None;
+    let mut reloaded: Option<Reloaded> = None;
+    loop {
+        let snapshot = WatchedFiles::new(paths.clone());
+        let started = Instant::now();
+        let result = build(reloaded.take());
+        let elapsed = started.elapsed().as_millis();
+        match result {
+            Ok(built) => {
+                paths = built;
+                if !matches!(progress, ProgressMode::Silent) {
+                    match &trigger {
+                        Some(Trigger::File(path)) => eprintln!(
+                            "[klumo] {} changed; rebuilt in {elapsed} ms",
+                            path.display()
+                        ),
+                        Some(Trigger::Config(path)) => eprintln!(
+                            "[klumo] {} changed; reloaded configuration and rebuilt in {elapsed} ms",
+                            path.display()
+                        ),
+                        None => eprintln!("[klumo] built in {elapsed} ms"),
+                    }
+                }
+            }
+            Err(err) => eprintln!("[klumo] bundle failed after {elapsed} ms: {err:#}"),
+        }
+        let watched = snapshot.retarget(paths.clone());
+        if !matches!(progress, ProgressMode::Silent) {
+            let files = if watched.len() == 1 { "file" } else { "files" };
+            eprintln!("[klumo] watching {} {files} for changes", watched.len());
+        }
+        trigger = Some(wait_for_change(&watched, &mut reloader, &mut reloaded)?);
+    }
+}
+
+/// Blocks until a watched file or the configuration changes. File events only wake it up;
+/// stamps decide what changed, so events for other files in the same directories are ignored
+/// and an edit made while the last build ran is seen at once.
+fn wait_for_change(
+    watched: &WatchedFiles,
+    reloader: &mut ConfigReloader,
+    reloaded: &mut Option<Reloaded>,
+) -> Result<Trigger> {
+    let (sender, events) = mpsc::channel::<DebounceEventResult>();
+    let mut debouncer =
+        new_debouncer(DEBOUNCE, sender).context("failed to start watching for file changes")?;
+    let mut dirs = watched.directories();
+    if let Some(dir) = parent_dir(reloader.path())
+        && !dirs.contains(&dir)
+    {
+        dirs.push(dir);
+    }
+    for dir in dirs.iter().filter(|dir| dir.is_dir()) {
+        debouncer
+            .watcher()
+            .watch(dir, RecursiveMode::NonRecursive)
+            .with_context(|| format!("failed to watch {}", dir.display()))?;
+    }
+    loop {
+        match reloader.poll() {
+            Some(Ok(settings)) => {
+                *reloaded = Some(settings);
+                return Ok(Trigger::Config(reloader.path().to_path_buf()));
+            }
+            Some(Err(err)) => eprintln!(
+                "[klumo] warning: {} changed but could not be loaded; keeping the previous configuration: {err:#}",
+                reloader.path().display()
+            ),
+            None => {}
+        }
+        if let Some(path) = watched.changed() {
+            return Ok(Trigger::File(path.to_path_buf()));
+        }
+        match events.recv() {
+            Ok(Ok(_)) => {}
+            Ok(Err(err)) => eprintln!("[klumo] warning: watching for file changes: {err}"),
+            Err(_) => return Err(anyhow!("the file watcher stopped")),
+        }
+    }
+}
+
+/// The absolute directory `path` sits in.
+fn parent_dir(path: &Path) -> Option<PathBuf> {
+    std::path::absolute(path)
+        .ok()?
+        .parent()
+        .map(Path::to_path_buf)
+}
@@ -1,9 +1,15 @@
-use anyhow::{Context, Result};
+use crate::Cli;
+use anyhow::{Context, Result, bail};
+use clap::parser::ValueSource;
+use clap::{ArgAction, CommandFactory};
 use klumo_config::{FileConfig, load_file_config};
+use serde_json::Value as JsonValue;
 use std::ffi::OsString;
+use std::path::PathBuf;
 
 const PREDEFINED_COMMANDS: &[&str] = &[
-    "run", "bundle", "install", "i", "lint", "fmt", "test", "eval", "repl",
+    "run", "bundle", "install", "i", "lint", "fmt", "test", "eval", "repl", "models", "migrate",
+    "init", "graph", "explain", "stats", "doctor", "keygen", "review", "cache", "verify",
 ];
 
 pub(crate) fn normalize_cli_args<I>(args: I) -> Vec<OsString>
@@ -26,6 +32,87 @@ where
     normalized
 }
 
+/// Fills the flags of `defaults.<command>` in `klumo.json` that are not run settings (those
+/// merge when settings are resolved) into `args`, right after the command name, unless the
+/// command line already passes them. Names are checked against the command's own arguments,
+/// so every flag it takes can have a default and a typo fails instead of being ignored.
+/// Missing required flags are left for the real parse to report, since a default may be what
+/// provides them; so are arguments clap cannot read at all.
+pub(crate) fn apply_flag_defaults(args: Vec<OsString>) -> Result<Vec<OsString>> {
+    let cli = Cli::command();
+    let Ok(matches) = cli.clone().ignore_errors(true).try_get_matches_from(&args) else {
+        return Ok(args);
+    };
+    let Some((name, command_matches)) = matches.subcommand() else {
+        return Ok(args);
+    };
+    let config = command_matches
+        .try_get_one::<PathBuf>("config")
+        .ok()
+        .flatten();
+    let cwd = std::env::current_dir().context("failed resolving current directory")?;
+    let Some(cfg) = load_file_config(config.map(PathBuf::as_path), &cwd)? else {
+        return Ok(args);
+    };
+    let Some(defaults) = cfg.command_defaults(name) else {
+        return Ok(args);
+    };
+    let command = cli
+        .find_subcommand(name)
+        .expect("matched subcommands are defined");
+
+    let mut filled = Vec::new();
+    for (key, value) in &defaults.flags {
+        let Some((arg, long)) = command
+            .get_arguments()
+            .find(|arg| arg.get_id() == key.as_str())
+            .and_then(|arg| Some((arg, arg.get_long()?)))
+        else {
+            bail!(
+                "defaults.{name}.{key} in klumo.json: `klumo {name}` has no --{} flag",
+                key.replace('_', "-")
+            );
+        };
+        if command_matches.value_source(key) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+        let values = match (arg.get_action(), value) {
+            (_, JsonValue::Null) => Vec::new(),
+            (ArgAction::SetTrue, JsonValue::Bool(set)) => {
+                if *set {
+                    filled.push(OsString::from(format!("--{long}")));
+                }
+                continue;
+            }
+            (ArgAction::SetTrue, _) => {
+                bail!("defaults.{name}.{key} in klumo.json must be true or false")
+            }
+            (ArgAction::Append, JsonValue::Array(items)) => items.iter().collect(),
+            (ArgAction::Set | ArgAction::Append, value) => vec![value],
+            _ => bail!("defaults.{name}.{key} in klumo.json: --{long} takes no default"),
+        };
+        for value in values {
+            let text = match value {
+                JsonValue::String(text) => text.clone(),
+                JsonValue::Bool(_) | JsonValue::Number(_) => value.to_string(),
+                _ => {
+                    bail!("defaults.{name}.{key} in klumo.json must be a string, number or boolean")
+                }
+            };
+            filled.push(OsString::from(format!("--{long}={text}")));
+        }
+    }
+
+    let mut args = args;
+    let at = args
+        .iter()
+        .skip(1)
+        .position(|arg| !arg.to_string_lossy().starts_with('-'))
+        .map_or(args.len(), |index| index + 2);
+    args.splice(at..at, filled);
+    Ok(args)
+}
+
 pub(crate) fn predefined_script_collisions(cfg: &FileConfig) -> Vec<String> {
     let Some(scripts) = cfg.scripts.as_ref() else {
         return Vec::new();
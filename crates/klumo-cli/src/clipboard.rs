@@ -0,0 +1,264 @@
+use anyhow::{Context, Result, bail};
+use klumo_config::ClipboardSetting;
+use klumo_engine::JsEngine;
+use std::io::{self, IsTerminal, Write};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+/// REPL scope text telling the model how to reach the clipboard.
+pub(crate) const CLIPBOARD_SCOPE_TEXT: &str = "Clipboard APIs available in REPL: klumo.clipboard.read() returns the clipboard text, klumo.clipboard.write(text) replaces it.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ClipboardAccess {
+    Read,
+    Write,
+}
+
+impl ClipboardAccess {
+    fn verb(self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Write => "write",
+        }
+    }
+}
+
+/// What the session has decided so far; in `Ask` mode each access kind is asked about once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ClipboardPermissions {
+    setting: ClipboardSetting,
+    read: Option<bool>,
+    write: Option<bool>,
+}
+
+impl ClipboardPermissions {
+    pub(crate) const fn new(setting: ClipboardSetting) -> Self {
+        Self {
+            setting,
+            read: None,
+            write: None,
+        }
+    }
+
+    /// The standing answer for `access`, or `None` when the user still has to be asked.
+    pub(crate) fn decided(&self, access: ClipboardAccess) -> Option<bool> {
+        match self.setting {
+            ClipboardSetting::Allow => Some(true),
+            ClipboardSetting::Deny => Some(false),
+            ClipboardSetting::Ask => match access {
+                ClipboardAccess::Read => self.read,
+                ClipboardAccess::Write => self.write,
+            },
+        }
+    }
+
+    pub(crate) fn remember(&mut self, access: ClipboardAccess, granted: bool) {
+        match access {
+            ClipboardAccess::Read => self.read = Some(granted),
+            ClipboardAccess::Write => self.write = Some(granted),
+        }
+    }
+}
+
+// Host functions are plain `fn` pointers, so the session's answers live here.
+static PERMISSIONS: Mutex<ClipboardPermissions> =
+    Mutex::new(ClipboardPermissions::new(ClipboardSetting::Deny));
+
+/// Exposes `klumo.clipboard.read()` and `klumo.clipboard.write(text)` to REPL code, gated by
+/// `setting`. Engines without host functions keep the REPL working without them.
+pub(crate) fn install_clipboard_api(
+    engine: &mut dyn JsEngine,
+    setting: ClipboardSetting,
+) -> Result<()> {
+    *PERMISSIONS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = ClipboardPermissions::new(setting);
+    let registered = engine
+        .register_host_function("__klumo_clipboard_read", host_read)
+        .and_then(|()| engine.register_host_function("__klumo_clipboard_write", host_write));
+    if let Err(err) = registered {
+        eprintln!("[klumo] klumo.clipboard is unavailable: {err}");
+        return Ok(());
+    }
+    engine.eval_script(
+        r#"
+globalThis.klumo = globalThis.klumo || {};
+globalThis.klumo.clipboard = {
+  read: () => __klumo_clipboard_read(),
+  write: (text) => {
+    __klumo_clipboard_write(String(text));
+  },
+};
+"#,
+        "<repl-clipboard-api>",
+    )?;
+    Ok(())
+}
+
+fn host_read(_args: &[String]) -> std::result::Result<Option<String>, String> {
+    check_permission(ClipboardAccess::Read)?;
+    read_clipboard().map(Some).map_err(|err| format!("{err:#}"))
+}
+
+fn host_write(args: &[String]) -> std::result::Result<Option<String>, String> {
+    check_permission(ClipboardAccess::Write)?;
+    let text = args.first().map(String::as_str).unwrap_or_default();
+    write_clipboard(text)
+        .map(|()| None)
+        .map_err(|err| format!("{err:#}"))
+}
+
+fn check_permission(access: ClipboardAccess) -> std::result::Result<(), String> {
+    let mut permissions = PERMISSIONS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let granted = match permissions.decided(access) {
+        Some(granted) => granted,
+        None => {
+            let granted = ask(access);
+            permissions.remember(access, granted);
+            granted
+        }
+    };
+    if granted {
+        Ok(())
+    } else {
+        Err(format!(
+            "clipboard {} access denied; set \"clipboard\": \"allow\" in klumo.json or KLUMO_CLIPBOARD=allow to permit it",
+            access.verb()
+        ))
+    }
+}
+
+/// Asks on stderr; without a terminal to answer on, access is denied.
+fn ask(access: ClipboardAccess) -> bool {
+    if !io::stdin().is_terminal() {
+        return false;
+    }
+    eprint!(
+        "[klumo] allow REPL code to {} the clipboard for this session? [y/N] ",
+        access.verb()
+    );
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).is_ok()
+        && matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+}
+
+/// Platform clipboard tools, in order of preference. On Linux the Wayland tools come first
+/// when a Wayland session is running.
+pub(crate) fn clipboard_commands(
+    os: &str,
+    wayland: bool,
+    access: ClipboardAccess,
+) -> Vec<(&'static str, &'static [&'static str])> {
+    let read = access == ClipboardAccess::Read;
+    match os {
+        "macos" => vec![if read {
+            ("pbpaste", &[][..])
+        } else {
+            ("pbcopy", &[][..])
+        }],
+        "windows" => vec![if read {
+            (
+                "powershell",
+                &["-NoProfile", "-Command", "Get-Clipboard -Raw"][..],
+            )
+        } else {
+            (
+                "powershell",
+                &[
+                    "-NoProfile",
+                    "-Command",
+                    "Set-Clipboard -Value ([Console]::In.ReadToEnd())",
+                ][..],
+            )
+        }],
+        _ => {
+            let mut commands: Vec<(&'static str, &'static [&'static str])> = if read {
+                vec![
+                    ("xclip", &["-selection", "clipboard", "-o"][..]),
+                    ("xsel", &["--clipboard", "--output"][..]),
+                ]
+            } else {
+                vec![
+                    ("xclip", &["-selection", "clipboard"][..]),
+                    ("xsel", &["--clipboard", "--input"][..]),
+                ]
+            };
+            let wayland_tool = if read {
+                ("wl-paste", &["--no-newline"][..])
+            } else {
+                ("wl-copy", &[][..])
+            };
+            if wayland {
+                commands.insert(0, wayland_tool);
+            } else {
+                commands.push(wayland_tool);
+            }
+            commands
+        }
+    }
+}
+
+fn platform_commands(access: ClipboardAccess) -> Vec<(&'static str, &'static [&'static str])> {
+    let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some();
+    clipboard_commands(std::env::consts::OS, wayland, access)
+}
+
+fn read_clipboard() -> Result<String> {
+    run_first_available(ClipboardAccess::Read, None)
+}
+
+fn write_clipboard(text: &str) -> Result<()> {
+    run_first_available(ClipboardAccess::Write, Some(text)).map(|_| ())
+}
+
+/// Runs the first clipboard tool that is installed, feeding it `input` on stdin when writing.
+fn run_first_available(access: ClipboardAccess, input: Option<&str>) -> Result<String> {
+    let commands = platform_commands(access);
+    for (program, args) in &commands {
+        let mut command = Command::new(program);
+        command.args(*args);
+        // Writers such as xclip and wl-copy fork to keep serving the selection, holding any
+        // pipe they inherit open; only readers get their output captured.
+        if input.is_some() {
+            command
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null());
+        } else {
+            command
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped());
+        }
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err).with_context(|| format!("failed starting {program}")),
+        };
+        if let (Some(text), Some(mut stdin)) = (input, child.stdin.take()) {
+            stdin
+                .write_all(text.as_bytes())
+                .with_context(|| format!("failed writing to {program}"))?;
+        }
+        let output = child
+            .wait_with_output()
+            .with_context(|| format!("failed running {program}"))?;
+        if !output.status.success() {
+            bail!(
+                "{program} exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        return String::from_utf8(output.stdout)
+            .with_context(|| format!("{program} returned text that is not UTF-8"));
+    }
+    let tools: Vec<&str> = commands.iter().map(|(program, _)| *program).collect();
+    bail!(
+        "no clipboard tool found; install one of: {}",
+        tools.join(", ")
+    )
+}
@@ -0,0 +1,68 @@
+use crate::runtime_context;
+use anyhow::Result;
+use klumo_config::{CliRunOverrides, RunDefaults};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Modification time and size: what tells an edited file apart.
+pub(crate) type FileStamp = (SystemTime, u64);
+
+/// Watches the `klumo.json` a long-running session resolved its settings from, so provider,
+/// model and prompt changes apply to the next compile without a restart.
+#[derive(Debug)]
+pub(crate) struct ConfigReloader {
+    config: Option<PathBuf>,
+    command: &'static str,
+    /// The flags as given on the command line, before `defaults.<command>` filled them in, so
+    /// an edited section is applied afresh.
+    cli_overrides: CliRunOverrides,
+    watched: PathBuf,
+    stamp: Option<FileStamp>,
+}
+
+impl ConfigReloader {
+    pub(crate) fn new(
+        config: Option<PathBuf>,
+        command: &'static str,
+        cli_overrides: CliRunOverrides,
+    ) -> Result<Self> {
+        let watched = match &config {
+            Some(path) => path.clone(),
+            None => std::env::current_dir()?.join("klumo.json"),
+        };
+        Ok(Self {
+            stamp: stamp(&watched),
+            config,
+            command,
+            cli_overrides,
+            watched,
+        })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.watched
+    }
+
+    /// Settings resolved again if the file was created, edited or removed since the last call,
+    /// with the command-line flags they were resolved from; `None` while it is unchanged. A
+    /// file that fails to load is only reported once, until it changes again.
+    pub(crate) fn poll(&mut self) -> Option<Result<(RunDefaults, CliRunOverrides)>> {
+        let current = stamp(&self.watched);
+        if current == self.stamp {
+            return None;
+        }
+        self.stamp = current;
+        let mut cli_overrides = self.cli_overrides.clone();
+        Some(
+            runtime_context::resolve_config(self.config.clone(), self.command, &mut cli_overrides)
+                .map(|resolved| (resolved, cli_overrides)),
+        )
+    }
+}
+
+/// `None` for a missing or unreadable file.
+pub(crate) fn stamp(path: &Path) -> Option<FileStamp> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
@@ -1,6 +1,17 @@
-use super::{Cli, Commands, bundle_command, eval_command, fmt_command, install_dependencies};
-use super::{lint_command, repl_command, run_command, test_command};
-use anyhow::Result;
+use super::{
+    BundleCommandOptions, CacheAction, Cli, Commands, bundle_command, cache_gc_command,
+    doctor_command, eval_command, explain_command, fmt_command, graph_command, init_command,
+    install_dependencies, keygen_command,
+};
+use super::{
+    lint_command, migrate_command, models_command, repl_command, run_command, stats_command,
+    test_command,
+};
+use crate::bundle_meta::verify_command;
+use crate::review::{ReviewAction, review_command};
+use crate::run_expectations::RunExpectations;
+use anyhow::{Result, anyhow};
+use klumo_compiler::OutputOptions;
 
 pub(crate) fn execute(cli: Cli) -> Result<()> {
     match cli.command {
@@ -13,11 +24,27 @@ pub(crate) fn execute(cli: Cli) -> Result<()> {
             force_llm,
             self_heal,
             max_heal_attempts,
+            heal_provider,
             no_progress,
             verbose,
             provider,
             ollama_url,
+            no_auto_pull,
+            llm_log,
             model,
+            temperature,
+            top_p,
+            max_tokens,
+            expect_output,
+            expect_exit,
+            verify_signatures,
+            report_memory,
+            raw_output,
+            no_output_limit,
+            deterministic,
+            integrity,
+            allow_remote,
+            module,
         }) => {
             if let Some(path) = file {
                 run_command(
@@ -29,12 +56,34 @@ pub(crate) fn execute(cli: Cli) -> Result<()> {
                     force_llm,
                     self_heal,
                     max_heal_attempts,
+                    heal_provider,
                     no_progress,
                     verbose,
                     provider,
                     ollama_url,
+                    no_auto_pull,
+                    llm_log,
                     model,
+                    temperature,
+                    top_p,
+                    max_tokens,
+                    verify_signatures,
+                    report_memory,
+                    raw_output,
+                    no_output_limit,
+                    deterministic,
+                    integrity,
+                    allow_remote,
+                    module,
+                    RunExpectations {
+                        output: expect_output,
+                        exit: expect_exit,
+                    },
                 )
+            } else if module {
+                Err(anyhow!(
+                    "--module needs a file to run; the REPL evaluates each line as a script so its bindings persist"
+                ))
             } else {
                 repl_command(
                     config,
@@ -45,13 +94,92 @@ pub(crate) fn execute(cli: Cli) -> Result<()> {
                     verbose,
                     provider,
                     ollama_url,
+                    no_auto_pull,
+                    llm_log,
                     model,
+                    temperature,
+                    top_p,
+                    max_tokens,
+                    None,
+                    None,
+                    no_output_limit,
                 )
             }
         }
-        Some(Commands::Eval { code }) => eval_command(code),
+        Some(Commands::Verify { bundle }) => verify_command(&bundle),
+        Some(Commands::Keygen { output }) => keygen_command(output),
+        Some(Commands::Review {
+            config,
+            list,
+            approve,
+            reject,
+        }) => review_command(
+            config,
+            if list {
+                ReviewAction::List
+            } else if !approve.is_empty() {
+                ReviewAction::Approve(approve)
+            } else if !reject.is_empty() {
+                ReviewAction::Reject(reject)
+            } else {
+                ReviewAction::Interactive
+            },
+        ),
+        Some(Commands::Eval {
+            code,
+            session,
+            module,
+        }) => eval_command(code, session, module),
+        Some(Commands::Models {
+            config,
+            ollama_url,
+            action,
+        }) => models_command(config, ollama_url, action),
+        Some(Commands::Doctor { config }) => doctor_command(config),
+        Some(Commands::Stats {
+            reset,
+            llm,
+            check_hit_rate,
+            window,
+        }) => stats_command(reset, llm, check_hit_rate, window),
+        Some(Commands::Cache {
+            action:
+                CacheAction::Gc {
+                    config,
+                    max_size_mb,
+                    max_age_days,
+                    all_versions,
+                },
+        }) => cache_gc_command(config, max_size_mb, max_age_days, all_versions),
+        Some(Commands::Migrate { dry_run }) => migrate_command(dry_run),
+        Some(Commands::Graph {
+            file,
+            format,
+            config,
+            lang,
+            no_cache,
+            force_llm,
+            provider,
+            model,
+        }) => graph_command(
+            file, format, config, lang, no_cache, force_llm, provider, model,
+        ),
+        Some(Commands::Explain {
+            file,
+            config,
+            lang,
+            force_llm,
+            provider,
+            model,
+        }) => explain_command(file, config, lang, force_llm, provider, model),
+        Some(Commands::Init {
+            dir,
+            template,
+            name,
+        }) => init_command(dir, template, name),
         Some(Commands::Bundle {
             file,
+            stdin: _,
             output,
             config,
             lang,
@@ -61,8 +189,23 @@ pub(crate) fn execute(cli: Cli) -> Result<()> {
             verbose,
             provider,
             ollama_url,
+            no_auto_pull,
+            llm_log,
             model,
-        }) => bundle_command(
+            temperature,
+            top_p,
+            max_tokens,
+            lint_fix,
+            source_map,
+            deterministic,
+            target,
+            banner,
+            strict,
+            minify,
+            keep_debug,
+            watch,
+            jobs,
+        }) => bundle_command(BundleCommandOptions {
             file,
             output,
             config,
@@ -73,8 +216,22 @@ pub(crate) fn execute(cli: Cli) -> Result<()> {
             verbose,
             provider,
             ollama_url,
+            no_auto_pull,
+            llm_log,
             model,
-        ),
+            temperature,
+            top_p,
+            max_tokens,
+            lint_fix,
+            source_map,
+            deterministic,
+            target,
+            output_options: OutputOptions { banner, strict },
+            minify,
+            keep_debug,
+            watch,
+            jobs,
+        }),
         Some(Commands::Install { config, dry_run }) => install_dependencies(config, dry_run),
         Some(Commands::Lint { fix, paths }) => lint_command(paths, fix),
         Some(Commands::Fmt { check, paths }) => fmt_command(paths, check),
@@ -88,7 +245,15 @@ pub(crate) fn execute(cli: Cli) -> Result<()> {
             verbose,
             provider,
             ollama_url,
+            no_auto_pull,
+            llm_log,
             model,
+            temperature,
+            top_p,
+            max_tokens,
+            exec,
+            script,
+            no_output_limit,
         }) => repl_command(
             config,
             lang,
@@ -98,8 +263,19 @@ pub(crate) fn execute(cli: Cli) -> Result<()> {
             verbose,
             provider,
             ollama_url,
+            no_auto_pull,
+            llm_log,
             model,
+            temperature,
+            top_p,
+            max_tokens,
+            exec,
+            script,
+            no_output_limit,
+        ),
+        None => repl_command(
+            None, None, None, false, false, false, None, None, false, None, None, None, None, None,
+            None, None, false,
         ),
-        None => repl_command(None, None, false, false, false, false, None, None, None),
     }
 }
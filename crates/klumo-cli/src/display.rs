@@ -0,0 +1,305 @@
+use anyhow::Result;
+use klumo_engine::JsEngine;
+use serde_json::Value as JsonValue;
+use std::io::{self, IsTerminal};
+
+/// REPL scope text telling the model how to show structured data.
+pub(crate) const DISPLAY_SCOPE_TEXT: &str = "Display APIs available in REPL: klumo.display.table(rows) prints an array of objects (or arrays) as an aligned table, klumo.display.json(value) prints highlighted, indented JSON; prefer them over console.log for structured data.";
+
+const KEY: &str = "\x1b[36m";
+const STRING: &str = "\x1b[32m";
+const NUMBER: &str = "\x1b[33m";
+const LITERAL: &str = "\x1b[35m";
+const RESET: &str = "\x1b[0m";
+
+/// Exposes `klumo.display.table(rows)` and `klumo.display.json(value)` to REPL code. Values
+/// cross into Rust as JSON and come back as text printed through `console.log`, so the output
+/// lands in order with the rest of the line's console output. The shim sends key order along,
+/// so tables do not depend on how `serde_json` orders maps, and lets `JSON.stringify` do the
+/// indenting.
+pub(crate) fn install_display_api(engine: &mut dyn JsEngine) -> Result<()> {
+    let registered = engine
+        .register_host_function("__klumo_display_table", host_table)
+        .and_then(|()| engine.register_host_function("__klumo_display_json", host_json));
+    if let Err(err) = registered {
+        eprintln!("[klumo] klumo.display is unavailable: {err}");
+        return Ok(());
+    }
+    engine.eval_script(
+        r#"
+globalThis.klumo = globalThis.klumo || {};
+globalThis.klumo.display = {
+  table: (rows) => {
+    const order = { index: [], columns: [] };
+    const isRecord = (value) =>
+      value !== null && typeof value === "object" && !Array.isArray(value);
+    const add = (keys, object) => {
+      for (const key of Object.keys(object)) if (!keys.includes(key)) keys.push(key);
+    };
+    if (isRecord(rows)) add(order.index, rows);
+    if (rows !== null && typeof rows === "object") {
+      for (const row of Object.values(rows)) if (isRecord(row)) add(order.columns, row);
+    }
+    console.log(
+      __klumo_display_table(
+        JSON.stringify(rows === undefined ? null : rows),
+        JSON.stringify(order),
+      ),
+    );
+  },
+  json: (value) => {
+    const text = JSON.stringify(value, null, 2);
+    console.log(__klumo_display_json(text === undefined ? "undefined" : text));
+  },
+};
+"#,
+        "<repl-display-api>",
+    )?;
+    Ok(())
+}
+
+/// Key order as seen by JavaScript: the keys of an object of rows, then the keys of the rows.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct KeyOrder {
+    pub(crate) index: Vec<String>,
+    pub(crate) columns: Vec<String>,
+}
+
+impl KeyOrder {
+    fn from_json(value: &JsonValue) -> Self {
+        let keys = |field: &str| -> Vec<String> {
+            value[field]
+                .as_array()
+                .map(|keys| {
+                    keys.iter()
+                        .filter_map(|key| key.as_str().map(str::to_string))
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        Self {
+            index: keys("index"),
+            columns: keys("columns"),
+        }
+    }
+}
+
+fn host_table(args: &[String]) -> std::result::Result<Option<String>, String> {
+    let rows = args.first().map(String::as_str).unwrap_or("null");
+    let rows: JsonValue = serde_json::from_str(rows)
+        .map_err(|err| format!("klumo.display.table rows are not JSON-serializable: {err}"))?;
+    let order = args
+        .get(1)
+        .and_then(|raw| serde_json::from_str::<JsonValue>(raw).ok())
+        .map(|order| KeyOrder::from_json(&order))
+        .unwrap_or_default();
+    render_table(&rows, &order).map(Some)
+}
+
+fn host_json(args: &[String]) -> std::result::Result<Option<String>, String> {
+    let color = io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    let text = args.first().map(String::as_str).unwrap_or("undefined");
+    Ok(Some(highlight_json(text, color)))
+}
+
+/// Sorts `keys` by their position in `order`; keys it does not list keep their order, last.
+fn order_keys(keys: &mut [String], order: &[String]) {
+    keys.sort_by_key(|key| {
+        order
+            .iter()
+            .position(|known| known == key)
+            .unwrap_or(usize::MAX)
+    });
+}
+
+/// Renders an array of rows, or an object of rows keyed by an `(index)` column, as an aligned
+/// table. Object rows contribute their keys as columns, array rows their positions, and other
+/// values a single `value` column. Numbers are right-aligned.
+pub(crate) fn render_table(
+    rows: &JsonValue,
+    order: &KeyOrder,
+) -> std::result::Result<String, String> {
+    let (index, rows): (Option<Vec<String>>, Vec<&JsonValue>) = match rows {
+        JsonValue::Array(items) => (None, items.iter().collect()),
+        JsonValue::Object(entries) => {
+            let mut keys: Vec<String> = entries.keys().cloned().collect();
+            order_keys(&mut keys, &order.index);
+            let rows = keys.iter().map(|key| &entries[key]).collect();
+            (Some(keys), rows)
+        }
+        other => {
+            return Err(format!(
+                "klumo.display.table expects an array or object of rows, got {}",
+                json_type(other)
+            ));
+        }
+    };
+    if rows.is_empty() {
+        return Ok("(no rows)".to_string());
+    }
+
+    let mut columns: Vec<String> = index.iter().map(|_| "(index)".to_string()).collect();
+    let mut cells: Vec<Vec<(String, &JsonValue)>> = Vec::with_capacity(rows.len());
+    for row in &rows {
+        let row_cells: Vec<(String, &JsonValue)> = match row {
+            JsonValue::Object(fields) => {
+                let mut keys: Vec<String> = fields.keys().cloned().collect();
+                order_keys(&mut keys, &order.columns);
+                keys.into_iter()
+                    .map(|key| {
+                        let value = &fields[&key];
+                        (key, value)
+                    })
+                    .collect()
+            }
+            JsonValue::Array(items) => items
+                .iter()
+                .enumerate()
+                .map(|(position, v)| (position.to_string(), v))
+                .collect(),
+            scalar => vec![("value".to_string(), *scalar)],
+        };
+        for (column, _) in &row_cells {
+            if !columns.contains(column) {
+                columns.push(column.clone());
+            }
+        }
+        cells.push(row_cells);
+    }
+
+    let grid: Vec<Vec<(String, bool)>> = cells
+        .iter()
+        .enumerate()
+        .map(|(row, row_cells)| {
+            columns
+                .iter()
+                .map(|column| {
+                    if column == "(index)"
+                        && let Some(keys) = &index
+                    {
+                        return (keys[row].clone(), false);
+                    }
+                    row_cells
+                        .iter()
+                        .find(|(name, _)| name == column)
+                        .map(|(_, value)| (cell_text(value), value.is_number()))
+                        .unwrap_or_default()
+                })
+                .collect()
+        })
+        .collect();
+    let widths: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(at, column)| {
+            grid.iter()
+                .map(|row| row[at].0.chars().count())
+                .chain([column.chars().count()])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+
+    let mut lines = Vec::with_capacity(grid.len() + 2);
+    lines.push(join_row(
+        columns
+            .iter()
+            .zip(&widths)
+            .map(|(column, width)| pad(column, *width, false)),
+    ));
+    lines.push(
+        widths
+            .iter()
+            .map(|width| "-".repeat(*width))
+            .collect::<Vec<_>>()
+            .join("-+-"),
+    );
+    for row in &grid {
+        lines.push(join_row(
+            row.iter()
+                .zip(&widths)
+                .map(|((text, numeric), width)| pad(text, *width, *numeric)),
+        ));
+    }
+    Ok(lines.join("\n"))
+}
+
+fn join_row(cells: impl Iterator<Item = String>) -> String {
+    cells.collect::<Vec<_>>().join(" | ").trim_end().to_string()
+}
+
+fn pad(text: &str, width: usize, right_align: bool) -> String {
+    let fill = " ".repeat(width.saturating_sub(text.chars().count()));
+    if right_align {
+        format!("{fill}{text}")
+    } else {
+        format!("{text}{fill}")
+    }
+}
+
+/// Strings print bare; everything else prints as compact JSON.
+fn cell_text(value: &JsonValue) -> String {
+    match value {
+        JsonValue::String(text) => text.replace('\n', "\\n"),
+        other => other.to_string(),
+    }
+}
+
+fn json_type(value: &JsonValue) -> &'static str {
+    match value {
+        JsonValue::Null => "null",
+        JsonValue::Bool(_) => "a boolean",
+        JsonValue::Number(_) => "a number",
+        JsonValue::String(_) => "a string",
+        JsonValue::Array(_) => "an array",
+        JsonValue::Object(_) => "an object",
+    }
+}
+
+/// Colors keys, strings, numbers and literals in JSON text with ANSI escapes when `color` is
+/// set; the text itself, including its layout, is left alone.
+pub(crate) fn highlight_json(text: &str, color: bool) -> String {
+    if !color {
+        return text.to_string();
+    }
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len() * 2);
+    let mut at = 0;
+    while at < chars.len() {
+        let start = at;
+        let style = match chars[at] {
+            '"' => {
+                at += 1;
+                while at < chars.len() && chars[at] != '"' {
+                    at += if chars[at] == '\\' { 2 } else { 1 };
+                }
+                at = (at + 1).min(chars.len());
+                let rest = chars[at..].iter().find(|c| !c.is_whitespace());
+                if rest == Some(&':') { KEY } else { STRING }
+            }
+            '-' | '0'..='9' => {
+                while at < chars.len()
+                    && matches!(chars[at], '-' | '+' | '.' | 'e' | 'E' | '0'..='9')
+                {
+                    at += 1;
+                }
+                NUMBER
+            }
+            c if c.is_ascii_alphabetic() => {
+                while at < chars.len() && chars[at].is_ascii_alphabetic() {
+                    at += 1;
+                }
+                LITERAL
+            }
+            c => {
+                out.push(c);
+                at += 1;
+                continue;
+            }
+        };
+        out.push_str(style);
+        out.extend(&chars[start..at]);
+        out.push_str(RESET);
+    }
+    out
+}
@@ -0,0 +1,143 @@
+use crate::runtime_context;
+use anyhow::{Result, anyhow};
+use klumo_config::RunDefaults;
+use klumo_llm_llamacpp::LlamaCppClient;
+use klumo_llm_ollama::OllamaClient;
+use klumo_llm_openai::OpenAiCompatibleClient;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Outcome of probing one configured provider for `klumo doctor`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ProviderHealth {
+    pub(crate) name: String,
+    pub(crate) endpoint: String,
+    pub(crate) latency: Duration,
+    /// A one-line summary when healthy, the failure otherwise.
+    pub(crate) result: std::result::Result<String, String>,
+}
+
+type HealthCheck = Box<dyn FnOnce() -> Result<String> + Send>;
+
+/// Probes Ollama, llama.cpp when configured, the OpenAI-compatible endpoint, and every custom
+/// provider in parallel.
+pub(crate) fn check_providers(resolved: &RunDefaults) -> Result<Vec<ProviderHealth>> {
+    runtime_context::warn_insecure_tls(resolved);
+    let tls = runtime_context::tls_options(resolved);
+    let mut checks: Vec<(String, String, HealthCheck)> = Vec::new();
+
+    let ollama = OllamaClient::new(resolved.ollama_url.clone())?.with_tls(tls.clone());
+    let ollama_model = resolved.ollama_model.clone();
+    checks.push((
+        "ollama".to_string(),
+        resolved.ollama_url.clone(),
+        Box::new(move || {
+            let installed = ollama.list_models()?;
+            let configured = if installed.iter().any(|name| {
+                name.strip_prefix(ollama_model.as_str())
+                    .is_some_and(|tag| tag.is_empty() || tag.starts_with(':'))
+            }) {
+                "installed"
+            } else {
+                "not installed"
+            };
+            Ok(format!(
+                "{} models, {ollama_model} {configured}",
+                installed.len()
+            ))
+        }),
+    ));
+
+    if let Some(url) = &resolved.llamacpp_url {
+        let llamacpp = LlamaCppClient::new(url.clone()).with_tls(tls.clone());
+        checks.push((
+            "llamacpp".to_string(),
+            url.clone(),
+            Box::new(move || {
+                llamacpp.health()?;
+                Ok("model loaded".to_string())
+            }),
+        ));
+    }
+
+    let mut openai_endpoints = vec![(
+        "openai-compatible".to_string(),
+        resolved.openai_base_url.clone(),
+        resolved
+            .openai_api_key
+            .clone()
+            .ok_or_else(|| "OPENAI_API_KEY is not set".to_string()),
+        resolved.openai_model.clone(),
+    )];
+    openai_endpoints.extend(resolved.providers.iter().map(|config| {
+        let api_key = match config.api_key_env.as_deref() {
+            Some(var) => std::env::var(var).map_err(|_| format!("{var} is not set")),
+            None => Ok(String::new()),
+        };
+        (
+            config.name.clone(),
+            config.base_url.clone(),
+            api_key,
+            config.primary_model().to_string(),
+        )
+    }));
+    for (name, base_url, api_key, model) in openai_endpoints {
+        let client = api_key.map(|key| {
+            OpenAiCompatibleClient::from_parts(base_url.clone(), key).with_tls(tls.clone())
+        });
+        checks.push((
+            name,
+            base_url,
+            Box::new(move || {
+                let models = client.map_err(|err| anyhow!(err))?.list_models()?;
+                let listed = if models.iter().any(|id| id == &model) {
+                    "listed"
+                } else {
+                    "not listed"
+                };
+                Ok(format!("{} models, {model} {listed}", models.len()))
+            }),
+        ));
+    }
+
+    Ok(thread::scope(|scope| {
+        let handles: Vec<_> = checks
+            .into_iter()
+            .map(|(name, endpoint, check)| {
+                scope.spawn(move || {
+                    let started = Instant::now();
+                    let result = check().map_err(|err| format!("{err:#}"));
+                    ProviderHealth {
+                        name,
+                        endpoint,
+                        latency: started.elapsed(),
+                        result,
+                    }
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("health check thread panicked"))
+            .collect()
+    }))
+}
+
+/// One line per provider: status, name, endpoint, latency, and the summary or failure.
+pub(crate) fn render_report(health: &[ProviderHealth]) -> Vec<String> {
+    health
+        .iter()
+        .map(|entry| {
+            let (status, detail) = match &entry.result {
+                Ok(summary) => ("ok  ", summary),
+                Err(error) => ("FAIL", error),
+            };
+            format!(
+                "{status} {:<20} {} ({}ms) {detail}",
+                entry.name,
+                entry.endpoint,
+                entry.latency.as_millis()
+            )
+        })
+        .collect()
+}
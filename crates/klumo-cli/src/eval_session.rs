@@ -0,0 +1,73 @@
+use anyhow::{Context, Result, anyhow, bail};
+use serde_json::{Value as JsonValue, json};
+use std::fs;
+use std::path::PathBuf;
+
+/// `KLUMO_SESSIONS_DIR`, or `~/.klumo/sessions` by default.
+pub(crate) fn sessions_dir() -> Result<PathBuf> {
+    Ok(match std::env::var_os("KLUMO_SESSIONS_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => dirs::home_dir()
+            .context("failed to resolve home directory")?
+            .join(".klumo/sessions"),
+    })
+}
+
+/// Persistent state for `klumo eval --session <name>`.
+///
+/// Engine heaps cannot be serialized, so a session is the list of snippets that evaluated
+/// successfully; loading it replays them in order into a fresh engine.
+pub(crate) struct EvalSession {
+    pub(crate) name: String,
+    path: PathBuf,
+    pub(crate) snippets: Vec<String>,
+}
+
+impl EvalSession {
+    /// Opens session `name` in [`sessions_dir`]. A session that was never saved starts empty.
+    pub(crate) fn open(name: &str) -> Result<Self> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            bail!("invalid session name '{name}': use letters, digits, '-' and '_'");
+        }
+        let path = sessions_dir()?.join(format!("{name}.json"));
+        let snippets = if path.exists() {
+            let text = fs::read_to_string(&path)
+                .with_context(|| format!("failed reading session {}", path.display()))?;
+            let parsed: JsonValue = serde_json::from_str(&text)
+                .with_context(|| format!("failed parsing session {}", path.display()))?;
+            parsed["snippets"]
+                .as_array()
+                .ok_or_else(|| anyhow!("session {} has no snippets list", path.display()))?
+                .iter()
+                .map(|snippet| {
+                    snippet.as_str().map(str::to_string).ok_or_else(|| {
+                        anyhow!("session {} contains a non-string snippet", path.display())
+                    })
+                })
+                .collect::<Result<_>>()?
+        } else {
+            Vec::new()
+        };
+        Ok(Self {
+            name: name.to_string(),
+            path,
+            snippets,
+        })
+    }
+
+    /// Records `code` as evaluated and writes the session back to disk.
+    pub(crate) fn push_and_save(&mut self, code: &str) -> Result<()> {
+        self.snippets.push(code.to_string());
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed creating {}", dir.display()))?;
+        }
+        let body = serde_json::to_string_pretty(&json!({ "snippets": self.snippets }))?;
+        fs::write(&self.path, body)
+            .with_context(|| format!("failed writing session {}", self.path.display()))
+    }
+}
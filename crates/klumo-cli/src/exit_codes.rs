@@ -0,0 +1,24 @@
+use klumo_llm::{ProviderErrorKind, ProviderRoutingError};
+
+/// Generic failure.
+pub(crate) const FAILURE: u8 = 1;
+/// Provider routing failed on the model's output (sysexits `EX_DATAERR`).
+pub(crate) const MODEL_ERROR: u8 = 65;
+/// Provider routing failed reaching an endpoint (sysexits `EX_UNAVAILABLE`).
+pub(crate) const NETWORK_ERROR: u8 = 69;
+/// Provider routing failed on credentials or configuration (sysexits `EX_CONFIG`).
+pub(crate) const CONFIG_ERROR: u8 = 78;
+
+/// Maps an error to the process exit code, keyed on the routing failure that ended the run.
+pub(crate) fn exit_code_for(err: &anyhow::Error) -> u8 {
+    let kind = err
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<ProviderRoutingError>())
+        .map(ProviderRoutingError::kind);
+    match kind {
+        Some(ProviderErrorKind::Config) => CONFIG_ERROR,
+        Some(ProviderErrorKind::Network) => NETWORK_ERROR,
+        Some(ProviderErrorKind::Model) => MODEL_ERROR,
+        Some(ProviderErrorKind::Other) | None => FAILURE,
+    }
+}
@@ -0,0 +1,57 @@
+use crate::module_graph::describe;
+use klumo_compiler::CompileResult;
+use std::fmt::Write;
+use std::path::Path;
+
+/// `klumo explain` output: how `file` was compiled, the translator's rationale, and a table of
+/// original constructs next to the JavaScript each became.
+pub(crate) fn render(file: &Path, compile: &CompileResult) -> String {
+    let mut out = format!("{} [{}]\n", file.display(), describe(compile));
+    if compile.metadata.provider.is_none() {
+        out.push_str(
+            "\nNot translated: the file runs as written, so there is nothing to explain.\n",
+        );
+        return out;
+    }
+    let Some(explanation) = &compile.metadata.explanation else {
+        out.push_str(
+            "\nThe model answered without an explanation; `klumo explain` asks again next time.\n",
+        );
+        return out;
+    };
+    if !explanation.rationale.trim().is_empty() {
+        out.push_str("\nRationale:\n");
+        for line in explanation.rationale.trim().lines() {
+            let _ = writeln!(out, "  {line}");
+        }
+    }
+    if !explanation.mappings.is_empty() {
+        let rows: Vec<(String, String)> = explanation
+            .mappings
+            .iter()
+            .map(|mapping| {
+                (
+                    single_line(&mapping.source),
+                    single_line(&mapping.generated),
+                )
+            })
+            .collect();
+        let width = rows
+            .iter()
+            .map(|(source, _)| source.chars().count())
+            .chain(["Original".len()])
+            .max()
+            .unwrap_or_default();
+        out.push_str("\nMappings:\n");
+        let _ = writeln!(out, "  {:<width$}  Generated", "Original");
+        for (source, generated) in rows {
+            let _ = writeln!(out, "  {source:<width$}  {generated}");
+        }
+    }
+    out
+}
+
+/// Table cells stay on one row: runs of whitespace, newlines included, become one space.
+fn single_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
@@ -0,0 +1,214 @@
+use anyhow::{Context, Result};
+use serde_json::{Value as JsonValue, json};
+use similar::{ChangeTag, TextDiff};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What a successful self-heal changed in a file: printed once the healed file runs, and
+/// appended to the heal history so the change can be looked up without diffing the backup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct HealSummary {
+    pub(crate) file: PathBuf,
+    pub(crate) attempts: usize,
+    pub(crate) lines_added: usize,
+    pub(crate) lines_removed: usize,
+    /// Functions containing an added or removed line, in order of first change.
+    pub(crate) functions: Vec<String>,
+    /// The model's one-sentence account of its last patch.
+    pub(crate) description: Option<String>,
+}
+
+impl HealSummary {
+    pub(crate) fn between(
+        file: &Path,
+        before: &str,
+        after: &str,
+        attempts: usize,
+        description: Option<String>,
+    ) -> Self {
+        let old_functions = enclosing_functions(before);
+        let new_functions = enclosing_functions(after);
+        let mut summary = Self {
+            file: file.to_path_buf(),
+            attempts,
+            lines_added: 0,
+            lines_removed: 0,
+            functions: Vec::new(),
+            description,
+        };
+        let diff = TextDiff::from_lines(before, after);
+        for change in diff.iter_all_changes() {
+            let function = match change.tag() {
+                ChangeTag::Equal => continue,
+                ChangeTag::Insert => {
+                    summary.lines_added += 1;
+                    change
+                        .new_index()
+                        .and_then(|line| new_functions[line].clone())
+                }
+                ChangeTag::Delete => {
+                    summary.lines_removed += 1;
+                    change
+                        .old_index()
+                        .and_then(|line| old_functions[line].clone())
+                }
+            };
+            if let Some(function) = function
+                && !summary.functions.contains(&function)
+            {
+                summary.functions.push(function);
+            }
+        }
+        summary
+    }
+
+    /// The `[klumo]` lines printed to stderr after the healed run.
+    pub(crate) fn render(&self) -> String {
+        let attempts = match self.attempts {
+            1 => "1 attempt".to_string(),
+            n => format!("{n} attempts"),
+        };
+        let mut lines = vec![format!(
+            "[klumo] self-heal changed {} in {attempts}: +{} -{} lines",
+            self.file.display(),
+            self.lines_added,
+            self.lines_removed
+        )];
+        if !self.functions.is_empty() {
+            lines.push(format!(
+                "[klumo]   functions touched: {}",
+                self.functions.join(", ")
+            ));
+        }
+        if let Some(description) = &self.description {
+            lines.push(format!("[klumo]   change: {description}"));
+        }
+        lines.join("\n")
+    }
+
+    fn to_json(&self, at: u64) -> JsonValue {
+        json!({
+            "at": at,
+            "file": self.file.display().to_string(),
+            "attempts": self.attempts,
+            "lines_added": self.lines_added,
+            "lines_removed": self.lines_removed,
+            "functions": self.functions,
+            "description": self.description,
+        })
+    }
+}
+
+/// `~/.klumo/heal-history.jsonl`, or `$KLUMO_HEAL_HISTORY` when set.
+pub(crate) fn history_path() -> Result<PathBuf> {
+    Ok(match std::env::var_os("KLUMO_HEAL_HISTORY") {
+        Some(path) => PathBuf::from(path),
+        None => dirs::home_dir()
+            .context("failed to resolve home directory")?
+            .join(".klumo/heal-history.jsonl"),
+    })
+}
+
+/// Appends `summary` to the history at `path` as one JSON line.
+pub(crate) fn append_history(path: &Path, summary: &HealSummary) -> Result<()> {
+    let at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed creating {}", parent.display()))?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{}", summary.to_json(at)))
+        .with_context(|| format!("failed writing {}", path.display()))
+}
+
+/// The first sentence of the model's notes, which is all the summary has room for.
+pub(crate) fn first_sentence(notes: &str) -> Option<String> {
+    let notes = notes.trim();
+    let end = notes
+        .find(['\n', '\r'])
+        .unwrap_or(notes.len())
+        .min(notes.find(". ").map_or(notes.len(), |stop| stop + 1));
+    let sentence = notes[..end].trim();
+    (!sentence.is_empty()).then(|| sentence.to_string())
+}
+
+/// For every line of `source`, the innermost named function it lies in. Brace counting over
+/// lines, ignoring strings and comments: good enough to name what a patch touched.
+fn enclosing_functions(source: &str) -> Vec<Option<String>> {
+    let mut open: Vec<(String, i64)> = Vec::new();
+    let mut depth: i64 = 0;
+    let mut lines = Vec::new();
+    for line in source.split_inclusive('\n') {
+        if let Some(name) = declared_function(line) {
+            open.push((name, depth));
+        }
+        lines.push(open.last().map(|(name, _)| name.clone()));
+        depth += line.matches('{').count() as i64 - line.matches('}').count() as i64;
+        while open.last().is_some_and(|(_, opened)| depth <= *opened) && line.contains('}') {
+            open.pop();
+        }
+    }
+    lines
+}
+
+/// The name a line declares a function under: `function name(`, `name = function`,
+/// `name = (...) =>` or a `name(...) {` method.
+fn declared_function(line: &str) -> Option<String> {
+    let is_name = |name: &str| {
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+    };
+    let trimmed = line.trim();
+    for (at, _) in trimmed.match_indices("function") {
+        let before = trimmed[..at].chars().next_back();
+        let rest = &trimmed[at + "function".len()..];
+        if before.is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+            || !rest.starts_with([' ', '*', '('])
+        {
+            continue;
+        }
+        let name = rest
+            .trim_start_matches([' ', '*'])
+            .split(|c: char| c == '(' || c.is_whitespace())
+            .next()
+            .unwrap_or_default();
+        if is_name(name) {
+            return Some(name.to_string());
+        }
+    }
+    if let Some((left, right)) = trimmed.split_once('=')
+        && !right.starts_with(['=', '>'])
+        && (right.trim_start().starts_with("function")
+            || right.trim_start().starts_with("async")
+            || right.contains("=>"))
+    {
+        let name = left.split_whitespace().last().unwrap_or_default();
+        if is_name(name) {
+            return Some(name.to_string());
+        }
+    }
+    let head = trimmed
+        .trim_start_matches("async ")
+        .trim_start_matches("static ");
+    if let Some((name, rest)) = head.split_once('(')
+        && is_name(name.trim())
+        && rest.trim_end().ends_with('{')
+        && !matches!(
+            name.trim(),
+            "if" | "for" | "while" | "switch" | "catch" | "with" | "function"
+        )
+    {
+        return Some(name.trim().to_string());
+    }
+    None
+}
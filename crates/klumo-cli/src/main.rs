@@ -1,24 +1,68 @@
+mod bundle_lint;
+mod bundle_meta;
+mod bundle_watch;
 mod cli_defaults;
+mod clipboard;
+mod config_reload;
 mod dispatch;
+mod display;
+mod doctor;
+mod eval_session;
+mod exit_codes;
+mod explain;
+mod heal_summary;
+mod memory;
+mod module_graph;
 mod project_commands;
+mod provider_setup;
+mod remote_source;
+mod repl_autosave;
 mod repl_helpers;
+mod repl_notebook;
+mod repl_prompt;
 mod repl_web;
+mod review;
+mod route_handlers;
+mod run_expectations;
 mod runtime_context;
 mod self_heal;
+mod templates;
 
 use anyhow::{Context, Result, anyhow};
-use klumo_compiler::{CompileRequest, Compiler, SourceKind};
-use klumo_config::{CliRunOverrides, ProviderSetting};
-use klumo_core::{ProgressMode, compile_file, eval_inline, run_file};
-use klumo_engine::JsEngine;
+use eval_session::EvalSession;
+use klumo_compiler::{
+    CacheHitLog, CachePolicy, CompileRequest, CompileResult, Compiler, DEFAULT_HIT_RATE_WINDOW,
+    GraphModule, ModuleGraph, OutputOptions, OutputTarget, SignaturePolicy, Signed, SigningKey,
+    SourceKind, finish_output, minify, rolling_hit_rate, signature_path, verify_signature_file,
+};
+use klumo_config::{
+    CliRunOverrides, CompileCacheSettings, ProviderSetting, RunDefaults, parse_provider,
+};
+use klumo_core::{
+    JsOutputTarget, ProgressMode, RunOptions, RunOutcome, STDIN_SOURCE_ID, compile_file,
+    compile_graph, compile_source, eval_inline, run_file, run_source, write_generated_js,
+};
+use clap::builder::{PossibleValue, TypedValueParser, ValueParserFactory};
+use clap::error::{ContextKind, ContextValue, ErrorKind};
 use clap::{Parser, Subcommand, ValueEnum};
+use klumo_engine::{ConsoleSink, JsEngine, OutputLimits};
+use klumo_llm::{
+    ProviderMetricsStore, ProviderSelection, ProviderStats, ProviderUsage, SamplingOptions,
+    UsageLog, pick_ollama_model,
+};
+use run_expectations::RunExpectations;
+use runtime_context::KlumoCompiler;
 use serde_json::Value as JsonValue;
-use std::collections::{HashMap, HashSet, VecDeque};
-use std::ffi::OsString;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::ffi::{OsStr, OsString};
+use std::fmt;
 use std::fs;
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Write};
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex, mpsc};
+use std::process::ExitCode;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock, mpsc};
 use std::thread;
 
 const REPL_HISTORY_LIMIT: usize = 20;
@@ -37,9 +81,92 @@ struct ApiRoute {
     status: u16,
     content_type: String,
     body: Vec<u8>,
+    /// Answers requests in place of `body` when set.
+    handler: Option<route_handlers::RouteHandler>,
 }
 
-type SharedApiRoutes = Arc<Mutex<HashMap<String, ApiRoute>>>;
+type SharedApiRoutes = Arc<RwLock<HashMap<String, ApiRoute>>>;
+type SharedPreview = Arc<RwLock<Option<PreviewContext>>>;
+
+/// Compile settings shared with the web daemon so `POST /preview` can translate
+/// editor buffers with the same provider/model as the REPL session.
+#[derive(Clone)]
+struct PreviewContext {
+    compiler: Arc<KlumoCompiler>,
+    default_lang: String,
+    provider_selection: ProviderSelection,
+    model_override: Option<String>,
+    sampling: SamplingOptions,
+    structured_output: bool,
+}
+
+impl fmt::Debug for PreviewContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PreviewContext")
+            .field("default_lang", &self.default_lang)
+            .field("provider_selection", &self.provider_selection)
+            .field("model_override", &self.model_override)
+            .field("sampling", &self.sampling)
+            .field("structured_output", &self.structured_output)
+            .finish_non_exhaustive()
+    }
+}
+
+/// What each REPL line is compiled with, derived from the resolved settings; rebuilt when
+/// `klumo.json` changes mid-session.
+struct ReplCompileSettings {
+    compiler: Arc<KlumoCompiler>,
+    lang: String,
+    provider_selection: ProviderSelection,
+    sampling: SamplingOptions,
+    heal_plan: self_heal::HealPlan,
+    preview: PreviewContext,
+}
+
+impl ReplCompileSettings {
+    fn new(resolved: &RunDefaults, cli_overrides: &CliRunOverrides) -> Result<Self> {
+        let compiler = Arc::new(runtime_context::build_compiler(resolved)?);
+        let lang = resolved
+            .lang
+            .clone()
+            .unwrap_or_else(|| "pseudocode".to_string());
+        let provider_selection = klumo_core::provider_selection(resolved.provider.clone());
+        let sampling = runtime_context::sampling_options(resolved);
+        let heal_plan = self_heal::HealPlan {
+            ladder: resolved.heal_escalation.clone(),
+            provider: cli_overrides
+                .heal_provider
+                .clone()
+                .map(klumo_core::provider_selection),
+            model: cli_overrides.model.clone(),
+        };
+        let preview = PreviewContext {
+            compiler: Arc::clone(&compiler),
+            default_lang: lang.clone(),
+            provider_selection: provider_selection.clone(),
+            model_override: cli_overrides.model.clone(),
+            sampling,
+            structured_output: resolved.structured_output,
+        };
+        Ok(Self {
+            compiler,
+            lang,
+            provider_selection,
+            sampling,
+            heal_plan,
+            preview,
+        })
+    }
+}
+
+/// Hands the web daemon the settings `POST /preview` translates with.
+fn publish_preview(web_server: &WebServerState, preview: PreviewContext) -> Result<()> {
+    *web_server
+        .preview
+        .write()
+        .map_err(|_| anyhow!("failed locking the preview settings"))? = Some(preview);
+    Ok(())
+}
 
 #[derive(Debug)]
 struct WebServerHandle {
@@ -63,6 +190,13 @@ struct WebServerState {
     active: Option<WebServerHandle>,
     last_config: Option<WebServerConfig>,
     api_routes: SharedApiRoutes,
+    /// Swapped when the REPL reloads its configuration; the daemon reads it per request.
+    preview: SharedPreview,
+    notebook: repl_notebook::SharedNotebook,
+    /// Timeout for route handlers registered without their own `timeoutMs`.
+    handler_timeout: std::time::Duration,
+    /// Engines route handlers run on, kept across daemon restarts.
+    handler_pool: Arc<route_handlers::HandlerPool>,
 }
 
 impl Default for WebServerState {
@@ -70,7 +204,13 @@ impl Default for WebServerState {
         Self {
             active: None,
             last_config: None,
-            api_routes: Arc::new(Mutex::new(HashMap::new())),
+            api_routes: Arc::new(RwLock::new(HashMap::new())),
+            preview: Arc::default(),
+            notebook: Arc::default(),
+            handler_timeout: std::time::Duration::from_millis(
+                RunDefaults::default().web_handler_timeout_ms,
+            ),
+            handler_pool: Arc::default(),
         }
     }
 }
@@ -80,22 +220,93 @@ impl Drop for WebServerState {
         if let Some(active) = self.active.as_mut() {
             active.stop();
         }
+        repl_notebook::close_streams(&self.notebook);
+    }
+}
+
+/// Values listed for `--provider` in help and errors; `custom:<name>` picks a provider
+/// declared under `providers` in klumo.json.
+const PROVIDER_ARG_VALUES: &[&str] = &[
+    "auto",
+    "ollama",
+    "openai",
+    "llamacpp",
+    "fixture",
+    "custom:<name>",
+];
+
+/// A `--provider` value, parsed like `KLUMO_PROVIDER` and the `provider` key of klumo.json.
+#[derive(Debug, Clone)]
+struct ProviderArg(ProviderSetting);
+
+impl ProviderArg {
+    fn into_setting(self) -> ProviderSetting {
+        self.0
+    }
+}
+
+impl ValueParserFactory for ProviderArg {
+    type Parser = ProviderArgParser;
+
+    fn value_parser() -> Self::Parser {
+        ProviderArgParser
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ProviderArgParser;
+
+impl TypedValueParser for ProviderArgParser {
+    type Value = ProviderArg;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&clap::Arg>,
+        value: &OsStr,
+    ) -> std::result::Result<ProviderArg, clap::Error> {
+        if let Some(setting) = value.to_str().and_then(parse_provider) {
+            return Ok(ProviderArg(setting));
+        }
+        let mut err = clap::Error::new(ErrorKind::InvalidValue).with_cmd(cmd);
+        err.insert(
+            ContextKind::InvalidArg,
+            ContextValue::String(arg.map(ToString::to_string).unwrap_or_default()),
+        );
+        err.insert(
+            ContextKind::InvalidValue,
+            ContextValue::String(value.to_string_lossy().into_owned()),
+        );
+        err.insert(
+            ContextKind::ValidValue,
+            ContextValue::Strings(
+                PROVIDER_ARG_VALUES
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect(),
+            ),
+        );
+        Err(err)
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        Some(Box::new(PROVIDER_ARG_VALUES.iter().map(PossibleValue::new)))
     }
 }
 
 #[derive(Debug, Clone, Copy, ValueEnum)]
-enum ProviderArg {
-    Auto,
-    Ollama,
-    Openai,
+enum TargetArg {
+    Esm,
+    Cjs,
+    Script,
 }
 
-impl ProviderArg {
-    fn as_setting(self) -> ProviderSetting {
+impl TargetArg {
+    fn as_target(self) -> OutputTarget {
         match self {
-            ProviderArg::Auto => ProviderSetting::Auto,
-            ProviderArg::Ollama => ProviderSetting::Ollama,
-            ProviderArg::Openai => ProviderSetting::Openai,
+            TargetArg::Esm => OutputTarget::Esm,
+            TargetArg::Cjs => OutputTarget::Cjs,
+            TargetArg::Script => OutputTarget::Script,
         }
     }
 }
@@ -111,35 +322,92 @@ struct Cli {
 enum Commands {
     /// Run a file in Klumo.
     Run {
+        /// Script or project script to run; `-` reads the source from stdin (needs a
+        /// language). Without one, starts the REPL.
         file: Option<PathBuf>,
         #[arg(long)]
         config: Option<PathBuf>,
         #[arg(long)]
         lang: Option<String>,
-        #[arg(long)]
-        print_js: bool,
+        /// Print generated JavaScript to stderr (default), stdout, or file:<path>.
+        #[arg(long, value_name = "TARGET", num_args = 0..=1, require_equals = true, default_missing_value = "stderr")]
+        print_js: Option<JsOutputTarget>,
         #[arg(long)]
         no_cache: bool,
         #[arg(long)]
         force_llm: bool,
         #[arg(long)]
         self_heal: bool,
-        #[arg(long, default_value_t = 1)]
-        max_heal_attempts: usize,
+        #[arg(long)]
+        max_heal_attempts: Option<usize>,
+        /// Provider used for self-heal repairs (defaults to the run's provider).
+        #[arg(long)]
+        heal_provider: Option<ProviderArg>,
         #[arg(long)]
         no_progress: bool,
         #[arg(long)]
         verbose: bool,
-        #[arg(long, value_enum)]
+        #[arg(long)]
         provider: Option<ProviderArg>,
         #[arg(long)]
         ollama_url: Option<String>,
+        /// Do not pull a missing Ollama model before translating.
+        #[arg(long)]
+        no_auto_pull: bool,
+        /// Dump every LLM prompt and raw response to timestamped files in this directory.
+        #[arg(long, value_name = "DIR")]
+        llm_log: Option<PathBuf>,
         #[arg(long)]
         model: Option<String>,
+        #[arg(long)]
+        temperature: Option<f32>,
+        #[arg(long)]
+        top_p: Option<f32>,
+        #[arg(long)]
+        max_tokens: Option<u32>,
+        /// Fail unless the program's output (console lines, then the final value) matches.
+        #[arg(long)]
+        expect_output: Option<String>,
+        /// Fail unless the run exits with this code (0 on success, see README for failures).
+        #[arg(long)]
+        expect_exit: Option<i32>,
+        /// Only run JavaScript signed by a key listed in `trusted_keys`.
+        #[arg(long)]
+        verify_signatures: bool,
+        /// Print the process's peak memory usage to stderr after the run.
+        #[arg(long)]
+        report_memory: bool,
+        /// Keep stdout to exactly what the script logs, for piping: the final value is not
+        /// printed and no output is truncated.
+        #[arg(long)]
+        raw_output: bool,
+        /// Print console output in full instead of truncating long entries and runs.
+        #[arg(long)]
+        no_output_limit: bool,
+        /// Translate with temperature 0 and a fixed seed, translating each cache miss twice
+        /// and failing with a diff when the outputs differ.
+        #[arg(long)]
+        deterministic: bool,
+        /// Expected `sha256-<base64>` integrity of a URL given as the file; replaces the pin
+        /// recorded for it.
+        #[arg(long, value_name = "SRI")]
+        integrity: Option<String>,
+        /// Download a URL given as the file without asking first.
+        #[arg(long)]
+        allow_remote: bool,
+        /// Evaluate the program as an ES module (strict mode, import/export, top-level await)
+        /// instead of a classic script. Needs a file: the REPL runs each line as a script.
+        #[arg(long)]
+        module: bool,
     },
     /// Compile a source file into JavaScript.
     Bundle {
-        file: PathBuf,
+        #[arg(required_unless_present = "stdin", conflicts_with = "stdin")]
+        file: Option<PathBuf>,
+        /// Read the source from stdin (needs a language) and, without --output, write the
+        /// bundle to stdout. Relative imports are not followed.
+        #[arg(long)]
+        stdin: bool,
         #[arg(short = 'o', long)]
         output: Option<PathBuf>,
         #[arg(long)]
@@ -154,12 +422,61 @@ enum Commands {
         no_progress: bool,
         #[arg(long)]
         verbose: bool,
-        #[arg(long, value_enum)]
+        #[arg(long)]
         provider: Option<ProviderArg>,
         #[arg(long)]
         ollama_url: Option<String>,
+        /// Do not pull a missing Ollama model before translating.
+        #[arg(long)]
+        no_auto_pull: bool,
+        /// Dump every LLM prompt and raw response to timestamped files in this directory.
+        #[arg(long, value_name = "DIR")]
+        llm_log: Option<PathBuf>,
         #[arg(long)]
         model: Option<String>,
+        #[arg(long)]
+        temperature: Option<f32>,
+        #[arg(long)]
+        top_p: Option<f32>,
+        #[arg(long)]
+        max_tokens: Option<u32>,
+        /// Before writing, remove unused top-level declarations and imports and merge
+        /// duplicate imports in the generated JavaScript.
+        #[arg(long)]
+        lint_fix: bool,
+        /// Write a line-level source map next to the bundle (`<output>.map`) and reference it
+        /// from the bundle.
+        #[arg(long)]
+        source_map: bool,
+        /// Translate with temperature 0 and a fixed seed, translating each cache miss twice
+        /// and failing with a diff when the outputs differ.
+        #[arg(long)]
+        deterministic: bool,
+        /// Module format of the bundle: an ES module or CommonJS module exporting the entry's
+        /// exports, or a classic script. Defaults to a script unless the entry is a lone ES
+        /// module.
+        #[arg(long, value_enum)]
+        target: Option<TargetArg>,
+        /// Start the bundle with a comment naming the provider, model, prompt version and
+        /// build time.
+        #[arg(long)]
+        banner: bool,
+        /// Force strict mode with a leading "use strict" directive.
+        #[arg(long)]
+        strict: bool,
+        /// Minify the bundle: compress it, shorten local names and drop comments and whitespace.
+        #[arg(long)]
+        minify: bool,
+        /// With --minify, also write the unminified bundle to `<output>.debug.js`.
+        #[arg(long, requires = "minify")]
+        keep_debug: bool,
+        /// Keep running and bundle again whenever the file or a module it imports changes.
+        #[arg(long, conflicts_with = "stdin")]
+        watch: bool,
+        /// Modules translated at once, one import level at a time; defaults to the number of
+        /// CPUs. Local providers (Ollama, llama.cpp) still translate one at a time.
+        #[arg(short = 'j', long, value_parser = clap::value_parser!(u32).range(1..))]
+        jobs: Option<u32>,
     },
     /// Install project dependencies from klumo.json.
     #[command(visible_alias = "i")]
@@ -188,31 +505,197 @@ enum Commands {
         #[arg(value_name = "ARGS", allow_hyphen_values = true, trailing_var_arg = true)]
         args: Vec<OsString>,
     },
+    /// Check a bundle against the sources it was built from, using its `.klumo-meta.json`.
+    Verify { bundle: PathBuf },
+    /// Generate an ed25519 key for signing translations and bundles.
+    Keygen {
+        /// Where to write the private key.
+        #[arg(short = 'o', long, default_value = "klumo-signing.key")]
+        output: PathBuf,
+    },
+    /// Approve or reject translations held back by `approval_required`.
+    Review {
+        /// Read `cache_dir` from this config file; approved translations go to that cache.
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// List pending translations without prompting.
+        #[arg(long, conflicts_with_all = ["approve", "reject"])]
+        list: bool,
+        /// Approve the pending translation with this key (or unique key prefix).
+        #[arg(long, value_name = "KEY", conflicts_with = "reject")]
+        approve: Vec<String>,
+        /// Reject the pending translation with this key (or unique key prefix).
+        #[arg(long, value_name = "KEY")]
+        reject: Vec<String>,
+    },
     /// Evaluate inline JavaScript.
-    Eval { code: String },
+    Eval {
+        code: String,
+        /// Restore the named session before evaluating and save it afterwards, so state builds
+        /// up across invocations.
+        #[arg(long)]
+        session: Option<String>,
+        /// Evaluate the code as an ES module; modules have no completion value to print.
+        /// Not available with --session: module bindings do not outlive one evaluation, so
+        /// there is no state to save.
+        #[arg(long, conflicts_with = "session")]
+        module: bool,
+    },
+    /// List models installed on the Ollama server.
+    Models {
+        #[arg(long, global = true)]
+        config: Option<PathBuf>,
+        #[arg(long, global = true)]
+        ollama_url: Option<String>,
+        #[command(subcommand)]
+        action: Option<ModelsAction>,
+    },
+    /// Check every configured provider and print a health report.
+    Doctor {
+        #[arg(long)]
+        config: Option<PathBuf>,
+    },
+    /// Show per-provider success rate, latency, and normalization failures.
+    Stats {
+        /// Clear the recorded provider metrics (the usage log with `--llm`).
+        #[arg(long)]
+        reset: bool,
+        /// Show latency percentiles, failure rates and cache-hit ratios from the opt-in usage
+        /// log (`usage_metrics`).
+        #[arg(long, conflicts_with = "check_hit_rate")]
+        llm: bool,
+        /// Fail when this project's rolling cache hit rate (0.0-1.0) is below the threshold.
+        #[arg(long, value_name = "THRESHOLD")]
+        check_hit_rate: Option<f64>,
+        /// Number of most recent translated compiles the hit rate covers.
+        #[arg(long, value_name = "COMPILES", default_value_t = DEFAULT_HIT_RATE_WINDOW)]
+        window: usize,
+    },
+    /// Manage the compile cache in ~/.klumo/cache/compile, or the project's `cache_dir`.
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    /// Print the import graph of a program, with how each module was compiled.
+    Graph {
+        file: PathBuf,
+        /// `tree` (default) or Graphviz `dot`.
+        #[arg(long, value_enum, default_value_t)]
+        format: module_graph::GraphFormat,
+        #[arg(long)]
+        config: Option<PathBuf>,
+        #[arg(long)]
+        lang: Option<String>,
+        #[arg(long)]
+        no_cache: bool,
+        #[arg(long)]
+        force_llm: bool,
+        #[arg(long)]
+        provider: Option<ProviderArg>,
+        #[arg(long)]
+        model: Option<String>,
+    },
+    /// Show why a file was translated the way it was: the model's rationale and a table of
+    /// original constructs next to the JavaScript they became.
+    Explain {
+        file: PathBuf,
+        #[arg(long)]
+        config: Option<PathBuf>,
+        #[arg(long)]
+        lang: Option<String>,
+        #[arg(long)]
+        force_llm: bool,
+        #[arg(long)]
+        provider: Option<ProviderArg>,
+        #[arg(long)]
+        model: Option<String>,
+    },
+    /// Create a project from a built-in template or a template git repository.
+    Init {
+        /// Directory to create the project in (defaults to the current directory).
+        dir: Option<PathBuf>,
+        /// Built-in template name or git URL (append `#<branch-or-tag>` to pick a ref).
+        #[arg(long, value_name = "NAME|GIT_URL", default_value = templates::DEFAULT_TEMPLATE)]
+        template: String,
+        /// Project name substituted into the template (defaults to the directory name).
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Convert a legacy beeno.json in the current directory into klumo.json.
+    Migrate {
+        #[arg(long)]
+        dry_run: bool,
+    },
     /// Start a JavaScript REPL.
     Repl {
         #[arg(long)]
         config: Option<PathBuf>,
         #[arg(long)]
         lang: Option<String>,
-        #[arg(long)]
-        print_js: bool,
+        /// Print generated JavaScript to stderr (default), stdout, or file:<path>.
+        #[arg(long, value_name = "TARGET", num_args = 0..=1, require_equals = true, default_missing_value = "stderr")]
+        print_js: Option<JsOutputTarget>,
         #[arg(long)]
         no_cache: bool,
         #[arg(long)]
         no_progress: bool,
         #[arg(long)]
         verbose: bool,
-        #[arg(long, value_enum)]
+        #[arg(long)]
         provider: Option<ProviderArg>,
         #[arg(long)]
         ollama_url: Option<String>,
+        /// Do not pull a missing Ollama model before translating.
+        #[arg(long)]
+        no_auto_pull: bool,
+        /// Dump every LLM prompt and raw response to timestamped files in this directory.
+        #[arg(long, value_name = "DIR")]
+        llm_log: Option<PathBuf>,
         #[arg(long)]
         model: Option<String>,
+        #[arg(long)]
+        temperature: Option<f32>,
+        #[arg(long)]
+        top_p: Option<f32>,
+        #[arg(long)]
+        max_tokens: Option<u32>,
+        /// Translate and run one statement before the interactive prompt.
+        #[arg(long)]
+        exec: Option<String>,
+        /// Preload a file into the session before the interactive prompt.
+        #[arg(long)]
+        script: Option<PathBuf>,
+        /// Print console output in full instead of truncating long entries and evaluations.
+        #[arg(long)]
+        no_output_limit: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum CacheAction {
+    /// Remove stale and least recently used entries and report the space reclaimed.
+    Gc {
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Shrink the cache to this many megabytes; defaults to `compile_cache.max_size_mb`.
+        #[arg(long, value_name = "MB")]
+        max_size_mb: Option<u64>,
+        /// Remove entries unused for this many days; defaults to `compile_cache.max_age_days`.
+        #[arg(long, value_name = "DAYS")]
+        max_age_days: Option<u64>,
+        /// Also remove entries written under other prompt versions, which this klumo never
+        /// reads but another klumo version sharing the cache may.
+        #[arg(long)]
+        all_versions: bool,
     },
 }
 
+#[derive(Debug, Subcommand)]
+enum ModelsAction {
+    /// Load a model into Ollama's memory now so the next translation starts at once.
+    Warm { name: String },
+}
+
 fn normalize_cli_args<I>(args: I) -> Vec<OsString>
 where
     I: IntoIterator<Item = OsString>,
@@ -224,8 +707,16 @@ fn warn_predefined_script_collisions() -> Result<()> {
     cli_defaults::warn_predefined_script_collisions()
 }
 
-fn web_server_scope_text(state: &WebServerState) -> String {
-    repl_web::web_server_scope_text(state)
+/// Scope text describing the REPL's host APIs: the web daemon, the clipboard, display and
+/// memory usage.
+fn host_api_scope_text(state: &WebServerState) -> String {
+    format!(
+        "{} {} {} {}",
+        repl_web::web_server_scope_text(state),
+        clipboard::CLIPBOARD_SCOPE_TEXT,
+        display::DISPLAY_SCOPE_TEXT,
+        memory::MEMORY_SCOPE_TEXT
+    )
 }
 
 fn install_repl_web_javascript_api(engine: &mut dyn JsEngine) -> Result<()> {
@@ -260,6 +751,102 @@ fn install_dependencies(config: Option<PathBuf>, dry_run: bool) -> Result<()> {
     project_commands::install_dependencies(config, dry_run)
 }
 
+fn migrate_command(dry_run: bool) -> Result<()> {
+    let cwd = std::env::current_dir().context("failed resolving current directory")?;
+    project_commands::migrate_legacy_config(&cwd, dry_run).map(|_| ())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn graph_command(
+    file: PathBuf,
+    format: module_graph::GraphFormat,
+    config: Option<PathBuf>,
+    lang: Option<String>,
+    no_cache: bool,
+    force_llm: bool,
+    provider: Option<ProviderArg>,
+    model: Option<String>,
+) -> Result<()> {
+    let mut cli_overrides = CliRunOverrides {
+        provider: provider.map(ProviderArg::into_setting),
+        model,
+        lang,
+        force_llm: force_llm.then_some(true),
+        no_cache: no_cache.then_some(true),
+        ..CliRunOverrides::default()
+    };
+    let resolved = runtime_context::resolve_config(config, "graph", &mut cli_overrides)?;
+    let compiler = runtime_context::build_compiler(&resolved)?;
+    let options = runtime_context::build_run_options(
+        &resolved,
+        cli_overrides.model.clone(),
+        JsOutputTarget::default(),
+    );
+    let graph = compile_graph(&compiler, &file, &options)
+        .with_context(|| format!("failed resolving the module graph of {}", file.display()))?;
+    print!("{}", module_graph::render(&graph, format));
+    Ok(())
+}
+
+/// Compiles `file` with `explain` set: a cached explanation is shown as is, and a translation
+/// cached without one is translated again so its entry gains one.
+fn explain_command(
+    file: PathBuf,
+    config: Option<PathBuf>,
+    lang: Option<String>,
+    force_llm: bool,
+    provider: Option<ProviderArg>,
+    model: Option<String>,
+) -> Result<()> {
+    let mut cli_overrides = CliRunOverrides {
+        provider: provider.map(ProviderArg::into_setting),
+        model,
+        lang,
+        force_llm: force_llm.then_some(true),
+        ..CliRunOverrides::default()
+    };
+    let resolved = runtime_context::resolve_config(config, "explain", &mut cli_overrides)?;
+    let compiler = runtime_context::build_compiler(&resolved)?;
+    let options = RunOptions {
+        explain: true,
+        ..runtime_context::build_run_options(
+            &resolved,
+            cli_overrides.model.clone(),
+            JsOutputTarget::default(),
+        )
+    };
+    let compile = compile_file(&compiler, &file, &options)
+        .with_context(|| format!("failed compiling {}", file.display()))?;
+    print!("{}", explain::render(&file, &compile));
+    Ok(())
+}
+
+fn init_command(dir: Option<PathBuf>, template: String, name: Option<String>) -> Result<()> {
+    let cwd = std::env::current_dir().context("failed resolving current directory")?;
+    let dir = dir.map_or_else(|| cwd.clone(), |dir| cwd.join(dir));
+    let name = match name {
+        Some(name) => name,
+        None => dir
+            .canonicalize()
+            .unwrap_or_else(|_| dir.clone())
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or_else(|| anyhow!("pass --name; {} has no directory name", dir.display()))?,
+    };
+    let resolved = runtime_context::resolve_config(None, "init", &mut CliRunOverrides::default())?;
+    let placeholders = templates::Placeholders::new(name, &resolved);
+    let files = templates::init_project(&dir, &template, &placeholders)?;
+    println!(
+        "Created {} from template {template} in {}:",
+        placeholders.project_name,
+        dir.display()
+    );
+    for file in files {
+        println!("  {}", file.display());
+    }
+    Ok(())
+}
+
 fn lint_command(paths: Vec<PathBuf>, fix: bool) -> Result<()> {
     project_commands::lint_command(paths, fix)
 }
@@ -281,44 +868,221 @@ fn run_command(
     file: PathBuf,
     config: Option<PathBuf>,
     lang: Option<String>,
-    print_js: bool,
+    print_js: Option<JsOutputTarget>,
     no_cache: bool,
     force_llm: bool,
     self_heal: bool,
-    max_heal_attempts: usize,
+    max_heal_attempts: Option<usize>,
+    heal_provider: Option<ProviderArg>,
     no_progress: bool,
     verbose: bool,
     provider: Option<ProviderArg>,
     ollama_url: Option<String>,
+    no_auto_pull: bool,
+    llm_log: Option<PathBuf>,
     model: Option<String>,
+    temperature: Option<f32>,
+    top_p: Option<f32>,
+    max_tokens: Option<u32>,
+    verify_signatures: bool,
+    report_memory: bool,
+    raw_output: bool,
+    no_output_limit: bool,
+    deterministic: bool,
+    integrity: Option<String>,
+    allow_remote: bool,
+    module: bool,
+    expectations: RunExpectations,
 ) -> Result<()> {
-    if let Some(script) = resolve_run_script_target(config.as_deref(), &file)? {
+    if raw_output && matches!(print_js, Some(JsOutputTarget::Stdout)) {
+        return Err(anyhow!(
+            "--raw-output keeps stdout for the script; use --print-js stderr or a file"
+        ));
+    }
+    let from_stdin = file.as_os_str() == "-";
+    if !from_stdin && let Some(script) = resolve_run_script_target(config.as_deref(), &file)? {
+        if !expectations.is_empty() {
+            return Err(anyhow!(
+                "--expect-output/--expect-exit are not supported for project scripts"
+            ));
+        }
         let script_name = file.to_string_lossy().to_string();
         return run_script_command(&script_name, &script);
     }
 
-    let cli_overrides = CliRunOverrides {
-        provider: provider.map(ProviderArg::as_setting),
+    let mut cli_overrides = CliRunOverrides {
+        provider: provider.map(ProviderArg::into_setting),
         ollama_url,
+        no_auto_pull: no_auto_pull.then_some(true),
+        llm_log,
         model,
         lang,
         force_llm: force_llm.then_some(true),
-        print_js: print_js.then_some(true),
+        print_js: print_js.is_some().then_some(true),
         no_cache: no_cache.then_some(true),
         verbose: verbose.then_some(true),
         no_progress: no_progress.then_some(true),
+        self_heal: self_heal.then_some(true),
+        max_heal_attempts,
+        heal_provider: heal_provider.map(ProviderArg::into_setting),
+        temperature,
+        top_p,
+        max_tokens,
+        verify_signatures: verify_signatures.then_some(true),
+        deterministic: deterministic.then_some(true),
     };
 
-    let resolved = runtime_context::resolve_config(config, &cli_overrides)?;
+    let mut resolved = runtime_context::resolve_config(config.clone(), "run", &mut cli_overrides)?;
     let compiler = runtime_context::build_compiler(&resolved)?;
-    let options = runtime_context::build_run_options(&resolved, cli_overrides.model.clone());
+    runtime_context::spawn_ollama_warm_up(&resolved, cli_overrides.model.as_deref());
+    let options = RunOptions {
+        module,
+        ..runtime_context::build_run_options(
+            &resolved,
+            cli_overrides.model.clone(),
+            print_js.clone().unwrap_or_default(),
+        )
+    };
+    let output_limits = if no_output_limit || raw_output {
+        OutputLimits::default()
+    } else {
+        runtime_context::output_limits(&resolved, io::stdout().is_terminal())
+    };
+    let self_heal = cli_overrides.self_heal.unwrap_or(false);
+    let max_heal_attempts = cli_overrides.max_heal_attempts.unwrap_or(1);
+    let stdin_source = if from_stdin {
+        if self_heal {
+            return Err(anyhow!(
+                "--self-heal rewrites the file it runs and does not support stdin"
+            ));
+        }
+        Some(read_stdin_source(&options)?)
+    } else {
+        None
+    };
+    let file = if from_stdin {
+        PathBuf::from(STDIN_SOURCE_ID)
+    } else if remote_source::is_remote(&file) {
+        if self_heal {
+            return Err(anyhow!(
+                "--self-heal rewrites the file it runs and does not support remote sources"
+            ));
+        }
+        let store = remote_source::RemoteStore::new(remote_source::remote_dir()?);
+        remote_source::fetch_remote_source(
+            &store,
+            &file.to_string_lossy(),
+            integrity.as_deref(),
+            allow_remote,
+        )?
+    } else if integrity.is_some() {
+        return Err(anyhow!(
+            "--integrity only applies to http:// and https:// sources"
+        ));
+    } else {
+        file
+    };
+    let heal_plan = self_heal::HealPlan {
+        ladder: resolved.heal_escalation.clone(),
+        provider: cli_overrides
+            .heal_provider
+            .map(klumo_core::provider_selection),
+        model: options.model_override.clone(),
+    };
+
+    let result = match run_with_self_heal(
+        &compiler,
+        &file,
+        stdin_source.as_deref(),
+        &options,
+        &heal_plan,
+        self_heal,
+        max_heal_attempts,
+        report_memory,
+        output_limits,
+    ) {
+        Err(err)
+            if provider_setup::should_offer(&err, &resolved)
+                && provider_setup::choose_provider(&mut resolved, config.as_deref())? =>
+        {
+            let compiler = runtime_context::build_compiler(&resolved)?;
+            let options = RunOptions {
+                module,
+                ..runtime_context::build_run_options(
+                    &resolved,
+                    cli_overrides.model.clone(),
+                    print_js.unwrap_or_default(),
+                )
+            };
+            run_with_self_heal(
+                &compiler,
+                &file,
+                stdin_source.as_deref(),
+                &options,
+                &heal_plan,
+                self_heal,
+                max_heal_attempts,
+                report_memory,
+                output_limits,
+            )
+        }
+        result => result,
+    };
+    if let Ok(outcome) = &result
+        && let Some(value) = &outcome.eval.value
+        && !raw_output
+    {
+        println!("{value}");
+    }
+
+    expectations.check(result)
+}
+
+/// Reads a `klumo run -` or `klumo bundle --stdin` source, which has no extension to tell its
+/// language by.
+fn read_stdin_source(options: &RunOptions) -> Result<String> {
+    if options.language_hint.is_none() {
+        return Err(anyhow!(
+            "reading source from stdin needs --lang (or `lang` in klumo.json) to name its language"
+        ));
+    }
+    io::read_to_string(io::stdin()).context("failed reading source from stdin")
+}
 
+/// Runs `file`, or `stdin_source` under the [`STDIN_SOURCE_ID`] `file` then holds.
+#[allow(clippy::too_many_arguments)]
+fn run_with_self_heal(
+    compiler: &KlumoCompiler,
+    file: &Path,
+    stdin_source: Option<&str>,
+    options: &klumo_core::RunOptions,
+    heal_plan: &self_heal::HealPlan,
+    self_heal: bool,
+    max_heal_attempts: usize,
+    report_memory: bool,
+    output_limits: OutputLimits,
+) -> Result<RunOutcome> {
     let mut engine = runtime_context::build_engine()?;
+    engine.set_output_limits(output_limits)?;
+    // Imported files are run as they are, so they need a signature of their own just like
+    // the entry file.
+    if let SignaturePolicy::Verify(keys) = &compiler.signatures {
+        let keys = keys.clone();
+        engine.set_import_check(Rc::new(move |path, text| {
+            verify_signature_file(&keys, path, text).map(drop)
+        }));
+    }
     let mut outcome = None;
     let mut last_err: Option<anyhow::Error> = None;
+    // The source before the first patch, the latest patch's description, and patch count.
+    let mut healed: Option<(String, Option<String>, usize)> = None;
 
     for attempt in 0..=max_heal_attempts {
-        match run_file(engine.as_mut(), &compiler, &file, &options) {
+        let attempt_result = match stdin_source {
+            Some(source) => run_source(engine.as_mut(), compiler, source, STDIN_SOURCE_ID, options),
+            None => run_file(engine.as_mut(), compiler, file, options),
+        };
+        match attempt_result {
             Ok(ok) => {
                 outcome = Some(ok);
                 break;
@@ -327,7 +1091,7 @@ fn run_command(
                 if !self_heal {
                     return Err(err).with_context(|| format!("failed running {}", file.display()));
                 }
-                if !self_heal::is_self_heal_supported_source(&file) {
+                if !self_heal::is_self_heal_supported_source(file) {
                     return Err(err).with_context(|| {
                         format!(
                             "failed running {} (self-heal currently supports .js/.mjs/.cjs/.jsx)",
@@ -349,36 +1113,77 @@ fn run_command(
                     );
                 }
 
-                if let Err(heal_err) =
-                    self_heal::try_self_heal(&compiler, &file, &options, &error_text, attempt)
-                {
-                    return Err(heal_err)
-                        .with_context(|| format!("self-heal failed for {}", file.display()));
+                match self_heal::try_self_heal(
+                    compiler,
+                    file,
+                    options,
+                    heal_plan,
+                    &error_text,
+                    attempt,
+                ) {
+                    Ok(patch) => {
+                        let (before, _, patches) = healed.take().unwrap_or((patch.before, None, 0));
+                        healed = Some((before, patch.description, patches + 1));
+                    }
+                    Err(heal_err) => {
+                        return Err(heal_err)
+                            .with_context(|| format!("self-heal failed for {}", file.display()));
+                    }
                 }
             }
         }
     }
 
-    let outcome = match outcome {
-        Some(value) => value,
+    if outcome.is_some()
+        && let Some((before, description, patches)) = healed
+    {
+        report_heal(file, &before, description, patches, options.progress_mode);
+    }
+
+    if report_memory {
+        engine.collect_garbage();
+        eprintln!(
+            "[klumo] {}",
+            memory::describe_memory_usage(&engine.memory_usage())
+        );
+    }
+
+    match outcome {
+        Some(value) => Ok(value),
         None => {
             let err = last_err
                 .map(|e| format!("{e:#}"))
                 .unwrap_or_else(|| "unknown error".to_string());
-            return Err(anyhow!(
+            Err(anyhow!(
                 "failed running {} after {} self-heal attempts: {}",
                 file.display(),
                 max_heal_attempts,
                 err
-            ));
+            ))
         }
-    };
-
-    if let Some(value) = outcome.eval.value {
-        println!("{value}");
     }
+}
 
-    Ok(())
+/// Prints what the heals that made `file` run changed and appends it to the heal history.
+fn report_heal(
+    file: &Path,
+    before: &str,
+    description: Option<String>,
+    patches: usize,
+    progress_mode: ProgressMode,
+) {
+    let Ok(after) = fs::read_to_string(file) else {
+        return;
+    };
+    let summary = heal_summary::HealSummary::between(file, before, &after, patches, description);
+    if !matches!(progress_mode, ProgressMode::Silent) {
+        eprintln!("{}", summary.render());
+    }
+    if let Err(err) =
+        heal_summary::history_path().and_then(|path| heal_summary::append_history(&path, &summary))
+    {
+        eprintln!("[klumo] warning: failed recording self-heal history: {err:#}");
+    }
 }
 
 fn default_bundle_output(file: &std::path::Path) -> PathBuf {
@@ -387,9 +1192,9 @@ fn default_bundle_output(file: &std::path::Path) -> PathBuf {
     out
 }
 
-#[allow(clippy::too_many_arguments)]
-fn bundle_command(
-    file: PathBuf,
+/// The flags of `klumo bundle`.
+struct BundleCommandOptions {
+    file: Option<PathBuf>,
     output: Option<PathBuf>,
     config: Option<PathBuf>,
     lang: Option<String>,
@@ -399,11 +1204,56 @@ fn bundle_command(
     verbose: bool,
     provider: Option<ProviderArg>,
     ollama_url: Option<String>,
+    no_auto_pull: bool,
+    llm_log: Option<PathBuf>,
     model: Option<String>,
-) -> Result<()> {
-    let cli_overrides = CliRunOverrides {
-        provider: provider.map(ProviderArg::as_setting),
+    temperature: Option<f32>,
+    top_p: Option<f32>,
+    max_tokens: Option<u32>,
+    lint_fix: bool,
+    source_map: bool,
+    deterministic: bool,
+    target: Option<TargetArg>,
+    output_options: OutputOptions,
+    minify: bool,
+    keep_debug: bool,
+    watch: bool,
+    jobs: Option<u32>,
+}
+
+fn bundle_command(options: BundleCommandOptions) -> Result<()> {
+    let BundleCommandOptions {
+        file,
+        output,
+        config,
+        lang,
+        no_cache,
+        force_llm,
+        no_progress,
+        verbose,
+        provider,
         ollama_url,
+        no_auto_pull,
+        llm_log,
+        model,
+        temperature,
+        top_p,
+        max_tokens,
+        lint_fix,
+        source_map,
+        deterministic,
+        target,
+        output_options,
+        minify,
+        keep_debug,
+        watch,
+        jobs,
+    } = options;
+    let mut cli_overrides = CliRunOverrides {
+        provider: provider.map(ProviderArg::into_setting),
+        ollama_url,
+        no_auto_pull: no_auto_pull.then_some(true),
+        llm_log,
         model,
         lang,
         force_llm: force_llm.then_some(true),
@@ -411,115 +1261,878 @@ fn bundle_command(
         no_cache: no_cache.then_some(true),
         verbose: verbose.then_some(true),
         no_progress: no_progress.then_some(true),
+        self_heal: None,
+        max_heal_attempts: None,
+        heal_provider: None,
+        temperature,
+        top_p,
+        max_tokens,
+        verify_signatures: None,
+        deterministic: deterministic.then_some(true),
     };
 
-    let resolved = runtime_context::resolve_config(config, &cli_overrides)?;
-    let compiler = runtime_context::build_compiler(&resolved)?;
-    let options = runtime_context::build_run_options(&resolved, cli_overrides.model.clone());
+    let reloader = watch
+        .then(|| {
+            config_reload::ConfigReloader::new(config.clone(), "bundle", cli_overrides.clone())
+        })
+        .transpose()?;
+    let mut resolved = runtime_context::resolve_config(config, "bundle", &mut cli_overrides)?;
+    let bundle_options = |resolved: &RunDefaults, cli_overrides: &CliRunOverrides| RunOptions {
+        jobs: jobs.map_or_else(
+            || thread::available_parallelism().map_or(1, usize::from),
+            |jobs| jobs as usize,
+        ),
+        ..runtime_context::build_run_options(
+            resolved,
+            cli_overrides.model.clone(),
+            JsOutputTarget::default(),
+        )
+    };
+    let mut options = bundle_options(&resolved, &cli_overrides);
+
+    let settings = BundleSettings {
+        file,
+        output,
+        target: target.map(TargetArg::as_target),
+        lint_fix,
+        source_map,
+        output_options,
+        minify,
+        keep_debug,
+    };
+    let mut compiler = runtime_context::build_compiler(&resolved)?.with_source_maps(source_map);
+    match (&settings.file, reloader) {
+        (Some(file), Some(reloader)) => {
+            bundle_watch::watch(file, options.progress_mode, reloader, |reloaded| {
+                if let Some((reloaded, overrides)) = reloaded {
+                    compiler =
+                        runtime_context::build_compiler(&reloaded)?.with_source_maps(source_map);
+                    options = bundle_options(&reloaded, &overrides);
+                    resolved = reloaded;
+                }
+                build_bundle(&settings, &compiler, &options, &resolved)
+            })
+        }
+        _ => build_bundle(&settings, &compiler, &options, &resolved).map(|_| ()),
+    }
+}
 
-    let compiled = compile_file(&compiler, &file, &options)
-        .with_context(|| format!("failed bundling {}", file.display()))?;
+/// What `klumo bundle` writes, the same for every build of a `--watch` session.
+struct BundleSettings {
+    /// `None` bundles stdin.
+    file: Option<PathBuf>,
+    output: Option<PathBuf>,
+    target: Option<OutputTarget>,
+    lint_fix: bool,
+    source_map: bool,
+    output_options: OutputOptions,
+    minify: bool,
+    /// With `minify`, also write the unminified bundle next to it.
+    keep_debug: bool,
+}
 
-    let target = output.unwrap_or_else(|| default_bundle_output(&file));
-    if let Some(parent) = target.parent() {
-        if !parent.as_os_str().is_empty() {
-            fs::create_dir_all(parent)
-                .with_context(|| format!("failed creating output dir {}", parent.display()))?;
+/// Compiles, links and writes one bundle, returning the files it was built from. A minified
+/// bundle is the linked one run through the minifier, so `--keep-debug` writes the same code
+/// unminified.
+fn build_bundle(
+    settings: &BundleSettings,
+    compiler: &KlumoCompiler,
+    options: &RunOptions,
+    resolved: &RunDefaults,
+) -> Result<Vec<PathBuf>> {
+    let stdin_source = match &settings.file {
+        Some(_) => None,
+        None => Some(read_stdin_source(options)?),
+    };
+    let (graph, mut compiled, fixes) =
+        link_bundle(settings, compiler, stdin_source.as_deref(), options)?;
+    let mut debug = None;
+    if settings.minify {
+        let minified = minify(&compiled.javascript)
+            .with_context(|| format!("failed minifying {}", bundle_name(&settings.file)))?;
+        let unminified = std::mem::replace(&mut compiled.javascript, minified);
+        let source_map = compiled.source_map.take();
+        if settings.keep_debug {
+            debug = Some(CompileResult {
+                javascript: unminified,
+                source_map,
+                ..compiled.clone()
+            });
         }
     }
+    let built_at = std::time::SystemTime::now();
+    finish_output(&mut compiled, settings.output_options, built_at);
+    if let Some(debug) = &mut debug {
+        finish_output(debug, settings.output_options, built_at);
+    }
+
+    let target = match (&settings.output, &settings.file) {
+        (Some(output), _) => output.clone(),
+        (None, Some(file)) => default_bundle_output(file),
+        (None, None) => {
+            write_bundle_to_stdout(&compiled, settings.source_map || debug.is_some(), resolved)?;
+            return Ok(Vec::new());
+        }
+    };
+    if let Some(parent) = target.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed creating output dir {}", parent.display()))?;
+    }
+    if settings.source_map {
+        write_bundle_source_map(&target, &mut compiled)?;
+    }
+
+    fs::write(&target, &compiled.javascript)
+        .with_context(|| format!("failed writing bundle {}", target.display()))?;
+    let debug_target = match &debug {
+        Some(debug) => {
+            let debug_target = debug_bundle_output(&target);
+            fs::write(&debug_target, &debug.javascript).with_context(|| {
+                format!(
+                    "failed writing unminified bundle {}",
+                    debug_target.display()
+                )
+            })?;
+            Some(debug_target)
+        }
+        None => None,
+    };
+    if let Some(key_path) = &resolved.signing_key {
+        let signature = SigningKey::load(key_path)?.sign(Signed::File, &compiled.javascript);
+        let signature_target = signature_path(&target);
+        fs::write(&signature_target, format!("{signature}\n"))
+            .with_context(|| format!("failed writing signature {}", signature_target.display()))?;
+    }
+    let meta_target = bundle_meta::write_meta(
+        &target,
+        &compiled.javascript,
+        &graph,
+        stdin_source.as_deref(),
+        built_at,
+    )?;
 
-    fs::write(&target, &compiled.javascript)
-        .with_context(|| format!("failed writing bundle {}", target.display()))?;
-
     match options.progress_mode {
         ProgressMode::Silent => {}
         ProgressMode::Minimal => {
-            if let Some(provider) = compiled.metadata.provider {
-                let model = compiled.metadata.model.unwrap_or_default();
+            if let Some(provider) = &compiled.metadata.provider {
+                let model = compiled.metadata.model.clone().unwrap_or_default();
                 eprintln!(
                     "[klumo] bundled via {}:{} (cache_hit={})",
-                    format!("{provider:?}").to_ascii_lowercase(),
-                    model,
-                    compiled.metadata.cache_hit
+                    provider, model, compiled.metadata.cache_hit
                 );
             }
+            if let Some(fixes) = &fixes {
+                eprintln!("[klumo] {}", describe_bundle_fixes(fixes));
+            }
             eprintln!("[klumo] wrote bundle {}", target.display());
+            if let Some(debug_target) = &debug_target {
+                eprintln!("[klumo] wrote unminified bundle {}", debug_target.display());
+            }
         }
         ProgressMode::Verbose => {
             eprintln!(
                 "[klumo] bundle compile complete provider={:?} model={:?} cache_hit={}",
                 compiled.metadata.provider, compiled.metadata.model, compiled.metadata.cache_hit
             );
+            if let Some(fixes) = &fixes {
+                eprintln!("[klumo] {}", describe_bundle_fixes(fixes));
+            }
             eprintln!("[klumo] wrote bundle {}", target.display());
+            eprintln!("[klumo] wrote bundle metadata {}", meta_target.display());
+            if let Some(debug_target) = &debug_target {
+                eprintln!("[klumo] wrote unminified bundle {}", debug_target.display());
+            }
         }
     }
 
     println!("{}", target.display());
+    Ok(graph
+        .modules
+        .into_iter()
+        .map(|module| module.path)
+        .collect())
+}
+
+/// Compiles and links the bundle of `settings` with `compiler`, applying `--lint-fix`.
+/// `stdin_source` is the source when `settings` has no file.
+fn link_bundle(
+    settings: &BundleSettings,
+    compiler: &KlumoCompiler,
+    stdin_source: Option<&str>,
+    options: &RunOptions,
+) -> Result<(ModuleGraph, CompileResult, Option<bundle_lint::BundleFixes>)> {
+    let graph = match &settings.file {
+        Some(file) => compile_graph(compiler, file, options),
+        None => compile_source(
+            compiler,
+            stdin_source.unwrap_or_default(),
+            STDIN_SOURCE_ID,
+            options,
+        )
+        .map(|compile| ModuleGraph {
+            modules: vec![GraphModule {
+                path: PathBuf::from(STDIN_SOURCE_ID),
+                compile,
+                imports: Vec::new(),
+            }],
+        }),
+    }
+    .with_context(|| format!("failed bundling {}", bundle_name(&settings.file)))?;
+    let (javascript, source_map) = graph
+        .link_as(settings.target)
+        .with_context(|| format!("failed bundling {}", bundle_name(&settings.file)))?;
+    let mut compiled = graph.entry().compile.clone();
+    compiled.javascript = javascript;
+    compiled.source_map = source_map;
+    let fixes = if settings.lint_fix {
+        let (javascript, fixes) = bundle_lint::fix_bundle(&compiled.javascript);
+        if javascript.lines().count() != compiled.javascript.lines().count() {
+            compiled.source_map = None;
+        }
+        compiled.javascript = javascript;
+        Some(fixes)
+    } else {
+        None
+    };
+    Ok((graph, compiled, fixes))
+}
+
+fn bundle_name(file: &Option<PathBuf>) -> String {
+    file.as_deref().map_or_else(
+        || STDIN_SOURCE_ID.to_string(),
+        |file| file.display().to_string(),
+    )
+}
+
+/// `app.bundle.js` becomes `app.bundle.debug.js`.
+fn debug_bundle_output(target: &Path) -> PathBuf {
+    let mut extension = OsString::from("debug.");
+    extension.push(target.extension().unwrap_or("js".as_ref()));
+    target.with_extension(extension)
+}
+
+/// Bundles of stdin without `--output` go to stdout, where no source map, unminified copy
+/// or signature sidecar can go with them.
+fn write_bundle_to_stdout(
+    compiled: &CompileResult,
+    sidecars: bool,
+    resolved: &RunDefaults,
+) -> Result<()> {
+    if sidecars {
+        return Err(anyhow!(
+            "--source-map and --keep-debug write files next to the bundle; pass --output with --stdin"
+        ));
+    }
+    if resolved.signing_key.is_some() {
+        eprintln!("[klumo] warning: bundle written to stdout is not signed; pass --output");
+    }
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(compiled.javascript.as_bytes())?;
+    if !compiled.javascript.ends_with('\n') {
+        stdout.write_all(b"\n")?;
+    }
     Ok(())
 }
 
-fn eval_command(code: String) -> Result<()> {
-    let mut engine = runtime_context::build_engine()?;
-    let out = eval_inline(engine.as_mut(), &code)?;
+/// Writes `<target>.map` and points the bundle at it with a `sourceMappingURL` comment.
+fn write_bundle_source_map(target: &Path, compiled: &mut CompileResult) -> Result<()> {
+    let Some(source_map) = &compiled.source_map else {
+        eprintln!(
+            "[klumo] warning: no source map for {}; --lint-fix or --minify moved its lines",
+            target.display()
+        );
+        return Ok(());
+    };
+    let mut map_target = target.as_os_str().to_os_string();
+    map_target.push(".map");
+    let map_target = PathBuf::from(map_target);
+    let bundle_name = target
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    fs::write(&map_target, source_map.to_json(&bundle_name))
+        .with_context(|| format!("failed writing source map {}", map_target.display()))?;
+    if !compiled.javascript.ends_with('\n') {
+        compiled.javascript.push('\n');
+    }
+    compiled
+        .javascript
+        .push_str(&format!("//# sourceMappingURL={bundle_name}.map\n"));
+    Ok(())
+}
+
+fn describe_bundle_fixes(fixes: &bundle_lint::BundleFixes) -> String {
+    if fixes.is_empty() {
+        return "lint fixes: nothing to fix".to_string();
+    }
+    let mut parts = Vec::new();
+    if !fixes.removed.is_empty() {
+        parts.push(format!("removed unused {}", fixes.removed.join(", ")));
+    }
+    if fixes.merged_imports > 0 {
+        parts.push(format!(
+            "merged {} duplicate import(s)",
+            fixes.merged_imports
+        ));
+    }
+    format!("lint fixes: {}", parts.join("; "))
+}
+
+fn keygen_command(output: PathBuf) -> Result<()> {
+    let (key, text) = SigningKey::generate()?;
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&output).map_err(|err| {
+        if err.kind() == io::ErrorKind::AlreadyExists {
+            anyhow!(
+                "{} already exists; remove it first to generate a new key",
+                output.display()
+            )
+        } else {
+            anyhow!("failed creating {}: {err}", output.display())
+        }
+    })?;
+    file.write_all(format!("{text}\n").as_bytes())
+        .with_context(|| format!("failed writing {}", output.display()))?;
+    println!("wrote signing key {}", output.display());
+    println!("public key: {}", key.public_key());
+    println!(
+        "Set \"signing_key\" to this file where translations are reviewed, keep it out of version control, and add the public key to \"trusted_keys\" in klumo.json."
+    );
+    Ok(())
+}
+
+/// Set while a saved session is replayed so its console output is not printed again.
+static REPLAYING_SESSION: AtomicBool = AtomicBool::new(false);
+
+/// Console of `eval --session` and the REPL: prints lines as they are logged, except while
+/// a saved session is replayed, and moves a shown REPL prompt below them.
+struct SessionConsole;
+
+impl ConsoleSink for SessionConsole {
+    fn write_line(&self, line: &str) {
+        if !REPLAYING_SESSION.load(Ordering::Relaxed) {
+            repl_prompt::print_line(repl_prompt::Stream::Stdout, line);
+        }
+    }
+}
+
+fn eval_command(code: String, session: Option<String>, module: bool) -> Result<()> {
+    let Some(name) = session else {
+        let mut engine = runtime_context::build_engine()?;
+        engine.set_timer_wait(Some(runtime_context::EVAL_TIMER_WAIT));
+        let out = eval_inline(engine.as_mut(), &code, module)?;
+        if let Some(value) = out.value {
+            println!("{value}");
+        }
+        let pending = engine.pending_timers();
+        if pending > 0 {
+            eprintln!(
+                "[klumo] warning: dropped {pending} timer(s) still pending after {}s; use `klumo run` to wait for them",
+                runtime_context::EVAL_TIMER_WAIT.as_secs()
+            );
+        }
+        return Ok(());
+    };
+
+    let mut session = EvalSession::open(&name)?;
+    let mut engine = runtime_context::build_engine_with_console(SessionConsole)?;
+    engine.set_timer_wait(Some(runtime_context::SESSION_TIMER_WAIT));
+    REPLAYING_SESSION.store(true, Ordering::Relaxed);
+    let restored = session
+        .snippets
+        .iter()
+        .enumerate()
+        .try_for_each(|(idx, snippet)| {
+            eval_inline(engine.as_mut(), snippet, false)
+                .map(drop)
+                .with_context(|| {
+                    format!(
+                        "failed restoring session '{}' at snippet {}",
+                        session.name,
+                        idx + 1
+                    )
+                })
+        });
+    REPLAYING_SESSION.store(false, Ordering::Relaxed);
+    restored?;
+
+    let out = eval_inline(engine.as_mut(), &code, false)?;
+    session.push_and_save(&code)?;
     if let Some(value) = out.value {
         println!("{value}");
     }
     Ok(())
 }
 
+/// Offers to replay the autosave of a REPL that ended abnormally, muting its console output,
+/// and returns the lines restored. Without a terminal to ask on, the autosave is left alone.
+fn restore_repl_autosave(
+    engine: &mut dyn JsEngine,
+    autosave: &repl_autosave::Autosave,
+) -> repl_autosave::SavedSession {
+    let mut restored = repl_autosave::SavedSession::default();
+    if !io::stdin().is_terminal() {
+        return restored;
+    }
+    let Some((path, previous)) = autosave.orphaned() else {
+        return restored;
+    };
+    eprint!(
+        "[klumo] the previous REPL session ended unexpectedly after {} lines. Restore it? [Y/n] ",
+        previous.statements.len()
+    );
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    let _ = io::stdin().read_line(&mut answer);
+    repl_autosave::remove_orphan(&path);
+    if matches!(answer.trim().to_ascii_lowercase().as_str(), "n" | "no") {
+        return restored;
+    }
+
+    REPLAYING_SESSION.store(true, Ordering::Relaxed);
+    for (statement, javascript) in previous.statements.iter().zip(&previous.javascript) {
+        if let Err(err) = engine.eval_script(javascript, "<repl>") {
+            eprintln!(
+                "[klumo] warning: stopped restoring at line {} ({statement}): {err:#}",
+                restored.statements.len() + 1
+            );
+            break;
+        }
+        restored.push(statement, javascript);
+    }
+    REPLAYING_SESSION.store(false, Ordering::Relaxed);
+    eprintln!(
+        "[klumo] restored {} of {} lines",
+        restored.statements.len(),
+        previous.statements.len()
+    );
+    restored
+}
+
+fn models_command(
+    config: Option<PathBuf>,
+    ollama_url: Option<String>,
+    action: Option<ModelsAction>,
+) -> Result<()> {
+    let mut cli_overrides = CliRunOverrides {
+        ollama_url,
+        ..CliRunOverrides::default()
+    };
+    let resolved = runtime_context::resolve_config(config, "models", &mut cli_overrides)?;
+    runtime_context::warn_insecure_tls(&resolved);
+    let client =
+        runtime_context::ollama_client(&resolved, runtime_context::tls_options(&resolved))?;
+    if let Some(ModelsAction::Warm { name }) = action {
+        let elapsed = client.warm_up(&name)?;
+        println!(
+            "Loaded {name} in {:.1}s; Ollama keeps it for {}.",
+            elapsed.as_secs_f64(),
+            resolved
+                .ollama_keep_alive
+                .as_deref()
+                .unwrap_or("its default keep-alive")
+        );
+        return Ok(());
+    }
+    let installed = client.list_models()?;
+    if installed.is_empty() {
+        println!(
+            "No models installed in Ollama at {}. Pull one with `ollama pull {}`.",
+            resolved.ollama_url, resolved.ollama_model
+        );
+        return Ok(());
+    }
+
+    let selected = pick_ollama_model(
+        &installed,
+        &resolved.ollama_model,
+        &resolved.ollama_model_preferences,
+    );
+    for line in render_model_list(&installed, selected.as_deref()) {
+        println!("{line}");
+    }
+    match selected {
+        Some(selected) if selected != resolved.ollama_model => eprintln!(
+            "[klumo] {} is not installed; --provider auto will use {selected}",
+            resolved.ollama_model
+        ),
+        Some(_) => {}
+        None => eprintln!(
+            "[klumo] {} is not installed and no installed model is in ollama_model_preferences; run `ollama pull {}`, set `ollama_auto_pull`, or add one of the models above to `ollama_model_preferences`",
+            resolved.ollama_model, resolved.ollama_model
+        ),
+    }
+    Ok(())
+}
+
+fn cache_gc_command(
+    config: Option<PathBuf>,
+    max_size_mb: Option<u64>,
+    max_age_days: Option<u64>,
+    all_versions: bool,
+) -> Result<()> {
+    let resolved =
+        runtime_context::resolve_config(config, "cache", &mut CliRunOverrides::default())?;
+    let settings = CompileCacheSettings {
+        max_size_mb: max_size_mb.or(resolved.compile_cache.max_size_mb),
+        max_age_days: max_age_days.or(resolved.compile_cache.max_age_days),
+    };
+    let policy = CachePolicy {
+        other_versions: all_versions,
+        ..runtime_context::cache_policy(&settings)
+    };
+    let cache = runtime_context::file_compile_cache(&resolved);
+    let report = cache.gc(policy)?;
+    println!(
+        "Removed {} entries from {}, reclaimed {}; {} entries ({}) remain.",
+        report.removed,
+        cache.root().display(),
+        format_size(report.reclaimed_bytes),
+        report.kept,
+        format_size(report.kept_bytes)
+    );
+    if report.other_versions > 0 {
+        println!(
+            "Left {} entries from other prompt versions; pass --all-versions to remove them.",
+            report.other_versions
+        );
+    }
+    Ok(())
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+/// One line per installed model, with `*` marking the model auto routing will use.
+fn render_model_list(installed: &[String], selected: Option<&str>) -> Vec<String> {
+    installed
+        .iter()
+        .map(|name| {
+            let is_selected = selected.is_some_and(|wanted| {
+                name == wanted
+                    || name
+                        .strip_prefix(wanted)
+                        .is_some_and(|tag| tag.starts_with(':'))
+            });
+            format!("{} {name}", if is_selected { "*" } else { " " })
+        })
+        .collect()
+}
+
+fn doctor_command(config: Option<PathBuf>) -> Result<()> {
+    let resolved =
+        runtime_context::resolve_config(config, "doctor", &mut CliRunOverrides::default())?;
+    let health = doctor::check_providers(&resolved)?;
+    for line in doctor::render_report(&health) {
+        println!("{line}");
+    }
+    let failed = health.iter().filter(|entry| entry.result.is_err()).count();
+    if failed > 0 {
+        return Err(anyhow!(
+            "{failed} of {} providers are unhealthy",
+            health.len()
+        ));
+    }
+    Ok(())
+}
+
+fn stats_command(reset: bool, llm: bool, check_hit_rate: Option<f64>, window: usize) -> Result<()> {
+    if let Some(threshold) = check_hit_rate {
+        return check_hit_rate_command(threshold, window);
+    }
+    if llm {
+        return usage_stats_command(reset);
+    }
+    let store = ProviderMetricsStore {
+        path: ProviderMetricsStore::default_path()?,
+    };
+    if reset {
+        store.reset()?;
+        println!("Cleared provider metrics at {}", store.path.display());
+        return Ok(());
+    }
+    let stats = store.load()?;
+    if stats.is_empty() {
+        println!("No provider calls recorded yet. Run a non-JavaScript file to collect metrics.");
+    } else {
+        for line in render_provider_scoreboard(&stats) {
+            println!("{line}");
+        }
+    }
+    let log = CacheHitLog::for_project(&std::env::current_dir()?)?;
+    if let Some((rate, compiles)) = rolling_hit_rate(&log.load()?, window) {
+        println!(
+            "cache hit rate for {}: {:.1}% over the last {compiles} compiles",
+            log.project(),
+            rate * 100.0
+        );
+    }
+    Ok(())
+}
+
+/// `klumo stats --check-hit-rate`: fails when the current project's recent compiles miss the
+/// cache more often than `threshold` allows, e.g. after a prompt change invalidated it.
+fn check_hit_rate_command(threshold: f64, window: usize) -> Result<()> {
+    if !(0.0..=1.0).contains(&threshold) {
+        return Err(anyhow!(
+            "--check-hit-rate takes a fraction between 0 and 1, got {threshold}"
+        ));
+    }
+    let log = CacheHitLog::for_project(&std::env::current_dir()?)?;
+    let Some((rate, compiles)) = rolling_hit_rate(&log.load()?, window) else {
+        println!(
+            "No translated compiles recorded for {} yet; nothing to check.",
+            log.project()
+        );
+        return Ok(());
+    };
+    println!(
+        "cache hit rate for {}: {:.1}% over the last {compiles} compiles (threshold {:.1}%)",
+        log.project(),
+        rate * 100.0,
+        threshold * 100.0
+    );
+    if rate < threshold {
+        return Err(anyhow!(
+            "cache hit rate {:.1}% is below the threshold {:.1}%",
+            rate * 100.0,
+            threshold * 100.0
+        ));
+    }
+    Ok(())
+}
+
+/// `klumo stats --llm`: the opt-in usage log aggregated per provider.
+fn usage_stats_command(reset: bool) -> Result<()> {
+    let log = UsageLog {
+        path: UsageLog::default_path()?,
+    };
+    if reset {
+        log.reset()?;
+        println!("Cleared usage log at {}", log.path.display());
+        return Ok(());
+    }
+    let events = log.load()?;
+    if events.is_empty() {
+        println!(
+            "No usage recorded yet. Set \"usage_metrics\": true in klumo.json or KLUMO_USAGE_METRICS=1 to collect it locally in {}.",
+            log.path.display()
+        );
+        return Ok(());
+    }
+    for line in render_usage_report(&ProviderUsage::aggregate(&events)) {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+/// A header plus one row per provider, busiest first.
+fn render_usage_report(usage: &BTreeMap<String, ProviderUsage>) -> Vec<String> {
+    let mut rows: Vec<_> = usage.iter().collect();
+    rows.sort_by_key(|(_, entry)| std::cmp::Reverse(entry.calls + entry.cache_hits));
+    let mut lines = vec![format!(
+        "{:<20} {:>6} {:>8} {:>8} {:>8} {:>8} {:>10}",
+        "provider", "calls", "p50", "p90", "p99", "failed", "cache hit"
+    )];
+    lines.extend(rows.into_iter().map(|(name, entry)| {
+        let percentile = |p| {
+            entry
+                .latency_percentile(p)
+                .map_or_else(|| "-".to_string(), |ms| format!("{ms}ms"))
+        };
+        format!(
+            "{name:<20} {:>6} {:>8} {:>8} {:>8} {:>7.1}% {:>9.1}%",
+            entry.calls,
+            percentile(50),
+            percentile(90),
+            percentile(99),
+            entry.failure_rate() * 100.0,
+            entry.cache_hit_ratio() * 100.0
+        )
+    }));
+    lines
+}
+
+/// A header plus one row per provider, most successful first.
+fn render_provider_scoreboard(stats: &BTreeMap<String, ProviderStats>) -> Vec<String> {
+    let mut rows: Vec<_> = stats.iter().collect();
+    rows.sort_by(|a, b| b.1.success_rate().total_cmp(&a.1.success_rate()));
+    let mut lines = vec![format!(
+        "{:<20} {:>6} {:>8} {:>12} {:>10}",
+        "provider", "calls", "success", "mean latency", "norm fail"
+    )];
+    lines.extend(rows.into_iter().map(|(name, entry)| {
+        let latency = entry
+            .mean_latency_ms()
+            .map_or_else(|| "-".to_string(), |ms| format!("{ms}ms"));
+        format!(
+            "{name:<20} {:>6} {:>7.1}% {latency:>12} {:>9.1}%",
+            entry.calls,
+            entry.success_rate() * 100.0,
+            entry.normalization_failure_rate() * 100.0
+        )
+    }));
+    lines
+}
+
 #[allow(clippy::too_many_arguments)]
 fn repl_command(
     config: Option<PathBuf>,
     lang: Option<String>,
-    print_js: bool,
+    print_js: Option<JsOutputTarget>,
     no_cache: bool,
     no_progress: bool,
     verbose: bool,
     provider: Option<ProviderArg>,
     ollama_url: Option<String>,
+    no_auto_pull: bool,
+    llm_log: Option<PathBuf>,
     model: Option<String>,
+    temperature: Option<f32>,
+    top_p: Option<f32>,
+    max_tokens: Option<u32>,
+    exec: Option<String>,
+    script: Option<PathBuf>,
+    no_output_limit: bool,
 ) -> Result<()> {
-    let cli_overrides = CliRunOverrides {
-        provider: provider.map(ProviderArg::as_setting),
+    let mut cli_overrides = CliRunOverrides {
+        provider: provider.map(ProviderArg::into_setting),
         ollama_url,
+        no_auto_pull: no_auto_pull.then_some(true),
+        llm_log,
         model,
         lang,
         force_llm: None,
-        print_js: print_js.then_some(true),
+        print_js: print_js.is_some().then_some(true),
         no_cache: no_cache.then_some(true),
         verbose: verbose.then_some(true),
         no_progress: no_progress.then_some(true),
+        self_heal: None,
+        max_heal_attempts: None,
+        heal_provider: None,
+        temperature,
+        top_p,
+        max_tokens,
+        verify_signatures: None,
+        deterministic: None,
     };
-    let resolved = runtime_context::resolve_config(config, &cli_overrides)?;
-    let compiler = runtime_context::build_compiler(&resolved)?;
-
-    let mut engine = runtime_context::build_engine()?;
+    let mut reloader =
+        config_reload::ConfigReloader::new(config.clone(), "repl", cli_overrides.clone())?;
+    let mut resolved = runtime_context::resolve_config(config, "repl", &mut cli_overrides)?;
+    let ReplCompileSettings {
+        mut compiler,
+        lang: mut repl_lang,
+        mut provider_selection,
+        mut sampling,
+        mut heal_plan,
+        preview,
+    } = ReplCompileSettings::new(&resolved, &cli_overrides)?;
+    runtime_context::spawn_ollama_warm_up(&resolved, cli_overrides.model.as_deref());
+
+    let mut engine = runtime_context::build_engine_with_console(SessionConsole)?;
+    engine.set_timer_wait(Some(runtime_context::SESSION_TIMER_WAIT));
+    if !no_output_limit {
+        // Limits always apply here: every line's output also lands in the session history.
+        engine.set_output_limits(runtime_context::output_limits(&resolved, true))?;
+    }
     install_repl_web_javascript_api(engine.as_mut())?;
+    clipboard::install_clipboard_api(engine.as_mut(), resolved.clipboard)?;
+    display::install_display_api(engine.as_mut())?;
     let baseline_globals = repl_helpers::read_global_names(engine.as_mut())?;
     let mut known_bindings: HashSet<String> = HashSet::new();
     let mut statement_history: VecDeque<String> = VecDeque::new();
     let mut js_history: VecDeque<String> = VecDeque::new();
     let mut web_server = WebServerState::default();
+    web_server.handler_timeout = std::time::Duration::from_millis(resolved.web_handler_timeout_ms);
+
+    let mut autosave = match repl_autosave::Autosave::for_this_process() {
+        Ok(autosave) => Some(autosave),
+        Err(err) => {
+            eprintln!("[klumo] warning: REPL autosave is off: {err:#}");
+            None
+        }
+    };
+    let mut saved_session = autosave
+        .as_ref()
+        .map(|autosave| restore_repl_autosave(engine.as_mut(), autosave))
+        .unwrap_or_default();
+    if !saved_session.statements.is_empty() {
+        for (statement, javascript) in saved_session
+            .statements
+            .iter()
+            .zip(&saved_session.javascript)
+        {
+            repl_helpers::push_bounded(
+                &mut statement_history,
+                statement.clone(),
+                REPL_HISTORY_LIMIT,
+            );
+            repl_helpers::push_bounded(&mut js_history, javascript.clone(), REPL_HISTORY_LIMIT);
+        }
+        if let Ok(current) = repl_helpers::read_global_names(engine.as_mut()) {
+            known_bindings = current
+                .difference(&baseline_globals)
+                .filter(|name| !name.starts_with("__klumo_"))
+                .cloned()
+                .collect();
+        }
+        // Restored lines may have queued `klumo.web` commands, such as starting the daemon.
+        match drain_repl_web_commands(engine.as_mut()) {
+            Ok(commands) => {
+                if let Err(err) = apply_repl_web_commands(commands, &mut web_server) {
+                    eprintln!("error: {err:#}");
+                }
+            }
+            Err(err) => eprintln!("error: failed reading JS web command queue: {err:#}"),
+        }
+        if let Some(saver) = &autosave
+            && let Err(err) = saver.save(&saved_session)
+        {
+            eprintln!("[klumo] warning: REPL autosave is off: {err:#}");
+            autosave = None;
+        }
+    }
     let mut line = String::new();
-    let repl_lang = resolved
-        .lang
-        .clone()
-        .unwrap_or_else(|| "pseudocode".to_string());
-    let provider_selection = runtime_context::provider_to_selection(resolved.provider);
+    let print_js_target = print_js.unwrap_or_default();
+    let mut startup_inputs = repl_helpers::startup_repl_inputs(script.as_deref(), exec.as_deref())?;
     let self_heal_limit = repl_helpers::repl_self_heal_limit();
+    publish_preview(&web_server, preview)?;
 
     println!("Klumo REPL (M2). Type .help for commands, .exit to quit.");
     write_repl_web_status(engine.as_mut(), &web_server)?;
     loop {
         line.clear();
-        print!("klumo> ");
-        io::stdout().flush().context("failed flushing stdout")?;
-
-        let bytes = io::stdin()
-            .read_line(&mut line)
-            .context("failed reading REPL input")?;
-        if bytes == 0 {
-            break;
+        if let Some(input) = startup_inputs.pop_front() {
+            line.push_str(&input);
+        } else {
+            repl_prompt::show_prompt().context("failed flushing stdout")?;
+
+            let read = io::stdin().read_line(&mut line);
+            repl_prompt::prompt_answered();
+            let bytes = read.context("failed reading REPL input")?;
+            if bytes == 0 {
+                break;
+            }
         }
 
         let trimmed = line.trim();
@@ -539,6 +2152,7 @@ fn repl_command(
             println!("  klumo.web.status()");
             println!("  klumo.web.routeJson(path, payload, {{ status }})");
             println!("  klumo.web.routeText(path, text, {{ status, contentType }})");
+            println!("  klumo.web.handle(path, (request) => response, {{ timeoutMs }})");
             println!("  klumo.web.unroute(path)");
             continue;
         }
@@ -555,6 +2169,42 @@ fn repl_command(
             break;
         }
 
+        // Checked before each translation, so an edit made while the prompt waited applies
+        // to the line just entered.
+        if let Some(reloaded) = reloader.poll() {
+            match reloaded.and_then(|(reloaded, overrides)| {
+                let settings = ReplCompileSettings::new(&reloaded, &overrides)?;
+                Ok((reloaded, overrides, settings))
+            }) {
+                Ok((reloaded, overrides, settings)) => {
+                    let preview;
+                    ReplCompileSettings {
+                        compiler,
+                        lang: repl_lang,
+                        provider_selection,
+                        sampling,
+                        heal_plan,
+                        preview,
+                    } = settings;
+                    if let Err(err) = publish_preview(&web_server, preview) {
+                        eprintln!("error: {err:#}");
+                    }
+                    web_server.handler_timeout =
+                        std::time::Duration::from_millis(reloaded.web_handler_timeout_ms);
+                    resolved = reloaded;
+                    cli_overrides = overrides;
+                    eprintln!(
+                        "[klumo] {} changed; reloaded configuration",
+                        reloader.path().display()
+                    );
+                }
+                Err(err) => eprintln!(
+                    "[klumo] warning: {} changed but could not be loaded; keeping the previous configuration: {err:#}",
+                    reloader.path().display()
+                ),
+            }
+        }
+
         let compiled = compiler.compile(&CompileRequest {
             source_text: trimmed.to_string(),
             source_id: "<repl>".to_string(),
@@ -564,16 +2214,27 @@ fn repl_command(
                 &known_bindings,
                 &statement_history,
                 &js_history,
-                Some(&web_server_scope_text(&web_server)),
+                Some(&host_api_scope_text(&web_server)),
             ),
             force_llm: true,
-            provider_selection,
+            provider_selection: provider_selection.clone(),
+            provider_override: None,
             model_override: cli_overrides.model.clone(),
+            model_override_for: None,
+            sampling,
+            structured_output: resolved.structured_output,
+            explain: false,
+            context_window: None,
             no_cache: resolved.no_cache,
         });
 
         let mut candidate_js = match compiled {
             Ok(compiled) => {
+                if resolved.verbose
+                    && let Some(notes) = compiled.metadata.notes.as_deref()
+                {
+                    eprintln!("[klumo] translator notes: {notes}");
+                }
                 let sanitized_js = repl_helpers::sanitize_repl_javascript(&compiled.javascript);
                 if sanitized_js.trim().is_empty() {
                     eprintln!("error: translated REPL code was empty after removing module syntax");
@@ -601,13 +2262,14 @@ fn repl_command(
                         &known_bindings,
                         &statement_history,
                         &js_history,
-                        Some(&web_server_scope_text(&web_server)),
+                        Some(&host_api_scope_text(&web_server)),
                     );
                     match self_heal::compile_repl_heal_candidate(
                         &compiler,
                         &repl_lang,
-                        provider_selection,
-                        cli_overrides.model.clone(),
+                        provider_selection.clone(),
+                        &heal_plan,
+                        sampling,
                         resolved.no_cache,
                         heal_scope,
                         heal_prompt,
@@ -637,10 +2299,10 @@ fn repl_command(
             }
         };
 
-        if resolved.verbose || resolved.print_js {
-            println!("/* ===== generated JavaScript ===== */");
-            println!("{}", candidate_js);
-            println!("/* ===== end generated JavaScript ===== */");
+        if (resolved.verbose || resolved.print_js)
+            && let Err(err) = write_generated_js(&print_js_target, "generated", &candidate_js)
+        {
+            eprintln!("error: {err:#}");
         }
 
         let mut eval_output = None;
@@ -668,13 +2330,14 @@ fn repl_command(
                         &known_bindings,
                         &statement_history,
                         &js_history,
-                        Some(&web_server_scope_text(&web_server)),
+                        Some(&host_api_scope_text(&web_server)),
                     );
                     match self_heal::compile_repl_heal_candidate(
                         &compiler,
                         &repl_lang,
-                        provider_selection,
-                        cli_overrides.model.clone(),
+                        provider_selection.clone(),
+                        &heal_plan,
+                        sampling,
                         resolved.no_cache,
                         heal_scope,
                         heal_prompt,
@@ -682,10 +2345,11 @@ fn repl_command(
                     ) {
                         Ok(healed_js) => {
                             candidate_js = healed_js;
-                            if resolved.verbose || resolved.print_js {
-                                println!("/* ===== healed JavaScript ===== */");
-                                println!("{}", candidate_js);
-                                println!("/* ===== end healed JavaScript ===== */");
+                            if (resolved.verbose || resolved.print_js)
+                                && let Err(err) =
+                                    write_generated_js(&print_js_target, "healed", &candidate_js)
+                            {
+                                eprintln!("error: {err:#}");
                             }
                         }
                         Err(heal_err) => {
@@ -708,6 +2372,18 @@ fn repl_command(
             );
         }
 
+        repl_notebook::publish_cell(
+            &web_server.notebook,
+            &repl_notebook::NotebookCell {
+                input: trimmed.to_string(),
+                javascript: Some(candidate_js.clone()),
+                console: eval_output
+                    .as_ref()
+                    .and_then(|output| output.console_output.clone()),
+                value: eval_output.as_ref().and_then(|output| output.value.clone()),
+                error: final_runtime_error.clone(),
+            },
+        );
         if let Some(output) = eval_output {
             repl_helpers::push_bounded(
                 &mut statement_history,
@@ -715,6 +2391,13 @@ fn repl_command(
                 REPL_HISTORY_LIMIT,
             );
             repl_helpers::push_bounded(&mut js_history, candidate_js.clone(), REPL_HISTORY_LIMIT);
+            saved_session.push(trimmed, &candidate_js);
+            if let Some(saver) = &autosave
+                && let Err(err) = saver.save(&saved_session)
+            {
+                eprintln!("[klumo] warning: REPL autosave is off: {err:#}");
+                autosave = None;
+            }
 
             if let Some(value) = output.value {
                 println!("{value}");
@@ -743,19 +2426,62 @@ fn repl_command(
         }
     }
 
+    if let Some(autosave) = &autosave {
+        autosave.discard();
+    }
     Ok(())
 }
 
+fn main() -> ExitCode {
+    let result = warn_predefined_script_collisions().and_then(|()| {
+        let args = cli_defaults::apply_flag_defaults(normalize_cli_args(std::env::args_os()))?;
+        let cli = Cli::parse_from(args);
+        dispatch::execute(cli)
+    });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            ExitCode::from(exit_codes::exit_code_for(&err))
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-use super::{
-        DEFAULT_WEB_HOST, DEFAULT_WEB_PORT, REPL_HISTORY_LIMIT, normalize_cli_args,
+    use super::{
+        Cli, Commands, DEFAULT_WEB_HOST, DEFAULT_WEB_PORT, ModelsAction, PreviewContext,
+        REPL_HISTORY_LIMIT, WebServerState, normalize_cli_args, render_model_list,
+        render_provider_scoreboard, render_usage_report,
+    };
+    use super::{
+        bundle_lint, bundle_watch, cli_defaults, clipboard, config_reload, display, doctor,
+        explain, heal_summary, memory, module_graph, project_commands, provider_setup,
+        remote_source, repl_autosave, repl_helpers, repl_notebook, repl_web, route_handlers,
+        runtime_context, self_heal, templates,
+    };
+    use klumo_config::{
+        ClipboardSetting, FileConfig, FmtConfig, LintConfig, RunDefaults, TestConfig, ToolSetting,
     };
-    use super::{cli_defaults, project_commands, repl_helpers, repl_web, self_heal};
-    use klumo_config::FileConfig;
-    use std::collections::{HashSet, VecDeque};
+    use klumo_llm::{ProviderSelection, ProviderStats, ProviderUsage, SamplingOptions};
+    use std::collections::{BTreeMap, HashSet, VecDeque};
     use std::ffi::OsString;
-    use std::path::Path;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn passthrough_preview_context() -> PreviewContext {
+        PreviewContext {
+            compiler: Arc::new(
+                runtime_context::build_compiler(&RunDefaults::default()).expect("compiler"),
+            ),
+            default_lang: "javascript".to_string(),
+            provider_selection: ProviderSelection::Auto,
+            model_override: None,
+            sampling: SamplingOptions::default(),
+            structured_output: false,
+        }
+    }
 
     #[test]
     fn repl_scope_context_includes_bindings_and_history() {
@@ -788,6 +2514,225 @@ use super::{
         assert!(ask_open);
     }
 
+    #[test]
+    fn notebook_streams_history_then_new_cells() {
+        use std::io::{BufRead, BufReader, Write};
+
+        let root = tempfile::tempdir().expect("tempdir");
+        let mut state = WebServerState::default();
+        let cell = |input: &str| repl_notebook::NotebookCell {
+            input: input.to_string(),
+            console: Some("hi".to_string()),
+            value: Some("2".to_string()),
+            ..repl_notebook::NotebookCell::default()
+        };
+        repl_notebook::publish_cell(&state.notebook, &cell("say hi"));
+        repl_web::handle_web_command(
+            &format!(
+                ".web start --dir {} --port 0 --no-open",
+                root.path().display()
+            ),
+            &mut state,
+        )
+        .expect("daemon should start");
+        let port = state.active.as_ref().expect("running").config.port;
+
+        let mut stream = std::net::TcpStream::connect(("127.0.0.1", port)).expect("connect");
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .expect("timeout");
+        stream
+            .write_all(b"GET /notebook/events HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .expect("request");
+        let mut reader = BufReader::new(stream);
+        let mut next_data = || loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).expect("event line");
+            if let Some(data) = line.strip_prefix("data: ") {
+                return serde_json::from_str::<serde_json::Value>(data).expect("cell JSON");
+            }
+        };
+        let first = next_data();
+        assert_eq!(first["index"], 0);
+        assert_eq!(first["input"], "say hi");
+        assert_eq!(first["console"], "hi");
+
+        repl_notebook::publish_cell(&state.notebook, &cell("add 1 and 1"));
+        let second = next_data();
+        assert_eq!(second["index"], 1);
+        assert_eq!(second["value"], "2");
+        assert!(second["error"].is_null());
+    }
+
+    #[test]
+    fn route_handlers_answer_requests_and_time_out_with_504() {
+        use std::io::{Read, Write};
+
+        let root = tempfile::tempdir().expect("tempdir");
+        let mut state = WebServerState::default();
+        repl_web::apply_repl_web_commands(
+            vec![
+                serde_json::json!({
+                    "action": "route_handler",
+                    "path": "/api/echo",
+                    "source": "(req) => ({ status: 201, body: { method: req.method, query: req.query, body: req.body } })",
+                }),
+                serde_json::json!({
+                    "action": "route_handler",
+                    "path": "/api/spin",
+                    "source": "() => { while (true) {} }",
+                    "options": { "timeoutMs": 100 },
+                }),
+            ],
+            &mut state,
+        )
+        .expect("handlers should register");
+        repl_web::handle_web_command(
+            &format!(
+                ".web start --dir {} --port 0 --no-open",
+                root.path().display()
+            ),
+            &mut state,
+        )
+        .expect("daemon should start");
+        let port = state.active.as_ref().expect("running").config.port;
+        let send = |request: &str| {
+            let mut stream = std::net::TcpStream::connect(("127.0.0.1", port)).expect("connect");
+            stream.write_all(request.as_bytes()).expect("request");
+            let mut response = String::new();
+            stream.read_to_string(&mut response).expect("response");
+            response
+        };
+
+        let echoed =
+            send("POST /api/echo?x=1 HTTP/1.1\r\nHost: localhost\r\nContent-Length: 2\r\n\r\nhi");
+        assert!(echoed.starts_with("HTTP/1.1 201 Created"), "{echoed}");
+        assert!(echoed.contains("application/json"));
+        assert!(echoed.ends_with(r#"{"method":"POST","query":"x=1","body":"hi"}"#));
+
+        let spun = send("GET /api/spin HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(spun.starts_with("HTTP/1.1 504 Gateway Timeout"), "{spun}");
+        assert!(spun.contains("did not finish within 100 ms"));
+
+        let again = send("GET /api/echo HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(again.contains(r#""method":"GET""#), "{again}");
+    }
+
+    #[test]
+    fn handler_pool_serves_other_routes_while_a_handler_runs() {
+        use std::io::{Read, Write};
+
+        let root = tempfile::tempdir().expect("tempdir");
+        let mut state = WebServerState::default();
+        state.handler_pool = Arc::new(route_handlers::HandlerPool::new(1));
+        repl_web::apply_repl_web_commands(
+            vec![
+                serde_json::json!({
+                    "action": "route_handler",
+                    "path": "/api/spin",
+                    "source": "() => { while (true) {} }",
+                    "options": { "timeoutMs": 1500 },
+                }),
+                serde_json::json!({
+                    "action": "route_handler",
+                    "path": "/api/hello",
+                    "source": "() => 'hello'",
+                }),
+                serde_json::json!({
+                    "action": "route_json",
+                    "path": "/api/health",
+                    "payload": { "ok": true },
+                }),
+            ],
+            &mut state,
+        )
+        .expect("routes should register");
+        repl_web::handle_web_command(
+            &format!(
+                ".web start --dir {} --port 0 --no-open",
+                root.path().display()
+            ),
+            &mut state,
+        )
+        .expect("daemon should start");
+        let port = state.active.as_ref().expect("running").config.port;
+        let send = move |path: &str| {
+            let mut stream = std::net::TcpStream::connect(("127.0.0.1", port)).expect("connect");
+            write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").expect("request");
+            let mut response = String::new();
+            stream.read_to_string(&mut response).expect("response");
+            response
+        };
+
+        let spinning = std::thread::spawn(move || send("/api/spin"));
+        std::thread::sleep(Duration::from_millis(200));
+        let health = send("/api/health");
+        assert!(health.ends_with(r#"{"ok":true}"#), "{health}");
+        assert!(
+            !spinning.is_finished(),
+            "health must not wait for the handler"
+        );
+
+        let spun = spinning.join().expect("spin request");
+        assert!(spun.starts_with("HTTP/1.1 504 Gateway Timeout"), "{spun}");
+        // The only engine is still stuck; a replacement worker answers instead.
+        let hello = send("/api/hello");
+        assert!(hello.starts_with("HTTP/1.1 200 OK"), "{hello}");
+        assert!(hello.ends_with("hello"));
+    }
+
+    #[test]
+    fn web_daemon_turns_away_connections_over_its_limit() {
+        use std::io::{Read, Write};
+
+        let root = tempfile::tempdir().expect("tempdir");
+        let mut state = WebServerState::default();
+        repl_web::handle_web_command(
+            &format!(
+                ".web start --dir {} --port 0 --no-open",
+                root.path().display()
+            ),
+            &mut state,
+        )
+        .expect("daemon should start");
+        let port = state.active.as_ref().expect("running").config.port;
+
+        // Connections that never send a request hold their slots.
+        let idle: Vec<_> = (0..repl_web::MAX_CONNECTIONS)
+            .map(|_| std::net::TcpStream::connect(("127.0.0.1", port)).expect("connect"))
+            .collect();
+        std::thread::sleep(Duration::from_millis(300));
+        let mut extra = std::net::TcpStream::connect(("127.0.0.1", port)).expect("connect");
+        let mut response = String::new();
+        extra.read_to_string(&mut response).expect("response");
+        assert!(
+            response.starts_with("HTTP/1.1 503 Service Unavailable"),
+            "{response}"
+        );
+
+        drop(idle);
+        std::thread::sleep(Duration::from_millis(300));
+        let mut stream = std::net::TcpStream::connect(("127.0.0.1", port)).expect("connect");
+        write!(stream, "GET /missing HTTP/1.1\r\nHost: localhost\r\n\r\n").expect("request");
+        let mut response = String::new();
+        stream.read_to_string(&mut response).expect("response");
+        assert!(response.starts_with("HTTP/1.1 404"), "{response}");
+    }
+
+    #[test]
+    fn handler_pool_caps_workers_stranded_by_timeouts() {
+        let pool = route_handlers::HandlerPool::new(1);
+        let spin = route_handlers::RouteHandler {
+            source: "() => { while (true) {} }".to_string(),
+            timeout: Duration::from_millis(20),
+        };
+        for _ in 0..4 {
+            let response = pool.run("/api/spin", &spin, &serde_json::json!({}));
+            assert_eq!(response.status, 504);
+        }
+        assert!(pool.workers() <= 2, "{} workers", pool.workers());
+    }
+
     #[test]
     fn web_start_parser_supports_flags() {
         let (config, open_override, ask_open) = repl_web::parse_web_start(&[
@@ -813,6 +2758,27 @@ use super::{
         assert!(err.to_string().contains("invalid route path"));
     }
 
+    #[test]
+    fn preview_returns_javascript_and_syntax_diagnostics() {
+        let preview = passthrough_preview_context();
+        let (status, payload) =
+            repl_web::handle_preview_request(br#"{"source":"const = 1;"}"#, &preview);
+        assert_eq!(status, 200);
+        assert_eq!(payload["javascript"], "const = 1;");
+        let message = payload["diagnostics"][0]["message"]
+            .as_str()
+            .expect("diagnostic message");
+        assert!(message.starts_with("SyntaxError"));
+    }
+
+    #[test]
+    fn preview_rejects_missing_source() {
+        let preview = passthrough_preview_context();
+        let (status, payload) = repl_web::handle_preview_request(br#"{"lang":"js"}"#, &preview);
+        assert_eq!(status, 400);
+        assert!(payload["error"].as_str().expect("error").contains("source"));
+    }
+
     #[test]
     fn push_bounded_trims_old_entries() {
         let mut history = VecDeque::new();
@@ -839,6 +2805,364 @@ use super::{
         assert_eq!(backup.to_string_lossy(), "/tmp/demo.js.klumo.bak");
     }
 
+    #[test]
+    fn module_graph_shows_how_each_module_was_compiled() {
+        use klumo_compiler::{
+            CompileMetadata, CompileResult, GraphModule, ModuleGraph, ModuleImport,
+        };
+        use klumo_llm::Provider;
+        let module = |path: &str, provider: Option<Provider>, cache_hit, imports| GraphModule {
+            path: PathBuf::from(path),
+            compile: CompileResult {
+                javascript: String::new(),
+                metadata: CompileMetadata {
+                    provider,
+                    model: Some("qwen".to_string()),
+                    served_model: None,
+                    prompt_version: String::new(),
+                    cache_hit,
+                    notes: None,
+                    confidence: None,
+                    explanation: None,
+                    signature: None,
+                    similarity: None,
+                },
+                source_map: None,
+            },
+            imports,
+        };
+        let import = |specifier: &str, module| ModuleImport {
+            specifier: specifier.to_string(),
+            module,
+        };
+        let graph = ModuleGraph {
+            modules: vec![
+                module("util.js", None, false, Vec::new()),
+                module(
+                    "cart.pseudo",
+                    Some(Provider::Ollama),
+                    true,
+                    vec![import("./util.js", 0)],
+                ),
+                module(
+                    "main.pseudo",
+                    Some(Provider::Ollama),
+                    false,
+                    vec![import("./cart.pseudo", 1), import("./util.js", 0)],
+                ),
+            ],
+        };
+
+        assert_eq!(
+            module_graph::render(&graph, module_graph::GraphFormat::Tree),
+            "main.pseudo [translated by ollama/qwen (fresh)]\n\
+├── cart.pseudo [translated by ollama/qwen (cached)]\n\
+│   └── util.js [passthrough]\n\
+└── util.js (see above)\n\
+\n\
+3 modules: 1 passthrough, 2 translated (1 cached, 1 fresh)\n"
+        );
+        let dot = module_graph::render(&graph, module_graph::GraphFormat::Dot);
+        assert!(dot.starts_with("digraph modules {"));
+        assert!(dot.contains("m2 -> m1 [label=\"./cart.pseudo\"];"));
+        assert!(dot.contains("label=\"cart.pseudo\\ntranslated by ollama/qwen (cached)\""));
+    }
+
+    #[test]
+    fn config_reloader_re_resolves_after_the_file_changes() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("klumo.json");
+        std::fs::write(&path, r#"{"lang": "python"}"#).expect("write config");
+        let mut reloader = config_reload::ConfigReloader::new(
+            Some(path.clone()),
+            "repl",
+            klumo_config::CliRunOverrides::default(),
+        )
+        .expect("reloader");
+        assert!(reloader.poll().is_none(), "nothing changed yet");
+
+        std::fs::write(
+            &path,
+            r#"{"lang": "ruby", "defaults": {"repl": {"no_cache": true}}}"#,
+        )
+        .expect("write config");
+        let (resolved, overrides) = reloader
+            .poll()
+            .expect("the edit is noticed")
+            .expect("the edit loads");
+        assert_eq!(resolved.lang.as_deref(), Some("ruby"));
+        assert!(resolved.no_cache);
+        assert_eq!(overrides.no_cache, Some(true));
+        assert!(reloader.poll().is_none(), "each change is reported once");
+
+        std::fs::write(&path, "{ not json").expect("write config");
+        let err = reloader
+            .poll()
+            .expect("the edit is noticed")
+            .expect_err("broken JSON does not load");
+        assert!(format!("{err:#}").contains("failed parsing config file"));
+        assert!(reloader.poll().is_none());
+    }
+
+    #[test]
+    fn watched_files_report_edits_including_those_made_during_a_build() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let main = dir.path().join("main.js");
+        let util = dir.path().join("util.js");
+        std::fs::write(&main, "import './util.js';").expect("write main");
+        std::fs::write(&util, "1").expect("write util");
+
+        let snapshot = bundle_watch::WatchedFiles::new(vec![main.clone()]);
+        // Edited while the build that discovered `util.js` ran.
+        std::fs::write(&main, "import './util.js'; 2").expect("write main");
+        let watched = snapshot.retarget(vec![util.clone(), main.clone()]);
+        assert_eq!(watched.len(), 2);
+        assert_eq!(watched.changed(), Some(main.as_path()));
+
+        let watched = bundle_watch::WatchedFiles::new(vec![util.clone(), main.clone()]);
+        assert_eq!(watched.changed(), None);
+        std::fs::remove_file(&util).expect("remove util");
+        assert_eq!(watched.changed(), Some(util.as_path()));
+    }
+
+    #[test]
+    fn explain_prints_the_rationale_and_a_mapping_table() {
+        use klumo_compiler::{CompileMetadata, CompileResult};
+        use klumo_llm::{ConstructMapping, Explanation, Provider};
+        let mut compile = CompileResult {
+            javascript: String::new(),
+            metadata: CompileMetadata {
+                provider: Some(Provider::Ollama),
+                model: Some("qwen".to_string()),
+                served_model: None,
+                prompt_version: String::new(),
+                cache_hit: true,
+                notes: None,
+                confidence: None,
+                explanation: Some(Explanation {
+                    rationale: "Loops became for...of.".to_string(),
+                    mappings: vec![
+                        ConstructMapping {
+                            source: "for each item in cart".to_string(),
+                            generated: "for (const item of cart)".to_string(),
+                        },
+                        ConstructMapping {
+                            source: "print\nx".to_string(),
+                            generated: "console.log(x)".to_string(),
+                        },
+                    ],
+                }),
+                signature: None,
+                similarity: None,
+            },
+            source_map: None,
+        };
+        assert_eq!(
+            explain::render(Path::new("cart.pseudo"), &compile),
+            "cart.pseudo [translated by ollama/qwen (cached)]\n\
+\n\
+Rationale:\n  Loops became for...of.\n\
+\n\
+Mappings:\n  Original               Generated\n  for each item in cart  for (const item of cart)\n  \
+print x                console.log(x)\n"
+        );
+
+        compile.metadata.explanation = None;
+        assert!(
+            explain::render(Path::new("cart.pseudo"), &compile)
+                .contains("answered without an explanation")
+        );
+        compile.metadata.provider = None;
+        assert!(explain::render(Path::new("util.js"), &compile).contains("Not translated"));
+    }
+
+    #[test]
+    fn remote_sources_are_urls_pinned_by_sri_digest() {
+        assert!(remote_source::is_remote(Path::new(
+            "https://example.com/a.pseudo"
+        )));
+        assert!(!remote_source::is_remote(Path::new("examples/a.pseudo")));
+        assert_eq!(
+            remote_source::integrity_of(b"abc"),
+            "sha256-ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0="
+        );
+        assert_eq!(
+            remote_source::integrity_of(b""),
+            "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="
+        );
+    }
+
+    #[test]
+    fn init_from_builtin_template_substitutes_and_records_provenance() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let placeholders =
+            templates::Placeholders::new("demo".to_string(), &RunDefaults::default());
+        let files = templates::init_project(dir.path(), "typescript", &placeholders)
+            .expect("init should succeed");
+        assert!(files.contains(&Path::new("src/main.ts").to_path_buf()));
+
+        let main = std::fs::read_to_string(dir.path().join("src/main.ts")).expect("main.ts");
+        assert!(main.contains("greet(\"demo\")"));
+        let config: FileConfig = serde_json::from_str(
+            &std::fs::read_to_string(dir.path().join("klumo.json")).expect("klumo.json"),
+        )
+        .expect("generated klumo.json should parse");
+        assert_eq!(config.name.as_deref(), Some("demo"));
+        assert_eq!(config.provider, Some(klumo_config::ProviderSetting::Auto));
+        assert_eq!(
+            config.template.map(|template| template.source).as_deref(),
+            Some("typescript")
+        );
+
+        let err = templates::init_project(dir.path(), "typescript", &placeholders)
+            .expect_err("existing files should not be overwritten");
+        assert!(err.to_string().contains("already exists"));
+        let err = templates::init_project(dir.path(), "no-such-kit", &placeholders)
+            .expect_err("unknown names should be rejected");
+        assert!(err.to_string().contains("pseudocode:"));
+
+        // Names are escaped inside klumo.json, whose keys keep the template's order.
+        let quoted = tempfile::tempdir().expect("tempdir");
+        let name = r#"say "hi" \ app"#;
+        let placeholders = templates::Placeholders::new(name.to_string(), &RunDefaults::default());
+        templates::init_project(quoted.path(), "pseudocode", &placeholders)
+            .expect("init should succeed");
+        let config: serde_json::Map<String, serde_json::Value> = serde_json::from_str(
+            &std::fs::read_to_string(quoted.path().join("klumo.json")).expect("klumo.json"),
+        )
+        .expect("generated klumo.json should parse");
+        assert_eq!(config["name"], name);
+        assert_eq!(
+            config.keys().collect::<Vec<_>>(),
+            ["name", "version", "provider", "lang", "scripts", "template"]
+        );
+    }
+
+    #[test]
+    fn init_from_git_template_records_the_cloned_revision() {
+        let repo = tempfile::tempdir().expect("tempdir");
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .args([
+                    "-c",
+                    "user.name=klumo",
+                    "-c",
+                    "user.email=klumo@example.com",
+                ])
+                .arg("-C")
+                .arg(repo.path())
+                .args(args)
+                .output()
+                .expect("git should run");
+            assert!(status.status.success(), "git {args:?} failed");
+            String::from_utf8_lossy(&status.stdout).trim().to_string()
+        };
+        git(&["init", "--quiet"]);
+        std::fs::write(
+            repo.path().join("app.js"),
+            "console.log('{{project_name}}');\n",
+        )
+        .expect("write template file");
+        std::fs::write(repo.path().join("run.sh"), "#!/bin/sh\n").expect("write script");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(
+                repo.path().join("run.sh"),
+                std::fs::Permissions::from_mode(0o755),
+            )
+            .expect("chmod script");
+        }
+        git(&["add", "app.js", "run.sh"]);
+        git(&["commit", "--quiet", "-m", "template"]);
+        let revision = git(&["rev-parse", "HEAD"]);
+
+        let target = tempfile::tempdir().expect("tempdir");
+        let url = format!("file://{}", repo.path().display());
+        let placeholders = templates::Placeholders::new("kit".to_string(), &RunDefaults::default());
+        let files = templates::init_project(target.path(), &url, &placeholders)
+            .expect("git template should be fetched");
+        assert_eq!(
+            files,
+            vec![
+                Path::new("app.js").to_path_buf(),
+                Path::new("run.sh").to_path_buf(),
+                Path::new("klumo.json").to_path_buf()
+            ]
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |file: &str| {
+                std::fs::metadata(target.path().join(file))
+                    .expect("metadata")
+                    .permissions()
+                    .mode()
+            };
+            assert_ne!(mode("run.sh") & 0o100, 0, "scripts stay executable");
+            assert_eq!(mode("app.js") & 0o111, 0);
+        }
+        assert_eq!(
+            std::fs::read_to_string(target.path().join("app.js")).expect("app.js"),
+            "console.log('kit');\n"
+        );
+        let config: FileConfig = serde_json::from_str(
+            &std::fs::read_to_string(target.path().join("klumo.json")).expect("klumo.json"),
+        )
+        .expect("generated klumo.json should parse");
+        assert_eq!(config.name.as_deref(), Some("kit"));
+        let template = config.template.expect("provenance should be recorded");
+        assert_eq!(template.source, url);
+        assert_eq!(template.revision, Some(revision));
+
+        // A link could copy any file of the user's into the project.
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("/etc/hostname", repo.path().join("host")).expect("symlink");
+            git(&["add", "host"]);
+            git(&["commit", "--quiet", "-m", "link"]);
+            let linked = tempfile::tempdir().expect("tempdir");
+            let err = templates::init_project(linked.path(), &url, &placeholders)
+                .expect_err("symbolic links should be refused");
+            assert!(err.to_string().contains("symbolic link"), "{err:#}");
+            assert!(!linked.path().join("klumo.json").exists());
+        }
+    }
+
+    #[test]
+    fn heal_summary_counts_lines_and_names_touched_functions() {
+        let before = "function load(path) {\n  return read(path);\n}\n\nconst parse = (text) => {\n  return JSON.parse(text);\n};\nmain();\n";
+        let after = "function load(path) {\n  if (!path) return null;\n  return read(path);\n}\n\nconst parse = (text) => {\n  return JSON.parse(text || '{}');\n};\nmain();\n";
+        let summary = heal_summary::HealSummary::between(
+            Path::new("app.js"),
+            before,
+            after,
+            2,
+            heal_summary::first_sentence("Guarded the empty path. Also fixed parse.\nMore."),
+        );
+        assert_eq!((summary.lines_added, summary.lines_removed), (2, 1));
+        assert_eq!(summary.functions, vec!["load", "parse"]);
+        assert_eq!(
+            summary.render(),
+            "[klumo] self-heal changed app.js in 2 attempts: +2 -1 lines\n\
+             [klumo]   functions touched: load, parse\n\
+             [klumo]   change: Guarded the empty path."
+        );
+
+        let dir = tempfile::tempdir().expect("tempdir");
+        let history = dir.path().join("nested/heal-history.jsonl");
+        heal_summary::append_history(&history, &summary).expect("append");
+        heal_summary::append_history(&history, &summary).expect("append");
+        let lines: Vec<serde_json::Value> = std::fs::read_to_string(&history)
+            .expect("history")
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("json line"))
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["functions"], serde_json::json!(["load", "parse"]));
+        assert_eq!(lines[0]["lines_added"], 2);
+    }
+
     #[test]
     fn self_heal_prompt_contains_error_and_source() {
         let prompt = self_heal::build_self_heal_request(
@@ -875,6 +3199,31 @@ use super::{
         assert!(!repl_helpers::can_continue_self_heal(1, Some(1)));
     }
 
+    #[test]
+    fn startup_repl_inputs_queue_script_before_exec() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let script = dir.path().join("setup.js");
+        std::fs::write(&script, "const greeting = 'hi';\n").expect("write script");
+
+        let inputs = repl_helpers::startup_repl_inputs(Some(&script), Some("print greeting"))
+            .expect("inputs");
+        assert_eq!(
+            Vec::from(inputs),
+            vec![
+                "const greeting = 'hi';\n".to_string(),
+                "print greeting".to_string()
+            ]
+        );
+
+        let missing = repl_helpers::startup_repl_inputs(Some(&dir.path().join("nope.js")), None);
+        assert!(missing.is_err());
+        assert!(
+            repl_helpers::startup_repl_inputs(None, Some("  "))
+                .expect("inputs")
+                .is_empty()
+        );
+    }
+
     #[test]
     fn non_recoverable_error_detection_matches_provider_failures() {
         assert!(repl_helpers::is_non_recoverable_self_heal_error(
@@ -904,6 +3253,88 @@ use super::{
         );
     }
 
+    fn os_args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn lint_config_maps_rules_and_include_per_tool() {
+        let config = LintConfig {
+            tool: None,
+            rules: Some(vec!["eqeqeq".to_string()]),
+            exclude_rules: Some(vec!["no-console".to_string()]),
+            include: Some(vec!["src/".to_string()]),
+        };
+        assert_eq!(
+            project_commands::lint_invocation(ToolSetting::Deno, &config, Vec::new(), false),
+            os_args(&[
+                "lint",
+                "--rules-include=eqeqeq",
+                "--rules-exclude=no-console",
+                "src/"
+            ])
+        );
+        assert_eq!(
+            project_commands::lint_invocation(
+                ToolSetting::Deno,
+                &config,
+                vec!["main.ts".into()],
+                true
+            ),
+            os_args(&[
+                "lint",
+                "--fix",
+                "--rules-include=eqeqeq",
+                "--rules-exclude=no-console",
+                "main.ts"
+            ])
+        );
+        assert_eq!(
+            project_commands::lint_invocation(ToolSetting::Cargo, &config, Vec::new(), false),
+            os_args(&[
+                "clippy",
+                "--all-targets",
+                "--all-features",
+                "--",
+                "-W",
+                "eqeqeq",
+                "-A",
+                "no-console"
+            ])
+        );
+    }
+
+    #[test]
+    fn fmt_and_test_config_shape_invocations() {
+        let fmt = FmtConfig {
+            tool: Some(ToolSetting::Deno),
+            line_width: Some(100),
+            include: None,
+        };
+        assert_eq!(
+            project_commands::fmt_invocation(ToolSetting::Deno, &fmt, Vec::new(), true),
+            os_args(&["fmt", "--check", "--line-width=100"])
+        );
+        assert_eq!(
+            project_commands::fmt_invocation(ToolSetting::Cargo, &fmt, Vec::new(), false),
+            os_args(&["fmt", "--all", "--", "--config", "max_width=100"])
+        );
+
+        let test = TestConfig {
+            tool: None,
+            args: Some(vec!["--allow-read".to_string()]),
+            include: Some(vec!["tests/".to_string()]),
+        };
+        assert_eq!(
+            project_commands::test_invocation(&test, Vec::new()),
+            os_args(&["test", "--allow-read", "tests/"])
+        );
+        assert_eq!(
+            project_commands::test_invocation(&test, os_args(&["only.test.ts"])),
+            os_args(&["test", "--allow-read", "only.test.ts"])
+        );
+    }
+
     #[test]
     fn normalize_cli_args_infers_run_for_bare_file() {
         let args = vec![OsString::from("klumo"), OsString::from("app.js")];
@@ -962,10 +3393,537 @@ use super::{
         std::fs::write(dir.path().join("package.json"), "{}").expect("write package.json");
         assert!(!project_commands::should_prefer_deno_tooling(dir.path()));
     }
+
+    #[test]
+    fn migrate_renames_legacy_keys_and_leaves_scripts_alone() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        std::fs::write(
+            dir.path().join("beeno.json"),
+            r#"{"provider":"ollama","model":"gpt-4o-mini","scripts":{"dev":"beeno run main.ts","docs":"open https://example.com/beeno-guide"}}"#,
+        )
+        .expect("write beeno.json");
+
+        let migration = project_commands::migrate_legacy_config(dir.path(), false)
+            .expect("migrate should work");
+        assert!(!dir.path().join("beeno.json").exists());
+        assert_eq!(migration.renamed, vec![("model", "openai_model")]);
+        assert_eq!(migration.scripts_to_review, vec!["dev", "docs"]);
+        let migrated: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(migration.target).expect("read klumo.json"),
+        )
+        .expect("klumo.json should be valid json");
+        assert_eq!(migrated["provider"], "ollama");
+        assert_eq!(migrated["openai_model"], "gpt-4o-mini");
+        assert!(migrated.get("model").is_none());
+        assert_eq!(migrated["scripts"]["dev"], "beeno run main.ts");
+        assert_eq!(
+            migrated["scripts"]["docs"],
+            "open https://example.com/beeno-guide"
+        );
+    }
+
+    #[test]
+    fn migrate_refuses_a_legacy_key_next_to_its_replacement() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        std::fs::write(
+            dir.path().join("beeno.json"),
+            r#"{"model":"old","openai_model":"new"}"#,
+        )
+        .expect("write beeno.json");
+
+        let err = project_commands::migrate_legacy_config(dir.path(), false)
+            .expect_err("migrate should refuse");
+        assert!(
+            err.to_string()
+                .contains("sets both `model` and its replacement `openai_model`")
+        );
+        assert!(dir.path().join("beeno.json").exists());
+    }
+
+    #[test]
+    fn migrate_reports_every_unrecognised_legacy_key() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        std::fs::write(
+            dir.path().join("beeno.json"),
+            r#"{"model":"gpt-4o-mini","ollama_url":"http://gpu:11434","force_llm":true,"openai_key":"sk","timeout_ms":500}"#,
+        )
+        .expect("write beeno.json");
+
+        let err = project_commands::migrate_legacy_config(dir.path(), true)
+            .expect_err("migrate should refuse");
+        assert!(
+            err.to_string()
+                .contains("does not know: `openai_key`, `timeout_ms`"),
+            "{err}"
+        );
+        assert!(dir.path().join("beeno.json").exists());
+
+        std::fs::write(
+            dir.path().join("beeno.json"),
+            r#"{"model":"gpt-4o-mini","ollama_url":"http://gpu:11434","force_llm":true}"#,
+        )
+        .expect("write beeno.json");
+        let migration = project_commands::migrate_legacy_config(dir.path(), false)
+            .expect("migrate should work");
+        let migrated: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(migration.target).expect("read klumo.json"),
+        )
+        .expect("klumo.json should be valid json");
+        assert_eq!(migrated["openai_model"], "gpt-4o-mini");
+        assert_eq!(migrated["ollama_url"], "http://gpu:11434");
+        assert_eq!(migrated["force_llm"], true);
+    }
+
+    #[test]
+    fn migrate_refuses_to_overwrite_existing_klumo_json() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        std::fs::write(dir.path().join("beeno.json"), "{}").expect("write beeno.json");
+        std::fs::write(dir.path().join("klumo.json"), "{}").expect("write klumo.json");
+
+        let err = project_commands::migrate_legacy_config(dir.path(), false)
+            .expect_err("migrate should refuse");
+        assert!(err.to_string().contains("klumo.json already exists"));
+        assert!(dir.path().join("beeno.json").exists());
+    }
+
+    #[test]
+    fn provider_chooser_reads_menu_answers() {
+        use provider_setup::{ProviderChoice, read_choice};
+        let choose = |answers: &str| {
+            read_choice(&mut std::io::Cursor::new(answers.to_string())).expect("read choice")
+        };
+        assert_eq!(choose("1\n"), Some(ProviderChoice::StartOllama));
+        assert_eq!(
+            choose("9\n2\n\n2\nsk-test\n"),
+            Some(ProviderChoice::OpenAiKey("sk-test".to_string()))
+        );
+        assert_eq!(choose(" 3 \n"), Some(ProviderChoice::Fixture));
+        assert_eq!(choose("q\n"), None);
+        assert_eq!(choose(""), None);
+    }
+
+    #[test]
+    fn provider_choice_is_saved_into_klumo_json() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("klumo.json");
+        provider_setup::persist_provider(&path, "ollama").expect("create klumo.json");
+        std::fs::write(
+            &path,
+            "{\"name\": \"app\", \"provider\": \"auto\", \"lang\": \"python\"}",
+        )
+        .expect("write klumo.json");
+        provider_setup::persist_provider(&path, "fixture").expect("update klumo.json");
+
+        let raw = std::fs::read_to_string(&path).expect("read klumo.json");
+        let saved: FileConfig = serde_json::from_str(&raw).expect("saved config should parse");
+        assert_eq!(saved.name.as_deref(), Some("app"));
+        assert_eq!(saved.provider, Some(klumo_config::ProviderSetting::Fixture));
+        // Keys keep the order they were written in.
+        assert_eq!(
+            raw,
+            "{\n  \"name\": \"app\",\n  \"provider\": \"fixture\",\n  \"lang\": \"python\"\n}\n"
+        );
+    }
+
+    #[test]
+    fn model_list_marks_auto_selection() {
+        let installed = vec!["llama3:latest".to_string(), "codellama:13b".to_string()];
+        assert_eq!(
+            render_model_list(&installed, Some("codellama:13b")),
+            vec!["  llama3:latest".to_string(), "* codellama:13b".to_string()]
+        );
+        assert_eq!(
+            render_model_list(&installed, Some("llama3")),
+            vec!["* llama3:latest".to_string(), "  codellama:13b".to_string()]
+        );
+    }
+
+    #[test]
+    fn clipboard_asks_once_per_access_and_picks_platform_tools() {
+        use clipboard::{ClipboardAccess, ClipboardPermissions, clipboard_commands};
+
+        let mut ask = ClipboardPermissions::new(ClipboardSetting::Ask);
+        assert_eq!(ask.decided(ClipboardAccess::Read), None);
+        ask.remember(ClipboardAccess::Read, true);
+        assert_eq!(ask.decided(ClipboardAccess::Read), Some(true));
+        assert_eq!(ask.decided(ClipboardAccess::Write), None);
+        assert_eq!(
+            ClipboardPermissions::new(ClipboardSetting::Deny).decided(ClipboardAccess::Write),
+            Some(false)
+        );
+
+        let programs = |os, wayland, access| -> Vec<&str> {
+            clipboard_commands(os, wayland, access)
+                .into_iter()
+                .map(|(program, _)| program)
+                .collect()
+        };
+        assert_eq!(programs("macos", false, ClipboardAccess::Write), ["pbcopy"]);
+        assert_eq!(
+            programs("windows", false, ClipboardAccess::Read),
+            ["powershell"]
+        );
+        assert_eq!(
+            programs("linux", true, ClipboardAccess::Read),
+            ["wl-paste", "xclip", "xsel"]
+        );
+        assert_eq!(
+            programs("linux", false, ClipboardAccess::Write),
+            ["xclip", "xsel", "wl-copy"]
+        );
+    }
+
+    #[test]
+    fn denied_clipboard_access_throws_in_the_repl() {
+        let mut engine = runtime_context::build_engine().expect("engine");
+        clipboard::install_clipboard_api(engine.as_mut(), ClipboardSetting::Deny)
+            .expect("clipboard api should install");
+        let out = engine
+            .eval_script(
+                "try { klumo.clipboard.write('{}'); 'written' } catch (e) { e.message }",
+                "repl.js",
+            )
+            .expect("eval should work");
+        let message = out.value.unwrap_or_default();
+        assert!(
+            message.contains("clipboard write access denied"),
+            "{message}"
+        );
+        assert!(message.contains("KLUMO_CLIPBOARD=allow"));
+    }
+
+    #[test]
+    fn bundle_lint_fix_drops_dead_code_and_merges_imports() {
+        let bundle = r#"import { readFile } from "node:fs";
+import { join } from "node:path";
+import { readFile } from "node:fs";
+import { writeFile } from "node:fs";
+const unusedLimit = 10;
+const started = Date.now();
+const url = "http://example.com//x"; // not a comment
+let counter = 0;
+function helper() {
+  return inner();
 }
+function inner() { return "unusedLimit"; }
+const pattern = /[{]/g;
+const message = `total ${counter}`;
+console.log(join("a", "b"), url, message, pattern.test("{"), writeFile);
+"#;
+        let (fixed, fixes) = bundle_lint::fix_bundle(bundle);
+        assert_eq!(
+            fixed,
+            r#"import { writeFile } from "node:fs";
+import { join } from "node:path";
+const started = Date.now();
+const url = "http://example.com//x"; // not a comment
+let counter = 0;
+const pattern = /[{]/g;
+const message = `total ${counter}`;
+console.log(join("a", "b"), url, message, pattern.test("{"), writeFile);
+"#
+        );
+        assert_eq!(
+            fixes.removed,
+            ["helper", "readFile", "inner", "unusedLimit"]
+        );
+        assert_eq!(fixes.merged_imports, 2);
+
+        let (untouched, fixes) =
+            bundle_lint::fix_bundle("const total = 1 + 2;\nconsole.log(total);\n");
+        assert_eq!(untouched, "const total = 1 + 2;\nconsole.log(total);\n");
+        assert!(fixes.is_empty());
+    }
+
+    #[test]
+    fn bundle_lint_fix_keeps_code_sharing_a_line_with_removed_declarations() {
+        let (fixed, fixes) = bundle_lint::fix_bundle("const noop = () => {}; init();\n");
+        assert_eq!(fixed, "init();\n");
+        assert_eq!(fixes.removed, ["noop"]);
+
+        let (fixed, fixes) = bundle_lint::fix_bundle(
+            "function helper() { return 1 } if (ready) { start() }\nready = true; const unused = 1;\n",
+        );
+        assert_eq!(fixed, "if (ready) { start() }\nready = true;\n");
+        assert_eq!(fixes.removed, ["helper", "unused"]);
+    }
+
+    #[test]
+    fn display_table_aligns_columns_in_javascript_key_order() {
+        let rows = serde_json::json!([
+            { "name": "Ada", "age": 36 },
+            { "name": "Grace", "age": 100, "lang": "COBOL" },
+        ]);
+        let order = display::KeyOrder {
+            index: Vec::new(),
+            columns: vec!["name".to_string(), "age".to_string(), "lang".to_string()],
+        };
+        assert_eq!(
+            display::render_table(&rows, &order).expect("table"),
+            "name  | age | lang\n------+-----+------\nAda   |  36 |\nGrace | 100 | COBOL"
+        );
+        assert_eq!(
+            display::render_table(&serde_json::json!({ "b": [1, 2], "a": 3 }), &order)
+                .expect("keyed table"),
+            "(index) | 0 | 1 | value\n--------+---+---+------\nb       | 1 | 2 |\na       |   |   |     3"
+        );
+        assert!(display::render_table(&serde_json::json!("nope"), &order).is_err());
+    }
 
-fn main() -> Result<()> {
-    warn_predefined_script_collisions()?;
-    let cli = Cli::parse_from(normalize_cli_args(std::env::args_os()));
-    dispatch::execute(cli)
+    #[test]
+    fn display_json_highlights_tokens_without_changing_layout() {
+        let text = "{\n  \"ok\": true,\n  \"n\": -1.5e3,\n  \"s\": \"a \\\"b\\\": c\"\n}";
+        assert_eq!(display::highlight_json(text, false), text);
+        let colored = display::highlight_json(text, true);
+        assert!(colored.contains("\x1b[36m\"ok\"\x1b[0m: \x1b[35mtrue\x1b[0m"));
+        assert!(colored.contains("\x1b[33m-1.5e3\x1b[0m"));
+        assert!(colored.contains("\x1b[32m\"a \\\"b\\\": c\"\x1b[0m"));
+
+        let mut engine = runtime_context::build_engine().expect("engine");
+        display::install_display_api(engine.as_mut()).expect("display api should install");
+        let out = engine
+            .eval_script(
+                "klumo.display.table([{ z: 1, a: 2 }]); klumo.display.json({ z: [1], a: {} })",
+                "repl.js",
+            )
+            .expect("eval should work");
+        let printed = out.console_output.unwrap_or_default();
+        assert!(printed.starts_with("z | a\n--+--\n1 | 2\n"), "{printed}");
+        assert!(
+            printed.ends_with("{\n  \"z\": [\n    1\n  ],\n  \"a\": {}\n}"),
+            "{printed}"
+        );
+    }
+
+    #[test]
+    fn repl_autosave_offers_only_sessions_whose_process_is_gone() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let current = repl_autosave::Autosave::in_dir(dir.path().to_path_buf(), std::process::id());
+        let mut mine = repl_autosave::SavedSession::default();
+        mine.push("set y to 1", "var y = 1;");
+        current.save(&mine).expect("save should work");
+        assert_eq!(
+            current.orphaned(),
+            None,
+            "the running session is not offered"
+        );
+
+        let crashed = repl_autosave::Autosave::in_dir(dir.path().to_path_buf(), u32::MAX - 1);
+        let mut lost = repl_autosave::SavedSession::default();
+        lost.push("set x to 2", "var x = 2;");
+        lost.push("print x", "console.log(x);");
+        crashed.save(&lost).expect("save should work");
+        std::fs::write(
+            dir.path().join(format!("{}.json", u32::MAX - 2)),
+            "{ not json",
+        )
+        .expect("write");
+        #[cfg(unix)]
+        {
+            let live = repl_autosave::Autosave::in_dir(
+                dir.path().to_path_buf(),
+                std::os::unix::process::parent_id(),
+            );
+            live.save(&mine).expect("save should work");
+        }
+
+        let (path, session) = current.orphaned().expect("the crashed session is offered");
+        assert_eq!(session, lost);
+        repl_autosave::remove_orphan(&path);
+        assert_eq!(current.orphaned(), None);
+
+        current.discard();
+        assert!(
+            !dir.path()
+                .join(format!("{}.json", std::process::id()))
+                .exists()
+        );
+    }
+
+    #[test]
+    fn memory_usage_api_is_installed_on_every_engine() {
+        let mut engine = runtime_context::build_engine().expect("engine");
+        let out = engine
+            .eval_script(
+                "const usage = klumo.memoryUsage(); Object.keys(usage).join(',') + ':' + usage.heapBytes",
+                "memory.js",
+            )
+            .expect("eval should work");
+        assert_eq!(
+            out.value.as_deref(),
+            Some("heapBytes,objects,residentBytes,peakResidentBytes:null")
+        );
+        if cfg!(target_os = "linux") {
+            let resident = engine
+                .eval_script("klumo.memoryUsage().residentBytes > 0", "memory.js")
+                .expect("eval should work");
+            assert_eq!(resident.value.as_deref(), Some("true"));
+        }
+
+        let usage = klumo_engine::MemoryUsage {
+            resident_bytes: Some(3 * 1024 * 1024),
+            peak_resident_bytes: Some(5 * 1024 * 1024 + 512 * 1024),
+            ..Default::default()
+        };
+        assert_eq!(
+            memory::describe_memory_usage(&usage),
+            "memory: peak resident 5.5 MiB, resident 3.0 MiB"
+        );
+        assert_eq!(
+            memory::describe_memory_usage(&klumo_engine::MemoryUsage::default()),
+            "memory: usage is not available on this platform"
+        );
+    }
+
+    #[test]
+    fn models_warm_takes_a_name_and_the_shared_options() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from([
+            "klumo",
+            "models",
+            "warm",
+            "qwen2.5-coder:7b",
+            "--ollama-url",
+            "http://gpu-box:11434",
+        ])
+        .expect("models warm should parse");
+        let Some(Commands::Models {
+            ollama_url, action, ..
+        }) = cli.command
+        else {
+            panic!("expected the models command");
+        };
+        assert_eq!(ollama_url.as_deref(), Some("http://gpu-box:11434"));
+        assert!(matches!(
+            action,
+            Some(ModelsAction::Warm { name }) if name == "qwen2.5-coder:7b"
+        ));
+
+        let cli = Cli::try_parse_from(["klumo", "models"]).expect("models should parse");
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Models { action: None, .. })
+        ));
+        assert!(Cli::try_parse_from(["klumo", "models", "warm"]).is_err());
+    }
+
+    #[test]
+    fn doctor_report_lists_status_and_latency() {
+        let lines = doctor::render_report(&[
+            doctor::ProviderHealth {
+                name: "ollama".to_string(),
+                endpoint: "http://127.0.0.1:11434".to_string(),
+                latency: Duration::from_millis(12),
+                result: Ok("2 models, qwen2.5-coder:7b installed".to_string()),
+            },
+            doctor::ProviderHealth {
+                name: "openai-compatible".to_string(),
+                endpoint: "https://api.openai.com/v1".to_string(),
+                latency: Duration::ZERO,
+                result: Err("OPENAI_API_KEY is not set".to_string()),
+            },
+        ]);
+        assert_eq!(
+            lines,
+            vec![
+                "ok   ollama               http://127.0.0.1:11434 (12ms) 2 models, qwen2.5-coder:7b installed",
+                "FAIL openai-compatible    https://api.openai.com/v1 (0ms) OPENAI_API_KEY is not set",
+            ]
+        );
+    }
+
+    #[test]
+    fn provider_scoreboard_ranks_by_success_rate() {
+        let stats = BTreeMap::from([
+            (
+                "ollama".to_string(),
+                ProviderStats {
+                    calls: 4,
+                    successes: 1,
+                    normalization_failures: 2,
+                    success_latency_ms: 900,
+                },
+            ),
+            (
+                "openai-compatible".to_string(),
+                ProviderStats {
+                    calls: 2,
+                    successes: 2,
+                    normalization_failures: 0,
+                    success_latency_ms: 3000,
+                },
+            ),
+        ]);
+        let lines = render_provider_scoreboard(&stats);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("provider"));
+        assert!(lines[1].starts_with("openai-compatible"));
+        assert!(lines[1].contains("100.0%") && lines[1].contains("1500ms"));
+        assert!(lines[2].starts_with("ollama"));
+        assert!(lines[2].contains("25.0%") && lines[2].contains("900ms"));
+        assert!(lines[2].ends_with("50.0%"));
+    }
+
+    #[test]
+    fn usage_report_shows_percentiles_failures_and_cache_hits() {
+        let usage = BTreeMap::from([
+            (
+                "ollama".to_string(),
+                ProviderUsage {
+                    calls: 4,
+                    failures: 1,
+                    cache_hits: 4,
+                    latencies_ms: vec![200, 400, 900],
+                },
+            ),
+            ("openai-compatible".to_string(), ProviderUsage::default()),
+        ]);
+        let lines = render_usage_report(&usage);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("provider"));
+        assert!(lines[1].starts_with("ollama"));
+        assert!(lines[1].contains("400ms") && lines[1].contains("900ms"));
+        assert!(lines[1].contains("25.0%") && lines[1].ends_with("50.0%"));
+        assert!(lines[2].starts_with("openai-compatible"));
+        assert!(lines[2].contains(" - "));
+    }
+
+    #[test]
+    fn heal_plan_escalates_along_configured_ladder() {
+        let plan = self_heal::HealPlan {
+            ladder: serde_json::from_str(
+                r#"[{"provider":"ollama","model":"small","attempts":2},{"provider":"openai"}]"#,
+            )
+            .expect("ladder should parse"),
+            provider: None,
+            model: Some("session-model".to_string()),
+        };
+
+        assert_eq!(
+            plan.for_attempt(1),
+            (Some(ProviderSelection::Ollama), Some("small".to_string()))
+        );
+        assert_eq!(
+            plan.for_attempt(2),
+            (
+                Some(ProviderSelection::OpenAiCompatible),
+                Some("session-model".to_string())
+            )
+        );
+        assert_eq!(
+            plan.describe_attempt(2).as_deref(),
+            Some("openai (session-model)")
+        );
+
+        let flat = self_heal::HealPlan {
+            provider: Some(ProviderSelection::OpenAiCompatible),
+            ..self_heal::HealPlan::default()
+        };
+        assert_eq!(
+            flat.for_attempt(5),
+            (Some(ProviderSelection::OpenAiCompatible), None)
+        );
+        assert_eq!(flat.describe_attempt(0), None);
+    }
 }
@@ -0,0 +1,58 @@
+use anyhow::Result;
+use klumo_engine::{JsEngine, MemoryUsage};
+
+/// REPL scope text telling the model how to check memory.
+pub(crate) const MEMORY_SCOPE_TEXT: &str = "klumo.memoryUsage() returns { heapBytes, objects, residentBytes, peakResidentBytes } in bytes, with null for figures the platform cannot measure.";
+
+/// Exposes `klumo.memoryUsage()` to scripts. Host functions cannot reach the engine that
+/// calls them, so scripts see the process figures; Boa reports nothing more.
+pub(crate) fn install_memory_api(engine: &mut dyn JsEngine) -> Result<()> {
+    if let Err(err) = engine.register_host_function("__klumo_memory_usage", host_memory_usage) {
+        eprintln!("[klumo] klumo.memoryUsage is unavailable: {err}");
+        return Ok(());
+    }
+    engine.eval_script(
+        r#"
+globalThis.klumo = globalThis.klumo || {};
+globalThis.klumo.memoryUsage = () => JSON.parse(__klumo_memory_usage());
+"#,
+        "<memory-api>",
+    )?;
+    Ok(())
+}
+
+fn host_memory_usage(_args: &[String]) -> std::result::Result<Option<String>, String> {
+    Ok(Some(MemoryUsage::process().to_json()))
+}
+
+/// One-line summary for `--report-memory`, e.g. `memory: peak resident 41.2 MiB, resident
+/// 39.8 MiB`.
+pub(crate) fn describe_memory_usage(usage: &MemoryUsage) -> String {
+    let mut parts = Vec::new();
+    if let Some(peak) = usage.peak_resident_bytes {
+        parts.push(format!("peak resident {}", format_bytes(peak)));
+    }
+    if let Some(resident) = usage.resident_bytes {
+        parts.push(format!("resident {}", format_bytes(resident)));
+    }
+    if let Some(heap) = usage.heap_bytes {
+        parts.push(format!("heap {}", format_bytes(heap)));
+    }
+    if let Some(objects) = usage.objects {
+        parts.push(format!("{objects} objects"));
+    }
+    if parts.is_empty() {
+        return "memory: usage is not available on this platform".to_string();
+    }
+    format!("memory: {}", parts.join(", "))
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const KIB: f64 = 1024.0;
+    let bytes = bytes as f64;
+    if bytes >= KIB * KIB {
+        format!("{:.1} MiB", bytes / (KIB * KIB))
+    } else {
+        format!("{:.1} KiB", bytes / KIB)
+    }
+}
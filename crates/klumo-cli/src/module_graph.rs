@@ -0,0 +1,141 @@
+use clap::ValueEnum;
+use klumo_compiler::{CompileResult, ModuleGraph};
+use std::fmt::Write;
+
+/// How `klumo graph` prints the module graph.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub(crate) enum GraphFormat {
+    /// An indented import tree rooted at the entry.
+    #[default]
+    Tree,
+    /// A Graphviz `digraph`, for `dot -Tsvg`.
+    Dot,
+}
+
+pub(crate) fn render(graph: &ModuleGraph, format: GraphFormat) -> String {
+    match format {
+        GraphFormat::Tree => render_tree(graph),
+        GraphFormat::Dot => render_dot(graph),
+    }
+}
+
+/// How a module got its JavaScript, e.g. `passthrough` or `translated by ollama/qwen (cached)`.
+pub(crate) fn describe(compile: &CompileResult) -> String {
+    let metadata = &compile.metadata;
+    let Some(provider) = &metadata.provider else {
+        return "passthrough".to_string();
+    };
+    let cache = if metadata.cache_hit {
+        "cached"
+    } else {
+        "fresh"
+    };
+    match &metadata.model {
+        Some(model) => format!("translated by {provider}/{model} ({cache})"),
+        None => format!("translated by {provider} ({cache})"),
+    }
+}
+
+/// One line per import below the entry; a module imported again is shown once and then
+/// marked `(see above)`, which also ends import cycles.
+fn render_tree(graph: &ModuleGraph) -> String {
+    let mut out = String::new();
+    let mut shown = vec![false; graph.modules.len()];
+    let entry = graph.modules.len() - 1;
+    tree_line(graph, entry, "", "", &mut shown, &mut out);
+    let _ = writeln!(out, "\n{}", summary(graph));
+    out
+}
+
+fn tree_line(
+    graph: &ModuleGraph,
+    index: usize,
+    prefix: &str,
+    branch: &str,
+    shown: &mut [bool],
+    out: &mut String,
+) {
+    let module = &graph.modules[index];
+    if shown[index] {
+        let _ = writeln!(out, "{prefix}{branch}{} (see above)", module.path.display());
+        return;
+    }
+    shown[index] = true;
+    let _ = writeln!(
+        out,
+        "{prefix}{branch}{} [{}]",
+        module.path.display(),
+        describe(&module.compile)
+    );
+    let child_prefix = match branch {
+        "" => prefix.to_string(),
+        "└── " => format!("{prefix}    "),
+        _ => format!("{prefix}│   "),
+    };
+    for (position, import) in module.imports.iter().enumerate() {
+        let branch = if position + 1 == module.imports.len() {
+            "└── "
+        } else {
+            "├── "
+        };
+        tree_line(graph, import.module, &child_prefix, branch, shown, out);
+    }
+}
+
+/// Module count and where their JavaScript came from: what building the graph cost.
+fn summary(graph: &ModuleGraph) -> String {
+    let translated: Vec<&CompileResult> = graph
+        .modules
+        .iter()
+        .map(|module| &module.compile)
+        .filter(|compile| compile.metadata.provider.is_some())
+        .collect();
+    let cached = translated
+        .iter()
+        .filter(|compile| compile.metadata.cache_hit)
+        .count();
+    format!(
+        "{} modules: {} passthrough, {} translated ({} cached, {} fresh)",
+        graph.modules.len(),
+        graph.modules.len() - translated.len(),
+        translated.len(),
+        cached,
+        translated.len() - cached
+    )
+}
+
+/// Translated modules are filled, darker when the translation was fresh; edges are labelled
+/// with the specifier as written.
+fn render_dot(graph: &ModuleGraph) -> String {
+    let mut out = String::from("digraph modules {\n  node [shape=box];\n");
+    for (index, module) in graph.modules.iter().enumerate() {
+        let metadata = &module.compile.metadata;
+        let style = match (metadata.provider.is_some(), metadata.cache_hit) {
+            (false, _) => "",
+            (true, true) => ", style=filled, fillcolor=\"#dbeafe\"",
+            (true, false) => ", style=filled, fillcolor=\"#fde68a\"",
+        };
+        let _ = writeln!(
+            out,
+            "  m{index} [label=\"{}\\n{}\"{style}];",
+            escape(&module.path.display().to_string()),
+            escape(&describe(&module.compile))
+        );
+    }
+    for (index, module) in graph.modules.iter().enumerate() {
+        for import in &module.imports {
+            let _ = writeln!(
+                out,
+                "  m{index} -> m{} [label=\"{}\"];",
+                import.module,
+                escape(&import.specifier)
+            );
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -1,6 +1,11 @@
 use anyhow::{Context, Result, anyhow};
-use klumo_config::{FileConfig, load_file_config};
+use klumo_config::{
+    FileConfig, FmtConfig, LintConfig, TestConfig, ToolSetting, is_file_config_key,
+    load_file_config,
+};
+use serde_json::Value as JsonValue;
 use std::ffi::OsString;
+use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
@@ -124,6 +129,119 @@ pub(crate) fn install_dependencies(config: Option<PathBuf>, dry_run: bool) -> Re
     Ok(())
 }
 
+/// `beeno.json` keys that `klumo.json` spells differently, with their new names. Beeno read
+/// every other setting under the name `klumo.json` uses, so those are carried over unchanged;
+/// keys that are neither are reported rather than copied.
+const LEGACY_CONFIG_KEYS: &[(&str, &str)] = &[("model", "openai_model")];
+
+/// What `klumo migrate` changed, or would change with `--dry-run`.
+#[derive(Debug)]
+pub(crate) struct Migration {
+    pub(crate) target: PathBuf,
+    /// Legacy keys that were renamed, as `(old, new)`.
+    pub(crate) renamed: Vec<(&'static str, &'static str)>,
+    /// Scripts whose command still mentions `beeno`. They are copied as they are, since only
+    /// their author knows which mentions are invocations and which are paths or URLs.
+    pub(crate) scripts_to_review: Vec<String>,
+}
+
+/// Rewrites a legacy `beeno.json` in `dir` into `klumo.json`, renaming the keys listed in
+/// [`LEGACY_CONFIG_KEYS`].
+pub(crate) fn migrate_legacy_config(dir: &Path, dry_run: bool) -> Result<Migration> {
+    let legacy_path = dir.join("beeno.json");
+    let target_path = dir.join("klumo.json");
+    if !legacy_path.exists() {
+        return Err(anyhow!("beeno.json not found in {}", dir.display()));
+    }
+    if target_path.exists() {
+        return Err(anyhow!(
+            "klumo.json already exists in {}; remove it or merge beeno.json by hand",
+            dir.display()
+        ));
+    }
+
+    let raw = fs::read_to_string(&legacy_path)
+        .with_context(|| format!("failed reading {}", legacy_path.display()))?;
+    let mut value: JsonValue = serde_json::from_str(&raw)
+        .with_context(|| format!("failed parsing {}", legacy_path.display()))?;
+    let fields = value
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("{} must contain a JSON object", legacy_path.display()))?;
+    let mut renamed = Vec::new();
+    for &(old, new) in LEGACY_CONFIG_KEYS {
+        let Some(setting) = fields.remove(old) else {
+            continue;
+        };
+        if fields.contains_key(new) {
+            return Err(anyhow!(
+                "{} sets both `{old}` and its replacement `{new}`; keep one and run klumo migrate again",
+                legacy_path.display()
+            ));
+        }
+        fields.insert(new.to_string(), setting);
+        renamed.push((old, new));
+    }
+    let mut unrecognised: Vec<&str> = fields
+        .keys()
+        .map(String::as_str)
+        .filter(|key| !is_file_config_key(key))
+        .collect();
+    unrecognised.sort_unstable();
+    if !unrecognised.is_empty() {
+        return Err(anyhow!(
+            "{} has settings klumo.json does not know: {}; rename or remove them and run klumo migrate again",
+            legacy_path.display(),
+            unrecognised
+                .iter()
+                .map(|key| format!("`{key}`"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    let scripts_to_review = fields
+        .get("scripts")
+        .and_then(JsonValue::as_object)
+        .map(|scripts| {
+            scripts
+                .iter()
+                .filter(|(_, command)| command.as_str().is_some_and(|line| line.contains("beeno")))
+                .map(|(name, _)| name.clone())
+                .collect()
+        })
+        .unwrap_or_default();
+    serde_json::from_value::<FileConfig>(value.clone())
+        .with_context(|| format!("{} is not a valid klumo config", legacy_path.display()))?;
+
+    let rendered = serde_json::to_string_pretty(&value)?;
+    let migration = Migration {
+        target: target_path,
+        renamed,
+        scripts_to_review,
+    };
+    for (old, new) in &migration.renamed {
+        println!("renamed `{old}` to `{new}`");
+    }
+    for name in &migration.scripts_to_review {
+        println!("script `{name}` mentions beeno and was left unchanged; update it by hand");
+    }
+    if dry_run {
+        println!("dry-run: would write {}:", migration.target.display());
+        println!("{rendered}");
+        return Ok(migration);
+    }
+
+    fs::write(&migration.target, format!("{rendered}\n"))
+        .with_context(|| format!("failed writing {}", migration.target.display()))?;
+    fs::remove_file(&legacy_path)
+        .with_context(|| format!("failed removing {}", legacy_path.display()))?;
+    println!(
+        "Migrated {} -> {}",
+        legacy_path.display(),
+        migration.target.display()
+    );
+    Ok(migration)
+}
+
 fn run_command_with_status(program: &str, args: &[OsString], display: &str) -> Result<()> {
     let status = Command::new(program)
         .args(args)
@@ -141,36 +259,140 @@ fn run_command_with_status(program: &str, args: &[OsString], display: &str) -> R
     ))
 }
 
-pub(crate) fn lint_command(paths: Vec<PathBuf>, fix: bool) -> Result<()> {
-    if let Some(script) = resolve_project_script("lint")? {
-        return run_script_command("lint", &script);
+fn project_config() -> Result<Option<FileConfig>> {
+    let cwd = std::env::current_dir().context("failed resolving current directory")?;
+    load_file_config(None, &cwd)
+}
+
+/// The tool declared in `klumo.json`, else Deno for Deno/JS projects with `deno` installed and
+/// Cargo otherwise.
+fn resolve_tool(configured: Option<ToolSetting>) -> Result<ToolSetting> {
+    if let Some(tool) = configured {
+        return Ok(tool);
     }
+    Ok(if use_deno_default()? {
+        ToolSetting::Deno
+    } else {
+        ToolSetting::Cargo
+    })
+}
 
-    if use_deno_default()? {
-        let mut args = vec![OsString::from("lint")];
-        if fix {
-            args.push(OsString::from("--fix"));
+fn tool_program(tool: ToolSetting) -> &'static str {
+    match tool {
+        ToolSetting::Deno => "deno",
+        ToolSetting::Cargo => "cargo",
+    }
+}
+
+/// CLI paths when given, else the configured `include` globs.
+fn paths_or_include(paths: Vec<PathBuf>, include: Option<&Vec<String>>) -> Vec<OsString> {
+    if !paths.is_empty() {
+        return paths.into_iter().map(PathBuf::into_os_string).collect();
+    }
+    include.into_iter().flatten().map(OsString::from).collect()
+}
+
+pub(crate) fn lint_invocation(
+    tool: ToolSetting,
+    config: &LintConfig,
+    paths: Vec<PathBuf>,
+    fix: bool,
+) -> Vec<OsString> {
+    let rules = config.rules.as_deref().unwrap_or_default();
+    let exclude_rules = config.exclude_rules.as_deref().unwrap_or_default();
+    match tool {
+        ToolSetting::Deno => {
+            let mut args = vec![OsString::from("lint")];
+            if fix {
+                args.push(OsString::from("--fix"));
+            }
+            if !rules.is_empty() {
+                args.push(format!("--rules-include={}", rules.join(",")).into());
+            }
+            if !exclude_rules.is_empty() {
+                args.push(format!("--rules-exclude={}", exclude_rules.join(",")).into());
+            }
+            args.extend(paths_or_include(paths, config.include.as_ref()));
+            args
         }
-        for path in paths {
-            args.push(path.into_os_string());
+        ToolSetting::Cargo => {
+            let mut args = vec![
+                OsString::from("clippy"),
+                OsString::from("--all-targets"),
+                OsString::from("--all-features"),
+            ];
+            if fix {
+                args.push(OsString::from("--fix"));
+                args.push(OsString::from("--allow-dirty"));
+                args.push(OsString::from("--allow-staged"));
+            }
+            for path in paths {
+                args.push(path.into_os_string());
+            }
+            if !rules.is_empty() || !exclude_rules.is_empty() {
+                args.push(OsString::from("--"));
+                for rule in rules {
+                    args.push(OsString::from("-W"));
+                    args.push(OsString::from(rule));
+                }
+                for rule in exclude_rules {
+                    args.push(OsString::from("-A"));
+                    args.push(OsString::from(rule));
+                }
+            }
+            args
         }
-        return run_command_with_status("deno", &args, "lint");
     }
+}
 
-    let mut args = vec![
-        OsString::from("clippy"),
-        OsString::from("--all-targets"),
-        OsString::from("--all-features"),
-    ];
-    if fix {
-        args.push(OsString::from("--fix"));
-        args.push(OsString::from("--allow-dirty"));
-        args.push(OsString::from("--allow-staged"));
+pub(crate) fn lint_command(paths: Vec<PathBuf>, fix: bool) -> Result<()> {
+    if let Some(script) = resolve_project_script("lint")? {
+        return run_script_command("lint", &script);
     }
-    for path in paths {
-        args.push(path.into_os_string());
+
+    let config = project_config()?
+        .and_then(|cfg| cfg.lint)
+        .unwrap_or_default();
+    let tool = resolve_tool(config.tool)?;
+    let args = lint_invocation(tool, &config, paths, fix);
+    let display = match tool {
+        ToolSetting::Deno => "lint",
+        ToolSetting::Cargo => "clippy",
+    };
+    run_command_with_status(tool_program(tool), &args, display)
+}
+
+pub(crate) fn fmt_invocation(
+    tool: ToolSetting,
+    config: &FmtConfig,
+    paths: Vec<PathBuf>,
+    check: bool,
+) -> Vec<OsString> {
+    match tool {
+        ToolSetting::Deno => {
+            let mut args = vec![OsString::from("fmt")];
+            if check {
+                args.push(OsString::from("--check"));
+            }
+            if let Some(width) = config.line_width {
+                args.push(format!("--line-width={width}").into());
+            }
+            args.extend(paths_or_include(paths, config.include.as_ref()));
+            args
+        }
+        ToolSetting::Cargo => {
+            let mut args = vec![OsString::from("fmt"), OsString::from("--all")];
+            if check {
+                args.push(OsString::from("--check"));
+            }
+            if let Some(width) = config.line_width {
+                args.push(OsString::from("--"));
+                args.push(OsString::from("--config"));
+                args.push(format!("max_width={width}").into());
+            }
+            args
+        }
     }
-    run_command_with_status("cargo", &args, "clippy")
 }
 
 pub(crate) fn fmt_command(paths: Vec<PathBuf>, check: bool) -> Result<()> {
@@ -178,34 +400,37 @@ pub(crate) fn fmt_command(paths: Vec<PathBuf>, check: bool) -> Result<()> {
         return run_script_command("fmt", &script);
     }
 
-    if use_deno_default()? {
-        let mut args = vec![OsString::from("fmt")];
-        if check {
-            args.push(OsString::from("--check"));
-        }
-        for path in paths {
-            args.push(path.into_os_string());
-        }
-        return run_command_with_status("deno", &args, "fmt");
-    }
-
-    if !paths.is_empty() {
+    let config = project_config()?
+        .and_then(|cfg| cfg.fmt)
+        .unwrap_or_default();
+    let tool = resolve_tool(config.tool)?;
+    if tool == ToolSetting::Cargo && !paths.is_empty() {
         eprintln!(
             "[klumo] warning: 'klumo fmt <paths...>' is not supported for cargo fmt; formatting full workspace instead"
         );
     }
+    let args = fmt_invocation(tool, &config, paths, check);
+    run_command_with_status(tool_program(tool), &args, "fmt")
+}
 
-    let mut args = vec![OsString::from("fmt"), OsString::from("--all")];
-    if check {
-        args.push(OsString::from("--check"));
+pub(crate) fn test_invocation(config: &TestConfig, args: Vec<OsString>) -> Vec<OsString> {
+    let mut invocation = vec![OsString::from("test")];
+    invocation.extend(config.args.iter().flatten().map(OsString::from));
+    if args.is_empty() && config.tool != Some(ToolSetting::Cargo) {
+        invocation.extend(config.include.iter().flatten().map(OsString::from));
     }
-    run_command_with_status("cargo", &args, "fmt")
+    invocation.extend(args);
+    invocation
 }
 
+/// Runs `deno test` unless `klumo.json` declares `"test": { "tool": "cargo" }`.
 pub(crate) fn test_command(args: Vec<OsString>) -> Result<()> {
-    let mut deno_args = vec![OsString::from("test")];
-    deno_args.extend(args);
-    run_command_with_status("deno", &deno_args, "test")
+    let config = project_config()?
+        .and_then(|cfg| cfg.test)
+        .unwrap_or_default();
+    let tool = config.tool.unwrap_or(ToolSetting::Deno);
+    let invocation = test_invocation(&config, args);
+    run_command_with_status(tool_program(tool), &invocation, "test")
 }
 
 pub(crate) fn run_script_command(script_name: &str, command_line: &str) -> Result<()> {
@@ -0,0 +1,200 @@
+use anyhow::{Context, Result, anyhow};
+use klumo_config::{Credentials, ProviderSetting, RunDefaults};
+use klumo_llm::ProviderRoutingError;
+use klumo_llm_ollama::OllamaClient;
+use serde_json::Value as JsonValue;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a freshly started `ollama serve` gets to answer before giving up.
+const OLLAMA_START_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How to continue after automatic routing found no provider.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ProviderChoice {
+    StartOllama,
+    OpenAiKey(String),
+    Fixture,
+}
+
+impl ProviderChoice {
+    fn setting(&self) -> ProviderSetting {
+        match self {
+            Self::StartOllama => ProviderSetting::Ollama,
+            Self::OpenAiKey(_) => ProviderSetting::Openai,
+            Self::Fixture => ProviderSetting::Fixture,
+        }
+    }
+
+    /// The `provider` value in `klumo.json`.
+    fn config_value(&self) -> &'static str {
+        match self {
+            Self::StartOllama => "ollama",
+            Self::OpenAiKey(_) => "openai",
+            Self::Fixture => "fixture",
+        }
+    }
+}
+
+/// Whether `err` should open the chooser: an `auto` run on a terminal where no provider could
+/// even be asked.
+pub(crate) fn should_offer(err: &anyhow::Error, resolved: &RunDefaults) -> bool {
+    resolved.provider == ProviderSetting::Auto
+        && io::stdin().is_terminal()
+        && io::stderr().is_terminal()
+        && err
+            .chain()
+            .filter_map(|cause| cause.downcast_ref::<ProviderRoutingError>())
+            .any(ProviderRoutingError::nothing_reachable)
+}
+
+/// Asks how to continue and points `resolved` at the chosen provider, saving the choice to
+/// `klumo.json` when asked to. Returns `false` when the user quits.
+pub(crate) fn choose_provider(resolved: &mut RunDefaults, config: Option<&Path>) -> Result<bool> {
+    eprintln!("[klumo] no LLM provider is reachable. How do you want to continue?");
+    eprintln!("  1) start Ollama at {}", resolved.ollama_url);
+    eprintln!("  2) enter an OpenAI API key");
+    eprintln!(
+        "  3) switch to fixture mode (replays {})",
+        resolved.fixtures_dir.display()
+    );
+    eprintln!("  q) quit");
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let Some(choice) = read_choice(&mut input)? else {
+        return Ok(false);
+    };
+
+    match &choice {
+        ProviderChoice::StartOllama => start_ollama(&resolved.ollama_url)?,
+        ProviderChoice::OpenAiKey(key) => resolved.openai_api_key = Some(key.clone()),
+        ProviderChoice::Fixture => {}
+    }
+    resolved.provider = choice.setting();
+
+    let path = config_path(config)?;
+    if confirm(
+        &mut input,
+        &format!("save this choice to {}? [y/N]: ", path.display()),
+    )? {
+        persist_choice(&path, &choice)?;
+    }
+    Ok(true)
+}
+
+/// Reads a menu answer, then the key for the OpenAI option; `None` on quit or end of input.
+pub(crate) fn read_choice(input: &mut impl BufRead) -> Result<Option<ProviderChoice>> {
+    loop {
+        let Some(line) = prompt(input, "choice [1/2/3/q]: ")? else {
+            return Ok(None);
+        };
+        match line.as_str() {
+            "1" => return Ok(Some(ProviderChoice::StartOllama)),
+            "2" => {
+                let Some(key) = prompt(input, "OpenAI API key: ")? else {
+                    return Ok(None);
+                };
+                if key.is_empty() {
+                    continue;
+                }
+                return Ok(Some(ProviderChoice::OpenAiKey(key)));
+            }
+            "3" => return Ok(Some(ProviderChoice::Fixture)),
+            "q" | "quit" => return Ok(None),
+            _ => continue,
+        }
+    }
+}
+
+fn confirm(input: &mut impl BufRead, question: &str) -> Result<bool> {
+    Ok(prompt(input, question)?
+        .is_some_and(|answer| matches!(answer.to_ascii_lowercase().as_str(), "y" | "yes")))
+}
+
+/// Trimmed answer to `question` on stderr; `None` at end of input.
+fn prompt(input: &mut impl BufRead, question: &str) -> Result<Option<String>> {
+    eprint!("{question}");
+    io::stderr().flush()?;
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        eprintln!();
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_string()))
+}
+
+fn start_ollama(url: &str) -> Result<()> {
+    Command::new("ollama")
+        .arg("serve")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("failed starting `ollama serve`; is Ollama installed?")?;
+    let client = OllamaClient::new(url.to_string())?;
+    let started = Instant::now();
+    while started.elapsed() < OLLAMA_START_TIMEOUT {
+        if client.is_reachable() {
+            eprintln!("[klumo] Ollama is up at {url}");
+            return Ok(());
+        }
+        thread::sleep(Duration::from_millis(250));
+    }
+    Err(anyhow!(
+        "Ollama did not answer at {url} within {}s of starting",
+        OLLAMA_START_TIMEOUT.as_secs()
+    ))
+}
+
+fn config_path(config: Option<&Path>) -> Result<PathBuf> {
+    match config {
+        Some(path) => Ok(path.to_path_buf()),
+        None => Ok(std::env::current_dir()
+            .context("failed resolving current directory")?
+            .join("klumo.json")),
+    }
+}
+
+/// Sets `provider` in `path`; an OpenAI key goes to the credentials file rather than
+/// `klumo.json`, which is usually committed.
+fn persist_choice(path: &Path, choice: &ProviderChoice) -> Result<()> {
+    let setting = choice.config_value();
+    persist_provider(path, setting)?;
+    eprintln!(
+        "[klumo] saved \"provider\": \"{setting}\" to {}",
+        path.display()
+    );
+    if let ProviderChoice::OpenAiKey(key) = choice {
+        let credentials = Credentials::default_path()
+            .ok_or_else(|| anyhow!("failed resolving the credentials file path"))?;
+        Credentials::save_api_key(&credentials, "openai", key)?;
+        eprintln!("[klumo] saved the OpenAI key to {}", credentials.display());
+    }
+    Ok(())
+}
+
+/// Writes `"provider": setting` into the JSON config at `path`, creating it when missing and
+/// keeping every other key.
+pub(crate) fn persist_provider(path: &Path, setting: &str) -> Result<()> {
+    let mut value = if path.exists() {
+        let raw = fs::read_to_string(path)
+            .with_context(|| format!("failed reading {}", path.display()))?;
+        serde_json::from_str(&raw).with_context(|| format!("failed parsing {}", path.display()))?
+    } else {
+        JsonValue::Object(Default::default())
+    };
+    let object = value
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("{} is not a JSON object", path.display()))?;
+    object.insert(
+        "provider".to_string(),
+        JsonValue::String(setting.to_string()),
+    );
+    let rendered = serde_json::to_string_pretty(&value)?;
+    fs::write(path, format!("{rendered}\n"))
+        .with_context(|| format!("failed writing {}", path.display()))
+}
@@ -0,0 +1,233 @@
+use anyhow::{Context, Result, anyhow, bail};
+use klumo_compiler::base64;
+use reqwest::blocking::Client;
+use serde_json::{Map as JsonMap, Value as JsonValue};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How long downloading a remote source may take.
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The largest remote source downloaded; bigger responses are refused.
+const MAX_DOWNLOAD_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Whether `klumo run` was given an `http://` or `https://` URL rather than a file.
+pub(crate) fn is_remote(target: &Path) -> bool {
+    target
+        .to_str()
+        .is_some_and(|target| target.starts_with("https://") || target.starts_with("http://"))
+}
+
+/// `KLUMO_REMOTE_DIR`, or `~/.klumo/remote` by default.
+pub(crate) fn remote_dir() -> Result<PathBuf> {
+    Ok(match std::env::var_os("KLUMO_REMOTE_DIR") {
+        Some(path) => PathBuf::from(path),
+        None => dirs::home_dir()
+            .context("failed to resolve home directory")?
+            .join(".klumo/remote"),
+    })
+}
+
+/// Downloaded sources under `sources/`, and `pins.json` mapping each URL to the integrity
+/// recorded the first time it ran.
+pub(crate) struct RemoteStore {
+    root: PathBuf,
+}
+
+impl RemoteStore {
+    pub(crate) fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn pins_path(&self) -> PathBuf {
+        self.root.join("pins.json")
+    }
+
+    fn pins(&self) -> Result<JsonMap<String, JsonValue>> {
+        let path = self.pins_path();
+        match fs::read_to_string(&path) {
+            Ok(raw) => serde_json::from_str(&raw)
+                .with_context(|| format!("failed parsing {}", path.display())),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(JsonMap::new()),
+            Err(err) => Err(err).with_context(|| format!("failed reading {}", path.display())),
+        }
+    }
+
+    pub(crate) fn pin(&self, url: &str) -> Result<Option<String>> {
+        Ok(self
+            .pins()?
+            .get(url)
+            .and_then(JsonValue::as_str)
+            .map(str::to_string))
+    }
+
+    fn record_pin(&self, url: &str, integrity: &str) -> Result<()> {
+        let mut pins = self.pins()?;
+        pins.insert(url.to_string(), JsonValue::String(integrity.to_string()));
+        let path = self.pins_path();
+        fs::create_dir_all(&self.root)
+            .with_context(|| format!("failed creating {}", self.root.display()))?;
+        fs::write(
+            &path,
+            format!(
+                "{}\n",
+                serde_json::to_string_pretty(&JsonValue::Object(pins))?
+            ),
+        )
+        .with_context(|| format!("failed writing {}", path.display()))
+    }
+
+    /// Where the download of `url` is kept: a directory per URL, holding the file under the
+    /// URL's own name so its extension still selects how it compiles.
+    fn cached_path(&self, url: &str) -> PathBuf {
+        let digest = Sha256::digest(url.as_bytes());
+        let dir: String = digest[..8]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        let name = url
+            .split(['?', '#'])
+            .next()
+            .and_then(|url| url.split_once("://"))
+            .and_then(|(_, rest)| rest.split_once('/'))
+            .and_then(|(_, path)| path.rsplit('/').next())
+            .filter(|name| !name.is_empty() && !name.starts_with('.'))
+            .unwrap_or("index");
+        self.root.join("sources").join(dir).join(name)
+    }
+}
+
+/// The Subresource Integrity string (`sha256-<base64>`) of `bytes`.
+pub(crate) fn integrity_of(bytes: &[u8]) -> String {
+    format!("sha256-{}", base64(&Sha256::digest(bytes)))
+}
+
+fn parse_integrity(value: &str) -> Result<String> {
+    let digest = value
+        .strip_prefix("sha256-")
+        .ok_or_else(|| anyhow!("--integrity must be `sha256-<base64 digest>`, got `{value}`"))?;
+    if digest.len() != 44
+        || !digest.ends_with('=')
+        || !digest[..43]
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/')
+    {
+        bail!("--integrity `{value}` is not a base64 SHA-256 digest");
+    }
+    Ok(value.to_string())
+}
+
+/// A local copy of the source at `url` to run, verified against `integrity` or else the pin
+/// recorded for the URL.
+///
+/// A cached download matching the expected integrity is used without touching the network.
+/// Otherwise the source is downloaded, which needs `allow_download` or a yes at the prompt.
+/// The first download of an `https://` URL run without `--integrity` records its integrity as
+/// the pin; later downloads must match it, and passing `--integrity` replaces it. A plain
+/// `http://` download could be tampered with in transit, so it is never pinned on first use
+/// and needs `--integrity` instead.
+pub(crate) fn fetch_remote_source(
+    store: &RemoteStore,
+    url: &str,
+    integrity: Option<&str>,
+    allow_download: bool,
+) -> Result<PathBuf> {
+    let requested = integrity.map(parse_integrity).transpose()?;
+    let pinned = store.pin(url)?;
+    let expected = requested.clone().or_else(|| pinned.clone());
+    let path = store.cached_path(url);
+
+    if let Some(expected) = &expected
+        && let Ok(cached) = fs::read(&path)
+        && integrity_of(&cached) == *expected
+    {
+        if pinned.as_ref() != Some(expected) {
+            store.record_pin(url, expected)?;
+        }
+        return Ok(path);
+    }
+
+    if expected.is_none() && !url.starts_with("https://") {
+        bail!(
+            "{url} is not served over https, so it cannot be pinned on first use; pass --integrity sha256-<digest> to run it"
+        );
+    }
+    if !allow_download && !ask(url) {
+        bail!("downloading {url} was not permitted; pass --allow-remote to allow it");
+    }
+    let bytes = download(url)?;
+    let actual = integrity_of(&bytes);
+    match (&expected, &requested) {
+        (Some(expected), Some(_)) if *expected != actual => {
+            bail!("integrity check failed for {url}: expected {expected}, got {actual}")
+        }
+        (Some(expected), None) if *expected != actual => bail!(
+            "{url} changed since it was pinned: expected {expected}, got {actual}; pass --integrity {actual} to accept the new content"
+        ),
+        (Some(_), _) => {}
+        (None, _) => eprintln!(
+            "[klumo] pinned {url} to {actual}; later runs refuse different content unless given a new --integrity"
+        ),
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed creating {}", parent.display()))?;
+    }
+    fs::write(&path, &bytes).with_context(|| format!("failed writing {}", path.display()))?;
+    if pinned.as_deref() != Some(actual.as_str()) {
+        store.record_pin(url, &actual)?;
+    }
+    Ok(path)
+}
+
+fn download(url: &str) -> Result<Vec<u8>> {
+    let client = Client::builder()
+        .timeout(DOWNLOAD_TIMEOUT)
+        .build()
+        .context("failed building HTTP client")?;
+    let response = client
+        .get(url)
+        .send()
+        .with_context(|| format!("failed downloading {url}"))?;
+    let status = response.status();
+    if !status.is_success() {
+        bail!("failed downloading {url}: server answered {status}");
+    }
+    let too_large = || {
+        anyhow!(
+            "{url} is larger than the {} MiB limit for remote sources",
+            MAX_DOWNLOAD_BYTES / (1024 * 1024)
+        )
+    };
+    if response
+        .content_length()
+        .is_some_and(|length| length > MAX_DOWNLOAD_BYTES)
+    {
+        return Err(too_large());
+    }
+    let mut bytes = Vec::new();
+    response
+        .take(MAX_DOWNLOAD_BYTES + 1)
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("failed reading {url}"))?;
+    if bytes.len() as u64 > MAX_DOWNLOAD_BYTES {
+        return Err(too_large());
+    }
+    Ok(bytes)
+}
+
+/// Asks on stderr; without a terminal to answer on, the download is refused.
+fn ask(url: &str) -> bool {
+    if !io::stdin().is_terminal() {
+        return false;
+    }
+    eprint!("[klumo] download and run {url}? [y/N] ");
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).is_ok()
+        && matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+}
@@ -0,0 +1,137 @@
+use crate::eval_session::sessions_dir;
+use anyhow::{Context, Result, anyhow};
+use serde_json::{Value as JsonValue, json};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What a REPL autosave holds: every line that evaluated, paired with the JavaScript it ran.
+/// Engine heaps cannot be serialized, so restoring replays `javascript` into a fresh engine.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct SavedSession {
+    pub(crate) statements: Vec<String>,
+    pub(crate) javascript: Vec<String>,
+}
+
+impl SavedSession {
+    pub(crate) fn push(&mut self, statement: &str, javascript: &str) {
+        self.statements.push(statement.to_string());
+        self.javascript.push(javascript.to_string());
+    }
+
+    fn to_json(&self) -> JsonValue {
+        json!({ "statements": self.statements, "javascript": self.javascript })
+    }
+
+    fn from_json(text: &str) -> Result<Self> {
+        let parsed: JsonValue = serde_json::from_str(text)?;
+        let list = |field: &str| -> Result<Vec<String>> {
+            parsed[field]
+                .as_array()
+                .ok_or_else(|| anyhow!("autosave has no {field} list"))?
+                .iter()
+                .map(|item| {
+                    item.as_str()
+                        .map(str::to_string)
+                        .ok_or_else(|| anyhow!("autosave {field} holds a non-string entry"))
+                })
+                .collect()
+        };
+        let session = Self {
+            statements: list("statements")?,
+            javascript: list("javascript")?,
+        };
+        if session.statements.len() != session.javascript.len() {
+            return Err(anyhow!(
+                "autosave statements and javascript differ in length"
+            ));
+        }
+        Ok(session)
+    }
+}
+
+/// Crash recovery for the REPL. Each REPL process keeps `<sessions dir>/autosave/<pid>.json`
+/// current and removes it when the session ends normally, so a file whose process is gone
+/// was left by a panic or a closed terminal.
+pub(crate) struct Autosave {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl Autosave {
+    pub(crate) fn for_this_process() -> Result<Self> {
+        Ok(Self::in_dir(
+            sessions_dir()?.join("autosave"),
+            std::process::id(),
+        ))
+    }
+
+    pub(crate) fn in_dir(dir: PathBuf, pid: u32) -> Self {
+        let path = dir.join(format!("{pid}.json"));
+        Self { dir, path }
+    }
+
+    /// Writes `session` through a temporary file, so a crash mid-write keeps the last save.
+    pub(crate) fn save(&self, session: &SavedSession) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("failed creating {}", self.dir.display()))?;
+        let temp = self.path.with_extension("json.tmp");
+        fs::write(&temp, session.to_json().to_string())
+            .with_context(|| format!("failed writing {}", temp.display()))?;
+        fs::rename(&temp, &self.path)
+            .with_context(|| format!("failed writing {}", self.path.display()))
+    }
+
+    /// Removes this process's autosave after a normal exit.
+    pub(crate) fn discard(&self) {
+        let _ = fs::remove_file(&self.path);
+    }
+
+    /// The most recent autosave left by a REPL that is no longer running, with its path.
+    /// Unreadable leftovers are skipped.
+    pub(crate) fn orphaned(&self) -> Option<(PathBuf, SavedSession)> {
+        let mut orphans: Vec<_> = fs::read_dir(&self.dir)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let path = entry.path();
+                let pid: u32 = path
+                    .file_name()?
+                    .to_str()?
+                    .strip_suffix(".json")?
+                    .parse()
+                    .ok()?;
+                if path == self.path || process_running(pid) {
+                    return None;
+                }
+                let modified = entry.metadata().and_then(|meta| meta.modified()).ok()?;
+                Some((modified, path))
+            })
+            .collect();
+        orphans.sort_by_key(|(modified, _)| *modified);
+        orphans.into_iter().rev().find_map(|(_, path)| {
+            let session = SavedSession::from_json(&fs::read_to_string(&path).ok()?).ok()?;
+            Some((path, session))
+        })
+    }
+}
+
+/// Removes an orphaned autosave once it was restored or declined.
+pub(crate) fn remove_orphan(path: &Path) {
+    let _ = fs::remove_file(path);
+}
+
+#[cfg(unix)]
+fn process_running(pid: u32) -> bool {
+    std::process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Without a cheap liveness check every other autosave counts as orphaned; restoring is only
+/// ever offered, so the user can decline one that belongs to a running REPL.
+#[cfg(not(unix))]
+fn process_running(_pid: u32) -> bool {
+    false
+}
@@ -1,6 +1,8 @@
 use anyhow::{Context, Result, anyhow};
 use klumo_engine::JsEngine;
 use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::path::Path;
 
 pub(crate) fn sanitize_repl_javascript(input: &str) -> String {
     let mut output = String::new();
@@ -172,6 +174,25 @@ REPAIR REQUIREMENTS\n\
     )
 }
 
+/// Inputs replayed before the interactive prompt: the `--script` file first, then `--exec`.
+pub(crate) fn startup_repl_inputs(
+    script: Option<&Path>,
+    exec: Option<&str>,
+) -> Result<VecDeque<String>> {
+    let mut inputs = VecDeque::new();
+    if let Some(path) = script {
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("failed reading REPL script {}", path.display()))?;
+        if !source.trim().is_empty() {
+            inputs.push_back(source);
+        }
+    }
+    if let Some(statement) = exec.filter(|s| !s.trim().is_empty()) {
+        inputs.push_back(statement.to_string());
+    }
+    Ok(inputs)
+}
+
 pub(crate) fn repl_self_heal_limit() -> Option<usize> {
     let raw = match std::env::var("KLUMO_REPL_SELF_HEAL_MAX_ATTEMPTS") {
         Ok(value) => value,
@@ -0,0 +1,190 @@
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+pub(crate) const NOTEBOOK_ROUTE: &str = "/notebook";
+pub(crate) const NOTEBOOK_EVENTS_ROUTE: &str = "/notebook/events";
+/// Cells replayed to a page that connects mid-session.
+const NOTEBOOK_HISTORY_LIMIT: usize = 200;
+/// Idle streams get a comment this often so closed tabs are noticed and their threads exit.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// One evaluated REPL line, as the notebook page renders it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct NotebookCell {
+    pub(crate) input: String,
+    pub(crate) javascript: Option<String>,
+    /// `EvalOutput::console_output` of the evaluation.
+    pub(crate) console: Option<String>,
+    pub(crate) value: Option<String>,
+    pub(crate) error: Option<String>,
+}
+
+/// Cells published so far and the event streams listening for more.
+#[derive(Debug, Default)]
+pub(crate) struct NotebookFeed {
+    cells: VecDeque<String>,
+    next_index: usize,
+    subscribers: Vec<Sender<String>>,
+}
+
+pub(crate) type SharedNotebook = Arc<Mutex<NotebookFeed>>;
+
+impl NotebookFeed {
+    /// Numbers `cell`, keeps it for pages that connect later and sends it to every open
+    /// stream, forgetting streams whose page went away.
+    pub(crate) fn publish(&mut self, cell: &NotebookCell) {
+        let payload = json!({
+            "index": self.next_index,
+            "input": cell.input,
+            "javascript": cell.javascript,
+            "console": cell.console,
+            "value": cell.value,
+            "error": cell.error,
+        })
+        .to_string();
+        self.next_index += 1;
+        if self.cells.len() == NOTEBOOK_HISTORY_LIMIT {
+            self.cells.pop_front();
+        }
+        self.cells.push_back(payload.clone());
+        self.subscribers
+            .retain(|subscriber| subscriber.send(payload.clone()).is_ok());
+    }
+
+    /// The cells so far, and a receiver for the ones published after them.
+    pub(crate) fn subscribe(&mut self) -> (Vec<String>, Receiver<String>) {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        (self.cells.iter().cloned().collect(), receiver)
+    }
+
+    /// Ends every open stream, e.g. when the daemon stops.
+    pub(crate) fn close_streams(&mut self) {
+        self.subscribers.clear();
+    }
+}
+
+pub(crate) fn publish_cell(notebook: &SharedNotebook, cell: &NotebookCell) {
+    notebook
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .publish(cell);
+}
+
+pub(crate) fn close_streams(notebook: &SharedNotebook) {
+    notebook
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .close_streams();
+}
+
+/// Answers `GET /notebook/events` with a server-sent event stream of cells: the history
+/// first, then each new one. The stream gets its own thread so the daemon keeps serving.
+pub(crate) fn serve_notebook_events(
+    mut stream: TcpStream,
+    notebook: &SharedNotebook,
+) -> Result<()> {
+    let (history, receiver) = notebook
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .subscribe();
+    stream
+        .write_all(
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n",
+        )
+        .context("failed starting notebook event stream")?;
+    thread::spawn(move || {
+        for payload in history {
+            if write_cell_event(&mut stream, &payload).is_err() {
+                return;
+            }
+        }
+        loop {
+            let written = match receiver.recv_timeout(KEEP_ALIVE_INTERVAL) {
+                Ok(payload) => write_cell_event(&mut stream, &payload),
+                Err(RecvTimeoutError::Timeout) => stream.write_all(b": keep-alive\n\n"),
+                Err(RecvTimeoutError::Disconnected) => return,
+            };
+            if written.is_err() {
+                return;
+            }
+        }
+    });
+    Ok(())
+}
+
+fn write_cell_event(stream: &mut TcpStream, payload: &str) -> std::io::Result<()> {
+    stream.write_all(format!("event: cell\ndata: {payload}\n\n").as_bytes())?;
+    stream.flush()
+}
+
+pub(crate) const NOTEBOOK_PAGE: &str = r#"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>klumo notebook</title>
+<style>
+  body { font: 14px/1.4 system-ui, sans-serif; margin: 2rem auto; max-width: 60rem; color: #222; }
+  .cell { border: 1px solid #ddd; border-radius: 6px; margin-bottom: 1rem; overflow: hidden; }
+  .cell pre { margin: 0; padding: .5rem .75rem; white-space: pre-wrap; font: 13px/1.4 ui-monospace, monospace; }
+  .input { background: #f5f5f5; }
+  .input::before { content: attr(data-label); color: #888; margin-right: .5rem; }
+  details { border-top: 1px solid #eee; padding: 0 .75rem; color: #666; }
+  details pre { padding: .5rem 0; }
+  .console { border-top: 1px solid #eee; }
+  .value { border-top: 1px solid #eee; color: #0a5; }
+  .error { border-top: 1px solid #eee; color: #c22; }
+  #status { color: #888; }
+</style>
+</head>
+<body>
+<h1>klumo notebook</h1>
+<p id="status">connecting…</p>
+<main id="cells"></main>
+<script>
+  const cells = document.getElementById("cells");
+  const status = document.getElementById("status");
+  const block = (className, text) => {
+    const pre = document.createElement("pre");
+    pre.className = className;
+    pre.textContent = text;
+    return pre;
+  };
+  // Reconnects replay the history; skip cells already shown.
+  let lastIndex = -1;
+  const events = new EventSource("/notebook/events");
+  events.onopen = () => { status.textContent = "live: new REPL lines appear below"; };
+  events.onerror = () => { status.textContent = "disconnected; retrying…"; };
+  events.addEventListener("cell", (event) => {
+    const cell = JSON.parse(event.data);
+    if (cell.index <= lastIndex) return;
+    lastIndex = cell.index;
+    const section = document.createElement("section");
+    section.className = "cell";
+    const input = block("input", cell.input);
+    input.dataset.label = `[${cell.index + 1}]`;
+    section.append(input);
+    if (cell.javascript) {
+      const details = document.createElement("details");
+      const summary = document.createElement("summary");
+      summary.textContent = "generated JavaScript";
+      details.append(summary, block("javascript", cell.javascript));
+      section.append(details);
+    }
+    if (cell.console) section.append(block("console", cell.console));
+    if (cell.value !== null) section.append(block("value", cell.value));
+    if (cell.error) section.append(block("error", cell.error));
+    cells.append(section);
+    section.scrollIntoView({ block: "end" });
+  });
+</script>
+</body>
+</html>
+"#;
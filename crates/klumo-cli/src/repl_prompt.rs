@@ -0,0 +1,47 @@
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const PROMPT: &str = "klumo> ";
+
+/// Set while the REPL waits at its prompt, so lines printed meanwhile (web route handlers
+/// logging from their threads) can move it below them.
+static PROMPT_SHOWN: AtomicBool = AtomicBool::new(false);
+
+/// Which stream [`print_line`] writes to.
+#[derive(Clone, Copy)]
+pub(crate) enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Prints the REPL prompt; it counts as shown until [`prompt_answered`].
+pub(crate) fn show_prompt() -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(PROMPT.as_bytes())?;
+    stdout.flush()?;
+    PROMPT_SHOWN.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+/// The user entered a line, so the cursor has left the prompt.
+pub(crate) fn prompt_answered() {
+    PROMPT_SHOWN.store(false, Ordering::SeqCst);
+}
+
+/// Prints `line` without breaking a shown prompt on a terminal: the prompt line is cleared,
+/// `line` printed in its place and the prompt drawn again below it. Input typed before the
+/// line arrived is still submitted with Enter, but is not redrawn.
+pub(crate) fn print_line(stream: Stream, line: &str) {
+    // Holding stdout keeps the prompt from being drawn halfway through.
+    let mut stdout = io::stdout().lock();
+    let redraw = PROMPT_SHOWN.load(Ordering::SeqCst) && stdout.is_terminal();
+    let prefix = if redraw { "\r\x1b[2K" } else { "" };
+    let _ = match stream {
+        Stream::Stdout => writeln!(stdout, "{prefix}{line}"),
+        Stream::Stderr => writeln!(io::stderr(), "{prefix}{line}"),
+    };
+    if redraw {
+        let _ = stdout.write_all(PROMPT.as_bytes());
+    }
+    let _ = stdout.flush();
+}
@@ -1,19 +1,32 @@
+use super::repl_notebook::{
+    self, NOTEBOOK_EVENTS_ROUTE, NOTEBOOK_PAGE, NOTEBOOK_ROUTE, SharedNotebook,
+};
+use super::route_handlers::{self, HandlerPool, RouteHandler};
 use super::{
-    ApiRoute, DEFAULT_WEB_HOST, DEFAULT_WEB_PORT, SharedApiRoutes, WebServerConfig,
-    WebServerHandle, WebServerState,
+    ApiRoute, DEFAULT_WEB_HOST, DEFAULT_WEB_PORT, PreviewContext, SharedApiRoutes, SharedPreview,
+    WebServerConfig, WebServerHandle, WebServerState,
 };
 use anyhow::{Context, Result, anyhow};
-use klumo_engine::JsEngine;
+use klumo_compiler::{CompileRequest, Compiler, SourceKind};
+use klumo_engine::{JsEngine, check_syntax};
 use serde_json::Value as JsonValue;
 use std::fs::File;
 use std::io::{self, Read, Write};
 use std::net::{TcpListener, TcpStream};
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, mpsc};
 use std::thread;
 use std::time::Duration;
 
+const PREVIEW_ROUTE: &str = "/preview";
+const MAX_REQUEST_BODY_BYTES: usize = 1024 * 1024;
+/// Connections served at once, each on its own thread; more are answered with 503.
+pub(crate) const MAX_CONNECTIONS: usize = 64;
+/// How long a connection may take to send its request before its slot is given back.
+const REQUEST_READ_TIMEOUT: Duration = Duration::from_secs(30);
+
 fn guess_content_type(path: &Path) -> &'static str {
     match path
         .extension()
@@ -65,7 +78,10 @@ fn status_text(code: u16) -> &'static str {
         400 => "Bad Request",
         404 => "Not Found",
         405 => "Method Not Allowed",
+        413 => "Payload Too Large",
         500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
         _ => "OK",
     }
 }
@@ -108,18 +124,140 @@ fn resolve_request_path(root: &Path, raw_path: &str) -> Option<PathBuf> {
     Some(candidate)
 }
 
+fn find_header_end(buffer: &[u8]) -> Option<usize> {
+    buffer
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .map(|pos| pos + 4)
+}
+
+fn content_length(head: &str) -> usize {
+    head.lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0)
+}
+
+/// Reads the request head and, when `Content-Length` is present, the full body.
+fn read_http_request(stream: &mut TcpStream) -> Result<Option<(String, Vec<u8>)>> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0_u8; 16_384];
+    let header_end = loop {
+        let read = stream.read(&mut chunk)?;
+        if read == 0 {
+            if buffer.is_empty() {
+                return Ok(None);
+            }
+            break find_header_end(&buffer).unwrap_or(buffer.len());
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        if let Some(end) = find_header_end(&buffer) {
+            break end;
+        }
+        if buffer.len() > MAX_REQUEST_BODY_BYTES {
+            return Err(anyhow!(
+                "request headers exceed {MAX_REQUEST_BODY_BYTES} bytes"
+            ));
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let expected = content_length(&head).min(MAX_REQUEST_BODY_BYTES);
+    let mut body = buffer[header_end..].to_vec();
+    while body.len() < expected {
+        let read = stream.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..read]);
+    }
+    body.truncate(expected);
+    Ok(Some((head, body)))
+}
+
+fn preview_error(status: u16, message: String) -> (u16, JsonValue) {
+    (status, serde_json::json!({ "error": message }))
+}
+
+/// Translates a `POST /preview` payload (`{source, lang, scope_context}`) into
+/// generated JavaScript plus syntax diagnostics. Never caches and never executes.
+pub(crate) fn handle_preview_request(body: &[u8], preview: &PreviewContext) -> (u16, JsonValue) {
+    let payload: JsonValue = match serde_json::from_slice(body) {
+        Ok(payload) => payload,
+        Err(err) => return preview_error(400, format!("invalid preview JSON: {err}")),
+    };
+    let Some(source) = payload.get("source").and_then(JsonValue::as_str) else {
+        return preview_error(
+            400,
+            "preview request requires a string 'source' field".to_string(),
+        );
+    };
+    let lang =
+        string_from_value(payload.get("lang")).unwrap_or_else(|| preview.default_lang.clone());
+
+    let compiled = preview.compiler.compile(&CompileRequest {
+        source_text: source.to_string(),
+        source_id: "<preview>".to_string(),
+        kind_hint: Some(SourceKind::from_hint(&lang)),
+        language_hint: Some(lang),
+        scope_context: string_from_value(payload.get("scope_context")),
+        force_llm: false,
+        provider_selection: preview.provider_selection.clone(),
+        provider_override: None,
+        model_override: preview.model_override.clone(),
+        model_override_for: None,
+        sampling: preview.sampling,
+        structured_output: preview.structured_output,
+        explain: false,
+        context_window: None,
+        no_cache: true,
+    });
+
+    match compiled {
+        Ok(compiled) => {
+            let diagnostics: Vec<JsonValue> = check_syntax(&compiled.javascript)
+                .into_iter()
+                .map(|diagnostic| serde_json::json!({ "message": diagnostic.to_string() }))
+                .collect();
+            (
+                200,
+                serde_json::json!({
+                    "javascript": compiled.javascript,
+                    "provider": compiled
+                        .metadata
+                        .provider
+                        .as_ref()
+                        .map(ToString::to_string),
+                    "model": compiled.metadata.model,
+                    "notes": compiled.metadata.notes,
+                    "confidence": compiled.metadata.confidence,
+                    "diagnostics": diagnostics,
+                }),
+            )
+        }
+        Err(err) => preview_error(500, format!("{err:#}")),
+    }
+}
+
 fn handle_web_connection(
     mut stream: TcpStream,
     root: &Path,
     api_routes: &SharedApiRoutes,
+    preview: Option<&PreviewContext>,
+    notebook: &SharedNotebook,
+    handler_pool: &HandlerPool,
 ) -> Result<()> {
-    let mut buffer = [0_u8; 16_384];
-    let read = stream.read(&mut buffer)?;
-    if read == 0 {
+    stream
+        .set_nonblocking(false)
+        .context("failed setting connection blocking mode")?;
+    stream
+        .set_read_timeout(Some(REQUEST_READ_TIMEOUT))
+        .context("failed setting connection read timeout")?;
+    let Some((request, body)) = read_http_request(&mut stream)? else {
         return Ok(());
-    }
+    };
 
-    let request = String::from_utf8_lossy(&buffer[..read]);
     let mut lines = request.lines();
     let first_line = match lines.next() {
         Some(line) => line,
@@ -130,13 +268,28 @@ fn handle_web_connection(
     let raw_path = parts.next().unwrap_or("/");
     let head_only = method.eq_ignore_ascii_case("HEAD");
 
-    if !method.eq_ignore_ascii_case("GET") && !head_only {
+    if method.eq_ignore_ascii_case("POST")
+        && raw_path.split('?').next() == Some(PREVIEW_ROUTE)
+        && let Some(preview) = preview
+    {
+        if content_length(&request) > MAX_REQUEST_BODY_BYTES {
+            return write_http_response(
+                &mut stream,
+                "413 Payload Too Large",
+                "text/plain; charset=utf-8",
+                b"Payload Too Large",
+                false,
+            );
+        }
+        let (code, payload) = handle_preview_request(&body, preview);
+        let encoded =
+            serde_json::to_vec(&payload).context("failed encoding preview response JSON")?;
         return write_http_response(
             &mut stream,
-            "405 Method Not Allowed",
-            "text/plain; charset=utf-8",
-            b"Method Not Allowed",
-            head_only,
+            &format!("{code} {}", status_text(code)),
+            "application/json; charset=utf-8",
+            &encoded,
+            false,
         );
     }
 
@@ -144,17 +297,67 @@ fn handle_web_connection(
     let normalized_request_path =
         decode_percent_path(path_without_query).unwrap_or_else(|| path_without_query.to_string());
 
-    if let Ok(routes) = api_routes.lock() {
-        if let Some(route) = routes.get(&normalized_request_path) {
-            let status = format!("{} {}", route.status, status_text(route.status));
+    // Handlers answer every method, so they are looked up before the GET-only paths.
+    let handler = api_routes.read().ok().and_then(|routes| {
+        routes
+            .get(&normalized_request_path)
+            .and_then(|route| route.handler.clone())
+    });
+    if let Some(handler) = handler {
+        if content_length(&request) > MAX_REQUEST_BODY_BYTES {
             return write_http_response(
                 &mut stream,
-                &status,
-                &route.content_type,
-                &route.body,
-                head_only,
+                "413 Payload Too Large",
+                "text/plain; charset=utf-8",
+                b"Payload Too Large",
+                false,
             );
         }
+        let handler_request = route_handlers::handler_request(&request, &body);
+        let response = handler_pool.run(&normalized_request_path, &handler, &handler_request);
+        return write_http_response(
+            &mut stream,
+            &format!("{} {}", response.status, status_text(response.status)),
+            &response.content_type,
+            &response.body,
+            head_only,
+        );
+    }
+
+    if !method.eq_ignore_ascii_case("GET") && !head_only {
+        return write_http_response(
+            &mut stream,
+            "405 Method Not Allowed",
+            "text/plain; charset=utf-8",
+            b"Method Not Allowed",
+            head_only,
+        );
+    }
+
+    if normalized_request_path == NOTEBOOK_EVENTS_ROUTE && !head_only {
+        return repl_notebook::serve_notebook_events(stream, notebook);
+    }
+    if normalized_request_path == NOTEBOOK_ROUTE {
+        return write_http_response(
+            &mut stream,
+            "200 OK",
+            "text/html; charset=utf-8",
+            NOTEBOOK_PAGE.as_bytes(),
+            head_only,
+        );
+    }
+
+    if let Ok(routes) = api_routes.read()
+        && let Some(route) = routes.get(&normalized_request_path)
+    {
+        let status = format!("{} {}", route.status, status_text(route.status));
+        return write_http_response(
+            &mut stream,
+            &status,
+            &route.content_type,
+            &route.body,
+            head_only,
+        );
     }
 
     let mut target = match resolve_request_path(root, raw_path) {
@@ -214,6 +417,9 @@ fn handle_web_connection(
 fn start_web_server(
     config: &WebServerConfig,
     api_routes: SharedApiRoutes,
+    preview: SharedPreview,
+    notebook: SharedNotebook,
+    handler_pool: Arc<HandlerPool>,
 ) -> Result<WebServerHandle> {
     let root_dir = config.root_dir.canonicalize().with_context(|| {
         format!(
@@ -247,18 +453,51 @@ fn start_web_server(
     let root_for_thread = root_dir.clone();
     let routes_for_thread = api_routes;
 
+    let active = Arc::new(AtomicUsize::new(0));
+
     let join_handle = thread::spawn(move || {
         loop {
             if stop_rx.try_recv().is_ok() {
                 break;
             }
             match listener.accept() {
-                Ok((stream, _)) => {
-                    if let Err(err) =
-                        handle_web_connection(stream, &root_for_thread, &routes_for_thread)
-                    {
-                        eprintln!("error: web daemon request failed: {err:#}");
+                Ok((mut stream, _)) => {
+                    if active.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                        active.fetch_sub(1, Ordering::SeqCst);
+                        let _ = stream
+                            .set_nonblocking(false)
+                            .and_then(|()| stream.set_write_timeout(Some(Duration::from_secs(1))));
+                        let _ = write_http_response(
+                            &mut stream,
+                            "503 Service Unavailable",
+                            "text/plain; charset=utf-8",
+                            b"too many connections",
+                            false,
+                        );
+                        continue;
                     }
+                    // Each connection gets a thread, so a slow handler holds up nobody else.
+                    let active = Arc::clone(&active);
+                    let root = root_for_thread.clone();
+                    let routes = Arc::clone(&routes_for_thread);
+                    let preview = Arc::clone(&preview);
+                    let notebook = Arc::clone(&notebook);
+                    let handler_pool = Arc::clone(&handler_pool);
+                    thread::spawn(move || {
+                        // Read per request: a reloaded configuration applies to the next one.
+                        let preview = preview.read().ok().and_then(|preview| preview.clone());
+                        if let Err(err) = handle_web_connection(
+                            stream,
+                            &root,
+                            &routes,
+                            preview.as_ref(),
+                            &notebook,
+                            &handler_pool,
+                        ) {
+                            eprintln!("error: web daemon request failed: {err:#}");
+                        }
+                        active.fetch_sub(1, Ordering::SeqCst);
+                    });
                 }
                 Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
                     thread::sleep(Duration::from_millis(40));
@@ -321,12 +560,12 @@ fn prompt_yes_no(prompt: &str) -> Result<bool> {
 pub(crate) fn web_server_scope_text(state: &WebServerState) -> String {
     let route_count = state
         .api_routes
-        .lock()
+        .read()
         .map(|routes| routes.len())
         .unwrap_or_default();
     if let Some(active) = state.active.as_ref() {
         return format!(
-            "Web daemon is running at {} and serving files from {}. Registered API routes: {}. File changes are reflected on refresh because content is read from disk per request. JavaScript APIs available in REPL: klumo.web.start(opts), klumo.web.stop(), klumo.web.restart(opts), klumo.web.status(), klumo.web.open(), klumo.web.routeJson(path, payload, opts), klumo.web.routeText(path, text, opts), klumo.web.unroute(path).",
+            "Web daemon is running at {} and serving files from {}. Registered API routes: {}. File changes are reflected on refresh because content is read from disk per request. JavaScript APIs available in REPL: klumo.web.start(opts), klumo.web.stop(), klumo.web.restart(opts), klumo.web.status(), klumo.web.open(), klumo.web.routeJson(path, payload, opts), klumo.web.routeText(path, text, opts), klumo.web.handle(path, (request) => response, opts), klumo.web.unroute(path). Handlers run in an isolated engine: they see only their request ({{ method, path, query, headers, body }}) and return a string, a JSON value, or {{ status, body, contentType }}.",
             active.url,
             active.config.root_dir.display(),
             route_count
@@ -334,7 +573,7 @@ pub(crate) fn web_server_scope_text(state: &WebServerState) -> String {
     }
 
     format!(
-        "Web daemon is not running. Registered API routes: {}. JavaScript APIs available in REPL: klumo.web.start(opts), klumo.web.stop(), klumo.web.restart(opts), klumo.web.status(), klumo.web.open(), klumo.web.routeJson(path, payload, opts), klumo.web.routeText(path, text, opts), klumo.web.unroute(path).",
+        "Web daemon is not running. Registered API routes: {}. JavaScript APIs available in REPL: klumo.web.start(opts), klumo.web.stop(), klumo.web.restart(opts), klumo.web.status(), klumo.web.open(), klumo.web.routeJson(path, payload, opts), klumo.web.routeText(path, text, opts), klumo.web.handle(path, (request) => response, opts), klumo.web.unroute(path). Handlers run in an isolated engine: they see only their request ({{ method, path, query, headers, body }}) and return a string, a JSON value, or {{ status, body, contentType }}.",
         route_count
     )
 }
@@ -361,6 +600,12 @@ globalThis.klumo.web = {
     __klumoQueueWeb({ action: "route_json", path, payload, options }),
   routeText: (path, text, options = {}) =>
     __klumoQueueWeb({ action: "route_text", path, text, options }),
+  handle: (path, handler, options = {}) => {
+    if (typeof handler !== "function") {
+      throw new TypeError("klumo.web.handle expects a handler function");
+    }
+    return __klumoQueueWeb({ action: "route_handler", path, source: String(handler), options });
+  },
   unroute: (path) => __klumoQueueWeb({ action: "unroute", path }),
 };
 "#,
@@ -412,7 +657,7 @@ pub(crate) fn write_repl_web_status(engine: &mut dyn JsEngine, state: &WebServer
 
     let routes: Vec<String> = state
         .api_routes
-        .lock()
+        .read()
         .map(|routes| routes.keys().cloned().collect())
         .unwrap_or_default();
 
@@ -463,7 +708,7 @@ fn register_json_route(
     let body = serde_json::to_vec(payload).context("failed encoding JSON route payload")?;
     let mut routes = state
         .api_routes
-        .lock()
+        .write()
         .map_err(|_| anyhow!("failed locking API route table"))?;
     routes.insert(
         key.clone(),
@@ -471,12 +716,43 @@ fn register_json_route(
             status,
             content_type: "application/json; charset=utf-8".to_string(),
             body,
+            handler: None,
         },
     );
     println!("registered API route {key}");
     Ok(())
 }
 
+fn register_handler_route(
+    path: &str,
+    source: &str,
+    timeout: Duration,
+    state: &mut WebServerState,
+) -> Result<()> {
+    let key = route_path(path)?;
+    let mut routes = state
+        .api_routes
+        .write()
+        .map_err(|_| anyhow!("failed locking API route table"))?;
+    routes.insert(
+        key.clone(),
+        ApiRoute {
+            status: 200,
+            content_type: "text/plain; charset=utf-8".to_string(),
+            body: Vec::new(),
+            handler: Some(RouteHandler {
+                source: source.to_string(),
+                timeout,
+            }),
+        },
+    );
+    println!(
+        "registered API handler {key} (timeout {} ms)",
+        timeout.as_millis()
+    );
+    Ok(())
+}
+
 fn register_text_route(
     path: &str,
     text: &str,
@@ -487,7 +763,7 @@ fn register_text_route(
     let key = route_path(path)?;
     let mut routes = state
         .api_routes
-        .lock()
+        .write()
         .map_err(|_| anyhow!("failed locking API route table"))?;
     routes.insert(
         key.clone(),
@@ -495,6 +771,7 @@ fn register_text_route(
             status,
             content_type: content_type.unwrap_or_else(|| "text/plain; charset=utf-8".to_string()),
             body: text.as_bytes().to_vec(),
+            handler: None,
         },
     );
     println!("registered API route {key}");
@@ -511,7 +788,13 @@ fn run_web_start(
         println!("web daemon is already running. Use .web restart or .web stop.");
         return Ok(());
     }
-    let handle = start_web_server(&config, Arc::clone(&state.api_routes))?;
+    let handle = start_web_server(
+        &config,
+        Arc::clone(&state.api_routes),
+        Arc::clone(&state.preview),
+        Arc::clone(&state.notebook),
+        Arc::clone(&state.handler_pool),
+    )?;
     println!(
         "web daemon started at {} (dir={})",
         handle.url,
@@ -545,6 +828,7 @@ fn run_web_stop(state: &mut WebServerState) {
     };
     let url = active.url.clone();
     active.stop();
+    repl_notebook::close_streams(&state.notebook);
     println!("web daemon stopped ({url})");
 }
 
@@ -569,9 +853,16 @@ fn run_web_restart(
 
     if let Some(mut active) = state.active.take() {
         active.stop();
+        repl_notebook::close_streams(&state.notebook);
     }
 
-    let handle = start_web_server(&restart_cfg, Arc::clone(&state.api_routes))?;
+    let handle = start_web_server(
+        &restart_cfg,
+        Arc::clone(&state.api_routes),
+        Arc::clone(&state.preview),
+        Arc::clone(&state.notebook),
+        Arc::clone(&state.handler_pool),
+    )?;
     println!(
         "web daemon restarted at {} (dir={})",
         handle.url,
@@ -684,12 +975,28 @@ pub(crate) fn apply_repl_web_commands(
                 let content_type = string_from_value(options.and_then(|o| o.get("contentType")));
                 register_text_route(path, text, status, content_type, state)?;
             }
+            "route_handler" => {
+                let (Some(path), Some(source)) = (
+                    command.get("path").and_then(JsonValue::as_str),
+                    command.get("source").and_then(JsonValue::as_str),
+                ) else {
+                    continue;
+                };
+                let timeout = command
+                    .get("options")
+                    .and_then(JsonValue::as_object)
+                    .and_then(|o| o.get("timeoutMs"))
+                    .and_then(JsonValue::as_u64)
+                    .map(Duration::from_millis)
+                    .unwrap_or(state.handler_timeout);
+                register_handler_route(path, source, timeout, state)?;
+            }
             "unroute" => {
                 let Some(path) = command.get("path").and_then(JsonValue::as_str) else {
                     continue;
                 };
                 let key = route_path(path)?;
-                if let Ok(mut routes) = state.api_routes.lock() {
+                if let Ok(mut routes) = state.api_routes.write() {
                     routes.remove(&key);
                 }
                 println!("removed API route {key}");
@@ -709,6 +1016,9 @@ pub(crate) fn print_web_usage() {
     println!("  .web status");
     println!("  .web restart");
     println!("  .web open");
+    println!("editor endpoints (while running):");
+    println!("  POST /preview {{ source, lang, scope_context }} - generated JS + diagnostics");
+    println!("  GET /notebook - REPL lines and their output as live cells");
 }
 
 pub(crate) fn parse_web_start(tokens: &[&str]) -> Result<(WebServerConfig, Option<bool>, bool)> {
@@ -785,7 +1095,7 @@ pub(crate) fn handle_web_command(input: &str, state: &mut WebServerState) -> Res
         "status" => {
             let route_count = state
                 .api_routes
-                .lock()
+                .read()
                 .map(|routes| routes.len())
                 .unwrap_or_default();
             if let Some(active) = state.active.as_ref() {
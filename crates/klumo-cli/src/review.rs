@@ -0,0 +1,162 @@
+use crate::runtime_context;
+use anyhow::{Result, bail};
+use klumo_compiler::{PendingStore, PendingTranslation};
+use klumo_config::CliRunOverrides;
+use similar::TextDiff;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+/// What `klumo review` should do with the pending translations.
+pub(crate) enum ReviewAction {
+    /// Show each entry and ask for a decision on stdin.
+    Interactive,
+    List,
+    Approve(Vec<String>),
+    Reject(Vec<String>),
+}
+
+pub(crate) fn review_command(config: Option<PathBuf>, action: ReviewAction) -> Result<()> {
+    let resolved =
+        runtime_context::resolve_config(config, "review", &mut CliRunOverrides::default())?;
+    let store = PendingStore::default();
+    let cache = runtime_context::file_compile_cache(&resolved);
+    let entries = store.list()?;
+    match action {
+        ReviewAction::List => {
+            if entries.is_empty() {
+                println!("No translations awaiting review.");
+            }
+            for entry in &entries {
+                println!("{}", summary(entry));
+            }
+        }
+        ReviewAction::Approve(keys) => {
+            for key in keys {
+                let entry = store.approve(&resolve_key(&entries, &key)?, &cache)?;
+                println!("approved {}", summary(&entry));
+            }
+        }
+        ReviewAction::Reject(keys) => {
+            for key in keys {
+                let entry = store.reject(&resolve_key(&entries, &key)?)?;
+                println!("rejected {}", summary(&entry));
+            }
+        }
+        ReviewAction::Interactive => {
+            if entries.is_empty() {
+                println!("No translations awaiting review.");
+                return Ok(());
+            }
+            let stdin = io::stdin();
+            let mut input = stdin.lock();
+            for entry in &entries {
+                for line in render_pending(entry) {
+                    println!("{line}");
+                }
+                let Some(decision) = ask(&mut input)? else {
+                    break;
+                };
+                match decision {
+                    Decision::Approve => {
+                        store.approve(&entry.key, &cache)?;
+                        println!("approved {}", entry.source_id);
+                    }
+                    Decision::Reject => {
+                        store.reject(&entry.key)?;
+                        println!("rejected {}", entry.source_id);
+                    }
+                    Decision::Skip => println!("skipped {}", entry.source_id),
+                    Decision::Quit => break,
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+enum Decision {
+    Approve,
+    Reject,
+    Skip,
+    Quit,
+}
+
+/// Prompts until a valid answer is read; `None` at end of input.
+fn ask(input: &mut impl BufRead) -> Result<Option<Decision>> {
+    loop {
+        print!("approve? [y]es / [n]o, reject / [s]kip / [q]uit: ");
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            println!();
+            return Ok(None);
+        }
+        let decision = match line.trim().to_ascii_lowercase().as_str() {
+            "y" | "yes" => Decision::Approve,
+            "n" | "no" => Decision::Reject,
+            "s" | "skip" | "" => Decision::Skip,
+            "q" | "quit" => Decision::Quit,
+            _ => continue,
+        };
+        return Ok(Some(decision));
+    }
+}
+
+fn short_key(key: &str) -> &str {
+    &key[..key.len().min(12)]
+}
+
+fn summary(entry: &PendingTranslation) -> String {
+    let model = match (&entry.provider, &entry.model) {
+        (Some(provider), Some(model)) => format!(" via {provider}/{model}"),
+        (Some(provider), None) => format!(" via {provider}"),
+        _ => String::new(),
+    };
+    format!("{} {}{model}", short_key(&entry.key), entry.source_id)
+}
+
+/// Finds the pending key starting with `prefix`, which must match exactly one entry.
+fn resolve_key(entries: &[PendingTranslation], prefix: &str) -> Result<String> {
+    let matches: Vec<&PendingTranslation> = entries
+        .iter()
+        .filter(|entry| entry.key.starts_with(prefix))
+        .collect();
+    match matches.as_slice() {
+        [entry] => Ok(entry.key.clone()),
+        [] => bail!("no pending translation '{prefix}'"),
+        _ => bail!("'{prefix}' matches {} pending translations", matches.len()),
+    }
+}
+
+/// Source, generated JavaScript, and the diff against the last approved JavaScript.
+fn render_pending(entry: &PendingTranslation) -> Vec<String> {
+    let mut lines = vec![format!("== {} ==", summary(entry))];
+    if let Some(confidence) = entry.confidence {
+        lines.push(format!("confidence: {confidence:.2}"));
+    }
+    if let Some(notes) = &entry.notes {
+        lines.push(format!("notes: {notes}"));
+    }
+    lines.push("--- source".to_string());
+    lines.extend(entry.source_text.lines().map(str::to_string));
+    lines.push("--- javascript".to_string());
+    lines.extend(entry.javascript.lines().map(str::to_string));
+    match &entry.previous {
+        Some(previous) => {
+            lines.push("--- diff against last approved".to_string());
+            let diff = TextDiff::from_lines(previous.as_str(), entry.javascript.as_str());
+            let unified = diff
+                .unified_diff()
+                .context_radius(3)
+                .missing_newline_hint(false)
+                .header("approved", "pending")
+                .to_string();
+            if unified.is_empty() {
+                lines.push("(identical to the last approved translation)".to_string());
+            }
+            lines.extend(unified.lines().map(str::to_string));
+        }
+        None => lines.push("(first translation of this source)".to_string()),
+    }
+    lines
+}
@@ -0,0 +1,311 @@
+use crate::repl_prompt::{self, Stream};
+use anyhow::{Context, Result, anyhow};
+use klumo_engine::{BoaEngine, JsEngine};
+use serde_json::{Map, Value as JsonValue, json};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Once, mpsc};
+use std::thread;
+use std::time::Duration;
+
+/// Engines kept for route handlers; requests beyond this many wait for a free one.
+pub(crate) const HANDLER_POOL_SIZE: usize = 4;
+
+/// Loop iterations a handler may run per millisecond of its timeout. A handler that outlives
+/// its timeout is abandoned, and this limit is what stops its thread soon after; it is set
+/// above what Boa gets through in a millisecond, so handlers within their timeout never hit it.
+const LOOP_ITERATIONS_PER_MS: u64 = 100_000;
+
+/// Workers that may be stuck in handlers past their timeout at once, as a multiple of the
+/// pool size. Beyond that, a timed-out handler is not replaced and the pool runs short until
+/// one of them reaches its loop limit.
+const STRANDED_WORKERS_PER_SLOT: usize = 1;
+
+/// A JavaScript function answering requests to one route. Handlers run in pooled engines,
+/// never the REPL's, so they see their request argument and standard globals only.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RouteHandler {
+    /// Source of the function, as `Function.prototype.toString` printed it.
+    pub(crate) source: String,
+    pub(crate) timeout: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct HandlerResponse {
+    pub(crate) status: u16,
+    pub(crate) content_type: String,
+    pub(crate) body: Vec<u8>,
+}
+
+impl HandlerResponse {
+    fn text(status: u16, message: String) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8".to_string(),
+            body: message.into_bytes(),
+        }
+    }
+}
+
+/// The request object a handler receives: `{ method, path, query, headers, body }`, with
+/// header names lowercased.
+pub(crate) fn handler_request(head: &str, body: &[u8]) -> JsonValue {
+    let mut lines = head.lines();
+    let mut first = lines.next().unwrap_or_default().split_whitespace();
+    let method = first.next().unwrap_or("GET");
+    let target = first.next().unwrap_or("/");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let headers: Map<String, JsonValue> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| {
+            (
+                name.trim().to_ascii_lowercase(),
+                JsonValue::String(value.trim().to_string()),
+            )
+        })
+        .collect();
+    json!({
+        "method": method.to_ascii_uppercase(),
+        "path": path,
+        "query": query,
+        "headers": headers,
+        "body": String::from_utf8_lossy(body),
+    })
+}
+
+#[derive(Debug)]
+struct HandlerJob {
+    script: String,
+    timeout: Duration,
+    /// Set by whichever side gets to the job first: a worker starting it, or the request
+    /// giving up on it after its timeout.
+    claimed: Arc<AtomicBool>,
+    reply: mpsc::Sender<Result<HandlerResponse>>,
+}
+
+/// Worker threads, each owning one engine, that answer route handler requests. The pool
+/// belongs to the web daemon's state, so handlers run while the REPL evaluates its own lines.
+/// Workers start with the first request and stop once the pool is dropped.
+#[derive(Debug)]
+pub(crate) struct HandlerPool {
+    size: usize,
+    jobs: mpsc::Sender<HandlerJob>,
+    queue: Arc<Mutex<mpsc::Receiver<HandlerJob>>>,
+    /// Workers alive, including ones still stuck in a handler that overran its timeout.
+    workers: Arc<AtomicUsize>,
+    started: Once,
+}
+
+impl Default for HandlerPool {
+    fn default() -> Self {
+        Self::new(HANDLER_POOL_SIZE)
+    }
+}
+
+impl HandlerPool {
+    pub(crate) fn new(size: usize) -> Self {
+        let (jobs, queue) = mpsc::channel();
+        Self {
+            size: size.max(1),
+            jobs,
+            queue: Arc::new(Mutex::new(queue)),
+            workers: Arc::new(AtomicUsize::new(0)),
+            started: Once::new(),
+        }
+    }
+
+    /// Calls `handler` with `request` on the next free engine. A handler still running after
+    /// its timeout is left behind and the request fails with 504; a fresh worker takes the
+    /// stuck one's place, so a runaway handler costs one thread rather than pool capacity,
+    /// up to [`STRANDED_WORKERS_PER_SLOT`] extra threads per engine.
+    pub(crate) fn run(
+        &self,
+        route: &str,
+        handler: &RouteHandler,
+        request: &JsonValue,
+    ) -> HandlerResponse {
+        self.started.call_once(|| {
+            for _ in 0..self.size {
+                self.spawn_worker();
+            }
+        });
+        let claimed = Arc::new(AtomicBool::new(false));
+        let (reply, receiver) = mpsc::channel();
+        let job = HandlerJob {
+            script: handler_script(&handler.source, request),
+            timeout: handler.timeout,
+            claimed: Arc::clone(&claimed),
+            reply,
+        };
+        if self.jobs.send(job).is_err() {
+            return HandlerResponse::text(500, "route handler pool is shut down".to_string());
+        }
+        match receiver.recv_timeout(handler.timeout) {
+            Ok(Ok(response)) => response,
+            Ok(Err(err)) => HandlerResponse::text(500, format!("route handler failed: {err:#}")),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                // Still queued: the job is dropped unstarted. Otherwise a worker is stuck in it.
+                if claimed.swap(true, Ordering::SeqCst)
+                    && self.workers.load(Ordering::SeqCst)
+                        < self.size * (1 + STRANDED_WORKERS_PER_SLOT)
+                {
+                    self.spawn_worker();
+                }
+                HandlerResponse::text(
+                    504,
+                    format!(
+                        "route handler for {route} did not finish within {} ms",
+                        handler.timeout.as_millis()
+                    ),
+                )
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                HandlerResponse::text(500, "route handler crashed".to_string())
+            }
+        }
+    }
+
+    /// Worker threads alive, stuck ones included.
+    #[cfg(test)]
+    pub(crate) fn workers(&self) -> usize {
+        self.workers.load(Ordering::SeqCst)
+    }
+
+    fn spawn_worker(&self) {
+        let queue = Arc::clone(&self.queue);
+        let workers = Arc::clone(&self.workers);
+        let size = self.size;
+        workers.fetch_add(1, Ordering::SeqCst);
+        let spawned = thread::Builder::new()
+            .name("klumo-route-handler".to_string())
+            .spawn(move || run_worker(&queue, &workers, size));
+        if let Err(err) = spawned {
+            self.workers.fetch_sub(1, Ordering::SeqCst);
+            eprintln!("error: failed starting route handler worker: {err}");
+        }
+    }
+}
+
+fn run_worker(queue: &Mutex<mpsc::Receiver<HandlerJob>>, workers: &AtomicUsize, size: usize) {
+    let mut engine = None;
+    loop {
+        let job = queue
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .recv();
+        let Ok(job) = job else {
+            break;
+        };
+        if job.claimed.swap(true, Ordering::SeqCst) {
+            continue;
+        }
+        let _ = job
+            .reply
+            .send(evaluate(&mut engine, &job.script, job.timeout));
+        // Collected between requests, so garbage from one handler does not pile up for hours.
+        if let Some(engine) = engine.as_mut() {
+            engine.collect_garbage();
+        }
+        // A replacement started while this worker overran a timeout; the pool is full again.
+        let retired = workers
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |alive| {
+                (alive > size).then(|| alive - 1)
+            })
+            .is_ok();
+        if retired {
+            return;
+        }
+    }
+    workers.fetch_sub(1, Ordering::SeqCst);
+}
+
+/// Handlers log from their own threads, possibly while the REPL prompt waits for input.
+fn handler_console(line: &str) {
+    repl_prompt::print_line(Stream::Stderr, &format!("[klumo route] {line}"));
+}
+
+/// Evaluates `script` on the worker's engine, creating it first if needed. An engine that
+/// failed mid-handler may hold half-updated globals, so it is replaced on the next request.
+fn evaluate(
+    engine: &mut Option<BoaEngine>,
+    script: &str,
+    timeout: Duration,
+) -> Result<HandlerResponse> {
+    let mut current = match engine.take() {
+        Some(current) => current,
+        None => {
+            let mut fresh = BoaEngine::with_console_sink(handler_console)?;
+            // A handler answers with its return value; only timers already due run.
+            fresh.set_timer_wait(Some(Duration::ZERO));
+            fresh
+        }
+    };
+    let millis = u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX);
+    current.set_loop_iteration_limit(millis.max(1).saturating_mul(LOOP_ITERATIONS_PER_MS));
+    let response = current
+        .eval_script(script, "<route-handler>")
+        .and_then(|output| {
+            output
+                .value
+                .ok_or_else(|| anyhow!("route handler produced no response"))
+        })
+        .and_then(|raw| parse_response(&raw));
+    // The engine serves unrelated requests next, which must not run this one's timers.
+    current.clear_timers();
+    if response.is_ok() {
+        *engine = Some(current);
+    }
+    response
+}
+
+/// Wraps the handler so its return value arrives as `{ status, contentType, body }` JSON:
+/// strings are sent as text, `{ status, body, contentType }` objects as described, and any
+/// other value as JSON.
+fn handler_script(source: &str, request: &JsonValue) -> String {
+    format!(
+        r#"(() => {{
+  const handler = ({source});
+  const result = handler({request});
+  const json = "application/json; charset=utf-8";
+  const text = "text/plain; charset=utf-8";
+  if (result !== null && typeof result === "object" && ("status" in result || "body" in result)) {{
+    const isText = typeof result.body === "string";
+    return JSON.stringify({{
+      status: result.status ?? 200,
+      contentType: result.contentType ?? (isText ? text : json),
+      body: isText ? result.body : JSON.stringify(result.body ?? null),
+    }});
+  }}
+  if (typeof result === "string") {{
+    return JSON.stringify({{ status: 200, contentType: text, body: result }});
+  }}
+  return JSON.stringify({{ status: 200, contentType: json, body: JSON.stringify(result ?? null) }});
+}})()"#
+    )
+}
+
+fn parse_response(raw: &str) -> Result<HandlerResponse> {
+    let value: JsonValue =
+        serde_json::from_str(raw).context("route handler response is not valid JSON")?;
+    let status = value["status"]
+        .as_u64()
+        .and_then(|status| u16::try_from(status).ok())
+        .filter(|status| (100..600).contains(status))
+        .ok_or_else(|| {
+            anyhow!(
+                "route handler returned an invalid status {}",
+                value["status"]
+            )
+        })?;
+    Ok(HandlerResponse {
+        status,
+        content_type: value["contentType"]
+            .as_str()
+            .unwrap_or("text/plain; charset=utf-8")
+            .to_string(),
+        body: value["body"]
+            .as_str()
+            .unwrap_or_default()
+            .as_bytes()
+            .to_vec(),
+    })
+}
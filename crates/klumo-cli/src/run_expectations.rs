@@ -0,0 +1,51 @@
+use crate::exit_codes::exit_code_for;
+use anyhow::{Result, bail};
+use klumo_core::RunOutcome;
+use klumo_engine::EvalOutput;
+
+/// `klumo run --expect-output/--expect-exit` checks for CI golden runs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct RunExpectations {
+    pub(crate) output: Option<String>,
+    pub(crate) exit: Option<i32>,
+}
+
+impl RunExpectations {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.output.is_none() && self.exit.is_none()
+    }
+
+    /// Compares a finished run against the expectations. A failed run counts as the exit code
+    /// its error maps to; without `--expect-exit` its error is returned unchanged.
+    pub(crate) fn check(&self, result: Result<RunOutcome>) -> Result<()> {
+        let (exit, output) = match result {
+            Ok(outcome) => (0, program_output(&outcome.eval)),
+            Err(err) if self.exit.is_some() => {
+                eprintln!("error: {err:#}");
+                (i32::from(exit_code_for(&err)), String::new())
+            }
+            Err(err) => return Err(err),
+        };
+
+        if let Some(expected) = self.exit
+            && expected != exit
+        {
+            bail!("expected exit code {expected}, got {exit}");
+        }
+        if let Some(expected) = self.output.as_deref()
+            && expected.trim_end() != output.trim_end()
+        {
+            bail!("expected output {expected:?}, got {output:?}");
+        }
+        Ok(())
+    }
+}
+
+/// Console lines followed by the final value, as printed by `klumo run`.
+pub(crate) fn program_output(eval: &EvalOutput) -> String {
+    [eval.console_output.as_deref(), eval.value.as_deref()]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -1,57 +1,204 @@
 use anyhow::{Result, anyhow};
-use klumo_compiler::{CompilerRouter, FileCompileCache, SourceKind};
+use klumo_compiler::{
+    CacheHitLog, CachePolicy, ChunkMapStore, CompileCache, CompilerRouter,
+    DEFAULT_SIMILARITY_THRESHOLD, Embedder, EmbeddingIndex, FileCompileCache, HttpCompileCache,
+    JsxPass, LanguageCache, LanguageRoute, ModelTier, PendingStore, SignaturePolicy, SigningKey,
+    SourceKind, TrustedKeys,
+};
 use klumo_config::{
-    CliRunOverrides, EnvConfig, ProgressSetting, ProviderSetting, RunDefaults, load_file_config,
-    resolve_run_defaults,
+    CliRunOverrides, CompileCacheSettings, CustomProviderConfig, EnvConfig, ProgressSetting,
+    ProviderSetting, RunDefaults, TranslationExampleConfig, legacy_env_warnings, load_credentials,
+    load_file_config, resolve_run_defaults,
 };
-use klumo_core::{ProgressMode, RunOptions};
-use klumo_engine::{BoaEngine, JsEngine};
+use klumo_core::{JsOutputTarget, ProgressMode, RunOptions, provider_selection};
+use klumo_engine::{BoaEngine, ConsoleSink, JsEngine, OutputLimits, StdoutSink};
 use klumo_engine_v8::V8Engine;
 use klumo_llm::{
-    LlmClient, LlmTranslateRequest, ProviderRouter, ProviderSelection, ReachabilityProbe,
+    CachedProbe, CircuitBreaker, CustomProvider, DEFAULT_PROBE_TTL, FixtureStore, LlamaCppProvider,
+    LlmClient, LlmLogDir, LlmObserver, LlmTranslateRequest, PromptTemplates, ProviderCallError,
+    ProviderErrorKind, ProviderMetricsStore, ProviderRouter, ReachabilityProbe, ResponseCache,
+    SamplingOptions, SizeLimits, TlsOptions, TranslationExample, UsageLog, pick_ollama_model,
 };
+use klumo_llm_llamacpp::LlamaCppClient;
 use klumo_llm_ollama::OllamaClient;
 use klumo_llm_openai::OpenAiCompatibleClient;
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Project directory holding `<template>.hbs` prompt overrides.
+const PROMPTS_DIR: &str = "prompts";
 
 pub(crate) struct OllamaProbe {
     client: OllamaClient,
+    /// Ask on the terminal before pulling; set when `ollama_auto_pull` is not configured.
+    confirm_pull: bool,
+    progress: ProgressMode,
 }
 
-type KlumoProviderRouter = ProviderRouter<OllamaClient, MaybeOpenAiClient, OllamaProbe>;
-pub(crate) type KlumoCompiler = CompilerRouter<KlumoProviderRouter, FileCompileCache>;
+type KlumoProviderRouter =
+    ProviderRouter<OllamaClient, MaybeOpenAiClient, CachedProbe<OllamaProbe>>;
+/// The local compile cache, or a shared one in front of it when `cache_url` is set.
+type KlumoCache = Box<dyn CompileCache + Send + Sync>;
+pub(crate) type KlumoCompiler = CompilerRouter<KlumoProviderRouter, KlumoCache>;
 
 impl ReachabilityProbe for OllamaProbe {
     fn ollama_reachable(&self) -> bool {
         self.client.is_reachable()
     }
+
+    fn installed_ollama_models(&self) -> Option<Vec<String>> {
+        self.client.list_models().ok()
+    }
+
+    fn pull_ollama_model(&self, model: &str) -> Result<()> {
+        if self.confirm_pull && !confirm_pull(model) {
+            return Err(anyhow!("not pulling {model}"));
+        }
+        let silent = matches!(self.progress, ProgressMode::Silent);
+        let redraw = !silent && io::stderr().is_terminal();
+        if !silent {
+            eprintln!(
+                "[klumo] {model} is not installed in Ollama; pulling it now (disable with --no-auto-pull)"
+            );
+        }
+        let mut last_line = String::new();
+        let mut last_status = String::new();
+        let result = self.client.pull_model(model, |progress| {
+            if redraw {
+                let line = match progress.percent() {
+                    Some(percent) => format!("{} {percent}%", progress.status),
+                    None => progress.status.clone(),
+                };
+                if line != last_line {
+                    eprint!("\r\x1b[2K[klumo] {line}");
+                    last_line = line;
+                }
+            } else if !silent && progress.status != last_status {
+                // Without a terminal to redraw on, percentages would flood the log.
+                eprintln!("[klumo] {}", progress.status);
+                last_status = progress.status.clone();
+            }
+        });
+        if redraw {
+            eprintln!();
+        }
+        if let Err(err) = &result {
+            eprintln!("[klumo] pulling {model} failed: {err:#}");
+        }
+        result
+    }
+}
+
+/// Asks on stderr whether to download `model`; without a terminal the answer is no.
+fn confirm_pull(model: &str) -> bool {
+    if !io::stdin().is_terminal() || !io::stderr().is_terminal() {
+        return false;
+    }
+    eprint!("[klumo] {model} is not installed in Ollama. Pull it now? [y/N] ");
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).is_ok()
+        && matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+}
+
+/// Embeds sources with an Ollama embedding model for `similar_cache`.
+struct OllamaEmbedder {
+    client: OllamaClient,
+    model: String,
+}
+
+impl Embedder for OllamaEmbedder {
+    fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        self.client.embed(&self.model, text)
+    }
 }
 
 pub(crate) struct MaybeOpenAiClient {
     inner: Option<OpenAiCompatibleClient>,
+    missing_key_error: String,
 }
 
 impl LlmClient for MaybeOpenAiClient {
     fn translate_to_js(&self, req: &LlmTranslateRequest, model: &str) -> Result<String> {
+        self.translate_reporting_model(req, model)
+            .map(|(raw, _)| raw)
+    }
+
+    fn translate_reporting_model(
+        &self,
+        req: &LlmTranslateRequest,
+        model: &str,
+    ) -> Result<(String, Option<String>)> {
         let client = self.inner.as_ref().ok_or_else(|| {
-            anyhow!("OPENAI_API_KEY is required for OpenAI-compatible translation")
+            ProviderCallError::new(ProviderErrorKind::Config, self.missing_key_error.clone())
         })?;
-        client.translate_to_js(req, model)
+        client.translate_reporting_model(req, model)
+    }
+
+    fn remaining_requests(&self) -> Option<u64> {
+        self.inner.as_ref()?.remaining_requests()
     }
 }
 
-fn parse_kind_hint(lang: Option<&str>) -> Option<SourceKind> {
-    lang.map(SourceKind::from_hint)
+/// Reads the example files declared under `examples` in `klumo.json`.
+fn load_examples(
+    config: &BTreeMap<String, Vec<TranslationExampleConfig>>,
+) -> Result<BTreeMap<String, Vec<TranslationExample>>> {
+    config
+        .iter()
+        .map(|(lang, pairs)| {
+            let examples = pairs
+                .iter()
+                .map(|pair| TranslationExample::load(&pair.input, &pair.output))
+                .collect::<Result<Vec<_>>>()?;
+            Ok((lang.clone(), examples))
+        })
+        .collect()
 }
 
-pub(crate) fn provider_to_selection(provider: ProviderSetting) -> ProviderSelection {
-    match provider {
-        ProviderSetting::Auto => ProviderSelection::Auto,
-        ProviderSetting::Ollama => ProviderSelection::Ollama,
-        ProviderSetting::Openai => ProviderSelection::OpenAiCompatible,
+/// Builds a custom provider. Its key comes from `api_key_env`, then the credentials file or
+/// keychain entry named after the provider; endpoints without `api_key_env` may go keyless.
+fn custom_provider(
+    config: &CustomProviderConfig,
+    resolved: &RunDefaults,
+    tls: &TlsOptions,
+    limits: SizeLimits,
+) -> CustomProvider<MaybeOpenAiClient> {
+    let api_key = config
+        .api_key_env
+        .as_deref()
+        .and_then(|var| std::env::var(var).ok())
+        .or_else(|| resolved.credentials.api_key(&config.name))
+        .or_else(|| config.api_key_env.is_none().then(String::new));
+    let verbose = resolved.verbose;
+    CustomProvider {
+        name: config.name.clone(),
+        client: MaybeOpenAiClient {
+            inner: api_key.map(|key| {
+                OpenAiCompatibleClient::from_parts(config.base_url.clone(), key)
+                    .with_verbose(verbose)
+                    .with_tls(tls.clone())
+                    .with_limits(limits)
+                    .with_fallback_models(config.fallback_models())
+            }),
+            missing_key_error: format!(
+                "{} is required for provider '{}'",
+                config.api_key_env.as_deref().unwrap_or_default(),
+                config.name
+            ),
+        },
+        model: config.primary_model().to_string(),
     }
 }
 
+fn parse_kind_hint(lang: Option<&str>) -> Option<SourceKind> {
+    lang.map(SourceKind::from_hint)
+}
+
 fn resolved_progress_mode(progress: ProgressSetting, verbose: bool) -> ProgressMode {
     match progress {
         ProgressSetting::Silent => ProgressMode::Silent,
@@ -66,62 +213,376 @@ fn resolved_progress_mode(progress: ProgressSetting, verbose: bool) -> ProgressM
     }
 }
 
-pub(crate) fn build_run_options(resolved: &RunDefaults, model_override: Option<String>) -> RunOptions {
+pub(crate) fn sampling_options(resolved: &RunDefaults) -> SamplingOptions {
+    SamplingOptions {
+        temperature: resolved.temperature,
+        top_p: resolved.top_p,
+        max_tokens: resolved.max_tokens,
+        seed: resolved.seed,
+    }
+}
+
+pub(crate) fn build_run_options(
+    resolved: &RunDefaults,
+    model_override: Option<String>,
+    print_js_target: JsOutputTarget,
+) -> RunOptions {
     RunOptions {
         kind_hint: parse_kind_hint(resolved.lang.as_deref()),
         language_hint: resolved.lang.clone(),
         force_llm: resolved.force_llm,
         no_cache: resolved.no_cache,
         print_js: resolved.print_js,
-        provider_selection: provider_to_selection(resolved.provider),
+        print_js_target,
+        provider_selection: provider_selection(resolved.provider.clone()),
         model_override,
+        sampling: sampling_options(resolved),
+        structured_output: resolved.structured_output,
+        explain: resolved.explain,
+        context_window: (resolved.context_window > 0).then_some(resolved.context_window),
+        module: false,
+        jobs: 1,
         progress_mode: resolved_progress_mode(resolved.progress, resolved.verbose),
     }
 }
 
+/// Resolves run defaults for `command`, first merging its `defaults.<command>` section from
+/// `klumo.json` into `cli_overrides`.
 pub(crate) fn resolve_config(
     config: Option<PathBuf>,
-    cli_overrides: &CliRunOverrides,
+    command: &str,
+    cli_overrides: &mut CliRunOverrides,
 ) -> Result<RunDefaults> {
     let cwd = std::env::current_dir()?;
     let file_cfg = load_file_config(config.as_deref(), &cwd)?;
+    if let Some(defaults) = file_cfg
+        .as_ref()
+        .and_then(|cfg| cfg.command_defaults(command))
+    {
+        cli_overrides.apply_command_defaults(defaults);
+    }
+    for warning in legacy_env_warnings() {
+        eprintln!("[klumo] warning: {warning}");
+    }
     let env_cfg = EnvConfig::from_current_env();
+    let credentials = load_credentials(&env_cfg, file_cfg.as_ref())?;
     Ok(resolve_run_defaults(
         cli_overrides,
         &env_cfg,
         file_cfg.as_ref(),
+        &credentials,
     ))
 }
 
+/// Warns that certificates go unchecked. Commands call it once where they set up their
+/// provider clients, not per client.
+pub(crate) fn warn_insecure_tls(resolved: &RunDefaults) {
+    if resolved.danger_accept_invalid_certs {
+        eprintln!(
+            "[klumo] warning: TLS certificate verification is disabled (danger_accept_invalid_certs)"
+        );
+    }
+}
+
+/// TLS settings shared by every provider client.
+pub(crate) fn tls_options(resolved: &RunDefaults) -> TlsOptions {
+    TlsOptions {
+        ca_bundle: resolved.openai_ca_bundle.clone(),
+        accept_invalid_certs: resolved.danger_accept_invalid_certs,
+    }
+}
+
+/// Ollama client for the resolved server and `keep_alive`, using `tls`.
+pub(crate) fn ollama_client(resolved: &RunDefaults, tls: TlsOptions) -> Result<OllamaClient> {
+    Ok(OllamaClient::new(resolved.ollama_url.clone())?
+        .with_tls(tls)
+        .with_keep_alive(resolved.ollama_keep_alive.clone()))
+}
+
+/// With `ollama_warm_up` on and a provider that may route to Ollama, loads the model on a
+/// background thread so the first translation does not wait for it. The model is
+/// `model_override` when given, else the one auto routing would pick. Failures only show
+/// with `--verbose`; a real problem surfaces again on the first translation.
+pub(crate) fn spawn_ollama_warm_up(resolved: &RunDefaults, model_override: Option<&str>) {
+    if !resolved.ollama_warm_up
+        || !matches!(
+            resolved.provider,
+            ProviderSetting::Auto | ProviderSetting::Ollama
+        )
+    {
+        return;
+    }
+    let Ok(client) = ollama_client(resolved, tls_options(resolved)) else {
+        return;
+    };
+    let model_override = model_override.map(str::to_string);
+    let configured = resolved.ollama_model.clone();
+    let preferences = resolved.ollama_model_preferences.clone();
+    let verbose = resolved.verbose;
+    thread::spawn(move || {
+        let model = model_override.unwrap_or_else(|| {
+            client
+                .list_models()
+                .ok()
+                .and_then(|installed| pick_ollama_model(&installed, &configured, &preferences))
+                .unwrap_or(configured)
+        });
+        match client.warm_up(&model) {
+            Ok(elapsed) if verbose => eprintln!(
+                "[klumo] warmed up Ollama model {model} in {}ms",
+                elapsed.as_millis()
+            ),
+            Err(err) if verbose => eprintln!("[klumo] Ollama warm-up of {model} skipped: {err:#}"),
+            _ => {}
+        }
+    });
+}
+
+pub(crate) fn cache_policy(settings: &CompileCacheSettings) -> CachePolicy {
+    const MB: u64 = 1024 * 1024;
+    const DAY: u64 = 24 * 60 * 60;
+    CachePolicy {
+        max_bytes: settings.max_size_mb.map(|mb| mb.saturating_mul(MB)),
+        max_age: settings
+            .max_age_days
+            .map(|days| Duration::from_secs(days.saturating_mul(DAY))),
+        other_versions: false,
+    }
+}
+
 pub(crate) fn build_compiler(resolved: &RunDefaults) -> Result<KlumoCompiler> {
-    let ollama_client = OllamaClient::new(resolved.ollama_url.clone())?;
+    warn_insecure_tls(resolved);
+    let tls = tls_options(resolved);
+    let limits = SizeLimits {
+        max_prompt_bytes: resolved.max_prompt_bytes,
+        max_response_bytes: resolved.max_response_bytes,
+        ..SizeLimits::default()
+    };
+    let ollama_client = ollama_client(resolved, tls.clone())?.with_limits(limits);
+    // Like the response cache, skipped when a fresh translation is asked for.
+    let embeddings = match &resolved.similar_cache.model {
+        Some(model) if !resolved.no_cache => Some(EmbeddingIndex::new(
+            EmbeddingIndex::root_in(file_compile_cache(resolved).root()),
+            OllamaEmbedder {
+                client: ollama_client.clone(),
+                model: model.clone(),
+            },
+            resolved
+                .similar_cache
+                .threshold
+                .unwrap_or(DEFAULT_SIMILARITY_THRESHOLD),
+        )),
+        _ => None,
+    };
     let openai_client = MaybeOpenAiClient {
         inner: resolved.openai_api_key.clone().map(|api_key| {
             OpenAiCompatibleClient::from_parts(resolved.openai_base_url.clone(), api_key)
+                .with_verbose(resolved.verbose)
+                .with_tls(tls.clone())
+                .with_limits(limits)
         }),
+        missing_key_error: "OPENAI_API_KEY is required for OpenAI-compatible translation"
+            .to_string(),
     };
 
+    let usage = resolved
+        .usage_metrics
+        .then(UsageLog::default_path)
+        .and_then(Result::ok)
+        .map(|path| UsageLog { path });
     let router = ProviderRouter {
         ollama: ollama_client.clone(),
         openai: openai_client,
-        reachability: OllamaProbe {
-            client: ollama_client,
-        },
+        reachability: CachedProbe::new(
+            OllamaProbe {
+                client: ollama_client,
+                confirm_pull: resolved.ollama_auto_pull.is_none(),
+                progress: resolved_progress_mode(resolved.progress, resolved.verbose),
+            },
+            DEFAULT_PROBE_TTL,
+        ),
         ollama_model: resolved.ollama_model.clone(),
         openai_model: resolved.openai_model.clone(),
+        llamacpp: resolved.llamacpp_url.clone().map(|url| LlamaCppProvider {
+            client: Box::new(
+                LlamaCppClient::new(url)
+                    .with_tls(tls.clone())
+                    .with_limits(limits),
+            ),
+            model: resolved.llamacpp_model.clone(),
+        }),
+        custom: resolved
+            .providers
+            .iter()
+            .map(|config| custom_provider(config, resolved, &tls, limits))
+            .collect(),
+        ollama_model_preferences: resolved.ollama_model_preferences.clone(),
+        fixtures: Some(FixtureStore {
+            dir: resolved.fixtures_dir.clone(),
+            record: resolved.record_fixtures,
+        }),
+        prompts: PromptTemplates::with_overrides(Path::new(PROMPTS_DIR))?
+            .with_examples(load_examples(&resolved.examples)?),
+        ollama_auto_pull: resolved.ollama_auto_pull != Some(false),
+        observer: resolved
+            .llm_log
+            .clone()
+            .map(|dir| Arc::new(LlmLogDir::new(dir)) as Arc<dyn LlmObserver>),
+        metrics: ProviderMetricsStore::default_path()
+            .ok()
+            .map(|path| ProviderMetricsStore { path }),
+        circuit: (resolved.circuit_breaker.failures > 0).then(|| {
+            CircuitBreaker::new(
+                resolved.circuit_breaker.failures,
+                Duration::from_secs(resolved.circuit_breaker.window_secs),
+                Duration::from_secs(resolved.circuit_breaker.cooldown_secs),
+            )
+        }),
+        // `--no-cache` asks for a fresh translation, so it skips this cache too, and a
+        // deterministic check must reach the provider both times.
+        response_cache: (resolved.response_cache && !resolved.no_cache && !resolved.deterministic)
+            .then(ResponseCache::default),
+        usage: usage.clone(),
     };
 
-    Ok(CompilerRouter {
-        translator: router,
-        cache: FileCompileCache::default(),
+    Ok(CompilerRouter::new(router, compile_cache(resolved)?)
+        .with_model_tiers(
+            resolved
+                .model_tiers
+                .iter()
+                .map(|tier| ModelTier {
+                    model: tier.model.clone(),
+                    provider: tier.provider.clone().map(provider_selection),
+                    max_source_bytes: tier.max_source_bytes,
+                    languages: tier.languages.clone().unwrap_or_default(),
+                })
+                .collect(),
+        )
+        .with_language_routes(
+            resolved
+                .language_providers
+                .iter()
+                .map(|(language, route)| LanguageRoute {
+                    language: language.clone(),
+                    provider: route.provider.clone().map(provider_selection),
+                    model: route.model.clone(),
+                })
+                .collect(),
+        )
+        .with_signatures(signature_policy(resolved)?)
+        .with_approval(resolved.approval_required.then(PendingStore::default))
+        .with_hit_log(
+            std::env::current_dir()
+                .ok()
+                .and_then(|dir| CacheHitLog::for_project(&dir).ok()),
+        )
+        .with_usage(usage)
+        .with_passes(vec![Box::new(jsx_pass(resolved))])
+        .with_deterministic(resolved.deterministic)
+        .with_syntax_repair_attempts(resolved.syntax_repair_attempts)
+        .with_incremental(resolved.incremental.then(|| {
+            ChunkMapStore::new(ChunkMapStore::root_in(file_compile_cache(resolved).root()))
+        }))
+        .with_classify_language(resolved.classify_language)
+        .with_language_cache(resolved.classify_language.then(|| {
+            LanguageCache::new(LanguageCache::root_in(file_compile_cache(resolved).root()))
+        }))
+        .with_python_fast_path(resolved.python_fast_path)
+        .with_embeddings(embeddings))
+}
+
+fn jsx_pass(resolved: &RunDefaults) -> JsxPass {
+    let defaults = JsxPass::default();
+    JsxPass {
+        pragma: resolved.jsx.pragma.clone().unwrap_or(defaults.pragma),
+        fragment: resolved.jsx.fragment.clone().unwrap_or(defaults.fragment),
+    }
+}
+
+/// The local compile cache: `cache_dir` (`KLUMO_CACHE_DIR` or `klumo.json`) when set, else
+/// the one under `~/.klumo`.
+pub(crate) fn file_compile_cache(resolved: &RunDefaults) -> FileCompileCache {
+    resolved
+        .cache_dir
+        .clone()
+        .map(FileCompileCache::new)
+        .unwrap_or_default()
+}
+
+fn compile_cache(resolved: &RunDefaults) -> Result<KlumoCache> {
+    let local = file_compile_cache(resolved).with_policy(cache_policy(&resolved.compile_cache));
+    Ok(match &resolved.cache_url {
+        Some(url) => Box::new(
+            HttpCompileCache::new(url.clone(), local)?.with_token(resolved.cache_token.clone()),
+        ),
+        None => Box::new(local),
+    })
+}
+
+fn signature_policy(resolved: &RunDefaults) -> Result<SignaturePolicy> {
+    if resolved.verify_signatures {
+        let keys = TrustedKeys::from_hex(&resolved.trusted_keys)?;
+        if keys.is_empty() {
+            return Err(anyhow!(
+                "--verify-signatures needs at least one public key in trusted_keys (klumo.json)"
+            ));
+        }
+        return Ok(SignaturePolicy::Verify(keys));
+    }
+    Ok(match &resolved.signing_key {
+        Some(path) => SignaturePolicy::Sign(SigningKey::load(path)?),
+        None => SignaturePolicy::Off,
     })
 }
 
+/// Console output limits for a command: the `output_limit` section of `klumo.json`, with the
+/// built-in limits for unset fields when output goes to a terminal. `0` turns a limit off.
+pub(crate) fn output_limits(resolved: &RunDefaults, terminal: bool) -> OutputLimits {
+    let standard = if terminal {
+        OutputLimits::standard()
+    } else {
+        OutputLimits::default()
+    };
+    let pick = |configured: Option<usize>, fallback: Option<usize>| match configured {
+        Some(0) => None,
+        Some(limit) => Some(limit),
+        None => fallback,
+    };
+    OutputLimits {
+        max_entry_chars: pick(
+            resolved.output_limit.max_entry_chars,
+            standard.max_entry_chars,
+        ),
+        max_total_chars: pick(
+            resolved.output_limit.max_total_chars,
+            standard.max_total_chars,
+        ),
+    }
+}
+
+/// How long a one-off `klumo eval` waits for timers after its code has run, so an interval it
+/// never clears does not keep it running. The timers still pending then are dropped with a
+/// warning.
+pub(crate) const EVAL_TIMER_WAIT: Duration = Duration::from_secs(5);
+
+/// Timer wait of sessions that evaluate again later (the REPL, `eval --session`): only timers
+/// already due run, and the rest run with a later line once due.
+pub(crate) const SESSION_TIMER_WAIT: Duration = Duration::ZERO;
+
 pub(crate) fn build_engine() -> Result<Box<dyn JsEngine>> {
+    build_engine_with_console(StdoutSink)
+}
+
+/// Like [`build_engine`], but Boa hands console lines to `sink` instead of printing them.
+pub(crate) fn build_engine_with_console(
+    sink: impl ConsoleSink + 'static,
+) -> Result<Box<dyn JsEngine>> {
     let selected = std::env::var("KLUMO_ENGINE").unwrap_or_else(|_| "boa".to_string());
-    match selected.trim().to_ascii_lowercase().as_str() {
-        "boa" => Ok(Box::new(BoaEngine::new())),
-        "v8" => Ok(Box::new(V8Engine::new()?)),
-        other => Err(anyhow!("unknown engine '{other}'. Supported: 'boa', 'v8'")),
-    }
+    let mut engine: Box<dyn JsEngine> = match selected.trim().to_ascii_lowercase().as_str() {
+        "boa" => Box::new(BoaEngine::with_console_sink(sink)?),
+        "v8" => Box::new(V8Engine::new()?),
+        other => return Err(anyhow!("unknown engine '{other}'. Supported: 'boa', 'v8'")),
+    };
+    crate::memory::install_memory_api(engine.as_mut())?;
+    Ok(engine)
 }
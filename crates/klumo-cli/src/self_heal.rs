@@ -1,22 +1,76 @@
+use crate::heal_summary;
 use crate::repl_helpers;
 use crate::runtime_context::KlumoCompiler;
 use anyhow::{Context, Result, anyhow};
-use klumo_compiler::{CompileRequest, Compiler, SourceKind};
-use klumo_core::{ProgressMode, RunOptions};
-use klumo_llm::ProviderSelection;
+use klumo_compiler::{CompileRequest, Compiler, RepairRequest, SourceKind};
+use klumo_config::{HealStep, heal_step_for};
+use klumo_core::{ProgressMode, RunOptions, provider_selection};
+use klumo_llm::{ProviderSelection, SamplingOptions};
 use std::fs;
 use std::path::{Path, PathBuf};
 
+/// Which provider/model each heal attempt uses: the `heal_escalation` ladder when one is
+/// configured, otherwise `heal_provider` and the session model for every attempt.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct HealPlan {
+    pub(crate) ladder: Vec<HealStep>,
+    pub(crate) provider: Option<ProviderSelection>,
+    pub(crate) model: Option<String>,
+}
+
+impl HealPlan {
+    pub(crate) fn for_attempt(
+        &self,
+        attempt: usize,
+    ) -> (Option<ProviderSelection>, Option<String>) {
+        match heal_step_for(&self.ladder, attempt) {
+            Some(step) => (
+                step.provider
+                    .clone()
+                    .map(provider_selection)
+                    .or_else(|| self.provider.clone()),
+                step.model.clone().or_else(|| self.model.clone()),
+            ),
+            None => (self.provider.clone(), self.model.clone()),
+        }
+    }
+
+    /// Human-readable rung for progress output; `None` when no ladder is configured.
+    pub(crate) fn describe_attempt(&self, attempt: usize) -> Option<String> {
+        heal_step_for(&self.ladder, attempt)?;
+        let (provider, model) = self.for_attempt(attempt);
+        let provider = match provider {
+            None => "session provider".to_string(),
+            Some(ProviderSelection::Auto) => "auto".to_string(),
+            Some(ProviderSelection::Ollama) => "ollama".to_string(),
+            Some(ProviderSelection::OpenAiCompatible) => "openai".to_string(),
+            Some(ProviderSelection::LlamaCpp) => "llamacpp".to_string(),
+            Some(ProviderSelection::Fixture) => "fixture".to_string(),
+            Some(ProviderSelection::Custom(name)) => format!("custom:{name}"),
+        };
+        Some(match model {
+            Some(model) => format!("{provider} ({model})"),
+            None => provider,
+        })
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn compile_repl_heal_candidate(
     compiler: &KlumoCompiler,
     repl_lang: &str,
     provider_selection: ProviderSelection,
-    model_override: Option<String>,
+    plan: &HealPlan,
+    sampling: SamplingOptions,
     no_cache: bool,
     scope_context: Option<String>,
     heal_prompt: String,
     attempt: usize,
 ) -> Result<String> {
+    let (provider_override, model_override) = plan.for_attempt(attempt);
+    if let Some(step) = plan.describe_attempt(attempt) {
+        eprintln!("[klumo] repl self-heal attempt {} via {step}", attempt + 1);
+    }
     let healed = compiler.compile(&CompileRequest {
         source_text: heal_prompt,
         source_id: format!("<repl-self-heal-{attempt}>"),
@@ -25,7 +79,13 @@ pub(crate) fn compile_repl_heal_candidate(
         scope_context,
         force_llm: true,
         provider_selection,
+        provider_override,
         model_override,
+        model_override_for: None,
+        sampling,
+        structured_output: false,
+        explain: false,
+        context_window: None,
         no_cache,
     })?;
     let sanitized_js = repl_helpers::sanitize_repl_javascript(&healed.javascript);
@@ -49,6 +109,12 @@ pub(crate) fn is_self_heal_supported_source(file: &Path) -> bool {
         .unwrap_or(false)
 }
 
+fn is_jsx_source(file: &Path) -> bool {
+    file.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("jsx"))
+}
+
 pub(crate) fn backup_path_for(file: &Path) -> PathBuf {
     let mut backup = file.as_os_str().to_os_string();
     backup.push(".klumo.bak");
@@ -56,29 +122,57 @@ pub(crate) fn backup_path_for(file: &Path) -> PathBuf {
 }
 
 pub(crate) fn build_self_heal_request(path: &Path, source: &str, error_text: &str) -> String {
-    format!(
-        "Repair this JavaScript file so it runs successfully.\n\
-Return ONLY complete JavaScript source for the full file, no markdown, no prose.\n\
-Preserve behavior and structure as much as possible.\n\
-File: {}\n\
-Runtime error:\n{}\n\
-SOURCE START\n{}\n\
-SOURCE END",
-        path.display(),
+    RepairRequest {
+        file: &path.display().to_string(),
+        error_label: "Runtime error",
         error_text,
-        source
-    )
+        source,
+        describe_change: true,
+    }
+    .render()
+}
+
+/// A patch `try_self_heal` wrote: the source it repaired and the model's account of the change.
+pub(crate) struct HealPatch {
+    pub(crate) before: String,
+    pub(crate) description: Option<String>,
 }
 
 pub(crate) fn try_self_heal(
     compiler: &KlumoCompiler,
     file: &Path,
     options: &RunOptions,
+    plan: &HealPlan,
     error_text: &str,
     attempt: usize,
-) -> Result<()> {
+) -> Result<HealPatch> {
     let current_source = fs::read_to_string(file)
         .with_context(|| format!("failed reading source for self-heal {}", file.display()))?;
+    // A JSX file ran as the JavaScript its JSX lowers to, so that is what the runtime error
+    // describes and what gets repaired; plain JavaScript is valid JSX to write back.
+    let current_source = if is_jsx_source(file) {
+        compiler
+            .prepare(&CompileRequest {
+                source_text: current_source,
+                source_id: file.display().to_string(),
+                kind_hint: options.kind_hint.clone(),
+                language_hint: options.language_hint.clone(),
+                scope_context: None,
+                force_llm: false,
+                provider_selection: options.provider_selection.clone(),
+                provider_override: None,
+                model_override: None,
+                model_override_for: None,
+                sampling: options.sampling,
+                structured_output: false,
+                explain: false,
+                context_window: None,
+                no_cache: true,
+            })?
+            .source_text
+    } else {
+        current_source
+    };
 
     let backup = backup_path_for(file);
     if !backup.exists() {
@@ -91,11 +185,18 @@ pub(crate) fn try_self_heal(
         })?;
     }
 
+    let (provider_override, model_override) = plan.for_attempt(attempt);
     if !matches!(options.progress_mode, ProgressMode::Silent) {
-        eprintln!(
-            "[klumo] self-heal attempt {}: requesting file patch via LLM",
-            attempt + 1
-        );
+        match plan.describe_attempt(attempt) {
+            Some(step) => eprintln!(
+                "[klumo] self-heal attempt {}: requesting file patch via {step}",
+                attempt + 1
+            ),
+            None => eprintln!(
+                "[klumo] self-heal attempt {}: requesting file patch via LLM",
+                attempt + 1
+            ),
+        }
     }
 
     let repaired = compiler.compile(&CompileRequest {
@@ -105,8 +206,15 @@ pub(crate) fn try_self_heal(
         language_hint: Some("self-heal-javascript".to_string()),
         scope_context: None,
         force_llm: true,
-        provider_selection: options.provider_selection,
-        model_override: options.model_override.clone(),
+        provider_selection: options.provider_selection.clone(),
+        provider_override,
+        model_override,
+        model_override_for: None,
+        sampling: options.sampling,
+        // The notes carry the one-sentence change description for the heal summary.
+        structured_output: true,
+        explain: false,
+        context_window: None,
         no_cache: true,
     })?;
 
@@ -114,11 +222,18 @@ pub(crate) fn try_self_heal(
         return Err(anyhow!("self-heal generated empty output"));
     }
 
-    fs::write(file, repaired.javascript)
+    fs::write(file, &repaired.javascript)
         .with_context(|| format!("failed writing healed file {}", file.display()))?;
 
     if !matches!(options.progress_mode, ProgressMode::Silent) {
         eprintln!("[klumo] self-heal wrote patch to {}", file.display());
     }
-    Ok(())
+    Ok(HealPatch {
+        before: current_source,
+        description: repaired
+            .metadata
+            .notes
+            .as_deref()
+            .and_then(heal_summary::first_sentence),
+    })
 }
@@ -0,0 +1,389 @@
+use anyhow::{Context, Result, bail};
+use klumo_config::{FileConfig, ProviderSetting, RunDefaults};
+use serde_json::{Map as JsonMap, Value as JsonValue, json};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A template file: its path relative to the project root, its contents and whether it is
+/// executable.
+struct TemplateFile {
+    path: PathBuf,
+    contents: Vec<u8>,
+    executable: bool,
+}
+
+/// Template `klumo init` uses when `--template` is not passed.
+pub(crate) const DEFAULT_TEMPLATE: &str = "pseudocode";
+
+/// A starter kit shipped inside the binary, addressed by name.
+struct BuiltinTemplate {
+    name: &'static str,
+    description: &'static str,
+    files: &'static [(&'static str, &'static str)],
+}
+
+const BUILTIN_TEMPLATES: &[BuiltinTemplate] = &[
+    BuiltinTemplate {
+        name: "pseudocode",
+        description: "a pseudocode entry point translated by the configured provider",
+        files: &[
+            (
+                "klumo.json",
+                r#"{
+  "name": "{{project_name}}",
+  "version": "0.1.0",
+  "provider": "{{provider}}",
+  "lang": "pseudocode",
+  "scripts": {
+    "start": "klumo run main.pseudocode"
+  }
+}
+"#,
+            ),
+            (
+                "main.pseudocode",
+                "write \"Hello from {{project_name}}\"\nset n to 5\nwrite \"n * 2 = \" + (n * 2)\n",
+            ),
+            (
+                "README.md",
+                "# {{project_name}}\n\nPseudocode app translated with `{{provider}}` (`{{model}}`).\n\n```sh\nklumo run main.pseudocode\n```\n",
+            ),
+        ],
+    },
+    BuiltinTemplate {
+        name: "typescript",
+        description: "a TypeScript entry point importing a second module",
+        files: &[
+            (
+                "klumo.json",
+                r#"{
+  "name": "{{project_name}}",
+  "version": "0.1.0",
+  "provider": "{{provider}}",
+  "scripts": {
+    "start": "klumo run src/main.ts"
+  }
+}
+"#,
+            ),
+            (
+                "src/main.ts",
+                "import { greet } from \"./greet.ts\";\n\nconsole.log(greet(\"{{project_name}}\"));\n",
+            ),
+            (
+                "src/greet.ts",
+                "export function greet(name: string): string {\n  return `Hello from ${name}`;\n}\n",
+            ),
+            (
+                "README.md",
+                "# {{project_name}}\n\n```sh\nklumo run src/main.ts\n```\n",
+            ),
+        ],
+    },
+];
+
+/// Where a template's files come from: a built-in registry entry or a git repository.
+enum TemplateSource<'a> {
+    Builtin(&'static BuiltinTemplate),
+    Git {
+        url: &'a str,
+        reference: Option<&'a str>,
+    },
+}
+
+impl<'a> TemplateSource<'a> {
+    /// A registry name, or a git URL with an optional `#<branch-or-tag>` suffix.
+    fn parse(spec: &'a str) -> Result<Self> {
+        if let Some(template) = BUILTIN_TEMPLATES.iter().find(|t| t.name == spec) {
+            return Ok(Self::Builtin(template));
+        }
+        if !is_git_url(spec) {
+            let names: Vec<String> = BUILTIN_TEMPLATES
+                .iter()
+                .map(|t| format!("  {}: {}", t.name, t.description))
+                .collect();
+            bail!(
+                "unknown template `{spec}`; use a git URL or a built-in template:\n{}",
+                names.join("\n")
+            );
+        }
+        Ok(match spec.rsplit_once('#') {
+            Some((url, reference)) if !reference.is_empty() => Self::Git {
+                url,
+                reference: Some(reference),
+            },
+            _ => Self::Git {
+                url: spec.trim_end_matches('#'),
+                reference: None,
+            },
+        })
+    }
+}
+
+fn is_git_url(spec: &str) -> bool {
+    ["https://", "http://", "ssh://", "git://", "file://", "git@"]
+        .iter()
+        .any(|scheme| spec.starts_with(scheme))
+        || spec
+            .split('#')
+            .next()
+            .is_some_and(|url| url.ends_with(".git"))
+}
+
+/// Values `{{name}}` placeholders in template files are replaced with.
+pub(crate) struct Placeholders {
+    pub(crate) project_name: String,
+    pub(crate) provider: String,
+    pub(crate) model: String,
+}
+
+impl Placeholders {
+    /// Provider and model come from the resolved run defaults, so a template picks up the
+    /// provider the user already has configured.
+    pub(crate) fn new(project_name: String, resolved: &RunDefaults) -> Self {
+        let (provider, model) = match &resolved.provider {
+            ProviderSetting::Auto => ("auto".to_string(), resolved.ollama_model.clone()),
+            ProviderSetting::Ollama => ("ollama".to_string(), resolved.ollama_model.clone()),
+            ProviderSetting::Openai => ("openai".to_string(), resolved.openai_model.clone()),
+            ProviderSetting::Llamacpp => ("llamacpp".to_string(), resolved.llamacpp_model.clone()),
+            ProviderSetting::Fixture => ("fixture".to_string(), resolved.ollama_model.clone()),
+            ProviderSetting::Custom(name) => (
+                format!("custom:{name}"),
+                resolved
+                    .providers
+                    .iter()
+                    .find(|entry| &entry.name == name)
+                    .map(|entry| entry.primary_model().to_string())
+                    .unwrap_or_default(),
+            ),
+        };
+        Self {
+            project_name,
+            provider,
+            model,
+        }
+    }
+
+    pub(crate) fn apply(&self, text: &str) -> String {
+        text.replace("{{project_name}}", &self.project_name)
+            .replace("{{provider}}", &self.provider)
+            .replace("{{model}}", &self.model)
+    }
+
+    /// [`Self::apply`] for a JSON file, whose placeholders sit inside string literals: the
+    /// values are escaped, so a name with quotes or backslashes still makes valid JSON.
+    fn apply_json(&self, text: &str) -> String {
+        let escape = |value: &str| {
+            let quoted = JsonValue::String(value.to_string()).to_string();
+            quoted[1..quoted.len() - 1].to_string()
+        };
+        Self {
+            project_name: escape(&self.project_name),
+            provider: escape(&self.provider),
+            model: escape(&self.model),
+        }
+        .apply(text)
+    }
+}
+
+/// Creates a project in `dir` from `spec` and returns the files written, relative to `dir`.
+///
+/// Nothing is written when any template file already exists in `dir`. The generated
+/// `klumo.json` records the template under `template`, creating the file when the template
+/// has none.
+pub(crate) fn init_project(
+    dir: &Path,
+    spec: &str,
+    placeholders: &Placeholders,
+) -> Result<Vec<PathBuf>> {
+    let source = TemplateSource::parse(spec)?;
+    let (mut files, provenance) = match source {
+        TemplateSource::Builtin(template) => (
+            template
+                .files
+                .iter()
+                .map(|(path, text)| TemplateFile {
+                    path: PathBuf::from(path),
+                    contents: text.as_bytes().to_vec(),
+                    executable: false,
+                })
+                .collect::<Vec<_>>(),
+            json!({
+                "source": template.name,
+                "revision": format!("klumo-{}", env!("CARGO_PKG_VERSION")),
+            }),
+        ),
+        TemplateSource::Git { url, reference } => {
+            let (files, revision) = fetch_git_template(url, reference)?;
+            (files, json!({ "source": spec, "revision": revision }))
+        }
+    };
+
+    for file in &mut files {
+        if let Ok(text) = std::str::from_utf8(&file.contents) {
+            file.contents = if file.path.extension().is_some_and(|ext| ext == "json") {
+                placeholders.apply_json(text)
+            } else {
+                placeholders.apply(text)
+            }
+            .into_bytes();
+        }
+    }
+    let config_index = match files
+        .iter()
+        .position(|file| file.path == Path::new("klumo.json"))
+    {
+        Some(index) => index,
+        None => {
+            files.push(TemplateFile {
+                path: PathBuf::from("klumo.json"),
+                contents: b"{}".to_vec(),
+                executable: false,
+            });
+            files.len() - 1
+        }
+    };
+    files[config_index].contents =
+        with_provenance(&files[config_index].contents, placeholders, provenance)?;
+
+    if let Some(file) = files.iter().find(|file| dir.join(&file.path).exists()) {
+        bail!(
+            "{} already exists; klumo init only writes into a directory without the template's files",
+            dir.join(&file.path).display()
+        );
+    }
+    for file in &files {
+        let target = dir.join(&file.path);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed creating {}", parent.display()))?;
+        }
+        fs::write(&target, &file.contents)
+            .with_context(|| format!("failed writing {}", target.display()))?;
+        if file.executable {
+            make_executable(&target)?;
+        }
+    }
+    Ok(files.into_iter().map(|file| file.path).collect())
+}
+
+/// Adds execute permission wherever `path` has read permission, like `chmod +x` under the
+/// default umask.
+#[cfg(unix)]
+fn make_executable(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mut permissions = fs::metadata(path)
+        .with_context(|| format!("failed reading {}", path.display()))?
+        .permissions();
+    permissions.set_mode(permissions.mode() | ((permissions.mode() & 0o444) >> 2));
+    fs::set_permissions(path, permissions)
+        .with_context(|| format!("failed making {} executable", path.display()))
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Adds `template` provenance (and `name` when missing) to a template's `klumo.json`.
+fn with_provenance(
+    raw: &[u8],
+    placeholders: &Placeholders,
+    provenance: JsonValue,
+) -> Result<Vec<u8>> {
+    let mut config: JsonMap<String, JsonValue> =
+        serde_json::from_slice(raw).context("template klumo.json is not a JSON object")?;
+    config
+        .entry("name")
+        .or_insert_with(|| JsonValue::String(placeholders.project_name.clone()));
+    config.insert("template".to_string(), provenance);
+    let config = JsonValue::Object(config);
+    serde_json::from_value::<FileConfig>(config.clone())
+        .context("template klumo.json is not a valid klumo config")?;
+    Ok(format!("{}\n", serde_json::to_string_pretty(&config)?).into_bytes())
+}
+
+/// Shallow-clones `url` into a fresh private directory, removed again afterwards, and returns
+/// its files (without `.git`) and the commit they came from.
+fn fetch_git_template(url: &str, reference: Option<&str>) -> Result<(Vec<TemplateFile>, String)> {
+    let scratch = tempfile::Builder::new()
+        .prefix("klumo-template-")
+        .tempdir()
+        .context("failed creating a directory to clone the template into")?;
+    let checkout = scratch.path().join("checkout");
+    let mut clone = Command::new("git");
+    clone.args(["clone", "--quiet", "--depth", "1"]);
+    if let Some(reference) = reference {
+        clone.args(["--branch", reference]);
+    }
+    // `--` keeps a URL starting with `-` from being read as an option.
+    let status = clone
+        .arg("--")
+        .arg(url)
+        .arg(&checkout)
+        .status()
+        .context("failed running git clone; is git installed?")?;
+    if !status.success() {
+        bail!("git clone {url} failed with exit status {status}");
+    }
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(&checkout)
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .context("failed running git rev-parse")?;
+    if !output.status.success() {
+        bail!("git rev-parse HEAD failed in cloned template");
+    }
+    let revision = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let mut files = Vec::new();
+    collect_files(&checkout, Path::new(""), &mut files)?;
+    Ok((files, revision))
+}
+
+/// Symbolic links are refused: one could point outside the template, e.g. at a file of the
+/// user's that the project would then contain a copy of.
+fn collect_files(root: &Path, relative: &Path, files: &mut Vec<TemplateFile>) -> Result<()> {
+    let dir = root.join(relative);
+    let mut entries = fs::read_dir(&dir)
+        .with_context(|| format!("failed reading {}", dir.display()))?
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        let path = relative.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            bail!(
+                "template file {} is a symbolic link, which klumo init does not copy",
+                path.display()
+            );
+        }
+        if file_type.is_dir() {
+            collect_files(root, &path, files)?;
+        } else {
+            let contents = fs::read(entry.path())
+                .with_context(|| format!("failed reading {}", entry.path().display()))?;
+            files.push(TemplateFile {
+                path,
+                executable: is_executable(&entry.metadata()?),
+                contents,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &fs::Metadata) -> bool {
+    false
+}
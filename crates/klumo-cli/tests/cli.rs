@@ -3,6 +3,7 @@ use insta::assert_snapshot;
 use predicates::prelude::PredicateBooleanExt;
 use predicates::str::{contains, is_empty};
 use std::fs;
+use std::path::Path;
 use tempfile::tempdir;
 
 #[test]
@@ -14,6 +15,81 @@ fn eval_prints_result() {
         .stdout(contains("6"));
 }
 
+#[test]
+fn eval_module_uses_module_semantics() {
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .args(["eval", "--module", "console.log(typeof this); 1"])
+        .assert()
+        .success()
+        .stdout("undefined\n");
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .args(["eval", "--module", "undeclared = 1"])
+        .assert()
+        .failure()
+        .stderr(contains("undeclared"));
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .args(["eval", "--module", "--session", "s", "1"])
+        .assert()
+        .failure()
+        .stderr(contains("cannot be used with"));
+}
+
+#[test]
+fn eval_session_is_not_blocked_by_an_uncleared_interval() {
+    let dir = tempdir().expect("tempdir should work");
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .env("KLUMO_SESSIONS_DIR", dir.path())
+        .args([
+            "eval",
+            "--session",
+            "ticker",
+            "setInterval(() => {}, 10); 'started'",
+        ])
+        .timeout(std::time::Duration::from_secs(30))
+        .assert()
+        .success()
+        .stdout("started\n");
+}
+
+#[test]
+fn eval_warns_about_the_timers_it_drops() {
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .args(["eval", "setTimeout(() => {}, 60000); 'started'"])
+        .timeout(std::time::Duration::from_secs(30))
+        .assert()
+        .success()
+        .stdout("started\n")
+        .stderr(contains("dropped 1 timer(s) still pending after 5s"));
+}
+
+#[test]
+fn eval_session_keeps_state_across_invocations() {
+    let dir = tempdir().expect("tempdir should work");
+    let eval = |code: &str| {
+        Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+            .env("KLUMO_SESSIONS_DIR", dir.path())
+            .args(["eval", "--session", "build", code])
+            .assert()
+    };
+
+    eval("var total = 1; console.log('set');")
+        .success()
+        .stdout("set\n");
+    // The first snippet is replayed silently before the new one runs.
+    eval("total += 2; total").success().stdout("3\n");
+    eval("total").success().stdout("3\n");
+    eval("missing").failure();
+    eval("total").success().stdout("3\n");
+    assert!(dir.path().join("build.json").exists());
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .env("KLUMO_SESSIONS_DIR", dir.path())
+        .args(["eval", "--session", "../escape", "1"])
+        .assert()
+        .failure()
+        .stderr(contains("invalid session name"));
+}
+
 #[test]
 fn no_args_enters_repl() {
     Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
@@ -63,6 +139,317 @@ fn run_js_file_works_without_llm() {
         .stdout(contains("42"));
 }
 
+#[test]
+fn run_typescript_strips_types_without_a_provider() {
+    let dir = tempdir().expect("tempdir should work");
+    let path = dir.path().join("main.ts");
+    fs::write(
+        &path,
+        "interface Point { x: number; y: number }\n\
+         enum Axis { X, Y }\n\
+         const origin: Point = { x: 1, y: 2 };\n\
+         function sum(p: Point): number { return p.x + p.y; }\n\
+         console.log(sum(origin), Axis[Axis.Y]);\n",
+    )
+    .expect("write should work");
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .current_dir(dir.path())
+        .args(["run", path.to_str().expect("path utf8"), "--raw-output"])
+        .assert()
+        .success()
+        .stdout("3 Y\n");
+}
+
+#[test]
+fn run_jsx_lowers_elements_with_the_configured_pragma() {
+    let dir = tempdir().expect("tempdir should work");
+    fs::write(dir.path().join("klumo.json"), r#"{"jsx":{"pragma":"h"}}"#)
+        .expect("write should work");
+    let path = dir.path().join("app.jsx");
+    fs::write(
+        &path,
+        "const h = (type, props, ...children) => `<${type}>${children.join('')}</${type}>`;\n\
+         console.log(<b title=\"x\">sum: {1 + 1}</b>);\n",
+    )
+    .expect("write should work");
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .current_dir(dir.path())
+        .args(["run", path.to_str().expect("path utf8"), "--raw-output"])
+        .assert()
+        .success()
+        .stdout("<b>sum: 2</b>\n");
+}
+
+#[test]
+fn run_warns_once_that_certificates_go_unchecked() {
+    let dir = tempdir().expect("tempdir should work");
+    fs::write(
+        dir.path().join("klumo.json"),
+        r#"{"danger_accept_invalid_certs":true}"#,
+    )
+    .expect("write should work");
+    fs::write(dir.path().join("hello.js"), "console.log('hi')\n").expect("write should work");
+
+    let output = Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .current_dir(dir.path())
+        .args(["run", "hello.js"])
+        .output()
+        .expect("klumo should run");
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr
+            .matches("TLS certificate verification is disabled")
+            .count(),
+        1,
+        "{stderr}"
+    );
+}
+
+#[test]
+fn run_and_bundle_follow_relative_imports() {
+    let dir = tempdir().expect("tempdir should work");
+    fs::create_dir(dir.path().join("lib")).expect("mkdir should work");
+    fs::write(
+        dir.path().join("main.mjs"),
+        "import { greet } from './lib/greet.mjs';\nconsole.log(greet('graph'));\n",
+    )
+    .expect("write should work");
+    fs::write(
+        dir.path().join("lib/greet.mjs"),
+        "const { suffix } = require('./suffix.cjs');\nexport const greet = (name) => `hello ${name}${suffix}`;\n",
+    )
+    .expect("write should work");
+    fs::write(dir.path().join("lib/suffix.cjs"), "exports.suffix = '!';\n")
+        .expect("write should work");
+    let entry = dir.path().join("main.mjs");
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .current_dir(dir.path())
+        .args(["run", entry.to_str().expect("path utf8"), "--raw-output"])
+        .assert()
+        .success()
+        .stdout("hello graph!\n");
+
+    let bundle = dir.path().join("out.js");
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .current_dir(dir.path())
+        .args([
+            "bundle",
+            entry.to_str().expect("path utf8"),
+            "-o",
+            bundle.to_str().expect("path utf8"),
+        ])
+        .assert()
+        .success();
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .args(["run", bundle.to_str().expect("path utf8"), "--raw-output"])
+        .assert()
+        .success()
+        .stdout("hello graph!\n");
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .current_dir(dir.path())
+        .args(["graph", "main.mjs"])
+        .assert()
+        .success()
+        .stdout(
+            "main.mjs [passthrough]\n\
+└── lib/greet.mjs [passthrough]\n    \
+    └── lib/suffix.cjs [passthrough]\n\
+\n\
+3 modules: 3 passthrough, 0 translated (0 cached, 0 fresh)\n",
+        );
+}
+
+#[test]
+fn linked_errors_and_bundle_source_maps_point_at_original_files() {
+    let dir = tempdir().expect("tempdir should work");
+    fs::write(
+        dir.path().join("main.mjs"),
+        "import { twice } from './math.mjs';\nconsole.log(twice(21));\n",
+    )
+    .expect("write should work");
+    fs::write(
+        dir.path().join("math.mjs"),
+        "// helpers\nexport const twice = (n) => n * ;\n",
+    )
+    .expect("write should work");
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .current_dir(dir.path())
+        .args(["run", "main.mjs"])
+        .assert()
+        .failure()
+        .stderr(contains("(from ").and(contains("math.mjs:2)")));
+
+    fs::write(
+        dir.path().join("math.mjs"),
+        "// helpers\nexport const twice = (n) => n * 2;\n",
+    )
+    .expect("write should work");
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .current_dir(dir.path())
+        .args(["bundle", "main.mjs", "-o", "out.js", "--source-map"])
+        .assert()
+        .success();
+    let bundle = fs::read_to_string(dir.path().join("out.js")).expect("bundle should exist");
+    assert!(bundle.ends_with("//# sourceMappingURL=out.js.map\n"));
+    let map = fs::read_to_string(dir.path().join("out.js.map")).expect("map should exist");
+    assert!(map.contains("\"version\":3") && map.contains("math.mjs"));
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .current_dir(dir.path())
+        .args(["run", "out.js", "--raw-output"])
+        .assert()
+        .success()
+        .stdout("42\n");
+}
+
+/// Answers the first `requests` connections with `body`.
+fn serve_source(body: &'static str, requests: usize) -> String {
+    use std::io::{Read, Write};
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("bind should work");
+    let addr = listener.local_addr().expect("local addr should work");
+    std::thread::spawn(move || {
+        for stream in listener.incoming().take(requests) {
+            let mut stream = stream.expect("accept should work");
+            let mut buf = [0_u8; 4096];
+            let _ = stream.read(&mut buf);
+            let reply = format!(
+                "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            stream
+                .write_all(reply.as_bytes())
+                .expect("write should work");
+        }
+    });
+    format!("http://{addr}")
+}
+
+#[test]
+fn run_remote_source_pins_and_caches_the_download() {
+    let dir = tempdir().expect("tempdir should work");
+    let url = format!(
+        "{}/hello.js",
+        serve_source("console.log('remote ok');\n", 2)
+    );
+    let run = |args: &[&str]| {
+        let mut command = Command::new(assert_cmd::cargo::cargo_bin!("klumo"));
+        command
+            .current_dir(dir.path())
+            .env("KLUMO_REMOTE_DIR", dir.path().join("remote"))
+            .args(["run", url.as_str(), "--raw-output"])
+            .args(args);
+        command.assert()
+    };
+
+    // Plain http is never pinned on first use.
+    run(&["--allow-remote"])
+        .failure()
+        .stderr(contains("not served over https"));
+    let integrity = "sha256-nyS0pISTGR016Ysqoz9mrc32nfgw2aLWgXijWi0MV5Q=";
+    run(&["--integrity", integrity])
+        .failure()
+        .stderr(contains("pass --allow-remote"));
+    run(&["--allow-remote", "--integrity", integrity])
+        .success()
+        .stdout("remote ok\n");
+    let pins = fs::read_to_string(dir.path().join("remote/pins.json")).expect("pins should exist");
+    assert!(pins.contains(&url));
+    // The pinned download is reused without asking or touching the network.
+    run(&[]).success().stdout("remote ok\n");
+    run(&[
+        "--allow-remote",
+        "--integrity",
+        "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=",
+    ])
+    .failure()
+    .stderr(contains("integrity check failed"));
+}
+
+#[test]
+fn run_truncates_console_entries_past_the_configured_limit() {
+    let dir = tempdir().expect("tempdir should work");
+    fs::write(
+        dir.path().join("klumo.json"),
+        r#"{"output_limit":{"max_entry_chars":5}}"#,
+    )
+    .expect("write should work");
+    let path = dir.path().join("loud.js");
+    fs::write(&path, "console.log('x'.repeat(12)); console.log('done');")
+        .expect("write should work");
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .current_dir(dir.path())
+        .args(["run", path.to_str().expect("path utf8")])
+        .assert()
+        .success()
+        .stdout(
+            "xxxxx… [truncated 7 characters; use --no-output-limit or redirect output to a file]\ndone\n",
+        );
+    for flag in ["--no-output-limit", "--raw-output"] {
+        Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+            .current_dir(dir.path())
+            .args(["run", path.to_str().expect("path utf8"), flag])
+            .assert()
+            .success()
+            .stdout("xxxxxxxxxxxx\ndone\n");
+    }
+}
+
+#[test]
+fn run_raw_output_passes_utf8_console_lines_through_untouched() {
+    let dir = tempdir().expect("tempdir should work");
+    let path = dir.path().join("utf8.js");
+    fs::write(&path, "console.log('héllo ✓', '日本 🦀'); 'final value'")
+        .expect("write should work");
+
+    let output = Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .args(["run", path.to_str().expect("path utf8"), "--raw-output"])
+        .output()
+        .expect("run should start");
+    assert!(output.status.success());
+    assert_eq!(output.stdout, "héllo ✓ 日本 🦀\n".as_bytes());
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .args(["run", path.to_str().expect("path utf8")])
+        .assert()
+        .success()
+        .stdout("héllo ✓ 日本 🦀\nfinal value\n");
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .args([
+            "run",
+            path.to_str().expect("path utf8"),
+            "--raw-output",
+            "--print-js=stdout",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("--raw-output keeps stdout for the script"));
+}
+
+#[test]
+fn run_report_memory_prints_peak_usage() {
+    let dir = tempdir().expect("tempdir should work");
+    let path = dir.path().join("memory.js");
+    fs::write(&path, "typeof klumo.memoryUsage().residentBytes").expect("write should work");
+
+    let assert = Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .args(["run", path.to_str().expect("path utf8"), "--report-memory"])
+        .assert()
+        .success();
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr).to_string();
+    if cfg!(target_os = "linux") {
+        assert!(stderr.contains("[klumo] memory: peak resident"), "{stderr}");
+        assert!(String::from_utf8_lossy(&assert.get_output().stdout).contains("number"));
+    } else {
+        assert!(stderr.contains("[klumo] memory:"), "{stderr}");
+    }
+}
+
 #[test]
 fn bare_file_argument_runs_like_runtime() {
     let dir = tempdir().expect("tempdir should work");
@@ -122,112 +509,611 @@ fn run_file_target_with_scripts_present_runs_file() {
         .stdout(contains("42"));
 }
 
-#[test]
-fn install_with_no_dependencies_reports_noop() {
-    let dir = tempdir().expect("tempdir should work");
-    let config = dir.path().join("klumo.json");
-    fs::write(&config, r#"{"name":"demo","version":"0.0.0"}"#).expect("write should work");
+#[test]
+fn init_typescript_template_creates_a_runnable_project() {
+    let dir = tempdir().expect("tempdir should work");
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .current_dir(dir.path())
+        .args(["init", "app", "--template", "typescript", "--name", "demo"])
+        .assert()
+        .success()
+        .stdout(contains("Created demo from template typescript").and(contains("src/greet.ts")));
+
+    let config = fs::read_to_string(dir.path().join("app/klumo.json")).expect("klumo.json");
+    assert!(config.contains("\"source\": \"typescript\""));
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .current_dir(dir.path().join("app"))
+        .args(["run", "src/main.ts"])
+        .assert()
+        .success()
+        .stdout(contains("Hello from demo"));
+}
+
+#[test]
+fn install_with_no_dependencies_reports_noop() {
+    let dir = tempdir().expect("tempdir should work");
+    let config = dir.path().join("klumo.json");
+    fs::write(&config, r#"{"name":"demo","version":"0.0.0"}"#).expect("write should work");
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .args(["install"])
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stdout(contains("No dependencies found in klumo.json."));
+}
+
+#[test]
+fn install_alias_i_uses_same_install_flow() {
+    let dir = tempdir().expect("tempdir should work");
+    let config = dir.path().join("klumo.json");
+    fs::write(
+        &config,
+        r#"{
+  "dependencies": {
+    "@arvid/is-char": "latest"
+  }
+}"#,
+    )
+    .expect("write should work");
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .args(["i", "--dry-run"])
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stdout(contains("dry-run: deno cache jsr:@arvid/is-char@latest"));
+}
+
+#[test]
+fn warns_when_klumo_json_has_reserved_script_names() {
+    let dir = tempdir().expect("tempdir should work");
+    let config = dir.path().join("klumo.json");
+    fs::write(
+        &config,
+        r#"{
+  "scripts": {
+    "lint": "echo custom-lint"
+  }
+}"#,
+    )
+    .expect("write should work");
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .write_stdin(".exit\n")
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stderr(contains("[klumo] warning: scripts.lint"));
+}
+
+#[test]
+fn lint_is_treated_as_builtin_command_not_file_argument() {
+    let dir = tempdir().expect("tempdir should work");
+    let path = dir.path().join("lint");
+    fs::write(&path, "1 + 1").expect("write should work");
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .args(["lint"])
+        .env("PATH", "")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(contains("failed running cargo clippy"));
+}
+
+#[test]
+fn test_command_uses_deno_semantics() {
+    let dir = tempdir().expect("tempdir should work");
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .args(["test", "--allow-all"])
+        .env("PATH", "")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(contains("failed running deno test"));
+}
+
+#[test]
+fn bundle_js_file_writes_output() {
+    let dir = tempdir().expect("tempdir should work");
+    let input = dir.path().join("hello.js");
+    let output = dir.path().join("dist").join("hello.out.js");
+    fs::write(&input, "40 + 2").expect("write should work");
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .args([
+            "bundle",
+            input.to_str().expect("path utf8"),
+            "--output",
+            output.to_str().expect("path utf8"),
+        ])
+        .assert()
+        .success()
+        .stdout(contains(output.to_str().expect("path utf8")));
+
+    let bundled = fs::read_to_string(&output).expect("bundle should exist");
+    assert!(bundled.contains("40 + 2"));
+}
+
+#[test]
+fn bundle_formats_output_for_the_requested_target() {
+    let dir = tempdir().expect("tempdir should work");
+    fs::write(dir.path().join("main.mjs"), "export const answer = 42;\n")
+        .expect("write should work");
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .current_dir(dir.path())
+        .args([
+            "bundle", "main.mjs", "-o", "out.cjs", "--target", "cjs", "--banner", "--strict",
+        ])
+        .assert()
+        .success();
+    let bundled = fs::read_to_string(dir.path().join("out.cjs")).expect("bundle should exist");
+    let lines: Vec<&str> = bundled.lines().collect();
+    assert!(lines[0].starts_with("// Generated by klumo: translated by none (passthrough)"));
+    assert!(lines[1].starts_with("// Built at "));
+    assert!(lines[2].starts_with("// Linked by klumo:"));
+    assert_eq!(lines[3], "\"use strict\";");
+    assert!(bundled.contains("module.exports = __klumo_load(0);"));
+
+    // A lone ES module already is ESM output.
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .current_dir(dir.path())
+        .args(["bundle", "main.mjs", "-o", "out.mjs", "--target", "esm"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(dir.path().join("out.mjs")).expect("bundle should exist"),
+        "export const answer = 42;\n"
+    );
+}
+
+#[test]
+fn bundle_minify_keeps_an_unminified_copy_on_request() {
+    let dir = tempdir().expect("tempdir should work");
+    let source =
+        "// adds two numbers\nfunction add (a, b) {\n  return a + b\n}\nconsole.log(add(40, 2))\n";
+    fs::write(dir.path().join("add.js"), source).expect("write should work");
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .current_dir(dir.path())
+        .args([
+            "bundle",
+            "add.js",
+            "-o",
+            "out.js",
+            "--minify",
+            "--keep-debug",
+        ])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(dir.path().join("out.js")).expect("bundle should exist"),
+        "function add(e,t){return e+t}console.log(add(40,2));"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("out.debug.js")).expect("debug bundle should exist"),
+        source
+    );
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .current_dir(dir.path())
+        .args(["run", "out.js"])
+        .assert()
+        .success()
+        .stdout("42\n");
+
+    // Minifying moves every line, so no source map describes the bundle.
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .current_dir(dir.path())
+        .args([
+            "bundle",
+            "add.js",
+            "-o",
+            "mapped.js",
+            "--minify",
+            "--source-map",
+        ])
+        .assert()
+        .success()
+        .stderr(contains("no source map for"));
+    assert!(!dir.path().join("mapped.js.map").exists());
+}
+
+#[test]
+fn run_and_bundle_read_sources_from_stdin() {
+    let dir = tempdir().expect("tempdir should work");
+    let klumo = || {
+        let mut command = Command::new(assert_cmd::cargo::cargo_bin!("klumo"));
+        command.current_dir(dir.path());
+        command
+    };
+
+    klumo()
+        .args(["run", "-", "--lang", "js"])
+        .write_stdin("console.log('piped'); 40 + 2")
+        .assert()
+        .success()
+        .stdout("piped\n42\n");
+    klumo()
+        .args(["bundle", "--stdin", "--lang", "js"])
+        .write_stdin("40 + 2")
+        .assert()
+        .success()
+        .stdout("40 + 2\n");
+    klumo()
+        .args(["run", "-"])
+        .write_stdin("40 + 2")
+        .assert()
+        .failure()
+        .stderr(contains("needs --lang"));
+}
+
+#[test]
+fn bundle_lint_fix_removes_unused_declarations() {
+    let dir = tempdir().expect("tempdir should work");
+    let input = dir.path().join("app.js");
+    let output = dir.path().join("app.out.js");
+    fs::write(
+        &input,
+        "const unused = 1;\nfunction helper() {\n  return 2;\n}\nconsole.log(40 + 2);\n",
+    )
+    .expect("write should work");
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .args([
+            "bundle",
+            input.to_str().expect("path utf8"),
+            "--output",
+            output.to_str().expect("path utf8"),
+            "--lint-fix",
+        ])
+        .assert()
+        .success();
+
+    let bundled = fs::read_to_string(&output).expect("bundle should exist");
+    assert_eq!(bundled, "console.log(40 + 2);\n");
+}
+
+#[test]
+fn signed_bundles_run_under_verify_signatures() {
+    let dir = tempdir().expect("tempdir should work");
+    let keygen = Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .current_dir(dir.path())
+        .args(["keygen"])
+        .output()
+        .expect("keygen should run");
+    assert!(keygen.status.success());
+    let stdout = String::from_utf8_lossy(&keygen.stdout);
+    let public_key = stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("public key: "))
+        .expect("keygen prints the public key");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(dir.path().join("klumo-signing.key"))
+            .expect("key file exists")
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .current_dir(dir.path())
+        .args(["keygen"])
+        .assert()
+        .failure()
+        .stderr(contains("already exists"));
+    fs::write(
+        dir.path().join("klumo.json"),
+        format!(r#"{{"signing_key":"klumo-signing.key","trusted_keys":["{public_key}"]}}"#),
+    )
+    .expect("write should work");
+    fs::write(dir.path().join("app.js"), "40 + 2").expect("write should work");
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .current_dir(dir.path())
+        .args(["bundle", "app.js", "--output", "app.out.js"])
+        .assert()
+        .success();
+    assert!(dir.path().join("app.out.js.sig").exists());
+
+    let run = || {
+        Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+            .current_dir(dir.path())
+            .args(["run", "app.out.js", "--verify-signatures"])
+            .assert()
+    };
+    run().success().stdout(contains("42"));
+
+    fs::write(dir.path().join("app.out.js"), "40 + 3").expect("write should work");
+    run()
+        .failure()
+        .stderr(contains("not a valid signature by a trusted key"));
+}
+
+#[test]
+fn verify_signatures_refuses_unsigned_imports() {
+    let dir = tempdir().expect("tempdir should work");
+    let keygen = Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .current_dir(dir.path())
+        .args(["keygen"])
+        .output()
+        .expect("keygen should run");
+    assert!(keygen.status.success());
+    let stdout = String::from_utf8_lossy(&keygen.stdout);
+    let public_key = stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("public key: "))
+        .expect("keygen prints the public key");
+    fs::write(
+        dir.path().join("klumo.json"),
+        format!(r#"{{"signing_key":"klumo-signing.key","trusted_keys":["{public_key}"]}}"#),
+    )
+    .expect("write should work");
+    fs::write(
+        dir.path().join("app.mjs"),
+        "const lib = './lib.mjs';\nconst { answer } = await import(lib);\nconsole.log(answer);\n",
+    )
+    .expect("write should work");
+    fs::write(dir.path().join("lib.mjs"), "export const answer = 42;\n")
+        .expect("write should work");
+
+    // The computed import is not followed by the bundler, so only the entry gets signed.
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .current_dir(dir.path())
+        .args(["bundle", "app.mjs", "--output", "app.out.mjs"])
+        .assert()
+        .success();
 
     Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
-        .args(["install"])
         .current_dir(dir.path())
+        .args(["run", "app.out.mjs", "--verify-signatures"])
         .assert()
-        .success()
-        .stdout(contains("No dependencies found in klumo.json."));
+        .failure()
+        .stderr(contains("cannot import"))
+        .stderr(contains("lib.mjs.sig"));
 }
 
 #[test]
-fn install_alias_i_uses_same_install_flow() {
-    let dir = tempdir().expect("tempdir should work");
-    let config = dir.path().join("klumo.json");
+fn review_approves_pending_translations_into_the_cache() {
+    let home = tempdir().expect("tempdir should work");
+    let pending = home.path().join(".klumo/pending");
+    fs::create_dir_all(&pending).expect("mkdir should work");
+    let key = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
     fs::write(
-        &config,
-        r#"{
-  "dependencies": {
-    "@arvid/is-char": "latest"
-  }
-}"#,
+        pending.join(format!("{key}.json")),
+        format!(
+            r#"{{"key":"{key}","source_id":"hello.pseudo","source_text":"write hello","javascript":"console.log('hello');","provider":"ollama","model":"qwen","prompt_version":"m1-v2","previous":"console.log('hi');"}}"#
+        ),
     )
     .expect("write should work");
 
-    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
-        .args(["i", "--dry-run"])
-        .current_dir(dir.path())
+    let review = |args: &[&str]| {
+        let mut command = Command::new(assert_cmd::cargo::cargo_bin!("klumo"));
+        command.env("HOME", home.path()).arg("review").args(args);
+        command
+    };
+    review(&["--list"])
         .assert()
         .success()
-        .stdout(contains("dry-run: deno cache jsr:@arvid/is-char@latest"));
+        .stdout(contains("0123456789ab hello.pseudo via ollama/qwen"));
+    review(&[])
+        .write_stdin("y\n")
+        .assert()
+        .success()
+        .stdout(contains("--- source\nwrite hello"))
+        .stdout(contains("-console.log('hi');\n+console.log('hello');"))
+        .stdout(contains("approved hello.pseudo"));
+
+    assert!(
+        home.path()
+            .join(format!(".klumo/cache/compile/{key}.json"))
+            .exists()
+    );
+    review(&["--list"])
+        .assert()
+        .success()
+        .stdout(contains("No translations awaiting review."));
+    review(&["--reject", "0123"])
+        .assert()
+        .failure()
+        .stderr(contains("no pending translation '0123'"));
 }
 
 #[test]
-fn warns_when_klumo_json_has_reserved_script_names() {
-    let dir = tempdir().expect("tempdir should work");
-    let config = dir.path().join("klumo.json");
+fn cache_gc_removes_stale_entries_and_reports_reclaimed_space() {
+    let home = tempdir().expect("tempdir should work");
+    let cache = home.path().join(".klumo/cache/compile");
+    fs::create_dir_all(&cache).expect("mkdir should work");
+    let version = klumo_compiler::PROMPT_VERSION;
+    let entry = format!(
+        r#"{{"javascript":"1","provider":"ollama","model":"qwen","prompt_version":"{version}"}}"#
+    );
+    let outdated = entry.replace(version, &format!("{version}-old"));
+    fs::write(cache.join("outdated.json"), &outdated).expect("write should work");
+    fs::write(cache.join("stale.json"), &entry).expect("write should work");
+    fs::write(cache.join("fresh.json"), &entry).expect("write should work");
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("clock")
+        .as_millis() as u64;
     fs::write(
-        &config,
-        r#"{
-  "scripts": {
-    "lint": "echo custom-lint"
-  }
-}"#,
+        cache.join("index.lru"),
+        format!("1000 stale\n{now} fresh\n"),
     )
     .expect("write should work");
 
-    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
-        .write_stdin(".exit\n")
-        .current_dir(dir.path())
+    let gc = |args: &[&str]| {
+        let mut command = Command::new(assert_cmd::cargo::cargo_bin!("klumo"));
+        command
+            .env("HOME", home.path())
+            .current_dir(home.path())
+            .args(["cache", "gc"])
+            .args(args);
+        command
+    };
+    // Another klumo version sharing the cache may still read entries of its prompt version.
+    gc(&[]).assert().success().stdout(
+        contains(format!(
+            "Removed 0 entries from {}, reclaimed 0 B; 2 entries ({} B) remain.",
+            cache.display(),
+            2 * entry.len()
+        ))
+        .and(contains(
+            "Left 1 entries from other prompt versions; pass --all-versions to remove them.",
+        )),
+    );
+    assert!(cache.join("outdated.json").exists());
+    gc(&["--all-versions"])
         .assert()
         .success()
-        .stderr(contains("[klumo] warning: scripts.lint"));
+        .stdout(contains(format!(
+            "Removed 1 entries from {}, reclaimed {} B; 2 entries ({} B) remain.",
+            cache.display(),
+            outdated.len(),
+            2 * entry.len()
+        )));
+    assert!(!cache.join("outdated.json").exists());
+    gc(&["--max-age-days", "30"])
+        .assert()
+        .success()
+        .stdout(contains(format!(
+            "Removed 1 entries from {}, reclaimed {} B; 1 entries ({} B) remain.",
+            cache.display(),
+            entry.len(),
+            entry.len()
+        )));
+    assert!(!cache.join("stale.json").exists());
+    assert!(cache.join("fresh.json").exists());
 }
 
 #[test]
-fn lint_is_treated_as_builtin_command_not_file_argument() {
-    let dir = tempdir().expect("tempdir should work");
-    let path = dir.path().join("lint");
-    fs::write(&path, "1 + 1").expect("write should work");
+fn cache_dir_from_the_project_config_or_env_replaces_the_home_cache() {
+    let home = tempdir().expect("tempdir should work");
+    let project = home.path().join("app");
+    let project_cache = project.join(".klumo-cache");
+    let ci_cache = home.path().join("ci-cache");
+    for dir in [&project_cache, &ci_cache] {
+        fs::create_dir_all(dir).expect("mkdir should work");
+        fs::write(
+            dir.join("outdated.json"),
+            r#"{"javascript":"1","provider":"ollama","model":"qwen","prompt_version":"m1-v2"}"#,
+        )
+        .expect("write should work");
+    }
+    fs::write(
+        project.join("klumo.json"),
+        r#"{"cache_dir": ".klumo-cache"}"#,
+    )
+    .expect("write should work");
 
-    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
-        .args(["lint"])
-        .env("PATH", "")
-        .current_dir(dir.path())
+    let gc = || {
+        let mut command = Command::new(assert_cmd::cargo::cargo_bin!("klumo"));
+        command
+            .env("HOME", home.path())
+            .current_dir(home.path())
+            .args([
+                "cache",
+                "gc",
+                "--all-versions",
+                "--config",
+                "app/klumo.json",
+            ]);
+        command
+    };
+    gc().env("KLUMO_CACHE_DIR", &ci_cache)
         .assert()
-        .failure()
-        .stderr(contains("failed running cargo clippy"));
+        .success()
+        .stdout(contains(format!("from {},", ci_cache.display())));
+    assert!(!ci_cache.join("outdated.json").exists());
+    assert!(project_cache.join("outdated.json").exists());
+
+    gc().assert().success().stdout(contains(format!(
+        "from {},",
+        Path::new("app").join(".klumo-cache").display()
+    )));
+    assert!(!project_cache.join("outdated.json").exists());
 }
 
 #[test]
-fn test_command_uses_deno_semantics() {
+fn verify_reports_bundles_whose_sources_changed_since_the_build() {
     let dir = tempdir().expect("tempdir should work");
+    fs::create_dir(dir.path().join("src")).expect("mkdir should work");
+    fs::write(
+        dir.path().join("src/main.js"),
+        "import { add } from './math.js';\nconsole.log(add(1, 2));\n",
+    )
+    .expect("write should work");
+    fs::write(
+        dir.path().join("src/math.js"),
+        "export const add = (a, b) => a + b;\n",
+    )
+    .expect("write should work");
 
     Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
-        .args(["test", "--allow-all"])
-        .env("PATH", "")
         .current_dir(dir.path())
+        .args(["bundle", "src/main.js", "--output", "dist/app.js"])
         .assert()
-        .failure()
-        .stderr(contains("failed running deno test"));
-}
-
-#[test]
-fn bundle_js_file_writes_output() {
-    let dir = tempdir().expect("tempdir should work");
-    let input = dir.path().join("hello.js");
-    let output = dir.path().join("dist").join("hello.out.js");
-    fs::write(&input, "40 + 2").expect("write should work");
+        .success();
+    let meta: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(dir.path().join("dist/app.js.klumo-meta.json"))
+            .expect("metadata should be written"),
+    )
+    .expect("metadata should parse");
+    let sources: Vec<&str> = meta["modules"]
+        .as_array()
+        .expect("modules")
+        .iter()
+        .map(|module| module["source"].as_str().expect("source"))
+        .collect();
+    assert_eq!(
+        sources,
+        [
+            Path::new("..").join("src").join("math.js"),
+            Path::new("..").join("src").join("main.js")
+        ]
+        .map(|path| path.display().to_string())
+    );
+    assert_eq!(
+        meta["modules"][0]["prompt_version"],
+        klumo_compiler::PROMPT_VERSION
+    );
+    assert!(meta["built_at"].as_str().expect("built_at").ends_with('Z'));
 
-    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
-        .args([
-            "bundle",
-            input.to_str().expect("path utf8"),
-            "--output",
-            output.to_str().expect("path utf8"),
-        ])
+    let verify = || {
+        let mut command = Command::new(assert_cmd::cargo::cargo_bin!("klumo"));
+        command
+            .current_dir(dir.path())
+            .args(["verify", "dist/app.js"]);
+        command
+    };
+    verify()
         .assert()
         .success()
-        .stdout(contains(output.to_str().expect("path utf8")));
+        .stdout(contains("dist/app.js is up to date (2 sources)"));
 
-    let bundled = fs::read_to_string(&output).expect("bundle should exist");
-    assert!(bundled.contains("40 + 2"));
+    fs::write(
+        dir.path().join("src/math.js"),
+        "export const add = (a, b) => a + b + 0;\n",
+    )
+    .expect("write should work");
+    let src = Path::new("src");
+    verify()
+        .assert()
+        .failure()
+        .stdout(contains(format!(
+            "changed  {}",
+            src.join("math.js").display()
+        )))
+        .stdout(contains(format!(
+            "ok       {}",
+            src.join("main.js").display()
+        )))
+        .stderr(contains("dist/app.js is stale (1 of its files changed)"));
 }
 
 #[test]
@@ -339,6 +1225,54 @@ fn config_file_applies_defaults() {
         .stderr(contains("OPENAI_API_KEY is required"));
 }
 
+#[test]
+fn config_command_defaults_apply_to_matching_command() {
+    let dir = tempdir().expect("tempdir should work");
+    let source = dir.path().join("hello.js");
+    let config = dir.path().join("klumo.json");
+
+    fs::write(&source, "40 + 2").expect("write should work");
+    fs::write(&config, r#"{"defaults":{"run":{"print_js":true}}}"#).expect("write should work");
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .args(["run", source.to_str().expect("path utf8")])
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stderr(contains("generated JavaScript"))
+        .stdout(contains("42"))
+        .stdout(contains("generated JavaScript").not());
+}
+
+#[test]
+fn config_command_defaults_accept_any_flag_of_the_command() {
+    let dir = tempdir().expect("tempdir should work");
+    fs::write(
+        dir.path().join("add.js"),
+        "function add (a, b) {\n  return a + b\n}\n",
+    )
+    .expect("write should work");
+    let config = dir.path().join("klumo.json");
+    fs::write(&config, r#"{"defaults":{"bundle":{"output":"out.js"}}}"#)
+        .expect("write should work");
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .current_dir(dir.path())
+        .args(["bundle", "add.js"])
+        .assert()
+        .success();
+    assert!(dir.path().join("out.js").exists());
+
+    fs::write(&config, r#"{"defaults":{"bundle":{"outptu":"out.js"}}}"#)
+        .expect("write should work");
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .current_dir(dir.path())
+        .args(["bundle", "add.js"])
+        .assert()
+        .failure()
+        .stderr(contains("defaults.bundle.outptu").and(contains("no --outptu flag")));
+}
+
 #[test]
 fn cli_overrides_config_provider() {
     let dir = tempdir().expect("tempdir should work");
@@ -364,6 +1298,37 @@ fn cli_overrides_config_provider() {
         .stderr(contains("failed calling Ollama"));
 }
 
+#[test]
+fn cli_provider_selects_a_custom_provider() {
+    let dir = tempdir().expect("tempdir should work");
+    let source = dir.path().join("hello.pseudo");
+    let config = dir.path().join("klumo.json");
+
+    fs::write(&source, "write hello").expect("write should work");
+    fs::write(
+        &config,
+        r#"{"providers":[{"name":"corp","base_url":"http://127.0.0.1:1/v1","model":"coder"}]}"#,
+    )
+    .expect("write should work");
+
+    let run = |provider: &str| {
+        Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+            .args([
+                "run",
+                source.to_str().expect("path utf8"),
+                "--provider",
+                provider,
+            ])
+            .current_dir(dir.path())
+            .assert()
+            .failure()
+    };
+    run("custom:corp").stderr(contains("1. custom:corp coder [network"));
+    run("corp").stderr(contains(
+        "[possible values: auto, ollama, openai, llamacpp, fixture, custom:<name>]",
+    ));
+}
+
 #[test]
 fn no_progress_suppresses_status_lines() {
     let dir = tempdir().expect("tempdir should work");
@@ -472,6 +1437,69 @@ fn config_openai_api_key_is_used() {
         .stderr(contains("OPENAI_API_KEY is required").not());
 }
 
+#[test]
+fn credentials_file_openai_api_key_is_used() {
+    let dir = tempdir().expect("tempdir should work");
+    let source = dir.path().join("hello.pseudo");
+    let credentials = dir.path().join("credentials.toml");
+
+    fs::write(&source, "write hello").expect("write should work");
+    fs::write(
+        &credentials,
+        "[openai]\napi_key = \"dummy-from-credentials\"\n",
+    )
+    .expect("write should work");
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .env_remove("OPENAI_API_KEY")
+        .env_remove("KLUMO_OPENAI_API_KEY")
+        .env("KLUMO_CREDENTIALS", &credentials)
+        .env("KLUMO_PROVIDER", "openai")
+        .env("KLUMO_FORCE_LLM", "1")
+        .env("OPENAI_BASE_URL", "http://127.0.0.1:1")
+        .current_dir(dir.path())
+        .args(["run", source.to_str().expect("path utf8")])
+        .assert()
+        .failure()
+        .stderr(contains("failed calling OpenAI-compatible endpoint"))
+        .stderr(contains("OPENAI_API_KEY is required").not());
+}
+
+#[test]
+fn stats_check_hit_rate_fails_after_cache_misses() {
+    let home = tempdir().expect("tempdir should work");
+    let project = tempdir().expect("tempdir should work");
+    fs::write(project.path().join("hello.pseudo"), "write hello").expect("write should work");
+    let klumo = || {
+        let mut command = Command::new(assert_cmd::cargo::cargo_bin!("klumo"));
+        command.env("HOME", home.path()).current_dir(project.path());
+        command
+    };
+
+    klumo()
+        .args(["stats", "--check-hit-rate", "0.9"])
+        .assert()
+        .success()
+        .stdout(contains("nothing to check"));
+    klumo()
+        .env("KLUMO_PROVIDER", "openai")
+        .env("OPENAI_API_KEY", "dummy")
+        .env("OPENAI_BASE_URL", "http://127.0.0.1:1")
+        .args(["run", "hello.pseudo"])
+        .assert()
+        .failure();
+    klumo()
+        .args(["stats", "--check-hit-rate", "0.9"])
+        .assert()
+        .failure()
+        .stdout(contains("0.0% over the last 1 compiles (threshold 90.0%)"))
+        .stderr(contains("cache hit rate 0.0% is below the threshold 90.0%"));
+    klumo()
+        .args(["stats", "--check-hit-rate", "0"])
+        .assert()
+        .success();
+}
+
 #[test]
 fn repl_routes_pseudocode_through_llm() {
     let output = Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
@@ -485,3 +1513,129 @@ fn repl_routes_pseudocode_through_llm() {
     let stderr = String::from_utf8_lossy(&output.stderr);
     assert!(stderr.contains("OPENAI_API_KEY is required"));
 }
+
+#[test]
+fn run_expect_output_passes_and_fails_on_mismatch() {
+    let dir = tempdir().expect("tempdir should work");
+    let source = dir.path().join("hello.js");
+    fs::write(&source, "console.log('hi');\n21 * 2").expect("write should work");
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .args(["run", source.to_str().expect("path utf8")])
+        .args(["--expect-output", "hi\n42"])
+        .current_dir(dir.path())
+        .assert()
+        .success();
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .args(["run", source.to_str().expect("path utf8")])
+        .args(["--expect-output", "41"])
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(contains("expected output \"41\""));
+}
+
+#[test]
+fn run_expect_exit_accepts_expected_failure() {
+    let dir = tempdir().expect("tempdir should work");
+    let source = dir.path().join("boom.js");
+    fs::write(&source, "throw new Error('boom')").expect("write should work");
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .args([
+            "run",
+            source.to_str().expect("path utf8"),
+            "--expect-exit",
+            "1",
+        ])
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stderr(contains("boom"));
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .args([
+            "run",
+            source.to_str().expect("path utf8"),
+            "--expect-exit",
+            "0",
+        ])
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(contains("expected exit code 0, got 1"));
+}
+
+#[test]
+fn routing_config_error_maps_to_config_exit_code() {
+    let dir = tempdir().expect("tempdir should work");
+    let source = dir.path().join("hello.pseudo");
+    fs::write(&source, "write hello").expect("write should work");
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .env_remove("OPENAI_API_KEY")
+        .args([
+            "run",
+            source.to_str().expect("path utf8"),
+            "--provider",
+            "openai",
+            "--force-llm",
+            "--no-cache",
+        ])
+        .current_dir(dir.path())
+        .assert()
+        .code(78)
+        .stderr(contains("[config]"));
+
+    Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+        .env_remove("OPENAI_API_KEY")
+        .args([
+            "run",
+            source.to_str().expect("path utf8"),
+            "--provider",
+            "openai",
+            "--force-llm",
+            "--no-cache",
+            "--expect-exit",
+            "78",
+        ])
+        .current_dir(dir.path())
+        .assert()
+        .success();
+}
+
+#[test]
+fn stats_prints_the_provider_scoreboard_and_resets_it() {
+    let home = tempdir().expect("tempdir should work");
+    let stats = |args: &[&str]| {
+        Command::new(assert_cmd::cargo::cargo_bin!("klumo"))
+            .env("HOME", home.path())
+            .current_dir(home.path())
+            .arg("stats")
+            .args(args)
+            .assert()
+    };
+
+    stats(&[])
+        .success()
+        .stdout(contains("No provider calls recorded yet"));
+
+    let metrics = home.path().join(".klumo").join("metrics");
+    fs::create_dir_all(&metrics).expect("create metrics dir");
+    fs::write(
+        metrics.join("providers.json"),
+        r#"{"ollama":{"calls":4,"successes":3,"normalization_failures":1,"success_latency_ms":300}}"#,
+    )
+    .expect("write provider metrics");
+    stats(&[]).success().stdout(
+        contains("provider")
+            .and(contains("ollama"))
+            .and(contains("100ms")),
+    );
+
+    stats(&["--reset"])
+        .success()
+        .stdout(contains("Cleared provider metrics"));
+    assert!(!metrics.join("providers.json").exists());
+}
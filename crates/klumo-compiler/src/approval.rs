@@ -0,0 +1,201 @@
+use crate::{CompileCache, CompileMetadata, CompileResult, format_provider, parse_provider};
+use anyhow::{Context, Result, anyhow};
+use klumo_llm::Explanation;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A fresh translation held back until it is approved with `klumo review`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingTranslation {
+    /// Compile cache key the translation is stored under once approved.
+    pub key: String,
+    pub source_id: String,
+    pub source_text: String,
+    pub javascript: String,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub served_model: Option<String>,
+    pub prompt_version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<Explanation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    /// JavaScript last approved for the same source, shown as a diff during review.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<String>,
+}
+
+impl PendingTranslation {
+    pub(crate) fn new(
+        key: String,
+        source_id: &str,
+        source_text: &str,
+        result: &CompileResult,
+    ) -> Self {
+        Self {
+            key,
+            source_id: source_id.to_string(),
+            source_text: source_text.to_string(),
+            javascript: result.javascript.clone(),
+            provider: result.metadata.provider.as_ref().map(format_provider),
+            model: result.metadata.model.clone(),
+            served_model: result.metadata.served_model.clone(),
+            prompt_version: result.metadata.prompt_version.clone(),
+            notes: result.metadata.notes.clone(),
+            confidence: result.metadata.confidence,
+            explanation: result.metadata.explanation.clone(),
+            signature: result.metadata.signature.clone(),
+            previous: None,
+        }
+    }
+
+    fn result(&self) -> CompileResult {
+        CompileResult {
+            javascript: self.javascript.clone(),
+            metadata: CompileMetadata {
+                provider: self.provider.as_deref().map(parse_provider),
+                model: self.model.clone(),
+                served_model: self.served_model.clone(),
+                prompt_version: self.prompt_version.clone(),
+                cache_hit: false,
+                notes: self.notes.clone(),
+                confidence: self.confidence,
+                explanation: self.explanation.clone(),
+                signature: self.signature.clone(),
+                similarity: None,
+            },
+            source_map: None,
+        }
+    }
+}
+
+/// Translations awaiting review in `approval_required` mode, one `<key>.json` per entry. The
+/// last approved JavaScript of each source is kept under `approved/` for review diffs.
+#[derive(Debug, Clone)]
+pub struct PendingStore {
+    root: PathBuf,
+}
+
+impl PendingStore {
+    pub fn default_root() -> Result<PathBuf> {
+        let home = dirs::home_dir().context("failed to resolve home directory")?;
+        Ok(home.join(".klumo").join("pending"))
+    }
+
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.root.join(format!("{key}.json"))
+    }
+
+    fn approved_path(&self, source_id: &str) -> PathBuf {
+        let digest = Sha256::digest(source_id.as_bytes());
+        self.root.join("approved").join(format!("{digest:x}.js"))
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.entry_path(key).is_file()
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<PendingTranslation>> {
+        let path = self.entry_path(key);
+        let raw = match fs::read_to_string(&path) {
+            Ok(raw) => raw,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed reading {}", path.display()));
+            }
+        };
+        serde_json::from_str(&raw)
+            .map(Some)
+            .with_context(|| format!("failed parsing pending translation {}", path.display()))
+    }
+
+    /// Queues `entry`, recording the last approved JavaScript of its source for the diff.
+    pub fn add(&self, mut entry: PendingTranslation) -> Result<()> {
+        fs::create_dir_all(&self.root)
+            .with_context(|| format!("failed creating {}", self.root.display()))?;
+        entry.previous = fs::read_to_string(self.approved_path(&entry.source_id)).ok();
+        let raw = serde_json::to_string_pretty(&entry)
+            .context("failed serializing pending translation")?;
+        let path = self.entry_path(&entry.key);
+        fs::write(&path, raw).with_context(|| format!("failed writing {}", path.display()))
+    }
+
+    /// Every pending translation, ordered by source then key. A missing dir yields none.
+    pub fn list(&self) -> Result<Vec<PendingTranslation>> {
+        let dir = match fs::read_dir(&self.root) {
+            Ok(dir) => dir,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed reading {}", self.root.display()));
+            }
+        };
+        let mut entries = Vec::new();
+        for item in dir {
+            let item = item.with_context(|| format!("failed reading {}", self.root.display()))?;
+            let Some(key) = item
+                .file_name()
+                .to_str()
+                .and_then(|name| name.strip_suffix(".json"))
+                .map(str::to_string)
+            else {
+                continue;
+            };
+            if let Some(entry) = self.get(&key)? {
+                entries.push(entry);
+            }
+        }
+        entries.sort_by(|a, b| (&a.source_id, &a.key).cmp(&(&b.source_id, &b.key)));
+        Ok(entries)
+    }
+
+    /// Moves the entry under `key` into `cache`, where runs can use it.
+    pub fn approve(&self, key: &str, cache: &impl CompileCache) -> Result<PendingTranslation> {
+        let entry = self.take(key)?;
+        cache.put(&entry.key, &entry.result())?;
+        let approved = self.approved_path(&entry.source_id);
+        if let Some(dir) = approved.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed creating {}", dir.display()))?;
+        }
+        fs::write(&approved, &entry.javascript)
+            .with_context(|| format!("failed writing {}", approved.display()))?;
+        Ok(entry)
+    }
+
+    /// Drops the entry under `key`; the source is translated afresh on its next run.
+    pub fn reject(&self, key: &str) -> Result<PendingTranslation> {
+        self.take(key)
+    }
+
+    fn take(&self, key: &str) -> Result<PendingTranslation> {
+        let entry = self
+            .get(key)?
+            .ok_or_else(|| anyhow!("no pending translation '{key}'"))?;
+        let path = self.entry_path(key);
+        fs::remove_file(&path).with_context(|| format!("failed removing {}", path.display()))?;
+        Ok(entry)
+    }
+}
+
+impl Default for PendingStore {
+    fn default() -> Self {
+        let root = Self::default_root().unwrap_or_else(|_| PathBuf::from(".klumo-pending"));
+        Self { root }
+    }
+}
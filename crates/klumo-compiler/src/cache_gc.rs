@@ -0,0 +1,337 @@
+use crate::embedding::retain_embeddings;
+use crate::{FileCompileCache, PROMPT_VERSION};
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Log in the cache dir of when each key was read or written, one `<unix ms> <key>` line
+/// appended per use so a hit never rewrites it; the latest line of a key wins. It is compacted
+/// to one line per key by `gc` and once it has doubled since the last compaction. It has no
+/// `.json` suffix so `entries()` never lists it as a cached compile.
+pub const LRU_INDEX_FILE: &str = "index.lru";
+
+/// [`CacheStats`] of the cache dir.
+const STATS_FILE: &str = "index.stats";
+
+/// Held, by whichever process or thread created it, while the stats are updated, the index is
+/// compacted or entries are collected.
+const LOCK_FILE: &str = "index.lock";
+
+/// Growth of the index past twice its compacted size that triggers a compaction, so small
+/// indexes are not compacted on every hit.
+const COMPACT_SLACK_BYTES: u64 = 64 * 1024;
+
+/// A bounded cache is collected at least this often, which applies `max_age` and corrects
+/// the entry size counter for entries removed without it.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How long to wait for the lock, and after how long a lock left behind by a crashed process
+/// is taken over.
+const LOCK_WAIT: Duration = Duration::from_secs(2);
+const LOCK_STALE: Duration = Duration::from_secs(30);
+
+/// Limits a `FileCompileCache` enforces after `put`s that cross them and on `gc`. Unset
+/// limits leave the cache unbounded in that dimension.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CachePolicy {
+    /// Total size of all entries, in bytes; least recently used entries go first.
+    pub max_bytes: Option<u64>,
+    /// Entries not used for this long are removed.
+    pub max_age: Option<Duration>,
+    /// Also remove entries written under another `PROMPT_VERSION`. Off by default, since a
+    /// different klumo version sharing the cache may still read them; otherwise they are left
+    /// alone and do not count towards the limits.
+    pub other_versions: bool,
+}
+
+impl CachePolicy {
+    pub fn is_bounded(&self) -> bool {
+        self.max_bytes.is_some() || self.max_age.is_some()
+    }
+}
+
+/// What a garbage collection pass removed and kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GcReport {
+    pub removed: usize,
+    pub reclaimed_bytes: u64,
+    pub kept: usize,
+    pub kept_bytes: u64,
+    /// Entries of other prompt versions left alone, which `kept` does not count.
+    pub other_versions: usize,
+}
+
+/// Counters that let a `put` decide whether to collect without listing the entries.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct CacheStats {
+    /// Total size of the entries as of the last `gc`, plus every bounded `put` since; `None`
+    /// until a `gc` has measured it.
+    #[serde(default)]
+    entry_bytes: Option<u64>,
+    /// Size of [`LRU_INDEX_FILE`] after its last compaction.
+    #[serde(default)]
+    index_bytes: u64,
+    /// When `gc` last ran, in milliseconds since the Unix epoch.
+    #[serde(default)]
+    collected_at: u64,
+}
+
+/// The cache dir's [`LOCK_FILE`], removed again on drop.
+struct CacheLock {
+    path: PathBuf,
+}
+
+impl CacheLock {
+    /// Takes the lock, waiting up to [`LOCK_WAIT`] for its holder.
+    fn acquire(root: &Path) -> Result<Self> {
+        let started = Instant::now();
+        loop {
+            if let Some(lock) = Self::try_acquire(root)? {
+                return Ok(lock);
+            }
+            if started.elapsed() > LOCK_WAIT {
+                let path = root.join(LOCK_FILE);
+                return Err(anyhow!(
+                    "compile cache {} is locked by another klumo process; remove {} if none is running",
+                    root.display(),
+                    path.display()
+                ));
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Takes the lock unless someone else holds it.
+    fn try_acquire(root: &Path) -> Result<Option<Self>> {
+        let path = root.join(LOCK_FILE);
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(_) => Ok(Some(Self { path })),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                let abandoned = fs::metadata(&path)
+                    .and_then(|stat| stat.modified())
+                    .ok()
+                    .and_then(|modified| modified.elapsed().ok())
+                    .is_some_and(|age| age > LOCK_STALE);
+                if abandoned {
+                    let _ = fs::remove_file(&path);
+                }
+                Ok(None)
+            }
+            Err(err) => Err(err).with_context(|| format!("failed creating {}", path.display())),
+        }
+    }
+}
+
+impl Drop for CacheLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl FileCompileCache {
+    pub fn with_policy(mut self, policy: CachePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn policy(&self) -> CachePolicy {
+        self.policy
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.root.join(LRU_INDEX_FILE)
+    }
+
+    /// Last use per key in milliseconds since the Unix epoch. A missing or unreadable index is
+    /// empty; entries it does not cover fall back to their file's modification time.
+    fn load_index(&self) -> BTreeMap<String, u64> {
+        let Ok(raw) = fs::read_to_string(self.index_path()) else {
+            return BTreeMap::new();
+        };
+        let mut index = BTreeMap::new();
+        for line in raw.lines() {
+            if let Some((at, key)) = line.split_once(' ')
+                && let Ok(at) = at.parse::<u64>()
+            {
+                let last_used = index.entry(key.to_string()).or_insert(at);
+                *last_used = (*last_used).max(at);
+            }
+        }
+        index
+    }
+
+    /// Replaces the index with one line per key of `index`; returns its size in bytes.
+    fn write_index(&self, index: &BTreeMap<String, u64>) -> Result<u64> {
+        let raw: String = index
+            .iter()
+            .map(|(key, at)| format!("{at} {key}\n"))
+            .collect();
+        self.replace(LRU_INDEX_FILE, raw.as_bytes())?;
+        Ok(raw.len() as u64)
+    }
+
+    fn load_stats(&self) -> CacheStats {
+        fs::read_to_string(self.root.join(STATS_FILE))
+            .ok()
+            .and_then(|raw| serde_json::from_str(&raw).ok())
+            .unwrap_or_default()
+    }
+
+    fn save_stats(&self, stats: &CacheStats) -> Result<()> {
+        self.replace(STATS_FILE, serde_json::to_string(stats)?.as_bytes())
+    }
+
+    /// Writes `contents` aside and renames it over `name`, so a concurrent reader never sees
+    /// half a file.
+    fn replace(&self, name: &str, contents: &[u8]) -> Result<()> {
+        let path = self.root.join(name);
+        let staging = self.root.join(format!("{name}.{}.tmp", std::process::id()));
+        fs::write(&staging, contents)
+            .with_context(|| format!("failed writing {}", staging.display()))?;
+        fs::rename(&staging, &path).with_context(|| format!("failed writing {}", path.display()))
+    }
+
+    /// Marks `key` as used now by appending to the index, then compacts the index if it has
+    /// doubled since its last compaction and no other process holds the lock. A use recorded
+    /// by another process while a compaction runs can be lost, which only affects eviction
+    /// order.
+    pub(crate) fn touch(&self, key: &str) -> Result<()> {
+        let path = self.index_path();
+        let mut index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("failed opening {}", path.display()))?;
+        index
+            .write_all(format!("{} {key}\n", unix_ms(SystemTime::now())).as_bytes())
+            .with_context(|| format!("failed writing {}", path.display()))?;
+        let size = index.metadata()?.len();
+        let stats = self.load_stats();
+        if size <= stats.index_bytes.saturating_mul(2) + COMPACT_SLACK_BYTES {
+            return Ok(());
+        }
+        let Some(_lock) = CacheLock::try_acquire(&self.root)? else {
+            return Ok(());
+        };
+        let index_bytes = self.write_index(&self.load_index())?;
+        self.save_stats(&CacheStats {
+            index_bytes,
+            ..self.load_stats()
+        })
+    }
+
+    /// Adds a bounded `put` that wrote `written` bytes over an entry of `replaced` bytes to the
+    /// entry size counter, and collects when the counter passes `max_bytes`, when the cache
+    /// was last collected more than [`PRUNE_INTERVAL`] ago, or when it never was.
+    pub(crate) fn record_put(&self, written: u64, replaced: u64) -> Result<()> {
+        let lock = CacheLock::acquire(&self.root)?;
+        let mut stats = self.load_stats();
+        let now = unix_ms(SystemTime::now());
+        let overdue = now.saturating_sub(stats.collected_at) > PRUNE_INTERVAL.as_millis() as u64;
+        let Some(entry_bytes) = stats.entry_bytes.filter(|_| !overdue) else {
+            return self.gc_locked(self.policy, &lock).map(drop);
+        };
+        let entry_bytes = (entry_bytes + written).saturating_sub(replaced);
+        if self.policy.max_bytes.is_some_and(|max| entry_bytes > max) {
+            return self.gc_locked(self.policy, &lock).map(drop);
+        }
+        stats.entry_bytes = Some(entry_bytes);
+        self.save_stats(&stats)
+    }
+
+    /// Applies the cache's own policy.
+    pub fn prune(&self) -> Result<GcReport> {
+        self.gc(self.policy)
+    }
+
+    /// Removes entries unused for longer than `policy.max_age`, then the least recently used
+    /// ones until the rest fit in `policy.max_bytes`. Only entries of the current
+    /// `PROMPT_VERSION` are collected, unless `policy.other_versions` removes the rest first.
+    /// The index and the similar-source embeddings are compacted to the kept entries and the
+    /// entry size counter reset to what is left. Waits for, or fails on, another process
+    /// collecting the same cache.
+    pub fn gc(&self, policy: CachePolicy) -> Result<GcReport> {
+        if !self.root.is_dir() {
+            return Ok(GcReport::default());
+        }
+        let lock = CacheLock::acquire(&self.root)?;
+        self.gc_locked(policy, &lock)
+    }
+
+    fn gc_locked(&self, policy: CachePolicy, _lock: &CacheLock) -> Result<GcReport> {
+        let now = unix_ms(SystemTime::now());
+        let index = self.load_index();
+        // Entries of other versions sort first so, when they are collected, they are gone
+        // before the size limit evicts current ones.
+        let mut entries: Vec<(bool, u64, String, u64)> = self
+            .entries()?
+            .into_iter()
+            .map(|entry| {
+                let last_used = index
+                    .get(&entry.key)
+                    .copied()
+                    .or(entry.modified.map(unix_ms))
+                    .unwrap_or(now);
+                let current = entry
+                    .record
+                    .is_none_or(|record| record.prompt_version == PROMPT_VERSION);
+                (current, last_used, entry.key, entry.size_bytes)
+            })
+            .collect();
+        entries.sort();
+
+        let mut kept = BTreeMap::new();
+        let mut other_versions = 0;
+        if !policy.other_versions {
+            entries.retain(|(current, last_used, key, _)| {
+                if !current {
+                    other_versions += 1;
+                    kept.insert(key.clone(), *last_used);
+                }
+                *current
+            });
+        }
+
+        let mut report = GcReport {
+            kept: entries.len(),
+            kept_bytes: entries.iter().map(|(_, _, _, size)| size).sum(),
+            other_versions,
+            ..GcReport::default()
+        };
+        let max_age_ms = policy.max_age.map(|age| age.as_millis() as u64);
+        for (current, last_used, key, size) in entries {
+            let expired = max_age_ms.is_some_and(|max| now.saturating_sub(last_used) > max);
+            let oversized = policy.max_bytes.is_some_and(|max| report.kept_bytes > max);
+            if current && !expired && !oversized {
+                kept.insert(key, last_used);
+                continue;
+            }
+            report.kept -= 1;
+            report.kept_bytes -= size;
+            // Another process may have removed it first; it is gone either way.
+            if self.remove(&key)? {
+                report.removed += 1;
+                report.reclaimed_bytes += size;
+            }
+        }
+        let index_bytes = self.write_index(&kept)?;
+        retain_embeddings(self.root(), |key| kept.contains_key(key))?;
+        self.save_stats(&CacheStats {
+            entry_bytes: Some(report.kept_bytes),
+            index_bytes,
+            collected_at: now,
+        })?;
+        Ok(report)
+    }
+}
+
+fn unix_ms(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis() as u64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,65 @@
+use crate::{PROMPT_VERSION, format_provider};
+use klumo_llm::{Provider, SamplingOptions};
+use sha2::{Digest, Sha256};
+
+/// The request fields a compile cache key may depend on.
+#[derive(Debug, Clone, Copy)]
+pub struct CacheKeyInput<'a> {
+    pub source_text: &'a str,
+    pub source_id: &'a str,
+    pub kind_hint: &'a str,
+    pub provider: &'a Provider,
+    pub model: &'a str,
+    pub sampling: &'a SamplingOptions,
+    /// Fingerprint of the prompt templates in use.
+    pub prompt_fingerprint: &'a str,
+    /// Context rendered into the prompt next to the source, such as a REPL session's bindings.
+    pub scope_context: Option<&'a str>,
+    /// The compile pass pipeline, as [`crate::pipeline_fingerprint`] renders it.
+    pub passes: &'a str,
+}
+
+/// Turns a compile request into its cache key. Embedders implement it to choose which fields
+/// participate, e.g. to share entries between content-identical snippets by ignoring
+/// `source_id`, or to keep tenants apart by mixing in a tenant id.
+pub trait CacheKeyStrategy: Send + Sync {
+    fn key(&self, input: &CacheKeyInput<'_>) -> String;
+}
+
+/// The default strategy: a SHA-256 over every field plus `PROMPT_VERSION`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sha256CacheKey;
+
+impl CacheKeyStrategy for Sha256CacheKey {
+    fn key(&self, input: &CacheKeyInput<'_>) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(input.source_text.as_bytes());
+        hasher.update(b"\n--source-id--\n");
+        hasher.update(input.source_id.as_bytes());
+        hasher.update(b"\n--kind--\n");
+        hasher.update(input.kind_hint.as_bytes());
+        hasher.update(b"\n--provider--\n");
+        hasher.update(format_provider(input.provider).as_bytes());
+        hasher.update(b"\n--model--\n");
+        hasher.update(input.model.as_bytes());
+        hasher.update(b"\n--prompt-version--\n");
+        hasher.update(PROMPT_VERSION.as_bytes());
+        hasher.update(b"\n--prompt-templates--\n");
+        hasher.update(input.prompt_fingerprint.as_bytes());
+        // Only hashed when set, so requests without sampling controls keep their keys.
+        if !input.sampling.is_unset() {
+            hasher.update(b"\n--sampling--\n");
+            hasher.update(input.sampling.fingerprint().as_bytes());
+        }
+        if let Some(scope) = input.scope_context {
+            hasher.update(b"\n--scope--\n");
+            hasher.update(Sha256::digest(scope.as_bytes()));
+        }
+        // Like sampling, only hashed when set so keys from before compile passes stay valid.
+        if !input.passes.is_empty() {
+            hasher.update(b"\n--passes--\n");
+            hasher.update(input.passes.as_bytes());
+        }
+        format!("{:x}", hasher.finalize())
+    }
+}
@@ -0,0 +1,345 @@
+use crate::SourceKind;
+use anyhow::{Context, Result};
+use klumo_engine::check_syntax;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Lines of a source [`detect_language`] looks at; the rest cannot change a clear verdict.
+const SNIFF_LINES: usize = 200;
+
+/// Extensions that say nothing about the language inside, like none at all.
+const PLAIN_TEXT_EXTENSIONS: &[&str] = &["txt", "text"];
+
+/// Whether `source_id` leaves the language to the content: it has no extension, or a
+/// plain-text one such as `.txt`.
+pub(crate) fn sniffs_content(source_id: &str) -> bool {
+    Path::new(source_id)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_none_or(|ext| PLAIN_TEXT_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// Directory of the [`LanguageCache`] inside a compile cache root.
+pub const LANGUAGES_DIR: &str = "languages";
+
+/// Languages a provider named for sources [`detect_language`] could not place, one file per
+/// source content hash, so each distinct source is classified once rather than on every
+/// compile.
+#[derive(Debug, Clone)]
+pub struct LanguageCache {
+    root: PathBuf,
+}
+
+impl LanguageCache {
+    /// Cache root for the compile cache in `cache_root`.
+    pub fn root_in(cache_root: &Path) -> PathBuf {
+        cache_root.join(LANGUAGES_DIR)
+    }
+
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn entry_path(&self, source_text: &str) -> PathBuf {
+        let digest = Sha256::digest(source_text.as_bytes());
+        self.root.join(format!("{digest:x}.txt"))
+    }
+
+    /// The language recorded for `source_text`; missing or unreadable entries count as none.
+    pub fn get(&self, source_text: &str) -> Option<String> {
+        let name = fs::read_to_string(self.entry_path(source_text)).ok()?;
+        let name = name.trim();
+        (!name.is_empty()).then(|| name.to_string())
+    }
+
+    pub fn put(&self, source_text: &str, language: &str) -> Result<()> {
+        fs::create_dir_all(&self.root)
+            .with_context(|| format!("failed creating {}", self.root.display()))?;
+        let path = self.entry_path(source_text);
+        fs::write(&path, language).with_context(|| format!("failed writing {}", path.display()))
+    }
+}
+
+/// Guesses the language of `source_text` from a `#!` line's interpreter or, failing that, from
+/// keywords and statement shapes typical of each language; `None` when nothing stands out. A
+/// JavaScript verdict also requires the text to parse, since JavaScript skips translation.
+pub fn detect_language(source_text: &str) -> Option<SourceKind> {
+    if let Some(kind) = source_text
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix("#!"))
+        .and_then(interpreter)
+    {
+        return Some(kind);
+    }
+    let mut scores = [0usize; LANGUAGES.len()];
+    for line in source_text
+        .lines()
+        .skip(usize::from(source_text.starts_with("#!")))
+        .take(SNIFF_LINES)
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+    {
+        if line.starts_with("<?php") {
+            return Some(SourceKind::Unknown("php".to_string()));
+        }
+        for (score, (_, signals)) in scores.iter_mut().zip(LANGUAGES) {
+            *score += signals.iter().filter(|signal| signal(line)).count();
+        }
+    }
+    // Type annotations sit on JavaScript-shaped lines, so TypeScript takes those signals too.
+    let (js, ts) = (index_of("javascript"), index_of("typescript"));
+    if scores[ts] > 0 {
+        scores[ts] += std::mem::take(&mut scores[js]);
+    }
+    let best = *scores.iter().max()?;
+    let mut leaders = (0..scores.len()).filter(|&index| scores[index] == best);
+    let (winner, tie) = (leaders.next()?, leaders.next());
+    if best == 0 || tie.is_some() {
+        return None;
+    }
+    match LANGUAGES[winner].0 {
+        "javascript" if !check_syntax(source_text).is_empty() => None,
+        name => Some(SourceKind::from_hint(name)),
+    }
+}
+
+/// The language run by the interpreter a `#!` line names, directly or through `env`.
+fn interpreter(line: &str) -> Option<SourceKind> {
+    let mut words = line.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-') && !word.contains('='))?;
+    }
+    let name = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    let language = match name {
+        "node" | "nodejs" | "bun" | "qjs" => return Some(SourceKind::JavaScript),
+        "deno" | "ts-node" | "tsx" => return Some(SourceKind::TypeScript),
+        "python" | "pypy" => "python",
+        "sh" | "bash" | "zsh" | "dash" | "ksh" => "shell",
+        "Rscript" => "r",
+        "" => return None,
+        other => other,
+    };
+    Some(SourceKind::Unknown(language.to_ascii_lowercase()))
+}
+
+type Signal = fn(&str) -> bool;
+
+/// Per language, line shapes that point to it; each matching line scores a point.
+const LANGUAGES: &[(&str, &[Signal])] = &[
+    (
+        "javascript",
+        &[
+            |l| {
+                ["const ", "let ", "var "].iter().any(|p| l.starts_with(p))
+                    && !l.starts_with("let mut ")
+            },
+            |l| l.starts_with("function ") && l.contains('{'),
+            |l| l.contains("=> ") || l.ends_with("=>"),
+            |l| l.contains("console.log("),
+            |l| l.contains("require(") || l.contains(" from '") || l.contains(" from \""),
+            |l| l.starts_with("export ") && !is_shell_export(l),
+        ],
+    ),
+    (
+        "typescript",
+        &[
+            |l| l.starts_with("interface ") || l.starts_with("export interface "),
+            |l| l.starts_with("type ") && l.contains(" = "),
+            |l| {
+                [": string", ": number", ": boolean", "): void"]
+                    .iter()
+                    .any(|t| l.contains(t))
+            },
+        ],
+    ),
+    (
+        "python",
+        &[
+            |l| {
+                ["def ", "class ", "if ", "elif ", "for ", "while ", "with "]
+                    .iter()
+                    .any(|p| l.starts_with(p))
+                    && l.ends_with(':')
+            },
+            |l| ["else:", "try:", "finally:"].contains(&l) || l.starts_with("except"),
+            |l| l.starts_with("from ") && l.contains(" import "),
+            |l| l.starts_with("import ") && !l.contains(['\'', '"', '{', ';']),
+            |l| l.contains("__name__") || l.starts_with("print(") && !l.ends_with(';'),
+        ],
+    ),
+    (
+        "ruby",
+        &[
+            |l| l.starts_with("def ") && !l.ends_with(':') && !l.contains('{'),
+            |l| l.starts_with("puts ") || l.starts_with("require_relative "),
+            |l| l.contains(" do |") || l.ends_with(" do"),
+            |l| l.starts_with("attr_accessor ") || l.starts_with("attr_reader "),
+        ],
+    ),
+    (
+        "lua",
+        &[
+            |l| l.starts_with("local "),
+            |l| l.starts_with("function ") && !l.contains('{'),
+            |l| l.ends_with(" then") && !l.contains(';') && !l.contains('['),
+            |l| l.contains(" ~= "),
+        ],
+    ),
+    (
+        "shell",
+        &[
+            |l| ["fi", "done", "esac"].contains(&l),
+            |l| l.contains("; then") || l.contains("; do"),
+            |l| l.starts_with("echo ") || l.contains("$(") || l.starts_with("set -e"),
+            is_shell_export,
+        ],
+    ),
+    (
+        "go",
+        &[
+            |l| l.starts_with("package ") || l.starts_with("func "),
+            |l| l.contains(" := "),
+            |l| l.starts_with("fmt."),
+        ],
+    ),
+    (
+        "rust",
+        &[
+            |l| l.starts_with("fn ") || l.starts_with("pub fn ") || l.starts_with("impl "),
+            |l| l.starts_with("let mut ") || l.contains("println!("),
+            |l| l.starts_with("use ") && l.contains("::") && l.ends_with(';'),
+        ],
+    ),
+    (
+        "pseudocode",
+        &[
+            |l| {
+                let word = l.split_whitespace().next().unwrap_or_default();
+                word.len() > 1
+                    && word.chars().all(|c| c.is_ascii_uppercase())
+                    && PSEUDOCODE_KEYWORDS.contains(&word)
+            },
+            |l| {
+                let lower = l.to_ascii_lowercase();
+                (lower.starts_with("set ") && lower.contains(" to "))
+                    || ["display ", "for each ", "repeat ", "increment ", "ask "]
+                        .iter()
+                        .any(|p| lower.starts_with(p))
+            },
+        ],
+    ),
+];
+
+const PSEUDOCODE_KEYWORDS: &[&str] = &[
+    "SET",
+    "DISPLAY",
+    "PRINT",
+    "INPUT",
+    "OUTPUT",
+    "READ",
+    "IF",
+    "ELSE",
+    "ENDIF",
+    "END",
+    "FOR",
+    "ENDFOR",
+    "WHILE",
+    "ENDWHILE",
+    "REPEAT",
+    "UNTIL",
+    "FUNCTION",
+    "PROCEDURE",
+    "RETURN",
+    "CALL",
+    "DECLARE",
+    "BEGIN",
+];
+
+/// `export NAME=value`, as opposed to a JavaScript `export` declaration.
+fn is_shell_export(line: &str) -> bool {
+    line.strip_prefix("export ")
+        .and_then(|rest| rest.split_once('='))
+        .is_some_and(|(name, _)| {
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+}
+
+fn index_of(language: &str) -> usize {
+    LANGUAGES
+        .iter()
+        .position(|(name, _)| *name == language)
+        .expect("language is listed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{detect_language, sniffs_content};
+    use crate::SourceKind;
+
+    fn unknown(name: &str) -> Option<SourceKind> {
+        Some(SourceKind::Unknown(name.to_string()))
+    }
+
+    #[test]
+    fn shebangs_name_the_interpreter() {
+        assert_eq!(
+            detect_language("#!/usr/bin/env python3\nx = 1\n"),
+            unknown("python")
+        );
+        assert_eq!(
+            detect_language("#!/usr/bin/env -S deno run --allow-net\n"),
+            Some(SourceKind::TypeScript)
+        );
+        assert_eq!(
+            detect_language("#!/usr/local/bin/node\n"),
+            Some(SourceKind::JavaScript)
+        );
+        assert_eq!(detect_language("#!/bin/bash\n"), unknown("shell"));
+        assert_eq!(detect_language("#!/usr/bin/ruby -w\n"), unknown("ruby"));
+    }
+
+    #[test]
+    fn keywords_tell_languages_apart() {
+        assert_eq!(
+            detect_language(
+                "import sys\n\ndef main():\n    print(sys.argv)\n\nif __name__ == '__main__':\n    main()\n"
+            ),
+            unknown("python")
+        );
+        assert_eq!(
+            detect_language(
+                "SET total TO 0\nFOR EACH item IN cart\n    ADD price TO total\nEND FOR\nDISPLAY total\n"
+            ),
+            unknown("pseudocode")
+        );
+        assert_eq!(
+            detect_language(
+                "interface User {\n  name: string;\n}\nconst greet = (u: User) => u.name;\n"
+            ),
+            Some(SourceKind::TypeScript)
+        );
+        assert_eq!(
+            detect_language("const fs = require('fs');\nconsole.log(fs.readdirSync('.'));\n"),
+            Some(SourceKind::JavaScript)
+        );
+        assert_eq!(
+            detect_language("local count = 0\nif count ~= 1 then\n  print(count)\nend\n"),
+            unknown("lua")
+        );
+        // JavaScript-looking text that does not parse is left to translation.
+        assert_eq!(detect_language("const total = sum of the list\n"), None);
+        assert_eq!(detect_language("hello there\n"), None);
+    }
+
+    #[test]
+    fn only_extensionless_and_plain_text_ids_are_sniffed() {
+        assert!(sniffs_content("bin/deploy"));
+        assert!(sniffs_content("./notes.TXT"));
+        assert!(sniffs_content("<stdin>"));
+        assert!(!sniffs_content("main.py"));
+        assert!(!sniffs_content("./app.js"));
+    }
+}
@@ -0,0 +1,159 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// Turns a source into a vector whose direction reflects what the source means, e.g. through
+/// Ollama's `/api/embeddings`. Embedders implement it to back an [`EmbeddingIndex`].
+pub trait Embedder: Send + Sync {
+    fn embed(&self, text: &str) -> Result<Vec<f32>>;
+}
+
+/// Cosine similarity a source needs with an earlier one to reuse its translation, unless
+/// configured otherwise. High, since a near miss is translated as the other source.
+pub const DEFAULT_SIMILARITY_THRESHOLD: f32 = 0.97;
+
+/// Directory of the [`EmbeddingIndex`] inside the compile cache it indexes, so the index
+/// moves, ships and is collected with the cache.
+pub const EMBEDDINGS_DIR: &str = "embeddings";
+
+/// Experimental lookup for `CompilerRouter.embeddings`: a cache miss reuses the cached
+/// translation of the earlier source whose embedding is most similar, when the similarity
+/// reaches `threshold`. Embeddings are kept per translation setup in `<scope>.jsonl` files
+/// of one JSON line per cached translation.
+pub struct EmbeddingIndex {
+    root: PathBuf,
+    embedder: Box<dyn Embedder>,
+    pub threshold: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexedSource {
+    key: String,
+    embedding: Vec<f32>,
+}
+
+impl EmbeddingIndex {
+    /// Index root for the compile cache in `cache_root`.
+    pub fn root_in(cache_root: &Path) -> PathBuf {
+        cache_root.join(EMBEDDINGS_DIR)
+    }
+
+    pub fn new(root: PathBuf, embedder: impl Embedder + 'static, threshold: f32) -> Self {
+        Self {
+            root,
+            embedder: Box::new(embedder),
+            threshold,
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        self.embedder.embed(text)
+    }
+
+    fn scope_path(&self, scope: &str) -> PathBuf {
+        self.root.join(format!("{scope}.jsonl"))
+    }
+
+    /// Cache keys of the sources in `scope` at least `threshold` similar to `embedding`, with
+    /// their similarity, most similar first. A missing or unreadable index has none.
+    pub fn nearest(&self, scope: &str, embedding: &[f32]) -> Vec<(String, f32)> {
+        let Ok(file) = fs::File::open(self.scope_path(scope)) else {
+            return Vec::new();
+        };
+        let mut matches: Vec<(String, f32)> = BufReader::new(file)
+            .lines()
+            .map_while(|line| line.ok())
+            .filter_map(|line| serde_json::from_str::<IndexedSource>(&line).ok())
+            .map(|indexed| {
+                let score = cosine_similarity(embedding, &indexed.embedding);
+                (indexed.key, score)
+            })
+            .filter(|(_, score)| *score >= self.threshold)
+            .collect();
+        matches.sort_by(|a, b| b.1.total_cmp(&a.1));
+        matches
+    }
+
+    /// Records that the translation cached under `key` came from a source with `embedding`.
+    pub fn add(&self, scope: &str, key: &str, embedding: &[f32]) -> Result<()> {
+        fs::create_dir_all(&self.root)
+            .with_context(|| format!("failed creating {}", self.root.display()))?;
+        let mut line = serde_json::to_string(&IndexedSource {
+            key: key.to_string(),
+            embedding: embedding.to_vec(),
+        })
+        .context("failed serializing embedding")?;
+        line.push('\n');
+        let path = self.scope_path(scope);
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .with_context(|| format!("failed writing {}", path.display()))
+    }
+}
+
+/// Drops the embeddings of the compile cache in `cache_root` whose cache key fails `keep`,
+/// and scope files left empty, so the index shrinks with the cache. Run by `gc` under the
+/// cache lock; an embedding appended while a file is rewritten can be lost, which only costs
+/// a similarity lookup.
+pub(crate) fn retain_embeddings(cache_root: &Path, keep: impl Fn(&str) -> bool) -> Result<()> {
+    let root = EmbeddingIndex::root_in(cache_root);
+    let Ok(dir) = fs::read_dir(&root) else {
+        return Ok(());
+    };
+    for path in dir.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+        if path.extension().is_none_or(|ext| ext != "jsonl") {
+            continue;
+        }
+        let raw = fs::read_to_string(&path)
+            .with_context(|| format!("failed reading {}", path.display()))?;
+        let kept: String = raw
+            .lines()
+            .filter(|line| {
+                serde_json::from_str::<IndexedSource>(line).is_ok_and(|indexed| keep(&indexed.key))
+            })
+            .map(|line| format!("{line}\n"))
+            .collect();
+        if kept.len() == raw.len() {
+            continue;
+        }
+        if kept.is_empty() {
+            fs::remove_file(&path)
+                .with_context(|| format!("failed removing {}", path.display()))?;
+            continue;
+        }
+        // Written aside and renamed so a concurrent lookup never reads half a file.
+        let staging = path.with_extension(format!("jsonl.{}.tmp", std::process::id()));
+        fs::write(&staging, kept)
+            .with_context(|| format!("failed writing {}", staging.display()))?;
+        fs::rename(&staging, &path)
+            .with_context(|| format!("failed writing {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Cosine of the angle between `a` and `b`; `0.0` when their lengths differ or either is zero.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let (mut dot, mut norm_a, mut norm_b) = (0.0f64, 0.0f64, 0.0f64);
+    for (x, y) in a.iter().zip(b) {
+        let (x, y) = (f64::from(*x), f64::from(*y));
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    (dot / (norm_a.sqrt() * norm_b.sqrt())) as f32
+}
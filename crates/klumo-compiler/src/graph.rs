@@ -0,0 +1,1153 @@
+use crate::{CompileResult, SourceMap};
+use anyhow::{Context, Result, anyhow, bail, ensure};
+use klumo_engine::check_syntax;
+use oxc_allocator::Allocator;
+use oxc_ast::ast::{
+    Argument, CallExpression, Declaration, ExportAllDeclaration, ExportDeclaration,
+    ExportDefaultDeclaration, ExportDefaultDeclarationKind, ExportFromDeclaration,
+    ExportNamedDeclaration, Expression, ImportDeclaration, ImportDeclarationSpecifier,
+    ImportExpression, ImportMeta, Program, Statement, WithClause,
+};
+use oxc_ast_visit::{Visit, walk};
+use oxc_parser::Parser;
+use oxc_span::{GetSpan, SourceType, Span};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Extensions tried, in order, for a relative specifier that names no existing file, and for
+/// the `index` file of a directory specifier.
+pub const MODULE_EXTENSIONS: &[&str] = &["js", "mjs", "cjs", "jsx", "ts", "tsx"];
+
+/// A program spread over several files: the entry and every file it reaches through relative
+/// `import`, `export ... from`, `import()` and `require()` specifiers, each compiled on its
+/// own (so each has its own cache entry).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleGraph {
+    /// Dependencies come before the modules importing them, except along import cycles; the
+    /// entry is last.
+    pub modules: Vec<GraphModule>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphModule {
+    pub path: PathBuf,
+    pub compile: CompileResult,
+    /// The relative specifiers in the compiled JavaScript, in source order.
+    pub imports: Vec<ModuleImport>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleImport {
+    pub specifier: String,
+    /// Index of the imported module in [`ModuleGraph::modules`].
+    pub module: usize,
+}
+
+impl GraphModule {
+    /// The entries of the registry object mapping this module's specifiers to module indexes.
+    fn specifiers(&self) -> String {
+        self.imports
+            .iter()
+            .map(|import| {
+                format!(
+                    "{}: {}",
+                    serde_json::Value::from(import.specifier.as_str()),
+                    import.module
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Compiles `entry` with `compile`, then every file its compiled JavaScript imports through a
+/// relative specifier, and so on. Specifiers are looked for in the compiled output, so a
+/// pseudocode file's imports count once the translation spells them out. Each file is
+/// compiled once, however many modules import it.
+pub fn build_module_graph<F>(entry: &Path, mut compile: F) -> Result<ModuleGraph>
+where
+    F: FnMut(&Path) -> Result<CompileResult>,
+{
+    build_module_graph_batched(entry, |paths| {
+        // Nothing after a failed file is compiled; its error is the one returned.
+        let mut failed = false;
+        paths
+            .iter()
+            .map(|path| {
+                if failed {
+                    return Err(anyhow!("not compiled after an earlier failure"));
+                }
+                let compiled = compile(path);
+                failed = compiled.is_err();
+                compiled
+            })
+            .collect()
+    })
+}
+
+/// [`build_module_graph`], handing `compile` every file found in one round at once, so a
+/// caller can compile them concurrently: first the entry, then the files it imports, then
+/// the files those import. `compile` returns one result per path, in order.
+pub fn build_module_graph_batched<F>(entry: &Path, mut compile: F) -> Result<ModuleGraph>
+where
+    F: FnMut(&[PathBuf]) -> Vec<Result<CompileResult>>,
+{
+    let mut paths = vec![entry.to_path_buf()];
+    let mut seen = HashMap::from([(identity(&paths[0]), 0)]);
+    let mut discovered: Vec<GraphModule> = Vec::new();
+    while discovered.len() < paths.len() {
+        let round = paths[discovered.len()..].to_vec();
+        let results = compile(&round);
+        ensure!(
+            results.len() == round.len(),
+            "compiled {} of {} modules",
+            results.len(),
+            round.len()
+        );
+        for (path, compiled) in round.into_iter().zip(results) {
+            let compiled = compiled?;
+            let mut imports = Vec::new();
+            // A linked program already holds its modules; its `require` calls are the registry's.
+            let specifiers = if is_linked(&compiled.javascript) {
+                Vec::new()
+            } else {
+                relative_specifiers(&compiled.javascript)
+            };
+            for specifier in specifiers {
+                let target = resolve_relative_import(&path, &specifier).ok_or_else(|| {
+                    anyhow!(
+                        "cannot resolve '{specifier}' imported from {}",
+                        path.display()
+                    )
+                })?;
+                let module = *seen.entry(identity(&target)).or_insert_with(|| {
+                    paths.push(target);
+                    paths.len() - 1
+                });
+                imports.push(ModuleImport { specifier, module });
+            }
+            discovered.push(GraphModule {
+                path,
+                compile: compiled,
+                imports,
+            });
+        }
+    }
+
+    let mut order = Vec::with_capacity(discovered.len());
+    let mut visited = vec![false; discovered.len()];
+    post_order(0, &discovered, &mut visited, &mut order);
+    let mut position = vec![0; discovered.len()];
+    for (new, old) in order.iter().enumerate() {
+        position[*old] = new;
+    }
+    let mut slots: Vec<Option<GraphModule>> = discovered.into_iter().map(Some).collect();
+    let modules = order
+        .iter()
+        .map(|old| {
+            let mut module = slots[*old].take().expect("each module is ordered once");
+            for import in &mut module.imports {
+                import.module = position[import.module];
+            }
+            module
+        })
+        .collect();
+    Ok(ModuleGraph { modules })
+}
+
+fn post_order(index: usize, modules: &[GraphModule], visited: &mut [bool], order: &mut Vec<usize>) {
+    visited[index] = true;
+    for import in &modules[index].imports {
+        if !visited[import.module] {
+            post_order(import.module, modules, visited, order);
+        }
+    }
+    order.push(index);
+}
+
+/// The file a relative `specifier` written in `importer` names: the path itself, then with
+/// each of [`MODULE_EXTENSIONS`] appended, then a `.ts`/`.tsx` file for a `.js` specifier (the
+/// TypeScript convention), then the directory's `index` file.
+pub fn resolve_relative_import(importer: &Path, specifier: &str) -> Option<PathBuf> {
+    let base = normalize(&importer.parent().unwrap_or(Path::new("")).join(specifier));
+    let mut candidates = vec![base.clone()];
+    candidates.extend(MODULE_EXTENSIONS.iter().map(|ext| {
+        let mut name = OsString::from(base.as_os_str());
+        name.push(format!(".{ext}"));
+        PathBuf::from(name)
+    }));
+    if base.extension().is_some_and(|ext| ext == "js") {
+        candidates.push(base.with_extension("ts"));
+        candidates.push(base.with_extension("tsx"));
+    }
+    candidates.extend(
+        MODULE_EXTENSIONS
+            .iter()
+            .map(|ext| base.join(format!("index.{ext}"))),
+    );
+    candidates.into_iter().find(|candidate| candidate.is_file())
+}
+
+/// Drops `.` components and folds `name/..`, so one file reached along different paths gets
+/// one source id.
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(out.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+fn identity(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn is_relative(specifier: &str) -> bool {
+    specifier.starts_with("./")
+        || specifier.starts_with("../")
+        || specifier == "."
+        || specifier == ".."
+}
+
+/// The relative specifiers `javascript` imports, exports from or requires, once each in source
+/// order. JavaScript that does not parse has none; running it reports the real error.
+pub fn relative_specifiers(javascript: &str) -> Vec<String> {
+    let allocator = Allocator::default();
+    let Ok(program) = parse(&allocator, javascript) else {
+        return Vec::new();
+    };
+    let mut finder = SpecifierFinder::default();
+    finder.visit_program(&program);
+    let mut specifiers: Vec<String> = Vec::new();
+    for specifier in finder.found {
+        if is_relative(&specifier) && !specifiers.contains(&specifier) {
+            specifiers.push(specifier);
+        }
+    }
+    specifiers
+}
+
+/// Parses compiled JavaScript: as a module when it has `import` or `export` declarations, as a
+/// script otherwise.
+fn parse<'a>(allocator: &'a Allocator, javascript: &'a str) -> Result<Program<'a>> {
+    let parsed = Parser::new(allocator, javascript, SourceType::unambiguous()).parse();
+    if let Some(diagnostic) = parsed.diagnostics.first() {
+        let offset = diagnostic
+            .labels
+            .first()
+            .map_or(0, |label| label.offset() as usize);
+        let line = javascript[..offset.min(javascript.len())]
+            .matches('\n')
+            .count()
+            + 1;
+        bail!("the module does not parse: {diagnostic} at line {line}");
+    }
+    if parsed.panicked {
+        bail!("the module does not parse");
+    }
+    Ok(parsed.program)
+}
+
+/// The specifier of a `require("...")` call.
+fn required_specifier(call: &CallExpression<'_>) -> Option<String> {
+    let Expression::Identifier(callee) = &call.callee else {
+        return None;
+    };
+    match call.arguments.as_slice() {
+        [Argument::StringLiteral(specifier)] if callee.name == "require" => {
+            Some(specifier.value.to_string())
+        }
+        _ => None,
+    }
+}
+
+#[derive(Default)]
+struct SpecifierFinder {
+    found: Vec<String>,
+}
+
+impl<'a> Visit<'a> for SpecifierFinder {
+    fn visit_import_declaration(&mut self, it: &ImportDeclaration<'a>) {
+        self.found.push(it.source.value.to_string());
+    }
+
+    fn visit_export_all_declaration(&mut self, it: &ExportAllDeclaration<'a>) {
+        self.found.push(it.source.value.to_string());
+    }
+
+    fn visit_export_from_declaration(&mut self, it: &ExportFromDeclaration<'a>) {
+        self.found.push(it.source.value.to_string());
+    }
+
+    fn visit_import_expression(&mut self, it: &ImportExpression<'a>) {
+        if let Expression::StringLiteral(specifier) = &it.source {
+            self.found.push(specifier.value.to_string());
+        }
+        walk::walk_import_expression(self, it);
+    }
+
+    fn visit_call_expression(&mut self, it: &CallExpression<'a>) {
+        self.found.extend(required_specifier(it));
+        walk::walk_call_expression(self, it);
+    }
+}
+
+/// First line of every program [`ModuleGraph::link`] builds from more than one module.
+pub const LINKED_HEADER: &str = "// Linked by klumo:";
+
+/// Whether `javascript` is a program [`ModuleGraph::link`] built, possibly under a banner
+/// of further `//` comments.
+pub fn is_linked(javascript: &str) -> bool {
+    javascript
+        .lines()
+        .take_while(|line| line.starts_with("//"))
+        .any(|line| line.starts_with(LINKED_HEADER))
+}
+
+/// The module format of a linked program, for tools loading it rather than `klumo run`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputTarget {
+    /// A classic script that runs the entry.
+    Script,
+    /// An ES module re-exporting the entry's exports.
+    Esm,
+    /// A CommonJS module assigning the entry's exports to `module.exports`.
+    Cjs,
+}
+
+/// Defines the module registry a linked program runs on, inside the function that runs the
+/// entry. `require` resolves the specifiers of the calling module only. ES modules mark
+/// their exports object with `__esModule` and expose their bindings through getters, so a
+/// namespace import sees later assignments; named and default imports are read once, when
+/// the import runs.
+const LINK_PRELUDE: &str = r#"const __klumo_cache = [];
+const __klumo_default = (namespace) => (namespace && namespace.__esModule ? namespace.default : namespace);
+const __klumo_export = (exports, getters) => {
+  Object.defineProperty(exports, "__esModule", { value: true });
+  for (const name of Object.keys(getters)) {
+    Object.defineProperty(exports, name, { enumerable: true, get: getters[name] });
+  }
+};
+const __klumo_export_all = (exports, namespace) => {
+  for (const name of Object.keys(namespace)) {
+    if (name !== "default" && !Object.prototype.hasOwnProperty.call(exports, name)) {
+      Object.defineProperty(exports, name, { enumerable: true, get: () => namespace[name] });
+    }
+  }
+};
+const __klumo_requirer = (specifiers) => {
+  const require = (specifier) => {
+    if (!Object.prototype.hasOwnProperty.call(specifiers, specifier)) {
+      throw new Error(`Cannot find module '${specifier}'`);
+    }
+    return __klumo_load(specifiers[specifier]);
+  };
+  require.dynamic = (specifier) => Promise.resolve().then(() => require(specifier));
+  return require;
+};
+const __klumo_load = (id) => {
+  if (__klumo_cache[id]) return __klumo_cache[id].exports;
+  const module = { exports: {} };
+  __klumo_cache[id] = module;
+  const [define, specifiers] = __klumo_modules[id];
+  define.call(module.exports, module, module.exports, __klumo_requirer(specifiers));
+  return module.exports;
+};
+const __klumo_modules = [
+"#;
+
+impl ModuleGraph {
+    pub fn entry(&self) -> &GraphModule {
+        self.modules.last().expect("a module graph holds its entry")
+    }
+
+    /// Whether any module of the graph came from a provider rather than a passthrough or a
+    /// local transpile.
+    pub fn translated(&self) -> bool {
+        self.modules
+            .iter()
+            .any(|module| module.compile.metadata.provider.is_some())
+    }
+
+    /// One classic script running the whole graph, entry last. ES modules are rewritten onto
+    /// a CommonJS-style registry (imports become `require` calls, exports getters on the
+    /// module's exports object) and CommonJS modules run as they are. A graph of one module
+    /// is returned unchanged, keeping its completion value and module goal.
+    ///
+    /// Fails on what the registry cannot express, `import.meta` or import attributes, and when
+    /// the linked script does not parse, e.g. because a module uses top-level `await`.
+    pub fn link(&self) -> Result<String> {
+        self.link_with_source_map()
+            .map(|(javascript, _)| javascript)
+    }
+
+    /// [`ModuleGraph::link`] together with a map of the linked program's lines back to each
+    /// module's source, composed from the modules' own maps; `None` when a module has none.
+    pub fn link_with_source_map(&self) -> Result<(String, Option<SourceMap>)> {
+        self.link_as(None)
+    }
+
+    /// [`ModuleGraph::link_with_source_map`] in the module format `target`, or in the
+    /// format `link` picks when it is `None`. A graph of one module is only returned
+    /// unchanged when it already has that format: a script for [`OutputTarget::Script`], an
+    /// ES module for [`OutputTarget::Esm`]. ES module output runs an ES module entry at its
+    /// top level, after the registry of the other modules, so the entry's exports stay its own
+    /// bindings; only its imports and re-exports are rewritten onto the registry, and names it
+    /// re-exports with `export *` are not exported. A CommonJS entry's `module.exports` becomes
+    /// the default export.
+    pub fn link_as(&self, target: Option<OutputTarget>) -> Result<(String, Option<SourceMap>)> {
+        let entry = self.entry();
+        let entry_exports = match target {
+            Some(OutputTarget::Esm | OutputTarget::Script) => {
+                exported_names(&entry.compile.javascript)
+                    .with_context(|| format!("failed linking {}", entry.path.display()))?
+            }
+            _ => None,
+        };
+        let unchanged = match target {
+            None => true,
+            Some(OutputTarget::Script) => entry_exports.is_none(),
+            Some(OutputTarget::Esm) => entry_exports.is_some(),
+            Some(OutputTarget::Cjs) => false,
+        };
+        if self.modules.len() == 1 && unchanged {
+            return Ok((
+                entry.compile.javascript.clone(),
+                entry.compile.source_map.clone(),
+            ));
+        }
+        let entry_index = self.modules.len() - 1;
+        let hoisted = target == Some(OutputTarget::Esm) && entry_exports.is_some();
+        if hoisted
+            && let Some(importer) = self.modules.iter().find(|module| {
+                module
+                    .imports
+                    .iter()
+                    .any(|import| import.module == entry_index)
+            })
+        {
+            bail!(
+                "{} imports the entry {}, which an ES module bundle cannot link; use --target script or cjs",
+                importer.path.display(),
+                entry.path.display()
+            );
+        }
+        let registry = if hoisted {
+            &self.modules[..entry_index]
+        } else {
+            &self.modules[..]
+        };
+        let opener = match target {
+            Some(OutputTarget::Esm) if hoisted => {
+                "const [__klumo_default, __klumo_require] = (() => {"
+            }
+            Some(OutputTarget::Esm) => "const __klumo_entry = (() => {",
+            _ => "(() => {",
+        };
+        let mut out = format!(
+            "{LINKED_HEADER} each module below resolves its imports through __klumo_load.\n{opener}\n{LINK_PRELUDE}"
+        );
+        let mut source_map = Some(SourceMap::default());
+        for module in registry {
+            let body = to_registry_module(&module.compile.javascript)
+                .map_err(|err| {
+                    // A module that does not parse names the source line it came from.
+                    match module
+                        .compile
+                        .source_map
+                        .as_ref()
+                        .and_then(|map| map.remap_error("", &err.to_string()))
+                    {
+                        Some(remapped) => anyhow!(remapped),
+                        None => err,
+                    }
+                })
+                .with_context(|| format!("failed linking {}", module.path.display()))?;
+            let specifiers = module.specifiers();
+            out.push_str(&format!(
+                "// {}\n[function (module, exports, require) {{\n",
+                module.path.display()
+            ));
+            // The registry rewrite keeps line breaks and only prepends its export header.
+            let header_lines = body
+                .matches('\n')
+                .count()
+                .saturating_sub(module.compile.javascript.matches('\n').count());
+            source_map = source_map.zip(module.compile.source_map.as_ref()).map(
+                |(mut linked, module_map)| {
+                    linked.extend_at(out.matches('\n').count() + header_lines, module_map);
+                    linked
+                },
+            );
+            out.push_str(&format!("{body}\n}}, {{ {specifiers} }}],\n"));
+        }
+        out.push_str(&match target {
+            None | Some(OutputTarget::Script) => {
+                format!("];\n__klumo_load({entry_index});\n}})();\n")
+            }
+            Some(OutputTarget::Cjs) => {
+                format!("];\nmodule.exports = __klumo_load({entry_index});\n}})();\n")
+            }
+            Some(OutputTarget::Esm) if hoisted => format!(
+                "];\nreturn [__klumo_default, __klumo_requirer({{ {} }})];\n}})();\n",
+                entry.specifiers()
+            ),
+            Some(OutputTarget::Esm) => format!(
+                "];\nreturn __klumo_load({entry_index});\n}})();\nexport default __klumo_entry;\n"
+            ),
+        });
+        if hoisted {
+            let body = to_hoisted_entry(&entry.compile.javascript)
+                .with_context(|| format!("failed linking {}", entry.path.display()))?;
+            source_map =
+                source_map
+                    .zip(entry.compile.source_map.as_ref())
+                    .map(|(mut linked, entry_map)| {
+                        linked.extend_at(out.matches('\n').count(), entry_map);
+                        linked
+                    });
+            out.push_str(&body);
+            if !out.ends_with('\n') {
+                out.push('\n');
+            }
+        }
+        if let Some(diagnostic) = check_syntax(&out).into_iter().next() {
+            bail!(
+                "the linked program for {} does not parse: {}",
+                entry.path.display(),
+                source_map
+                    .as_ref()
+                    .and_then(|map| map.remap_error("", &diagnostic.to_string()))
+                    .unwrap_or_else(|| diagnostic.to_string())
+            );
+        }
+        Ok((out, source_map))
+    }
+}
+
+/// The names an ES module exports, `None` for anything without `import`/`export`
+/// declarations.
+fn exported_names(javascript: &str) -> Result<Option<Vec<String>>> {
+    let allocator = Allocator::default();
+    let rewriter = Rewriter::walked(&parse(&allocator, javascript)?, false)?;
+    Ok(rewriter
+        .esm
+        .then(|| rewriter.exports.into_iter().map(|(name, _)| name).collect()))
+}
+
+/// `name` as written after `as` in an `export { … }` clause.
+fn export_name(name: &str) -> String {
+    if is_identifier(name) {
+        name.to_string()
+    } else {
+        serde_json::Value::from(name).to_string()
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|first| first.is_alphabetic() || first == '_' || first == '$')
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+}
+
+/// Rewrites the `import`/`export` declarations of an ES module onto the registry of
+/// [`LINK_PRELUDE`]; anything without them is returned as it is. Replaced declarations keep
+/// their line breaks.
+fn to_registry_module(javascript: &str) -> Result<String> {
+    let allocator = Allocator::default();
+    let program = parse(&allocator, javascript)?;
+    let mut rewriter = Rewriter::walked(&program, false)?;
+    let mut dynamic = DynamicImports::default();
+    dynamic.visit_program(&program);
+    if dynamic.import_meta {
+        bail!("import.meta cannot be linked into a bundle");
+    }
+    rewriter.dynamic_imports(&dynamic.starts);
+
+    let mut out = String::new();
+    if rewriter.esm {
+        let getters = rewriter
+            .exports
+            .iter()
+            .map(|(name, local)| {
+                format!("{}: () => {local}", serde_json::Value::from(name.as_str()))
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        out.push_str(&format!(
+            "\"use strict\"; __klumo_export(exports, {{ {getters} }});\n"
+        ));
+    }
+    rewriter.apply(javascript, &mut out);
+    Ok(out)
+}
+
+/// Rewrites the imports and re-exports of an ES module entry onto `__klumo_require`, for
+/// running it at the top level of [`OutputTarget::Esm`] output; its own exports stay as they
+/// are. Replaced declarations keep their line breaks.
+fn to_hoisted_entry(javascript: &str) -> Result<String> {
+    let allocator = Allocator::default();
+    let program = parse(&allocator, javascript)?;
+    let mut rewriter = Rewriter::walked(&program, true)?;
+    let mut dynamic = DynamicImports::default();
+    dynamic.visit_program(&program);
+    rewriter.dynamic_imports(&dynamic.starts);
+    let mut out = String::new();
+    rewriter.apply(javascript, &mut out);
+    Ok(out)
+}
+
+/// Where `import(...)` calls start, and whether `import.meta` appears.
+#[derive(Default)]
+struct DynamicImports {
+    starts: Vec<u32>,
+    import_meta: bool,
+}
+
+impl<'a> Visit<'a> for DynamicImports {
+    fn visit_import_expression(&mut self, it: &ImportExpression<'a>) {
+        self.starts.push(it.span.start);
+        walk::walk_import_expression(self, it);
+    }
+
+    fn visit_import_meta(&mut self, _it: &ImportMeta) {
+        self.import_meta = true;
+    }
+}
+
+struct Rewriter {
+    /// Byte ranges of the source and their replacements.
+    edits: Vec<(usize, usize, String)>,
+    /// Exported name and the expression reading it.
+    exports: Vec<(String, String)>,
+    esm: bool,
+    temporaries: usize,
+    /// The module is the entry of ES module output, run at its top level: it loads other
+    /// modules through `__klumo_require`, and only its re-exports of them are rewritten.
+    hoisted: bool,
+}
+
+impl Rewriter {
+    /// A rewriter that has walked the top-level statements of `program`.
+    fn walked(program: &Program<'_>, hoisted: bool) -> Result<Self> {
+        let mut rewriter = Rewriter {
+            edits: Vec::new(),
+            exports: Vec::new(),
+            esm: false,
+            temporaries: 0,
+            hoisted,
+        };
+        for statement in &program.body {
+            rewriter.statement(statement)?;
+        }
+        Ok(rewriter)
+    }
+
+    /// Turns the `import(...)` calls starting at `starts` into registry loads.
+    fn dynamic_imports(&mut self, starts: &[u32]) {
+        let dynamic = format!("{}.dynamic", self.require_name());
+        for &start in starts {
+            self.replace(
+                Span::new(start, start + "import".len() as u32),
+                dynamic.clone(),
+            );
+        }
+        self.edits.sort_by_key(|(start, _, _)| *start);
+    }
+
+    /// Appends `javascript` with the edits applied to `out`, padding each replacement with the
+    /// line breaks of what it replaced.
+    fn apply(&self, javascript: &str, out: &mut String) {
+        let mut copied = 0;
+        for (start, end, replacement) in &self.edits {
+            out.push_str(&javascript[copied..*start]);
+            out.push_str(replacement);
+            out.push_str(&"\n".repeat(javascript[*start..*end].matches('\n').count()));
+            copied = *end;
+        }
+        out.push_str(&javascript[copied..]);
+    }
+
+    fn statement(&mut self, statement: &Statement<'_>) -> Result<()> {
+        match statement {
+            Statement::ImportDeclaration(import) => self.import_declaration(import),
+            Statement::ExportDeclaration(export) => self.export_declaration(export),
+            Statement::ExportNamedDeclaration(export) => {
+                self.export_list(export);
+                Ok(())
+            }
+            Statement::ExportFromDeclaration(export) => self.export_from(export),
+            Statement::ExportAllDeclaration(export) => self.export_all(export),
+            Statement::ExportDefaultDeclaration(export) => {
+                self.export_default(export);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn replace(&mut self, span: Span, replacement: String) {
+        self.edits
+            .push((span.start as usize, span.end as usize, replacement));
+    }
+
+    fn temporary(&mut self) -> String {
+        self.temporaries += 1;
+        format!("__klumo_import_{}", self.temporaries)
+    }
+
+    fn require_name(&self) -> &'static str {
+        if self.hoisted {
+            "__klumo_require"
+        } else {
+            "require"
+        }
+    }
+
+    fn require(&self, specifier: &str, with_clause: Option<&WithClause<'_>>) -> Result<String> {
+        if with_clause.is_some() {
+            bail!("import attributes cannot be linked into a bundle");
+        }
+        Ok(format!(
+            "{}({})",
+            self.require_name(),
+            serde_json::Value::from(specifier)
+        ))
+    }
+
+    fn import_declaration(&mut self, import: &ImportDeclaration<'_>) -> Result<()> {
+        self.esm = true;
+        let require = self.require(&import.source.value, import.with_clause.as_deref())?;
+        let specifiers = import
+            .specifiers
+            .as_ref()
+            .map_or(&[][..], |specifiers| specifiers.as_slice());
+        if specifiers.is_empty() {
+            self.replace(import.span, format!("{require};"));
+            return Ok(());
+        }
+
+        let module = self.temporary();
+        let mut replacement = format!("const {module} = {require};");
+        let mut named = Vec::new();
+        for specifier in specifiers {
+            match specifier {
+                ImportDeclarationSpecifier::ImportDefaultSpecifier(default) => replacement
+                    .push_str(&format!(
+                        " const {} = __klumo_default({module});",
+                        default.local.name
+                    )),
+                ImportDeclarationSpecifier::ImportNamespaceSpecifier(namespace) => {
+                    replacement.push_str(&format!(" const {} = {module};", namespace.local.name))
+                }
+                ImportDeclarationSpecifier::ImportSpecifier(binding) => {
+                    let imported = binding.imported.name().to_string();
+                    let local = binding.local.name.to_string();
+                    named.push(if imported == local {
+                        local
+                    } else {
+                        format!("{}: {local}", serde_json::Value::from(imported))
+                    });
+                }
+            }
+        }
+        if !named.is_empty() {
+            replacement.push_str(&format!(" const {{ {} }} = {module};", named.join(", ")));
+        }
+        self.replace(import.span, replacement);
+        Ok(())
+    }
+
+    /// `export const`/`let`/`var`, `export function` and `export class`: the declaration stays
+    /// and every name it binds is exported.
+    fn export_declaration(&mut self, export: &ExportDeclaration<'_>) -> Result<()> {
+        self.esm = true;
+        let names: Vec<String> = match &export.declaration {
+            Declaration::VariableDeclaration(variables) => variables
+                .declarations
+                .iter()
+                .flat_map(|declarator| declarator.id.get_binding_identifiers())
+                .map(|binding| binding.name.to_string())
+                .collect(),
+            Declaration::FunctionDeclaration(function) => {
+                function.id.iter().map(|id| id.name.to_string()).collect()
+            }
+            Declaration::ClassDeclaration(class) => {
+                class.id.iter().map(|id| id.name.to_string()).collect()
+            }
+            _ => bail!("unsupported export declaration"),
+        };
+        for name in names {
+            self.exports.push((name.clone(), name));
+        }
+        if !self.hoisted {
+            self.replace(
+                Span::new(export.span.start, export.declaration.span().start),
+                String::new(),
+            );
+        }
+        Ok(())
+    }
+
+    fn export_list(&mut self, export: &ExportNamedDeclaration<'_>) {
+        self.esm = true;
+        for specifier in &export.specifiers {
+            self.exports.push((
+                specifier.exported.name().to_string(),
+                specifier.local.name().to_string(),
+            ));
+        }
+        if !self.hoisted {
+            self.replace(export.span, String::new());
+        }
+    }
+
+    fn export_from(&mut self, export: &ExportFromDeclaration<'_>) -> Result<()> {
+        self.esm = true;
+        let require = self.require(&export.source.value, export.with_clause.as_deref())?;
+        let module = self.temporary();
+        let mut replacement = format!("const {module} = {require};");
+        for specifier in &export.specifiers {
+            let local = specifier.local.name().to_string();
+            let read = format!("{module}[{}]", serde_json::Value::from(local));
+            let exported = specifier.exported.name().to_string();
+            if self.hoisted {
+                // ES module exports cannot forward a registry module's getters, so re-exports
+                // are read once, when the declaration runs.
+                let binding = self.temporary();
+                replacement.push_str(&format!(
+                    " const {binding} = {read}; export {{ {binding} as {} }};",
+                    export_name(&exported)
+                ));
+            }
+            self.exports.push((exported, read));
+        }
+        self.replace(export.span, replacement);
+        Ok(())
+    }
+
+    fn export_all(&mut self, export: &ExportAllDeclaration<'_>) -> Result<()> {
+        self.esm = true;
+        let require = self.require(&export.source.value, export.with_clause.as_deref())?;
+        let replacement = match &export.exported {
+            Some(name) if self.hoisted => {
+                let module = self.temporary();
+                self.exports.push((name.name().to_string(), module.clone()));
+                format!(
+                    "const {module} = {require}; export {{ {module} as {} }};",
+                    export_name(&name.name())
+                )
+            }
+            Some(name) => {
+                let module = self.temporary();
+                self.exports.push((name.name().to_string(), module.clone()));
+                format!("const {module} = {require};")
+            }
+            None if self.hoisted => format!("{require};"),
+            None => format!("__klumo_export_all(exports, {require});"),
+        };
+        self.replace(export.span, replacement);
+        Ok(())
+    }
+
+    /// A named function or class keeps its declaration; anything else is bound to a constant.
+    fn export_default(&mut self, export: &ExportDefaultDeclaration<'_>) {
+        self.esm = true;
+        if self.hoisted {
+            return;
+        }
+        let declared = match &export.declaration {
+            ExportDefaultDeclarationKind::FunctionDeclaration(function) => function.id.as_ref(),
+            ExportDefaultDeclarationKind::ClassDeclaration(class) => class.id.as_ref(),
+            _ => None,
+        };
+        let keyword = Span::new(export.span.start, export.declaration.span().start);
+        match declared {
+            Some(id) => {
+                self.exports
+                    .push(("default".to_string(), id.name.to_string()));
+                self.replace(keyword, String::new());
+            }
+            None => {
+                self.exports
+                    .push(("default".to_string(), "__klumo_default_export".to_string()));
+                self.replace(keyword, "const __klumo_default_export = ".to_string());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        LINKED_HEADER, OutputTarget, build_module_graph, build_module_graph_batched,
+        relative_specifiers, resolve_relative_import,
+    };
+    use crate::{CompileMetadata, CompileResult, SourceMap};
+    use klumo_engine::{BoaEngine, JsEngine};
+    use std::fs;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    fn passthrough(path: &Path) -> anyhow::Result<CompileResult> {
+        let javascript = fs::read_to_string(path)?;
+        Ok(CompileResult {
+            source_map: Some(SourceMap::identity(
+                &path.display().to_string(),
+                &javascript,
+            )),
+            javascript,
+            metadata: CompileMetadata {
+                provider: None,
+                model: None,
+                served_model: None,
+                prompt_version: String::new(),
+                cache_hit: false,
+                notes: None,
+                confidence: None,
+                explanation: None,
+                signature: None,
+                similarity: None,
+            },
+        })
+    }
+
+    #[test]
+    fn finds_relative_specifiers_only() {
+        let specifiers = relative_specifiers(
+            "import a, { b as c } from './a.js';\n\
+             import './side';\n\
+             import fs from 'node:fs';\n\
+             export * from '../shared/index.js';\n\
+             export { d } from \"./a.js\";\n\
+             const lazy = () => import('./lazy.js');\n\
+             const cjs = require('./cjs');\n\
+             obj.require('./not-a-module');\n",
+        );
+        assert_eq!(
+            specifiers,
+            vec![
+                "./a.js",
+                "./side",
+                "../shared/index.js",
+                "./lazy.js",
+                "./cjs"
+            ]
+        );
+    }
+
+    #[test]
+    fn resolves_extensions_typescript_sources_and_index_files() {
+        let dir = tempdir().expect("tempdir should work");
+        fs::create_dir(dir.path().join("lib")).expect("mkdir should work");
+        for file in ["util.ts", "lib/index.js", "data.mjs"] {
+            fs::write(dir.path().join(file), "").expect("write should work");
+        }
+        let importer = dir.path().join("main.js");
+        let resolve = |specifier: &str| resolve_relative_import(&importer, specifier);
+        assert_eq!(resolve("./util.js"), Some(dir.path().join("util.ts")));
+        assert_eq!(resolve("./lib"), Some(dir.path().join("lib/index.js")));
+        assert_eq!(resolve("./data"), Some(dir.path().join("data.mjs")));
+        assert_eq!(resolve("./missing"), None);
+    }
+
+    #[test]
+    fn graph_orders_dependencies_first_and_compiles_each_file_once() {
+        let dir = tempdir().expect("tempdir should work");
+        fs::write(
+            dir.path().join("main.js"),
+            "import { twice } from './math.js';\nimport { label } from './label.js';\nconsole.log(label(twice(21)));\n",
+        )
+        .expect("write should work");
+        fs::write(
+            dir.path().join("math.js"),
+            "export const twice = (n) => n * 2;\n",
+        )
+        .expect("write should work");
+        fs::write(
+            dir.path().join("label.js"),
+            "import { twice } from './math.js';\nexport function label(n) { return `n=${n}`; }\n",
+        )
+        .expect("write should work");
+
+        let mut compiled = Vec::new();
+        let graph = build_module_graph(&dir.path().join("main.js"), |path| {
+            compiled.push(path.to_path_buf());
+            passthrough(path)
+        })
+        .expect("graph should build");
+        assert_eq!(compiled.len(), 3);
+        let names: Vec<_> = graph
+            .modules
+            .iter()
+            .map(|module| module.path.file_name().expect("file name").to_owned())
+            .collect();
+        assert_eq!(names, ["math.js", "label.js", "main.js"]);
+        assert_eq!(graph.entry().imports[0].module, 0);
+        assert_eq!(graph.entry().imports[1].module, 1);
+
+        // Batched, each import level arrives at once; `math.js` is not handed over twice.
+        let mut rounds = Vec::new();
+        let batched = build_module_graph_batched(&dir.path().join("main.js"), |paths| {
+            rounds.push(
+                paths
+                    .iter()
+                    .map(|path| path.file_name().expect("file name").to_owned())
+                    .collect::<Vec<_>>(),
+            );
+            paths.iter().map(|path| passthrough(path)).collect()
+        })
+        .expect("graph should build");
+        assert_eq!(rounds, [vec!["main.js"], vec!["math.js", "label.js"]]);
+        assert_eq!(batched.link().ok(), graph.link().ok());
+    }
+
+    #[test]
+    fn linked_graph_runs_esm_and_commonjs_modules_together() {
+        let dir = tempdir().expect("tempdir should work");
+        fs::write(
+            dir.path().join("main.mjs"),
+            "import greet, { count, bump, shout } from './greet.mjs';\n\
+             import * as shapes from './shapes.cjs';\n\
+             bump();\n\
+             globalThis.result = `${greet(shout('klumo'))} ${count} ${shapes.area(2)}`;\n",
+        )
+        .expect("write should work");
+        fs::write(
+            dir.path().join("greet.mjs"),
+            "export let count = 0;\n\
+             export function bump() { count += 1; }\n\
+             export const { shout } = { shout: (text) => text.toUpperCase() };\n\
+             export default (name) => `hello ${name}`;\n",
+        )
+        .expect("write should work");
+        fs::write(
+            dir.path().join("shapes.cjs"),
+            "module.exports = { area: (side) => side * side };\n",
+        )
+        .expect("write should work");
+
+        let graph = build_module_graph(&dir.path().join("main.mjs"), passthrough)
+            .expect("graph should build");
+        let (linked, source_map) = graph.link_with_source_map().expect("graph should link");
+        assert!(linked.starts_with(LINKED_HEADER));
+        let source_map = source_map.expect("modules with maps link into a map");
+        let line_of = |needle: &str| {
+            let line = linked
+                .lines()
+                .position(|line| line.contains(needle))
+                .expect("linked line");
+            let (source, line) = source_map.lookup(line + 1).expect("mapped line");
+            (
+                Path::new(source).file_name().map(|name| name.to_owned()),
+                line,
+            )
+        };
+        assert_eq!(line_of("side * side"), (Some("shapes.cjs".into()), 1));
+        assert_eq!(line_of("globalThis.result"), (Some("main.mjs".into()), 4));
+        assert_eq!(line_of("count += 1"), (Some("greet.mjs".into()), 2));
+        let mut engine = BoaEngine::new().expect("engine should initialize");
+        engine
+            .eval_script(&linked, "linked.js")
+            .expect("linked program should run");
+        let result = engine
+            .eval_script("globalThis.result", "<test>")
+            .expect("eval should pass");
+        // Named imports are read once, before `bump` ran.
+        assert_eq!(result.value.as_deref(), Some("hello KLUMO 0 4"));
+    }
+
+    #[test]
+    fn linked_graphs_convert_to_each_output_target() {
+        let dir = tempdir().expect("tempdir should work");
+        fs::write(
+            dir.path().join("main.mjs"),
+            "import shapes from './shapes.cjs';\n\
+             export const total = shapes.area(3);\n\
+             export default 'main';\n",
+        )
+        .expect("write should work");
+        fs::write(
+            dir.path().join("shapes.cjs"),
+            "module.exports = { area: (side) => side * side };\n",
+        )
+        .expect("write should work");
+        let graph = build_module_graph(&dir.path().join("main.mjs"), passthrough)
+            .expect("graph should build");
+
+        let (esm, _) = graph
+            .link_as(Some(OutputTarget::Esm))
+            .expect("graph should link");
+        assert!(esm.starts_with(LINKED_HEADER));
+        // The entry runs at the top level, so its exports are its own bindings.
+        assert!(esm.ends_with(
+            "const __klumo_import_1 = __klumo_require(\"./shapes.cjs\"); const shapes = __klumo_default(__klumo_import_1);\n\
+             export const total = shapes.area(3);\n\
+             export default 'main';\n"
+        ));
+        let mut engine = BoaEngine::new().expect("engine should initialize");
+        engine
+            .eval_module(&format!("{esm}globalThis.result = total;\n"), "linked.mjs")
+            .expect("linked module should run");
+        let total = engine
+            .eval_script("globalThis.result", "<test>")
+            .expect("eval should pass");
+        assert_eq!(total.value.as_deref(), Some("9"));
+
+        let (cjs, _) = graph
+            .link_as(Some(OutputTarget::Cjs))
+            .expect("graph should link");
+        let mut engine = BoaEngine::new().expect("engine should initialize");
+        engine
+            .eval_script(
+                &format!("var module = {{ exports: {{}} }};\n{cjs}"),
+                "linked.cjs",
+            )
+            .expect("linked program should run");
+        let exports = engine
+            .eval_script(
+                "`${module.exports.default} ${module.exports.total}`",
+                "<test>",
+            )
+            .expect("eval should pass");
+        assert_eq!(exports.value.as_deref(), Some("main 9"));
+
+        let shapes = build_module_graph(&dir.path().join("shapes.cjs"), passthrough)
+            .expect("graph should build");
+        let (script, _) = shapes
+            .link_as(Some(OutputTarget::Script))
+            .expect("graph should link");
+        assert_eq!(
+            script,
+            "module.exports = { area: (side) => side * side };\n"
+        );
+        let (esm, _) = shapes
+            .link_as(Some(OutputTarget::Esm))
+            .expect("graph should link");
+        assert!(esm.ends_with("export default __klumo_entry;\n"));
+    }
+
+    #[test]
+    fn missing_relative_imports_name_the_importer() {
+        let dir = tempdir().expect("tempdir should work");
+        fs::write(dir.path().join("main.js"), "import './gone.js';\n").expect("write should work");
+        let err = build_module_graph(&dir.path().join("main.js"), passthrough)
+            .expect_err("missing import should fail");
+        assert!(
+            err.to_string()
+                .starts_with("cannot resolve './gone.js' imported from")
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Compiles remembered per project; older ones are dropped as new ones arrive.
+pub const HIT_LOG_CAPACITY: usize = 1000;
+
+/// Compiles the rolling hit rate covers by default.
+pub const DEFAULT_HIT_RATE_WINDOW: usize = 100;
+
+static RECORD_LOCK: Mutex<()> = Mutex::new(());
+
+/// One translated (non-JavaScript) compile: served from the cache or not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheLookup {
+    /// Seconds since the Unix epoch.
+    pub at: u64,
+    pub hit: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct HitLogFile {
+    project: String,
+    lookups: Vec<CacheLookup>,
+}
+
+/// Per-project record of compile cache hits and misses, for spotting cache invalidation
+/// regressions such as a prompt change that turns every compile into a provider call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheHitLog {
+    pub path: PathBuf,
+    project: String,
+}
+
+impl CacheHitLog {
+    /// Log for the project rooted at `project_dir`, under `~/.klumo/metrics/cache-hits`.
+    pub fn for_project(project_dir: &Path) -> Result<Self> {
+        let home = dirs::home_dir().context("failed to resolve home directory")?;
+        let project = project_dir
+            .canonicalize()
+            .unwrap_or_else(|_| project_dir.to_path_buf())
+            .display()
+            .to_string();
+        let digest = Sha256::digest(project.as_bytes());
+        let path = home
+            .join(".klumo")
+            .join("metrics")
+            .join("cache-hits")
+            .join(format!("{:x}.json", digest));
+        Ok(Self::new(path, project))
+    }
+
+    pub fn new(path: PathBuf, project: impl Into<String>) -> Self {
+        Self {
+            path,
+            project: project.into(),
+        }
+    }
+
+    pub fn project(&self) -> &str {
+        &self.project
+    }
+
+    /// Recorded lookups, oldest first. A missing file yields none.
+    pub fn load(&self) -> Result<Vec<CacheLookup>> {
+        let raw = match fs::read_to_string(&self.path) {
+            Ok(raw) => raw,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed reading {}", self.path.display()));
+            }
+        };
+        let file: HitLogFile = serde_json::from_str(&raw)
+            .with_context(|| format!("failed parsing {}", self.path.display()))?;
+        Ok(file.lookups)
+    }
+
+    /// Appends one lookup. A log that cannot be parsed is reported rather than overwritten.
+    pub fn record(&self, hit: bool) -> Result<()> {
+        // Batched compiles record from several threads; keep each read-modify-write whole.
+        let _guard = RECORD_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut lookups = self.load()?;
+        let at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        lookups.push(CacheLookup { at, hit });
+        let excess = lookups.len().saturating_sub(HIT_LOG_CAPACITY);
+        lookups.drain(..excess);
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed creating {}", dir.display()))?;
+        }
+        let file = HitLogFile {
+            project: self.project.clone(),
+            lookups,
+        };
+        let raw = serde_json::to_string_pretty(&file).context("failed serializing hit log")?;
+        // Written aside and renamed over the log, so a concurrent reader never sees half a file.
+        let staging = self
+            .path
+            .with_extension(format!("json.{}.tmp", std::process::id()));
+        fs::write(&staging, raw)
+            .with_context(|| format!("failed writing {}", staging.display()))?;
+        fs::rename(&staging, &self.path)
+            .with_context(|| format!("failed writing {}", self.path.display()))
+    }
+}
+
+/// Hit rate over the last `window` lookups with the number of lookups it covers, or `None`
+/// when nothing was recorded.
+pub fn rolling_hit_rate(lookups: &[CacheLookup], window: usize) -> Option<(f64, usize)> {
+    let recent = &lookups[lookups.len().saturating_sub(window.max(1))..];
+    if recent.is_empty() {
+        return None;
+    }
+    let hits = recent.iter().filter(|lookup| lookup.hit).count();
+    Some((hits as f64 / recent.len() as f64, recent.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CacheHitLog, CacheLookup, HIT_LOG_CAPACITY, rolling_hit_rate};
+
+    #[test]
+    fn rolling_rate_covers_only_the_latest_lookups() {
+        let lookups: Vec<CacheLookup> = [true, true, true, false, false]
+            .into_iter()
+            .map(|hit| CacheLookup { at: 0, hit })
+            .collect();
+        assert_eq!(rolling_hit_rate(&lookups, 100), Some((0.6, 5)));
+        assert_eq!(rolling_hit_rate(&lookups, 2), Some((0.0, 2)));
+        assert_eq!(rolling_hit_rate(&[], 10), None);
+    }
+
+    #[test]
+    fn log_keeps_a_bounded_history() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let log = CacheHitLog::new(dir.path().join("hits.json"), "/work/app");
+        for index in 0..HIT_LOG_CAPACITY + 1 {
+            log.record(index % 2 == 0).expect("record should work");
+        }
+        let lookups = log.load().expect("load should work");
+        assert_eq!(lookups.len(), HIT_LOG_CAPACITY);
+        assert!(!lookups[0].hit, "the oldest entry was dropped");
+    }
+
+    #[test]
+    fn concurrent_records_keep_every_lookup_and_never_reset_a_bad_log() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let log = CacheHitLog::new(dir.path().join("hits.json"), "/work/app");
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..10 {
+                        log.record(true).expect("record should work");
+                    }
+                });
+            }
+        });
+        assert_eq!(log.load().expect("load should work").len(), 80);
+
+        std::fs::write(&log.path, "{\"project\": ").expect("write");
+        assert!(log.record(false).is_err());
+        assert_eq!(
+            std::fs::read_to_string(&log.path).expect("read"),
+            "{\"project\": "
+        );
+    }
+}
@@ -0,0 +1,296 @@
+use crate::{CachedResult, CompileCache, CompileResult, FileCompileCache};
+use anyhow::{Context, Result, anyhow};
+use reqwest::StatusCode;
+use reqwest::blocking::Client;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// How long one request to the shared cache may take before the local cache answers instead.
+pub const HTTP_CACHE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A compile cache shared by a team over HTTP. Entries are fetched with `GET` and stored with
+/// `PUT` at `{base_url}/{key}.json`, in the layout `FileCompileCache` writes, so any store
+/// that speaks plain GET/PUT (an S3 or GCS bucket, nginx with WebDAV) can hold them. A token,
+/// when set, is sent as `Authorization: Bearer <token>`.
+///
+/// Every entry is also written to `local`. Once the server cannot be reached, the rest of the
+/// run uses `local` alone, so a flaky network costs one timeout rather than one per compile.
+pub struct HttpCompileCache {
+    base_url: String,
+    token: Option<String>,
+    client: Client,
+    local: FileCompileCache,
+    offline: AtomicBool,
+}
+
+impl std::fmt::Debug for HttpCompileCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HttpCompileCache")
+            .field("base_url", &self.base_url)
+            .field("token", &self.token.as_ref().map(|_| "<redacted>"))
+            .field("local", &self.local)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HttpCompileCache {
+    pub fn new(base_url: impl Into<String>, local: FileCompileCache) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(HTTP_CACHE_TIMEOUT)
+            .build()
+            .context("failed building shared cache HTTP client")?;
+        Ok(Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            token: None,
+            client,
+            local,
+            offline: AtomicBool::new(false),
+        })
+    }
+
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token;
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn local(&self) -> &FileCompileCache {
+        &self.local
+    }
+
+    fn entry_url(&self, key: &str) -> String {
+        format!("{}/{key}.json", self.base_url)
+    }
+
+    fn authorized(
+        &self,
+        request: reqwest::blocking::RequestBuilder,
+    ) -> reqwest::blocking::RequestBuilder {
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    /// The shared entry under `key`; `Ok(None)` when the server has none.
+    fn fetch(&self, key: &str) -> Result<Option<CompileResult>> {
+        let url = self.entry_url(key);
+        let response = self
+            .authorized(self.client.get(&url))
+            .send()
+            .with_context(|| format!("failed reaching {url}"))?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => {
+                let raw = response
+                    .text()
+                    .with_context(|| format!("failed reading {url}"))?;
+                // A corrupt or foreign entry is a miss, like one in the local cache.
+                Ok(serde_json::from_str::<CachedResult>(&raw)
+                    .ok()
+                    .map(CachedResult::into_result))
+            }
+            status => Err(anyhow!("GET {url} answered {status}")),
+        }
+    }
+
+    fn store(&self, key: &str, result: &CompileResult) -> Result<()> {
+        let url = self.entry_url(key);
+        let raw = serde_json::to_string(&CachedResult::from_result(result))
+            .context("failed serializing cache payload")?;
+        let response = self
+            .authorized(self.client.put(&url))
+            .header("Content-Type", "application/json")
+            .body(raw)
+            .send()
+            .with_context(|| format!("failed reaching {url}"))?;
+        if !response.status().is_success() {
+            return Err(anyhow!("PUT {url} answered {}", response.status()));
+        }
+        Ok(())
+    }
+
+    fn go_offline(&self, err: &anyhow::Error) {
+        if !self.offline.swap(true, Ordering::SeqCst) {
+            eprintln!(
+                "[klumo] warning: shared compile cache unavailable, using the local cache: {err:#}"
+            );
+        }
+    }
+
+    fn is_offline(&self) -> bool {
+        self.offline.load(Ordering::SeqCst)
+    }
+}
+
+impl CompileCache for HttpCompileCache {
+    fn get(&self, key: &str) -> Option<CompileResult> {
+        if !self.is_offline() {
+            match self.fetch(key) {
+                Ok(Some(result)) => {
+                    // Kept locally so the entry is still there when the server is not.
+                    let _ = self.local.put(key, &result);
+                    return Some(result);
+                }
+                Ok(None) => {}
+                Err(err) => self.go_offline(&err),
+            }
+        }
+        self.local.get(key)
+    }
+
+    fn put(&self, key: &str, result: &CompileResult) -> Result<()> {
+        self.local.put(key, result)?;
+        if !self.is_offline()
+            && let Err(err) = self.store(key, result)
+        {
+            self.go_offline(&err);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HttpCompileCache;
+    use crate::{CompileCache, CompileMetadata, CompileResult, FileCompileCache, PROMPT_VERSION};
+    use klumo_llm::Provider;
+    use std::collections::HashMap;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    fn compiled(javascript: &str) -> CompileResult {
+        CompileResult {
+            javascript: javascript.to_string(),
+            metadata: CompileMetadata {
+                provider: Some(Provider::Ollama),
+                model: Some("qwen2.5-coder:7b".to_string()),
+                served_model: None,
+                prompt_version: PROMPT_VERSION.to_string(),
+                cache_hit: false,
+                notes: None,
+                confidence: None,
+                explanation: None,
+                signature: None,
+                similarity: None,
+            },
+            source_map: None,
+        }
+    }
+
+    /// A GET/PUT object store answering `requests` requests, which records the
+    /// `Authorization` header of each.
+    fn serve_bucket(requests: usize) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind should work");
+        let addr = listener.local_addr().expect("local addr should work");
+        let auth = Arc::new(Mutex::new(Vec::new()));
+        let auth_seen = Arc::clone(&auth);
+        std::thread::spawn(move || {
+            let mut objects: HashMap<String, String> = HashMap::new();
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.expect("accept should work");
+                let mut raw = Vec::new();
+                let mut buf = [0_u8; 8192];
+                let (head, mut body) = loop {
+                    let n = stream.read(&mut buf).expect("read should work");
+                    raw.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&raw).to_string();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        break (head.to_string(), body.to_string());
+                    }
+                };
+                let header = |name: &str| {
+                    head.lines()
+                        .filter_map(|line| line.split_once(':'))
+                        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                        .map(|(_, value)| value.trim().to_string())
+                };
+                let length: usize = header("content-length")
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(0);
+                while body.len() < length {
+                    let n = stream.read(&mut buf).expect("read should work");
+                    body.push_str(&String::from_utf8_lossy(&buf[..n]));
+                }
+                auth_seen
+                    .lock()
+                    .expect("lock")
+                    .push(header("authorization").unwrap_or_default());
+                let mut first = head.split_whitespace();
+                let method = first.next().unwrap_or_default().to_string();
+                let path = first.next().unwrap_or_default().to_string();
+                let (status, reply) = if method == "PUT" {
+                    objects.insert(path, body);
+                    (200, String::new())
+                } else {
+                    match objects.get(&path) {
+                        Some(object) => (200, object.clone()),
+                        None => (404, String::new()),
+                    }
+                };
+                let response = format!(
+                    "HTTP/1.1 {status} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{reply}",
+                    reply.len()
+                );
+                stream
+                    .write_all(response.as_bytes())
+                    .expect("write should work");
+            }
+        });
+        (format!("http://{addr}/team/"), auth)
+    }
+
+    #[test]
+    fn shared_entries_reach_other_machines_with_the_token() {
+        let (url, auth) = serve_bucket(3);
+        let writer_dir = tempfile::tempdir().expect("tempdir");
+        let reader_dir = tempfile::tempdir().expect("tempdir");
+        let writer = HttpCompileCache::new(
+            url.clone(),
+            FileCompileCache::new(writer_dir.path().to_path_buf()),
+        )
+        .expect("cache should build")
+        .with_token(Some("s3cret".to_string()));
+        let reader =
+            HttpCompileCache::new(url, FileCompileCache::new(reader_dir.path().to_path_buf()))
+                .expect("cache should build")
+                .with_token(Some("s3cret".to_string()));
+
+        assert_eq!(reader.get("abc"), None);
+        writer
+            .put("abc", &compiled("console.log(1);"))
+            .expect("put should work");
+        let hit = reader.get("abc").expect("shared entry should be found");
+        assert_eq!(hit.javascript, "console.log(1);");
+        assert!(hit.metadata.cache_hit);
+        assert_eq!(
+            reader.local().get("abc").map(|hit| hit.javascript),
+            Some("console.log(1);".to_string()),
+            "a shared hit is kept locally"
+        );
+        assert_eq!(*auth.lock().expect("lock"), vec!["Bearer s3cret"; 3]);
+    }
+
+    #[test]
+    fn unreachable_server_falls_back_to_the_local_cache() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind should work");
+        let url = format!("http://{}", listener.local_addr().expect("addr"));
+        drop(listener);
+        let dir = tempfile::tempdir().expect("tempdir");
+        let cache = HttpCompileCache::new(url, FileCompileCache::new(dir.path().to_path_buf()))
+            .expect("cache should build");
+
+        cache
+            .put("abc", &compiled("console.log(2);"))
+            .expect("put should fall back to the local cache");
+        assert_eq!(
+            cache.get("abc").map(|hit| hit.javascript),
+            Some("console.log(2);".to_string())
+        );
+        assert_eq!(cache.get("missing"), None);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::{CompileRequest, PROMPT_VERSION};
+use anyhow::{Context, Result};
+use klumo_llm::ProviderSelection;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The translated units of one file as of its last incremental compile, in source order.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkMap {
+    pub units: Vec<ChunkEntry>,
+}
+
+impl ChunkMap {
+    /// The entry translated from a unit with this text, wherever it sat in the file.
+    pub fn find(&self, source_hash: &str) -> Option<&ChunkEntry> {
+        self.units
+            .iter()
+            .find(|entry| entry.source_hash == source_hash)
+    }
+}
+
+/// One unit of a [`ChunkMap`]: a hash of its source text and the JavaScript it became.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkEntry {
+    /// See [`unit_hash`].
+    pub source_hash: String,
+    pub javascript: String,
+    pub provider: String,
+    pub model: String,
+}
+
+/// Identifies a unit's text in a [`ChunkMap`].
+pub fn unit_hash(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+/// Directory of the chunk maps inside a compile cache root.
+pub const CHUNKS_DIR: &str = "chunks";
+
+/// Chunk maps for `CompilerRouter.incremental`, one `<key>.json` per file and translation
+/// setup. A map is replaced on every incremental compile of its file, so units that left the
+/// file leave the map with it.
+#[derive(Debug, Clone)]
+pub struct ChunkMapStore {
+    root: PathBuf,
+}
+
+impl ChunkMapStore {
+    /// Store root for the compile cache in `cache_root`, so a project's `cache_dir` keeps its
+    /// chunk maps too.
+    pub fn root_in(cache_root: &Path) -> PathBuf {
+        cache_root.join(CHUNKS_DIR)
+    }
+
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.root.join(format!("{key}.json"))
+    }
+
+    /// The map stored under `key`; missing or unreadable maps count as empty.
+    pub fn get(&self, key: &str) -> ChunkMap {
+        fs::read_to_string(self.entry_path(key))
+            .ok()
+            .and_then(|raw| serde_json::from_str(&raw).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn put(&self, key: &str, map: &ChunkMap) -> Result<()> {
+        fs::create_dir_all(&self.root)
+            .with_context(|| format!("failed creating {}", self.root.display()))?;
+        let raw = serde_json::to_string_pretty(map).context("failed serializing chunk map")?;
+        let path = self.entry_path(key);
+        fs::write(&path, raw).with_context(|| format!("failed writing {}", path.display()))
+    }
+}
+
+/// Names a file's chunk map: its source id plus everything besides the source text that
+/// shapes a translation, so another provider, model, prompt or pipeline starts a fresh map.
+pub(crate) fn chunk_map_key(
+    req: &CompileRequest,
+    kind_hint: &str,
+    selection: ProviderSelection,
+    prompt_fingerprint: &str,
+    passes: &str,
+) -> String {
+    let mut hasher = Sha256::new();
+    for field in [
+        req.source_id.as_str(),
+        kind_hint,
+        &selection_label(&selection),
+        req.model_override.as_deref().unwrap_or_default(),
+        &req.model_override_for
+            .as_ref()
+            .map(ToString::to_string)
+            .unwrap_or_default(),
+        &req.sampling.fingerprint(),
+        req.scope_context.as_deref().unwrap_or_default(),
+        prompt_fingerprint,
+        passes,
+        PROMPT_VERSION,
+    ] {
+        hasher.update(field.as_bytes());
+        hasher.update(b"\0");
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+fn selection_label(selection: &ProviderSelection) -> String {
+    match selection {
+        ProviderSelection::Auto => "auto".to_string(),
+        ProviderSelection::Ollama => "ollama".to_string(),
+        ProviderSelection::OpenAiCompatible => "openai-compatible".to_string(),
+        ProviderSelection::LlamaCpp => "llamacpp".to_string(),
+        ProviderSelection::Fixture => "fixture".to_string(),
+        ProviderSelection::Custom(name) => format!("custom:{name}"),
+    }
+}
@@ -0,0 +1,178 @@
+use crate::passes::CompilePass;
+use crate::typescript::transpile;
+use crate::{CompileRequest, SourceKind};
+use anyhow::Result;
+use oxc_span::SourceType;
+use oxc_transformer::{JsxOptions, JsxRuntime, TransformOptions};
+
+pub const DEFAULT_JSX_PRAGMA: &str = "React.createElement";
+pub const DEFAULT_JSX_FRAGMENT: &str = "React.Fragment";
+
+/// Lowers JSX in `.jsx` and `.tsx` sources to calls of a pragma function (the classic
+/// runtime: `<a href={url}>hi</a>` becomes `React.createElement("a", { href: url }, "hi")`),
+/// so JSX runs without a translation: both then take the JavaScript passthrough, `.tsx` with
+/// its types already stripped. A source that does not parse is left for the LLM.
+///
+/// A leading `/** @jsx h */` or `/** @jsxFrag Fragment */` comment overrides the pragma or the
+/// fragment for its file, as it does in Babel and TypeScript.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsxPass {
+    pub pragma: String,
+    pub fragment: String,
+}
+
+impl Default for JsxPass {
+    fn default() -> Self {
+        Self {
+            pragma: DEFAULT_JSX_PRAGMA.to_string(),
+            fragment: DEFAULT_JSX_FRAGMENT.to_string(),
+        }
+    }
+}
+
+impl CompilePass for JsxPass {
+    fn name(&self) -> String {
+        format!("jsx({},{})", self.pragma, self.fragment)
+    }
+
+    /// Only `.jsx` and `.tsx` sources, so other cache keys do not depend on JSX settings.
+    fn applies(&self, req: &CompileRequest) -> bool {
+        jsx_kind(req).is_some()
+    }
+
+    fn prepare(&self, req: &mut CompileRequest) -> Result<()> {
+        let Some(typescript) = jsx_kind(req) else {
+            return Ok(());
+        };
+        if let Ok(javascript) =
+            lower_jsx(&req.source_text, typescript, &self.pragma, &self.fragment)
+        {
+            req.source_text = javascript;
+            req.language_hint = Some(SourceKind::JavaScript.as_hint());
+            req.kind_hint = Some(SourceKind::JavaScript);
+        }
+        Ok(())
+    }
+}
+
+/// Whether `req` is TSX (`true`) or JSX (`false`), by its kind hint or else its extension.
+fn jsx_kind(req: &CompileRequest) -> Option<bool> {
+    let kind = match req.kind_hint.clone().unwrap_or(SourceKind::Auto) {
+        SourceKind::Auto => SourceKind::infer_from_source_id(&req.source_id),
+        explicit => explicit,
+    };
+    match kind.as_hint().as_str() {
+        "jsx" => Some(false),
+        "tsx" => Some(true),
+        _ => None,
+    }
+}
+
+/// Rewrites every JSX element and fragment in `source` as a `pragma(type, props, ...children)`
+/// call with oxc; with `typescript` the source is TSX and its types are stripped too.
+pub fn lower_jsx(source: &str, typescript: bool, pragma: &str, fragment: &str) -> Result<String> {
+    let source_type = if typescript {
+        SourceType::tsx()
+    } else {
+        SourceType::jsx()
+    };
+    let options = TransformOptions {
+        jsx: JsxOptions {
+            runtime: JsxRuntime::Classic,
+            pragma: Some(pragma.to_string()),
+            pragma_frag: Some(fragment.to_string()),
+            display_name_plugin: false,
+            pure: false,
+            ..JsxOptions::enable()
+        },
+        ..TransformOptions::default()
+    };
+    transpile(source, source_type.with_unambiguous(true), &options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lower(source: &str) -> String {
+        lower_jsx(source, false, DEFAULT_JSX_PRAGMA, DEFAULT_JSX_FRAGMENT)
+            .expect("jsx should lower")
+    }
+
+    #[test]
+    fn lowers_elements_attributes_and_children() {
+        assert_eq!(
+            lower(
+                r#"const el = <a href={url} data-id="7" download {...rest}>Hi &amp; bye {name}</a>;"#
+            ),
+            "const el = React.createElement(\"a\", {\n\thref: url,\n\t\"data-id\": \"7\",\n\tdownload: true,\n\t...rest\n}, \"Hi & bye \", name);\n"
+        );
+        assert_eq!(
+            lower("const list = <><Item.Row key={1} /><br/></>;"),
+            "const list = React.createElement(React.Fragment, null, React.createElement(Item.Row, { key: 1 }), React.createElement(\"br\", null));\n"
+        );
+    }
+
+    #[test]
+    fn trims_text_like_jsx_and_honours_file_pragmas() {
+        assert_eq!(
+            lower(
+                "/** @jsx h @jsxFrag Frag */\nconst p = <p>\n  Hello,\n  world\n</p>;\nconst f = <>x</>;"
+            ),
+            "/** @jsx h @jsxFrag Frag */\nconst p = h(\"p\", null, \"Hello, world\");\nconst f = h(Frag, null, \"x\");\n"
+        );
+    }
+
+    #[test]
+    fn pass_lowers_only_jsx_sources_and_leaves_broken_jsx_for_the_llm() {
+        let pass = JsxPass {
+            pragma: "h".to_string(),
+            fragment: "Fragment".to_string(),
+        };
+        let request = |source_id: &str, source: &str| CompileRequest {
+            source_text: source.to_string(),
+            source_id: source_id.to_string(),
+            kind_hint: None,
+            language_hint: None,
+            scope_context: None,
+            force_llm: false,
+            provider_selection: klumo_llm::ProviderSelection::Auto,
+            provider_override: None,
+            model_override: None,
+            model_override_for: None,
+            sampling: klumo_llm::SamplingOptions::default(),
+            structured_output: false,
+            explain: false,
+            context_window: None,
+            no_cache: false,
+        };
+
+        let mut jsx = request("app.jsx", "render(<b>hi</b>);");
+        pass.prepare(&mut jsx).expect("jsx should prepare");
+        assert_eq!(jsx.source_text, "render(h(\"b\", null, \"hi\"));\n");
+        assert_eq!(jsx.kind_hint, Some(SourceKind::JavaScript));
+
+        let mut tsx = request("app.tsx", "const id = <T,>(x: T) => <i>{x}</i>;");
+        pass.prepare(&mut tsx).expect("tsx should prepare");
+        assert_eq!(tsx.source_text, "const id = (x) => h(\"i\", null, x);\n");
+        assert_eq!(tsx.kind_hint, Some(SourceKind::JavaScript));
+
+        let mut plain = request("app.js", "a <b> c");
+        assert!(!pass.applies(&plain));
+        pass.prepare(&mut plain)
+            .expect("plain js should pass through");
+        assert_eq!(plain.source_text, "a <b> c");
+        let passes: Vec<Box<dyn CompilePass>> = vec![Box::new(pass.clone())];
+        assert_eq!(crate::pipeline_fingerprint(&passes, &plain), "");
+        assert_eq!(
+            crate::pipeline_fingerprint(&passes, &request("app.tsx", "")),
+            "jsx(h,Fragment)"
+        );
+
+        let mut broken = request("app.jsx", "render(<b>hi);");
+        pass.prepare(&mut broken)
+            .expect("broken jsx should be left for the llm");
+        assert_eq!(broken.source_text, "render(<b>hi);");
+        assert_eq!(broken.kind_hint, None);
+    }
+}
@@ -1,11 +1,75 @@
-use anyhow::{Context, Result};
-use klumo_llm::{LlmTranslateRequest, Provider, ProviderSelection, TranslationService};
+mod approval;
+mod cache_gc;
+mod cache_key;
+mod detect;
+mod embedding;
+mod graph;
+mod hit_rate;
+mod http_cache;
+mod incremental;
+mod jsx;
+mod minify;
+mod output;
+mod passes;
+mod python;
+mod repair;
+mod signing;
+mod source_map;
+mod typescript;
+
+pub use approval::{PendingStore, PendingTranslation};
+pub use cache_gc::{CachePolicy, GcReport, LRU_INDEX_FILE};
+pub use cache_key::{CacheKeyInput, CacheKeyStrategy, Sha256CacheKey};
+pub use detect::{LANGUAGES_DIR, LanguageCache, detect_language};
+pub use embedding::{
+    DEFAULT_SIMILARITY_THRESHOLD, EMBEDDINGS_DIR, Embedder, EmbeddingIndex, cosine_similarity,
+};
+pub use graph::{
+    GraphModule, LINKED_HEADER, MODULE_EXTENSIONS, ModuleGraph, ModuleImport, OutputTarget,
+    build_module_graph, build_module_graph_batched, is_linked, relative_specifiers,
+    resolve_relative_import,
+};
+pub use hit_rate::{
+    CacheHitLog, CacheLookup, DEFAULT_HIT_RATE_WINDOW, HIT_LOG_CAPACITY, rolling_hit_rate,
+};
+pub use http_cache::{HTTP_CACHE_TIMEOUT, HttpCompileCache};
+pub use incremental::{CHUNKS_DIR, ChunkEntry, ChunkMap, ChunkMapStore, unit_hash};
+pub use jsx::{DEFAULT_JSX_FRAGMENT, DEFAULT_JSX_PRAGMA, JsxPass, lower_jsx};
+pub use minify::{MinifyPass, minify};
+pub use output::{OutputOptions, finish_output, provenance_banner, utc_timestamp};
+pub use passes::{CompilePass, SyntaxCheckPass, pipeline_fingerprint};
+pub use python::transpile_python;
+pub use repair::RepairRequest;
+pub use signing::{
+    SignaturePolicy, Signed, SigningKey, TrustedKeys, signature_path, verify_signature_file,
+};
+pub use source_map::{OriginalLine, SourceMap, base64};
+pub use typescript::transpile_typescript;
+
+use anyhow::{Context, Result, anyhow};
+use incremental::chunk_map_key;
+use klumo_engine::check_syntax;
+use klumo_llm::{
+    Explanation, LlmTranslateRequest, LlmTranslateResponse, PromptTemplates, Provider,
+    ProviderDescriptor, ProviderSelection, SamplingOptions, SourceChunk, TranslationService,
+    UsageEvent, UsageLog, UsageOutcome, chunk_budget, split_source, split_units,
+    split_units_within,
+};
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
+use similar::TextDiff;
 use std::fs;
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::SystemTime;
 
-pub const PROMPT_VERSION: &str = "m1-v1";
+pub const PROMPT_VERSION: &str = "m1-v3";
+
+/// Default number of repair prompts sent when a provider returns JavaScript that fails to
+/// parse; see [`CompilerRouter::syntax_repair_attempts`].
+pub const VALIDATION_RETRIES: usize = 2;
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SourceKind {
@@ -32,6 +96,15 @@ impl SourceKind {
         Self::Unknown("unknown".to_string())
     }
 
+    /// [`Self::infer_from_source_id`], except that a source id without an extension, or with
+    /// a plain-text one such as `.txt`, is judged by its text with [`detect_language`].
+    pub fn infer(source_id: &str, source_text: &str) -> Self {
+        detect::sniffs_content(source_id)
+            .then(|| detect_language(source_text))
+            .flatten()
+            .unwrap_or_else(|| Self::infer_from_source_id(source_id))
+    }
+
     pub fn as_hint(&self) -> String {
         match self {
             Self::JavaScript => "javascript".to_string(),
@@ -51,26 +124,67 @@ pub struct CompileRequest {
     pub scope_context: Option<String>,
     pub force_llm: bool,
     pub provider_selection: ProviderSelection,
+    /// Routes this compile through a different provider than the session's `provider_selection`.
+    pub provider_override: Option<ProviderSelection>,
     pub model_override: Option<String>,
+    /// The one provider `model_override` is meant for, as with a [`ModelTier`]; the rest of
+    /// the chain keeps its own models. `None` gives the model to every provider.
+    pub model_override_for: Option<Provider>,
+    pub sampling: SamplingOptions,
+    /// Request a JSON response carrying notes and a confidence score alongside the code.
+    pub structured_output: bool,
+    /// Also request an [`Explanation`] of the translation, returned in
+    /// [`CompileMetadata::explanation`] and stored with the cache entry. A cached entry without
+    /// one is translated again.
+    pub explain: bool,
+    /// Model context window in tokens. Sources estimated to overflow it are split with
+    /// `split_source` and translated chunk by chunk; `None` always sends the whole source.
+    pub context_window: Option<usize>,
     pub no_cache: bool,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct CompileMetadata {
     pub provider: Option<Provider>,
     pub model: Option<String>,
+    /// Model that actually answered when the endpoint fell back from `model`.
+    pub served_model: Option<String>,
     pub prompt_version: String,
     pub cache_hit: bool,
+    /// Translator remarks from a structured response.
+    pub notes: Option<String>,
+    /// Self-reported confidence in `0.0..=1.0` from a structured response.
+    pub confidence: Option<f32>,
+    /// Rationale and construct mapping, when the compile was requested with `explain`.
+    pub explanation: Option<Explanation>,
+    /// Hex ed25519 signature over `javascript` by the project signing key.
+    pub signature: Option<String>,
+    /// Cosine similarity to the earlier source whose cached translation was reused, when an
+    /// [`EmbeddingIndex`] served the compile rather than an exact cache hit.
+    pub similarity: Option<f32>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct CompileResult {
     pub javascript: String,
     pub metadata: CompileMetadata,
+    /// Where each line of `javascript` came from in the source; set by `CompilerRouter`.
+    pub source_map: Option<SourceMap>,
 }
 
 pub trait Compiler {
     fn compile(&self, req: &CompileRequest) -> Result<CompileResult>;
+
+    /// Compiles every request in `reqs`, returning one result per request in the same order,
+    /// with at most `jobs` of them compiling at once. The default compiles them one after
+    /// another.
+    fn compile_many(&self, reqs: &[CompileRequest], jobs: usize) -> Vec<Result<CompileResult>>
+    where
+        Self: Sync,
+    {
+        let _ = jobs;
+        reqs.iter().map(|req| self.compile(req)).collect()
+    }
 }
 
 pub trait CompileCache {
@@ -78,9 +192,20 @@ pub trait CompileCache {
     fn put(&self, key: &str, result: &CompileResult) -> Result<()>;
 }
 
+impl<C: CompileCache + ?Sized> CompileCache for Box<C> {
+    fn get(&self, key: &str) -> Option<CompileResult> {
+        (**self).get(key)
+    }
+
+    fn put(&self, key: &str, result: &CompileResult) -> Result<()> {
+        (**self).put(key, result)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct FileCompileCache {
     root: PathBuf,
+    policy: CachePolicy,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -88,59 +213,204 @@ struct CachedResult {
     javascript: String,
     provider: Option<String>,
     model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    served_model: Option<String>,
     prompt_version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    notes: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    confidence: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    explanation: Option<Explanation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signature: Option<String>,
+}
+
+impl CachedResult {
+    fn from_result(result: &CompileResult) -> Self {
+        Self {
+            javascript: result.javascript.clone(),
+            provider: result.metadata.provider.as_ref().map(format_provider),
+            model: result.metadata.model.clone(),
+            served_model: result.metadata.served_model.clone(),
+            prompt_version: result.metadata.prompt_version.clone(),
+            notes: result.metadata.notes.clone(),
+            confidence: result.metadata.confidence,
+            explanation: result.metadata.explanation.clone(),
+            signature: result.metadata.signature.clone(),
+        }
+    }
+
+    /// The cached compile, marked as a cache hit.
+    fn into_result(self) -> CompileResult {
+        CompileResult {
+            javascript: self.javascript,
+            metadata: CompileMetadata {
+                provider: self.provider.as_deref().map(parse_provider),
+                model: self.model,
+                served_model: self.served_model,
+                prompt_version: self.prompt_version,
+                cache_hit: true,
+                notes: self.notes,
+                confidence: self.confidence,
+                explanation: self.explanation,
+                signature: self.signature,
+                similarity: None,
+            },
+            source_map: None,
+        }
+    }
+}
+
+/// Translation details stored alongside a cached compile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheRecord {
+    pub provider: Option<Provider>,
+    pub model: Option<String>,
+    pub prompt_version: String,
+}
+
+/// One file in a [`FileCompileCache`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheEntry {
+    pub key: String,
+    pub path: PathBuf,
+    pub size_bytes: u64,
+    pub modified: Option<SystemTime>,
+    /// `None` when the payload could not be decoded; `get` treats such entries as misses.
+    pub record: Option<CacheRecord>,
 }
 
+/// Environment variable naming the compile cache directory in place of the default.
+pub const CACHE_DIR_ENV: &str = "KLUMO_CACHE_DIR";
+
 impl FileCompileCache {
+    /// [`CACHE_DIR_ENV`] when set, else `~/.klumo/cache/compile`. The CLI opens a project's
+    /// `cache_dir` from `klumo.json` with [`FileCompileCache::new`] unless the variable is set.
     pub fn default_root() -> Result<PathBuf> {
+        if let Some(dir) = std::env::var_os(CACHE_DIR_ENV).filter(|dir| !dir.is_empty()) {
+            return Ok(PathBuf::from(dir));
+        }
         let home = dirs::home_dir().context("failed to resolve home directory")?;
         Ok(home.join(".klumo").join("cache").join("compile"))
     }
 
     pub fn new(root: PathBuf) -> Self {
-        Self { root }
+        Self {
+            root,
+            policy: CachePolicy::default(),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.root.join(format!("{key}.json"))
+    }
+
+    /// Lists every cached compile, sorted by key. A missing cache dir yields no entries.
+    pub fn entries(&self) -> Result<Vec<CacheEntry>> {
+        let dir = match fs::read_dir(&self.root) {
+            Ok(dir) => dir,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("failed reading cache dir {}", self.root.display()));
+            }
+        };
+
+        let mut entries = Vec::new();
+        for item in dir {
+            let item =
+                item.with_context(|| format!("failed reading cache dir {}", self.root.display()))?;
+            let path = item.path();
+            let Some(key) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".json"))
+            else {
+                continue;
+            };
+            if let Some(entry) = self.metadata(key)? {
+                entries.push(entry);
+            }
+        }
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(entries)
+    }
+
+    /// Describes the entry stored under `key`, or `None` when there is no such entry.
+    pub fn metadata(&self, key: &str) -> Result<Option<CacheEntry>> {
+        let path = self.entry_path(key);
+        let stat = match fs::metadata(&path) {
+            Ok(stat) => stat,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed reading {}", path.display()));
+            }
+        };
+        let record = fs::read_to_string(&path)
+            .ok()
+            .and_then(|raw| serde_json::from_str::<CachedResult>(&raw).ok())
+            .map(|parsed| CacheRecord {
+                provider: parsed.provider.as_deref().map(parse_provider),
+                model: parsed.model,
+                prompt_version: parsed.prompt_version,
+            });
+
+        Ok(Some(CacheEntry {
+            key: key.to_string(),
+            size_bytes: stat.len(),
+            modified: stat.modified().ok(),
+            path,
+            record,
+        }))
+    }
+
+    /// Deletes the entry stored under `key`; returns whether anything was removed.
+    pub fn remove(&self, key: &str) -> Result<bool> {
+        let path = self.entry_path(key);
+        match fs::remove_file(&path) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err).with_context(|| format!("failed removing {}", path.display())),
+        }
     }
 }
 
 impl Default for FileCompileCache {
     fn default() -> Self {
         let root = Self::default_root().unwrap_or_else(|_| PathBuf::from(".klumo-cache"));
-        Self { root }
+        Self::new(root)
     }
 }
 
 impl CompileCache for FileCompileCache {
     fn get(&self, key: &str) -> Option<CompileResult> {
-        let path = self.root.join(format!("{key}.json"));
+        let path = self.entry_path(key);
         let raw = fs::read_to_string(path).ok()?;
         let parsed: CachedResult = serde_json::from_str(&raw).ok()?;
-
-        Some(CompileResult {
-            javascript: parsed.javascript,
-            metadata: CompileMetadata {
-                provider: parsed.provider.as_deref().map(parse_provider),
-                model: parsed.model,
-                prompt_version: parsed.prompt_version,
-                cache_hit: true,
-            },
-        })
+        // Recency only steers eviction; failing to record it must not turn a hit into a miss.
+        let _ = self.touch(key);
+        Some(parsed.into_result())
     }
 
     fn put(&self, key: &str, result: &CompileResult) -> Result<()> {
         fs::create_dir_all(&self.root)
             .with_context(|| format!("failed creating cache dir {}", self.root.display()))?;
-        let path = self.root.join(format!("{key}.json"));
+        let path = self.entry_path(key);
+        let replaced = fs::metadata(&path).map_or(0, |stat| stat.len());
 
-        let payload = CachedResult {
-            javascript: result.javascript.clone(),
-            provider: result.metadata.provider.map(format_provider),
-            model: result.metadata.model.clone(),
-            prompt_version: result.metadata.prompt_version.clone(),
-        };
-
-        let raw =
-            serde_json::to_string_pretty(&payload).context("failed serializing cache payload")?;
-        fs::write(path, raw).context("failed writing cache file")?;
+        let raw = serde_json::to_string_pretty(&CachedResult::from_result(result))
+            .context("failed serializing cache payload")?;
+        fs::write(path, &raw).context("failed writing cache file")?;
+        let _ = self.touch(key);
+        if self.policy.is_bounded() {
+            // Best effort: the entry is written, and the next `put` or `klumo cache gc` retries.
+            let _ = self.record_put(raw.len() as u64, replaced);
+        }
         Ok(())
     }
 }
@@ -152,6 +422,102 @@ where
 {
     pub translator: T,
     pub cache: C,
+    /// Models picked by source size and language when the request has no model override.
+    pub model_tiers: Vec<ModelTier>,
+    /// Provider and model per language hint; they fill overrides the request leaves unset.
+    pub language_routes: Vec<LanguageRoute>,
+    pub signatures: SignaturePolicy,
+    /// `approval_required`: fresh translations wait here for review instead of entering the
+    /// cache, and only approved (cached) translations run.
+    pub approval: Option<PendingStore>,
+    /// Records whether each translated compile was a cache hit, for `klumo stats`.
+    pub hit_log: Option<CacheHitLog>,
+    /// Opt-in local usage telemetry; compile cache hits count towards the provider's hit ratio.
+    pub usage: Option<UsageLog>,
+    /// Derives cache keys from requests; `Sha256CacheKey` unless an embedder swaps it.
+    pub cache_keys: Box<dyn CacheKeyStrategy>,
+    /// Transformations run on every request and its output, in order; see [`CompilePass`].
+    pub passes: Vec<Box<dyn CompilePass>>,
+    /// Translate every cache miss twice and fail with a diff when the outputs differ, so only
+    /// reproducible translations are returned and cached.
+    pub deterministic: bool,
+    /// Times a translation that does not parse is sent back with its parse error and code
+    /// before the compile fails; [`VALIDATION_RETRIES`] by default.
+    pub syntax_repair_attempts: usize,
+    /// Incremental mode: a cache miss of a file with a [`ChunkMap`] here is translated unit by
+    /// unit (see `split_units`, with units over the context window split further), and units
+    /// whose text is unchanged since the file's last compile reuse their JavaScript from the
+    /// map instead of being translated again. A file without a map is translated whole.
+    pub incremental: Option<ChunkMapStore>,
+    /// For `SourceKind::Auto` sources that neither their extension nor [`detect_language`]
+    /// identifies, ask `TranslationService::classify_language` before translating.
+    pub classify_language: bool,
+    /// Remembers the languages `classify_language` named, so cache hits make no extra call.
+    pub languages: Option<LanguageCache>,
+    /// Python sources within the subset [`transpile_python`] handles are transpiled locally
+    /// instead of translated; the rest still go to a provider.
+    pub python_fast_path: bool,
+    /// Experimental: cache misses reuse the translation of a cached source with a similar
+    /// embedding, and fresh translations are indexed for later ones.
+    pub embeddings: Option<EmbeddingIndex>,
+    /// Infer where the lines of translated and transpiled output came from (see
+    /// [`SourceMap::infer`]). Off by default: only output identical to its source gets a map.
+    pub source_maps: bool,
+}
+
+/// A model used in place of the provider's configured one for the sources it matches, e.g. a
+/// small fast model for short snippets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelTier {
+    pub model: String,
+    /// The provider the model belongs to; `None` means the first provider the source would be
+    /// sent to. Other providers of the chain keep their own models.
+    pub provider: Option<ProviderSelection>,
+    /// Matches sources of at most this many bytes; `None` matches any size.
+    pub max_source_bytes: Option<usize>,
+    /// Language hints the tier applies to, case-insensitive; empty matches every language.
+    pub languages: Vec<String>,
+}
+
+impl ModelTier {
+    fn matches(&self, source_text: &str, language: &str) -> bool {
+        self.max_source_bytes
+            .is_none_or(|max| source_text.len() <= max)
+            && (self.languages.is_empty()
+                || self
+                    .languages
+                    .iter()
+                    .any(|lang| lang.eq_ignore_ascii_case(language)))
+    }
+}
+
+/// First tier in `tiers` matching the source, in declaration order.
+pub fn select_model_tier<'a>(
+    tiers: &'a [ModelTier],
+    source_text: &str,
+    language: &str,
+) -> Option<&'a ModelTier> {
+    tiers
+        .iter()
+        .find(|tier| tier.matches(source_text, language))
+}
+
+/// The provider and/or model every source with `language` as its hint is translated with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LanguageRoute {
+    pub language: String,
+    pub provider: Option<ProviderSelection>,
+    pub model: Option<String>,
+}
+
+/// The route for `language`, compared case-insensitively.
+pub fn select_language_route<'a>(
+    routes: &'a [LanguageRoute],
+    language: &str,
+) -> Option<&'a LanguageRoute> {
+    routes
+        .iter()
+        .find(|route| route.language.eq_ignore_ascii_case(language))
 }
 
 impl<T, C> CompilerRouter<T, C>
@@ -159,37 +525,706 @@ where
     T: TranslationService,
     C: CompileCache,
 {
+    /// A router translating with `translator` and caching in `cache`, with every option at
+    /// its default; the `with_*` methods set them.
+    pub fn new(translator: T, cache: C) -> Self {
+        Self {
+            translator,
+            cache,
+            model_tiers: Vec::new(),
+            language_routes: Vec::new(),
+            signatures: SignaturePolicy::Off,
+            approval: None,
+            hit_log: None,
+            usage: None,
+            cache_keys: Box::new(Sha256CacheKey),
+            passes: Vec::new(),
+            deterministic: false,
+            syntax_repair_attempts: VALIDATION_RETRIES,
+            incremental: None,
+            classify_language: false,
+            languages: None,
+            python_fast_path: false,
+            embeddings: None,
+            source_maps: false,
+        }
+    }
+
+    pub fn with_model_tiers(mut self, model_tiers: Vec<ModelTier>) -> Self {
+        self.model_tiers = model_tiers;
+        self
+    }
+
+    pub fn with_language_routes(mut self, language_routes: Vec<LanguageRoute>) -> Self {
+        self.language_routes = language_routes;
+        self
+    }
+
+    pub fn with_signatures(mut self, signatures: SignaturePolicy) -> Self {
+        self.signatures = signatures;
+        self
+    }
+
+    pub fn with_approval(mut self, approval: Option<PendingStore>) -> Self {
+        self.approval = approval;
+        self
+    }
+
+    pub fn with_hit_log(mut self, hit_log: Option<CacheHitLog>) -> Self {
+        self.hit_log = hit_log;
+        self
+    }
+
+    pub fn with_usage(mut self, usage: Option<UsageLog>) -> Self {
+        self.usage = usage;
+        self
+    }
+
+    pub fn with_cache_keys(mut self, cache_keys: impl CacheKeyStrategy + 'static) -> Self {
+        self.cache_keys = Box::new(cache_keys);
+        self
+    }
+
+    pub fn with_passes(mut self, passes: Vec<Box<dyn CompilePass>>) -> Self {
+        self.passes = passes;
+        self
+    }
+
+    pub fn with_deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    pub fn with_syntax_repair_attempts(mut self, attempts: usize) -> Self {
+        self.syntax_repair_attempts = attempts;
+        self
+    }
+
+    pub fn with_incremental(mut self, incremental: Option<ChunkMapStore>) -> Self {
+        self.incremental = incremental;
+        self
+    }
+
+    pub fn with_classify_language(mut self, classify_language: bool) -> Self {
+        self.classify_language = classify_language;
+        self
+    }
+
+    pub fn with_language_cache(mut self, languages: Option<LanguageCache>) -> Self {
+        self.languages = languages;
+        self
+    }
+
+    pub fn with_python_fast_path(mut self, python_fast_path: bool) -> Self {
+        self.python_fast_path = python_fast_path;
+        self
+    }
+
+    pub fn with_embeddings(mut self, embeddings: Option<EmbeddingIndex>) -> Self {
+        self.embeddings = embeddings;
+        self
+    }
+
+    pub fn with_source_maps(mut self, source_maps: bool) -> Self {
+        self.source_maps = source_maps;
+        self
+    }
+
     fn contains_jsr_specifier(source_text: &str) -> bool {
         source_text.contains("jsr:")
     }
 
+    /// The kind behind `SourceKind::Auto`: by extension, by content, and finally, with
+    /// [`CompilerRouter::classify_language`] set, as named by the translator, once per source
+    /// when [`CompilerRouter::languages`] remembers the answers.
     fn resolved_kind(&self, req: &CompileRequest) -> SourceKind {
         match req.kind_hint.clone().unwrap_or(SourceKind::Auto) {
-            SourceKind::Auto => SourceKind::infer_from_source_id(&req.source_id),
+            SourceKind::Auto
+                if self.classify_language && detect::sniffs_content(&req.source_id) =>
+            {
+                detect_language(&req.source_text)
+                    .or_else(|| {
+                        let cached = self
+                            .languages
+                            .as_ref()
+                            .and_then(|languages| languages.get(&req.source_text));
+                        let name = cached.or_else(|| {
+                            let name = self.translator.classify_language(
+                                req.provider_override
+                                    .clone()
+                                    .unwrap_or(req.provider_selection.clone()),
+                                &req.source_text,
+                            )?;
+                            if let Some(languages) = &self.languages {
+                                let _ = languages.put(&req.source_text, &name);
+                            }
+                            Some(name)
+                        })?;
+                        Some(SourceKind::from_hint(&name))
+                    })
+                    .unwrap_or_else(|| SourceKind::infer_from_source_id(&req.source_id))
+            }
+            SourceKind::Auto => SourceKind::infer(&req.source_id, &req.source_text),
             explicit => explicit,
         }
     }
 
+    /// [`Self::resolved_kind`] when working it out takes no classifier call, `None` when it
+    /// would ask a provider.
+    fn kind_without_classifying(&self, req: &CompileRequest) -> Option<SourceKind> {
+        match req.kind_hint.clone().unwrap_or(SourceKind::Auto) {
+            SourceKind::Auto
+                if self.classify_language && detect::sniffs_content(&req.source_id) =>
+            {
+                detect_language(&req.source_text).or_else(|| {
+                    let name = self.languages.as_ref()?.get(&req.source_text)?;
+                    Some(SourceKind::from_hint(&name))
+                })
+            }
+            _ => Some(self.resolved_kind(req)),
+        }
+    }
+
+    /// The selections [`Compiler::compile`] may translate `req` through: its provider
+    /// override, else the provider of the language route it lands on, else the session's
+    /// selection. When its language is not known without a classifier call, every route's
+    /// provider counts.
+    fn possible_selections(&self, req: &CompileRequest) -> Vec<ProviderSelection> {
+        if let Some(selection) = &req.provider_override {
+            return vec![selection.clone()];
+        }
+        let routed = self.prepare(req).ok().and_then(|prepared| {
+            let kind_hint = match prepared.language_hint {
+                Some(hint) => hint,
+                None => self.kind_without_classifying(&prepared)?.as_hint(),
+            };
+            Some(
+                select_language_route(&self.language_routes, &kind_hint)
+                    .and_then(|route| route.provider.clone()),
+            )
+        });
+        match routed {
+            Some(provider) => vec![provider.unwrap_or_else(|| req.provider_selection.clone())],
+            None => self
+                .language_routes
+                .iter()
+                .filter_map(|route| route.provider.clone())
+                .chain([req.provider_selection.clone()])
+                .collect(),
+        }
+    }
+
+    /// Translates `llm_req` with `req`'s model override, given to the provider it is for.
+    fn translate(
+        &self,
+        selection: ProviderSelection,
+        llm_req: &LlmTranslateRequest,
+        req: &CompileRequest,
+    ) -> Result<LlmTranslateResponse> {
+        match (&req.model_override, &req.model_override_for) {
+            (Some(model), Some(provider)) => self
+                .translator
+                .translate_for_provider(selection, llm_req, provider, model),
+            (model, _) => self
+                .translator
+                .translate(selection, llm_req, model.as_deref()),
+        }
+    }
+
+    /// The (provider, model) pairs that could answer `req`, as
+    /// [`TranslationService::resolved_chain`] but with a provider's own override kept to it.
+    fn resolved_chain(
+        &self,
+        selection: ProviderSelection,
+        req: &CompileRequest,
+    ) -> Vec<ProviderDescriptor> {
+        let (Some(model), Some(provider)) = (&req.model_override, &req.model_override_for) else {
+            return self
+                .translator
+                .resolved_chain(selection, req.model_override.as_deref());
+        };
+        let mut chain: Vec<ProviderDescriptor> = Vec::new();
+        for mut entry in self.translator.resolved_chain(selection, None) {
+            if entry.provider == *provider && entry.provider != Provider::Fixture {
+                entry.model = model.clone();
+            }
+            if !chain.contains(&entry) {
+                chain.push(entry);
+            }
+        }
+        chain
+    }
+
+    /// Translates `req` and parses the output before it can be cached or executed, sending
+    /// repair prompts up to [`CompilerRouter::syntax_repair_attempts`] times.
+    fn translate_validated(
+        &self,
+        req: &CompileRequest,
+        selection: ProviderSelection,
+        kind_hint: &str,
+    ) -> Result<LlmTranslateResponse> {
+        let mut llm_req = translation_request(req, kind_hint);
+        let translated = self.translate(selection.clone(), &llm_req, req)?;
+        self.repair_syntax(&mut llm_req, selection, req, translated)
+    }
+
+    /// Returns `translated` once its JavaScript parses. Until then, `llm_req` is sent again
+    /// carrying a [`RepairRequest`] with the parse error and the code, up to
+    /// `syntax_repair_attempts` times.
+    fn repair_syntax(
+        &self,
+        llm_req: &mut LlmTranslateRequest,
+        selection: ProviderSelection,
+        req: &CompileRequest,
+        mut translated: LlmTranslateResponse,
+    ) -> Result<LlmTranslateResponse> {
+        let mut attempt = 0;
+        loop {
+            let Some(diagnostic) = check_syntax(&translated.javascript).into_iter().next() else {
+                return Ok(translated);
+            };
+            let error_text = diagnostic.to_string();
+            if attempt >= self.syntax_repair_attempts {
+                return Err(anyhow!(
+                    "{} returned JavaScript that does not parse after {attempt} repair attempt(s): {}",
+                    translated.provider,
+                    error_text
+                ));
+            }
+            attempt += 1;
+            llm_req.repair_hint = Some(
+                RepairRequest {
+                    file: &req.source_id,
+                    error_label: "Syntax error",
+                    error_text: &error_text,
+                    source: &translated.javascript,
+                    describe_change: false,
+                }
+                .render(),
+            );
+            translated = self.translate(selection.clone(), llm_req, req)?;
+        }
+    }
+
+    /// Translates an oversized source chunk by chunk, showing each request the JavaScript
+    /// produced so far, and joins the parts into one program.
+    fn translate_chunked(
+        &self,
+        req: &CompileRequest,
+        selection: ProviderSelection,
+        kind_hint: &str,
+        chunks: &[SourceChunk],
+    ) -> Result<LlmTranslateResponse> {
+        let mut javascript = String::new();
+        let mut parts = Vec::with_capacity(chunks.len());
+        let mut notes = Vec::new();
+        let mut confidence: Option<f32> = None;
+        let mut explanation: Option<Explanation> = None;
+        let mut last = None;
+        for chunk in chunks {
+            let part_req = CompileRequest {
+                source_text: chunk.text.clone(),
+                scope_context: Some(chunk.scope_context(
+                    &req.source_id,
+                    req.scope_context.as_deref(),
+                    &javascript,
+                )),
+                ..req.clone()
+            };
+            let part = self
+                .translate_validated(&part_req, selection.clone(), kind_hint)
+                .with_context(|| {
+                    format!(
+                        "failed translating part {} of {} of {}",
+                        chunk.index + 1,
+                        chunk.total,
+                        req.source_id
+                    )
+                })?;
+            if !javascript.is_empty() && !javascript.ends_with('\n') {
+                javascript.push('\n');
+            }
+            javascript.push_str(&part.javascript);
+            parts.push(part.javascript.clone());
+            notes.extend(part.notes.clone());
+            confidence = match (confidence, part.confidence) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+            if let Some(part_explanation) = part.explanation.clone() {
+                let merged = explanation.get_or_insert_with(Explanation::default);
+                if !merged.rationale.is_empty() {
+                    merged.rationale.push('\n');
+                }
+                merged.rationale.push_str(&part_explanation.rationale);
+                merged.mappings.extend(part_explanation.mappings);
+            }
+            last = Some(part);
+        }
+
+        let last = last.ok_or_else(|| anyhow!("{} produced no chunks", req.source_id))?;
+        Ok(LlmTranslateResponse {
+            javascript: self.stitch(req, selection, kind_hint, chunks, &mut parts)?,
+            notes: (!notes.is_empty()).then(|| notes.join("\n")),
+            confidence,
+            explanation,
+            ..last
+        })
+    }
+
+    /// Translates `units` of `req`, taking the JavaScript of every unit whose text is in the
+    /// file's `previous` chunk map from there, and returns the map to replace it. Units translated here see
+    /// the JavaScript before them like chunks do; reused ones keep what they were translated
+    /// against, so an edit that renames something other units use needs a `no_cache` compile
+    /// to reach them.
+    fn translate_incremental(
+        &self,
+        req: &CompileRequest,
+        selection: ProviderSelection,
+        kind_hint: &str,
+        units: &[SourceChunk],
+        previous: &ChunkMap,
+    ) -> Result<(LlmTranslateResponse, ChunkMap)> {
+        let mut javascript = String::new();
+        let mut entries: Vec<ChunkEntry> = Vec::with_capacity(units.len());
+        for unit in units {
+            let source_hash = unit_hash(&unit.text);
+            let entry = match previous.find(&source_hash) {
+                Some(entry) => entry.clone(),
+                None => {
+                    let unit_req = CompileRequest {
+                        source_text: unit.text.clone(),
+                        scope_context: Some(unit.scope_context(
+                            &req.source_id,
+                            req.scope_context.as_deref(),
+                            &javascript,
+                        )),
+                        ..req.clone()
+                    };
+                    let part = self
+                        .translate_validated(&unit_req, selection.clone(), kind_hint)
+                        .with_context(|| {
+                            format!(
+                                "failed translating part {} of {} of {}",
+                                unit.index + 1,
+                                unit.total,
+                                req.source_id
+                            )
+                        })?;
+                    ChunkEntry {
+                        source_hash,
+                        javascript: part.javascript,
+                        provider: format_provider(&part.provider),
+                        model: part.model,
+                    }
+                }
+            };
+            if !javascript.is_empty() && !javascript.ends_with('\n') {
+                javascript.push('\n');
+            }
+            javascript.push_str(&entry.javascript);
+            entries.push(entry);
+        }
+
+        let mut parts: Vec<String> = entries
+            .iter()
+            .map(|entry| entry.javascript.clone())
+            .collect();
+        let javascript = self.stitch(req, selection, kind_hint, units, &mut parts)?;
+        for (entry, part) in entries.iter_mut().zip(parts) {
+            entry.javascript = part;
+        }
+        let last = entries
+            .last()
+            .ok_or_else(|| anyhow!("{} produced no units", req.source_id))?;
+        let stitched = LlmTranslateResponse {
+            javascript,
+            provider: parse_provider(&last.provider),
+            model: last.model.clone(),
+            served_model: None,
+            notes: None,
+            confidence: None,
+            explanation: None,
+        };
+        Ok((stitched, ChunkMap { units: entries }))
+    }
+
+    /// Joins the JavaScript translated for each of `chunks` into one program. Parts parse on
+    /// their own, but may clash once joined (a binding declared twice, say). The first part
+    /// that breaks the join is translated again from its chunk, shown the error and the
+    /// JavaScript before it like any later chunk, up to `syntax_repair_attempts` times; no
+    /// prompt holds more than one part, and a part that still does not fit fails the compile.
+    fn stitch(
+        &self,
+        req: &CompileRequest,
+        selection: ProviderSelection,
+        kind_hint: &str,
+        chunks: &[SourceChunk],
+        parts: &mut [String],
+    ) -> Result<String> {
+        let joined = join_parts(parts);
+        if check_syntax(&joined).is_empty() {
+            return Ok(joined);
+        }
+        let mut javascript = String::new();
+        for (chunk, part) in chunks.iter().zip(parts.iter_mut()) {
+            let mut attempt = 0;
+            loop {
+                let candidate = join_parts(&[javascript.clone(), part.clone()]);
+                let Some(mut diagnostic) = check_syntax(&candidate).into_iter().next() else {
+                    javascript = candidate;
+                    break;
+                };
+                // Point at the line within this part rather than the joined program.
+                let offset = u32::try_from(candidate.lines().count() - part.lines().count())
+                    .unwrap_or(u32::MAX);
+                diagnostic.position = diagnostic
+                    .position
+                    .filter(|position| position.line > offset)
+                    .map(|mut position| {
+                        position.line -= offset;
+                        position
+                    });
+                if attempt >= self.syntax_repair_attempts {
+                    return Err(anyhow!(
+                        "translation of {} does not parse where part {} of {} joins the parts before it, after {attempt} repair attempt(s): {diagnostic}",
+                        req.source_id,
+                        chunk.index + 1,
+                        chunk.total
+                    ));
+                }
+                attempt += 1;
+                let part_req = CompileRequest {
+                    source_text: chunk.text.clone(),
+                    scope_context: Some(chunk.scope_context(
+                        &req.source_id,
+                        req.scope_context.as_deref(),
+                        &javascript,
+                    )),
+                    ..req.clone()
+                };
+                let mut llm_req = translation_request(&part_req, kind_hint);
+                llm_req.repair_hint = Some(
+                    RepairRequest {
+                        file: &req.source_id,
+                        error_label: "Syntax error once joined to the JavaScript before it",
+                        error_text: &diagnostic.to_string(),
+                        source: part,
+                        describe_change: false,
+                    }
+                    .render(),
+                );
+                *part = self.translate(selection.clone(), &llm_req, req)?.javascript;
+            }
+        }
+        Ok(javascript)
+    }
+
+    /// Whether a cached compile may serve `req`: signed by a trusted key under `Verify`.
+    fn usable(&self, req: &CompileRequest, cached: &CompileResult) -> bool {
+        if let SignaturePolicy::Verify(keys) = &self.signatures
+            && !cached
+                .metadata
+                .signature
+                .as_deref()
+                .is_some_and(|signature| {
+                    keys.verify(
+                        Signed::Translation(&req.source_text),
+                        &cached.javascript,
+                        signature,
+                    )
+                })
+        {
+            return false;
+        }
+        true
+    }
+
+    /// `cached`, stored under `key`, with an explanation when `req` asks for one and it has
+    /// none yet. The provider is shown the cached JavaScript and only the explanation it gives
+    /// is kept, so explaining a compile never changes the code that runs.
+    fn explain_cached(
+        &self,
+        req: &CompileRequest,
+        selection: ProviderSelection,
+        kind_hint: &str,
+        key: &str,
+        mut cached: CompileResult,
+    ) -> Result<CompileResult> {
+        if !req.explain || cached.metadata.explanation.is_some() {
+            return Ok(cached);
+        }
+        let llm_req = LlmTranslateRequest {
+            source_text: req.source_text.clone(),
+            source_id: req.source_id.clone(),
+            language_hint: Some(kind_hint.to_string()),
+            scope_context: req.scope_context.clone(),
+            repair_hint: None,
+            temperature: req.sampling.temperature,
+            top_p: req.sampling.top_p,
+            max_tokens: req.sampling.max_tokens,
+            seed: req.sampling.seed,
+            structured_output: true,
+            explain: true,
+            explain_javascript: Some(cached.javascript.clone()),
+            prompts: PromptTemplates::default(),
+        };
+        let explained = self.translate(selection, &llm_req, req)?;
+        if let Some(explanation) = explained.explanation.filter(|found| !found.is_empty()) {
+            cached.metadata.explanation = Some(explanation);
+            self.cache.put(key, &cached)?;
+        }
+        Ok(cached)
+    }
+
+    /// `cached`, recorded as a hit for `candidate` and mapped onto `req`'s source.
+    fn cache_hit(
+        &self,
+        req: &CompileRequest,
+        candidate: &ProviderDescriptor,
+        cached: CompileResult,
+    ) -> CompileResult {
+        self.record_lookup(true);
+        if let Some(usage) = &self.usage {
+            // Best effort, like the hit log.
+            let _ = usage.record(&UsageEvent::new(
+                &candidate.provider,
+                &candidate.model,
+                UsageOutcome::CacheHit,
+                None,
+            ));
+        }
+        CompileResult {
+            source_map: self.source_map(req, &cached.javascript),
+            ..cached
+        }
+    }
+
+    /// Names the [`EmbeddingIndex`] file for `req`: its cache key without the source text and
+    /// id, so only sources translated the same way share translations.
+    fn embedding_scope(
+        &self,
+        req: &CompileRequest,
+        kind_hint: &str,
+        provider: &Provider,
+        model: &str,
+        prompt_fingerprint: &str,
+        passes: &str,
+    ) -> String {
+        let unnamed = CompileRequest {
+            source_text: String::new(),
+            source_id: String::new(),
+            ..req.clone()
+        };
+        self.cache_key(
+            &unnamed,
+            kind_hint,
+            provider,
+            model,
+            prompt_fingerprint,
+            passes,
+        )
+    }
+
+    fn record_lookup(&self, hit: bool) {
+        if let Some(log) = &self.hit_log {
+            // Best effort, like provider metrics: a read-only home must not fail the compile.
+            let _ = log.record(hit);
+        }
+    }
+
     fn cache_key(
-        source_text: &str,
-        source_id: &str,
+        &self,
+        req: &CompileRequest,
         kind_hint: &str,
-        provider: Provider,
+        provider: &Provider,
         model: &str,
+        prompt_fingerprint: &str,
+        passes: &str,
     ) -> String {
-        let mut hasher = Sha256::new();
-        hasher.update(source_text.as_bytes());
-        hasher.update(b"\n--source-id--\n");
-        hasher.update(source_id.as_bytes());
-        hasher.update(b"\n--kind--\n");
-        hasher.update(kind_hint.as_bytes());
-        hasher.update(b"\n--provider--\n");
-        hasher.update(format_provider(provider).as_bytes());
-        hasher.update(b"\n--model--\n");
-        hasher.update(model.as_bytes());
-        hasher.update(b"\n--prompt-version--\n");
-        hasher.update(PROMPT_VERSION.as_bytes());
-        format!("{:x}", hasher.finalize())
+        self.cache_keys.key(&CacheKeyInput {
+            source_text: &req.source_text,
+            source_id: &req.source_id,
+            kind_hint,
+            provider,
+            model,
+            sampling: &req.sampling,
+            prompt_fingerprint,
+            scope_context: req.scope_context.as_deref(),
+            passes,
+        })
+    }
+
+    /// `req` after every pass's `prepare`: the source a compile actually works on, e.g. a JSX
+    /// file's lowered JavaScript.
+    pub fn prepare(&self, req: &CompileRequest) -> Result<CompileRequest> {
+        let mut prepared = req.clone();
+        for pass in self.passes.iter().filter(|pass| pass.applies(req)) {
+            pass.prepare(&mut prepared).with_context(|| {
+                format!("compile pass {} failed on {}", pass.name(), req.source_id)
+            })?;
+        }
+        Ok(prepared)
+    }
+
+    /// Where the lines of `javascript`, compiled from `req`, came from. Transpiled TypeScript is
+    /// regenerated, so its lines are aligned like a translation's, which only happens with
+    /// [`Self::source_maps`] on. `None` when a pass that ran moved the lines, e.g. minifying.
+    fn source_map(&self, req: &CompileRequest, javascript: &str) -> Option<SourceMap> {
+        if self
+            .passes
+            .iter()
+            .any(|pass| pass.applies(req) && pass.moves_lines())
+        {
+            return None;
+        }
+        if javascript == req.source_text {
+            Some(SourceMap::identity(&req.source_id, javascript))
+        } else {
+            self.source_maps
+                .then(|| SourceMap::infer(&req.source_id, &req.source_text, javascript))
+        }
+    }
+
+    fn finish(&self, req: &CompileRequest, result: &mut CompileResult) -> Result<()> {
+        for pass in self.passes.iter().filter(|pass| pass.applies(req)) {
+            pass.finish(req, result).with_context(|| {
+                format!("compile pass {} failed on {}", pass.name(), req.source_id)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// A result compiled without a provider: JavaScript passed through or TypeScript transpiled
+    /// locally. Nothing is cached, and under `Verify` the source's sidecar must check out.
+    fn without_llm(&self, req: &CompileRequest, javascript: String) -> Result<CompileResult> {
+        let signature = match &self.signatures {
+            SignaturePolicy::Verify(keys) => Some(verify_signature_file(
+                keys,
+                Path::new(&req.source_id),
+                &req.source_text,
+            )?),
+            _ => None,
+        };
+        let mut result = CompileResult {
+            javascript,
+            metadata: CompileMetadata {
+                provider: None,
+                model: None,
+                served_model: None,
+                prompt_version: PROMPT_VERSION.to_string(),
+                cache_hit: false,
+                notes: None,
+                confidence: None,
+                explanation: None,
+                signature,
+                similarity: None,
+            },
+            source_map: None,
+        };
+        self.finish(req, &mut result)?;
+        result.source_map = self.source_map(req, &result.javascript);
+        Ok(result)
     }
 }
 
@@ -199,6 +1234,8 @@ where
     C: CompileCache,
 {
     fn compile(&self, req: &CompileRequest) -> Result<CompileResult> {
+        let prepared = self.prepare(req)?;
+        let req = &prepared;
         let kind = self.resolved_kind(req);
         let kind_hint = req.language_hint.clone().unwrap_or_else(|| kind.as_hint());
         let needs_llm = req.force_llm
@@ -206,98 +1243,415 @@ where
             || Self::contains_jsr_specifier(&req.source_text);
 
         if !needs_llm {
-            return Ok(CompileResult {
-                javascript: req.source_text.clone(),
-                metadata: CompileMetadata {
-                    provider: None,
-                    model: None,
-                    prompt_version: PROMPT_VERSION.to_string(),
-                    cache_hit: false,
-                },
-            });
+            return self.without_llm(req, req.source_text.clone());
+        }
+
+        // TypeScript that oxc can transpile never reaches a provider; a source that does not
+        // parse, or whose output does not, is translated.
+        if matches!(kind, SourceKind::TypeScript)
+            && !req.force_llm
+            && !Self::contains_jsr_specifier(&req.source_text)
+            && let Ok(javascript) = transpile_typescript(&req.source_text)
+            && check_syntax(&javascript).is_empty()
+        {
+            return self.without_llm(req, javascript);
         }
 
+        // Likewise simple Python scripts, when the fast path is on.
+        if self.python_fast_path
+            && !req.force_llm
+            && matches!(kind_hint.as_str(), "py" | "python" | "python3")
+            && let Ok(javascript) = transpile_python(&req.source_text)
+            && check_syntax(&javascript).is_empty()
+        {
+            return self.without_llm(req, javascript);
+        }
+
+        // A language route picks the candidate chain through the provider and model overrides
+        // it fills in, so its model is keyed like an explicit one.
+        let routed;
+        let req = match select_language_route(&self.language_routes, &kind_hint) {
+            Some(route) => {
+                routed = CompileRequest {
+                    provider_override: req.provider_override.clone().or(route.provider.clone()),
+                    model_override: req.model_override.clone().or_else(|| route.model.clone()),
+                    ..req.clone()
+                };
+                &routed
+            }
+            None => req,
+        };
+
+        let selection = req
+            .provider_override
+            .clone()
+            .unwrap_or_else(|| req.provider_selection.clone());
+
+        // A tier's model stands in for a model override of its provider only, so it is part of
+        // that provider's cache key below.
+        let tiered;
+        let req = match select_model_tier(&self.model_tiers, &req.source_text, &kind_hint) {
+            Some(tier) if req.model_override.is_none() => {
+                let provider = self
+                    .translator
+                    .candidate_chain(tier.provider.clone().unwrap_or_else(|| selection.clone()))
+                    .into_iter()
+                    .next()
+                    .map(|entry| entry.provider);
+                tiered = CompileRequest {
+                    model_override: Some(tier.model.clone()),
+                    model_override_for: provider,
+                    ..req.clone()
+                };
+                &tiered
+            }
+            _ => req,
+        };
+
+        let prompt_fingerprint = self.translator.prompt_fingerprint();
+        let pipeline = pipeline_fingerprint(&self.passes, req);
         if !req.no_cache {
-            for candidate in self.translator.candidate_chain(req.provider_selection) {
-                let model_for_key = req
-                    .model_override
-                    .as_deref()
-                    .unwrap_or(&candidate.model)
-                    .to_string();
-                let key = Self::cache_key(
-                    &req.source_text,
-                    &req.source_id,
+            // Keyed like the entries written below: on the provider and model that answered.
+            let chain = self.resolved_chain(selection.clone(), req);
+            for candidate in chain {
+                let key = self.cache_key(
+                    req,
                     &kind_hint,
-                    candidate.provider,
-                    &model_for_key,
+                    &candidate.provider,
+                    &candidate.model,
+                    &prompt_fingerprint,
+                    &pipeline,
                 );
-                if let Some(cached) = self.cache.get(&key) {
-                    return Ok(cached);
+                if let Some(cached) = self.cache.get(&key)
+                    && self.usable(req, &cached)
+                {
+                    let cached =
+                        self.explain_cached(req, selection.clone(), &kind_hint, &key, cached)?;
+                    return Ok(self.cache_hit(req, &candidate, cached));
+                }
+                if let Some(pending) = &self.approval
+                    && pending.contains(&key)
+                {
+                    return Err(awaiting_review(&req.source_id, &key));
                 }
             }
         }
-
-        let translated = self.translator.translate(
-            req.provider_selection,
-            &LlmTranslateRequest {
-                source_text: req.source_text.clone(),
-                source_id: req.source_id.clone(),
-                language_hint: Some(kind_hint.clone()),
-                scope_context: req.scope_context.clone(),
-            },
-            req.model_override.as_deref(),
-        )?;
-
-        let result = CompileResult {
-            javascript: translated.javascript,
-            metadata: CompileMetadata {
-                provider: Some(translated.provider),
-                model: Some(translated.model.clone()),
-                prompt_version: PROMPT_VERSION.to_string(),
-                cache_hit: false,
-            },
+        // Embedding failures only cost the similarity lookup, never the compile.
+        let embedding = match &self.embeddings {
+            // A deterministic check needs this source's own translation, twice, and an
+            // explanation describes this source's translation, not a similar one's.
+            Some(index) if !req.no_cache && !self.deterministic && !req.explain => {
+                index.embed(&req.source_text).ok()
+            }
+            _ => None,
         };
-
-        if !req.no_cache {
-            let key = Self::cache_key(
-                &req.source_text,
+        if let (Some(index), Some(embedding)) = (&self.embeddings, &embedding) {
+            let chain = self.resolved_chain(selection.clone(), req);
+            for candidate in chain {
+                let scope = self.embedding_scope(
+                    req,
+                    &kind_hint,
+                    &candidate.provider,
+                    &candidate.model,
+                    &prompt_fingerprint,
+                    &pipeline,
+                );
+                let similar =
+                    index
+                        .nearest(&scope, embedding)
+                        .into_iter()
+                        .find_map(|(key, score)| {
+                            let cached = self.cache.get(&key)?;
+                            self.usable(req, &cached).then_some((cached, score))
+                        });
+                if let Some((mut cached, score)) = similar {
+                    cached.metadata.similarity = Some(score);
+                    return Ok(self.cache_hit(req, &candidate, cached));
+                }
+            }
+        }
+        if !req.no_cache {
+            self.record_lookup(false);
+        }
+        if matches!(self.signatures, SignaturePolicy::Verify(_)) {
+            return Err(anyhow!(
+                "refusing to translate {}: --verify-signatures only runs cached translations signed by a trusted key; translate it where the project signing key is configured and review the result first",
+                req.source_id
+            ));
+        }
+
+        let chunks = req
+            .context_window
+            .map(|window| split_source(&req.source_text, chunk_budget(window)))
+            .unwrap_or_default();
+        // Structured and explained compiles describe the whole file, so they are not assembled
+        // from units; `no_cache` asks for a fresh translation, and a deterministic check must
+        // translate every unit both times.
+        let units = match &self.incremental {
+            Some(_)
+                if !req.structured_output
+                    && !req.explain
+                    && !req.no_cache
+                    && !self.deterministic =>
+            {
+                match req.context_window {
+                    Some(window) => split_units_within(&req.source_text, chunk_budget(window)),
+                    None => split_units(&req.source_text),
+                }
+            }
+            _ => Vec::new(),
+        };
+        let map_key = chunk_map_key(
+            req,
+            &kind_hint,
+            selection.clone(),
+            &prompt_fingerprint,
+            &pipeline,
+        );
+        let incremental = self
+            .incremental
+            .as_ref()
+            .filter(|_| units.len() > 1)
+            .map(|store| (store, store.get(&map_key)));
+        let translate = || {
+            let (translated, map) = match &incremental {
+                Some((_, previous)) if !previous.units.is_empty() => self
+                    .translate_incremental(req, selection.clone(), &kind_hint, &units, previous)
+                    .map(|(translated, map)| (translated, Some(map)))?,
+                _ => {
+                    let translated = if chunks.len() > 1 {
+                        self.translate_chunked(req, selection.clone(), &kind_hint, &chunks)
+                    } else {
+                        self.translate_validated(req, selection.clone(), &kind_hint)
+                    }?;
+                    // Without a map there is nothing to reuse, so the file is translated whole
+                    // and its map starts from that; the next edit is translated unit by unit.
+                    let map = incremental.is_some().then(|| ChunkMap {
+                        units: vec![ChunkEntry {
+                            source_hash: unit_hash(&req.source_text),
+                            javascript: translated.javascript.clone(),
+                            provider: format_provider(&translated.provider),
+                            model: translated.model.clone(),
+                        }],
+                    });
+                    (translated, map)
+                }
+            };
+            // Best effort, like the hit log: without the map the next compile starts over.
+            if let (Some((store, _)), Some(map)) = (&incremental, map) {
+                let _ = store.put(&map_key, &map);
+            }
+            Ok::<_, anyhow::Error>(translated)
+        };
+        let translated = translate()?;
+        if self.deterministic {
+            let again = translate()?;
+            if again.javascript != translated.javascript {
+                return Err(nondeterministic(
+                    req,
+                    &translated.javascript,
+                    &again.javascript,
+                ));
+            }
+        }
+
+        let mut result = CompileResult {
+            javascript: translated.javascript,
+            metadata: CompileMetadata {
+                provider: Some(translated.provider.clone()),
+                model: Some(translated.model.clone()),
+                served_model: translated.served_model.clone(),
+                prompt_version: PROMPT_VERSION.to_string(),
+                cache_hit: false,
+                notes: translated.notes.clone(),
+                confidence: translated.confidence,
+                explanation: translated.explanation.clone(),
+                signature: None,
+                similarity: None,
+            },
+            source_map: None,
+        };
+        self.finish(req, &mut result)?;
+        result.source_map = self.source_map(req, &result.javascript);
+        // Signed after the passes, so the signature covers the JavaScript that is cached, and
+        // bound to the source so it cannot vouch for a translation of anything else.
+        if let SignaturePolicy::Sign(key) = &self.signatures {
+            result.metadata.signature =
+                Some(key.sign(Signed::Translation(&req.source_text), &result.javascript));
+        }
+
+        let key = self.cache_key(
+            req,
+            &kind_hint,
+            &translated.provider,
+            &translated.model,
+            &prompt_fingerprint,
+            &pipeline,
+        );
+        if let Some(pending) = &self.approval {
+            pending.add(PendingTranslation::new(
+                key.clone(),
                 &req.source_id,
-                &kind_hint,
-                translated.provider,
-                &translated.model,
-            );
+                &req.source_text,
+                &result,
+            ))?;
+            return Err(awaiting_review(&req.source_id, &key));
+        }
+        if !req.no_cache {
             self.cache.put(&key, &result)?;
+            if let (Some(index), Some(embedding)) = (&self.embeddings, &embedding) {
+                let scope = self.embedding_scope(
+                    req,
+                    &kind_hint,
+                    &translated.provider,
+                    &translated.model,
+                    &prompt_fingerprint,
+                    &pipeline,
+                );
+                // Best effort: the translation is cached either way.
+                let _ = index.add(&scope, &key, embedding);
+            }
         }
 
         Ok(result)
     }
+
+    /// Compiles on up to `jobs` threads, fewer when the translator allows fewer translations
+    /// in flight for the providers the requests route to (see
+    /// `TranslationService::max_concurrency`), so a local model server is not sent more than
+    /// it serves at once.
+    fn compile_many(&self, reqs: &[CompileRequest], jobs: usize) -> Vec<Result<CompileResult>>
+    where
+        Self: Sync,
+    {
+        let provider_limit = reqs
+            .iter()
+            .flat_map(|req| self.possible_selections(req))
+            .map(|selection| self.translator.max_concurrency(selection))
+            .min()
+            .unwrap_or(1);
+        let workers = jobs.min(provider_limit).min(reqs.len());
+        if workers < 2 {
+            return reqs.iter().map(|req| self.compile(req)).collect();
+        }
+
+        let next = AtomicUsize::new(0);
+        let results: Mutex<Vec<Option<Result<CompileResult>>>> =
+            Mutex::new(reqs.iter().map(|_| None).collect());
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| {
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(req) = reqs.get(index) else {
+                            break;
+                        };
+                        let result = self.compile(req);
+                        results
+                            .lock()
+                            .unwrap_or_else(|poisoned| poisoned.into_inner())[index] = Some(result);
+                    }
+                });
+            }
+        });
+        results
+            .into_inner()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .into_iter()
+            .map(|result| result.expect("every request is compiled"))
+            .collect()
+    }
+}
+
+/// The provider request translating `req` as `kind_hint`.
+fn translation_request(req: &CompileRequest, kind_hint: &str) -> LlmTranslateRequest {
+    LlmTranslateRequest {
+        source_text: req.source_text.clone(),
+        source_id: req.source_id.clone(),
+        language_hint: Some(kind_hint.to_string()),
+        scope_context: req.scope_context.clone(),
+        repair_hint: None,
+        temperature: req.sampling.temperature,
+        top_p: req.sampling.top_p,
+        max_tokens: req.sampling.max_tokens,
+        seed: req.sampling.seed,
+        structured_output: req.structured_output || req.explain,
+        explain: req.explain,
+        explain_javascript: None,
+        prompts: PromptTemplates::default(),
+    }
+}
+
+/// `parts` one after another, each starting on a new line.
+fn join_parts(parts: &[String]) -> String {
+    let mut joined = String::new();
+    for part in parts {
+        if !joined.is_empty() && !joined.ends_with('\n') {
+            joined.push('\n');
+        }
+        joined.push_str(part);
+    }
+    joined
+}
+
+fn nondeterministic(req: &CompileRequest, first: &str, second: &str) -> anyhow::Error {
+    let diff = TextDiff::from_lines(first, second)
+        .unified_diff()
+        .header("first translation", "second translation")
+        .to_string();
+    anyhow!(
+        "translation of {} is not deterministic (temperature {:?}, seed {:?}): two runs differ\n{diff}",
+        req.source_id,
+        req.sampling.temperature,
+        req.sampling.seed
+    )
+}
+
+fn awaiting_review(source_id: &str, key: &str) -> anyhow::Error {
+    anyhow!(
+        "translation of {source_id} is awaiting review (pending {}); approve it with `klumo review`",
+        &key[..key.len().min(12)]
+    )
 }
 
 fn parse_provider(value: &str) -> Provider {
     if value == "ollama" {
         Provider::Ollama
+    } else if value == "fixture" {
+        Provider::Fixture
+    } else if value == "llamacpp" {
+        Provider::LlamaCpp
+    } else if let Some(name) = value.strip_prefix("custom:") {
+        Provider::Custom(name.to_string())
     } else {
         Provider::OpenAiCompatible
     }
 }
 
-fn format_provider(provider: Provider) -> String {
+fn format_provider(provider: &Provider) -> String {
     match provider {
         Provider::Ollama => "ollama".to_string(),
         Provider::OpenAiCompatible => "openai-compatible".to_string(),
+        Provider::LlamaCpp => "llamacpp".to_string(),
+        Provider::Custom(name) => format!("custom:{name}"),
+        Provider::Fixture => "fixture".to_string(),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::{
-        CompileCache, CompileRequest, CompileResult, Compiler, CompilerRouter, FileCompileCache,
-        PROMPT_VERSION, SourceKind,
+        CacheHitLog, CacheKeyInput, CacheKeyStrategy, CachePolicy, CacheRecord, ChunkMapStore,
+        CompileCache, CompileMetadata, CompilePass, CompileRequest, CompileResult, Compiler,
+        CompilerRouter, Embedder, EmbeddingIndex, FileCompileCache, GcReport, LRU_INDEX_FILE,
+        LanguageCache, LanguageRoute, ModelTier, PROMPT_VERSION, PendingStore, Sha256CacheKey,
+        SignaturePolicy, Signed, SigningKey, SourceKind, SyntaxCheckPass, TrustedKeys,
+        VALIDATION_RETRIES, cosine_similarity, select_model_tier,
     };
     use anyhow::{Result, anyhow};
     use klumo_llm::{
-        LlmTranslateRequest, LlmTranslateResponse, Provider, ProviderDescriptor, ProviderSelection,
-        TranslationService,
+        ConstructMapping, Explanation, LlmTranslateRequest, LlmTranslateResponse, Provider,
+        ProviderDescriptor, ProviderSelection, SamplingOptions, TranslationService,
     };
     use std::collections::HashMap;
     use std::path::PathBuf;
@@ -335,8 +1689,12 @@ mod tests {
 
             Ok(LlmTranslateResponse {
                 javascript: self.response_js.clone(),
-                provider: self.provider,
+                provider: self.provider.clone(),
                 model: self.model.clone(),
+                served_model: None,
+                notes: None,
+                confidence: None,
+                explanation: None,
             })
         }
     }
@@ -369,15 +1727,21 @@ mod tests {
             scope_context: None,
             force_llm: false,
             provider_selection: ProviderSelection::Auto,
+            provider_override: None,
             model_override: None,
+            model_override_for: None,
+            sampling: SamplingOptions::default(),
+            structured_output: false,
+            explain: false,
+            context_window: None,
             no_cache: false,
         }
     }
 
     #[test]
     fn passthrough_for_js_without_force_llm() {
-        let router = CompilerRouter {
-            translator: MockTranslator {
+        let router = CompilerRouter::new(
+            MockTranslator {
                 fail: true,
                 response_js: String::new(),
                 provider: Provider::Ollama,
@@ -385,8 +1749,8 @@ mod tests {
                 chain: vec![],
                 call_counter: None,
             },
-            cache: MemoryCache::default(),
-        };
+            MemoryCache::default(),
+        );
 
         let req = CompileRequest {
             source_text: "1+1".to_string(),
@@ -396,7 +1760,13 @@ mod tests {
             scope_context: None,
             force_llm: false,
             provider_selection: ProviderSelection::Auto,
+            provider_override: None,
             model_override: None,
+            model_override_for: None,
+            sampling: SamplingOptions::default(),
+            structured_output: false,
+            explain: false,
+            context_window: None,
             no_cache: false,
         };
 
@@ -407,8 +1777,8 @@ mod tests {
 
     #[test]
     fn non_js_uses_llm() {
-        let router = CompilerRouter {
-            translator: MockTranslator {
+        let router = CompilerRouter::new(
+            MockTranslator {
                 fail: false,
                 response_js: "console.log('hi')".to_string(),
                 provider: Provider::Ollama,
@@ -419,8 +1789,8 @@ mod tests {
                 }],
                 call_counter: None,
             },
-            cache: MemoryCache::default(),
-        };
+            MemoryCache::default(),
+        );
 
         let result = router
             .compile(&pseudo_request())
@@ -429,10 +1799,39 @@ mod tests {
         assert_eq!(result.metadata.provider, Some(Provider::Ollama));
     }
 
+    #[test]
+    fn translations_are_mapped_only_with_source_maps_on() {
+        let router = CompilerRouter::new(
+            MockTranslator {
+                fail: false,
+                response_js: "console.log('hello')".to_string(),
+                provider: Provider::Ollama,
+                model: "model".to_string(),
+                chain: vec![ProviderDescriptor {
+                    provider: Provider::Ollama,
+                    model: "model".to_string(),
+                }],
+                call_counter: None,
+            },
+            MemoryCache::default(),
+        );
+        let result = router.compile(&pseudo_request()).expect("compile");
+        assert_eq!(result.source_map, None);
+
+        let router = router.with_source_maps(true);
+        let result = router.compile(&pseudo_request()).expect("compile");
+        assert_eq!(
+            result.source_map.and_then(|map| map
+                .lookup(1)
+                .map(|(source, line)| (source.to_string(), line))),
+            Some(("sample.pseudo".to_string(), 1))
+        );
+    }
+
     #[test]
     fn force_llm_on_js() {
-        let router = CompilerRouter {
-            translator: MockTranslator {
+        let router = CompilerRouter::new(
+            MockTranslator {
                 fail: false,
                 response_js: "2+2".to_string(),
                 provider: Provider::OpenAiCompatible,
@@ -443,8 +1842,8 @@ mod tests {
                 }],
                 call_counter: None,
             },
-            cache: MemoryCache::default(),
-        };
+            MemoryCache::default(),
+        );
 
         let req = CompileRequest {
             source_text: "1+1".to_string(),
@@ -454,7 +1853,13 @@ mod tests {
             scope_context: None,
             force_llm: true,
             provider_selection: ProviderSelection::OpenAiCompatible,
+            provider_override: None,
             model_override: None,
+            model_override_for: None,
+            sampling: SamplingOptions::default(),
+            structured_output: false,
+            explain: false,
+            context_window: None,
             no_cache: false,
         };
 
@@ -462,10 +1867,117 @@ mod tests {
         assert_eq!(result.metadata.provider, Some(Provider::OpenAiCompatible));
     }
 
+    #[test]
+    fn typescript_is_transpiled_locally_before_asking_the_llm() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let router = CompilerRouter::new(
+            MockTranslator {
+                fail: false,
+                response_js: "console.log('translated')".to_string(),
+                provider: Provider::Ollama,
+                model: "qwen".to_string(),
+                chain: vec![ProviderDescriptor {
+                    provider: Provider::Ollama,
+                    model: "qwen".to_string(),
+                }],
+                call_counter: Some(calls.clone()),
+            },
+            MemoryCache::default(),
+        );
+        let request = |source: &str, force_llm: bool| CompileRequest {
+            source_text: source.to_string(),
+            source_id: "main.ts".to_string(),
+            kind_hint: Some(SourceKind::TypeScript),
+            language_hint: None,
+            scope_context: None,
+            force_llm,
+            provider_selection: ProviderSelection::Auto,
+            provider_override: None,
+            model_override: None,
+            model_override_for: None,
+            sampling: SamplingOptions::default(),
+            structured_output: false,
+            explain: false,
+            context_window: None,
+            no_cache: false,
+        };
+
+        let typed = "const greeting: string = `hi`;\nconsole.log(greeting as string);";
+        let local = router.compile(&request(typed, false)).expect("transpiles");
+        assert_eq!(
+            local.javascript,
+            "const greeting = `hi`;\nconsole.log(greeting);\n"
+        );
+        assert_eq!(local.metadata.provider, None);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        let forced = router.compile(&request(typed, true)).expect("translates");
+        assert_eq!(forced.metadata.provider, Some(Provider::Ollama));
+
+        let broken = "const side: number = ;";
+        let translated = router
+            .compile(&request(broken, false))
+            .expect("falls back to the llm");
+        assert_eq!(translated.javascript, "console.log('translated')");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn python_fast_path_transpiles_simple_scripts_and_translates_the_rest() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let router = |python_fast_path| {
+            CompilerRouter::new(
+                MockTranslator {
+                    fail: false,
+                    response_js: "console.log('translated')".to_string(),
+                    provider: Provider::Ollama,
+                    model: "qwen".to_string(),
+                    chain: vec![ProviderDescriptor {
+                        provider: Provider::Ollama,
+                        model: "qwen".to_string(),
+                    }],
+                    call_counter: Some(calls.clone()),
+                },
+                MemoryCache::default(),
+            )
+            .with_python_fast_path(python_fast_path)
+        };
+        let request = |source: &str| CompileRequest {
+            source_text: source.to_string(),
+            source_id: "main.py".to_string(),
+            kind_hint: Some(SourceKind::from_hint("py")),
+            language_hint: None,
+            ..pseudo_request()
+        };
+        let script = "name = 'klumo'\nprint(f\"hi {name}\")\n";
+
+        let fast = router(true);
+        let local = fast.compile(&request(script)).expect("transpiles");
+        assert!(
+            local
+                .javascript
+                .starts_with("var name = 'klumo';\nconsole.log(`hi ${__py_str(name)}`);\n"),
+            "{}",
+            local.javascript
+        );
+        assert_eq!(local.metadata.provider, None);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        let imported = fast
+            .compile(&request("import sys\nprint(sys.argv)\n"))
+            .expect("falls back to the llm");
+        assert_eq!(imported.metadata.provider, Some(Provider::Ollama));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let off = router(false).compile(&request(script)).expect("translates");
+        assert_eq!(off.javascript, "console.log('translated')");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
     #[test]
     fn jsr_source_in_js_routes_through_llm() {
-        let router = CompilerRouter {
-            translator: MockTranslator {
+        let router = CompilerRouter::new(
+            MockTranslator {
                 fail: false,
                 response_js: "console.log('jsr translated')".to_string(),
                 provider: Provider::Ollama,
@@ -476,8 +1988,8 @@ mod tests {
                 }],
                 call_counter: None,
             },
-            cache: MemoryCache::default(),
-        };
+            MemoryCache::default(),
+        );
 
         let req = CompileRequest {
             source_text: "import { join } from \"jsr:@std/path\";\njoin('a','b');".to_string(),
@@ -487,7 +1999,13 @@ mod tests {
             scope_context: None,
             force_llm: false,
             provider_selection: ProviderSelection::Auto,
+            provider_override: None,
             model_override: None,
+            model_override_for: None,
+            sampling: SamplingOptions::default(),
+            structured_output: false,
+            explain: false,
+            context_window: None,
             no_cache: false,
         };
 
@@ -496,13 +2014,180 @@ mod tests {
         assert_eq!(result.metadata.provider, Some(Provider::Ollama));
     }
 
+    /// Tags requests in `prepare` and prepends `// <banner>` in `finish`.
+    struct BannerPass(&'static str);
+
+    impl CompilePass for BannerPass {
+        fn name(&self) -> String {
+            format!("banner:{}", self.0)
+        }
+
+        fn prepare(&self, req: &mut CompileRequest) -> Result<()> {
+            req.source_text.push_str(" (prepared)");
+            Ok(())
+        }
+
+        fn finish(&self, _req: &CompileRequest, result: &mut CompileResult) -> Result<()> {
+            result.javascript = format!("// {}\n{}", self.0, result.javascript);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn compile_passes_transform_requests_and_output_before_caching() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let dir = tempdir().expect("tempdir");
+        let router = |passes: Vec<Box<dyn CompilePass>>, response_js: &str| {
+            CompilerRouter::new(
+                MockTranslator {
+                    fail: false,
+                    response_js: response_js.to_string(),
+                    provider: Provider::Ollama,
+                    model: "qwen".to_string(),
+                    chain: vec![ProviderDescriptor {
+                        provider: Provider::Ollama,
+                        model: "qwen".to_string(),
+                    }],
+                    call_counter: Some(Arc::clone(&counter)),
+                },
+                FileCompileCache::new(dir.path().to_path_buf()),
+            )
+            .with_passes(passes)
+        };
+
+        let banner = router(vec![Box::new(BannerPass("v1"))], "console.log(1);");
+        let first = banner.compile(&pseudo_request()).expect("compile");
+        assert_eq!(first.javascript, "// v1\nconsole.log(1);");
+        let cached = banner.compile(&pseudo_request()).expect("compile");
+        assert!(cached.metadata.cache_hit);
+        assert_eq!(
+            cached.javascript, first.javascript,
+            "passes run before caching"
+        );
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+        let stored = banner.cache.entries().expect("entries").len();
+
+        let other = router(vec![Box::new(BannerPass("v2"))], "console.log(1);");
+        assert_eq!(
+            other
+                .compile(&pseudo_request())
+                .expect("compile")
+                .javascript,
+            "// v2\nconsole.log(1);"
+        );
+        assert_eq!(
+            counter.load(Ordering::SeqCst),
+            2,
+            "pipelines key separately"
+        );
+        assert_eq!(banner.cache.entries().expect("entries").len(), stored + 1);
+
+        let js = CompileRequest {
+            source_text: "1 + 1".to_string(),
+            source_id: "plain.js".to_string(),
+            kind_hint: Some(SourceKind::JavaScript),
+            language_hint: None,
+            ..pseudo_request()
+        };
+        assert_eq!(
+            banner.compile(&js).expect("compile").javascript,
+            "// v1\n1 + 1 (prepared)"
+        );
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+
+        let checked = router(
+            vec![Box::new(BannerPass("v3")), Box::new(SyntaxCheckPass)],
+            "console.log(1);",
+        );
+        assert!(checked.compile(&pseudo_request()).is_ok());
+        let err = checked
+            .compile(&CompileRequest {
+                source_text: "if (".to_string(),
+                ..js
+            })
+            .expect_err("broken output should fail the syntax check");
+        assert!(
+            format!("{err:#}").contains("compile pass syntax-check failed on plain.js"),
+            "{err:#}"
+        );
+
+        // Minified lines match nothing in the source, fresh or from the cache.
+        let minified = router(
+            vec![Box::new(crate::MinifyPass)],
+            "console.log(1)\n\nconsole.log(2)\n",
+        );
+        for _ in 0..2 {
+            let result = minified.compile(&pseudo_request()).expect("compile");
+            assert_eq!(result.javascript, "console.log(1),console.log(2);");
+            assert_eq!(result.source_map, None);
+        }
+    }
+
+    #[test]
+    fn verify_mode_only_returns_translations_signed_by_trusted_keys() {
+        let (key, _) = SigningKey::generate().expect("key should generate");
+        let trusted = TrustedKeys::from_hex(&[key.public_key()]).expect("key should parse");
+        let counter = Arc::new(AtomicUsize::new(0));
+        let translator = || MockTranslator {
+            fail: false,
+            response_js: "console.log('reviewed')".to_string(),
+            provider: Provider::Ollama,
+            model: "qwen".to_string(),
+            chain: vec![ProviderDescriptor {
+                provider: Provider::Ollama,
+                model: "qwen".to_string(),
+            }],
+            call_counter: Some(counter.clone()),
+        };
+        let temp = tempdir().expect("tempdir should work");
+        let cache = || FileCompileCache::new(PathBuf::from(temp.path()));
+
+        let verifying = CompilerRouter::new(translator(), cache())
+            .with_signatures(SignaturePolicy::Verify(trusted.clone()));
+        let err = verifying
+            .compile(&pseudo_request())
+            .expect_err("unsigned source must not be translated");
+        assert!(
+            err.to_string()
+                .contains("refusing to translate sample.pseudo")
+        );
+        assert_eq!(counter.load(Ordering::SeqCst), 0);
+
+        let signing =
+            CompilerRouter::new(translator(), cache()).with_signatures(SignaturePolicy::Sign(key));
+        let signed = signing
+            .compile(&pseudo_request())
+            .expect("signing compile should pass");
+        let signature = signed.metadata.signature.expect("fresh output is signed");
+        assert!(trusted.verify(
+            Signed::Translation(&pseudo_request().source_text),
+            &signed.javascript,
+            &signature
+        ));
+
+        let verified = verifying
+            .compile(&pseudo_request())
+            .expect("signed cache entry should run");
+        assert!(verified.metadata.cache_hit);
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+
+        let script = temp.path().join("app.js");
+        let mut js_request = pseudo_request();
+        js_request.source_id = script.display().to_string();
+        js_request.kind_hint = Some(SourceKind::JavaScript);
+        let err = verifying
+            .compile(&js_request)
+            .expect_err("JavaScript without a .sig file is refused");
+        assert!(err.to_string().contains("app.js.sig"));
+    }
+
     #[test]
     fn cache_includes_provider_and_model_and_hits() {
         let temp = tempdir().expect("tempdir should work");
         let cache = FileCompileCache::new(PathBuf::from(temp.path()));
 
-        let router = CompilerRouter {
-            translator: MockTranslator {
+        let router = CompilerRouter::new(
+            MockTranslator {
                 fail: false,
                 response_js: "console.log('cached')".to_string(),
                 provider: Provider::Ollama,
@@ -514,7 +2199,11 @@ mod tests {
                 call_counter: None,
             },
             cache,
-        };
+        )
+        .with_hit_log(Some(CacheHitLog::new(
+            temp.path().join("hits.json"),
+            "project",
+        )));
 
         let req = pseudo_request();
         let first = router.compile(&req).expect("first compile should pass");
@@ -523,12 +2212,295 @@ mod tests {
         let second = router.compile(&req).expect("second compile should pass");
         assert!(second.metadata.cache_hit);
         assert_eq!(second.metadata.prompt_version, PROMPT_VERSION);
+
+        let log = router.hit_log.as_ref().expect("hit log");
+        let lookups = log.load().expect("hit log should load");
+        assert_eq!(
+            lookups.iter().map(|lookup| lookup.hit).collect::<Vec<_>>(),
+            [false, true]
+        );
+    }
+
+    #[test]
+    fn approval_required_holds_translations_until_approved() {
+        let temp = tempdir().expect("tempdir should work");
+        let calls = Arc::new(AtomicUsize::new(0));
+        let router = CompilerRouter::new(
+            MockTranslator {
+                fail: false,
+                response_js: "console.log('hello')".to_string(),
+                provider: Provider::Ollama,
+                model: "qwen".to_string(),
+                chain: vec![ProviderDescriptor {
+                    provider: Provider::Ollama,
+                    model: "qwen".to_string(),
+                }],
+                call_counter: Some(calls.clone()),
+            },
+            FileCompileCache::new(temp.path().join("cache")),
+        )
+        .with_approval(Some(PendingStore::new(temp.path().join("pending"))));
+        let pending = router.approval.as_ref().expect("approval store");
+
+        let req = pseudo_request();
+        let err = router
+            .compile(&req)
+            .expect_err("fresh translation must wait");
+        assert!(err.to_string().contains("sample.pseudo is awaiting review"));
+        let err = router.compile(&req).expect_err("still pending");
+        assert!(err.to_string().contains("awaiting review"));
+        assert_eq!(
+            calls.load(Ordering::SeqCst),
+            1,
+            "pending entries are not re-translated"
+        );
+
+        let entries = pending.list().expect("list should work");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].source_text, "write hello");
+        assert_eq!(entries[0].previous, None);
+        pending
+            .approve(&entries[0].key, &router.cache)
+            .expect("approve should work");
+        let approved = router.compile(&req).expect("approved translation runs");
+        assert_eq!(approved.javascript, "console.log('hello')");
+        assert!(approved.metadata.cache_hit);
+
+        let edited = CompileRequest {
+            source_text: "write hello twice".to_string(),
+            ..pseudo_request()
+        };
+        router
+            .compile(&edited)
+            .expect_err("edited source must wait");
+        let entries = pending.list().expect("list should work");
+        assert_eq!(entries[0].previous.as_deref(), Some("console.log('hello')"));
+        pending.reject(&entries[0].key).expect("reject should work");
+        assert!(pending.list().expect("list should work").is_empty());
+        assert!(pending.reject(&entries[0].key).is_err());
+    }
+
+    #[test]
+    fn model_tiers_match_by_size_and_language() {
+        let tiers = vec![
+            ModelTier {
+                model: "tiny".to_string(),
+                provider: None,
+                max_source_bytes: Some(16),
+                languages: vec!["Pseudocode".to_string()],
+            },
+            ModelTier {
+                model: "large".to_string(),
+                provider: None,
+                max_source_bytes: None,
+                languages: Vec::new(),
+            },
+        ];
+        let pick = |source: &str, lang: &str| {
+            select_model_tier(&tiers, source, lang).map(|tier| tier.model.as_str())
+        };
+        assert_eq!(pick("write hello", "pseudocode"), Some("tiny"));
+        assert_eq!(pick("write hello", "cobol"), Some("large"));
+        assert_eq!(pick(&"x".repeat(17), "pseudocode"), Some("large"));
+        assert_eq!(select_model_tier(&tiers[..1], "write hello", "cobol"), None);
+    }
+
+    #[test]
+    fn tier_model_is_part_of_the_cache_key() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let router = CompilerRouter::new(
+            MockTranslator {
+                fail: false,
+                response_js: "console.log('hello')".to_string(),
+                provider: Provider::Ollama,
+                model: "tiny".to_string(),
+                chain: vec![ProviderDescriptor {
+                    provider: Provider::Ollama,
+                    model: "qwen".to_string(),
+                }],
+                call_counter: Some(counter.clone()),
+            },
+            MemoryCache::default(),
+        )
+        .with_model_tiers(vec![ModelTier {
+            model: "tiny".to_string(),
+            provider: None,
+            max_source_bytes: Some(64),
+            languages: Vec::new(),
+        }]);
+
+        let req = pseudo_request();
+        router.compile(&req).expect("first compile should pass");
+        // The lookup uses the tier model, which matches the model the translation was stored under.
+        router.compile(&req).expect("second compile should pass");
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+
+        let mut explicit = pseudo_request();
+        explicit.model_override = Some("qwen".to_string());
+        router
+            .compile(&explicit)
+            .expect("override compile should pass");
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+    }
+
+    #[derive(Default)]
+    struct OverrideRecorder {
+        seen: Mutex<Vec<(ProviderSelection, Option<String>)>>,
+    }
+
+    impl TranslationService for OverrideRecorder {
+        fn candidate_chain(&self, _selection: ProviderSelection) -> Vec<ProviderDescriptor> {
+            Vec::new()
+        }
+
+        fn translate(
+            &self,
+            selection: ProviderSelection,
+            _req: &LlmTranslateRequest,
+            model_override: Option<&str>,
+        ) -> Result<LlmTranslateResponse> {
+            self.seen
+                .lock()
+                .expect("lock must work")
+                .push((selection, model_override.map(str::to_string)));
+            Ok(LlmTranslateResponse {
+                javascript: "1".to_string(),
+                provider: Provider::Ollama,
+                model: model_override.unwrap_or("default").to_string(),
+                served_model: None,
+                notes: None,
+                confidence: None,
+                explanation: None,
+            })
+        }
+    }
+
+    /// An `auto` chain of Ollama then an OpenAI-compatible endpoint, where Ollama is down.
+    #[derive(Default)]
+    struct FallbackChain {
+        scoped: Mutex<Vec<(Provider, String)>>,
+    }
+
+    impl TranslationService for FallbackChain {
+        fn candidate_chain(&self, selection: ProviderSelection) -> Vec<ProviderDescriptor> {
+            let mut chain = vec![
+                ProviderDescriptor {
+                    provider: Provider::Ollama,
+                    model: "qwen".to_string(),
+                },
+                ProviderDescriptor {
+                    provider: Provider::OpenAiCompatible,
+                    model: "gpt".to_string(),
+                },
+            ];
+            if selection == ProviderSelection::OpenAiCompatible {
+                chain.remove(0);
+            }
+            chain
+        }
+
+        fn translate(
+            &self,
+            _selection: ProviderSelection,
+            _req: &LlmTranslateRequest,
+            model_override: Option<&str>,
+        ) -> Result<LlmTranslateResponse> {
+            Ok(LlmTranslateResponse {
+                javascript: "1".to_string(),
+                provider: Provider::OpenAiCompatible,
+                model: model_override.unwrap_or("gpt").to_string(),
+                served_model: None,
+                notes: None,
+                confidence: None,
+                explanation: None,
+            })
+        }
+
+        fn translate_for_provider(
+            &self,
+            selection: ProviderSelection,
+            req: &LlmTranslateRequest,
+            provider: &Provider,
+            model: &str,
+        ) -> Result<LlmTranslateResponse> {
+            self.scoped
+                .lock()
+                .expect("lock must work")
+                .push((provider.clone(), model.to_string()));
+            let model = (*provider == Provider::OpenAiCompatible).then_some(model);
+            self.translate(selection, req, model)
+        }
+    }
+
+    #[test]
+    fn tier_models_apply_only_to_their_provider() {
+        let tier = |provider: Option<ProviderSelection>| ModelTier {
+            model: "tiny".to_string(),
+            provider,
+            max_source_bytes: None,
+            languages: Vec::new(),
+        };
+        let router = CompilerRouter::new(FallbackChain::default(), MemoryCache::default())
+            .with_model_tiers(vec![tier(None)]);
+        let req = pseudo_request();
+        let first = router.compile(&req).expect("compile should pass");
+        // Ollama failed over to the endpoint, which answered with its own model.
+        assert_eq!(first.metadata.model.as_deref(), Some("gpt"));
+        // The endpoint's entry is keyed on its own model, so the lookup finds it.
+        router.compile(&req).expect("compile should pass");
+        assert_eq!(
+            *router.translator.scoped.lock().expect("lock must work"),
+            vec![(Provider::Ollama, "tiny".to_string())]
+        );
+
+        let router = CompilerRouter::new(FallbackChain::default(), MemoryCache::default())
+            .with_model_tiers(vec![tier(Some(ProviderSelection::OpenAiCompatible))]);
+        let compiled = router.compile(&req).expect("compile should pass");
+        assert_eq!(compiled.metadata.model.as_deref(), Some("tiny"));
+    }
+
+    #[test]
+    fn language_routes_fill_unset_overrides_before_tiers() {
+        let router = CompilerRouter::new(OverrideRecorder::default(), MemoryCache::default())
+            .with_model_tiers(vec![ModelTier {
+                model: "tiny".to_string(),
+                provider: None,
+                max_source_bytes: None,
+                languages: Vec::new(),
+            }])
+            .with_language_routes(vec![LanguageRoute {
+                language: "python".to_string(),
+                provider: Some(ProviderSelection::Ollama),
+                model: Some("codellama".to_string()),
+            }]);
+        let request = |language: &str, model: Option<&str>| CompileRequest {
+            language_hint: Some(language.to_string()),
+            model_override: model.map(str::to_string),
+            no_cache: true,
+            ..pseudo_request()
+        };
+
+        for req in [
+            request("Python", None),
+            request("python", Some("qwen")),
+            request("cobol", None),
+        ] {
+            router.compile(&req).expect("compile should pass");
+        }
+        assert_eq!(
+            *router.translator.seen.lock().expect("lock must work"),
+            vec![
+                (ProviderSelection::Ollama, Some("codellama".to_string())),
+                (ProviderSelection::Ollama, Some("qwen".to_string())),
+                (ProviderSelection::Auto, Some("tiny".to_string())),
+            ]
+        );
     }
 
     #[test]
     fn snapshot_error_for_llm_failure() {
-        let router = CompilerRouter {
-            translator: MockTranslator {
+        let router = CompilerRouter::new(
+            MockTranslator {
                 fail: true,
                 response_js: String::new(),
                 provider: Provider::Ollama,
@@ -539,8 +2511,8 @@ mod tests {
                 }],
                 call_counter: None,
             },
-            cache: MemoryCache::default(),
-        };
+            MemoryCache::default(),
+        );
 
         let err = router
             .compile(&pseudo_request())
@@ -549,15 +2521,46 @@ mod tests {
     }
 
     #[test]
-    fn no_cache_bypasses_cached_entries() {
+    fn scope_context_is_part_of_the_cache_key() {
         let counter = Arc::new(AtomicUsize::new(0));
-        let temp = tempdir().expect("tempdir should work");
-        let cache = FileCompileCache::new(PathBuf::from(temp.path()));
-
-        let router = CompilerRouter {
-            translator: MockTranslator {
+        let router = CompilerRouter::new(
+            MockTranslator {
                 fail: false,
-                response_js: "console.log('fresh')".to_string(),
+                response_js: "console.log(total)".to_string(),
+                provider: Provider::Ollama,
+                model: "qwen".to_string(),
+                chain: vec![ProviderDescriptor {
+                    provider: Provider::Ollama,
+                    model: "qwen".to_string(),
+                }],
+                call_counter: Some(counter.clone()),
+            },
+            MemoryCache::default(),
+        );
+
+        let mut req = pseudo_request();
+        req.scope_context = Some("let total = 1;".to_string());
+        router.compile(&req).expect("first compile should pass");
+        router.compile(&req).expect("same scope should hit");
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+        req.scope_context = Some("let total = [1, 2];".to_string());
+        router.compile(&req).expect("new scope should translate");
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+        req.scope_context = None;
+        router.compile(&req).expect("no scope should translate");
+        assert_eq!(counter.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn no_cache_bypasses_cached_entries() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let temp = tempdir().expect("tempdir should work");
+        let cache = FileCompileCache::new(PathBuf::from(temp.path()));
+
+        let router = CompilerRouter::new(
+            MockTranslator {
+                fail: false,
+                response_js: "console.log('fresh')".to_string(),
                 provider: Provider::Ollama,
                 model: "qwen".to_string(),
                 chain: vec![ProviderDescriptor {
@@ -567,7 +2570,7 @@ mod tests {
                 call_counter: Some(counter.clone()),
             },
             cache,
-        };
+        );
 
         let mut req = pseudo_request();
         req.no_cache = false;
@@ -579,4 +2582,1218 @@ mod tests {
         router.compile(&req).expect("no-cache compile should pass");
         assert_eq!(counter.load(Ordering::SeqCst), 2);
     }
+
+    /// Keys on content alone, so identical snippets share an entry, and keeps tenants apart.
+    struct TenantContentKey(&'static str);
+
+    impl CacheKeyStrategy for TenantContentKey {
+        fn key(&self, input: &CacheKeyInput<'_>) -> String {
+            Sha256CacheKey.key(&CacheKeyInput {
+                source_id: self.0,
+                ..*input
+            })
+        }
+    }
+
+    #[derive(Default)]
+    struct CountingCache {
+        inner: MemoryCache,
+        lookups: AtomicUsize,
+    }
+
+    impl CompileCache for CountingCache {
+        fn get(&self, key: &str) -> Option<CompileResult> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            self.inner.get(key)
+        }
+
+        fn put(&self, key: &str, result: &CompileResult) -> Result<()> {
+            self.inner.put(key, result)
+        }
+    }
+
+    #[test]
+    fn model_override_collapses_the_cache_precheck_chain() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let descriptor = |provider: Provider, model: &str| ProviderDescriptor {
+            provider,
+            model: model.to_string(),
+        };
+        let router = CompilerRouter::new(
+            MockTranslator {
+                fail: false,
+                response_js: "console.log('hello');".to_string(),
+                // The first provider failed, so the fallback answered with the override.
+                provider: Provider::OpenAiCompatible,
+                model: "big".to_string(),
+                chain: vec![
+                    descriptor(Provider::Ollama, "qwen"),
+                    descriptor(Provider::Ollama, "llama"),
+                    descriptor(Provider::OpenAiCompatible, "gpt"),
+                ],
+                call_counter: Some(Arc::clone(&calls)),
+            },
+            CountingCache::default(),
+        );
+        assert_eq!(
+            router
+                .translator
+                .resolved_chain(ProviderSelection::Auto, Some("big")),
+            vec![
+                descriptor(Provider::Ollama, "big"),
+                descriptor(Provider::OpenAiCompatible, "big"),
+            ]
+        );
+
+        let mut req = pseudo_request();
+        req.model_override = Some("big".to_string());
+        router
+            .compile(&req)
+            .expect("first compile should translate");
+        assert_eq!(router.cache.lookups.load(Ordering::SeqCst), 2);
+
+        let cached = router.compile(&req).expect("second compile should hit");
+        assert_eq!(cached.metadata.model.as_deref(), Some("big"));
+        assert_eq!(calls.load(Ordering::SeqCst), 1, "served from the cache");
+        assert_eq!(router.cache.lookups.load(Ordering::SeqCst), 4);
+
+        // Without the override each candidate is looked up under its own model.
+        router
+            .compile(&pseudo_request())
+            .expect("unoverridden compile should translate");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(router.cache.lookups.load(Ordering::SeqCst), 7);
+    }
+
+    #[test]
+    fn cache_key_strategy_decides_which_fields_are_keyed() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let temp = tempdir().expect("tempdir should work");
+        let router = |tenant: &'static str| {
+            CompilerRouter::new(
+                MockTranslator {
+                    fail: false,
+                    response_js: "console.log('shared')".to_string(),
+                    provider: Provider::Ollama,
+                    model: "qwen".to_string(),
+                    chain: vec![ProviderDescriptor {
+                        provider: Provider::Ollama,
+                        model: "qwen".to_string(),
+                    }],
+                    call_counter: Some(counter.clone()),
+                },
+                FileCompileCache::new(PathBuf::from(temp.path())),
+            )
+            .with_cache_keys(TenantContentKey(tenant))
+        };
+
+        let mut req = pseudo_request();
+        req.no_cache = false;
+        let acme = router("acme");
+        acme.compile(&req).expect("first compile should pass");
+        req.source_id = "copy.pseudo".to_string();
+        let cached = acme.compile(&req).expect("second compile should pass");
+        assert!(
+            cached.metadata.cache_hit,
+            "source_id is not part of the key"
+        );
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+
+        router("globex")
+            .compile(&req)
+            .expect("other tenant compile should pass");
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+    }
+
+    /// Letter counts: sources with the same letters point the same way.
+    struct LetterEmbedder;
+
+    impl Embedder for LetterEmbedder {
+        fn embed(&self, text: &str) -> Result<Vec<f32>> {
+            let mut counts = vec![0.0; 26];
+            for c in text.chars().filter(char::is_ascii_lowercase) {
+                counts[(c as u8 - b'a') as usize] += 1.0;
+            }
+            Ok(counts)
+        }
+    }
+
+    #[test]
+    fn similar_sources_reuse_a_cached_translation_and_report_the_score() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let temp = tempdir().expect("tempdir should work");
+        let router = CompilerRouter::new(
+            MockTranslator {
+                fail: false,
+                response_js: "console.log('hello')".to_string(),
+                provider: Provider::Ollama,
+                model: "qwen".to_string(),
+                chain: vec![ProviderDescriptor {
+                    provider: Provider::Ollama,
+                    model: "qwen".to_string(),
+                }],
+                call_counter: Some(counter.clone()),
+            },
+            FileCompileCache::new(temp.path().to_path_buf()),
+        )
+        .with_embeddings(Some(EmbeddingIndex::new(
+            EmbeddingIndex::root_in(temp.path()),
+            LetterEmbedder,
+            0.99,
+        )));
+
+        let first = router
+            .compile(&pseudo_request())
+            .expect("first compile should pass");
+        assert_eq!(first.metadata.similarity, None);
+
+        let edited = CompileRequest {
+            source_text: "write   hello!".to_string(),
+            source_id: "edited.pseudo".to_string(),
+            ..pseudo_request()
+        };
+        let reused = router
+            .compile(&edited)
+            .expect("similar compile should pass");
+        assert!(reused.metadata.cache_hit);
+        assert_eq!(reused.javascript, "console.log('hello')");
+        assert!(
+            reused
+                .metadata
+                .similarity
+                .is_some_and(|score| score > 0.999)
+        );
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+
+        let other = CompileRequest {
+            source_text: "add two numbers".to_string(),
+            ..pseudo_request()
+        };
+        let fresh = router.compile(&other).expect("other compile should pass");
+        assert!(!fresh.metadata.cache_hit);
+        assert_eq!(fresh.metadata.similarity, None);
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+
+        let routed = CompileRequest {
+            model_override: Some("other".to_string()),
+            ..edited
+        };
+        router
+            .compile(&routed)
+            .expect("other model compile should pass");
+        assert_eq!(
+            counter.load(Ordering::SeqCst),
+            3,
+            "another model does not share similar translations"
+        );
+        assert_eq!(
+            FileCompileCache::new(temp.path().to_path_buf())
+                .entries()
+                .expect("entries")
+                .len(),
+            3,
+            "the index inside the cache dir is not an entry"
+        );
+
+        let deterministic = router.with_deterministic(true);
+        let checked = deterministic
+            .compile(&CompileRequest {
+                source_text: "write hello !".to_string(),
+                ..pseudo_request()
+            })
+            .expect("deterministic compile should pass");
+        assert_eq!(checked.metadata.similarity, None);
+        assert_eq!(counter.load(Ordering::SeqCst), 5);
+
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 2.0]), 0.0);
+        assert!((cosine_similarity(&[1.0, 1.0], &[2.0, 2.0]) - 1.0).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn file_cache_lists_describes_and_removes_entries() {
+        let temp = tempdir().expect("tempdir should work");
+        let cache = FileCompileCache::new(PathBuf::from(temp.path()));
+        assert!(
+            cache
+                .entries()
+                .expect("empty listing should work")
+                .is_empty()
+        );
+
+        let result = CompileResult {
+            javascript: "1".to_string(),
+            metadata: CompileMetadata {
+                provider: Some(Provider::Custom("corp".to_string())),
+                model: Some("coder".to_string()),
+                served_model: Some("coder-mini".to_string()),
+                prompt_version: PROMPT_VERSION.to_string(),
+                cache_hit: false,
+                notes: Some("kept loop".to_string()),
+                confidence: Some(0.75),
+                explanation: None,
+                signature: None,
+                similarity: None,
+            },
+            source_map: None,
+        };
+        cache.put("bbb", &result).expect("put should work");
+        cache.put("aaa", &result).expect("put should work");
+        std::fs::write(temp.path().join("broken.json"), "{").expect("write should work");
+        std::fs::write(temp.path().join("notes.txt"), "x").expect("write should work");
+
+        let entries = cache.entries().expect("listing should work");
+        let keys: Vec<&str> = entries.iter().map(|entry| entry.key.as_str()).collect();
+        assert_eq!(keys, vec!["aaa", "bbb", "broken"]);
+        assert_eq!(entries[2].record, None);
+
+        let entry = cache
+            .metadata("aaa")
+            .expect("metadata should work")
+            .expect("entry should exist");
+        assert!(entry.size_bytes > 0);
+        assert_eq!(
+            entry.record,
+            Some(CacheRecord {
+                provider: Some(Provider::Custom("corp".to_string())),
+                model: Some("coder".to_string()),
+                prompt_version: PROMPT_VERSION.to_string(),
+            })
+        );
+
+        let cached = cache.get("bbb").expect("cached result should load");
+        assert_eq!(cached.metadata.notes.as_deref(), Some("kept loop"));
+        assert_eq!(cached.metadata.confidence, Some(0.75));
+        assert_eq!(cached.metadata.served_model.as_deref(), Some("coder-mini"));
+
+        assert!(cache.remove("aaa").expect("remove should work"));
+        assert!(!cache.remove("aaa").expect("second remove should work"));
+        assert_eq!(cache.metadata("aaa").expect("metadata should work"), None);
+    }
+
+    fn cached_js(javascript: &str) -> CompileResult {
+        CompileResult {
+            javascript: javascript.to_string(),
+            metadata: CompileMetadata {
+                provider: Some(Provider::Ollama),
+                model: Some("qwen".to_string()),
+                served_model: None,
+                prompt_version: PROMPT_VERSION.to_string(),
+                cache_hit: false,
+                notes: None,
+                confidence: None,
+                explanation: None,
+                signature: None,
+                similarity: None,
+            },
+            source_map: None,
+        }
+    }
+
+    #[test]
+    fn gc_removes_stale_entries_then_least_recently_used_ones() {
+        let temp = tempdir().expect("tempdir should work");
+        let cache = FileCompileCache::new(PathBuf::from(temp.path()));
+        for key in ["old", "cold", "warm"] {
+            cache.put(key, &cached_js("1")).expect("put should work");
+        }
+        let size = cache
+            .metadata("old")
+            .expect("metadata should work")
+            .expect("entry should exist")
+            .size_bytes;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("clock")
+            .as_millis() as u64;
+        let day = 24 * 60 * 60 * 1000;
+        std::fs::write(
+            temp.path().join(LRU_INDEX_FILE),
+            format!(
+                "{} old\n{} cold\n{} warm\n1 gone\n",
+                now - 40 * day,
+                now - 2 * day,
+                now - 1000
+            ),
+        )
+        .expect("write index");
+        let embeddings = EmbeddingIndex::root_in(temp.path());
+        std::fs::create_dir_all(&embeddings).expect("create embeddings dir");
+        std::fs::write(
+            embeddings.join("scope.jsonl"),
+            "{\"key\":\"old\",\"embedding\":[1.0]}\n{\"key\":\"warm\",\"embedding\":[1.0]}\n",
+        )
+        .expect("write embeddings");
+
+        let report = cache
+            .gc(CachePolicy {
+                max_bytes: None,
+                max_age: Some(std::time::Duration::from_secs(30 * 24 * 60 * 60)),
+                other_versions: false,
+            })
+            .expect("gc should work");
+        assert_eq!(
+            report,
+            GcReport {
+                removed: 1,
+                reclaimed_bytes: size,
+                kept: 2,
+                kept_bytes: 2 * size,
+                other_versions: 0,
+            }
+        );
+        let indexed = std::fs::read_to_string(embeddings.join("scope.jsonl")).expect("embeddings");
+        assert_eq!(indexed, "{\"key\":\"warm\",\"embedding\":[1.0]}\n");
+
+        // A hit refreshes `cold`, so `warm` is now the least recently used.
+        assert!(cache.get("cold").is_some());
+        let report = cache
+            .gc(CachePolicy {
+                max_bytes: Some(size),
+                max_age: None,
+                other_versions: false,
+            })
+            .expect("gc should work");
+        assert_eq!((report.removed, report.kept), (1, 1));
+        let keys: Vec<String> = cache
+            .entries()
+            .expect("listing should work")
+            .into_iter()
+            .map(|entry| entry.key)
+            .collect();
+        assert_eq!(keys, vec!["cold"]);
+        let index = std::fs::read_to_string(temp.path().join(LRU_INDEX_FILE)).expect("index");
+        assert!(index.contains("cold") && !index.contains("gone") && !index.contains("warm"));
+        assert!(!embeddings.join("scope.jsonl").exists());
+    }
+
+    #[test]
+    fn gc_drops_entries_from_other_prompt_versions_only_on_request() {
+        let temp = tempdir().expect("tempdir should work");
+        let cache = FileCompileCache::new(PathBuf::from(temp.path()));
+        cache
+            .put("current", &cached_js("1"))
+            .expect("put should work");
+        let mut outdated = cached_js("1");
+        outdated.metadata.prompt_version = format!("{PROMPT_VERSION}-other");
+        cache.put("outdated", &outdated).expect("put should work");
+        let size = cache
+            .metadata("current")
+            .expect("metadata should work")
+            .expect("entry should exist")
+            .size_bytes;
+
+        // Another klumo version may still read it, and it does not count towards the limits.
+        let report = cache
+            .gc(CachePolicy {
+                max_bytes: Some(size),
+                ..CachePolicy::default()
+            })
+            .expect("gc should work");
+        assert_eq!(
+            (report.removed, report.kept, report.other_versions),
+            (0, 1, 1)
+        );
+        assert_eq!(cache.entries().expect("listing should work").len(), 2);
+
+        // Recently used, yet unreachable under the current prompt version.
+        assert!(cache.get("outdated").is_some());
+        let report = cache
+            .gc(CachePolicy {
+                other_versions: true,
+                ..CachePolicy::default()
+            })
+            .expect("gc should work");
+        assert_eq!(
+            (report.removed, report.kept, report.other_versions),
+            (1, 1, 0)
+        );
+        let keys: Vec<String> = cache
+            .entries()
+            .expect("listing should work")
+            .into_iter()
+            .map(|entry| entry.key)
+            .collect();
+        assert_eq!(keys, vec!["current"]);
+    }
+
+    #[test]
+    fn put_prunes_to_the_cache_policy() {
+        let temp = tempdir().expect("tempdir should work");
+        let probe = FileCompileCache::new(temp.path().join("probe"));
+        probe.put("k", &cached_js("1")).expect("put should work");
+        let size = probe
+            .metadata("k")
+            .expect("metadata should work")
+            .expect("entry should exist")
+            .size_bytes;
+
+        let cache = FileCompileCache::new(temp.path().join("cache")).with_policy(CachePolicy {
+            max_bytes: Some(2 * size),
+            max_age: None,
+            other_versions: false,
+        });
+        for key in ["a", "b", "c", "d"] {
+            cache.put(key, &cached_js("1")).expect("put should work");
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        let entries = cache.entries().expect("listing should work");
+        assert_eq!(entries.len(), 2);
+        assert!(cache.get("d").is_some());
+        assert!(cache.get("a").is_none());
+    }
+
+    #[test]
+    fn puts_under_the_size_limit_only_bump_the_counter() {
+        let temp = tempdir().expect("tempdir should work");
+        let cache = FileCompileCache::new(PathBuf::from(temp.path())).with_policy(CachePolicy {
+            max_bytes: Some(1 << 20),
+            max_age: None,
+            other_versions: false,
+        });
+        let stats = || -> serde_json::Value {
+            let raw = std::fs::read_to_string(temp.path().join("index.stats")).expect("stats");
+            serde_json::from_str(&raw).expect("stats should parse")
+        };
+        // The first bounded put collects to measure the cache.
+        cache.put("a", &cached_js("1")).expect("put should work");
+        let collected_at = stats()["collected_at"].clone();
+        let size = cache
+            .metadata("a")
+            .expect("metadata should work")
+            .expect("entry should exist")
+            .size_bytes;
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        cache.put("b", &cached_js("1")).expect("put should work");
+        cache.put("c", &cached_js("1")).expect("put should work");
+        assert_eq!(stats()["collected_at"], collected_at);
+        assert_eq!(stats()["entry_bytes"], 3 * size);
+
+        // A collection measures the entries again, including ones removed behind its back.
+        cache.remove("b").expect("remove should work");
+        let report = cache.prune().expect("prune should work");
+        assert_eq!((report.removed, report.kept), (0, 2));
+        assert_eq!(stats()["entry_bytes"], 2 * size);
+    }
+
+    #[test]
+    fn hits_append_to_the_index_until_gc_compacts_it() {
+        let temp = tempdir().expect("tempdir should work");
+        let cache = FileCompileCache::new(PathBuf::from(temp.path()));
+        cache.put("k", &cached_js("1")).expect("put should work");
+        for _ in 0..3 {
+            assert!(cache.get("k").is_some());
+        }
+        let index_path = temp.path().join(LRU_INDEX_FILE);
+        let index = std::fs::read_to_string(&index_path).expect("index should exist");
+        assert_eq!(index.lines().count(), 4);
+
+        cache.gc(CachePolicy::default()).expect("gc should work");
+        let index = std::fs::read_to_string(&index_path).expect("index should exist");
+        assert_eq!(index.lines().count(), 1);
+        assert!(index.ends_with(" k\n"));
+    }
+
+    #[test]
+    fn hits_compact_the_index_once_it_outgrows_its_slack() {
+        let temp = tempdir().expect("tempdir should work");
+        let cache = FileCompileCache::new(PathBuf::from(temp.path()));
+        // Long keys fill the 64 KiB slack in a few hundred hits.
+        let key = "k".repeat(200);
+        cache.put(&key, &cached_js("1")).expect("put should work");
+        let hits = 400;
+        for _ in 0..hits {
+            assert!(cache.get(&key).is_some());
+        }
+        let index =
+            std::fs::read_to_string(temp.path().join(LRU_INDEX_FILE)).expect("index should exist");
+        assert!(
+            index.lines().count() < hits,
+            "index was never compacted: {} lines",
+            index.lines().count()
+        );
+        assert!(index.ends_with(&format!(" {key}\n")));
+    }
+
+    struct SelectionRecorder {
+        seen: Mutex<Vec<ProviderSelection>>,
+    }
+
+    impl TranslationService for SelectionRecorder {
+        fn candidate_chain(&self, selection: ProviderSelection) -> Vec<ProviderDescriptor> {
+            self.seen.lock().expect("lock must work").push(selection);
+            Vec::new()
+        }
+
+        fn translate(
+            &self,
+            selection: ProviderSelection,
+            _req: &LlmTranslateRequest,
+            _model_override: Option<&str>,
+        ) -> Result<LlmTranslateResponse> {
+            self.seen.lock().expect("lock must work").push(selection);
+            Ok(LlmTranslateResponse {
+                javascript: "1".to_string(),
+                provider: Provider::OpenAiCompatible,
+                model: "gpt".to_string(),
+                served_model: None,
+                notes: None,
+                confidence: None,
+                explanation: None,
+            })
+        }
+    }
+
+    #[test]
+    fn provider_override_replaces_session_selection() {
+        let router = CompilerRouter::new(
+            SelectionRecorder {
+                seen: Mutex::new(Vec::new()),
+            },
+            MemoryCache::default(),
+        );
+
+        let mut req = pseudo_request();
+        req.provider_selection = ProviderSelection::Ollama;
+        req.provider_override = Some(ProviderSelection::OpenAiCompatible);
+        router.compile(&req).expect("compile should pass");
+
+        assert_eq!(
+            *router.translator.seen.lock().expect("lock must work"),
+            vec![
+                ProviderSelection::OpenAiCompatible,
+                ProviderSelection::OpenAiCompatible
+            ]
+        );
+    }
+
+    struct ScriptedTranslator {
+        outputs: Mutex<Vec<&'static str>>,
+        repair_hints: Mutex<Vec<Option<String>>>,
+        scopes: Mutex<Vec<Option<String>>>,
+    }
+
+    impl TranslationService for ScriptedTranslator {
+        fn candidate_chain(&self, _selection: ProviderSelection) -> Vec<ProviderDescriptor> {
+            Vec::new()
+        }
+
+        fn translate(
+            &self,
+            _selection: ProviderSelection,
+            req: &LlmTranslateRequest,
+            _model_override: Option<&str>,
+        ) -> Result<LlmTranslateResponse> {
+            self.repair_hints
+                .lock()
+                .expect("lock must work")
+                .push(req.repair_hint.clone());
+            self.scopes
+                .lock()
+                .expect("lock must work")
+                .push(req.scope_context.clone());
+            Ok(LlmTranslateResponse {
+                javascript: self
+                    .outputs
+                    .lock()
+                    .expect("lock must work")
+                    .remove(0)
+                    .to_string(),
+                provider: Provider::Ollama,
+                model: "qwen".to_string(),
+                served_model: None,
+                notes: None,
+                confidence: None,
+                explanation: None,
+            })
+        }
+    }
+
+    #[test]
+    fn unparseable_output_is_reprompted_with_parse_error() {
+        let router = CompilerRouter::new(
+            ScriptedTranslator {
+                outputs: Mutex::new(vec!["let = ;", "let x = 1;"]),
+                repair_hints: Mutex::new(Vec::new()),
+                scopes: Mutex::new(Vec::new()),
+            },
+            MemoryCache::default(),
+        );
+
+        let result = router
+            .compile(&pseudo_request())
+            .expect("second answer should validate");
+        assert_eq!(result.javascript, "let x = 1;");
+
+        let hints = router
+            .translator
+            .repair_hints
+            .lock()
+            .expect("lock must work");
+        assert_eq!(hints[0], None);
+        let hint = hints[1].as_deref().expect("re-prompt should carry a hint");
+        assert!(hint.contains("SyntaxError"));
+        assert!(hint.contains("let = ;"));
+    }
+
+    #[test]
+    fn seeded_sampling_gets_its_own_cache_key() {
+        let key = |seed| {
+            Sha256CacheKey.key(&CacheKeyInput {
+                source_text: "write 1",
+                source_id: "a.pseudo",
+                kind_hint: "pseudocode",
+                provider: &Provider::Ollama,
+                model: "qwen",
+                sampling: &SamplingOptions {
+                    temperature: Some(0.0),
+                    seed,
+                    ..SamplingOptions::default()
+                },
+                prompt_fingerprint: "",
+                scope_context: None,
+                passes: "",
+            })
+        };
+        assert_ne!(key(None), key(Some(42)));
+        assert_ne!(key(Some(7)), key(Some(42)));
+        assert_eq!(key(Some(42)), key(Some(42)));
+    }
+
+    #[test]
+    fn deterministic_mode_translates_twice_and_rejects_differing_outputs() {
+        let router = |outputs: Vec<&'static str>| {
+            CompilerRouter::new(
+                ScriptedTranslator {
+                    outputs: Mutex::new(outputs),
+                    repair_hints: Mutex::new(Vec::new()),
+                    scopes: Mutex::new(Vec::new()),
+                },
+                MemoryCache::default(),
+            )
+            .with_deterministic(true)
+        };
+
+        let stable = router(vec!["let x = 1;", "let x = 1;"]);
+        let result = stable
+            .compile(&pseudo_request())
+            .expect("matching translations should pass");
+        assert_eq!(result.javascript, "let x = 1;");
+        assert_eq!(
+            stable.translator.repair_hints.lock().expect("lock").len(),
+            2
+        );
+        assert_eq!(stable.cache.map.lock().expect("lock").len(), 1);
+
+        let unstable = router(vec!["let x = 1;\n", "let x = 2;\n"]);
+        let err = unstable
+            .compile(&pseudo_request())
+            .expect_err("differing translations should fail");
+        let message = err.to_string();
+        assert!(message.contains("is not deterministic"));
+        assert!(message.contains("-let x = 1;") && message.contains("+let x = 2;"));
+        assert!(unstable.cache.map.lock().expect("lock").is_empty());
+    }
+
+    #[test]
+    fn persistent_syntax_errors_fail_without_caching() {
+        let router = CompilerRouter::new(
+            ScriptedTranslator {
+                outputs: Mutex::new(vec!["let = ;", "function (", "if {"]),
+                repair_hints: Mutex::new(Vec::new()),
+                scopes: Mutex::new(Vec::new()),
+            },
+            MemoryCache::default(),
+        );
+
+        let err = router
+            .compile(&pseudo_request())
+            .expect_err("invalid output should be rejected");
+        assert!(
+            err.to_string()
+                .contains("does not parse after 2 repair attempt(s)")
+        );
+        let hints = router.translator.repair_hints.lock().expect("lock");
+        assert_eq!(hints.len(), 1 + VALIDATION_RETRIES);
+        // Each repair prompt carries the code the previous answer produced.
+        assert!(
+            hints[2]
+                .as_deref()
+                .is_some_and(|hint| hint.contains("function ("))
+        );
+        assert!(router.cache.map.lock().expect("lock must work").is_empty());
+    }
+
+    #[test]
+    fn oversized_sources_are_translated_in_chunks_and_stitched() {
+        let router = CompilerRouter::new(
+            ScriptedTranslator {
+                outputs: Mutex::new(vec!["let a = 1;", "let b = a + 1;"]),
+                repair_hints: Mutex::new(Vec::new()),
+                scopes: Mutex::new(Vec::new()),
+            },
+            MemoryCache::default(),
+        );
+        let mut req = pseudo_request();
+        req.source_text = "a = 1\nb = a + 1\n".to_string();
+        req.context_window = Some(6);
+
+        let result = router.compile(&req).expect("chunks should stitch");
+        assert_eq!(result.javascript, "let a = 1;\nlet b = a + 1;");
+
+        let scopes = router.translator.scopes.lock().expect("lock must work");
+        assert_eq!(scopes.len(), 2);
+        let second = scopes[1].as_deref().expect("later chunks carry context");
+        assert!(second.contains("part 2 of 2 of sample.pseudo"));
+        assert!(second.contains("let a = 1;"));
+        assert_eq!(router.cache.map.lock().expect("lock must work").len(), 1);
+    }
+
+    #[test]
+    fn stitched_chunks_that_do_not_parse_together_are_repaired() {
+        let router = CompilerRouter::new(
+            ScriptedTranslator {
+                outputs: Mutex::new(vec!["let a = 1;", "let a = 2;", "a = 2;"]),
+                repair_hints: Mutex::new(Vec::new()),
+                scopes: Mutex::new(Vec::new()),
+            },
+            MemoryCache::default(),
+        );
+        let mut req = pseudo_request();
+        req.source_text = "a = 1\na = 2\n".to_string();
+        req.context_window = Some(6);
+
+        let result = router
+            .compile(&req)
+            .expect("stitched program should be repaired");
+        assert_eq!(result.javascript, "let a = 1;\na = 2;");
+        let hints = router
+            .translator
+            .repair_hints
+            .lock()
+            .expect("lock must work");
+        // Only the part that broke the join is sent back, with the code before it as context.
+        let hint = hints[2].as_deref().expect("repair carries a hint");
+        assert!(
+            hint.contains("Syntax error once joined") && hint.contains("declared multiple times")
+        );
+        assert!(hint.contains("let a = 2;") && !hint.contains("let a = 1;"));
+        let scopes = router.translator.scopes.lock().expect("lock must work");
+        let scope = scopes[2]
+            .as_deref()
+            .expect("repair carries the JavaScript before it");
+        assert!(scope.contains("part 2 of 2") && scope.contains("let a = 1;"));
+    }
+
+    #[test]
+    fn stitched_chunks_that_stay_broken_name_the_failing_part() {
+        let router = CompilerRouter::new(
+            ScriptedTranslator {
+                outputs: Mutex::new(vec!["let a = 1;", "let a = 2;", "let a = 3;", "let a = 4;"]),
+                repair_hints: Mutex::new(Vec::new()),
+                scopes: Mutex::new(Vec::new()),
+            },
+            MemoryCache::default(),
+        );
+        let mut req = pseudo_request();
+        req.source_text = "a = 1\na = 2\n".to_string();
+        req.context_window = Some(6);
+
+        let err = router
+            .compile(&req)
+            .expect_err("parts that never fit should fail");
+        assert!(
+            err.to_string()
+                .contains("where part 2 of 2 joins the parts before it, after 2 repair attempt(s)")
+        );
+        assert!(router.cache.map.lock().expect("lock must work").is_empty());
+    }
+
+    /// Explains its translation only when asked to, recording each request's flags and
+    /// whether it was shown existing JavaScript to explain. Its JavaScript differs on every
+    /// call, so a compile that keeps the cached code is easy to tell apart.
+    #[derive(Default)]
+    struct ExplainingTranslator {
+        requests: Mutex<Vec<(bool, bool, bool)>>,
+    }
+
+    impl TranslationService for ExplainingTranslator {
+        fn candidate_chain(&self, _selection: ProviderSelection) -> Vec<ProviderDescriptor> {
+            vec![ProviderDescriptor {
+                provider: Provider::Ollama,
+                model: "qwen".to_string(),
+            }]
+        }
+
+        fn translate(
+            &self,
+            _selection: ProviderSelection,
+            req: &LlmTranslateRequest,
+            _model_override: Option<&str>,
+        ) -> Result<LlmTranslateResponse> {
+            let mut requests = self.requests.lock().expect("lock must work");
+            requests.push((
+                req.explain,
+                req.structured_output,
+                req.explain_javascript.is_some(),
+            ));
+            Ok(LlmTranslateResponse {
+                javascript: format!("console.log('hello {}')", requests.len()),
+                provider: Provider::Ollama,
+                model: "qwen".to_string(),
+                served_model: None,
+                notes: None,
+                confidence: None,
+                explanation: req.explain.then(|| Explanation {
+                    rationale: "print became console.log".to_string(),
+                    mappings: vec![ConstructMapping {
+                        source: "write hello".to_string(),
+                        generated: "console.log('hello')".to_string(),
+                    }],
+                }),
+            })
+        }
+    }
+
+    #[test]
+    fn explanations_of_cached_entries_keep_their_javascript() {
+        let temp = tempdir().expect("tempdir should work");
+        let router = CompilerRouter::new(
+            ExplainingTranslator::default(),
+            FileCompileCache::new(PathBuf::from(temp.path())),
+        );
+
+        let plain = router
+            .compile(&pseudo_request())
+            .expect("plain compile should pass");
+        assert_eq!(plain.metadata.explanation, None);
+
+        let explain = CompileRequest {
+            explain: true,
+            ..pseudo_request()
+        };
+        let explained = router
+            .compile(&explain)
+            .expect("explained compile should pass");
+        assert!(explained.metadata.cache_hit);
+        assert_eq!(explained.javascript, plain.javascript);
+        let explanation = explained
+            .metadata
+            .explanation
+            .expect("the cached entry is explained");
+        assert_eq!(explanation.rationale, "print became console.log");
+
+        // The explanation was stored with the entry, which still runs the same code.
+        let cached = router
+            .compile(&explain)
+            .expect("cached compile should pass");
+        assert!(cached.metadata.explanation.is_some());
+        let rerun = router
+            .compile(&pseudo_request())
+            .expect("plain compile should pass");
+        assert_eq!(rerun.javascript, plain.javascript);
+        assert_eq!(
+            *router.translator.requests.lock().expect("lock must work"),
+            vec![(false, false, false), (true, true, true)]
+        );
+    }
+
+    /// Records the language hint of every translation and names unrecognized sources `cobol`.
+    #[derive(Default)]
+    struct ClassifyingTranslator {
+        hints: Mutex<Vec<Option<String>>>,
+        classified: AtomicUsize,
+    }
+
+    impl TranslationService for ClassifyingTranslator {
+        fn candidate_chain(&self, _selection: ProviderSelection) -> Vec<ProviderDescriptor> {
+            Vec::new()
+        }
+
+        fn translate(
+            &self,
+            _selection: ProviderSelection,
+            req: &LlmTranslateRequest,
+            _model_override: Option<&str>,
+        ) -> Result<LlmTranslateResponse> {
+            self.hints
+                .lock()
+                .expect("lock must work")
+                .push(req.language_hint.clone());
+            Ok(LlmTranslateResponse {
+                javascript: "console.log('ok');".to_string(),
+                provider: Provider::Ollama,
+                model: "qwen".to_string(),
+                served_model: None,
+                notes: None,
+                confidence: None,
+                explanation: None,
+            })
+        }
+
+        fn classify_language(
+            &self,
+            _selection: ProviderSelection,
+            _source: &str,
+        ) -> Option<String> {
+            self.classified.fetch_add(1, Ordering::SeqCst);
+            Some("cobol".to_string())
+        }
+    }
+
+    #[test]
+    fn auto_sources_without_an_extension_are_sniffed_then_classified() {
+        let router = |classify_language| {
+            CompilerRouter::new(ClassifyingTranslator::default(), MemoryCache::default())
+                .with_classify_language(classify_language)
+        };
+        let auto = |source_id: &str, source_text: &str| CompileRequest {
+            source_text: source_text.to_string(),
+            source_id: source_id.to_string(),
+            kind_hint: Some(SourceKind::Auto),
+            language_hint: None,
+            ..pseudo_request()
+        };
+        let classifying = router(true);
+        for (source_id, source_text) in [
+            ("bin/report", "#!/usr/bin/env python3\nprint('hi')\n"),
+            ("notes.txt", "SET x TO 1\nDISPLAY x\n"),
+            ("notes.txt", "IDENTIFICATION DIVISION.\n"),
+            ("main.rb", "puts 'hi'\n"),
+        ] {
+            classifying
+                .compile(&auto(source_id, source_text))
+                .expect("compile should pass");
+        }
+        assert_eq!(
+            *classifying.translator.hints.lock().expect("lock must work"),
+            ["python", "pseudocode", "cobol", "rb"].map(|hint| Some(hint.to_string()))
+        );
+        assert_eq!(classifying.translator.classified.load(Ordering::SeqCst), 1);
+
+        let sniffing = router(false);
+        sniffing
+            .compile(&auto("notes.txt", "IDENTIFICATION DIVISION.\n"))
+            .expect("compile should pass");
+        assert_eq!(
+            *sniffing.translator.hints.lock().expect("lock must work"),
+            [Some("txt".to_string())]
+        );
+        assert_eq!(sniffing.translator.classified.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn classified_languages_are_remembered_across_routers() {
+        let temp = tempdir().expect("tempdir should work");
+        let router = || {
+            CompilerRouter::new(ClassifyingTranslator::default(), MemoryCache::default())
+                .with_classify_language(true)
+                .with_language_cache(Some(LanguageCache::new(temp.path().to_path_buf())))
+        };
+        let req = CompileRequest {
+            source_text: "IDENTIFICATION DIVISION.\n".to_string(),
+            source_id: "notes.txt".to_string(),
+            kind_hint: Some(SourceKind::Auto),
+            language_hint: None,
+            ..pseudo_request()
+        };
+        let first = router();
+        first.compile(&req).expect("compile should pass");
+        assert_eq!(first.translator.classified.load(Ordering::SeqCst), 1);
+
+        let second = router();
+        second.compile(&req).expect("compile should pass");
+        assert_eq!(second.translator.classified.load(Ordering::SeqCst), 0);
+        assert_eq!(
+            *second.translator.hints.lock().expect("lock must work"),
+            [Some("cobol".to_string())]
+        );
+    }
+
+    /// Answers each unit with a `console.log` of its first line, recording what was sent.
+    #[derive(Default)]
+    struct UnitTranslator {
+        sources: Mutex<Vec<String>>,
+    }
+
+    impl TranslationService for UnitTranslator {
+        fn candidate_chain(&self, _selection: ProviderSelection) -> Vec<ProviderDescriptor> {
+            Vec::new()
+        }
+
+        fn translate(
+            &self,
+            _selection: ProviderSelection,
+            req: &LlmTranslateRequest,
+            _model_override: Option<&str>,
+        ) -> Result<LlmTranslateResponse> {
+            self.sources
+                .lock()
+                .expect("lock must work")
+                .push(req.source_text.clone());
+            let first = req.source_text.lines().next().unwrap_or_default();
+            Ok(LlmTranslateResponse {
+                javascript: format!("console.log({first:?});"),
+                provider: Provider::Ollama,
+                model: "qwen".to_string(),
+                served_model: None,
+                notes: None,
+                confidence: None,
+                explanation: None,
+            })
+        }
+    }
+
+    #[test]
+    fn incremental_compiles_only_translate_changed_units() {
+        let temp = tempdir().expect("tempdir should work");
+        let router = CompilerRouter::new(UnitTranslator::default(), MemoryCache::default())
+            .with_incremental(Some(ChunkMapStore::new(temp.path().to_path_buf())));
+        let source = "def a():\n    return 1\n\ndef b():\n    return 2\n\nprint a()\n";
+        let compile = |text: &str| {
+            router
+                .compile(&CompileRequest {
+                    source_text: text.to_string(),
+                    ..pseudo_request()
+                })
+                .expect("compile should pass")
+        };
+
+        // Without a chunk map there is nothing to reuse, so the file is translated whole.
+        assert_eq!(compile(source).javascript, "console.log(\"def a():\");");
+        assert_eq!(router.translator.sources.lock().expect("lock").len(), 1);
+
+        let edited = source.replace("return 2", "return 22");
+        let second = compile(&edited);
+        assert!(!second.metadata.cache_hit);
+        assert_eq!(
+            second.javascript,
+            "console.log(\"def a():\");\nconsole.log(\"def b():\");\nconsole.log(\"print a()\");"
+        );
+        assert_eq!(router.translator.sources.lock().expect("lock").len(), 4);
+
+        let third = compile(&edited.replace("return 22", "return 222"));
+        assert_eq!(third.javascript, second.javascript);
+        let sources = router.translator.sources.lock().expect("lock");
+        assert_eq!(sources.len(), 5, "only the edited unit is translated again");
+        assert_eq!(sources[4], "def b():\n    return 222\n\n");
+    }
+
+    /// Takes a while per translation and records how many ran at once, allowing `limit`.
+    struct SlowTranslator {
+        limit: usize,
+        active: AtomicUsize,
+        peak: AtomicUsize,
+    }
+
+    impl TranslationService for SlowTranslator {
+        fn candidate_chain(&self, _selection: ProviderSelection) -> Vec<ProviderDescriptor> {
+            Vec::new()
+        }
+
+        fn translate(
+            &self,
+            _selection: ProviderSelection,
+            req: &LlmTranslateRequest,
+            _model_override: Option<&str>,
+        ) -> Result<LlmTranslateResponse> {
+            let active = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(active, Ordering::SeqCst);
+            std::thread::sleep(std::time::Duration::from_millis(40));
+            self.active.fetch_sub(1, Ordering::SeqCst);
+            Ok(LlmTranslateResponse {
+                javascript: format!("console.log({:?});", req.source_text),
+                provider: Provider::OpenAiCompatible,
+                model: "gpt".to_string(),
+                served_model: None,
+                notes: None,
+                confidence: None,
+                explanation: None,
+            })
+        }
+
+        /// Ollama serves one translation at a time, like a local server behind the router.
+        fn max_concurrency(&self, selection: ProviderSelection) -> usize {
+            match selection {
+                ProviderSelection::Ollama => 1,
+                _ => self.limit,
+            }
+        }
+    }
+
+    #[test]
+    fn compile_many_keeps_request_order_within_jobs_and_provider_limits() {
+        let router = |limit| {
+            CompilerRouter::new(
+                SlowTranslator {
+                    limit,
+                    active: AtomicUsize::new(0),
+                    peak: AtomicUsize::new(0),
+                },
+                MemoryCache::default(),
+            )
+        };
+        let reqs: Vec<CompileRequest> = (0..6)
+            .map(|index| CompileRequest {
+                source_text: format!("write {index}"),
+                source_id: format!("step{index}.pseudo"),
+                ..pseudo_request()
+            })
+            .collect();
+        let outputs = |router: &CompilerRouter<SlowTranslator, MemoryCache>, jobs| {
+            router
+                .compile_many(&reqs, jobs)
+                .into_iter()
+                .map(|result| result.expect("compile should pass").javascript)
+                .collect::<Vec<_>>()
+        };
+        let expected: Vec<String> = (0..6)
+            .map(|index| format!("console.log(\"write {index}\");"))
+            .collect();
+
+        let remote = router(4);
+        assert_eq!(outputs(&remote, 3), expected);
+        assert_eq!(remote.translator.peak.load(Ordering::SeqCst), 3);
+
+        let local = router(1);
+        assert_eq!(outputs(&local, 8), expected);
+        assert_eq!(
+            local.translator.peak.load(Ordering::SeqCst),
+            1,
+            "the provider limit caps --jobs"
+        );
+    }
+
+    #[test]
+    fn compile_many_limits_by_the_provider_a_language_route_pins() {
+        let router = CompilerRouter::new(
+            SlowTranslator {
+                limit: 4,
+                active: AtomicUsize::new(0),
+                peak: AtomicUsize::new(0),
+            },
+            MemoryCache::default(),
+        )
+        .with_language_routes(vec![LanguageRoute {
+            language: "pseudocode".to_string(),
+            provider: Some(ProviderSelection::Ollama),
+            model: None,
+        }]);
+        let reqs: Vec<CompileRequest> = (0..4)
+            .map(|index| CompileRequest {
+                source_text: format!("write {index}"),
+                source_id: format!("step{index}.pseudo"),
+                provider_selection: ProviderSelection::OpenAiCompatible,
+                ..pseudo_request()
+            })
+            .collect();
+        for result in router.compile_many(&reqs, 4) {
+            result.expect("compile should pass");
+        }
+        assert_eq!(
+            router.translator.peak.load(Ordering::SeqCst),
+            1,
+            "the routed provider's limit applies, not the session's"
+        );
+    }
 }
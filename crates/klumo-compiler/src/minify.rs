@@ -0,0 +1,126 @@
+use crate::typescript::diagnostic_messages;
+use crate::{CompilePass, CompileRequest, CompileResult};
+use anyhow::{Result, bail};
+use oxc_allocator::Allocator;
+use oxc_codegen::{Codegen, CodegenOptions};
+use oxc_minifier::{Minifier, MinifierOptions};
+use oxc_parser::Parser;
+use oxc_span::SourceType;
+
+/// Minifies `javascript` with oxc: the compressor folds and drops dead code, the mangler
+/// shortens local names, and the result is printed without whitespace or comments. Names in
+/// a script's top-level scope are globals other scripts may read, so they keep their names;
+/// engine errors about locals name the shortened bindings. A leading `#!` line is kept.
+pub fn minify(javascript: &str) -> Result<String> {
+    let allocator = Allocator::default();
+    let parsed = Parser::new(&allocator, javascript, SourceType::unambiguous()).parse();
+    if parsed.panicked || !parsed.diagnostics.is_empty() {
+        bail!(
+            "the JavaScript does not parse: {}",
+            diagnostic_messages(&parsed.diagnostics)
+        );
+    }
+    let mut program = parsed.program;
+    let minified = Minifier::new(MinifierOptions::default()).minify(&allocator, &mut program);
+    Ok(Codegen::new()
+        .with_options(CodegenOptions::minify())
+        .with_scoping(minified.scoping)
+        .with_private_member_mappings(minified.class_private_mappings)
+        .build(&program)
+        .code)
+}
+
+/// [`minify`] as a pipeline step: `finish` minifies fresh translations and JavaScript passed
+/// through, before they are signed or cached. Its lines no longer match the source, so
+/// results it finished have no source map.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MinifyPass;
+
+impl CompilePass for MinifyPass {
+    fn name(&self) -> String {
+        "minify".to_string()
+    }
+
+    fn moves_lines(&self) -> bool {
+        true
+    }
+
+    fn finish(&self, req: &CompileRequest, result: &mut CompileResult) -> Result<()> {
+        result.javascript = minify(&result.javascript)
+            .map_err(|err| err.context(format!("failed minifying {}", req.source_id)))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::minify;
+    use klumo_engine::{BoaEngine, JsEngine};
+
+    fn run(javascript: &str) -> Option<String> {
+        let mut engine = BoaEngine::new().expect("engine should initialize");
+        engine
+            .eval_script(javascript, "minified.js")
+            .expect("minified program should run")
+            .value
+    }
+
+    #[test]
+    fn minify_drops_comments_and_whitespace() {
+        let source = "// greet someone\n\
+                      const name = 'klumo' /* inline */ ;\n\
+                      function greet ( who ) {\n\
+                      \x20   return `hello ${ who }` + ' ' + (1 .toString())\n\
+                      }\n\
+                      let total = 1\n\
+                      total = total + +2\n\
+                      ++total\n\
+                      greet(name) + total\n";
+        let minified = minify(source).expect("source should minify");
+        assert!(!minified.contains("greet someone"), "{minified}");
+        assert!(!minified.contains("inline"), "{minified}");
+        assert!(minified.lines().count() <= 1, "{minified}");
+        assert_eq!(run(&minified).as_deref(), Some("hello klumo 14"));
+
+        let hashbang = minify("#!/usr/bin/env node\nconsole.log(1)\n").expect("minify");
+        assert!(hashbang.starts_with("#!/usr/bin/env node\n"), "{hashbang}");
+    }
+
+    #[test]
+    fn minify_keeps_what_line_breaks_after_restricted_keywords_mean() {
+        let source = "function f() {\n  return\n  -1\n}\n\
+                      function g() {\n  return\n  (2)\n}\n\
+                      function h() {\n  return\n  `t`\n}\n\
+                      function* n() {\n  yield\n  [3]\n}\n\
+                      for (;;) {\n  break\n  +4\n}\n\
+                      [f(), g(), h(), n().next().value].map(String).join(' ')\n";
+        let minified = minify(source).expect("source should minify");
+        assert_eq!(
+            run(&minified).as_deref(),
+            Some("undefined undefined undefined undefined")
+        );
+    }
+
+    #[test]
+    fn minify_compresses_and_mangles_locals_but_not_globals() {
+        let source = "function describeTotal(firstValue, secondValue) {\n\
+                      \x20   const combinedValue = firstValue + secondValue;\n\
+                      \x20   if (false) { console.log('never'); }\n\
+                      \x20   return 'total: ' + combinedValue * (60 * 60);\n\
+                      }\n\
+                      describeTotal(1, 2)\n";
+        let minified = minify(source).expect("source should minify");
+        assert!(minified.contains("describeTotal"), "{minified}");
+        assert!(!minified.contains("firstValue"), "{minified}");
+        assert!(!minified.contains("combinedValue"), "{minified}");
+        assert!(!minified.contains("never"), "{minified}");
+        assert!(minified.contains("3600"), "{minified}");
+        assert_eq!(run(&minified).as_deref(), Some("total: 10800"));
+    }
+
+    #[test]
+    fn javascript_that_does_not_parse_is_rejected() {
+        let err = minify("const = 1;").expect_err("does not parse");
+        assert!(err.to_string().contains("does not parse"));
+    }
+}
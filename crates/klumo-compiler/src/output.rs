@@ -0,0 +1,180 @@
+use crate::{CompileMetadata, CompileResult};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Post-processing for a finished program, applied by `klumo bundle` after linking. The
+/// result is written out, never cached, so the banner's timestamp is the build's own.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputOptions {
+    /// Head the program with a [`provenance_banner`].
+    pub banner: bool,
+    /// Make the whole program strict with a `"use strict"` directive, unless it starts with
+    /// one already.
+    pub strict: bool,
+}
+
+/// `//` lines naming what produced a program from `metadata` (a linked program's entry):
+/// the provider and model that translated it, the prompt version, and the UTC time
+/// `built_at`.
+pub fn provenance_banner(metadata: &CompileMetadata, built_at: SystemTime) -> String {
+    let translated_by = match &metadata.provider {
+        Some(provider) => format!(
+            "{provider}/{}",
+            metadata
+                .served_model
+                .as_deref()
+                .or(metadata.model.as_deref())
+                .unwrap_or("unknown")
+        ),
+        None => "none (passthrough)".to_string(),
+    };
+    format!(
+        "// Generated by klumo: translated by {translated_by}, prompt_version {}\n// Built at {}\n",
+        metadata.prompt_version,
+        utc_timestamp(built_at)
+    )
+}
+
+/// Applies `options` to `compiled`, shifting its source map past the lines added. The
+/// directive goes below the leading comments, so a linked program keeps its header in the
+/// comment block above the code; the banner goes first, below a `#!` line only.
+pub fn finish_output(compiled: &mut CompileResult, options: OutputOptions, built_at: SystemTime) {
+    let lines: Vec<&str> = compiled.javascript.lines().collect();
+    let hashbang = usize::from(lines.first().is_some_and(|line| line.starts_with("#!")));
+    if options.strict && !starts_strict(&lines[hashbang..]) {
+        let comments = lines[hashbang..]
+            .iter()
+            .take_while(|line| line.starts_with("//"))
+            .count();
+        insert_lines(compiled, hashbang + comments, "\"use strict\";\n");
+    }
+    if options.banner {
+        let banner = provenance_banner(&compiled.metadata, built_at);
+        insert_lines(compiled, hashbang, &banner);
+    }
+}
+
+/// Whether the first statement below the leading comments is a `"use strict"` directive.
+fn starts_strict(lines: &[&str]) -> bool {
+    lines
+        .iter()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty() && !line.starts_with("//"))
+        .is_some_and(|line| line.starts_with("\"use strict\"") || line.starts_with("'use strict'"))
+}
+
+/// Inserts `text`, whole lines ending in `\n`, before 0-based line `at`.
+fn insert_lines(compiled: &mut CompileResult, at: usize, text: &str) {
+    let offset = if at == 0 {
+        0
+    } else {
+        compiled
+            .javascript
+            .match_indices('\n')
+            .nth(at - 1)
+            .map_or(compiled.javascript.len(), |(index, _)| index + 1)
+    };
+    if offset == compiled.javascript.len() && !compiled.javascript.is_empty() {
+        if !compiled.javascript.ends_with('\n') {
+            compiled.javascript.push('\n');
+        }
+        compiled.javascript.push_str(text);
+    } else {
+        compiled.javascript.insert_str(offset, text);
+    }
+    if let Some(map) = &mut compiled.source_map {
+        map.insert_lines(at, text.matches('\n').count());
+    }
+}
+
+/// `YYYY-MM-DDTHH:MM:SSZ` for `at`, by the civil-from-days algorithm.
+pub fn utc_timestamp(at: SystemTime) -> String {
+    let secs = at
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let (days, rest) = (secs / 86_400, secs % 86_400);
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        rest / 3_600,
+        rest % 3_600 / 60,
+        rest % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OutputOptions, finish_output, utc_timestamp};
+    use crate::{CompileMetadata, CompileResult, LINKED_HEADER, SourceMap, is_linked};
+    use klumo_llm::Provider;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn timestamps_are_utc() {
+        assert_eq!(utc_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            utc_timestamp(UNIX_EPOCH + Duration::from_secs(1_709_210_096)),
+            "2024-02-29T12:34:56Z"
+        );
+    }
+
+    #[test]
+    fn banner_and_directive_keep_linked_headers_and_source_maps() {
+        let javascript = format!("{LINKED_HEADER} registry\n(() => {{\n}})();\n");
+        let mut compiled = CompileResult {
+            source_map: Some(SourceMap::identity("main.pseudo", &javascript)),
+            javascript,
+            metadata: CompileMetadata {
+                provider: Some(Provider::Ollama),
+                model: Some("qwen".to_string()),
+                served_model: None,
+                prompt_version: "m1-v3".to_string(),
+                cache_hit: false,
+                notes: None,
+                confidence: None,
+                explanation: None,
+                signature: None,
+                similarity: None,
+            },
+        };
+        finish_output(
+            &mut compiled,
+            OutputOptions {
+                banner: true,
+                strict: true,
+            },
+            UNIX_EPOCH,
+        );
+        assert_eq!(
+            compiled.javascript,
+            format!(
+                "// Generated by klumo: translated by ollama/qwen, prompt_version m1-v3\n\
+                 // Built at 1970-01-01T00:00:00Z\n\
+                 {LINKED_HEADER} registry\n\"use strict\";\n(() => {{\n}})();\n"
+            )
+        );
+        assert!(is_linked(&compiled.javascript));
+        let map = compiled.source_map.as_ref().expect("map is kept");
+        assert_eq!(map.lookup(3), Some(("main.pseudo", 1)));
+        assert_eq!(map.lookup(4), None);
+        assert_eq!(map.lookup(5), Some(("main.pseudo", 2)));
+
+        let before = compiled.javascript.clone();
+        finish_output(
+            &mut compiled,
+            OutputOptions {
+                banner: false,
+                strict: true,
+            },
+            UNIX_EPOCH,
+        );
+        assert_eq!(compiled.javascript, before, "already strict");
+    }
+}
@@ -0,0 +1,69 @@
+use crate::{CompileRequest, CompileResult};
+use anyhow::{Result, anyhow};
+use klumo_engine::check_syntax;
+
+/// A transformation hooked into `CompilerRouter::compile`. Passes run in pipeline order:
+/// `prepare` sees the request before the cache is consulted or a provider is asked, and
+/// `finish` sees the JavaScript of a fresh translation (or a JavaScript source passed through)
+/// before it is signed, cached or returned.
+///
+/// Cache entries hold `finish` output, so the names of the passes a compile runs are part of
+/// its cache key; a pass whose output depends on settings should put them in its name.
+pub trait CompilePass: Send + Sync {
+    /// Stable identifier, e.g. `banner` or `minify`.
+    fn name(&self) -> String;
+
+    /// Whether the pass takes part in compiling `req`, checked on the request each hook would
+    /// see. Passes that do not are skipped and left out of the request's cache key, so adding
+    /// a pass for one kind of source keeps the entries of every other kind.
+    fn applies(&self, _req: &CompileRequest) -> bool {
+        true
+    }
+
+    /// Whether `finish` output no longer lines up with its input, so no source map can be
+    /// inferred for results the pass finished.
+    fn moves_lines(&self) -> bool {
+        false
+    }
+
+    fn prepare(&self, _req: &mut CompileRequest) -> Result<()> {
+        Ok(())
+    }
+
+    fn finish(&self, _req: &CompileRequest, _result: &mut CompileResult) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The names of the `passes` that apply to `req` in order, joined with `,`; what cache keys
+/// hash.
+pub fn pipeline_fingerprint(passes: &[Box<dyn CompilePass>], req: &CompileRequest) -> String {
+    passes
+        .iter()
+        .filter(|pass| pass.applies(req))
+        .map(|pass| pass.name())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Fails the compile when the JavaScript does not parse. Translations are already checked
+/// before any pass runs, so this belongs after passes that rewrite the output.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SyntaxCheckPass;
+
+impl CompilePass for SyntaxCheckPass {
+    fn name(&self) -> String {
+        "syntax-check".to_string()
+    }
+
+    fn finish(&self, req: &CompileRequest, result: &mut CompileResult) -> Result<()> {
+        match check_syntax(&result.javascript).into_iter().next() {
+            Some(diagnostic) => Err(anyhow!(
+                "compiled JavaScript for {} does not parse after the compile passes: {}",
+                req.source_id,
+                diagnostic
+            )),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,1745 @@
+use anyhow::{Result, anyhow, bail};
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashSet};
+
+/// Translates a script written in a small subset of Python to JavaScript without a model:
+/// assignments (including `a, b = b, a` and augmented ones), `def` with positional and default
+/// parameters, `if`/`elif`/`else`, `for` over `range(...)` or a list or string, `while`,
+/// `return`/`break`/`continue`/`pass`, lists, f-strings, `print` and a handful of builtins and
+/// string and list methods. Each Python line becomes one JavaScript line (closing braces go at
+/// the end of a block's last line), so runtime errors point at the source line.
+///
+/// Where JavaScript operators would behave differently for some operand types, the output
+/// calls `__py_*` helpers that follow Python instead (truthiness of lists, `*` on strings and
+/// lists, in-place `+=` and `*=` on lists, `%` taking the divisor's sign, `ZeroDivisionError`
+/// and `IndexError`, `print` and `str` formatting, list equality and ordering, `1 == True`).
+/// Only the helpers a script uses are appended, after its last line, so line numbers still
+/// match. Floats are refused, since JavaScript prints `2.0` as `2`; integers past 2**53 lose
+/// precision. So are defaults other than literals, which Python evaluates once, and names
+/// read in a scope before their first assignment there, where Python raises instead of
+/// reading `None`; a name assigned on only some paths still reads as `None` there. Fails on
+/// anything else (classes, imports, dicts, tuples, slices, keyword arguments,
+/// comprehensions, statements spanning lines, ...); the compiler
+/// then asks the LLM instead. The output is not guaranteed to parse either, so callers check
+/// it before using it.
+pub fn transpile_python(source: &str) -> Result<String> {
+    let mut lines: Vec<Line> = Vec::new();
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut scopes: Vec<Scope> = vec![Scope::default()];
+    let mut pending: Option<(BlockKind, usize)> = None;
+
+    for (number, raw) in source.lines().enumerate() {
+        let at = || format!("line {}", number + 1);
+        let (tokens, comment) = tokenize(raw).map_err(|err| err.context(at()))?;
+        let indent_text = &raw[..raw.len() - raw.trim_start().len()];
+        let comment = comment.map(|text| format!("//{}", &text[1..]));
+        if tokens.is_empty() {
+            lines.push(Line {
+                indent: indent_text.to_string(),
+                code: String::new(),
+                comment,
+            });
+            continue;
+        }
+        if indent_text.contains('\t') && indent_text.contains(' ') {
+            bail!("{}: indentation mixes tabs and spaces", at());
+        }
+        let indent = indent_text.len();
+        let level = blocks.last().map_or(0, |block| block.body_indent);
+
+        let mut closed = None;
+        match pending.take() {
+            Some((kind, header_indent)) => {
+                if indent <= level {
+                    bail!("{}: expected an indented block", at());
+                }
+                blocks.push(Block {
+                    kind,
+                    header_indent,
+                    body_indent: indent,
+                });
+            }
+            None => {
+                while blocks
+                    .last()
+                    .is_some_and(|block| block.body_indent > indent)
+                {
+                    let block = blocks.pop().expect("a block is open");
+                    close_block(&mut lines);
+                    if block.kind == BlockKind::Function {
+                        scopes.pop();
+                    }
+                    closed = Some(block);
+                }
+                if blocks.last().map_or(0, |block| block.body_indent) != indent {
+                    bail!("{}: indentation does not match an enclosing block", at());
+                }
+            }
+        }
+
+        let in_function = blocks.iter().any(|block| block.kind == BlockKind::Function);
+        let mut statement = Statement {
+            tokens: &tokens,
+            scopes: &mut scopes,
+            in_function,
+        };
+        let (code, opens) = statement
+            .translate(
+                closed
+                    .as_ref()
+                    .filter(|block| block.header_indent == indent),
+            )
+            .map_err(|err| err.context(at()))?;
+        if let Some(kind) = opens {
+            pending = Some((kind, indent));
+        }
+        lines.push(Line {
+            indent: indent_text.to_string(),
+            code,
+            comment,
+        });
+    }
+    if pending.is_some() {
+        bail!("expected an indented block at the end of the script");
+    }
+    for _ in blocks {
+        close_block(&mut lines);
+    }
+
+    let mut javascript = lines
+        .iter()
+        .map(|line| match (&line.code[..], &line.comment) {
+            ("", Some(comment)) => format!("{}{comment}", line.indent),
+            (code, Some(comment)) => format!("{}{code} {comment}", line.indent),
+            ("", None) => String::new(),
+            (code, None) => format!("{}{code}", line.indent),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        + if source.ends_with('\n') { "\n" } else { "" };
+    append_helpers(&mut javascript);
+    Ok(javascript)
+}
+
+/// Runtime support for the generated code, one function declaration per line. Declarations are
+/// hoisted, so they can follow the script.
+const HELPERS: &[(&str, &str)] = &[
+    (
+        "__py_raise",
+        "function __py_raise(name, message) { const err = new Error(message); err.name = name; throw err; }",
+    ),
+    (
+        "__py_truthy",
+        "function __py_truthy(v) { return Array.isArray(v) ? v.length > 0 : Boolean(v); }",
+    ),
+    (
+        "__py_and",
+        "function __py_and(a, b) { return __py_truthy(a) ? b() : a; }",
+    ),
+    (
+        "__py_or",
+        "function __py_or(a, b) { return __py_truthy(a) ? a : b(); }",
+    ),
+    (
+        "__py_add",
+        r#"function __py_add(a, b) { if (Array.isArray(a) && Array.isArray(b)) return a.concat(b); if (Array.isArray(a) || Array.isArray(b) || (typeof a === "string") !== (typeof b === "string")) throw new TypeError("unsupported operand types for +"); return a + b; }"#,
+    ),
+    (
+        "__py_iadd",
+        "function __py_iadd(a, b) { if (!Array.isArray(a)) return __py_add(a, b); a.push(...b); return a; }",
+    ),
+    (
+        "__py_mul",
+        r#"function __py_mul(a, b) { if (typeof b === "string" || Array.isArray(b)) [a, b] = [b, a]; if (typeof a === "string") return a.repeat(Math.max(0, b)); if (Array.isArray(a)) { const out = []; for (let i = 0; i < b; i++) out.push(...a); return out; } return a * b; }"#,
+    ),
+    (
+        "__py_imul",
+        "function __py_imul(a, b) { if (!Array.isArray(a)) return __py_mul(a, b); const items = a.slice(); a.length = 0; for (let i = 0; i < b; i++) a.push(...items); return a; }",
+    ),
+    (
+        "__py_floordiv",
+        r#"function __py_floordiv(a, b) { if (b === 0 || b === false) __py_raise("ZeroDivisionError", "integer division or modulo by zero"); return Math.floor(a / b); }"#,
+    ),
+    (
+        "__py_mod",
+        r#"function __py_mod(a, b) { if (b === 0 || b === false) __py_raise("ZeroDivisionError", "integer modulo by zero"); return a - b * Math.floor(a / b); }"#,
+    ),
+    (
+        "__py_at",
+        r#"function __py_at(v, i) { if (typeof i === "boolean") i = Number(i); if (!Number.isInteger(i)) throw new TypeError("indices must be integers"); const at = i < 0 ? i + v.length : i; if (at < 0 || at >= v.length) __py_raise("IndexError", (typeof v === "string" ? "string" : "list") + " index out of range"); return at; }"#,
+    ),
+    (
+        "__py_index",
+        "function __py_index(v, i) { return v[__py_at(v, i)]; }",
+    ),
+    (
+        "__py_eq",
+        r#"function __py_eq(a, b) { if (Array.isArray(a) && Array.isArray(b)) return a.length === b.length && a.every((x, i) => __py_eq(x, b[i])); if (typeof a === "boolean") a = Number(a); if (typeof b === "boolean") b = Number(b); return a === b || (a == null && b == null); }"#,
+    ),
+    (
+        "__py_in",
+        r#"function __py_in(x, c) { return typeof c === "string" ? c.includes(x) : [...c].some((y) => __py_eq(x, y)); }"#,
+    ),
+    (
+        "__py_cmp",
+        r#"function __py_cmp(a, b) { const kind = (v) => (Array.isArray(v) ? "list" : typeof v === "boolean" ? "number" : typeof v); if (a == null || kind(a) !== kind(b)) throw new TypeError("values of different types cannot be ordered"); if (kind(a) === "list") { for (let i = 0; i < a.length && i < b.length; i++) if (!__py_eq(a[i], b[i])) return __py_cmp(a[i], b[i]); return a.length - b.length; } return a < b ? -1 : a > b ? 1 : 0; }"#,
+    ),
+    (
+        "__py_min",
+        "function __py_min(...args) { return (args.length === 1 ? [...args[0]] : args).reduce((min, x) => (__py_cmp(x, min) < 0 ? x : min)); }",
+    ),
+    (
+        "__py_max",
+        "function __py_max(...args) { return (args.length === 1 ? [...args[0]] : args).reduce((max, x) => (__py_cmp(x, max) > 0 ? x : max)); }",
+    ),
+    (
+        "__py_repr",
+        r#"function __py_repr(v) { if (typeof v !== "string") return __py_str(v); const q = v.includes("'") && !v.includes('"') ? '"' : "'"; return q + v.replace(/[\\\n\r\t]/g, (c) => ({ "\\": "\\\\", "\n": "\\n", "\r": "\\r", "\t": "\\t" })[c]).replaceAll(q, "\\" + q) + q; }"#,
+    ),
+    (
+        "__py_str",
+        r#"function __py_str(v) { if (v == null) return "None"; if (typeof v === "boolean") return v ? "True" : "False"; if (Array.isArray(v)) return "[" + v.map(__py_repr).join(", ") + "]"; return String(v); }"#,
+    ),
+    (
+        "__py_print",
+        r#"function __py_print(...args) { console.log(args.map(__py_str).join(" ")); }"#,
+    ),
+];
+
+/// Appends the helpers `javascript` calls, and those they call.
+fn append_helpers(javascript: &mut String) {
+    let mut used = BTreeSet::new();
+    let mut scanned = javascript.clone();
+    loop {
+        let found: Vec<usize> = HELPERS
+            .iter()
+            .enumerate()
+            .filter(|(index, (name, _))| !used.contains(index) && scanned.contains(name))
+            .map(|(index, _)| index)
+            .collect();
+        if found.is_empty() {
+            break;
+        }
+        scanned = found.iter().map(|index| HELPERS[*index].1).collect();
+        used.extend(found);
+    }
+    if used.is_empty() {
+        return;
+    }
+    if !javascript.is_empty() && !javascript.ends_with('\n') {
+        javascript.push('\n');
+    }
+    for index in used {
+        javascript.push_str(HELPERS[index].1);
+        javascript.push('\n');
+    }
+}
+
+/// The names a function, or the module, assigns, and those it reads while they are not
+/// assigned in it yet.
+#[derive(Default)]
+struct Scope {
+    names: HashSet<String>,
+    reads: RefCell<HashSet<String>>,
+}
+
+struct Line {
+    indent: String,
+    code: String,
+    comment: Option<String>,
+}
+
+/// Ends a block on the last line with code, before its comment.
+fn close_block(lines: &mut [Line]) {
+    if let Some(line) = lines.iter_mut().rev().find(|line| !line.code.is_empty()) {
+        line.code
+            .push_str(if line.code.ends_with('{') { "}" } else { " }" });
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BlockKind {
+    Function,
+    /// `if` or `elif`, which an `elif` or `else` may follow.
+    If,
+    Other,
+}
+
+struct Block {
+    kind: BlockKind,
+    header_indent: usize,
+    body_indent: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Name(String),
+    Number(String),
+    /// A string literal, already written as JavaScript.
+    Str(String),
+    Op(&'static str),
+}
+
+const OPERATORS: &[&str] = &[
+    "**=", "//=", "**", "//", "==", "!=", "<=", ">=", "+=", "-=", "*=", "/=", "%=", "->", ":=",
+    "+", "-", "*", "/", "%", "<", ">", "=", "(", ")", "[", "]", "{", "}", ",", ".", ":", ";", "@",
+    "&", "|", "^", "~", "!",
+];
+
+/// The tokens of one line and its `#` comment. Statements continued on the next line, through
+/// an open bracket or a trailing backslash, are refused.
+fn tokenize(line: &str) -> Result<(Vec<Tok>, Option<&str>)> {
+    let mut tokens = Vec::new();
+    let mut depth = 0usize;
+    let mut rest = line.trim_start();
+    let mut comment = None;
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = rest.trim_start();
+            continue;
+        }
+        if c == '#' {
+            comment = Some(rest.trim_end());
+            break;
+        }
+        if c == '\\' {
+            bail!("backslash line continuations are not supported");
+        }
+        let word_end = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let after_word = &rest[word_end..];
+        if word_end > 0 && !c.is_ascii_digit() {
+            if after_word.starts_with(['\'', '"']) {
+                let prefix = &rest[..word_end];
+                let (token, len) = match prefix {
+                    "f" | "F" => fstring(after_word)?,
+                    _ => bail!("`{prefix}` string literals are not supported"),
+                };
+                tokens.push(token);
+                rest = &after_word[len..];
+            } else {
+                tokens.push(Tok::Name(rest[..word_end].to_string()));
+                rest = after_word;
+            }
+        } else if c.is_ascii_digit()
+            || (c == '.' && rest[1..].starts_with(|c: char| c.is_ascii_digit()))
+        {
+            let end = number_end(rest);
+            let number = &rest[..end];
+            if number.ends_with(['j', 'J']) {
+                bail!("complex numbers are not supported");
+            }
+            tokens.push(Tok::Number(number.to_string()));
+            rest = &rest[end..];
+        } else if c == '\'' || c == '"' {
+            let (literal, len) = string(rest)?;
+            tokens.push(Tok::Str(literal));
+            rest = &rest[len..];
+        } else {
+            let op = OPERATORS
+                .iter()
+                .find(|op| rest.starts_with(**op))
+                .ok_or_else(|| anyhow!("unexpected character `{c}`"))?;
+            match *op {
+                "(" | "[" | "{" => depth += 1,
+                ")" | "]" | "}" => {
+                    depth = depth
+                        .checked_sub(1)
+                        .ok_or_else(|| anyhow!("unbalanced `{op}`"))?
+                }
+                _ => {}
+            }
+            tokens.push(Tok::Op(op));
+            rest = &rest[op.len()..];
+        }
+    }
+    if depth > 0 {
+        bail!("statements spanning several lines are not supported");
+    }
+    Ok((tokens, comment))
+}
+
+fn number_end(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let mut end = 0;
+    while end < bytes.len() {
+        let c = bytes[end];
+        let exponent_sign = matches!(c, b'+' | b'-')
+            && end > 0
+            && matches!(bytes[end - 1], b'e' | b'E')
+            && !text.starts_with("0x");
+        if c.is_ascii_alphanumeric() || c == b'_' || c == b'.' || exponent_sign {
+            end += 1;
+        } else {
+            break;
+        }
+    }
+    end
+}
+
+/// A quoted string at the start of `text` as a JavaScript literal, and its length. Escapes
+/// mean the same in both languages for the ones scripts use (`\n`, `\t`, `\\`, quotes).
+fn string(text: &str) -> Result<(String, usize)> {
+    let quote = text.chars().next().expect("a quote starts the literal");
+    let triple: String = [quote; 3].iter().collect();
+    if let Some(body) = text.strip_prefix(&triple) {
+        // A one-line docstring; longer ones span lines.
+        let end = body
+            .find(&triple)
+            .ok_or_else(|| anyhow!("strings spanning several lines are not supported"))?;
+        let content = &body[..end];
+        if content.contains('\\') {
+            bail!("escapes in triple-quoted strings are not supported");
+        }
+        return Ok((
+            format!("\"{}\"", content.replace('"', "\\\"")),
+            end + 2 * triple.len(),
+        ));
+    }
+    let end = quoted_end(text, quote)?;
+    Ok((text[..end].to_string(), end))
+}
+
+/// The length of the quoted literal at the start of `text`, quotes included.
+fn quoted_end(text: &str, quote: char) -> Result<usize> {
+    let mut chars = text.char_indices().skip(1);
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            c if c == quote => return Ok(index + 1),
+            _ => {}
+        }
+    }
+    bail!("unterminated string")
+}
+
+/// An f-string at the start of `text` as a template literal, and its length. Replacement
+/// fields are translated as expressions; conversions and format specs are refused.
+fn fstring(text: &str) -> Result<(Tok, usize)> {
+    let quote = text.chars().next().expect("a quote starts the literal");
+    if text.starts_with(&[quote; 3].iter().collect::<String>()) {
+        bail!("triple-quoted f-strings are not supported");
+    }
+    let end = quoted_end(text, quote)?;
+    let body = &text[1..end - 1];
+    let mut out = String::from("`");
+    let mut chars = body.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '\\' => {
+                out.push(c);
+                if let Some((_, escaped)) = chars.next() {
+                    out.push(escaped);
+                }
+            }
+            '{' if chars.peek().is_some_and(|(_, next)| *next == '{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.peek().is_some_and(|(_, next)| *next == '}') => {
+                chars.next();
+                out.push('}');
+            }
+            '{' => {
+                let start = index + 1;
+                let mut depth = 0usize;
+                let mut quote = None;
+                let mut close = None;
+                for (offset, c) in chars.by_ref() {
+                    match (quote, c) {
+                        (Some(open), c) if c == open => quote = None,
+                        (Some(_), _) => {}
+                        (None, '\'' | '"') => quote = Some(c),
+                        (None, '(' | '[' | '{') => depth += 1,
+                        (None, ')' | ']' | '}') if depth > 0 => depth -= 1,
+                        (None, '}') => {
+                            close = Some(offset);
+                            break;
+                        }
+                        _ => {}
+                    }
+                }
+                let close = close.ok_or_else(|| anyhow!("unterminated f-string field"))?;
+                let field = &body[start..close];
+                let conversion = field
+                    .char_indices()
+                    .any(|(at, c)| c == '!' && !field[at + 1..].starts_with('='));
+                if conversion || field.contains(':') || field.trim_end().ends_with('=') {
+                    bail!("f-string conversions and format specs are not supported");
+                }
+                let field = translate_expression(field)?;
+                out.push_str("${");
+                out.push_str(&match field.ty {
+                    Ty::Str | Ty::Num => field.js,
+                    _ => format!("__py_str({})", field.js),
+                });
+                out.push('}');
+            }
+            '}' => bail!("single `}}` in f-string"),
+            '`' => out.push_str("\\`"),
+            '$' => out.push_str("\\$"),
+            c => out.push(c),
+        }
+    }
+    out.push('`');
+    Ok((Tok::Str(out), end))
+}
+
+/// A lone expression, such as an f-string field.
+fn translate_expression(source: &str) -> Result<Expr> {
+    let (tokens, comment) = tokenize(source)?;
+    if comment.is_some() {
+        bail!("comments inside f-string fields are not supported");
+    }
+    let scopes = [Scope::default()];
+    let mut parser = Parser {
+        tokens: &tokens,
+        pos: 0,
+        scopes: &scopes,
+    };
+    let expr = parser.expression()?;
+    parser.finish()?;
+    Ok(expr)
+}
+
+/// Python keywords and soft keywords the subset has no statement for.
+const UNSUPPORTED_STATEMENTS: &[&str] = &[
+    "class", "import", "from", "try", "except", "finally", "with", "raise", "assert", "global",
+    "nonlocal", "del", "async", "await", "yield", "lambda", "match", "case",
+];
+
+/// Names JavaScript reserves that Python scripts may use as variables.
+const JS_RESERVED: &[&str] = &[
+    "arguments",
+    "case",
+    "catch",
+    "const",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "enum",
+    "eval",
+    "export",
+    "extends",
+    "function",
+    "implements",
+    "instanceof",
+    "interface",
+    "let",
+    "new",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "static",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "typeof",
+    "undefined",
+    "var",
+    "void",
+];
+
+/// Python keywords, which never name a value.
+const PY_KEYWORDS: &[&str] = &[
+    "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif",
+    "else", "except", "finally", "for", "from", "global", "if", "import", "in", "is", "lambda",
+    "nonlocal", "not", "or", "pass", "raise", "return", "try", "while", "with", "yield",
+];
+
+/// Builtins whose Python behavior the subset does not reproduce.
+const UNSUPPORTED_BUILTINS: &[&str] = &[
+    "all",
+    "any",
+    "chr",
+    "dict",
+    "divmod",
+    "enumerate",
+    "eval",
+    "exec",
+    "filter",
+    "float",
+    "format",
+    "getattr",
+    "globals",
+    "hasattr",
+    "input",
+    "isinstance",
+    "iter",
+    "list",
+    "locals",
+    "map",
+    "next",
+    "open",
+    "ord",
+    "pow",
+    "range",
+    "repr",
+    "reversed",
+    "round",
+    "set",
+    "setattr",
+    "sorted",
+    "sum",
+    "tuple",
+    "type",
+    "zip",
+];
+
+struct Statement<'a> {
+    tokens: &'a [Tok],
+    scopes: &'a mut Vec<Scope>,
+    in_function: bool,
+}
+
+impl Statement<'_> {
+    /// The JavaScript for one line, and the kind of block it opens. `closed` is the block that
+    /// just ended at this line's indentation, which `elif` and `else` continue.
+    fn translate(&mut self, closed: Option<&Block>) -> Result<(String, Option<BlockKind>)> {
+        let tokens = self.tokens;
+        let keyword = match &tokens[0] {
+            Tok::Name(name) => name.as_str(),
+            _ => "",
+        };
+        if tokens.last() == Some(&Tok::Op(":")) {
+            let body = &tokens[1..tokens.len() - 1];
+            return match keyword {
+                "def" => self.function(body),
+                "if" => Ok((
+                    format!("if ({}) {{", self.condition(body)?),
+                    Some(BlockKind::If),
+                )),
+                "elif" | "else" if !closed.is_some_and(|block| block.kind == BlockKind::If) => {
+                    bail!("`{keyword}` only follows `if` or `elif` blocks here")
+                }
+                "elif" => Ok((
+                    format!("else if ({}) {{", self.condition(body)?),
+                    Some(BlockKind::If),
+                )),
+                "else" if body.is_empty() => Ok(("else {".to_string(), Some(BlockKind::Other))),
+                "while" => Ok((
+                    format!("while ({}) {{", self.condition(body)?),
+                    Some(BlockKind::Other),
+                )),
+                "for" => self.for_loop(body),
+                _ => bail!("unsupported block `{keyword}`"),
+            };
+        }
+        if UNSUPPORTED_STATEMENTS.contains(&keyword) {
+            bail!("`{keyword}` is not supported");
+        }
+        let rest = &tokens[1..];
+        let code = match keyword {
+            "pass" if rest.is_empty() => String::new(),
+            "break" | "continue" if rest.is_empty() => format!("{keyword};"),
+            "return" if !self.in_function => bail!("`return` outside a function"),
+            "return" if rest.is_empty() => "return;".to_string(),
+            "return" => format!("return {};", self.expression(rest)?),
+            _ => self.assignment_or_expression()?,
+        };
+        Ok((code, None))
+    }
+
+    fn function(&mut self, header: &[Tok]) -> Result<(String, Option<BlockKind>)> {
+        let (Some(Tok::Name(name)), Some(Tok::Op("(")), Some(Tok::Op(")"))) =
+            (header.first(), header.get(1), header.last())
+        else {
+            bail!("unsupported function definition");
+        };
+        check_name(name)?;
+        let mut params = Vec::new();
+        let mut names = HashSet::new();
+        for param in split_top_level(&header[2..header.len() - 1]) {
+            match param {
+                [] => {}
+                [Tok::Name(param)] => {
+                    check_name(param)?;
+                    params.push(param.clone());
+                    names.insert(param.clone());
+                }
+                [Tok::Name(param), Tok::Op("="), default @ ..] => {
+                    check_name(param)?;
+                    // Python evaluates defaults once, so a list default is shared between
+                    // calls and a name default keeps its value at definition time.
+                    if !literal(default) {
+                        bail!(
+                            "only number, string, `True`, `False` and `None` defaults are supported"
+                        );
+                    }
+                    params.push(format!("{param} = {}", self.expression(default)?));
+                    names.insert(param.clone());
+                }
+                _ => bail!("only positional parameters with optional defaults are supported"),
+            }
+        }
+        self.declare(name)?;
+        self.scopes.push(Scope {
+            names,
+            reads: RefCell::default(),
+        });
+        Ok((
+            format!("function {name}({}) {{", params.join(", ")),
+            Some(BlockKind::Function),
+        ))
+    }
+
+    fn for_loop(&mut self, header: &[Tok]) -> Result<(String, Option<BlockKind>)> {
+        let [Tok::Name(target), Tok::Name(keyword), iterable @ ..] = header else {
+            bail!("only `for name in ...` loops are supported");
+        };
+        if keyword != "in" {
+            bail!("only `for name in ...` loops are supported");
+        }
+        check_name(target)?;
+        self.declare(target)?;
+        let code = match iterable {
+            [Tok::Name(range), Tok::Op("("), args @ .., Tok::Op(")")]
+                if range == "range" && !self.shadowed("range") =>
+            {
+                let args = split_top_level(args)
+                    .into_iter()
+                    .map(|arg| self.parsed(arg))
+                    .collect::<Result<Vec<_>>>()?;
+                let (start, stop, step) = match &args[..] {
+                    [stop] => ("0".to_string(), stop, 1.0),
+                    [start, stop] => (start.js.clone(), stop, 1.0),
+                    [start, stop, step] => (
+                        start.js.clone(),
+                        stop,
+                        step.js
+                            .replace(' ', "")
+                            .parse::<f64>()
+                            .map_err(|_| anyhow!("range() steps must be number literals"))?,
+                    ),
+                    _ => bail!("range() takes one to three arguments"),
+                };
+                let update = if step == 1.0 {
+                    format!("{target}++")
+                } else if step == -1.0 {
+                    format!("{target}--")
+                } else if step > 0.0 {
+                    format!("{target} += {step}")
+                } else if step < 0.0 {
+                    format!("{target} -= {}", -step)
+                } else {
+                    bail!("range() step must not be zero");
+                };
+                let compare = if step > 0.0 { "<" } else { ">" };
+                let stop = stop.at(RELATIONAL + 1);
+                format!("for (var {target} = {start}; {target} {compare} {stop}; {update}) {{")
+            }
+            _ => format!("for (var {target} of {}) {{", self.expression(iterable)?),
+        };
+        Ok((code, Some(BlockKind::Other)))
+    }
+
+    fn assignment_or_expression(&mut self) -> Result<String> {
+        let tokens = self.tokens;
+        let assignments: Vec<usize> = top_level_positions(tokens, |tok| {
+            matches!(
+                tok,
+                Tok::Op("=" | "+=" | "-=" | "*=" | "/=" | "%=" | "**=" | "//=")
+            )
+        });
+        if assignments.iter().any(|at| tokens[*at] == Tok::Op("/=")) {
+            bail!("true division is not supported, since it returns floats");
+        }
+        let &[at] = &assignments[..] else {
+            if assignments.is_empty() {
+                return Ok(format!("{};", self.expression(tokens)?));
+            }
+            bail!("chained assignments are not supported");
+        };
+        let (target, value) = (&tokens[..at], &tokens[at + 1..]);
+        let Tok::Op(op) = &tokens[at] else {
+            unreachable!("positions are operators");
+        };
+        let targets = split_top_level(target);
+        if targets.len() > 1 {
+            if *op != "=" {
+                bail!("augmented assignment to several targets");
+            }
+            let value_js = self.unpacked(value)?;
+            let mut names = Vec::new();
+            let mut fresh = false;
+            for target in targets {
+                let [Tok::Name(name)] = target else {
+                    bail!("only names can be unpacked into");
+                };
+                check_name(name)?;
+                fresh |= self.declare(name)?;
+                names.push(name.clone());
+            }
+            let var = if fresh { "var " } else { "" };
+            return Ok(format!("{var}[{}] = {value_js};", names.join(", ")));
+        }
+        let value = self.parsed(value)?;
+        let value_js = &value.js;
+        if let [Tok::Name(name)] = target {
+            check_name(name)?;
+            if *op == "=" && self.declare(name)? {
+                return Ok(format!("var {name} = {value_js};"));
+            }
+        } else if *op == "//=" {
+            bail!("`//=` is only supported on names");
+        }
+        let target_js = match subscript(target) {
+            Some((base, index)) => self.item_target(base, index)?,
+            None => self.expression(target)?,
+        };
+        // The target's type is unknown, so operators that JavaScript applies differently to
+        // strings and lists go through the helpers. `+=` and `*=` extend a list in place, so
+        // other names for it see the change.
+        Ok(match *op {
+            "//=" if nonzero_literal(&value) => format!(
+                "{target_js} = Math.floor({target_js} / {});",
+                value.at(MULTIPLICATIVE + 1)
+            ),
+            "//=" => format!("{target_js} = __py_floordiv({target_js}, {value_js});"),
+            "+=" if !matches!(value.ty, Ty::Num | Ty::Str | Ty::Bool) => {
+                format!("{target_js} = __py_iadd({target_js}, {value_js});")
+            }
+            "*=" => format!("{target_js} = __py_imul({target_js}, {value_js});"),
+            "%=" => format!("{target_js} = __py_mod({target_js}, {value_js});"),
+            op => format!("{target_js} {op} {value_js};"),
+        })
+    }
+
+    /// An item of a list as an assignment target. Its index is checked like a read's, which
+    /// evaluates the list expression twice, so it may not call anything.
+    fn item_target(&self, base: &[Tok], index: &[Tok]) -> Result<String> {
+        if base.contains(&Tok::Op("(")) {
+            bail!("assigning to items of call results is not supported");
+        }
+        if index.first() == Some(&Tok::Op("-")) {
+            bail!("negative indexes are not supported");
+        }
+        if !top_level_positions(index, |tok| *tok == Tok::Op(":")).is_empty() {
+            bail!("slices are not supported");
+        }
+        let base = self.parsed(base)?;
+        let index = self.parsed(index)?;
+        Ok(format!(
+            "{}[__py_at({}, {})]",
+            base.at(POSTFIX),
+            base.js,
+            index.js
+        ))
+    }
+
+    /// The values of `a, b = 1, 2` as an array, or the single list or call unpacked.
+    fn unpacked(&self, tokens: &[Tok]) -> Result<String> {
+        let items = split_top_level(tokens);
+        if items.len() == 1 {
+            return self.expression(tokens);
+        }
+        let items = items
+            .into_iter()
+            .filter(|item| !item.is_empty())
+            .map(|item| self.expression(item))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(format!("[{}]", items.join(", ")))
+    }
+
+    fn expression(&self, tokens: &[Tok]) -> Result<String> {
+        if split_top_level(tokens).len() > 1 {
+            bail!("tuples are not supported");
+        }
+        Ok(self.parsed(tokens)?.js)
+    }
+
+    /// An `if` or `while` condition, tested for Python truthiness.
+    fn condition(&self, tokens: &[Tok]) -> Result<String> {
+        Ok(self.parsed(tokens)?.truthy().js)
+    }
+
+    fn parsed(&self, tokens: &[Tok]) -> Result<Expr> {
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            scopes: self.scopes,
+        };
+        let expr = parser.expression()?;
+        parser.finish()?;
+        Ok(expr)
+    }
+
+    /// Records `name` in the innermost scope; whether it was new there. Python raises
+    /// `UnboundLocalError` (or `NameError`) for reads of a name before its first assignment in
+    /// a scope, where a hoisted `var` would read `undefined`, so such names are refused.
+    fn declare(&mut self, name: &str) -> Result<bool> {
+        let scope = self.scopes.last_mut().expect("the module scope stays");
+        if scope.names.contains(name) {
+            return Ok(false);
+        }
+        if scope.reads.get_mut().contains(name) {
+            bail!("`{name}` is read before it is assigned");
+        }
+        Ok(scope.names.insert(name.to_string()))
+    }
+
+    fn shadowed(&self, name: &str) -> bool {
+        self.scopes.iter().any(|scope| scope.names.contains(name))
+    }
+}
+
+/// Whether `tokens` are a constant literal, which evaluates the same whenever it is evaluated.
+fn literal(tokens: &[Tok]) -> bool {
+    match tokens {
+        [Tok::Op("-" | "+"), Tok::Number(_)] | [Tok::Number(_)] => true,
+        // A template literal with fields reads variables.
+        [Tok::Str(literal)] => !(literal.starts_with('`') && literal.contains("${")),
+        [Tok::Name(name)] => matches!(name.as_str(), "True" | "False" | "None"),
+        _ => false,
+    }
+}
+
+fn check_name(name: &str) -> Result<()> {
+    if PY_KEYWORDS.contains(&name) || JS_RESERVED.contains(&name) || name.starts_with("__py_") {
+        bail!("`{name}` cannot be used as a name here");
+    }
+    Ok(())
+}
+
+/// Indices of the tokens outside brackets that match `is_wanted`.
+fn top_level_positions(tokens: &[Tok], is_wanted: impl Fn(&Tok) -> bool) -> Vec<usize> {
+    let mut depth = 0usize;
+    let mut positions = Vec::new();
+    for (index, tok) in tokens.iter().enumerate() {
+        match tok {
+            Tok::Op("(" | "[" | "{") => depth += 1,
+            Tok::Op(")" | "]" | "}") => depth = depth.saturating_sub(1),
+            tok if depth == 0 && is_wanted(tok) => positions.push(index),
+            _ => {}
+        }
+    }
+    positions
+}
+
+/// The list and index of a target like `xs[i]`, split at the last top-level `[`.
+fn subscript(tokens: &[Tok]) -> Option<(&[Tok], &[Tok])> {
+    let (Tok::Op("]"), rest) = tokens.split_last()? else {
+        return None;
+    };
+    let mut depth = 0usize;
+    for (index, tok) in rest.iter().enumerate().rev() {
+        match tok {
+            Tok::Op(")" | "]" | "}") => depth += 1,
+            Tok::Op("[") if depth == 0 => {
+                return (index > 0).then(|| (&rest[..index], &rest[index + 1..]));
+            }
+            Tok::Op("(" | "[" | "{") => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Whether `expr` is an integer literal other than zero, by which `//` cannot fail.
+fn nonzero_literal(expr: &Expr) -> bool {
+    expr.js
+        .replace(' ', "")
+        .parse::<i64>()
+        .is_ok_and(|value| value != 0)
+}
+
+/// `tokens` split at commas outside brackets; a trailing comma leaves an empty last item.
+fn split_top_level(tokens: &[Tok]) -> Vec<&[Tok]> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    for comma in top_level_positions(tokens, |tok| *tok == Tok::Op(",")) {
+        items.push(&tokens[start..comma]);
+        start = comma + 1;
+    }
+    items.push(&tokens[start..]);
+    items
+}
+
+/// JavaScript precedence levels of the expressions the parser emits.
+const TERNARY: u8 = 2;
+const OR: u8 = 3;
+const AND: u8 = 4;
+const EQUALITY: u8 = 8;
+const RELATIONAL: u8 = 9;
+const ADDITIVE: u8 = 11;
+const MULTIPLICATIVE: u8 = 12;
+const EXPONENT: u8 = 13;
+const UNARY: u8 = 14;
+const POSTFIX: u8 = 17;
+
+/// What is known about the Python type of an expression's value, which decides whether a
+/// JavaScript operator can stand in for the Python one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ty {
+    Num,
+    Str,
+    Bool,
+    None,
+    List,
+    Unknown,
+}
+
+impl Ty {
+    /// Whether JavaScript's truthiness and `&&`/`||` agree with Python's for the value.
+    fn primitive(self) -> bool {
+        matches!(self, Ty::Num | Ty::Str | Ty::Bool | Ty::None)
+    }
+}
+
+/// Generated JavaScript, the precedence of its outermost operator and the value's type.
+struct Expr {
+    js: String,
+    prec: u8,
+    ty: Ty,
+}
+
+impl Expr {
+    fn new(js: String, prec: u8) -> Self {
+        Self {
+            js,
+            prec,
+            ty: Ty::Unknown,
+        }
+    }
+
+    fn typed(mut self, ty: Ty) -> Self {
+        self.ty = ty;
+        self
+    }
+
+    /// The expression as a JavaScript boolean condition with Python's truthiness, under which
+    /// empty lists are false.
+    fn truthy(self) -> Expr {
+        if self.ty.primitive() {
+            return self;
+        }
+        Expr::new(format!("__py_truthy({})", self.js), POSTFIX).typed(Ty::Bool)
+    }
+
+    /// The expression as an operand that needs at least precedence `min`.
+    fn at(&self, min: u8) -> String {
+        if self.prec < min {
+            format!("({})", self.js)
+        } else {
+            self.js.clone()
+        }
+    }
+}
+
+/// Precedence climbing over Python's expression grammar, lowest first: conditional
+/// expressions, `or`, `and`, `not`, comparisons, `+`/`-`, `*`/`/`/`//`/`%`, unary signs, `**`
+/// and calls, subscripts and method calls.
+struct Parser<'a> {
+    tokens: &'a [Tok],
+    pos: usize,
+    scopes: &'a [Scope],
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Tok> {
+        self.tokens.get(self.pos)
+    }
+
+    fn eat_op(&mut self, op: &str) -> bool {
+        let found = matches!(self.peek(), Some(Tok::Op(found)) if *found == op);
+        self.pos += usize::from(found);
+        found
+    }
+
+    fn eat_name(&mut self, name: &str) -> bool {
+        let found = matches!(self.peek(), Some(Tok::Name(found)) if found == name);
+        self.pos += usize::from(found);
+        found
+    }
+
+    fn expect_op(&mut self, op: &str) -> Result<()> {
+        if self.eat_op(op) {
+            return Ok(());
+        }
+        Err(self.unexpected())
+    }
+
+    fn unexpected(&self) -> anyhow::Error {
+        match self.peek() {
+            Some(Tok::Name(text) | Tok::Number(text) | Tok::Str(text)) => {
+                anyhow!("unsupported Python syntax near `{text}`")
+            }
+            Some(Tok::Op(op)) => anyhow!("unsupported Python syntax near `{op}`"),
+            None => anyhow!("incomplete expression"),
+        }
+    }
+
+    fn finish(&self) -> Result<()> {
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(self.unexpected()),
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expr> {
+        let body = self.or()?;
+        if !self.eat_name("if") {
+            return Ok(body);
+        }
+        let condition = self.or()?;
+        if !self.eat_name("else") {
+            return Err(self.unexpected());
+        }
+        let other = self.expression()?;
+        let ty = if body.ty == other.ty {
+            body.ty
+        } else {
+            Ty::Unknown
+        };
+        Ok(Expr::new(
+            format!(
+                "{} ? {} : {}",
+                condition.truthy().at(OR),
+                body.at(TERNARY),
+                other.at(TERNARY)
+            ),
+            TERNARY,
+        )
+        .typed(ty))
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut left = self.and()?;
+        while self.eat_name("or") {
+            let right = self.and()?;
+            left = logical(left, "||", "__py_or", right, OR);
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut left = self.not()?;
+        while self.eat_name("and") {
+            let right = self.not()?;
+            left = logical(left, "&&", "__py_and", right, AND);
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Expr> {
+        if self.eat_name("not") {
+            let operand = self.not()?.truthy();
+            return Ok(Expr::new(format!("!{}", operand.at(UNARY)), UNARY).typed(Ty::Bool));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        let left = self.additive()?;
+        let Some(op) = self.comparison_operator() else {
+            return Ok(left);
+        };
+        let right = self.additive()?;
+        if self.comparison_operator().is_some() {
+            bail!("chained comparisons are not supported");
+        }
+        let none = left.ty == Ty::None || right.ty == Ty::None;
+        let known = left.ty.primitive() || right.ty.primitive();
+        // Booleans equal the integers 1 and 0 in Python, so `===` only stands in for `==`
+        // where neither side can be a boolean compared against a number.
+        let strict = left.ty == Ty::Str
+            || right.ty == Ty::Str
+            || (left.ty == right.ty && left.ty.primitive());
+        let strings = left.ty == Ty::Str || right.ty == Ty::Str;
+        let expr = match op {
+            "in" if strings => Expr::new(
+                format!("{}.includes({})", right.at(POSTFIX), left.js),
+                POSTFIX,
+            ),
+            "not in" if strings => Expr::new(
+                format!("!{}.includes({})", right.at(POSTFIX), left.js),
+                UNARY,
+            ),
+            "in" => Expr::new(format!("__py_in({}, {})", left.js, right.js), POSTFIX),
+            "not in" => Expr::new(format!("!__py_in({}, {})", left.js, right.js), UNARY),
+            // Functions that end without `return` return `undefined`, which is Python's
+            // `None` too.
+            "===" | "is" if none => binary(left, "==", right, EQUALITY),
+            "!==" | "is not" if none => binary(left, "!=", right, EQUALITY),
+            "is" => binary(left, "===", right, EQUALITY),
+            "is not" => binary(left, "!==", right, EQUALITY),
+            "===" | "!==" if strict => binary(left, op, right, EQUALITY),
+            "===" => Expr::new(format!("__py_eq({}, {})", left.js, right.js), POSTFIX),
+            "!==" => Expr::new(format!("!__py_eq({}, {})", left.js, right.js), UNARY),
+            // Ordering a number or string against another type fails in Python, so only
+            // values of unknown types need Python's list ordering.
+            op if known => binary(left, op, right, RELATIONAL),
+            op => Expr::new(
+                format!("__py_cmp({}, {}) {op} 0", left.js, right.js),
+                RELATIONAL,
+            ),
+        };
+        Ok(expr.typed(Ty::Bool))
+    }
+
+    fn comparison_operator(&mut self) -> Option<&'static str> {
+        let op = match self.peek()? {
+            Tok::Op("==") => "===",
+            Tok::Op("!=") => "!==",
+            Tok::Op(op @ ("<" | ">" | "<=" | ">=")) => *op,
+            Tok::Name(name) if name == "in" => "in",
+            Tok::Name(name) if name == "is" => {
+                self.pos += 1;
+                return Some(if self.eat_name("not") { "is not" } else { "is" });
+            }
+            Tok::Name(name) if name == "not" => {
+                if !matches!(self.tokens.get(self.pos + 1), Some(Tok::Name(next)) if next == "in") {
+                    return None;
+                }
+                self.pos += 2;
+                return Some("not in");
+            }
+            _ => return None,
+        };
+        self.pos += 1;
+        Some(op)
+    }
+
+    fn additive(&mut self) -> Result<Expr> {
+        let mut left = self.term()?;
+        loop {
+            let op = if self.eat_op("+") {
+                "+"
+            } else if self.eat_op("-") {
+                "-"
+            } else {
+                return Ok(left);
+            };
+            let right = self.term()?;
+            left = match op {
+                "-" => binary(left, op, right, ADDITIVE).typed(Ty::Num),
+                // `+` on a number or string either matches JavaScript's or fails in Python;
+                // only lists need concatenating.
+                _ if matches!(left.ty, Ty::Num | Ty::Bool)
+                    && matches!(right.ty, Ty::Num | Ty::Bool) =>
+                {
+                    binary(left, op, right, ADDITIVE).typed(Ty::Num)
+                }
+                _ if left.ty == Ty::Str || right.ty == Ty::Str => {
+                    binary(left, op, right, ADDITIVE).typed(Ty::Str)
+                }
+                _ if matches!(left.ty, Ty::Num | Ty::Bool)
+                    || matches!(right.ty, Ty::Num | Ty::Bool) =>
+                {
+                    binary(left, op, right, ADDITIVE).typed(Ty::Num)
+                }
+                _ => Expr::new(format!("__py_add({}, {})", left.js, right.js), POSTFIX),
+            };
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr> {
+        let mut left = self.unary()?;
+        loop {
+            if matches!(self.peek(), Some(Tok::Op("/"))) {
+                bail!("true division is not supported, since it returns floats");
+            }
+            let op = ["*", "//", "%"].into_iter().find(|op| self.eat_op(op));
+            let Some(op) = op else {
+                return Ok(left);
+            };
+            let right = self.unary()?;
+            let numbers =
+                matches!(left.ty, Ty::Num | Ty::Bool) && matches!(right.ty, Ty::Num | Ty::Bool);
+            left = match op {
+                "//" if !nonzero_literal(&right) => {
+                    Expr::new(format!("__py_floordiv({}, {})", left.js, right.js), POSTFIX)
+                        .typed(Ty::Num)
+                }
+                "//" => Expr::new(
+                    format!(
+                        "Math.floor({} / {})",
+                        left.at(MULTIPLICATIVE),
+                        right.at(MULTIPLICATIVE + 1)
+                    ),
+                    POSTFIX,
+                )
+                .typed(Ty::Num),
+                "*" if numbers => binary(left, op, right, MULTIPLICATIVE).typed(Ty::Num),
+                "*" => {
+                    let ty = match (left.ty, right.ty) {
+                        (Ty::Str, _) | (_, Ty::Str) => Ty::Str,
+                        (Ty::List, _) | (_, Ty::List) => Ty::List,
+                        _ => Ty::Unknown,
+                    };
+                    Expr::new(format!("__py_mul({}, {})", left.js, right.js), POSTFIX).typed(ty)
+                }
+                _ if left.ty == Ty::Str => bail!("`%` string formatting is not supported"),
+                // JavaScript's `%` takes the sign of the dividend, Python's that of the divisor.
+                _ => Expr::new(format!("__py_mod({}, {})", left.js, right.js), POSTFIX)
+                    .typed(Ty::Num),
+            };
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        for sign in ["-", "+"] {
+            if self.eat_op(sign) {
+                let operand = self.unary()?;
+                let js = operand.at(UNARY);
+                // `- -x` must not become a decrement.
+                let space = if js.starts_with(['-', '+']) { " " } else { "" };
+                return Ok(Expr::new(format!("{sign}{space}{js}"), UNARY).typed(Ty::Num));
+            }
+        }
+        self.power()
+    }
+
+    fn power(&mut self) -> Result<Expr> {
+        let base = self.postfix()?;
+        if !self.eat_op("**") {
+            return Ok(base);
+        }
+        if matches!(self.peek(), Some(Tok::Op("-"))) {
+            bail!("negative exponents are not supported, since they return floats");
+        }
+        let exponent = self.unary()?;
+        Ok(Expr::new(
+            format!("{} ** {}", base.at(POSTFIX), exponent.at(EXPONENT)),
+            EXPONENT,
+        )
+        .typed(Ty::Num))
+    }
+
+    fn postfix(&mut self) -> Result<Expr> {
+        let mut builtin = match self.peek() {
+            Some(Tok::Name(name)) if !self.shadowed(name) => Some(name.clone()),
+            _ => None,
+        };
+        let mut expr = self.atom()?;
+        loop {
+            if self.eat_op("(") {
+                let args = self.arguments(")")?;
+                expr = match builtin.take() {
+                    Some(name) => call_builtin(&name, &expr, &args)?,
+                    None => Expr::new(format!("{}({})", expr.at(POSTFIX), join(&args)), POSTFIX),
+                };
+            } else if self.eat_op("[") {
+                if matches!(self.peek(), Some(Tok::Op("-"))) {
+                    bail!("negative indexes are not supported");
+                }
+                let index = self.expression()?;
+                if matches!(self.peek(), Some(Tok::Op(":"))) {
+                    bail!("slices are not supported");
+                }
+                self.expect_op("]")?;
+                // Python raises `IndexError` where JavaScript would read `undefined`.
+                expr = Expr::new(format!("__py_index({}, {})", expr.js, index.js), POSTFIX);
+            } else if self.eat_op(".") {
+                let Some(Tok::Name(method)) = self.peek().cloned() else {
+                    return Err(self.unexpected());
+                };
+                self.pos += 1;
+                if !self.eat_op("(") {
+                    bail!("attribute access (`.{method}`) is not supported");
+                }
+                let args = self.arguments(")")?;
+                expr = call_method(&expr, &method, &args)?;
+            } else {
+                return Ok(expr);
+            }
+            builtin = None;
+        }
+    }
+
+    /// Comma-separated expressions up to `close`, which is consumed.
+    fn arguments(&mut self, close: &str) -> Result<Vec<Expr>> {
+        let mut args = Vec::new();
+        while !self.eat_op(close) {
+            if matches!(self.peek(), Some(Tok::Op("*" | "**"))) {
+                bail!("unpacking arguments is not supported");
+            }
+            if matches!(self.tokens.get(self.pos + 1), Some(Tok::Op("="))) {
+                bail!("keyword arguments are not supported");
+            }
+            args.push(self.expression()?);
+            if !self.eat_op(",") {
+                self.expect_op(close)?;
+                break;
+            }
+        }
+        Ok(args)
+    }
+
+    fn atom(&mut self) -> Result<Expr> {
+        let Some(tok) = self.peek().cloned() else {
+            return Err(self.unexpected());
+        };
+        self.pos += 1;
+        let (js, ty) = match tok {
+            Tok::Number(number) => {
+                let hex = number.starts_with("0x") || number.starts_with("0X");
+                if number.contains('.') || (!hex && number.contains(['e', 'E'])) {
+                    bail!("floats are not supported, since JavaScript prints `2.0` as `2`");
+                }
+                (number, Ty::Num)
+            }
+            Tok::Str(literal) => {
+                if matches!(self.peek(), Some(Tok::Str(_))) {
+                    bail!("implicitly concatenated strings are not supported");
+                }
+                (literal, Ty::Str)
+            }
+            Tok::Name(name) => match name.as_str() {
+                "True" => ("true".to_string(), Ty::Bool),
+                "False" => ("false".to_string(), Ty::Bool),
+                "None" => ("null".to_string(), Ty::None),
+                "__name__" => ("\"__main__\"".to_string(), Ty::Str),
+                _ => {
+                    check_name(&name)?;
+                    let scope = self.scopes.last().expect("the module scope stays");
+                    if !scope.names.contains(&name) {
+                        scope.reads.borrow_mut().insert(name.clone());
+                    }
+                    if UNSUPPORTED_BUILTINS.contains(&name.as_str()) && !self.shadowed(&name) {
+                        bail!("the `{name}` builtin is not supported");
+                    }
+                    (name, Ty::Unknown)
+                }
+            },
+            Tok::Op("(") => {
+                let inner = self.expression()?;
+                if self.eat_op(")") {
+                    return Ok(inner);
+                }
+                bail!("tuples are not supported");
+            }
+            Tok::Op("[") => (format!("[{}]", join(&self.arguments("]")?)), Ty::List),
+            Tok::Op(_) => {
+                self.pos -= 1;
+                return Err(self.unexpected());
+            }
+        };
+        Ok(Expr::new(js, POSTFIX).typed(ty))
+    }
+
+    fn shadowed(&self, name: &str) -> bool {
+        self.scopes.iter().any(|scope| scope.names.contains(name))
+    }
+}
+
+fn binary(left: Expr, op: &str, right: Expr, prec: u8) -> Expr {
+    Expr::new(
+        format!("{} {op} {}", left.at(prec), right.at(prec + 1)),
+        prec,
+    )
+}
+
+/// `and`/`or`, which return one of their operands. JavaScript picks the same one as Python
+/// unless the left operand may be a list, whose truthiness differs.
+fn logical(left: Expr, op: &str, helper: &str, right: Expr, prec: u8) -> Expr {
+    let ty = if left.ty == right.ty {
+        left.ty
+    } else {
+        Ty::Unknown
+    };
+    if left.ty.primitive() {
+        return binary(left, op, right, prec).typed(ty);
+    }
+    Expr::new(
+        format!("{helper}({}, () => {})", left.js, right.js),
+        POSTFIX,
+    )
+    .typed(ty)
+}
+
+fn join(args: &[Expr]) -> String {
+    args.iter()
+        .map(|arg| arg.js.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// `print`, `len`, `str`, `int`, `bool`, `abs`, `min` and `max`; other names are called as
+/// they are.
+fn call_builtin(name: &str, callee: &Expr, args: &[Expr]) -> Result<Expr> {
+    let one = || match args {
+        [arg] => Ok(arg),
+        _ => Err(anyhow!("{name}() takes one argument here")),
+    };
+    let (js, ty) = match name {
+        // `console.log` prints strings and integers the way Python does.
+        "print" if args.iter().all(|arg| matches!(arg.ty, Ty::Str | Ty::Num)) => {
+            (format!("console.log({})", join(args)), Ty::None)
+        }
+        "print" => (format!("__py_print({})", join(args)), Ty::None),
+        "len" => (format!("{}.length", one()?.at(POSTFIX)), Ty::Num),
+        "str" if one()?.ty == Ty::Str => (one()?.js.clone(), Ty::Str),
+        "str" => (format!("__py_str({})", one()?.js), Ty::Str),
+        "int" => (format!("Math.trunc(Number({}))", one()?.js), Ty::Num),
+        "bool" if one()?.ty.primitive() => (format!("Boolean({})", one()?.js), Ty::Bool),
+        "bool" => (format!("__py_truthy({})", one()?.js), Ty::Bool),
+        "abs" => (format!("Math.abs({})", one()?.js), Ty::Num),
+        "min" | "max" => match args {
+            [] => bail!("{name}() needs arguments"),
+            args => (format!("__py_{name}({})", join(args)), Ty::Unknown),
+        },
+        _ => (format!("{}({})", callee.js, join(args)), Ty::Unknown),
+    };
+    Ok(Expr::new(js, POSTFIX).typed(ty))
+}
+
+/// String and list methods with a JavaScript counterpart.
+fn call_method(receiver: &Expr, method: &str, args: &[Expr]) -> Result<Expr> {
+    let receiver_js = receiver.at(POSTFIX);
+    let (renamed, ty) = match (method, args.len()) {
+        ("append", 1) => {
+            // `push` returns the new length; `append` returns `None`.
+            return Ok(
+                Expr::new(format!("void {receiver_js}.push({})", join(args)), UNARY)
+                    .typed(Ty::None),
+            );
+        }
+        ("pop", 0) => ("pop", Ty::Unknown),
+        ("upper", 0) => ("toUpperCase", Ty::Str),
+        ("lower", 0) => ("toLowerCase", Ty::Str),
+        ("strip", 0) => ("trim", Ty::Str),
+        ("lstrip", 0) => ("trimStart", Ty::Str),
+        ("rstrip", 0) => ("trimEnd", Ty::Str),
+        ("startswith", 1) => ("startsWith", Ty::Bool),
+        ("endswith", 1) => ("endsWith", Ty::Bool),
+        ("find", 1) => ("indexOf", Ty::Num),
+        ("split", 1) => ("split", Ty::List),
+        ("replace", 2) => ("replaceAll", Ty::Str),
+        ("join", 1) => {
+            return Ok(Expr::new(
+                format!("{}.join({receiver_js})", args[0].at(POSTFIX)),
+                POSTFIX,
+            )
+            .typed(Ty::Str));
+        }
+        _ => bail!(
+            "the `.{method}()` method with {} arguments is not supported",
+            args.len()
+        ),
+    };
+    Ok(Expr::new(format!("{receiver_js}.{renamed}({})", join(args)), POSTFIX).typed(ty))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::transpile_python;
+    use klumo_engine::{BoaEngine, JsEngine};
+
+    #[test]
+    fn simple_scripts_keep_their_lines() {
+        let source = "# totals\n\
+                      def total(prices, tax=25):\n\
+                      \x20   sum = 0\n\
+                      \x20   for price in prices:\n\
+                      \x20       sum += price  # running\n\
+                      \x20   return sum * (100 + tax) // 100\n\
+                      \n\
+                      a, b = 0, 1\n\
+                      for i in range(10, 0, -2):\n\
+                      \x20   a, b = b, a + b\n\
+                      if not a > 5 and b is not None:\n\
+                      \x20   label = 'small'\n\
+                      elif a // 2 == 2:\n\
+                      \x20   pass\n\
+                      else:\n\
+                      \x20   label = f\"big {a ** 2}\"\n\
+                      print(label, len([1, 2]))\n";
+        let javascript = transpile_python(source).expect("script should transpile");
+        let (script, helpers) = javascript.split_at(
+            javascript
+                .find("function __py_")
+                .expect("helpers follow the script"),
+        );
+        assert_eq!(
+            script,
+            "// totals\n\
+             function total(prices, tax = 25) {\n\
+             \x20   var sum = 0;\n\
+             \x20   for (var price of prices) {\n\
+             \x20       sum = __py_iadd(sum, price); } // running\n\
+             \x20   return Math.floor(__py_mul(sum, 100 + tax) / 100); }\n\
+             \n\
+             var [a, b] = [0, 1];\n\
+             for (var i = 10; i > 0; i -= 2) {\n\
+             \x20   [a, b] = [b, __py_add(a, b)]; }\n\
+             if (!(a > 5) && b != null) {\n\
+             \x20   var label = 'small'; }\n\
+             else if (Math.floor(a / 2) === 2) {}\n\
+             \n\
+             else {\n\
+             \x20   label = `big ${a ** 2}`; }\n\
+             __py_print(label, [1, 2].length);\n"
+        );
+        let names: Vec<&str> = helpers
+            .lines()
+            .map(|line| &line["function ".len()..line.find('(').expect("a parameter list")])
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "__py_add",
+                "__py_iadd",
+                "__py_mul",
+                "__py_repr",
+                "__py_str",
+                "__py_print"
+            ]
+        );
+    }
+
+    #[test]
+    fn transpiled_scripts_run() {
+        let source = "def fib(n):\n\
+                      \x20   a, b = 0, 1\n\
+                      \x20   while n > 0:\n\
+                      \x20       a, b = b, a + b\n\
+                      \x20       n -= 1\n\
+                      \x20   return a\n\
+                      \n\
+                      words = []\n\
+                      for word in 'a b c'.split(' '):\n\
+                      \x20   if word != 'b':\n\
+                      \x20       words.append(word.upper())\n\
+                      result = f\"{fib(10)}:{'-'.join(words)}:{-2 ** 2}:{7 // 2}\"\n\
+                      result if 'A' in words else None\n";
+        let javascript = transpile_python(source).expect("script should transpile");
+        let mut engine = BoaEngine::new().expect("engine should initialize");
+        let output = engine
+            .eval_script(&javascript, "fib.js")
+            .expect("transpiled script should run");
+        assert_eq!(output.value.as_deref(), Some("55:A-C:-4:3"));
+    }
+
+    /// Expected output is what CPython prints for the same script.
+    #[test]
+    fn transpiled_scripts_follow_python_semantics() {
+        let source = "def nothing():\n\
+                      \x20   pass\n\
+                      \n\
+                      xs = []\n\
+                      if xs:\n\
+                      \x20   print(\"empty list is truthy\")\n\
+                      else:\n\
+                      \x20   print(\"empty list is falsy\")\n\
+                      word = \"a\"\n\
+                      print(word * 3, 3 * [0], [1] + [2, 3])\n\
+                      n = -7\n\
+                      print(n % 3, 7 % -3, n // 2)\n\
+                      print(True, None, nothing())\n\
+                      print([1, 2], ['it\\'s', \"a\\\\b\", [None, False]])\n\
+                      label = xs or \"fallback\"\n\
+                      print(label, xs and \"unused\", not xs, bool([0]))\n\
+                      print([1, 2] == [1, 2], [1, 2] != [1, 3])\n\
+                      print([1, 2] < [1, 3], nothing() is None)\n\
+                      print(str([True]), f\"{xs}|{None}|{word * 2}\")\n\
+                      print(max(\"b\", \"c\", \"a\"), min([3, 1, 2]))\n\
+                      total = 0\n\
+                      total += 5\n\
+                      total %= 3\n\
+                      words = [\"x\"]\n\
+                      words *= 2\n\
+                      words += [\"y\"]\n\
+                      print(total, words)\n";
+        let javascript = transpile_python(source).expect("script should transpile");
+        let mut engine = BoaEngine::new().expect("engine should initialize");
+        let output = engine
+            .eval_script(&javascript, "semantics.js")
+            .expect("transpiled script should run");
+        assert_eq!(
+            output.console_output.as_deref(),
+            Some(
+                "empty list is falsy\n\
+                 aaa [0, 0, 0] [1, 2, 3]\n\
+                 2 -2 -4\n\
+                 True None None\n\
+                 [1, 2] [\"it's\", 'a\\\\b', [None, False]]\n\
+                 fallback [] True True\n\
+                 True True\n\
+                 True True\n\
+                 [True] []|None|aa\n\
+                 c 1\n\
+                 2 ['x', 'x', 'y']"
+            )
+        );
+    }
+
+    /// Each case fails in CPython with the error named, or prints the output given.
+    #[test]
+    fn transpiled_scripts_raise_where_python_does() {
+        let run = |source: &str| {
+            let javascript = transpile_python(source).expect("script should transpile");
+            let mut engine = BoaEngine::new().expect("engine should initialize");
+            engine.eval_script(&javascript, "script.js")
+        };
+        for (source, error) in [
+            ("n = 0\nprint(7 // n)\n", "ZeroDivisionError"),
+            ("n = 7\nn //= 0\n", "ZeroDivisionError"),
+            ("xs = [1, 2]\nprint(xs[5])\n", "IndexError"),
+            ("xs = [1, 2]\nxs[2] = 3\n", "IndexError"),
+        ] {
+            let err = run(source).expect_err("script should fail");
+            assert!(format!("{err:#}").contains(error), "{source:?}: {err:#}");
+        }
+
+        let output = run("a = [1, 2]\n\
+                          b = a\n\
+                          b += [3]\n\
+                          c = b\n\
+                          c *= 2\n\
+                          i = 1\n\
+                          a[i] = 5\n\
+                          n = 1\n\
+                          print(a, n == True, True in [n], [1] in [[True]], a[i])\n")
+        .expect("script should run");
+        assert_eq!(
+            output.console_output.as_deref(),
+            Some("[1, 5, 3, 1, 2, 3] True True True 5")
+        );
+    }
+
+    #[test]
+    fn unsupported_constructs_are_left_to_translation() {
+        for source in [
+            "import sys\n",
+            "class Point:\n    pass\n",
+            "counts = {}\n",
+            "print(x, end='')\n",
+            "items = [x * 2 for x in range(3)]\n",
+            "tail = items[1:]\n",
+            "total = (1 +\n    2)\n",
+            "if x: y = 1\n",
+            "for i in range(3):\n    pass\nelse:\n    pass\n",
+            "x = sorted(items)\n",
+            "ok = 1 < x < 3\n",
+            "def f(*args):\n    pass\n",
+            "if x:\ny = 1\n",
+            "name.title()\n",
+            "half = 7 / 2\n",
+            "rate = 0.5\n",
+            "x = float(y)\n",
+            "pair = (1, 2)\n",
+            "pair = 1, 2\n",
+            "def f():\n    return 1, 2\n",
+            "text = '%d' % 3\n",
+            "x = 2 ** -1\n",
+            "def f(xs=[]):\n    pass\n",
+            "limit = 3\ndef f(n=limit):\n    pass\n",
+            "def f():\n    print(x)\n    x = 1\n",
+            "total = 0\ndef f():\n    total = total + 1\n",
+            "print(x)\nx = 1\n",
+            "f()[0] = 1\n",
+        ] {
+            assert!(
+                transpile_python(source).is_err(),
+                "{source:?} should not transpile"
+            );
+        }
+    }
+}
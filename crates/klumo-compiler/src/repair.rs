@@ -0,0 +1,34 @@
+/// A request asking a model to repair JavaScript that failed, given the error and the full
+/// source. Compile-time syntax repairs and `klumo run --self-heal` send the same wording.
+#[derive(Debug, Clone, Copy)]
+pub struct RepairRequest<'a> {
+    /// Names the file in the prompt: a path, or a source id for translations.
+    pub file: &'a str,
+    /// Heading of the error block, e.g. `Runtime error` or `Syntax error`.
+    pub error_label: &'a str,
+    pub error_text: &'a str,
+    pub source: &'a str,
+    /// Ask for a one-sentence account of the change in the structured `notes` field.
+    pub describe_change: bool,
+}
+
+impl RepairRequest<'_> {
+    pub fn render(&self) -> String {
+        let describe = if self.describe_change {
+            "Describe what you changed in one sentence in the notes field.\n"
+        } else {
+            ""
+        };
+        format!(
+            "Repair this JavaScript file so it runs successfully.\n\
+Return ONLY complete JavaScript source for the full file, no markdown, no prose.\n\
+Preserve behavior and structure as much as possible.\n\
+{describe}\
+File: {}\n\
+{}:\n{}\n\
+SOURCE START\n{}\n\
+SOURCE END",
+            self.file, self.error_label, self.error_text, self.source
+        )
+    }
+}
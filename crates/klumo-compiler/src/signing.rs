@@ -0,0 +1,225 @@
+use anyhow::{Context, Result, anyhow, bail};
+use ring::rand::SystemRandom;
+use ring::signature::{ED25519, Ed25519KeyPair, KeyPair, UnparsedPublicKey};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What a signature covers besides the JavaScript itself, so a valid signature cannot be
+/// moved onto other code paths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signed<'a> {
+    /// A translation of this source text, as kept in the compile cache.
+    Translation(&'a str),
+    /// A JavaScript file run as it is, signed in a `<file>.sig` sidecar.
+    File,
+}
+
+impl Signed<'_> {
+    /// The signed bytes: a tag for the kind of artifact, the SHA-256 of a translation's
+    /// source, then the JavaScript.
+    fn message(self, javascript: &str) -> Vec<u8> {
+        let mut message = match self {
+            Self::Translation(source) => format!(
+                "klumo translation\n{}\n",
+                to_hex(&Sha256::digest(source.as_bytes()))
+            ),
+            Self::File => "klumo file\n".to_string(),
+        }
+        .into_bytes();
+        message.extend_from_slice(javascript.as_bytes());
+        message
+    }
+}
+
+/// A project's ed25519 signing key, stored hex-encoded (PKCS#8) in a key file.
+pub struct SigningKey {
+    pair: Ed25519KeyPair,
+}
+
+impl fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SigningKey")
+            .field("public_key", &self.public_key())
+            .finish()
+    }
+}
+
+impl SigningKey {
+    /// Generates a new key and returns it with the hex PKCS#8 text to store in a key file.
+    pub fn generate() -> Result<(Self, String)> {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .map_err(|_| anyhow!("failed generating ed25519 key"))?;
+        let text = to_hex(pkcs8.as_ref());
+        Ok((Self::from_hex(&text)?, text))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed reading signing key {}", path.display()))?;
+        Self::from_hex(text.trim())
+            .with_context(|| format!("invalid signing key {}", path.display()))
+    }
+
+    fn from_hex(text: &str) -> Result<Self> {
+        let pair = Ed25519KeyPair::from_pkcs8(&from_hex(text)?)
+            .map_err(|err| anyhow!("not an ed25519 PKCS#8 key: {err}"))?;
+        Ok(Self { pair })
+    }
+
+    /// Hex public key, the form listed under `trusted_keys`.
+    pub fn public_key(&self) -> String {
+        to_hex(self.pair.public_key().as_ref())
+    }
+
+    /// Hex signature over `javascript` as `signed`.
+    pub fn sign(&self, signed: Signed<'_>, javascript: &str) -> String {
+        to_hex(self.pair.sign(&signed.message(javascript)).as_ref())
+    }
+}
+
+/// Public keys whose signatures are accepted in `--verify-signatures` mode.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TrustedKeys {
+    keys: Vec<Vec<u8>>,
+}
+
+impl TrustedKeys {
+    pub fn from_hex<S: AsRef<str>>(keys: &[S]) -> Result<Self> {
+        let keys = keys
+            .iter()
+            .map(|key| {
+                let bytes = from_hex(key.as_ref())
+                    .with_context(|| format!("invalid trusted key '{}'", key.as_ref()))?;
+                if bytes.len() != 32 {
+                    bail!(
+                        "trusted key '{}' is not a 32-byte ed25519 key",
+                        key.as_ref()
+                    );
+                }
+                Ok(bytes)
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { keys })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Whether `signature` (hex) over `javascript` as `signed` was made by one of the
+    /// trusted keys.
+    pub fn verify(&self, signed: Signed<'_>, javascript: &str, signature: &str) -> bool {
+        let Ok(signature) = from_hex(signature.trim()) else {
+            return false;
+        };
+        let message = signed.message(javascript);
+        self.keys.iter().any(|key| {
+            UnparsedPublicKey::new(&ED25519, key)
+                .verify(&message, &signature)
+                .is_ok()
+        })
+    }
+}
+
+/// How compile results are signed or checked.
+#[derive(Debug, Default)]
+pub enum SignaturePolicy {
+    #[default]
+    Off,
+    /// Attach a signature to every fresh translation before it is cached.
+    Sign(SigningKey),
+    /// Only run JavaScript signed by a trusted key; unsigned translations are never made.
+    Verify(TrustedKeys),
+}
+
+/// Checks the `<path>.sig` sidecar of the JavaScript file `path`, whose contents are
+/// `javascript`, and returns its signature.
+pub fn verify_signature_file(keys: &TrustedKeys, path: &Path, javascript: &str) -> Result<String> {
+    let sidecar = signature_path(path);
+    let signature = fs::read_to_string(&sidecar).map_err(|_| {
+        anyhow!(
+            "refusing to run {}: --verify-signatures requires a signature in {}",
+            path.display(),
+            sidecar.display()
+        )
+    })?;
+    if !keys.verify(Signed::File, javascript, &signature) {
+        bail!(
+            "refusing to run {}: {} is not a valid signature by a trusted key",
+            path.display(),
+            sidecar.display()
+        );
+    }
+    Ok(signature.trim().to_string())
+}
+
+/// Sidecar file holding the signature of a JavaScript file, e.g. a bundle: `<path>.sig`.
+pub fn signature_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".sig");
+    PathBuf::from(name)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn from_hex(text: &str) -> Result<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        bail!("hex text has an odd length");
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|idx| {
+            text.get(idx..idx + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| anyhow!("invalid hex text"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Signed, SigningKey, TrustedKeys, signature_path};
+    use std::path::Path;
+
+    #[test]
+    fn signatures_verify_only_for_trusted_keys_and_exact_code() {
+        let (key, text) = SigningKey::generate().expect("key should generate");
+        let signed = Signed::Translation("print 1");
+        let signature = key.sign(signed, "console.log(1);");
+
+        let trusted = TrustedKeys::from_hex(&[key.public_key()]).expect("key should parse");
+        assert!(trusted.verify(signed, "console.log(1);", &signature));
+        assert!(!trusted.verify(signed, "console.log(2);", &signature));
+        assert!(!trusted.verify(signed, "console.log(1);", "zz"));
+        assert!(!trusted.verify(
+            Signed::Translation("print 2"),
+            "console.log(1);",
+            &signature
+        ));
+        assert!(!trusted.verify(Signed::File, "console.log(1);", &signature));
+
+        let (other, _) = SigningKey::generate().expect("key should generate");
+        let untrusted = TrustedKeys::from_hex(&[other.public_key()]).expect("key should parse");
+        assert!(!untrusted.verify(signed, "console.log(1);", &signature));
+
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("signing.key");
+        std::fs::write(&path, format!("{text}\n")).expect("write key");
+        let loaded = SigningKey::load(&path).expect("key should load");
+        assert_eq!(loaded.public_key(), key.public_key());
+    }
+
+    #[test]
+    fn rejects_malformed_trusted_keys() {
+        assert!(TrustedKeys::from_hex(&["abcd"]).is_err());
+        assert!(TrustedKeys::from_hex(&["not hex"]).is_err());
+        assert_eq!(
+            signature_path(Path::new("dist/app.js")),
+            Path::new("dist/app.js.sig")
+        );
+    }
+}
@@ -0,0 +1,354 @@
+use std::collections::HashSet;
+
+/// Alignment tables larger than this many cells map lines proportionally instead.
+const MAX_ALIGNMENT_CELLS: usize = 4_000_000;
+
+/// Words that say nothing about which source line a JavaScript line came from.
+const JS_NOISE_WORDS: &[&str] = &[
+    "const",
+    "let",
+    "var",
+    "function",
+    "return",
+    "console",
+    "log",
+    "if",
+    "else",
+    "for",
+    "while",
+    "of",
+    "in",
+    "new",
+    "this",
+    "true",
+    "false",
+    "null",
+    "undefined",
+    "async",
+    "await",
+];
+
+/// A position in an original source: an index into [`SourceMap::sources`] and a 1-based line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OriginalLine {
+    pub source: usize,
+    pub line: usize,
+}
+
+/// For every line of some generated JavaScript, the source line it came from, when known.
+///
+/// JavaScript passthrough is unchanged, so its map is the identity. Translations and locally
+/// transpiled TypeScript are mapped best-effort: a `// @line N` (or `/* @line N */`) comment
+/// maps the lines after it to line `N` of the source, and output without markers is aligned to
+/// the source by the identifiers, numbers and string words the two share.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    /// Source ids (file paths) the map points into.
+    pub sources: Vec<String>,
+    /// Indexed by generated line, 0-based.
+    pub lines: Vec<Option<OriginalLine>>,
+}
+
+impl SourceMap {
+    /// Every generated line maps to the same line of `source_id`.
+    pub fn identity(source_id: &str, javascript: &str) -> Self {
+        Self {
+            sources: vec![source_id.to_string()],
+            lines: (1..=line_count(javascript))
+                .map(|line| Some(OriginalLine { source: 0, line }))
+                .collect(),
+        }
+    }
+
+    /// Maps a translation of `source` back onto it, from `@line` markers when the output has
+    /// any and by aligning shared words otherwise.
+    pub fn infer(source_id: &str, source: &str, javascript: &str) -> Self {
+        let source_lines = line_count(source);
+        let lines = marked_lines(javascript, source_lines)
+            .unwrap_or_else(|| aligned_lines(source, javascript));
+        Self {
+            sources: vec![source_id.to_string()],
+            lines: lines
+                .into_iter()
+                .map(|line| line.map(|line| OriginalLine { source: 0, line }))
+                .collect(),
+        }
+    }
+
+    /// The source and line a 1-based generated line came from.
+    pub fn lookup(&self, generated_line: usize) -> Option<(&str, usize)> {
+        let original = (*self.lines.get(generated_line.checked_sub(1)?)?)?;
+        Some((self.sources.get(original.source)?.as_str(), original.line))
+    }
+
+    /// Appends `other` for lines starting at 0-based `offset`, padding any gap with unmapped
+    /// lines. Used to map a program stitched together from separately compiled files.
+    pub fn extend_at(&mut self, offset: usize, other: &SourceMap) {
+        let sources: Vec<usize> = other
+            .sources
+            .iter()
+            .map(
+                |source| match self.sources.iter().position(|known| known == source) {
+                    Some(index) => index,
+                    None => {
+                        self.sources.push(source.clone());
+                        self.sources.len() - 1
+                    }
+                },
+            )
+            .collect();
+        if self.lines.len() < offset {
+            self.lines.resize(offset, None);
+        }
+        self.lines.truncate(offset);
+        self.lines.extend(other.lines.iter().map(|line| {
+            line.map(|line| OriginalLine {
+                source: sources[line.source],
+                line: line.line,
+            })
+        }));
+    }
+
+    /// Inserts `count` unmapped lines before 0-based generated line `at`, for lines added to
+    /// the top of a finished program.
+    pub fn insert_lines(&mut self, at: usize, count: usize) {
+        let at = at.min(self.lines.len());
+        self.lines.splice(at..at, std::iter::repeat_n(None, count));
+    }
+
+    /// Adds the original source and line to an engine error reporting `at line N` of the
+    /// generated program `generated_id`; `None` when the message has no mappable position or
+    /// it maps onto itself.
+    pub fn remap_error(&self, generated_id: &str, message: &str) -> Option<String> {
+        let marker = "at line ";
+        let at = message.find(marker)?;
+        let digits: String = message[at + marker.len()..]
+            .chars()
+            .take_while(char::is_ascii_digit)
+            .collect();
+        let generated_line = digits.parse().ok()?;
+        let (source, line) = self.lookup(generated_line)?;
+        if (source, line) == (generated_id, generated_line) {
+            return None;
+        }
+        Some(format!("{message} (from {source}:{line})"))
+    }
+
+    /// The map in the Source Map v3 JSON format, pointing each mapped line at column 0 of its
+    /// original line.
+    pub fn to_json(&self, file: &str) -> String {
+        let mut mappings = String::new();
+        let (mut source, mut line) = (0i64, 0i64);
+        for (index, original) in self.lines.iter().enumerate() {
+            if index > 0 {
+                mappings.push(';');
+            }
+            if let Some(original) = original {
+                let next_source = original.source as i64;
+                let next_line = original.line as i64 - 1;
+                for value in [0, next_source - source, next_line - line, 0] {
+                    encode_vlq(value, &mut mappings);
+                }
+                source = next_source;
+                line = next_line;
+            }
+        }
+        serde_json::json!({
+            "version": 3,
+            "file": file,
+            "sources": self.sources,
+            "names": [],
+            "mappings": mappings,
+        })
+        .to_string()
+    }
+}
+
+fn line_count(text: &str) -> usize {
+    text.lines().count().max(1)
+}
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Standard padded base64 of `bytes`, as in Subresource Integrity strings.
+pub fn base64(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, byte)| {
+            group | u32::from(*byte) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(group >> (18 - 6 * i) & 0b111111) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn encode_vlq(value: i64, out: &mut String) {
+    let mut rest = if value < 0 {
+        ((-value) << 1) | 1
+    } else {
+        value << 1
+    };
+    loop {
+        let mut digit = rest & 0b11111;
+        rest >>= 5;
+        if rest > 0 {
+            digit |= 0b100000;
+        }
+        out.push(BASE64[digit as usize] as char);
+        if rest == 0 {
+            break;
+        }
+    }
+}
+
+/// Lines following each `@line N` comment map to `N`; `None` when no line carries a marker
+/// in range.
+fn marked_lines(javascript: &str, source_lines: usize) -> Option<Vec<Option<usize>>> {
+    let mut current = None;
+    let mut any = false;
+    let lines = javascript
+        .lines()
+        .map(|line| {
+            if let Some(marked) = line_marker(line).filter(|line| (1..=source_lines).contains(line))
+            {
+                current = Some(marked);
+                any = true;
+            }
+            current
+        })
+        .collect();
+    any.then_some(lines)
+}
+
+fn line_marker(line: &str) -> Option<usize> {
+    let comment = line.find("//").or_else(|| line.find("/*"))?;
+    let rest = line[comment + 2..].trim_start().strip_prefix("@line")?;
+    let digits: String = rest
+        .trim_start()
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect();
+    digits.parse().ok()
+}
+
+/// Aligns generated lines to source lines monotonically, maximizing the words each pair
+/// shares. Lines sharing nothing inherit the line before them.
+fn aligned_lines(source: &str, javascript: &str) -> Vec<Option<usize>> {
+    let source_words: Vec<HashSet<String>> = source.lines().map(words).collect();
+    let js_words: Vec<HashSet<String>> = javascript
+        .lines()
+        .map(|line| {
+            let mut words = words(line);
+            words.retain(|word| !JS_NOISE_WORDS.contains(&word.as_str()));
+            words
+        })
+        .collect();
+    let (rows, cols) = (js_words.len(), source_words.len());
+    if rows == 0 || cols == 0 {
+        return vec![None; rows];
+    }
+    if rows * cols > MAX_ALIGNMENT_CELLS {
+        return (0..rows).map(|row| Some(row * cols / rows + 1)).collect();
+    }
+
+    let score = |row: usize, col: usize| js_words[row].intersection(&source_words[col]).count();
+    // best[col] is the best total for the rows so far with the current row on `col`; `from`
+    // records, per row and column, which column the previous row sat on.
+    let mut best: Vec<usize> = (0..cols).map(|col| score(0, col)).collect();
+    let mut from = vec![0u32; rows * cols];
+    for row in 1..rows {
+        let mut running = (0, 0);
+        let mut next = vec![0; cols];
+        for col in 0..cols {
+            if best[col] >= running.0 {
+                running = (best[col], col);
+            }
+            next[col] = running.0 + score(row, col);
+            from[row * cols + col] = running.1 as u32;
+        }
+        best = next;
+    }
+    let mut col = (0..cols).max_by_key(|&col| (best[col], col)).unwrap_or(0);
+    let mut placed = vec![0; rows];
+    for row in (0..rows).rev() {
+        placed[row] = col;
+        col = from[row * cols + col] as usize;
+    }
+
+    let mut current = None;
+    (0..rows)
+        .map(|row| {
+            if score(row, placed[row]) > 0 {
+                current = Some(placed[row] + 1);
+            }
+            current
+        })
+        .collect()
+}
+
+/// Lowercased identifiers, numbers and string words in a line.
+fn words(line: &str) -> HashSet<String> {
+    line.split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$'))
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SourceMap;
+
+    #[test]
+    fn aligns_translated_lines_by_shared_words() {
+        let source = "set total to 0\nfor each price in prices\n  add price to total\nwrite total";
+        let javascript = "let total = 0;\nfor (const price of prices) {\n  total += price;\n}\nconsole.log(total);\n";
+        let map = SourceMap::infer("cart.pseudo", source, javascript);
+        let lines: Vec<Option<usize>> = (1..=5)
+            .map(|line| map.lookup(line).map(|(_, line)| line))
+            .collect();
+        assert_eq!(lines, vec![Some(1), Some(2), Some(3), Some(3), Some(4)]);
+    }
+
+    #[test]
+    fn line_markers_take_precedence_over_alignment() {
+        let javascript = "// @line 3\nconst a = 1;\n/* @line 1 */ const b = 2;\n";
+        let map = SourceMap::infer("x.pseudo", "one\ntwo\nthree", javascript);
+        assert_eq!(map.lookup(2), Some(("x.pseudo", 3)));
+        assert_eq!(map.lookup(3), Some(("x.pseudo", 1)));
+        assert_eq!(map.lookup(9), None);
+    }
+
+    #[test]
+    fn extend_at_offsets_lines_and_shares_sources() {
+        let mut map = SourceMap::default();
+        map.extend_at(2, &SourceMap::identity("a.js", "x\ny"));
+        map.extend_at(5, &SourceMap::identity("b.js", "z"));
+        assert_eq!(map.sources, vec!["a.js", "b.js"]);
+        assert_eq!(map.lookup(1), None);
+        assert_eq!(map.lookup(4), Some(("a.js", 2)));
+        assert_eq!(map.lookup(6), Some(("b.js", 1)));
+        assert_eq!(
+            map.remap_error("out.js", "SyntaxError: unexpected token at line 6, col 2"),
+            Some("SyntaxError: unexpected token at line 6, col 2 (from b.js:1)".to_string())
+        );
+        let identity = SourceMap::identity("a.js", "x\ny");
+        assert_eq!(identity.remap_error("a.js", "at line 2, col 1"), None);
+    }
+
+    #[test]
+    fn serializes_as_source_map_v3() {
+        let mut map = SourceMap::identity("a.js", "x\ny");
+        map.lines.insert(0, None);
+        let json: serde_json::Value =
+            serde_json::from_str(&map.to_json("out.js")).expect("valid JSON");
+        assert_eq!(json["version"], 3);
+        assert_eq!(json["sources"][0], "a.js");
+        assert_eq!(json["mappings"], ";AAAA;AACA");
+    }
+}
@@ -0,0 +1,96 @@
+use anyhow::{Result, anyhow};
+use oxc_allocator::Allocator;
+use oxc_codegen::Codegen;
+use oxc_parser::Parser;
+use oxc_semantic::SemanticBuilder;
+use oxc_span::SourceType;
+use oxc_transformer::{TransformOptions, Transformer};
+use std::fmt::Display;
+use std::path::Path;
+
+/// Transpiles TypeScript to JavaScript with oxc: types, interfaces and type-only imports are
+/// stripped, and enums, namespaces and constructor parameter properties are lowered. Sources
+/// without `import`/`export` parse as scripts. Fails when the source does not parse or the
+/// transform reports an error; callers fall back to the LLM then.
+pub fn transpile_typescript(source: &str) -> Result<String> {
+    transpile(
+        source,
+        SourceType::ts().with_unambiguous(true),
+        &TransformOptions::default(),
+    )
+}
+
+/// Parses `source` as `source_type`, runs the oxc transforms `options` enable and prints the
+/// result.
+pub(crate) fn transpile(
+    source: &str,
+    source_type: SourceType,
+    options: &TransformOptions,
+) -> Result<String> {
+    let allocator = Allocator::default();
+    let parsed = Parser::new(&allocator, source, source_type).parse();
+    if parsed.panicked || !parsed.diagnostics.is_empty() {
+        return Err(anyhow!(
+            "source did not parse: {}",
+            diagnostic_messages(&parsed.diagnostics)
+        ));
+    }
+    let mut program = parsed.program;
+
+    // Enum lowering reads the member values semantic analysis evaluates.
+    let analysed = SemanticBuilder::new_compiler()
+        .with_enum_eval(true)
+        .build(&program);
+    if !analysed.diagnostics.is_empty() {
+        return Err(anyhow!(
+            "source did not parse: {}",
+            diagnostic_messages(&analysed.diagnostics)
+        ));
+    }
+    let scoping = analysed.semantic.into_scoping();
+    let transformed = Transformer::new(&allocator, Path::new("main"), options)
+        .build_with_scoping(scoping, &mut program);
+    if !transformed.diagnostics.is_empty() {
+        return Err(anyhow!(
+            "source could not be transpiled: {}",
+            diagnostic_messages(&transformed.diagnostics)
+        ));
+    }
+
+    Ok(Codegen::new().build(&program).code)
+}
+
+pub(crate) fn diagnostic_messages(diagnostics: &[impl Display]) -> String {
+    diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.to_string())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_types_and_lowers_enums() {
+        let javascript = transpile_typescript(
+            "interface Point { x: number }\n\
+             enum Axis { X, Y }\n\
+             const p: Point = { x: 1 } as Point;\n\
+             console.log(p.x!, Axis[Axis.Y]);\n",
+        )
+        .expect("transpiles");
+
+        assert!(!javascript.contains("interface"));
+        assert!(!javascript.contains(": Point"));
+        assert!(javascript.contains("Axis[Axis[\"Y\"] = 1] = \"Y\""));
+        assert!(klumo_engine::check_syntax(&javascript).is_empty());
+    }
+
+    #[test]
+    fn sources_that_do_not_parse_are_rejected() {
+        let err = transpile_typescript("const x: = 1;").expect_err("does not parse");
+        assert!(err.to_string().contains("did not parse"));
+    }
+}
@@ -0,0 +1,202 @@
+use anyhow::{Context, Result, anyhow};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Keychain service name that provider API keys are stored under.
+pub const KEYCHAIN_SERVICE: &str = "klumo";
+
+/// Per-provider API keys from `~/.klumo/credentials.toml`, optionally backed by the OS keychain.
+///
+/// The file holds one table per provider (`openai` or a custom provider name):
+///
+/// ```toml
+/// [openai]
+/// api_key = "sk-..."
+/// ```
+#[derive(Clone, PartialEq, Eq, Default)]
+pub struct Credentials {
+    keys: BTreeMap<String, String>,
+    /// Fall back to the OS keychain (service `klumo`, account = provider name).
+    pub keychain: bool,
+}
+
+/// Keys are secrets, so only the provider names are printed.
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("providers", &self.keys.keys().collect::<Vec<_>>())
+            .field("keychain", &self.keychain)
+            .finish()
+    }
+}
+
+impl Credentials {
+    /// `KLUMO_CREDENTIALS` when set, otherwise `~/.klumo/credentials.toml`.
+    pub fn default_path() -> Option<PathBuf> {
+        env_path().or_else(|| dirs::home_dir().map(|home| home.join(".klumo/credentials.toml")))
+    }
+
+    /// Loads `path`; a missing file yields no keys.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed reading credentials {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("failed parsing credentials {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let doc = toml_edit::Document::parse(text).map_err(|err| anyhow!("{err}"))?;
+        let mut keys = BTreeMap::new();
+        for (provider, item) in doc.as_table().iter() {
+            let table = item
+                .as_table_like()
+                .ok_or_else(|| anyhow!("`{provider}` must be a table with an `api_key`"))?;
+            for (field, value) in table.iter() {
+                if field != "api_key" {
+                    return Err(anyhow!("unknown field `{field}` in `[{provider}]`"));
+                }
+                let key = value
+                    .as_str()
+                    .ok_or_else(|| anyhow!("`{provider}.api_key` must be a string"))?;
+                keys.insert(provider.to_string(), key.to_string());
+            }
+        }
+        Ok(Self {
+            keys,
+            keychain: false,
+        })
+    }
+
+    pub fn with_keychain(mut self, keychain: bool) -> Self {
+        self.keychain = keychain;
+        self
+    }
+
+    /// Key for `provider`: the credentials file first, then the keychain when enabled.
+    pub fn api_key(&self, provider: &str) -> Option<String> {
+        self.keys
+            .get(provider)
+            .cloned()
+            .or_else(|| self.keychain.then(|| keychain_lookup(provider)).flatten())
+    }
+
+    /// Writes `api_key` into the `[provider]` table of the file at `path`, keeping its other
+    /// entries. The new content goes to a staging file that is created readable only by its
+    /// owner on Unix and then renamed over `path`, so the key is never exposed in between.
+    pub fn save_api_key(path: &Path, provider: &str, api_key: &str) -> Result<()> {
+        let existing = if path.exists() {
+            fs::read_to_string(path)
+                .with_context(|| format!("failed reading credentials {}", path.display()))?
+        } else {
+            String::new()
+        };
+        let mut doc: toml_edit::DocumentMut = existing
+            .parse()
+            .with_context(|| format!("failed parsing credentials {}", path.display()))?;
+        doc[provider]["api_key"] = toml_edit::value(api_key);
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed creating {}", dir.display()))?;
+        }
+        let staging = path.with_extension(format!("toml.{}.tmp", std::process::id()));
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options
+            .open(&staging)
+            .and_then(|mut file| file.write_all(doc.to_string().as_bytes()))
+            .and_then(|()| fs::rename(&staging, path))
+            .with_context(|| format!("failed writing credentials {}", path.display()))
+    }
+}
+
+fn env_path() -> Option<PathBuf> {
+    std::env::var_os("KLUMO_CREDENTIALS").map(PathBuf::from)
+}
+
+/// Reads a generic password through the platform keychain CLI (`security` on macOS,
+/// `secret-tool` elsewhere). Any failure means "no key".
+fn keychain_lookup(provider: &str) -> Option<String> {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = Command::new("security");
+        command.args([
+            "find-generic-password",
+            "-s",
+            KEYCHAIN_SERVICE,
+            "-a",
+            provider,
+            "-w",
+        ]);
+        command
+    } else if cfg!(unix) {
+        let mut command = Command::new("secret-tool");
+        command.args(["lookup", "service", KEYCHAIN_SERVICE, "account", provider]);
+        command
+    } else {
+        return None;
+    };
+    let output = command.output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let key = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (!key.is_empty()).then_some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Credentials;
+
+    #[test]
+    fn parses_provider_tables() {
+        let credentials = Credentials::parse(
+            "[openai]\napi_key = \"sk-file\"\n\n[corp]\napi_key = \"corp-key\"\n",
+        )
+        .expect("credentials should parse");
+        assert_eq!(credentials.api_key("openai").as_deref(), Some("sk-file"));
+        assert_eq!(credentials.api_key("corp").as_deref(), Some("corp-key"));
+        assert_eq!(credentials.api_key("other"), None);
+        assert!(!format!("{credentials:?}").contains("sk-file"));
+    }
+
+    #[test]
+    fn rejects_malformed_entries() {
+        let err = Credentials::parse("openai = \"sk\"\n").expect_err("not a table");
+        assert!(err.to_string().contains("must be a table"));
+        let err = Credentials::parse("[openai]\nkey = \"sk\"\n").expect_err("unknown field");
+        assert!(err.to_string().contains("unknown field `key`"));
+    }
+
+    #[test]
+    fn saving_a_key_keeps_other_providers() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("nested/credentials.toml");
+        Credentials::save_api_key(&path, "corp", "corp-key").expect("first save");
+        Credentials::save_api_key(&path, "openai", "sk-old").expect("second save");
+        Credentials::save_api_key(&path, "openai", "sk-new").expect("overwrite");
+
+        let credentials = Credentials::load(&path).expect("saved file should load");
+        assert_eq!(credentials.api_key("openai").as_deref(), Some("sk-new"));
+        assert_eq!(credentials.api_key("corp").as_deref(), Some("corp-key"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path)
+                .expect("saved")
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+}
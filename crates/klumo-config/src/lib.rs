@@ -1,16 +1,35 @@
-use anyhow::{Context, Result};
+mod credentials;
+
+pub use credentials::{Credentials, KEYCHAIN_SERVICE};
+
+use anyhow::{Context, Result, anyhow};
 use serde::Deserialize;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::env;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
-#[serde(rename_all = "lowercase")]
+/// Seed sent in deterministic mode when `klumo.json` does not set `seed`.
+pub const DETERMINISTIC_SEED: u64 = 42;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
 pub enum ProviderSetting {
     Auto,
     Ollama,
     Openai,
+    Llamacpp,
+    Fixture,
+    /// A provider declared under `providers`, written `custom:<name>`.
+    Custom(String),
+}
+
+impl TryFrom<String> for ProviderSetting {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        parse_provider(&value).ok_or_else(|| format!("unknown provider '{value}'"))
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
@@ -21,7 +40,17 @@ pub enum ProgressSetting {
     Verbose,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Default)]
+/// Whether REPL code may use `klumo.clipboard`: ask once per session and access kind, or
+/// always allow or deny.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClipboardSetting {
+    Ask,
+    Allow,
+    Deny,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Default)]
 #[serde(deny_unknown_fields)]
 pub struct FileConfig {
     pub name: Option<String>,
@@ -31,34 +60,370 @@ pub struct FileConfig {
     pub provider: Option<ProviderSetting>,
     pub ollama_url: Option<String>,
     pub ollama_model: Option<String>,
+    pub llamacpp_url: Option<String>,
+    pub llamacpp_model: Option<String>,
     pub openai_base_url: Option<String>,
     pub openai_api_key: Option<String>,
     pub openai_model: Option<String>,
+    /// Look up provider API keys in the OS keychain when no other source has one.
+    pub keychain: Option<bool>,
     pub lang: Option<String>,
     pub force_llm: Option<bool>,
     pub print_js: Option<bool>,
     pub no_cache: Option<bool>,
     pub verbose: Option<bool>,
     pub progress: Option<ProgressSetting>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    /// Sampling seed forwarded to providers that accept one.
+    pub seed: Option<u64>,
+    /// Pin sampling and translate every cache miss twice, failing when the outputs differ.
+    pub deterministic: Option<bool>,
+    pub structured_output: Option<bool>,
+    /// Ask for a rationale and construct mapping with each translation, for `klumo explain`.
+    pub explain: Option<bool>,
+    /// Re-translate only the top-level units of an edited file that changed.
+    pub incremental: Option<bool>,
+    /// Ask the provider to name the language of a source that neither its extension nor its
+    /// content identifies.
+    pub classify_language: Option<bool>,
+    /// Transpile simple Python scripts locally instead of translating them.
+    pub python_fast_path: Option<bool>,
+    pub fixtures_dir: Option<PathBuf>,
+    pub ollama_auto_pull: Option<bool>,
+    pub ollama_keep_alive: Option<String>,
+    pub ollama_warm_up: Option<bool>,
+    pub openai_ca_bundle: Option<PathBuf>,
+    pub danger_accept_invalid_certs: Option<bool>,
+    pub max_prompt_bytes: Option<usize>,
+    pub max_response_bytes: Option<usize>,
+    pub context_window: Option<usize>,
+    pub syntax_repair_attempts: Option<usize>,
+    pub defaults: Option<BTreeMap<String, CommandDefaults>>,
+    pub providers: Option<Vec<CustomProviderConfig>>,
+    pub examples: Option<BTreeMap<String, Vec<TranslationExampleConfig>>>,
+    pub model_tiers: Option<Vec<ModelTierConfig>>,
+    pub language_providers: Option<BTreeMap<String, LanguageProviderConfig>>,
+    pub signing_key: Option<PathBuf>,
+    pub trusted_keys: Option<Vec<String>>,
+    pub verify_signatures: Option<bool>,
+    pub approval_required: Option<bool>,
+    pub response_cache: Option<bool>,
+    pub usage_metrics: Option<bool>,
+    pub clipboard: Option<ClipboardSetting>,
+    pub web_handler_timeout_ms: Option<u64>,
+    pub cache_url: Option<String>,
+    pub cache_token: Option<String>,
+    /// Compile cache directory, relative to the directory of `klumo.json`.
+    pub cache_dir: Option<PathBuf>,
+    pub ollama_model_preferences: Option<Vec<String>>,
+    pub circuit_breaker: Option<CircuitBreakerSettings>,
+    pub compile_cache: Option<CompileCacheSettings>,
+    pub similar_cache: Option<SimilarCacheSettings>,
+    pub heal_escalation: Option<Vec<HealStep>>,
+    pub jsx: Option<JsxSettings>,
+    pub output_limit: Option<OutputLimitSettings>,
+    /// Starter template the project was created from by `klumo init`.
+    pub template: Option<TemplateProvenance>,
+    pub lint: Option<LintConfig>,
+    pub fmt: Option<FmtConfig>,
+    pub test: Option<TestConfig>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Default)]
+/// Toolchain a project command runs; unset falls back to detecting Deno vs Cargo projects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolSetting {
+    Deno,
+    Cargo,
+}
+
+/// `lint` section of `klumo.json`, consumed by `klumo lint`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct LintConfig {
+    pub tool: Option<ToolSetting>,
+    /// Rules to enable (Deno rule names or Clippy lint names).
+    pub rules: Option<Vec<String>>,
+    /// Rules to disable.
+    pub exclude_rules: Option<Vec<String>>,
+    /// Paths or globs linted when no paths are passed on the command line.
+    pub include: Option<Vec<String>>,
+}
+
+/// `fmt` section of `klumo.json`, consumed by `klumo fmt`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct FmtConfig {
+    pub tool: Option<ToolSetting>,
+    pub line_width: Option<u32>,
+    /// Paths or globs formatted when no paths are passed on the command line.
+    pub include: Option<Vec<String>>,
+}
+
+/// `test` section of `klumo.json`, consumed by `klumo test`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct TestConfig {
+    pub tool: Option<ToolSetting>,
+    /// Arguments placed before any passed on the command line.
+    pub args: Option<Vec<String>>,
+    /// Test paths or globs used when no arguments are passed on the command line.
+    pub include: Option<Vec<String>>,
+}
+
+/// One rung of the self-heal escalation ladder declared under `heal_escalation`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HealStep {
+    pub provider: Option<ProviderSetting>,
+    pub model: Option<String>,
+    /// Heal attempts spent on this rung before moving to the next one (default 1).
+    pub attempts: Option<usize>,
+}
+
+/// Returns the ladder rung for zero-based heal `attempt`; attempts past the end stay on the
+/// last rung. `None` only when the ladder is empty.
+pub fn heal_step_for(ladder: &[HealStep], attempt: usize) -> Option<&HealStep> {
+    let mut remaining = attempt;
+    for step in ladder {
+        let budget = step.attempts.unwrap_or(1).max(1);
+        if remaining < budget {
+            return Some(step);
+        }
+        remaining -= budget;
+    }
+    ladder.last()
+}
+
+/// An additional OpenAI-compatible endpoint declared in the `providers` array of `klumo.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomProviderConfig {
+    pub name: String,
+    pub base_url: String,
+    /// Environment variable holding the API key; omit for endpoints without auth.
+    pub api_key_env: Option<String>,
+    /// May be omitted when `models` is given.
+    #[serde(default)]
+    pub model: String,
+    /// Prioritized models for endpoints hosting many (OpenRouter): the endpoint answers with
+    /// the first one available.
+    #[serde(default)]
+    pub models: Vec<String>,
+}
+
+impl CustomProviderConfig {
+    /// `model`, or the first of `models` when it is omitted.
+    pub fn primary_model(&self) -> &str {
+        if self.model.is_empty() {
+            self.models.first().map_or("", String::as_str)
+        } else {
+            &self.model
+        }
+    }
+
+    /// The models after the primary one, in priority order.
+    pub fn fallback_models(&self) -> Vec<String> {
+        let primary = self.primary_model();
+        self.models
+            .iter()
+            .filter(|model| model.as_str() != primary)
+            .cloned()
+            .collect()
+    }
+}
+
+/// A few-shot translation example declared under `examples.<language>` in `klumo.json`: a
+/// source file and the JavaScript it should become.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TranslationExampleConfig {
+    pub input: PathBuf,
+    pub output: PathBuf,
+}
+
+/// One entry of `model_tiers` in `klumo.json`: sources that fit it are translated with
+/// `model` instead of the configured model. The first matching tier wins.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelTierConfig {
+    pub model: String,
+    /// The provider the model is for; the first provider a source would be sent to when unset.
+    pub provider: Option<ProviderSetting>,
+    /// Only sources of at most this many bytes match.
+    pub max_source_bytes: Option<usize>,
+    /// Only sources with one of these language hints match.
+    pub languages: Option<Vec<String>>,
+}
+
+/// Provider and/or model for sources with one language hint, declared under
+/// `language_providers` in `klumo.json`, e.g. `"python": {"provider": "ollama", "model":
+/// "codellama"}`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LanguageProviderConfig {
+    pub provider: Option<ProviderSetting>,
+    pub model: Option<String>,
+}
+
+/// `circuit_breaker` section of `klumo.json`: after `failures` consecutive network failures
+/// within `window_secs`, a provider is skipped for `cooldown_secs`. `failures: 0` disables it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CircuitBreakerSettings {
+    pub failures: u32,
+    pub window_secs: u64,
+    pub cooldown_secs: u64,
+}
+
+impl Default for CircuitBreakerSettings {
+    fn default() -> Self {
+        Self {
+            failures: 3,
+            window_secs: 300,
+            cooldown_secs: 60,
+        }
+    }
+}
+
+/// `compile_cache` section of `klumo.json`: the compile cache is pruned to `max_size_mb`,
+/// least recently used entries first, and entries unused for `max_age_days` are dropped.
+/// Unset limits leave the cache unbounded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompileCacheSettings {
+    pub max_size_mb: Option<u64>,
+    pub max_age_days: Option<u64>,
+}
+
+/// `similar_cache` section of `klumo.json`, experimental: a source without a cached
+/// translation reuses the one of the most similar cached source, compared by the cosine
+/// similarity of their embeddings from the Ollama `model`, when it reaches `threshold`
+/// (`0.97` by default). Off unless `model` is set.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimilarCacheSettings {
+    pub model: Option<String>,
+    pub threshold: Option<f32>,
+}
+
+/// `jsx` section of `klumo.json`: the function JSX elements are lowered to and the component
+/// fragments use. Unset fields keep `React.createElement` and `React.Fragment`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JsxSettings {
+    pub pragma: Option<String>,
+    pub fragment: Option<String>,
+}
+
+/// `output_limit` section of `klumo.json`: how many characters a single console entry and all
+/// console output of one evaluation may print before they are truncated. `0` turns a limit
+/// off; unset fields keep the built-in limits, which only guard a terminal. Configured limits
+/// apply wherever output goes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputLimitSettings {
+    pub max_entry_chars: Option<usize>,
+    pub max_total_chars: Option<usize>,
+}
+
+/// `template` section of `klumo.json`: the registry name or git URL `klumo init` used and
+/// the revision it was taken from.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateProvenance {
+    pub source: String,
+    pub revision: Option<String>,
+}
+
+/// Flag defaults for a single command, declared under `defaults.<command>` in `klumo.json`.
+///
+/// Values fill in flags that were not passed on the command line, so explicit CLI flags
+/// still win. Any flag of the command beyond the typed fields lands in `flags`, keyed like
+/// the typed ones (`no_cache` for `--no-cache`), for the CLI to check against the command's
+/// arguments and fill in before parsing.
+#[derive(Debug, Clone, PartialEq, Deserialize, Default)]
+pub struct CommandDefaults {
+    pub provider: Option<ProviderSetting>,
+    pub ollama_url: Option<String>,
+    pub model: Option<String>,
+    pub lang: Option<String>,
+    pub force_llm: Option<bool>,
+    pub print_js: Option<bool>,
+    pub no_cache: Option<bool>,
+    pub verbose: Option<bool>,
+    pub no_progress: Option<bool>,
+    pub self_heal: Option<bool>,
+    pub max_heal_attempts: Option<usize>,
+    pub heal_provider: Option<ProviderSetting>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub no_auto_pull: Option<bool>,
+    pub deterministic: Option<bool>,
+    #[serde(flatten)]
+    pub flags: BTreeMap<String, serde_json::Value>,
+}
+
+impl FileConfig {
+    pub fn command_defaults(&self, command: &str) -> Option<&CommandDefaults> {
+        self.defaults.as_ref()?.get(command)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct EnvConfig {
     pub provider: Option<ProviderSetting>,
     pub ollama_url: Option<String>,
     pub ollama_model: Option<String>,
+    pub llamacpp_url: Option<String>,
+    pub llamacpp_model: Option<String>,
     pub openai_base_url: Option<String>,
     pub openai_api_key: Option<String>,
     pub openai_model: Option<String>,
+    /// Look up provider API keys in the OS keychain when no other source has one.
+    pub keychain: Option<bool>,
     pub lang: Option<String>,
     pub force_llm: Option<bool>,
     pub print_js: Option<bool>,
     pub no_cache: Option<bool>,
     pub verbose: Option<bool>,
     pub progress: Option<ProgressSetting>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub structured_output: Option<bool>,
+    pub explain: Option<bool>,
+    pub incremental: Option<bool>,
+    pub classify_language: Option<bool>,
+    pub python_fast_path: Option<bool>,
+    pub fixtures_dir: Option<PathBuf>,
+    pub record_fixtures: Option<bool>,
+    pub ollama_auto_pull: Option<bool>,
+    pub ollama_keep_alive: Option<String>,
+    pub ollama_warm_up: Option<bool>,
+    pub openai_ca_bundle: Option<PathBuf>,
+    pub danger_accept_invalid_certs: Option<bool>,
+    pub max_prompt_bytes: Option<usize>,
+    pub max_response_bytes: Option<usize>,
+    pub context_window: Option<usize>,
+    pub syntax_repair_attempts: Option<usize>,
+    pub llm_log: Option<PathBuf>,
+    pub signing_key: Option<PathBuf>,
+    pub verify_signatures: Option<bool>,
+    pub approval_required: Option<bool>,
+    pub response_cache: Option<bool>,
+    pub usage_metrics: Option<bool>,
+    pub clipboard: Option<ClipboardSetting>,
+    pub web_handler_timeout_ms: Option<u64>,
+    pub cache_url: Option<String>,
+    pub cache_token: Option<String>,
+    pub cache_dir: Option<PathBuf>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct CliRunOverrides {
     pub provider: Option<ProviderSetting>,
     pub ollama_url: Option<String>,
@@ -69,14 +434,59 @@ pub struct CliRunOverrides {
     pub no_cache: Option<bool>,
     pub verbose: Option<bool>,
     pub no_progress: Option<bool>,
+    pub self_heal: Option<bool>,
+    pub max_heal_attempts: Option<usize>,
+    pub heal_provider: Option<ProviderSetting>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub no_auto_pull: Option<bool>,
+    pub llm_log: Option<PathBuf>,
+    pub verify_signatures: Option<bool>,
+    pub deterministic: Option<bool>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+impl CliRunOverrides {
+    /// Fills every flag that was not set on the command line from `defaults`.
+    pub fn apply_command_defaults(&mut self, defaults: &CommandDefaults) {
+        self.provider = self.provider.take().or_else(|| defaults.provider.clone());
+        self.ollama_url = self
+            .ollama_url
+            .take()
+            .or_else(|| defaults.ollama_url.clone());
+        self.model = self.model.take().or_else(|| defaults.model.clone());
+        self.lang = self.lang.take().or_else(|| defaults.lang.clone());
+        self.force_llm = self.force_llm.or(defaults.force_llm);
+        self.print_js = self.print_js.or(defaults.print_js);
+        self.no_cache = self.no_cache.or(defaults.no_cache);
+        self.verbose = self.verbose.or(defaults.verbose);
+        self.no_progress = self.no_progress.or(defaults.no_progress);
+        self.self_heal = self.self_heal.or(defaults.self_heal);
+        self.max_heal_attempts = self.max_heal_attempts.or(defaults.max_heal_attempts);
+        self.heal_provider = self
+            .heal_provider
+            .take()
+            .or_else(|| defaults.heal_provider.clone());
+        self.temperature = self.temperature.or(defaults.temperature);
+        self.top_p = self.top_p.or(defaults.top_p);
+        self.max_tokens = self.max_tokens.or(defaults.max_tokens);
+        self.no_auto_pull = self.no_auto_pull.or(defaults.no_auto_pull);
+        self.deterministic = self.deterministic.or(defaults.deterministic);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct RunDefaults {
     pub provider: ProviderSetting,
     pub ollama_url: String,
     pub ollama_model: String,
+    /// llama.cpp server (`llama-server`) base URL; the provider is only routed when set.
+    pub llamacpp_url: Option<String>,
+    /// Name reported for llama.cpp translations; the server answers with whatever it loaded.
+    pub llamacpp_model: String,
     pub openai_base_url: String,
+    /// Resolved OpenAI key: env vars, then `klumo.json`, then the credentials file, then the
+    /// keychain.
     pub openai_api_key: Option<String>,
     pub openai_model: String,
     pub lang: Option<String>,
@@ -85,6 +495,96 @@ pub struct RunDefaults {
     pub no_cache: bool,
     pub verbose: bool,
     pub progress: ProgressSetting,
+    /// Sampling temperature sent to providers; `0.0` keeps translations deterministic.
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub seed: Option<u64>,
+    /// Translations are verified by translating twice; see `DETERMINISTIC_SEED`.
+    pub deterministic: bool,
+    /// Ask providers for JSON responses with notes and confidence alongside the code.
+    pub structured_output: bool,
+    /// Ask providers for an explanation of each translation, stored with its cache entry.
+    pub explain: bool,
+    /// Translate edited files unit by unit, reusing the JavaScript of unchanged units.
+    pub incremental: bool,
+    /// Fall back to a quick provider call for the language of unrecognized sources.
+    pub classify_language: bool,
+    /// Skip the provider for Python scripts within the subset `transpile_python` handles.
+    pub python_fast_path: bool,
+    /// Directory of recorded translations used by the `fixture` provider.
+    pub fixtures_dir: PathBuf,
+    /// Record missing fixtures through the `auto` chain instead of failing.
+    pub record_fixtures: bool,
+    /// Pull a configured Ollama model that the server does not have yet: `Some(true)` pulls,
+    /// `Some(false)` never does and unset asks first on a terminal.
+    pub ollama_auto_pull: Option<bool>,
+    /// Ollama `keep_alive` sent with every request, e.g. `"30m"` or `-1`; unset keeps the
+    /// server default.
+    pub ollama_keep_alive: Option<String>,
+    /// Load the Ollama model in the background when a run starts so the first translation
+    /// does not wait for it.
+    pub ollama_warm_up: bool,
+    /// Extra root certificates (PEM) trusted by provider HTTP clients.
+    pub openai_ca_bundle: Option<PathBuf>,
+    /// Disable TLS certificate verification for provider HTTP clients.
+    pub danger_accept_invalid_certs: bool,
+    /// Prompts larger than this are refused before they reach a provider.
+    pub max_prompt_bytes: usize,
+    /// Replies (including continuations) larger than this fail the translation.
+    pub max_response_bytes: usize,
+    /// Model context window in tokens; larger sources are translated in chunks. `0` disables
+    /// chunking.
+    pub context_window: usize,
+    /// Times a translation that does not parse is sent back to the provider for repair.
+    pub syntax_repair_attempts: usize,
+    /// Directory that receives every LLM prompt/response exchange (`--llm-log`).
+    pub llm_log: Option<PathBuf>,
+    pub providers: Vec<CustomProviderConfig>,
+    /// Keys for custom providers whose `api_key_env` variable is unset.
+    pub credentials: Credentials,
+    /// Few-shot examples added to prompts, keyed by language hint.
+    pub examples: BTreeMap<String, Vec<TranslationExampleConfig>>,
+    /// Size/language based model choices, ignored when a model is given explicitly.
+    pub model_tiers: Vec<ModelTierConfig>,
+    /// Provider/model routes keyed by lowercase language hint; they take precedence over the
+    /// session provider and `model_tiers`, but not over an explicit model.
+    pub language_providers: BTreeMap<String, LanguageProviderConfig>,
+    /// ed25519 key file (hex PKCS#8) used to sign fresh translations and bundles.
+    pub signing_key: Option<PathBuf>,
+    /// Hex ed25519 public keys accepted by `verify_signatures`.
+    pub trusted_keys: Vec<String>,
+    /// Only run JavaScript signed by one of `trusted_keys`.
+    pub verify_signatures: bool,
+    /// Hold fresh translations for `klumo review`; only approved ones run.
+    pub approval_required: bool,
+    /// Reuse provider answers for inputs that differ only in formatting and comments.
+    pub response_cache: bool,
+    /// Append provider calls and cache hits to `~/.klumo/metrics.jsonl` for `klumo stats --llm`.
+    /// Off unless enabled; the log never leaves the machine.
+    pub usage_metrics: bool,
+    /// Access to the system clipboard from `klumo.clipboard` in the REPL.
+    pub clipboard: ClipboardSetting,
+    /// How long a JavaScript route handler on the REPL web daemon may run before the request
+    /// fails with 504.
+    pub web_handler_timeout_ms: u64,
+    /// Base URL of a compile cache shared over HTTP GET/PUT; translations are still kept in
+    /// the local cache, which answers alone when the server cannot be reached.
+    pub cache_url: Option<String>,
+    /// Bearer token sent to `cache_url`: env, then `klumo.json`, then the `[cache]` entry of
+    /// the credentials file.
+    pub cache_token: Option<String>,
+    /// Directory of the local compile cache; `None` keeps `~/.klumo/cache/compile`.
+    pub cache_dir: Option<PathBuf>,
+    /// Coding models tried in order when `ollama_model` is not pulled on the Ollama server.
+    pub ollama_model_preferences: Vec<String>,
+    pub circuit_breaker: CircuitBreakerSettings,
+    pub compile_cache: CompileCacheSettings,
+    /// Reuse translations of similar sources; see [`SimilarCacheSettings`].
+    pub similar_cache: SimilarCacheSettings,
+    pub heal_escalation: Vec<HealStep>,
+    pub jsx: JsxSettings,
+    pub output_limit: OutputLimitSettings,
 }
 
 impl Default for RunDefaults {
@@ -93,6 +593,8 @@ impl Default for RunDefaults {
             provider: ProviderSetting::Auto,
             ollama_url: "http://127.0.0.1:11434".to_string(),
             ollama_model: "qwen2.5-coder:7b".to_string(),
+            llamacpp_url: None,
+            llamacpp_model: "default".to_string(),
             openai_base_url: "https://api.openai.com/v1".to_string(),
             openai_api_key: None,
             openai_model: "gpt-4.1-mini".to_string(),
@@ -102,10 +604,73 @@ impl Default for RunDefaults {
             no_cache: false,
             verbose: false,
             progress: ProgressSetting::Auto,
+            temperature: Some(0.0),
+            top_p: None,
+            max_tokens: None,
+            seed: None,
+            deterministic: false,
+            structured_output: false,
+            explain: false,
+            incremental: false,
+            classify_language: false,
+            python_fast_path: false,
+            fixtures_dir: PathBuf::from("klumo-fixtures"),
+            record_fixtures: false,
+            ollama_auto_pull: None,
+            ollama_keep_alive: None,
+            ollama_warm_up: false,
+            openai_ca_bundle: None,
+            danger_accept_invalid_certs: false,
+            max_prompt_bytes: 256 * 1024,
+            max_response_bytes: 1024 * 1024,
+            context_window: 8192,
+            syntax_repair_attempts: 2,
+            llm_log: None,
+            providers: Vec::new(),
+            credentials: Credentials::default(),
+            examples: BTreeMap::new(),
+            model_tiers: Vec::new(),
+            language_providers: BTreeMap::new(),
+            signing_key: None,
+            trusted_keys: Vec::new(),
+            verify_signatures: false,
+            approval_required: false,
+            response_cache: false,
+            usage_metrics: false,
+            clipboard: ClipboardSetting::Ask,
+            web_handler_timeout_ms: 5_000,
+            cache_url: None,
+            cache_dir: None,
+            cache_token: None,
+            ollama_model_preferences: [
+                "qwen2.5-coder",
+                "deepseek-coder-v2",
+                "deepseek-coder",
+                "codellama",
+                "codegemma",
+                "starcoder2",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+            circuit_breaker: CircuitBreakerSettings::default(),
+            compile_cache: CompileCacheSettings::default(),
+            similar_cache: SimilarCacheSettings::default(),
+            heal_escalation: Vec::new(),
+            jsx: JsxSettings::default(),
+            output_limit: OutputLimitSettings::default(),
         }
     }
 }
 
+/// Whether `key` is a top-level `klumo.json` setting. Every [`FileConfig`] field is optional, so
+/// a lone `null` under a known key parses and one under an unknown key does not.
+pub fn is_file_config_key(key: &str) -> bool {
+    let mut probe = serde_json::Map::new();
+    probe.insert(key.to_string(), serde_json::Value::Null);
+    serde_json::from_value::<FileConfig>(serde_json::Value::Object(probe)).is_ok()
+}
+
 pub fn load_file_config(explicit_path: Option<&Path>, cwd: &Path) -> Result<Option<FileConfig>> {
     let path = match explicit_path {
         Some(p) => p.to_path_buf(),
@@ -120,49 +685,203 @@ pub fn load_file_config(explicit_path: Option<&Path>, cwd: &Path) -> Result<Opti
 
     let raw = fs::read_to_string(&path)
         .with_context(|| format!("failed reading config file {}", path.display()))?;
-    let parsed: FileConfig = serde_json::from_str(&raw)
+    let mut parsed: FileConfig = serde_json::from_str(&raw)
         .with_context(|| format!("failed parsing config file {}", path.display()))?;
+    validate_custom_providers(parsed.providers.as_deref().unwrap_or_default())
+        .with_context(|| format!("invalid providers in config file {}", path.display()))?;
+    if let Some(project) = path.parent() {
+        parsed.resolve_paths(project);
+    }
     Ok(Some(parsed))
 }
 
+impl FileConfig {
+    /// Paths in `klumo.json` belong to the project, so they are relative to its directory,
+    /// not the cwd.
+    fn resolve_paths(&mut self, project: &Path) {
+        let paths = [
+            self.cache_dir.as_mut(),
+            self.fixtures_dir.as_mut(),
+            self.openai_ca_bundle.as_mut(),
+            self.signing_key.as_mut(),
+        ];
+        let examples = self
+            .examples
+            .iter_mut()
+            .flat_map(|by_lang| by_lang.values_mut())
+            .flatten()
+            .flat_map(|pair| [&mut pair.input, &mut pair.output]);
+        for path in paths.into_iter().flatten().chain(examples) {
+            *path = project.join(&*path);
+        }
+    }
+}
+
+/// Rejects custom providers whose name is empty, is taken by a built-in provider, or is
+/// declared twice, so `custom:<name>` always picks exactly one entry.
+fn validate_custom_providers(providers: &[CustomProviderConfig]) -> Result<()> {
+    let mut seen = BTreeSet::new();
+    for provider in providers {
+        let name = provider.name.as_str();
+        if name.trim().is_empty() {
+            return Err(anyhow!("custom provider names must not be empty"));
+        }
+        if parse_provider(name).is_some() {
+            return Err(anyhow!(
+                "custom provider name '{name}' is reserved for a built-in provider"
+            ));
+        }
+        if !seen.insert(name) {
+            return Err(anyhow!(
+                "custom provider '{name}' is declared more than once"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Suffixes of settings read from `KLUMO_*` variables that also accept a legacy `BEENO_*` alias,
+/// each with the unprefixed variable that takes precedence over both, if any.
+const BRANDED_ENV_KEYS: &[(&str, Option<&str>)] = &[
+    ("PROVIDER", None),
+    ("OLLAMA_URL", None),
+    ("OLLAMA_MODEL", None),
+    ("OPENAI_API_KEY", Some("OPENAI_API_KEY")),
+    ("MODEL", None),
+    ("LANG", None),
+    ("FORCE_LLM", None),
+    ("PRINT_JS", None),
+    ("NO_CACHE", None),
+    ("VERBOSE", None),
+    ("PROGRESS", None),
+];
+
+fn branded_var<F>(lookup: &F, key: &str) -> Option<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    lookup(&format!("KLUMO_{key}")).or_else(|| lookup(&format!("BEENO_{key}")))
+}
+
 impl EnvConfig {
     pub fn from_current_env() -> Self {
+        Self::from_lookup(|key| env::var(key).ok())
+    }
+
+    /// Resolves env settings through `lookup`, preferring `KLUMO_*` over legacy `BEENO_*` names.
+    pub fn from_lookup<F>(lookup: F) -> Self
+    where
+        F: Fn(&str) -> Option<String>,
+    {
         Self {
-            provider: env::var("KLUMO_PROVIDER")
-                .ok()
-                .and_then(|v| parse_provider(&v)),
-            ollama_url: env::var("KLUMO_OLLAMA_URL").ok(),
-            ollama_model: env::var("KLUMO_OLLAMA_MODEL").ok(),
-            openai_base_url: env::var("OPENAI_BASE_URL").ok(),
-            openai_api_key: env::var("OPENAI_API_KEY")
-                .ok()
-                .or_else(|| env::var("KLUMO_OPENAI_API_KEY").ok()),
-            openai_model: env::var("KLUMO_MODEL").ok(),
-            lang: env::var("KLUMO_LANG").ok(),
-            force_llm: env::var("KLUMO_FORCE_LLM")
-                .ok()
+            provider: branded_var(&lookup, "PROVIDER").and_then(|v| parse_provider(&v)),
+            ollama_url: branded_var(&lookup, "OLLAMA_URL"),
+            ollama_model: branded_var(&lookup, "OLLAMA_MODEL"),
+            llamacpp_url: lookup("KLUMO_LLAMACPP_URL"),
+            llamacpp_model: lookup("KLUMO_LLAMACPP_MODEL"),
+            openai_base_url: lookup("OPENAI_BASE_URL"),
+            openai_api_key: lookup("OPENAI_API_KEY")
+                .or_else(|| branded_var(&lookup, "OPENAI_API_KEY")),
+            openai_model: branded_var(&lookup, "MODEL"),
+            keychain: lookup("KLUMO_KEYCHAIN").and_then(|v| parse_bool(&v)),
+            lang: branded_var(&lookup, "LANG"),
+            force_llm: branded_var(&lookup, "FORCE_LLM").and_then(|v| parse_bool(&v)),
+            print_js: branded_var(&lookup, "PRINT_JS").and_then(|v| parse_bool(&v)),
+            no_cache: branded_var(&lookup, "NO_CACHE").and_then(|v| parse_bool(&v)),
+            verbose: branded_var(&lookup, "VERBOSE").and_then(|v| parse_bool(&v)),
+            progress: branded_var(&lookup, "PROGRESS").and_then(|v| parse_progress(&v)),
+            temperature: lookup("KLUMO_TEMPERATURE").and_then(|v| v.trim().parse().ok()),
+            top_p: lookup("KLUMO_TOP_P").and_then(|v| v.trim().parse().ok()),
+            max_tokens: lookup("KLUMO_MAX_TOKENS").and_then(|v| v.trim().parse().ok()),
+            structured_output: lookup("KLUMO_STRUCTURED_OUTPUT").and_then(|v| parse_bool(&v)),
+            explain: lookup("KLUMO_EXPLAIN").and_then(|v| parse_bool(&v)),
+            incremental: lookup("KLUMO_INCREMENTAL").and_then(|v| parse_bool(&v)),
+            classify_language: lookup("KLUMO_CLASSIFY_LANGUAGE").and_then(|v| parse_bool(&v)),
+            python_fast_path: lookup("KLUMO_PYTHON_FAST_PATH").and_then(|v| parse_bool(&v)),
+            fixtures_dir: lookup("KLUMO_FIXTURES_DIR").map(PathBuf::from),
+            record_fixtures: lookup("KLUMO_RECORD_FIXTURES").and_then(|v| parse_bool(&v)),
+            ollama_auto_pull: lookup("KLUMO_OLLAMA_AUTO_PULL").and_then(|v| parse_bool(&v)),
+            ollama_keep_alive: lookup("KLUMO_OLLAMA_KEEP_ALIVE")
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty()),
+            ollama_warm_up: lookup("KLUMO_OLLAMA_WARM_UP").and_then(|v| parse_bool(&v)),
+            openai_ca_bundle: lookup("KLUMO_OPENAI_CA_BUNDLE").map(PathBuf::from),
+            danger_accept_invalid_certs: lookup("KLUMO_DANGER_ACCEPT_INVALID_CERTS")
                 .and_then(|v| parse_bool(&v)),
-            print_js: env::var("KLUMO_PRINT_JS").ok().and_then(|v| parse_bool(&v)),
-            no_cache: env::var("KLUMO_NO_CACHE").ok().and_then(|v| parse_bool(&v)),
-            verbose: env::var("KLUMO_VERBOSE").ok().and_then(|v| parse_bool(&v)),
-            progress: env::var("KLUMO_PROGRESS")
-                .ok()
-                .and_then(|v| parse_progress(&v)),
+            max_prompt_bytes: lookup("KLUMO_MAX_PROMPT_BYTES").and_then(|v| v.trim().parse().ok()),
+            max_response_bytes: lookup("KLUMO_MAX_RESPONSE_BYTES")
+                .and_then(|v| v.trim().parse().ok()),
+            context_window: lookup("KLUMO_CONTEXT_WINDOW").and_then(|v| v.trim().parse().ok()),
+            syntax_repair_attempts: lookup("KLUMO_SYNTAX_REPAIR_ATTEMPTS")
+                .and_then(|v| v.trim().parse().ok()),
+            llm_log: lookup("KLUMO_LLM_LOG").map(PathBuf::from),
+            signing_key: lookup("KLUMO_SIGNING_KEY").map(PathBuf::from),
+            verify_signatures: lookup("KLUMO_VERIFY_SIGNATURES").and_then(|v| parse_bool(&v)),
+            approval_required: lookup("KLUMO_APPROVAL_REQUIRED").and_then(|v| parse_bool(&v)),
+            response_cache: lookup("KLUMO_RESPONSE_CACHE").and_then(|v| parse_bool(&v)),
+            usage_metrics: lookup("KLUMO_USAGE_METRICS").and_then(|v| parse_bool(&v)),
+            clipboard: lookup("KLUMO_CLIPBOARD").and_then(|v| parse_clipboard(&v)),
+            web_handler_timeout_ms: lookup("KLUMO_WEB_HANDLER_TIMEOUT_MS")
+                .and_then(|v| v.trim().parse().ok()),
+            cache_url: lookup("KLUMO_CACHE_URL")
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty()),
+            cache_token: lookup("KLUMO_CACHE_TOKEN"),
+            cache_dir: lookup("KLUMO_CACHE_DIR")
+                .filter(|v| !v.trim().is_empty())
+                .map(PathBuf::from),
         }
     }
 }
 
+/// Deprecation notices for legacy `BEENO_*` variables that are in effect: set, and not shadowed
+/// by their `KLUMO_*` name or a variable that takes precedence over both.
+pub fn legacy_env_warnings() -> Vec<String> {
+    legacy_env_warnings_from(|key| env::var(key).ok())
+}
+
+fn legacy_env_warnings_from<F>(lookup: F) -> Vec<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    BRANDED_ENV_KEYS
+        .iter()
+        .filter(|(key, unbranded)| {
+            unbranded.is_none_or(|name| lookup(name).is_none())
+                && lookup(&format!("KLUMO_{key}")).is_none()
+                && lookup(&format!("BEENO_{key}")).is_some()
+        })
+        .map(|(key, _)| format!("BEENO_{key} is deprecated; rename it to KLUMO_{key}"))
+        .collect()
+}
+
+/// Loads the user's credentials file, with keychain lookups enabled by `KLUMO_KEYCHAIN` or
+/// `keychain` in `klumo.json`.
+pub fn load_credentials(env_cfg: &EnvConfig, file_cfg: Option<&FileConfig>) -> Result<Credentials> {
+    let keychain = env_cfg
+        .keychain
+        .or(file_cfg.and_then(|c| c.keychain))
+        .unwrap_or(false);
+    let credentials = match Credentials::default_path() {
+        Some(path) => Credentials::load(&path)?,
+        None => Credentials::default(),
+    };
+    Ok(credentials.with_keychain(keychain))
+}
+
 pub fn resolve_run_defaults(
     cli: &CliRunOverrides,
     env_cfg: &EnvConfig,
     file_cfg: Option<&FileConfig>,
+    credentials: &Credentials,
 ) -> RunDefaults {
     let base = RunDefaults::default();
 
     let provider = cli
         .provider
-        .or(env_cfg.provider)
-        .or(file_cfg.and_then(|c| c.provider))
+        .clone()
+        .or_else(|| env_cfg.provider.clone())
+        .or_else(|| file_cfg.and_then(|c| c.provider.clone()))
         .unwrap_or(base.provider);
 
     let ollama_url = cli
@@ -179,6 +898,17 @@ pub fn resolve_run_defaults(
         .or_else(|| file_cfg.and_then(|c| c.ollama_model.clone()))
         .unwrap_or(base.ollama_model);
 
+    let llamacpp_url = env_cfg
+        .llamacpp_url
+        .clone()
+        .or_else(|| file_cfg.and_then(|c| c.llamacpp_url.clone()))
+        .or(base.llamacpp_url);
+    let llamacpp_model = env_cfg
+        .llamacpp_model
+        .clone()
+        .or_else(|| file_cfg.and_then(|c| c.llamacpp_model.clone()))
+        .unwrap_or(base.llamacpp_model);
+
     let openai_base_url = env_cfg
         .openai_base_url
         .clone()
@@ -189,7 +919,8 @@ pub fn resolve_run_defaults(
         .openai_api_key
         .clone()
         .or_else(|| file_cfg.and_then(|c| c.openai_api_key.clone()))
-        .or(base.openai_api_key);
+        .or(base.openai_api_key)
+        .or_else(|| credentials.api_key("openai"));
 
     let openai_model = cli
         .model
@@ -238,10 +969,214 @@ pub fn resolve_run_defaults(
         progress = ProgressSetting::Silent;
     }
 
+    let deterministic = cli
+        .deterministic
+        .or(file_cfg.and_then(|c| c.deterministic))
+        .unwrap_or(base.deterministic);
+    let seed = file_cfg.and_then(|c| c.seed).or(base.seed);
+    let (temperature, seed) = if deterministic {
+        (Some(0.0), seed.or(Some(DETERMINISTIC_SEED)))
+    } else {
+        let temperature = cli
+            .temperature
+            .or(env_cfg.temperature)
+            .or(file_cfg.and_then(|c| c.temperature))
+            .or(base.temperature);
+        (temperature, seed)
+    };
+
+    let top_p = cli
+        .top_p
+        .or(env_cfg.top_p)
+        .or(file_cfg.and_then(|c| c.top_p))
+        .or(base.top_p);
+
+    let max_tokens = cli
+        .max_tokens
+        .or(env_cfg.max_tokens)
+        .or(file_cfg.and_then(|c| c.max_tokens))
+        .or(base.max_tokens);
+
+    let structured_output = env_cfg
+        .structured_output
+        .or(file_cfg.and_then(|c| c.structured_output))
+        .unwrap_or(base.structured_output);
+    let explain = env_cfg
+        .explain
+        .or(file_cfg.and_then(|c| c.explain))
+        .unwrap_or(base.explain);
+    let incremental = env_cfg
+        .incremental
+        .or(file_cfg.and_then(|c| c.incremental))
+        .unwrap_or(base.incremental);
+    let classify_language = env_cfg
+        .classify_language
+        .or(file_cfg.and_then(|c| c.classify_language))
+        .unwrap_or(base.classify_language);
+    let python_fast_path = env_cfg
+        .python_fast_path
+        .or(file_cfg.and_then(|c| c.python_fast_path))
+        .unwrap_or(base.python_fast_path);
+
+    let fixtures_dir = env_cfg
+        .fixtures_dir
+        .clone()
+        .or_else(|| file_cfg.and_then(|c| c.fixtures_dir.clone()))
+        .unwrap_or(base.fixtures_dir);
+    let record_fixtures = env_cfg.record_fixtures.unwrap_or(base.record_fixtures);
+
+    let ollama_auto_pull = cli
+        .no_auto_pull
+        .map(|disabled| !disabled)
+        .or(env_cfg.ollama_auto_pull)
+        .or(file_cfg.and_then(|c| c.ollama_auto_pull))
+        .or(base.ollama_auto_pull);
+    let ollama_keep_alive = env_cfg
+        .ollama_keep_alive
+        .clone()
+        .or_else(|| file_cfg.and_then(|c| c.ollama_keep_alive.clone()))
+        .or(base.ollama_keep_alive);
+    let ollama_warm_up = env_cfg
+        .ollama_warm_up
+        .or(file_cfg.and_then(|c| c.ollama_warm_up))
+        .unwrap_or(base.ollama_warm_up);
+
+    let openai_ca_bundle = env_cfg
+        .openai_ca_bundle
+        .clone()
+        .or_else(|| file_cfg.and_then(|c| c.openai_ca_bundle.clone()))
+        .or(base.openai_ca_bundle);
+    let danger_accept_invalid_certs = env_cfg
+        .danger_accept_invalid_certs
+        .or(file_cfg.and_then(|c| c.danger_accept_invalid_certs))
+        .unwrap_or(base.danger_accept_invalid_certs);
+    let max_prompt_bytes = env_cfg
+        .max_prompt_bytes
+        .or(file_cfg.and_then(|c| c.max_prompt_bytes))
+        .unwrap_or(base.max_prompt_bytes);
+    let max_response_bytes = env_cfg
+        .max_response_bytes
+        .or(file_cfg.and_then(|c| c.max_response_bytes))
+        .unwrap_or(base.max_response_bytes);
+    let context_window = env_cfg
+        .context_window
+        .or(file_cfg.and_then(|c| c.context_window))
+        .unwrap_or(base.context_window);
+    let syntax_repair_attempts = env_cfg
+        .syntax_repair_attempts
+        .or(file_cfg.and_then(|c| c.syntax_repair_attempts))
+        .unwrap_or(base.syntax_repair_attempts);
+    let llm_log = cli
+        .llm_log
+        .clone()
+        .or_else(|| env_cfg.llm_log.clone())
+        .or(base.llm_log);
+
+    let providers = file_cfg
+        .and_then(|c| c.providers.clone())
+        .unwrap_or(base.providers);
+
+    let examples = file_cfg
+        .and_then(|c| c.examples.clone())
+        .unwrap_or(base.examples);
+
+    let model_tiers = file_cfg
+        .and_then(|c| c.model_tiers.clone())
+        .unwrap_or(base.model_tiers);
+
+    // Language hints are matched case-insensitively, so `Python` and `python` are one entry.
+    let language_providers = file_cfg
+        .and_then(|c| c.language_providers.clone())
+        .map(|routes| {
+            routes
+                .into_iter()
+                .map(|(language, route)| (language.to_ascii_lowercase(), route))
+                .collect()
+        })
+        .unwrap_or(base.language_providers);
+
+    let signing_key = env_cfg
+        .signing_key
+        .clone()
+        .or_else(|| file_cfg.and_then(|c| c.signing_key.clone()))
+        .or(base.signing_key);
+    let trusted_keys = file_cfg
+        .and_then(|c| c.trusted_keys.clone())
+        .unwrap_or(base.trusted_keys);
+    let verify_signatures = cli
+        .verify_signatures
+        .or(env_cfg.verify_signatures)
+        .or(file_cfg.and_then(|c| c.verify_signatures))
+        .unwrap_or(base.verify_signatures);
+    let approval_required = env_cfg
+        .approval_required
+        .or(file_cfg.and_then(|c| c.approval_required))
+        .unwrap_or(base.approval_required);
+    let response_cache = env_cfg
+        .response_cache
+        .or(file_cfg.and_then(|c| c.response_cache))
+        .unwrap_or(base.response_cache);
+    let usage_metrics = env_cfg
+        .usage_metrics
+        .or(file_cfg.and_then(|c| c.usage_metrics))
+        .unwrap_or(base.usage_metrics);
+    let clipboard = env_cfg
+        .clipboard
+        .or(file_cfg.and_then(|c| c.clipboard))
+        .unwrap_or(base.clipboard);
+    let web_handler_timeout_ms = env_cfg
+        .web_handler_timeout_ms
+        .or(file_cfg.and_then(|c| c.web_handler_timeout_ms))
+        .unwrap_or(base.web_handler_timeout_ms);
+    let cache_url = env_cfg
+        .cache_url
+        .clone()
+        .or_else(|| file_cfg.and_then(|c| c.cache_url.clone()))
+        .or(base.cache_url);
+    let cache_token = env_cfg
+        .cache_token
+        .clone()
+        .or_else(|| file_cfg.and_then(|c| c.cache_token.clone()))
+        .or(base.cache_token)
+        .or_else(|| {
+            cache_url
+                .as_ref()
+                .and_then(|_| credentials.api_key("cache"))
+        });
+    let cache_dir = env_cfg
+        .cache_dir
+        .clone()
+        .or_else(|| file_cfg.and_then(|c| c.cache_dir.clone()))
+        .or(base.cache_dir);
+
+    let ollama_model_preferences = file_cfg
+        .and_then(|c| c.ollama_model_preferences.clone())
+        .unwrap_or(base.ollama_model_preferences);
+
+    let circuit_breaker = file_cfg
+        .and_then(|c| c.circuit_breaker)
+        .unwrap_or(base.circuit_breaker);
+    let compile_cache = file_cfg
+        .and_then(|c| c.compile_cache)
+        .unwrap_or(base.compile_cache);
+    let similar_cache = file_cfg
+        .and_then(|c| c.similar_cache.clone())
+        .unwrap_or(base.similar_cache);
+
+    let heal_escalation = file_cfg
+        .and_then(|c| c.heal_escalation.clone())
+        .unwrap_or(base.heal_escalation);
+    let jsx = file_cfg.and_then(|c| c.jsx.clone()).unwrap_or(base.jsx);
+    let output_limit = file_cfg
+        .and_then(|c| c.output_limit)
+        .unwrap_or(base.output_limit);
+
     RunDefaults {
         provider,
         ollama_url,
         ollama_model,
+        llamacpp_url,
+        llamacpp_model,
         openai_base_url,
         openai_api_key,
         openai_model,
@@ -251,6 +1186,51 @@ pub fn resolve_run_defaults(
         no_cache,
         verbose,
         progress,
+        temperature,
+        top_p,
+        max_tokens,
+        seed,
+        deterministic,
+        structured_output,
+        explain,
+        incremental,
+        classify_language,
+        python_fast_path,
+        fixtures_dir,
+        record_fixtures,
+        ollama_auto_pull,
+        ollama_keep_alive,
+        ollama_warm_up,
+        openai_ca_bundle,
+        danger_accept_invalid_certs,
+        max_prompt_bytes,
+        max_response_bytes,
+        context_window,
+        syntax_repair_attempts,
+        llm_log,
+        providers,
+        credentials: credentials.clone(),
+        examples,
+        model_tiers,
+        language_providers,
+        signing_key,
+        trusted_keys,
+        verify_signatures,
+        approval_required,
+        response_cache,
+        usage_metrics,
+        clipboard,
+        web_handler_timeout_ms,
+        cache_url,
+        cache_token,
+        cache_dir,
+        ollama_model_preferences,
+        circuit_breaker,
+        compile_cache,
+        similar_cache,
+        heal_escalation,
+        jsx,
+        output_limit,
     }
 }
 
@@ -262,11 +1242,28 @@ fn parse_bool(input: &str) -> Option<bool> {
     }
 }
 
-fn parse_provider(input: &str) -> Option<ProviderSetting> {
-    match input.trim().to_ascii_lowercase().as_str() {
+/// Parses a provider name as written in `klumo.json`, `KLUMO_PROVIDER` or `--provider`.
+/// Built-in names are case-insensitive; `custom:<name>` keeps the declared name as is.
+pub fn parse_provider(input: &str) -> Option<ProviderSetting> {
+    let input = input.trim();
+    match input.to_ascii_lowercase().as_str() {
         "auto" => Some(ProviderSetting::Auto),
         "ollama" => Some(ProviderSetting::Ollama),
         "openai" | "openai-compatible" => Some(ProviderSetting::Openai),
+        "llamacpp" | "llama.cpp" => Some(ProviderSetting::Llamacpp),
+        "fixture" => Some(ProviderSetting::Fixture),
+        _ => input
+            .strip_prefix("custom:")
+            .filter(|name| !name.is_empty())
+            .map(|name| ProviderSetting::Custom(name.to_string())),
+    }
+}
+
+fn parse_clipboard(input: &str) -> Option<ClipboardSetting> {
+    match input.trim().to_ascii_lowercase().as_str() {
+        "ask" => Some(ClipboardSetting::Ask),
+        "allow" => Some(ClipboardSetting::Allow),
+        "deny" => Some(ClipboardSetting::Deny),
         _ => None,
     }
 }
@@ -283,10 +1280,15 @@ fn parse_progress(input: &str) -> Option<ProgressSetting> {
 #[cfg(test)]
 mod tests {
     use super::{
-        CliRunOverrides, EnvConfig, FileConfig, ProgressSetting, ProviderSetting, load_file_config,
-        resolve_run_defaults,
+        CircuitBreakerSettings, CliRunOverrides, ClipboardSetting, CommandDefaults,
+        CompileCacheSettings, Credentials, DETERMINISTIC_SEED, EnvConfig, FileConfig, HealStep,
+        JsxSettings, LanguageProviderConfig, OutputLimitSettings, ProgressSetting, ProviderSetting,
+        RunDefaults, TemplateProvenance, ToolSetting, TranslationExampleConfig, heal_step_for,
+        legacy_env_warnings_from, load_file_config, parse_provider, resolve_run_defaults,
     };
+    use std::collections::HashMap;
     use std::fs;
+    use std::path::{Path, PathBuf};
     use tempfile::tempdir;
 
     #[test]
@@ -331,6 +1333,332 @@ mod tests {
         assert_eq!(parsed.force_llm, Some(true));
     }
 
+    #[test]
+    fn custom_providers_parse_and_resolve() {
+        let dir = tempdir().expect("tempdir should work");
+        let path = dir.path().join("klumo.json");
+        fs::write(
+            &path,
+            r#"{
+  "providers": [
+    {"name":"corp","base_url":"https://llm.corp/v1","api_key_env":"CORP_KEY","model":"coder"},
+    {"name":"local","base_url":"http://127.0.0.1:8000/v1","model":"qwen"},
+    {"name":"router","base_url":"https://openrouter.ai/api/v1","models":["openai/gpt-4.1","anthropic/claude-sonnet"]}
+  ]
+}"#,
+        )
+        .expect("write should work");
+
+        let parsed = load_file_config(None, dir.path())
+            .expect("parse should work")
+            .expect("file should exist");
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            Some(&parsed),
+            &Credentials::default(),
+        );
+        assert_eq!(resolved.providers.len(), 3);
+        assert_eq!(resolved.providers[0].name, "corp");
+        assert_eq!(
+            resolved.providers[0].api_key_env.as_deref(),
+            Some("CORP_KEY")
+        );
+        assert_eq!(resolved.providers[1].api_key_env, None);
+        assert_eq!(
+            resolved.providers[1].fallback_models(),
+            Vec::<String>::new()
+        );
+        assert_eq!(resolved.providers[2].primary_model(), "openai/gpt-4.1");
+        assert_eq!(
+            resolved.providers[2].fallback_models(),
+            vec!["anthropic/claude-sonnet"]
+        );
+    }
+
+    #[test]
+    fn custom_provider_is_selected_by_prefixed_name() {
+        let dir = tempdir().expect("tempdir should work");
+        let path = dir.path().join("klumo.json");
+        fs::write(
+            &path,
+            r#"{
+  "provider": "custom:Corp",
+  "providers": [{"name":"Corp","base_url":"https://llm.corp/v1","model":"coder"}]
+}"#,
+        )
+        .expect("write should work");
+
+        let parsed = load_file_config(None, dir.path())
+            .expect("parse should work")
+            .expect("file should exist");
+        assert_eq!(
+            parsed.provider,
+            Some(ProviderSetting::Custom("Corp".to_string()))
+        );
+        assert_eq!(parse_provider("custom:"), None);
+    }
+
+    #[test]
+    fn reserved_and_duplicate_custom_provider_names_are_rejected() {
+        let dir = tempdir().expect("tempdir should work");
+        let path = dir.path().join("klumo.json");
+        let provider = |name: &str| {
+            format!(r#"{{"name":"{name}","base_url":"http://127.0.0.1:8000/v1","model":"m"}}"#)
+        };
+
+        for (providers, expected) in [
+            (
+                provider("ollama"),
+                "custom provider name 'ollama' is reserved for a built-in provider",
+            ),
+            (
+                provider("OpenAI-Compatible"),
+                "custom provider name 'OpenAI-Compatible' is reserved for a built-in provider",
+            ),
+            (
+                format!("{},{}", provider("corp"), provider("corp")),
+                "custom provider 'corp' is declared more than once",
+            ),
+        ] {
+            fs::write(&path, format!(r#"{{"providers":[{providers}]}}"#))
+                .expect("write should work");
+            let err = load_file_config(None, dir.path()).expect_err("config should be rejected");
+            assert!(
+                format!("{err:#}").contains(expected),
+                "unexpected error: {err:#}"
+            );
+        }
+    }
+
+    #[test]
+    fn translation_examples_parse_and_resolve() {
+        let dir = tempdir().expect("tempdir should work");
+        fs::write(
+            dir.path().join("klumo.json"),
+            r#"{"examples":{"ledger":[{"input":"examples/debit.ledger","output":"examples/debit.js"}]}}"#,
+        )
+        .expect("write should work");
+
+        let parsed = load_file_config(None, dir.path())
+            .expect("parse should work")
+            .expect("file should exist");
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            Some(&parsed),
+            &Credentials::default(),
+        );
+        assert_eq!(
+            resolved.examples["ledger"],
+            vec![TranslationExampleConfig {
+                input: dir.path().join("examples/debit.ledger"),
+                output: dir.path().join("examples/debit.js"),
+            }]
+        );
+    }
+
+    #[test]
+    fn model_tiers_parse_in_order() {
+        let dir = tempdir().expect("tempdir should work");
+        fs::write(
+            dir.path().join("klumo.json"),
+            r#"{"model_tiers":[{"max_source_bytes":2048,"model":"qwen2.5-coder:1.5b"},{"languages":["cobol"],"model":"qwen2.5-coder:32b"}]}"#,
+        )
+        .expect("write should work");
+
+        let parsed = load_file_config(None, dir.path())
+            .expect("parse should work")
+            .expect("file should exist");
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            Some(&parsed),
+            &Credentials::default(),
+        );
+        assert_eq!(resolved.model_tiers.len(), 2);
+        assert_eq!(resolved.model_tiers[0].max_source_bytes, Some(2048));
+        assert_eq!(
+            resolved.model_tiers[1].languages.as_deref(),
+            Some(&["cobol".to_string()][..])
+        );
+    }
+
+    #[test]
+    fn language_providers_are_keyed_by_lowercase_hint() {
+        let dir = tempdir().expect("tempdir should work");
+        fs::write(
+            dir.path().join("klumo.json"),
+            r#"{"language_providers":{"Python":{"provider":"ollama","model":"codellama"},"cobol":{"model":"qwen2.5-coder:32b"},"go":{"provider":"custom:corp"}}}"#,
+        )
+        .expect("write should work");
+
+        let parsed = load_file_config(None, dir.path())
+            .expect("parse should work")
+            .expect("file should exist");
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            Some(&parsed),
+            &Credentials::default(),
+        );
+        assert_eq!(
+            resolved.language_providers.get("python"),
+            Some(&LanguageProviderConfig {
+                provider: Some(ProviderSetting::Ollama),
+                model: Some("codellama".to_string()),
+            })
+        );
+        assert_eq!(
+            resolved.language_providers["cobol"].provider, None,
+            "the session provider stays in charge"
+        );
+        assert_eq!(
+            resolved.language_providers["go"].provider,
+            Some(ProviderSetting::Custom("corp".to_string()))
+        );
+
+        let err = serde_json::from_str::<FileConfig>(
+            r#"{"language_providers":{"python":{"provider":"ollama","temperature":0}}}"#,
+        )
+        .expect_err("unknown route field");
+        assert!(err.to_string().contains("unknown field `temperature`"));
+    }
+
+    #[test]
+    fn compile_cache_limits_default_to_unbounded() {
+        let base = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            None,
+            &Credentials::default(),
+        );
+        assert_eq!(base.compile_cache, CompileCacheSettings::default());
+
+        let file: FileConfig = serde_json::from_str(r#"{"compile_cache":{"max_size_mb":256}}"#)
+            .expect("compile_cache should parse");
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            Some(&file),
+            &Credentials::default(),
+        );
+        assert_eq!(resolved.compile_cache.max_size_mb, Some(256));
+        assert_eq!(resolved.compile_cache.max_age_days, None);
+        assert!(serde_json::from_str::<FileConfig>(r#"{"compile_cache":{"max_size":1}}"#).is_err());
+    }
+
+    #[test]
+    fn jsx_section_sets_pragma_and_fragment() {
+        let file: FileConfig =
+            serde_json::from_str(r#"{"jsx":{"pragma":"h"}}"#).expect("jsx section should parse");
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            Some(&file),
+            &Credentials::default(),
+        );
+        assert_eq!(
+            resolved.jsx,
+            JsxSettings {
+                pragma: Some("h".to_string()),
+                fragment: None,
+            }
+        );
+        assert!(serde_json::from_str::<FileConfig>(r#"{"jsx":{"factory":"h"}}"#).is_err());
+    }
+
+    #[test]
+    fn output_limit_section_keeps_unset_limits_open() {
+        let file: FileConfig = serde_json::from_str(r#"{"output_limit":{"max_entry_chars":80}}"#)
+            .expect("output_limit section should parse");
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            Some(&file),
+            &Credentials::default(),
+        );
+        assert_eq!(
+            resolved.output_limit,
+            OutputLimitSettings {
+                max_entry_chars: Some(80),
+                max_total_chars: None,
+            }
+        );
+        assert!(serde_json::from_str::<FileConfig>(r#"{"output_limit":{"max_lines":3}}"#).is_err());
+    }
+
+    #[test]
+    fn template_provenance_parses_from_klumo_json() {
+        let file: FileConfig = serde_json::from_str(
+            r#"{"template":{"source":"https://example.com/kit.git","revision":"abc123"}}"#,
+        )
+        .expect("template section should parse");
+        assert_eq!(
+            file.template,
+            Some(TemplateProvenance {
+                source: "https://example.com/kit.git".to_string(),
+                revision: Some("abc123".to_string()),
+            })
+        );
+        assert!(serde_json::from_str::<FileConfig>(r#"{"template":{"url":"x"}}"#).is_err());
+    }
+
+    #[test]
+    fn circuit_breaker_fills_unset_fields_with_defaults() {
+        let dir = tempdir().expect("tempdir should work");
+        fs::write(
+            dir.path().join("klumo.json"),
+            r#"{"circuit_breaker":{"cooldown_secs":120}}"#,
+        )
+        .expect("write should work");
+
+        let parsed = load_file_config(None, dir.path())
+            .expect("parse should work")
+            .expect("file should exist");
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            Some(&parsed),
+            &Credentials::default(),
+        );
+        assert_eq!(
+            resolved.circuit_breaker,
+            CircuitBreakerSettings {
+                failures: 3,
+                window_secs: 300,
+                cooldown_secs: 120,
+            }
+        );
+    }
+
+    #[test]
+    fn similar_cache_is_off_until_a_model_is_set() {
+        let base = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            None,
+            &Credentials::default(),
+        );
+        assert_eq!(base.similar_cache.model, None);
+
+        let file: FileConfig =
+            serde_json::from_str(r#"{"similar_cache":{"model":"nomic-embed-text"}}"#)
+                .expect("similar_cache should parse");
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            Some(&file),
+            &Credentials::default(),
+        );
+        assert_eq!(
+            resolved.similar_cache.model.as_deref(),
+            Some("nomic-embed-text")
+        );
+        assert_eq!(resolved.similar_cache.threshold, None);
+        assert!(serde_json::from_str::<FileConfig>(r#"{"similar_cache":{"min":0.9}}"#).is_err());
+    }
+
     #[test]
     fn unknown_field_is_rejected() {
         let dir = tempdir().expect("tempdir should work");
@@ -378,10 +1706,593 @@ mod tests {
             ..CliRunOverrides::default()
         };
 
-        let resolved = resolve_run_defaults(&cli, &env_cfg, Some(&file));
+        let resolved = resolve_run_defaults(&cli, &env_cfg, Some(&file), &Credentials::default());
         assert_eq!(resolved.provider, ProviderSetting::Auto);
         assert!(resolved.force_llm);
         assert_eq!(resolved.progress, ProgressSetting::Silent);
         assert_eq!(resolved.openai_api_key.as_deref(), Some("env-key"));
     }
+
+    #[test]
+    fn sampling_controls_resolve_with_precedence() {
+        let base = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            None,
+            &Credentials::default(),
+        );
+        assert_eq!(base.temperature, Some(0.0));
+        assert_eq!(base.max_tokens, None);
+
+        let file = FileConfig {
+            temperature: Some(0.7),
+            top_p: Some(0.9),
+            max_tokens: Some(512),
+            ..FileConfig::default()
+        };
+        let env_cfg = EnvConfig {
+            top_p: Some(0.5),
+            ..EnvConfig::default()
+        };
+        let cli = CliRunOverrides {
+            temperature: Some(0.2),
+            ..CliRunOverrides::default()
+        };
+
+        let resolved = resolve_run_defaults(&cli, &env_cfg, Some(&file), &Credentials::default());
+        assert_eq!(resolved.temperature, Some(0.2));
+        assert_eq!(resolved.top_p, Some(0.5));
+        assert_eq!(resolved.max_tokens, Some(512));
+        assert_eq!(resolved.seed, None);
+    }
+
+    #[test]
+    fn deterministic_mode_pins_temperature_and_seed() {
+        let file = FileConfig {
+            temperature: Some(0.7),
+            ..FileConfig::default()
+        };
+        let cli = CliRunOverrides {
+            temperature: Some(0.9),
+            deterministic: Some(true),
+            ..CliRunOverrides::default()
+        };
+        let resolved = resolve_run_defaults(
+            &cli,
+            &EnvConfig::default(),
+            Some(&file),
+            &Credentials::default(),
+        );
+        assert!(resolved.deterministic);
+        assert_eq!(resolved.temperature, Some(0.0));
+        assert_eq!(resolved.seed, Some(DETERMINISTIC_SEED));
+
+        let file: FileConfig = serde_json::from_str(r#"{"deterministic":true,"seed":7}"#)
+            .expect("deterministic settings should parse");
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            Some(&file),
+            &Credentials::default(),
+        );
+        assert_eq!(resolved.seed, Some(7));
+    }
+
+    #[test]
+    fn ollama_auto_pull_is_unset_by_default_and_cli_flag_disables_it() {
+        let base = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            None,
+            &Credentials::default(),
+        );
+        assert_eq!(base.ollama_auto_pull, None);
+
+        let file = FileConfig {
+            ollama_auto_pull: Some(false),
+            ..FileConfig::default()
+        };
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            Some(&file),
+            &Credentials::default(),
+        );
+        assert_eq!(resolved.ollama_auto_pull, Some(false));
+
+        let env_cfg = EnvConfig::from_lookup(|key| {
+            (key == "KLUMO_OLLAMA_AUTO_PULL").then(|| "true".to_string())
+        });
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &env_cfg,
+            Some(&file),
+            &Credentials::default(),
+        );
+        assert_eq!(resolved.ollama_auto_pull, Some(true));
+
+        let cli = CliRunOverrides {
+            no_auto_pull: Some(true),
+            ..CliRunOverrides::default()
+        };
+        let resolved = resolve_run_defaults(&cli, &env_cfg, Some(&file), &Credentials::default());
+        assert_eq!(resolved.ollama_auto_pull, Some(false));
+    }
+
+    #[test]
+    fn ollama_keep_alive_and_warm_up_read_from_file_and_env() {
+        let base = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            None,
+            &Credentials::default(),
+        );
+        assert_eq!(base.ollama_keep_alive, None);
+        assert!(!base.ollama_warm_up);
+
+        let file: FileConfig =
+            serde_json::from_str(r#"{"ollama_keep_alive": "30m", "ollama_warm_up": true}"#)
+                .expect("ollama keys should parse");
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            Some(&file),
+            &Credentials::default(),
+        );
+        assert_eq!(resolved.ollama_keep_alive.as_deref(), Some("30m"));
+        assert!(resolved.ollama_warm_up);
+
+        let env_cfg = EnvConfig::from_lookup(|key| match key {
+            "KLUMO_OLLAMA_KEEP_ALIVE" => Some(" -1 ".to_string()),
+            "KLUMO_OLLAMA_WARM_UP" => Some("0".to_string()),
+            _ => None,
+        });
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &env_cfg,
+            Some(&file),
+            &Credentials::default(),
+        );
+        assert_eq!(resolved.ollama_keep_alive.as_deref(), Some("-1"));
+        assert!(!resolved.ollama_warm_up);
+    }
+
+    #[test]
+    fn clipboard_access_defaults_to_ask() {
+        let base = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            None,
+            &Credentials::default(),
+        );
+        assert_eq!(base.clipboard, ClipboardSetting::Ask);
+
+        let file: FileConfig =
+            serde_json::from_str(r#"{"clipboard": "allow"}"#).expect("clipboard should parse");
+        let env_cfg =
+            EnvConfig::from_lookup(|key| (key == "KLUMO_CLIPBOARD").then(|| "Deny".to_string()));
+        let from_file = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            Some(&file),
+            &Credentials::default(),
+        );
+        let from_env = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &env_cfg,
+            Some(&file),
+            &Credentials::default(),
+        );
+        assert_eq!(from_file.clipboard, ClipboardSetting::Allow);
+        assert_eq!(from_env.clipboard, ClipboardSetting::Deny);
+    }
+
+    #[test]
+    fn web_handler_timeout_reads_from_file_and_env() {
+        let file: FileConfig = serde_json::from_str(r#"{"web_handler_timeout_ms": 250}"#)
+            .expect("timeout should parse");
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            Some(&file),
+            &Credentials::default(),
+        );
+        assert_eq!(resolved.web_handler_timeout_ms, 250);
+
+        let env_cfg = EnvConfig::from_lookup(|key| {
+            (key == "KLUMO_WEB_HANDLER_TIMEOUT_MS").then(|| "1500".to_string())
+        });
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &env_cfg,
+            None,
+            &Credentials::default(),
+        );
+        assert_eq!(resolved.web_handler_timeout_ms, 1500);
+        assert_eq!(RunDefaults::default().web_handler_timeout_ms, 5_000);
+    }
+
+    #[test]
+    fn shared_cache_url_and_token_resolve_from_env_file_and_credentials() {
+        let file: FileConfig =
+            serde_json::from_str(r#"{"cache_url": "https://cache.example/klumo"}"#)
+                .expect("cache_url should parse");
+        let credentials =
+            Credentials::parse("[cache]\napi_key = \"stored\"\n").expect("credentials parse");
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            Some(&file),
+            &credentials,
+        );
+        assert_eq!(
+            resolved.cache_url.as_deref(),
+            Some("https://cache.example/klumo")
+        );
+        assert_eq!(resolved.cache_token.as_deref(), Some("stored"));
+
+        let env_cfg = EnvConfig::from_lookup(|key| match key {
+            "KLUMO_CACHE_URL" => Some("http://localhost:9000".to_string()),
+            "KLUMO_CACHE_TOKEN" => Some("from-env".to_string()),
+            _ => None,
+        });
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &env_cfg,
+            Some(&file),
+            &credentials,
+        );
+        assert_eq!(resolved.cache_url.as_deref(), Some("http://localhost:9000"));
+        assert_eq!(resolved.cache_token.as_deref(), Some("from-env"));
+        assert_eq!(RunDefaults::default().cache_url, None);
+    }
+
+    #[test]
+    fn paths_are_relative_to_the_config_file_and_env_overrides_them() {
+        let dir = tempfile::tempdir().expect("tempdir should work");
+        let project = dir.path().join("app");
+        std::fs::create_dir(&project).expect("create project dir");
+        std::fs::write(
+            project.join("klumo.json"),
+            r#"{
+                "cache_dir": ".klumo-cache",
+                "fixtures_dir": "fixtures",
+                "openai_ca_bundle": "certs/corp.pem",
+                "signing_key": "/keys/klumo.key",
+                "examples": {"ledger": [{"input": "ex/a.ledger", "output": "ex/a.js"}]}
+            }"#,
+        )
+        .expect("write config");
+        let parsed = load_file_config(Some(&project.join("klumo.json")), dir.path())
+            .expect("config should load")
+            .expect("config should exist");
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            Some(&parsed),
+            &Credentials::default(),
+        );
+        assert_eq!(resolved.cache_dir, Some(project.join(".klumo-cache")));
+        assert_eq!(resolved.fixtures_dir, project.join("fixtures"));
+        assert_eq!(
+            resolved.openai_ca_bundle,
+            Some(project.join("certs/corp.pem"))
+        );
+        assert_eq!(resolved.signing_key, Some(PathBuf::from("/keys/klumo.key")));
+        let example = &resolved.examples["ledger"][0];
+        assert_eq!(example.input, project.join("ex/a.ledger"));
+        assert_eq!(example.output, project.join("ex/a.js"));
+
+        let env_cfg = EnvConfig::from_lookup(|key| {
+            (key == "KLUMO_CACHE_DIR").then(|| "/ci/klumo-cache".to_string())
+        });
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &env_cfg,
+            Some(&parsed),
+            &Credentials::default(),
+        );
+        assert_eq!(resolved.cache_dir, Some(PathBuf::from("/ci/klumo-cache")));
+        assert_eq!(RunDefaults::default().cache_dir, None);
+    }
+
+    #[test]
+    fn tls_options_read_from_file_and_env() {
+        let file: FileConfig = serde_json::from_str(
+            r#"{"openai_ca_bundle": "certs/corp.pem", "danger_accept_invalid_certs": true}"#,
+        )
+        .expect("tls keys should parse");
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            Some(&file),
+            &Credentials::default(),
+        );
+        assert_eq!(
+            resolved.openai_ca_bundle.as_deref(),
+            Some(Path::new("certs/corp.pem"))
+        );
+        assert!(resolved.danger_accept_invalid_certs);
+
+        let env_cfg = EnvConfig::from_lookup(|key| {
+            (key == "KLUMO_OPENAI_CA_BUNDLE").then(|| "/etc/ssl/proxy.pem".to_string())
+        });
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &env_cfg,
+            Some(&file),
+            &Credentials::default(),
+        );
+        assert_eq!(
+            resolved.openai_ca_bundle.as_deref(),
+            Some(Path::new("/etc/ssl/proxy.pem"))
+        );
+    }
+
+    #[test]
+    fn llamacpp_is_configured_from_file_or_env() {
+        let base = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            None,
+            &Credentials::default(),
+        );
+        assert_eq!(base.llamacpp_url, None);
+        assert_eq!(base.llamacpp_model, "default");
+
+        let file: FileConfig = serde_json::from_str(
+            r#"{"provider": "llamacpp", "llamacpp_url": "http://127.0.0.1:8080", "llamacpp_model": "qwen"}"#,
+        )
+        .expect("llamacpp keys should parse");
+        let env_cfg = EnvConfig::from_lookup(|key| {
+            (key == "KLUMO_LLAMACPP_URL").then(|| "http://gpu-box:8080".to_string())
+        });
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &env_cfg,
+            Some(&file),
+            &Credentials::default(),
+        );
+        assert_eq!(resolved.provider, ProviderSetting::Llamacpp);
+        assert_eq!(
+            resolved.llamacpp_url.as_deref(),
+            Some("http://gpu-box:8080")
+        );
+        assert_eq!(resolved.llamacpp_model, "qwen");
+    }
+
+    #[test]
+    fn size_limits_prefer_env_over_file() {
+        let file: FileConfig =
+            serde_json::from_str(r#"{"max_prompt_bytes": 65536, "max_response_bytes": 131072}"#)
+                .expect("limit keys should parse");
+        let env_cfg = EnvConfig::from_lookup(|key| {
+            (key == "KLUMO_MAX_PROMPT_BYTES").then(|| "4096".to_string())
+        });
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &env_cfg,
+            Some(&file),
+            &Credentials::default(),
+        );
+        assert_eq!(resolved.max_prompt_bytes, 4096);
+        assert_eq!(resolved.max_response_bytes, 131072);
+
+        let base = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            None,
+            &Credentials::default(),
+        );
+        assert_eq!(base.max_prompt_bytes, 256 * 1024);
+        assert_eq!(base.context_window, 8192);
+        assert_eq!(base.syntax_repair_attempts, 2);
+
+        let file: FileConfig =
+            serde_json::from_str(r#"{"context_window": 32768, "syntax_repair_attempts": 0}"#)
+                .expect("context window parses");
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            Some(&file),
+            &Credentials::default(),
+        );
+        assert_eq!(resolved.context_window, 32768);
+        assert_eq!(resolved.syntax_repair_attempts, 0);
+    }
+
+    #[test]
+    fn structured_output_prefers_env_over_file() {
+        let base = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            None,
+            &Credentials::default(),
+        );
+        assert!(!base.structured_output);
+        assert!(!base.explain);
+
+        let file = FileConfig {
+            structured_output: Some(true),
+            explain: Some(true),
+            incremental: Some(true),
+            classify_language: Some(true),
+            python_fast_path: Some(true),
+            ..FileConfig::default()
+        };
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &EnvConfig::default(),
+            Some(&file),
+            &Credentials::default(),
+        );
+        assert!(resolved.structured_output);
+        assert!(resolved.explain);
+        assert!(resolved.incremental);
+        assert!(resolved.classify_language);
+        assert!(resolved.python_fast_path);
+
+        let env_cfg = EnvConfig::from_lookup(|key| {
+            matches!(key, "KLUMO_STRUCTURED_OUTPUT" | "KLUMO_PYTHON_FAST_PATH")
+                .then(|| "false".to_string())
+        });
+        let resolved = resolve_run_defaults(
+            &CliRunOverrides::default(),
+            &env_cfg,
+            Some(&file),
+            &Credentials::default(),
+        );
+        assert!(!resolved.structured_output);
+        assert!(!resolved.python_fast_path);
+    }
+
+    #[test]
+    fn command_defaults_fill_unset_cli_flags_only() {
+        let dir = tempdir().expect("tempdir should work");
+        let path = dir.path().join("klumo.json");
+        fs::write(
+            &path,
+            r#"{
+  "defaults": {
+    "run": {"self_heal": true, "max_heal_attempts": 3, "provider": "ollama"},
+    "bundle": {"no_cache": true},
+    "test": {"jobs": 4, "minify": true}
+  }
+}"#,
+        )
+        .expect("write should work");
+
+        let parsed = load_file_config(None, dir.path())
+            .expect("parse should work")
+            .expect("file should exist");
+        let run_defaults = parsed.command_defaults("run").expect("run defaults");
+        assert_eq!(
+            parsed.command_defaults("bundle"),
+            Some(&CommandDefaults {
+                no_cache: Some(true),
+                ..CommandDefaults::default()
+            })
+        );
+        assert!(parsed.command_defaults("repl").is_none());
+        let test_defaults = parsed.command_defaults("test").expect("test defaults");
+        assert_eq!(test_defaults.flags.len(), 2);
+        assert_eq!(test_defaults.flags["jobs"], 4);
+
+        let mut cli = CliRunOverrides {
+            provider: Some(ProviderSetting::Openai),
+            ..CliRunOverrides::default()
+        };
+        cli.apply_command_defaults(run_defaults);
+        assert_eq!(cli.provider, Some(ProviderSetting::Openai));
+        assert_eq!(cli.self_heal, Some(true));
+        assert_eq!(cli.max_heal_attempts, Some(3));
+    }
+
+    #[test]
+    fn tool_sections_parse_and_reject_unknown_keys() {
+        let dir = tempdir().expect("tempdir should work");
+        let path = dir.path().join("klumo.json");
+        fs::write(
+            &path,
+            r#"{
+  "lint": {"tool": "deno", "rules": ["eqeqeq"], "include": ["src/"]},
+  "fmt": {"line_width": 100},
+  "test": {"tool": "cargo", "args": ["--workspace"]}
+}"#,
+        )
+        .expect("write should work");
+
+        let parsed = load_file_config(None, dir.path())
+            .expect("parse should work")
+            .expect("file should exist");
+        let lint = parsed.lint.expect("lint section");
+        assert_eq!(lint.tool, Some(ToolSetting::Deno));
+        assert_eq!(lint.rules, Some(vec!["eqeqeq".to_string()]));
+        assert_eq!(parsed.fmt.and_then(|fmt| fmt.line_width), Some(100));
+        assert_eq!(
+            parsed.test.and_then(|test| test.tool),
+            Some(ToolSetting::Cargo)
+        );
+
+        fs::write(&path, r#"{"fmt": {"width": 100}}"#).expect("write should work");
+        assert!(load_file_config(None, dir.path()).is_err());
+    }
+
+    #[test]
+    fn legacy_beeno_env_vars_alias_klumo_names() {
+        let vars: HashMap<&str, &str> = [
+            ("BEENO_PROVIDER", "ollama"),
+            ("BEENO_MODEL", "legacy-model"),
+            ("KLUMO_MODEL", "current-model"),
+            ("BEENO_FORCE_LLM", "1"),
+        ]
+        .into_iter()
+        .collect();
+        let lookup = |key: &str| vars.get(key).map(|v| v.to_string());
+
+        let env_cfg = EnvConfig::from_lookup(lookup);
+        assert_eq!(env_cfg.provider, Some(ProviderSetting::Ollama));
+        assert_eq!(env_cfg.openai_model.as_deref(), Some("current-model"));
+        assert_eq!(env_cfg.force_llm, Some(true));
+
+        let warnings = legacy_env_warnings_from(lookup);
+        assert_eq!(
+            warnings,
+            vec![
+                "BEENO_PROVIDER is deprecated; rename it to KLUMO_PROVIDER".to_string(),
+                "BEENO_FORCE_LLM is deprecated; rename it to KLUMO_FORCE_LLM".to_string(),
+            ]
+        );
+
+        // OPENAI_API_KEY wins over both prefixed names, so the legacy one has no effect.
+        let key_vars: HashMap<&str, &str> = [
+            ("OPENAI_API_KEY", "sk-current"),
+            ("BEENO_OPENAI_API_KEY", "sk-legacy"),
+        ]
+        .into_iter()
+        .collect();
+        let lookup = |key: &str| key_vars.get(key).map(|v| v.to_string());
+        assert_eq!(
+            EnvConfig::from_lookup(lookup).openai_api_key.as_deref(),
+            Some("sk-current")
+        );
+        assert!(legacy_env_warnings_from(lookup).is_empty());
+        let legacy_only = |key: &str| (key == "BEENO_OPENAI_API_KEY").then(|| "sk".to_string());
+        assert_eq!(
+            legacy_env_warnings_from(legacy_only),
+            vec![
+                "BEENO_OPENAI_API_KEY is deprecated; rename it to KLUMO_OPENAI_API_KEY".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn heal_escalation_ladder_walks_attempt_budgets() {
+        let ladder: Vec<HealStep> = serde_json::from_str(
+            r#"[
+  {"provider":"ollama","model":"qwen2.5-coder:1.5b","attempts":2},
+  {"provider":"custom:corp"},
+  {"provider":"openai","model":"gpt-4.1"}
+]"#,
+        )
+        .expect("ladder should parse");
+
+        let models: Vec<Option<&str>> = (0..4)
+            .map(|attempt| heal_step_for(&ladder, attempt).and_then(|step| step.model.as_deref()))
+            .collect();
+        assert_eq!(
+            models,
+            vec![
+                Some("qwen2.5-coder:1.5b"),
+                Some("qwen2.5-coder:1.5b"),
+                None,
+                Some("gpt-4.1"),
+            ]
+        );
+        assert_eq!(
+            heal_step_for(&ladder, 2).and_then(|step| step.provider.clone()),
+            Some(ProviderSetting::Custom("corp".to_string()))
+        );
+        assert_eq!(heal_step_for(&[], 0), None);
+    }
 }
@@ -1,9 +1,17 @@
-use anyhow::{Context, Result};
-use klumo_compiler::{CompileRequest, CompileResult, Compiler, SourceKind};
-use klumo_engine::{EvalOutput, JsEngine};
-use klumo_llm::ProviderSelection;
-use std::fs;
-use std::path::Path;
+use anyhow::{Context, Result, anyhow};
+use klumo_compiler::{
+    CompileRequest, CompileResult, Compiler, MODULE_EXTENSIONS, ModuleGraph, OutputTarget,
+    SourceKind, build_module_graph_batched,
+};
+use klumo_config::{ProviderSetting, parse_provider};
+use klumo_engine::{
+    DiagnosticKind, EvalOutput, JsEngine, RuntimeDiagnostic, ScriptError, ScriptGoal, goal_for,
+};
+use klumo_llm::{ProviderSelection, SamplingOptions};
+use std::fs::{self, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ProgressMode {
@@ -12,6 +20,53 @@ pub enum ProgressMode {
     Verbose,
 }
 
+/// Destination for `--print-js` output. Stdout stays reserved for program results by default.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum JsOutputTarget {
+    Stdout,
+    #[default]
+    Stderr,
+    /// Appends each generated snippet to the file.
+    File(PathBuf),
+}
+
+impl FromStr for JsOutputTarget {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "stdout" => Ok(Self::Stdout),
+            "stderr" => Ok(Self::Stderr),
+            other => match other.strip_prefix("file:") {
+                Some(path) if !path.is_empty() => Ok(Self::File(PathBuf::from(path))),
+                _ => Err(format!(
+                    "invalid print-js target '{other}' (expected stdout, stderr, or file:<path>)"
+                )),
+            },
+        }
+    }
+}
+
+/// Writes `javascript` between `/* ===== {label} JavaScript ===== */` markers to `target`.
+pub fn write_generated_js(target: &JsOutputTarget, label: &str, javascript: &str) -> Result<()> {
+    let block = format!(
+        "/* ===== {label} JavaScript ===== */\n{javascript}\n/* ===== end {label} JavaScript ===== */\n"
+    );
+    match target {
+        JsOutputTarget::Stdout => print!("{block}"),
+        JsOutputTarget::Stderr => eprint!("{block}"),
+        JsOutputTarget::File(path) => OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(block.as_bytes()))
+            .with_context(|| {
+                format!("failed writing generated JavaScript to {}", path.display())
+            })?,
+    }
+    Ok(())
+}
+
 #[derive(Debug, Clone)]
 pub struct RunOptions {
     pub kind_hint: Option<SourceKind>,
@@ -19,37 +74,225 @@ pub struct RunOptions {
     pub force_llm: bool,
     pub no_cache: bool,
     pub print_js: bool,
+    pub print_js_target: JsOutputTarget,
     pub provider_selection: ProviderSelection,
     pub model_override: Option<String>,
+    pub sampling: SamplingOptions,
+    pub structured_output: bool,
+    /// Ask for an explanation of each translation, stored with its cache entry.
+    pub explain: bool,
+    /// Model context window in tokens; larger sources are translated in chunks.
+    pub context_window: Option<usize>,
+    /// Evaluate the JavaScript as an ES module even when it also parses as a script, so it
+    /// runs in strict mode with module scoping; otherwise `goal_for` decides.
+    pub module: bool,
+    /// Modules of a graph compiled at once by [`compile_graph`]; see `Compiler::compile_many`.
+    pub jobs: usize,
     pub progress_mode: ProgressMode,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct RunOutcome {
     pub compile: CompileResult,
     pub eval: EvalOutput,
 }
 
+/// Translation settings carried by a file's first line, e.g.
+/// `// klumo: lang=pseudocode provider=ollama model=qwen2.5-coder:7b`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileDirectives {
+    pub lang: Option<String>,
+    pub provider: Option<ProviderSelection>,
+    pub model: Option<String>,
+}
+
+/// Parses a `klumo:` directive from the first line of `source`. The line may be commented with
+/// `//`, `#`, or `--`; `None` means the file has no directive.
+pub fn parse_file_directives(source: &str) -> Result<Option<FileDirectives>> {
+    let first = source.lines().next().unwrap_or_default().trim();
+    let Some(body) = ["//", "#", "--"]
+        .iter()
+        .find_map(|prefix| first.strip_prefix(prefix))
+        .and_then(|rest| rest.trim_start().strip_prefix("klumo:"))
+    else {
+        return Ok(None);
+    };
+
+    let mut directives = FileDirectives::default();
+    for pair in body.split_whitespace() {
+        let (key, value) = pair
+            .split_once('=')
+            .filter(|(_, value)| !value.is_empty())
+            .ok_or_else(|| anyhow!("invalid klumo directive '{pair}' (expected key=value)"))?;
+        match key {
+            "lang" => directives.lang = Some(value.to_string()),
+            "model" => directives.model = Some(value.to_string()),
+            "provider" => {
+                let provider = parse_provider(value)
+                    .ok_or_else(|| anyhow!("unknown provider '{value}' in klumo directive"))?;
+                directives.provider = Some(provider_selection(provider));
+            }
+            other => return Err(anyhow!("unknown klumo directive key '{other}'")),
+        }
+    }
+    Ok(Some(directives))
+}
+
+/// The router's name for a provider as written in configuration, a flag or a directive.
+pub fn provider_selection(provider: ProviderSetting) -> ProviderSelection {
+    match provider {
+        ProviderSetting::Auto => ProviderSelection::Auto,
+        ProviderSetting::Ollama => ProviderSelection::Ollama,
+        ProviderSetting::Openai => ProviderSelection::OpenAiCompatible,
+        ProviderSetting::Llamacpp => ProviderSelection::LlamaCpp,
+        ProviderSetting::Custom(name) => ProviderSelection::Custom(name),
+        ProviderSetting::Fixture => ProviderSelection::Fixture,
+    }
+}
+
+/// The `source_id` of sources read from standard input, e.g. by `klumo run -`. Their cache
+/// entries are keyed by it like a file's by its path, so piping the same text again hits.
+pub const STDIN_SOURCE_ID: &str = "<stdin>";
+
+/// Compiles `path`, letting a first-line [`FileDirectives`] override `options` for that file.
+/// A `fixture` provider selection is kept so recorded CI runs never reach the network, and a
+/// model passed with `--model` wins over the directive's.
 pub fn compile_file<C>(compiler: &C, path: &Path, options: &RunOptions) -> Result<CompileResult>
 where
     C: Compiler,
 {
+    compiler.compile(&file_request(path, options)?)
+}
+
+fn file_request(path: &Path, options: &RunOptions) -> Result<CompileRequest> {
     let source = fs::read_to_string(path)
         .with_context(|| format!("failed reading script file {}", path.display()))?;
+    source_request(&source, &path.display().to_string(), options)
+}
+
+/// Compiles source text that has no file of its own, such as standard input or an inline
+/// string, under `source_id`, the same way [`compile_file`] compiles a file. Without a
+/// language in `options` or a directive, the kind is inferred from `source_id`'s extension.
+pub fn compile_source<C>(
+    compiler: &C,
+    source: &str,
+    source_id: &str,
+    options: &RunOptions,
+) -> Result<CompileResult>
+where
+    C: Compiler,
+{
+    compiler.compile(&source_request(source, source_id, options)?)
+}
+
+fn source_request(source: &str, source_id: &str, options: &RunOptions) -> Result<CompileRequest> {
+    let directives = parse_file_directives(source)
+        .with_context(|| format!("failed parsing directives in {source_id}"))?
+        .unwrap_or_default();
 
-    compiler.compile(&CompileRequest {
-        source_text: source,
-        source_id: path.display().to_string(),
-        kind_hint: options.kind_hint.clone(),
-        language_hint: options.language_hint.clone(),
+    let (kind_hint, language_hint) = match directives.lang {
+        Some(lang) => (Some(SourceKind::from_hint(&lang)), Some(lang)),
+        None => (options.kind_hint.clone(), options.language_hint.clone()),
+    };
+    let provider_selection = match (&options.provider_selection, directives.provider) {
+        (ProviderSelection::Fixture, _) | (_, None) => options.provider_selection.clone(),
+        (_, Some(provider)) => provider,
+    };
+
+    Ok(CompileRequest {
+        source_text: source.to_string(),
+        source_id: source_id.to_string(),
+        kind_hint,
+        language_hint,
         scope_context: None,
         force_llm: options.force_llm,
-        provider_selection: options.provider_selection,
-        model_override: options.model_override.clone(),
+        provider_selection,
+        provider_override: None,
+        model_override: options.model_override.clone().or(directives.model),
+        model_override_for: None,
+        sampling: options.sampling,
+        structured_output: options.structured_output,
+        explain: options.explain,
+        context_window: options.context_window,
         no_cache: options.no_cache,
     })
 }
 
+/// Compiles `path` and every file it reaches through relative imports into a
+/// [`ModuleGraph`], each file like [`compile_file`] and the files of each import level up to
+/// `options.jobs` at once. `--lang` and a configured language name the entry's language;
+/// imported files with a JavaScript or TypeScript extension are compiled by their extension
+/// instead.
+pub fn compile_graph<C>(compiler: &C, path: &Path, options: &RunOptions) -> Result<ModuleGraph>
+where
+    C: Compiler + Sync,
+{
+    let entry = PathBuf::from(path);
+    let by_extension = RunOptions {
+        kind_hint: None,
+        language_hint: None,
+        ..options.clone()
+    };
+    build_module_graph_batched(path, |modules| {
+        let mut requests = Vec::new();
+        let mut unread = Vec::new();
+        for module in modules {
+            let script_extension = module
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| MODULE_EXTENSIONS.contains(&ext));
+            let options = if *module != entry && script_extension {
+                &by_extension
+            } else {
+                options
+            };
+            match file_request(module, options) {
+                Ok(request) => {
+                    requests.push(request);
+                    unread.push(None);
+                }
+                Err(err) => unread.push(Some(err)),
+            }
+        }
+        let mut compiled = compiler.compile_many(&requests, options.jobs).into_iter();
+        unread
+            .into_iter()
+            .map(|err| match err {
+                Some(err) => Err(err),
+                None => compiled.next().expect("one result per request"),
+            })
+            .collect()
+    })
+}
+
+/// Compiles the module graph of `path` and links it into one program; for a file without
+/// relative imports that is just its [`compile_file`] result. A linked program is returned
+/// with the entry's metadata and a source map covering every module.
+pub fn compile_linked<C>(compiler: &C, path: &Path, options: &RunOptions) -> Result<CompileResult>
+where
+    C: Compiler + Sync,
+{
+    compile_linked_as(compiler, path, options, None)
+}
+
+/// [`compile_linked`] in the module format `target` (see `ModuleGraph::link_as`).
+pub fn compile_linked_as<C>(
+    compiler: &C,
+    path: &Path,
+    options: &RunOptions,
+    target: Option<OutputTarget>,
+) -> Result<CompileResult>
+where
+    C: Compiler + Sync,
+{
+    let graph = compile_graph(compiler, path, options)?;
+    let (javascript, source_map) = graph.link_as(target)?;
+    let mut compiled = graph.entry().compile.clone();
+    compiled.javascript = javascript;
+    compiled.source_map = source_map;
+    Ok(compiled)
+}
+
 pub fn run_file<E, C>(
     engine: &mut E,
     compiler: &C,
@@ -58,7 +301,7 @@ pub fn run_file<E, C>(
 ) -> Result<RunOutcome>
 where
     E: JsEngine + ?Sized,
-    C: Compiler,
+    C: Compiler + Sync,
 {
     if matches!(options.progress_mode, ProgressMode::Verbose) {
         eprintln!("[klumo] loading source {}", path.display());
@@ -66,13 +309,45 @@ where
     if matches!(options.progress_mode, ProgressMode::Verbose) {
         eprintln!("[klumo] compiling source (force_llm={})", options.force_llm);
     }
-    let compile = compile_file(compiler, path, options)?;
+    let compile = compile_linked(compiler, path, options)?;
+    evaluate_compiled(engine, compile, &path.display().to_string(), options)
+}
+
+/// Compiles `source` with [`compile_source`] and runs it like [`run_file`]. Relative imports
+/// are not followed: there is no directory to resolve them against.
+pub fn run_source<E, C>(
+    engine: &mut E,
+    compiler: &C,
+    source: &str,
+    source_id: &str,
+    options: &RunOptions,
+) -> Result<RunOutcome>
+where
+    E: JsEngine + ?Sized,
+    C: Compiler,
+{
+    if matches!(options.progress_mode, ProgressMode::Verbose) {
+        eprintln!(
+            "[klumo] compiling {source_id} (force_llm={})",
+            options.force_llm
+        );
+    }
+    let compile = compile_source(compiler, source, source_id, options)?;
+    evaluate_compiled(engine, compile, source_id, options)
+}
 
+fn evaluate_compiled<E>(
+    engine: &mut E,
+    compile: CompileResult,
+    source_name: &str,
+    options: &RunOptions,
+) -> Result<RunOutcome>
+where
+    E: JsEngine + ?Sized,
+{
     let llm_path = compile.metadata.provider.is_some();
     if options.print_js || (matches!(options.progress_mode, ProgressMode::Verbose) && llm_path) {
-        println!("/* ===== generated JavaScript ===== */");
-        println!("{}", compile.javascript);
-        println!("/* ===== end generated JavaScript ===== */");
+        write_generated_js(&options.print_js_target, "generated", &compile.javascript)?;
     }
 
     match options.progress_mode {
@@ -82,13 +357,19 @@ where
                 let provider = compile
                     .metadata
                     .provider
-                    .map(|p| format!("{p:?}").to_ascii_lowercase())
+                    .as_ref()
+                    .map(ToString::to_string)
                     .unwrap_or_else(|| "unknown".to_string());
                 let model = compile.metadata.model.clone().unwrap_or_default();
                 eprintln!(
                     "[klumo] compiling via {}:{} (cache_hit={})",
                     provider, model, compile.metadata.cache_hit
                 );
+                if let Some(similarity) = compile.metadata.similarity {
+                    eprintln!(
+                        "[klumo] reused the translation of a similar source (similarity {similarity:.3})"
+                    );
+                }
                 eprintln!("[klumo] executing");
             }
         }
@@ -97,17 +378,107 @@ where
                 "[klumo] compile complete provider={:?} model={:?} cache_hit={}",
                 compile.metadata.provider, compile.metadata.model, compile.metadata.cache_hit
             );
+            if let Some(notes) = compile.metadata.notes.as_deref() {
+                eprintln!("[klumo] translator notes: {notes}");
+            }
+            if let Some(confidence) = compile.metadata.confidence {
+                eprintln!("[klumo] translator confidence: {confidence:.2}");
+            }
+            if let Some(similarity) = compile.metadata.similarity {
+                eprintln!(
+                    "[klumo] reused the translation of a similar source (similarity {similarity:.3})"
+                );
+            }
+            if let Some(served) = compile.metadata.served_model.as_deref() {
+                eprintln!("[klumo] served by fallback model {served}");
+            }
             eprintln!("[klumo] executing JavaScript");
         }
     }
 
-    let eval = engine.eval_script(&compile.javascript, &path.display().to_string())?;
+    // Errors the engine reports at a line of the generated program also name the source line
+    // it came from, and list the functions the error was raised in.
+    let goal = if options.module {
+        ScriptGoal::Module
+    } else {
+        goal_for(&compile.javascript, source_name)
+    };
+    let eval = engine
+        .evaluate_as(goal, &compile.javascript, source_name)
+        .map_err(|err| {
+            let remapped = compile
+                .source_map
+                .as_ref()
+                .and_then(|map| map.remap_error(source_name, &err.to_string()));
+            let stack = err
+                .downcast_ref::<ScriptError>()
+                .map(|script_error| stack_lines(&script_error.diagnostic.stack, false))
+                .unwrap_or_default();
+            match remapped {
+                Some(remapped) => anyhow!("{remapped}{stack}"),
+                None if stack.is_empty() => err,
+                None => anyhow!("{err}{stack}"),
+            }
+        })?;
+    let color = io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    for diagnostic in &eval.diagnostics {
+        eprintln!("{}", render_diagnostic(diagnostic, color));
+    }
     if matches!(options.progress_mode, ProgressMode::Verbose) {
         eprintln!("[klumo] execution complete");
     }
     Ok(RunOutcome { compile, eval })
 }
 
-pub fn eval_inline<E: JsEngine + ?Sized>(engine: &mut E, code: &str) -> Result<EvalOutput> {
-    engine.eval_script(code, "<eval>")
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+/// `diagnostic` as klumo prints it to stderr: a `[klumo]` line labelled with its kind, then
+/// one `    at <function>` line per stack frame. `color` adds ANSI colors to the label and
+/// frames.
+pub fn render_diagnostic(diagnostic: &RuntimeDiagnostic, color: bool) -> String {
+    let (label, label_color) = match diagnostic.kind {
+        DiagnosticKind::Warning => ("warning:", YELLOW),
+        DiagnosticKind::UnhandledRejection => ("unhandled promise rejection:", RED),
+        DiagnosticKind::Exception => ("uncaught", RED),
+    };
+    let label = if color {
+        format!("{label_color}{label}{RESET}")
+    } else {
+        label.to_string()
+    };
+    format!(
+        "[klumo] {label} {diagnostic}{}",
+        stack_lines(&diagnostic.stack, color)
+    )
+}
+
+/// One `\n    at <function>` line per frame, dimmed with `color`.
+fn stack_lines(stack: &[String], color: bool) -> String {
+    stack
+        .iter()
+        .map(|frame| {
+            if color {
+                format!("\n{DIM}    at {frame}{RESET}")
+            } else {
+                format!("\n    at {frame}")
+            }
+        })
+        .collect()
+}
+
+/// Evaluates `code` as `klumo eval` does: as an ES module with `module`, else with the goal
+/// `goal_for` picks.
+pub fn eval_inline<E: JsEngine + ?Sized>(
+    engine: &mut E,
+    code: &str,
+    module: bool,
+) -> Result<EvalOutput> {
+    if module {
+        engine.evaluate_as(ScriptGoal::Module, code, "<eval>")
+    } else {
+        engine.evaluate(code, "<eval>")
+    }
 }
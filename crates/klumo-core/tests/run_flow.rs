@@ -1,10 +1,15 @@
 use anyhow::{Result, anyhow};
-use klumo_compiler::{CompileCache, CompileResult, CompilerRouter, SourceKind};
-use klumo_core::{ProgressMode, RunOptions, compile_file, run_file};
-use klumo_engine::BoaEngine;
+use klumo_compiler::{
+    CompileCache, CompileRequest, CompileResult, Compiler, CompilerRouter, SourceKind,
+};
+use klumo_core::{
+    FileDirectives, JsOutputTarget, ProgressMode, RunOptions, STDIN_SOURCE_ID, compile_file,
+    compile_source, parse_file_directives, render_diagnostic, run_file, run_source,
+};
+use klumo_engine::{BoaEngine, DiagnosticKind, RuntimeDiagnostic, SourcePosition};
 use klumo_llm::{
     LlmTranslateRequest, LlmTranslateResponse, Provider, ProviderDescriptor, ProviderSelection,
-    TranslationService,
+    SamplingOptions, TranslationService,
 };
 use std::collections::HashMap;
 use std::fs;
@@ -24,12 +29,16 @@ impl Default for MemoryCache {
 }
 
 impl CompileCache for MemoryCache {
+    /// Marks hits like `FileCompileCache` does.
     fn get(&self, key: &str) -> Option<CompileResult> {
-        self.data
+        let mut hit = self
+            .data
             .lock()
             .expect("lock should work")
             .get(key)
-            .cloned()
+            .cloned()?;
+        hit.metadata.cache_hit = true;
+        Some(hit)
     }
 
     fn put(&self, key: &str, result: &CompileResult) -> Result<()> {
@@ -66,8 +75,12 @@ impl TranslationService for MockService {
 
         Ok(LlmTranslateResponse {
             javascript: self.js.clone(),
-            provider: self.provider,
+            provider: self.provider.clone(),
             model: self.model.clone(),
+            served_model: None,
+            notes: None,
+            confidence: None,
+            explanation: None,
         })
     }
 }
@@ -79,8 +92,15 @@ fn options() -> RunOptions {
         force_llm: false,
         no_cache: true,
         print_js: false,
+        print_js_target: JsOutputTarget::default(),
         provider_selection: ProviderSelection::Auto,
         model_override: None,
+        sampling: SamplingOptions::default(),
+        structured_output: false,
+        explain: false,
+        context_window: None,
+        module: false,
+        jobs: 1,
         progress_mode: ProgressMode::Silent,
     }
 }
@@ -91,30 +111,99 @@ fn runs_js_file_without_llm() {
     let file = dir.path().join("hello.js");
     fs::write(&file, "21 * 2").expect("write should work");
 
-    let compiler = CompilerRouter {
-        translator: MockService {
+    let compiler = CompilerRouter::new(
+        MockService {
             fail: true,
             js: String::new(),
             provider: Provider::Ollama,
             model: "qwen".to_string(),
             chain: vec![],
         },
-        cache: MemoryCache::default(),
-    };
+        MemoryCache::default(),
+    );
 
-    let mut engine = BoaEngine::new();
+    let mut engine = BoaEngine::new().expect("engine should initialize");
     let outcome = run_file(&mut engine, &compiler, &file, &options()).expect("run should pass");
     assert_eq!(outcome.eval.value.as_deref(), Some("42"));
 }
 
+#[test]
+fn runs_module_js_as_a_module() {
+    let dir = tempdir().expect("tempdir should work");
+    let file = dir.path().join("main.mjs");
+    fs::write(
+        &file,
+        "export const answer = await Promise.resolve(42);\nconsole.log('answer', answer);",
+    )
+    .expect("write should work");
+
+    let compiler = CompilerRouter::new(
+        MockService {
+            fail: true,
+            js: String::new(),
+            provider: Provider::Ollama,
+            model: "qwen".to_string(),
+            chain: vec![],
+        },
+        MemoryCache::default(),
+    );
+
+    let mut engine = BoaEngine::new().expect("engine should initialize");
+    let outcome = run_file(&mut engine, &compiler, &file, &options()).expect("run should pass");
+    assert_eq!(outcome.eval.value, None);
+    assert_eq!(outcome.eval.console_output.as_deref(), Some("answer 42"));
+}
+
+#[test]
+fn module_option_runs_scripts_with_module_semantics() {
+    let dir = tempdir().expect("tempdir should work");
+    let file = dir.path().join("main.js");
+    fs::write(
+        &file,
+        "var global = 1;\nconsole.log(typeof this, typeof globalThis.global);",
+    )
+    .expect("write should work");
+    let compiler = CompilerRouter::new(
+        MockService {
+            fail: true,
+            js: String::new(),
+            provider: Provider::Ollama,
+            model: "qwen".to_string(),
+            chain: vec![],
+        },
+        MemoryCache::default(),
+    );
+
+    let mut engine = BoaEngine::new().expect("engine should initialize");
+    let script = run_file(&mut engine, &compiler, &file, &options()).expect("run should pass");
+    assert_eq!(script.eval.console_output.as_deref(), Some("object number"));
+
+    let mut engine = BoaEngine::new().expect("engine should initialize");
+    let module = run_file(
+        &mut engine,
+        &compiler,
+        &file,
+        &RunOptions {
+            module: true,
+            ..options()
+        },
+    )
+    .expect("run should pass");
+    assert_eq!(
+        module.eval.console_output.as_deref(),
+        Some("undefined undefined")
+    );
+    assert_eq!(module.eval.value, None);
+}
+
 #[test]
 fn runs_non_js_with_llm_compile() {
     let dir = tempdir().expect("tempdir should work");
     let file = dir.path().join("hello.pseudo");
     fs::write(&file, "write hello").expect("write should work");
 
-    let compiler = CompilerRouter {
-        translator: MockService {
+    let compiler = CompilerRouter::new(
+        MockService {
             fail: false,
             js: "'compiled-' + 'ok'".to_string(),
             provider: Provider::Ollama,
@@ -124,10 +213,10 @@ fn runs_non_js_with_llm_compile() {
                 model: "qwen".to_string(),
             }],
         },
-        cache: MemoryCache::default(),
-    };
+        MemoryCache::default(),
+    );
 
-    let mut engine = BoaEngine::new();
+    let mut engine = BoaEngine::new().expect("engine should initialize");
     let outcome = run_file(
         &mut engine,
         &compiler,
@@ -143,14 +232,54 @@ fn runs_non_js_with_llm_compile() {
     assert_eq!(outcome.eval.value.as_deref(), Some("compiled-ok"));
 }
 
+#[test]
+fn stdin_sources_are_translated_and_cached_like_files() {
+    let compiler = CompilerRouter::new(
+        MockService {
+            fail: false,
+            js: "'piped-' + 'ok'".to_string(),
+            provider: Provider::Ollama,
+            model: "qwen".to_string(),
+            chain: vec![ProviderDescriptor {
+                provider: Provider::Ollama,
+                model: "qwen".to_string(),
+            }],
+        },
+        MemoryCache::default(),
+    );
+    let options = RunOptions {
+        kind_hint: Some(SourceKind::Unknown("python".to_string())),
+        language_hint: Some("python".to_string()),
+        no_cache: false,
+        ..options()
+    };
+
+    let mut engine = BoaEngine::new().expect("engine should initialize");
+    let outcome = run_source(
+        &mut engine,
+        &compiler,
+        "print('piped')",
+        STDIN_SOURCE_ID,
+        &options,
+    )
+    .expect("run should pass");
+    assert_eq!(outcome.eval.value.as_deref(), Some("piped-ok"));
+    assert!(!outcome.compile.metadata.cache_hit);
+
+    let again = compile_source(&compiler, "print('piped')", STDIN_SOURCE_ID, &options)
+        .expect("compile should pass");
+    assert!(again.metadata.cache_hit);
+    assert_eq!(again.javascript, outcome.compile.javascript);
+}
+
 #[test]
 fn compile_failure_returns_error() {
     let dir = tempdir().expect("tempdir should work");
     let file = dir.path().join("bad.pseudo");
     fs::write(&file, "broken").expect("write should work");
 
-    let compiler = CompilerRouter {
-        translator: MockService {
+    let compiler = CompilerRouter::new(
+        MockService {
             fail: true,
             js: String::new(),
             provider: Provider::Ollama,
@@ -160,10 +289,10 @@ fn compile_failure_returns_error() {
                 model: "qwen".to_string(),
             }],
         },
-        cache: MemoryCache::default(),
-    };
+        MemoryCache::default(),
+    );
 
-    let mut engine = BoaEngine::new();
+    let mut engine = BoaEngine::new().expect("engine should initialize");
     let err = run_file(
         &mut engine,
         &compiler,
@@ -183,22 +312,68 @@ fn compile_failure_returns_error() {
 fn runtime_failure_returns_error() {
     let dir = tempdir().expect("tempdir should work");
     let file = dir.path().join("boom.js");
-    fs::write(&file, "throw new Error('boom')").expect("write should work");
+    fs::write(
+        &file,
+        "function explode() {\n  throw new Error('boom');\n}\nexplode();\n",
+    )
+    .expect("write should work");
 
-    let compiler = CompilerRouter {
-        translator: MockService {
+    let compiler = CompilerRouter::new(
+        MockService {
             fail: true,
             js: String::new(),
             provider: Provider::Ollama,
             model: "qwen".to_string(),
             chain: vec![],
         },
-        cache: MemoryCache::default(),
-    };
+        MemoryCache::default(),
+    );
 
-    let mut engine = BoaEngine::new();
+    let mut engine = BoaEngine::new().expect("engine should initialize");
     let err = run_file(&mut engine, &compiler, &file, &options()).expect_err("run should fail");
     assert!(err.to_string().contains("failed evaluating"));
+    assert!(
+        err.to_string()
+            .ends_with("Error: boom\n    at explode\n    at <main>")
+    );
+}
+
+#[test]
+fn diagnostics_render_with_their_kind_and_stack() {
+    let rejection = RuntimeDiagnostic {
+        kind: DiagnosticKind::UnhandledRejection,
+        name: Some("TypeError".to_string()),
+        message: "bad input".to_string(),
+        position: None,
+        stack: vec!["parse".to_string(), "<main>".to_string()],
+    };
+    assert_eq!(
+        render_diagnostic(&rejection, false),
+        "[klumo] unhandled promise rejection: TypeError: bad input\n    at parse\n    at <main>"
+    );
+    assert_eq!(
+        render_diagnostic(&rejection, true),
+        "[klumo] \x1b[31munhandled promise rejection:\x1b[0m TypeError: bad input\n\x1b[2m    at parse\x1b[0m\n\x1b[2m    at <main>\x1b[0m"
+    );
+
+    let syntax = RuntimeDiagnostic {
+        kind: DiagnosticKind::Exception,
+        name: Some("SyntaxError".to_string()),
+        message: "unexpected token ';'".to_string(),
+        position: Some(SourcePosition { line: 3, column: 7 }),
+        stack: Vec::new(),
+    };
+    assert_eq!(
+        render_diagnostic(&syntax, false),
+        "[klumo] uncaught SyntaxError: unexpected token ';' at line 3, col 7"
+    );
+    assert_eq!(
+        render_diagnostic(
+            &RuntimeDiagnostic::warning("console output was lost"),
+            false
+        ),
+        "[klumo] warning: console output was lost"
+    );
 }
 
 #[test]
@@ -207,16 +382,16 @@ fn compile_file_passthrough_for_js() {
     let file = dir.path().join("hello.js");
     fs::write(&file, "1 + 2").expect("write should work");
 
-    let compiler = CompilerRouter {
-        translator: MockService {
+    let compiler = CompilerRouter::new(
+        MockService {
             fail: true,
             js: String::new(),
             provider: Provider::Ollama,
             model: "qwen".to_string(),
             chain: vec![],
         },
-        cache: MemoryCache::default(),
-    };
+        MemoryCache::default(),
+    );
 
     let out = compile_file(&compiler, &file, &options()).expect("compile should pass");
     assert_eq!(out.javascript, "1 + 2");
@@ -229,8 +404,8 @@ fn compile_file_uses_llm_for_non_js() {
     let file = dir.path().join("hello.pseudo");
     fs::write(&file, "write hello").expect("write should work");
 
-    let compiler = CompilerRouter {
-        translator: MockService {
+    let compiler = CompilerRouter::new(
+        MockService {
             fail: false,
             js: "console.log('hello')".to_string(),
             provider: Provider::Ollama,
@@ -240,8 +415,8 @@ fn compile_file_uses_llm_for_non_js() {
                 model: "qwen".to_string(),
             }],
         },
-        cache: MemoryCache::default(),
-    };
+        MemoryCache::default(),
+    );
 
     let out = compile_file(
         &compiler,
@@ -256,3 +431,138 @@ fn compile_file_uses_llm_for_non_js() {
     assert_eq!(out.metadata.provider, Some(Provider::Ollama));
     assert_eq!(out.javascript, "console.log('hello')");
 }
+
+#[test]
+fn print_js_appends_marked_output_to_file_target() {
+    let dir = tempdir().expect("tempdir should work");
+    let file = dir.path().join("hello.js");
+    let js_out = dir.path().join("generated.js");
+    fs::write(&file, "21 * 2").expect("write should work");
+
+    let compiler = CompilerRouter::new(
+        MockService {
+            fail: true,
+            js: String::new(),
+            provider: Provider::Ollama,
+            model: "qwen".to_string(),
+            chain: vec![],
+        },
+        MemoryCache::default(),
+    );
+    let target: JsOutputTarget = format!("file:{}", js_out.display())
+        .parse()
+        .expect("target should parse");
+
+    let mut engine = BoaEngine::new().expect("engine should initialize");
+    let options = RunOptions {
+        print_js: true,
+        print_js_target: target,
+        ..options()
+    };
+    run_file(&mut engine, &compiler, &file, &options).expect("run should pass");
+
+    let written = fs::read_to_string(&js_out).expect("generated js should be written");
+    assert!(written.starts_with("/* ===== generated JavaScript ===== */\n21 * 2\n"));
+    assert!("stdout".parse::<JsOutputTarget>().is_ok());
+    assert!("file:".parse::<JsOutputTarget>().is_err());
+}
+
+#[test]
+fn file_directives_parse_first_line_only() {
+    let parsed = parse_file_directives(
+        "// klumo: lang=pseudocode provider=ollama model=qwen2.5-coder:7b\nwrite hello",
+    )
+    .expect("directive should parse");
+    assert_eq!(
+        parsed,
+        Some(FileDirectives {
+            lang: Some("pseudocode".to_string()),
+            provider: Some(ProviderSelection::Ollama),
+            model: Some("qwen2.5-coder:7b".to_string()),
+        })
+    );
+
+    assert_eq!(
+        parse_file_directives("# klumo: lang=python")
+            .expect("hash comments should parse")
+            .and_then(|d| d.lang)
+            .as_deref(),
+        Some("python")
+    );
+    assert_eq!(
+        parse_file_directives("1 + 2\n// klumo: lang=pseudocode").expect("no directive"),
+        None
+    );
+    assert_eq!(
+        parse_file_directives("// klumo: provider=custom:corp")
+            .expect("custom providers should parse")
+            .and_then(|d| d.provider),
+        Some(ProviderSelection::Custom("corp".to_string()))
+    );
+    assert!(parse_file_directives("// klumo: color=blue").is_err());
+    assert!(parse_file_directives("// klumo: provider=cloud").is_err());
+    assert!(parse_file_directives("// klumo: provider=custom:").is_err());
+}
+
+/// Records the model each request asks for.
+#[derive(Default)]
+struct ModelRecorder {
+    models: Mutex<Vec<Option<String>>>,
+}
+
+impl Compiler for ModelRecorder {
+    fn compile(&self, req: &CompileRequest) -> Result<CompileResult> {
+        self.models
+            .lock()
+            .expect("models lock")
+            .push(req.model_override.clone());
+        Err(anyhow!("recorded"))
+    }
+}
+
+#[test]
+fn model_flag_wins_over_the_model_directive() {
+    let source = "// klumo: model=from-file\nwrite hello";
+    let recorder = ModelRecorder::default();
+    let _ = compile_source(&recorder, source, "hello.pseudo", &options());
+    let flagged = RunOptions {
+        model_override: Some("from-flag".to_string()),
+        ..options()
+    };
+    let _ = compile_source(&recorder, source, "hello.pseudo", &flagged);
+    assert_eq!(
+        *recorder.models.lock().expect("models lock"),
+        vec![Some("from-file".to_string()), Some("from-flag".to_string())]
+    );
+    assert_eq!(
+        parse_file_directives("// klumo: provider=Ollama")
+            .expect("provider names are case-insensitive")
+            .and_then(|d| d.provider),
+        Some(ProviderSelection::Ollama)
+    );
+}
+
+#[test]
+fn compile_file_applies_lang_directive() {
+    let dir = tempdir().expect("tempdir should work");
+    let file = dir.path().join("hello.js");
+    fs::write(&file, "// klumo: lang=pseudocode\nwrite hello").expect("write should work");
+
+    let compiler = CompilerRouter::new(
+        MockService {
+            fail: false,
+            js: "console.log('hello')".to_string(),
+            provider: Provider::Ollama,
+            model: "qwen".to_string(),
+            chain: vec![ProviderDescriptor {
+                provider: Provider::Ollama,
+                model: "qwen".to_string(),
+            }],
+        },
+        MemoryCache::default(),
+    );
+
+    let out = compile_file(&compiler, &file, &options()).expect("compile should pass");
+    assert_eq!(out.metadata.provider, Some(Provider::Ollama));
+    assert_eq!(out.javascript, "console.log('hello')");
+}
@@ -0,0 +1,293 @@
+//! Behavior every `JsEngine` backend must share: a table of scripts with the completion
+//! value, console output or error each one has to produce, and a runner that evaluates them
+//! on a fresh engine per case. Backends run [`run_suite`] from their tests, so Boa, V8 and
+//! later engines cannot drift apart unnoticed.
+
+use anyhow::Result;
+use klumo_engine::{JsEngine, ScriptGoal};
+
+/// What evaluating a case must produce.
+#[derive(Debug, Clone, Copy)]
+pub enum Expected {
+    /// Evaluation succeeds with this completion value (`None` for `undefined` and modules),
+    /// this console output and no diagnostics.
+    Completes {
+        value: Option<&'static str>,
+        console: Option<&'static str>,
+    },
+    /// Evaluation fails with an error whose message contains this text.
+    Fails(&'static str),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Case {
+    pub name: &'static str,
+    /// `Module` cases go through `eval_module`; engines without module support must refuse
+    /// them with an explanation instead.
+    pub goal: ScriptGoal,
+    pub source: &'static str,
+    pub expected: Expected,
+}
+
+const fn script(
+    name: &'static str,
+    source: &'static str,
+    value: Option<&'static str>,
+    console: Option<&'static str>,
+) -> Case {
+    Case {
+        name,
+        goal: ScriptGoal::Script,
+        source,
+        expected: Expected::Completes { value, console },
+    }
+}
+
+const fn module(name: &'static str, source: &'static str, console: Option<&'static str>) -> Case {
+    Case {
+        name,
+        goal: ScriptGoal::Module,
+        source,
+        expected: Expected::Completes {
+            value: None,
+            console,
+        },
+    }
+}
+
+const fn fails(
+    name: &'static str,
+    goal: ScriptGoal,
+    source: &'static str,
+    error: &'static str,
+) -> Case {
+    Case {
+        name,
+        goal,
+        source,
+        expected: Expected::Fails(error),
+    }
+}
+
+pub const CASES: &[Case] = &[
+    // Completion values
+    script("expression value", "1 + 2", Some("3"), None),
+    script("undefined has no value", "const a = 1;", None, None),
+    script("strings render as text", "'a' + \"b\"", Some("ab"), None),
+    script("arrays render joined", "[1, [2, 3]]", Some("1,2,3"), None),
+    script(
+        "json round trip",
+        "JSON.stringify({ a: [1, 'x'], b: null })",
+        Some("{\"a\":[1,\"x\"],\"b\":null}"),
+        None,
+    ),
+    script(
+        "non-ascii text stays utf-8",
+        "'🦀 ' + 'héllo'",
+        Some("🦀 héllo"),
+        None,
+    ),
+    // Console
+    script(
+        "console.log joins its arguments with spaces",
+        "console.log('a', 1, true, null)",
+        None,
+        Some("a 1 true null"),
+    ),
+    script(
+        "console.error shares the console output",
+        "console.log('out'); console.error('err');",
+        None,
+        Some("out\nerr"),
+    ),
+    script(
+        "console output and a value",
+        "console.log('x'); 42",
+        Some("42"),
+        Some("x"),
+    ),
+    script(
+        "console.log inspects objects",
+        "console.log({ a: [1, 'x'], b: { c: { d: {} } } }, new Map([[1, true]]))",
+        None,
+        Some("{ a: [ 1, 'x' ], b: { c: { d: {} } } } Map(1) { 1 => true }"),
+    ),
+    script(
+        "console groups indent their lines",
+        "console.group('g'); console.warn('w'); console.groupEnd(); console.info('i')",
+        None,
+        Some("g\n  w\ni"),
+    ),
+    script(
+        "console is a global object",
+        "typeof console.log",
+        Some("function"),
+        None,
+    ),
+    // Errors
+    fails(
+        "thrown errors carry their message",
+        ScriptGoal::Script,
+        "throw new Error('boom')",
+        "boom",
+    ),
+    fails(
+        "thrown values that are not errors",
+        ScriptGoal::Script,
+        "throw 'plain failure'",
+        "plain failure",
+    ),
+    fails(
+        "unknown names are reference errors",
+        ScriptGoal::Script,
+        "missingName + 1",
+        "ReferenceError",
+    ),
+    fails(
+        "property access on null is a type error",
+        ScriptGoal::Script,
+        "null.x",
+        "TypeError",
+    ),
+    fails(
+        "syntax errors are reported",
+        ScriptGoal::Script,
+        "let = ;",
+        "SyntaxError",
+    ),
+    // ES modules
+    module(
+        "module declarations and exports",
+        "export const answer = 42;\nconsole.log('module', answer);",
+        Some("module 42"),
+    ),
+    module(
+        "top-level await",
+        "const value = await Promise.resolve(7);\nconsole.log(value);",
+        Some("7"),
+    ),
+    fails(
+        "rejected top-level await fails the module",
+        ScriptGoal::Module,
+        "await Promise.reject(new Error('nope'));",
+        "nope",
+    ),
+    fails(
+        "module errors carry their message",
+        ScriptGoal::Module,
+        "export const x = 1;\nthrow new Error('module boom');",
+        "module boom",
+    ),
+    // Async
+    module(
+        "async functions resume after await",
+        "async function twice(n) { await null; return n * 2; }\nconsole.log(await twice(21));",
+        Some("42"),
+    ),
+    module(
+        "microtasks run after synchronous code",
+        "Promise.resolve().then(() => console.log(3));\nconsole.log(1);\nconsole.log(2);\nawait null;",
+        Some("1\n2\n3"),
+    ),
+    script(
+        "script promise callbacks run before evaluation returns",
+        "Promise.resolve(2).then((v) => console.log('then', v));\n'sync'",
+        Some("sync"),
+        Some("then 2"),
+    ),
+    // Timers
+    script(
+        "timers fire in delay order after microtasks",
+        "setTimeout(() => console.log('late'), 20);\nsetTimeout(() => console.log('soon'), 0);\nqueueMicrotask(() => console.log('micro'));\nconsole.log('sync');",
+        None,
+        Some("sync\nmicro\nsoon\nlate"),
+    ),
+    script(
+        "timeouts pass their arguments and can be cleared",
+        "const id = setTimeout(() => console.log('cleared'), 0);\nclearTimeout(id);\nvoid setTimeout((a, b) => console.log(a + b), 1, 1, 2);",
+        None,
+        Some("3"),
+    ),
+    script(
+        "intervals repeat until cleared",
+        "let n = 0;\nconst id = setInterval(() => { n += 1; console.log('tick', n); if (n === 3) clearInterval(id); }, 1);",
+        None,
+        Some("tick 1\ntick 2\ntick 3"),
+    ),
+    module(
+        "top-level await on a timer",
+        "const value = await new Promise((resolve) => setTimeout(resolve, 5, 'done'));\nconsole.log(value);",
+        Some("done"),
+    ),
+    fails(
+        "errors thrown by timers fail the evaluation",
+        ScriptGoal::Script,
+        "setTimeout(() => { throw new Error('timer boom'); }, 0);",
+        "timer boom",
+    ),
+];
+
+/// Evaluates `case` on `engine`; `Err` describes how the result differs from the case.
+pub fn run_case(engine: &mut dyn JsEngine, case: &Case) -> std::result::Result<(), String> {
+    let source_name = format!("{}.js", case.name.replace(' ', "-"));
+    let outcome = match case.goal {
+        ScriptGoal::Script => engine.eval_script(case.source, &source_name),
+        ScriptGoal::Module if engine.supports_modules() => {
+            engine.eval_module(case.source, &source_name)
+        }
+        ScriptGoal::Module => {
+            return match engine.evaluate(case.source, &source_name) {
+                Err(err) if err.to_string().contains("ES module") => Ok(()),
+                Err(err) => Err(format!(
+                    "expected the module to be refused as unsupported, got: {err:#}"
+                )),
+                Ok(output) => Err(format!(
+                    "expected the module to be refused as unsupported, got {output:?}"
+                )),
+            };
+        }
+    };
+    match (case.expected, outcome) {
+        (Expected::Completes { value, console }, Ok(output)) => {
+            if output.value.as_deref() != value {
+                return Err(format!("value {:?}, expected {value:?}", output.value));
+            }
+            if output.console_output.as_deref() != console {
+                return Err(format!(
+                    "console output {:?}, expected {console:?}",
+                    output.console_output
+                ));
+            }
+            if !output.diagnostics.is_empty() {
+                return Err(format!("unexpected diagnostics {:?}", output.diagnostics));
+            }
+            Ok(())
+        }
+        (Expected::Completes { .. }, Err(err)) => Err(format!("failed: {err:#}")),
+        (Expected::Fails(text), Err(err)) if format!("{err:#}").contains(text) => Ok(()),
+        (Expected::Fails(text), Err(err)) => {
+            Err(format!("error {err:#} does not mention {text:?}"))
+        }
+        (Expected::Fails(text), Ok(output)) => Err(format!(
+            "expected an error mentioning {text:?}, got {output:?}"
+        )),
+    }
+}
+
+/// Runs every case in [`CASES`] on a fresh engine from `new_engine`, returning one line per
+/// case that failed, or whose engine could not be created.
+pub fn run_suite<E, F>(mut new_engine: F) -> Vec<String>
+where
+    E: JsEngine,
+    F: FnMut() -> Result<E>,
+{
+    CASES
+        .iter()
+        .filter_map(|case| {
+            let result = new_engine()
+                .map_err(|err| format!("engine did not start: {err:#}"))
+                .and_then(|mut engine| run_case(&mut engine, case));
+            result.err().map(|err| format!("{}: {err}", case.name))
+        })
+        .collect()
+}
@@ -0,0 +1,28 @@
+use klumo_engine::BoaEngine;
+use klumo_engine_tests::{CASES, run_suite};
+use klumo_engine_v8::V8Engine;
+
+fn discard_line(_line: &str) {}
+
+#[test]
+fn boa_engine_passes_the_conformance_suite() {
+    let failures = run_suite(|| BoaEngine::with_console_sink(discard_line));
+    assert!(
+        failures.is_empty(),
+        "{} of {} cases failed:\n{}",
+        failures.len(),
+        CASES.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn v8_engine_runs_the_suite_once_it_starts() {
+    // The backend is a scaffold; once `V8Engine::new` succeeds, it is held to every case.
+    if let Err(err) = V8Engine::new() {
+        assert!(err.to_string().contains("not implemented"), "{err:#}");
+        return;
+    }
+    let failures = run_suite(V8Engine::new);
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
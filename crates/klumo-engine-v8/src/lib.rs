@@ -1,5 +1,9 @@
 use anyhow::{Result, anyhow};
-use klumo_engine::{EvalOutput, JsEngine};
+use klumo_engine::{EvalOutput, HostFunction, JsEngine, MemoryUsage};
+use klumo_runtime_std::StdBackend;
+
+const UNAVAILABLE: &str =
+    "V8 backend is scaffolded but not implemented yet. Use KLUMO_ENGINE=boa for now.";
 
 /// Placeholder V8 backend entrypoint.
 ///
@@ -9,28 +13,65 @@ use klumo_engine::{EvalOutput, JsEngine};
 pub struct V8Engine;
 
 impl V8Engine {
+    /// Once implemented, this creates the isolate and hands it to
+    /// `klumo_runtime_std::install`, like `BoaEngine::new`.
     pub fn new() -> Result<Self> {
-        Err(anyhow!(
-            "V8 backend is scaffolded but not implemented yet. Use KLUMO_ENGINE=boa for now."
-        ))
+        Err(anyhow!(UNAVAILABLE))
+    }
+}
+
+/// The V8 installer for `klumo-runtime-std`: each method maps onto an isolate operation
+/// (a `FunctionTemplate` on the global object, a script run, a boolean evaluation).
+impl StdBackend for V8Engine {
+    fn register_shared(
+        &mut self,
+        _name: &str,
+        _arity: usize,
+        _function: HostFunction,
+    ) -> Result<()> {
+        Err(anyhow!(UNAVAILABLE))
+    }
+
+    fn register_engine_bound(&mut self, _name: &str, _arity: usize) -> Result<()> {
+        Err(anyhow!(UNAVAILABLE))
+    }
+
+    fn run_setup(&mut self, _source: &str, _module: &str) -> Result<()> {
+        Err(anyhow!(UNAVAILABLE))
+    }
+
+    fn eval_bool(&mut self, _expression: &str) -> Result<bool> {
+        Err(anyhow!(UNAVAILABLE))
     }
 }
 
 impl JsEngine for V8Engine {
     fn eval_script(&mut self, _source: &str, _source_name: &str) -> Result<EvalOutput> {
-        Err(anyhow!(
-            "V8 backend is scaffolded but not implemented yet. Use KLUMO_ENGINE=boa for now."
-        ))
+        Err(anyhow!(UNAVAILABLE))
+    }
+
+    /// Heap figures are unsupported until there is an isolate to read them from; then
+    /// `heap_bytes` comes from its `HeapStatistics::used_heap_size`. V8 does not count live
+    /// objects, so `objects` stays `None` even then.
+    fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage::process()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::V8Engine;
+    use klumo_engine::JsEngine;
 
     #[test]
     fn v8_engine_reports_unavailable() {
         let err = V8Engine::new().expect_err("v8 is not implemented yet");
         assert!(err.to_string().contains("not implemented"));
+
+        let err = klumo_runtime_std::install(&mut V8Engine).expect_err("no isolate to install");
+        assert!(format!("{err:#}").contains("not implemented"));
+
+        let usage = V8Engine.memory_usage();
+        assert_eq!((usage.heap_bytes, usage.objects), (None, None));
     }
 }
@@ -0,0 +1,107 @@
+use boa_engine::gc::empty_trace;
+use boa_engine::{Context, Finalize, JsData, JsResult, JsValue, NativeFunction, Trace};
+use klumo_runtime_std::OutputBudget;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Receives each console line as soon as the script prints it, so long-running scripts show
+/// progress. Plain functions and closures taking `&str` are sinks too.
+pub trait ConsoleSink {
+    fn write_line(&self, line: &str);
+}
+
+impl<F: Fn(&str)> ConsoleSink for F {
+    fn write_line(&self, line: &str) {
+        self(line)
+    }
+}
+
+/// Prints each line to stdout; what [`BoaEngine::new`](crate::BoaEngine::new) uses.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdoutSink;
+
+impl ConsoleSink for StdoutSink {
+    fn write_line(&self, line: &str) {
+        println!("{line}");
+    }
+}
+
+/// Keeps every line in memory instead of printing it. Clones share the lines, so a test can
+/// keep one and hand the other to the engine.
+#[derive(Debug, Clone, Default)]
+pub struct CapturedConsole {
+    lines: Rc<RefCell<Vec<String>>>,
+}
+
+impl CapturedConsole {
+    /// The lines written so far, across evaluations.
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.borrow().clone()
+    }
+}
+
+impl ConsoleSink for CapturedConsole {
+    fn write_line(&self, line: &str) {
+        self.lines.borrow_mut().push(line.to_string());
+    }
+}
+
+/// The console of one context, kept as context data: where lines go, the lines of the
+/// running evaluation for `EvalOutput::console_output`, and the output limits.
+#[derive(JsData)]
+pub(crate) struct ConsoleOutput {
+    sink: Box<dyn ConsoleSink>,
+    lines: RefCell<Vec<String>>,
+    budget: RefCell<OutputBudget>,
+}
+
+impl Finalize for ConsoleOutput {}
+
+// SAFETY: holds no garbage-collected values.
+unsafe impl Trace for ConsoleOutput {
+    empty_trace!();
+}
+
+impl ConsoleOutput {
+    pub(crate) fn new(sink: Box<dyn ConsoleSink>) -> Self {
+        Self {
+            sink,
+            lines: RefCell::default(),
+            budget: RefCell::default(),
+        }
+    }
+}
+
+/// The native behind `CONSOLE_WRITE`. Text leaves the engine as UTF-8, with U+FFFD for lone
+/// surrogates, which have no UTF-8 form.
+pub(crate) fn console_write() -> NativeFunction {
+    NativeFunction::from_fn_ptr(|_this, args, ctx| {
+        let line = match args.first() {
+            Some(value) => value.to_string(ctx)?.to_std_string_lossy(),
+            None => String::new(),
+        };
+        if let Some(console) = ctx.get_data::<ConsoleOutput>()
+            && let Some(line) = console.budget.borrow_mut().admit(&line)
+        {
+            console.sink.write_line(&line);
+            console.lines.borrow_mut().push(line);
+        }
+        JsResult::Ok(JsValue::undefined())
+    })
+}
+
+/// Applies `budget` from the next line on.
+pub(crate) fn set_budget(ctx: &Context, budget: OutputBudget) {
+    if let Some(console) = ctx.get_data::<ConsoleOutput>() {
+        *console.budget.borrow_mut() = budget;
+    }
+}
+
+/// The lines of the evaluation that just ended, newline-joined, clearing them and starting
+/// a fresh output budget for the next one.
+pub(crate) fn take_output(ctx: &Context) -> Option<String> {
+    let console = ctx.get_data::<ConsoleOutput>()?;
+    console.budget.borrow_mut().reset();
+    let text = console.lines.take().join("\n");
+    (!text.is_empty()).then_some(text)
+}
@@ -0,0 +1,300 @@
+use boa_engine::builtins::promise::{OperationType, PromiseState};
+use boa_engine::context::HostHooks;
+use boa_engine::gc::custom_trace;
+use boa_engine::object::builtins::JsPromise;
+use boa_engine::{
+    Context, Finalize, JsData, JsError, JsObject, JsResult, JsString, JsValue, NativeFunction,
+    Trace, js_string,
+};
+use std::fmt;
+
+/// What a [`RuntimeDiagnostic`] reports.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    /// Something went wrong around the program, such as console output lost to a replaced
+    /// shim, or source that does not parse.
+    #[default]
+    Warning,
+    /// A promise was rejected and nothing had handled it when the evaluation returned.
+    UnhandledRejection,
+    /// The exception that ended a failed evaluation, carried by [`ScriptError`].
+    Exception,
+}
+
+/// A 1-based line and column in the evaluated source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourcePosition {
+    pub line: u32,
+    pub column: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RuntimeDiagnostic {
+    pub kind: DiagnosticKind,
+    /// The error's `name`, such as `TypeError`; `None` for thrown values that are not errors
+    /// and for klumo's own warnings.
+    pub name: Option<String>,
+    pub message: String,
+    /// Where the parser stopped. Boa 0.20 tracks no positions while code runs, so only
+    /// syntax errors have one.
+    pub position: Option<SourcePosition>,
+    /// The functions that were running when the error was constructed, innermost first, with
+    /// `<main>` for top-level code. Errors Boa raises itself, such as the `TypeError` of
+    /// reading a property of `null`, have none.
+    pub stack: Vec<String>,
+}
+
+impl RuntimeDiagnostic {
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            ..Self::default()
+        }
+    }
+
+    /// A `SyntaxError` from a parser message, whose trailing `at line N, col M` becomes the
+    /// position.
+    pub(crate) fn syntax(message: &str) -> Self {
+        let (message, position) = split_position(message);
+        Self {
+            name: Some("SyntaxError".to_string()),
+            message: message.to_string(),
+            position,
+            ..Self::default()
+        }
+    }
+
+    /// Reads `err`'s name, message and recorded stack.
+    pub(crate) fn from_error(err: &JsError, kind: DiagnosticKind, ctx: &mut Context) -> Self {
+        let (name, message, stack) = match (err.as_native(), err.as_opaque()) {
+            (Some(native), _) => (
+                Some(native.kind.to_string()),
+                native.message().to_string(),
+                None,
+            ),
+            (None, Some(value)) => describe_thrown(value, ctx),
+            (None, None) => (None, err.to_string(), None),
+        };
+        let (message, position) = split_position(&message);
+        Self {
+            kind,
+            name,
+            message: message.to_string(),
+            position,
+            stack: stack.map(|stack| frames_of(&stack)).unwrap_or_default(),
+        }
+    }
+}
+
+/// `Name: message`, followed by ` at line N, col M` when the position is known.
+impl fmt::Display for RuntimeDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) if !self.message.is_empty() => write!(f, "{name}: {}", self.message)?,
+            Some(name) => f.write_str(name)?,
+            None => f.write_str(&self.message)?,
+        }
+        if let Some(SourcePosition { line, column }) = self.position {
+            write!(f, " at line {line}, col {column}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The error `eval_script` and `eval_module` fail with when the program throws or does not
+/// parse: `failed evaluating <source_name>: <diagnostic>`. Callers that want the structured
+/// fields downcast the `anyhow::Error` to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptError {
+    pub source_name: String,
+    pub diagnostic: RuntimeDiagnostic,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "failed evaluating {}: {}",
+            self.source_name, self.diagnostic
+        )
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+/// Name, message and `stack` property of a thrown value. Objects with a string `message`
+/// count as errors; anything else is reported as it displays.
+fn describe_thrown(value: &JsValue, ctx: &mut Context) -> (Option<String>, String, Option<String>) {
+    let text_of = |object: &JsObject, key: &str, ctx: &mut Context| {
+        object
+            .get(JsString::from(key), ctx)
+            .ok()
+            .and_then(|value| value.as_string().map(JsString::to_std_string_lossy))
+    };
+    if let Some(object) = value.as_object()
+        && let Some(message) = text_of(object, "message", ctx)
+    {
+        return (
+            text_of(object, "name", ctx),
+            message,
+            text_of(object, "stack", ctx),
+        );
+    }
+    (None, value.display().to_string(), None)
+}
+
+/// The frames of a `stack` string in the `    at name` layout [`STACK_SETUP`] writes; the
+/// leading `Name: message` line is skipped.
+fn frames_of(stack: &str) -> Vec<String> {
+    stack
+        .lines()
+        .filter_map(|line| line.trim_start().strip_prefix("at "))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Splits Boa's ` at line N, col M` suffix off a message.
+fn split_position(message: &str) -> (&str, Option<SourcePosition>) {
+    let parsed = message.rsplit_once(" at line ").and_then(|(head, tail)| {
+        let (line, column) = tail.split_once(", col ")?;
+        let position = SourcePosition {
+            line: line.parse().ok()?,
+            column: column.parse().ok()?,
+        };
+        Some((head, Some(position)))
+    });
+    parsed.unwrap_or((message, None))
+}
+
+/// Engine-internal global [`STACK_SETUP`] takes the frame recorder from.
+pub(crate) const STACK_FRAMES: &str = "__klumo_stack_frames";
+
+/// Gives every error constructed by script code a V8-style `stack` string. The error
+/// constructors are wrapped in proxies whose traps ask [`STACK_FRAMES`] for the callers,
+/// since Boa records no stack itself.
+pub(crate) const STACK_SETUP: &str = r#"
+(() => {
+  const frames = globalThis.__klumo_stack_frames;
+  delete globalThis.__klumo_stack_frames;
+  const names = ["Error", "TypeError", "RangeError", "ReferenceError", "SyntaxError", "EvalError", "URIError", "AggregateError"];
+  for (const name of names) {
+    const original = globalThis[name];
+    if (typeof original !== "function") continue;
+    const record = (error, callers) => {
+      if (error !== null && typeof error === "object") {
+        const head = error.message ? `${error.name}: ${error.message}` : String(error.name);
+        Object.defineProperty(error, "stack", {
+          value: head + callers,
+          writable: true,
+          configurable: true,
+          enumerable: false,
+        });
+      }
+      return error;
+    };
+    const wrapped = new Proxy(original, {
+      construct: (target, args, newTarget) =>
+        record(Reflect.construct(target, args, newTarget === wrapped ? target : newTarget), frames()),
+      apply: (target, self, args) => record(Reflect.apply(target, self, args), frames()),
+    });
+    Object.defineProperty(original.prototype, "constructor", {
+      value: wrapped,
+      writable: true,
+      configurable: true,
+      enumerable: false,
+    });
+    Object.defineProperty(globalThis, name, {
+      value: wrapped,
+      writable: true,
+      configurable: true,
+      enumerable: false,
+    });
+  }
+})();
+"#;
+
+/// The native behind [`STACK_FRAMES`]: one `\n    at name` line per calling frame. The proxy
+/// trap calling it is the running frame, which Boa leaves out of its trace; the last frame
+/// is the context's own bottom frame and is dropped too.
+pub(crate) fn stack_frames() -> NativeFunction {
+    NativeFunction::from_fn_ptr(|_this, _args, ctx| {
+        let frames: Vec<String> = ctx
+            .stack_trace()
+            .map(
+                |frame| match frame.code_block().name().to_std_string_lossy() {
+                    name if name.is_empty() => "<anonymous>".to_string(),
+                    name => name,
+                },
+            )
+            .collect();
+        let callers = &frames[..frames.len().saturating_sub(1)];
+        let text: String = callers
+            .iter()
+            .map(|name| format!("\n    at {name}"))
+            .collect();
+        Ok(JsString::from(text.as_str()).into())
+    })
+}
+
+/// Promises rejected without a handler, kept on the context until the evaluation ends.
+#[derive(Debug, Default, JsData)]
+struct UnhandledRejections(Vec<JsObject>);
+
+impl Finalize for UnhandledRejections {}
+
+// SAFETY: traces every promise it holds. (`#[derive(Trace)]` expects a direct `boa_gc`
+// dependency.)
+unsafe impl Trace for UnhandledRejections {
+    custom_trace!(this, mark, mark(&this.0));
+}
+
+/// Host hooks of every `BoaEngine` context: tracks unhandled rejections for
+/// [`take_unhandled_rejections`].
+pub(crate) struct KlumoHooks;
+
+impl HostHooks for KlumoHooks {
+    fn promise_rejection_tracker(
+        &self,
+        promise: &JsObject,
+        operation: OperationType,
+        ctx: &mut Context,
+    ) {
+        let mut tracked = ctx
+            .remove_data::<UnhandledRejections>()
+            .map(|boxed| *boxed)
+            .unwrap_or_default();
+        match operation {
+            OperationType::Reject => tracked.0.push(promise.clone()),
+            OperationType::Handle => tracked.0.retain(|known| known != promise),
+        }
+        ctx.insert_data(tracked);
+    }
+}
+
+/// One [`DiagnosticKind::UnhandledRejection`] per promise still rejected without a handler,
+/// clearing the list.
+pub(crate) fn take_unhandled_rejections(ctx: &mut Context) -> Vec<RuntimeDiagnostic> {
+    let tracked = ctx
+        .remove_data::<UnhandledRejections>()
+        .map(|boxed| boxed.0)
+        .unwrap_or_default();
+    tracked
+        .into_iter()
+        .filter_map(|promise| JsPromise::from_object(promise).ok())
+        .filter_map(|promise| match promise.state() {
+            PromiseState::Rejected(reason) => Some(reason),
+            _ => None,
+        })
+        .map(|reason| {
+            let err = JsError::from_opaque(reason);
+            RuntimeDiagnostic::from_error(&err, DiagnosticKind::UnhandledRejection, ctx)
+        })
+        .collect()
+}
+
+/// Registers [`STACK_FRAMES`] and runs [`STACK_SETUP`].
+pub(crate) fn install_stack_capture(ctx: &mut Context) -> JsResult<()> {
+    ctx.register_global_callable(js_string!(STACK_FRAMES), 0, stack_frames())?;
+    ctx.eval(boa_engine::Source::from_bytes(STACK_SETUP))
+        .map(drop)
+}
@@ -0,0 +1,280 @@
+use boa_engine::builtins::promise::PromiseState;
+use boa_engine::object::builtins::{JsDate, JsMap, JsPromise, JsRegExp, JsSet};
+use boa_engine::property::PropertyKey;
+use boa_engine::{
+    Context, JsError, JsObject, JsResult, JsString, JsValue, NativeFunction, js_string,
+};
+use klumo_runtime_std::{
+    INSPECT_DEPTH, INSPECT_MAX_ITEMS, Inspected, format_log_line, render_table,
+};
+
+/// The native behind `CONSOLE_FORMAT`: its arguments as one console line.
+pub(crate) fn console_format() -> NativeFunction {
+    NativeFunction::from_fn_ptr(|_this, args, ctx| {
+        let args = args
+            .iter()
+            .map(|arg| inspect(arg, ctx))
+            .collect::<JsResult<Vec<_>>>()?;
+        Ok(JsString::from(format_log_line(&args).as_str()).into())
+    })
+}
+
+/// The native behind `CONSOLE_TABLE`: `console.table(data, columns)` as a table, or
+/// `undefined` when `data` has no rows.
+pub(crate) fn console_table() -> NativeFunction {
+    NativeFunction::from_fn_ptr(|_this, args, ctx| {
+        let data = inspect(args.first().unwrap_or(&JsValue::undefined()), ctx)?;
+        let columns = match args.get(1).and_then(JsValue::as_object) {
+            Some(columns) if columns.is_array() => {
+                let length = columns.get(js_string!("length"), ctx)?.to_length(ctx)?;
+                let mut names = Vec::new();
+                for index in 0..length {
+                    let name = columns.get(index, ctx)?.to_string(ctx)?;
+                    names.push(name.to_std_string_lossy());
+                }
+                Some(names)
+            }
+            _ => None,
+        };
+        Ok(match render_table(&data, columns.as_deref()) {
+            Some(table) => JsString::from(table.as_str()).into(),
+            None => JsValue::undefined(),
+        })
+    })
+}
+
+/// `value` as console output shows it, expanding objects [`INSPECT_DEPTH`] levels deep.
+pub(crate) fn inspect(value: &JsValue, ctx: &mut Context) -> JsResult<Inspected> {
+    Inspector {
+        parents: Vec::new(),
+    }
+    .value(value, 0, ctx)
+}
+
+/// Walks values into [`Inspected`] trees. `parents` are the objects being expanded, so an
+/// object met again inside itself prints `[Circular]`.
+struct Inspector {
+    parents: Vec<JsObject>,
+}
+
+impl Inspector {
+    fn value(&mut self, value: &JsValue, depth: usize, ctx: &mut Context) -> JsResult<Inspected> {
+        Ok(match value {
+            JsValue::String(text) => Inspected::Text(text.to_std_string_lossy()),
+            JsValue::Object(object) => self.object(object, depth, ctx)?,
+            JsValue::Symbol(symbol) => {
+                Inspected::Literal(symbol.descriptive_string().to_std_string_lossy())
+            }
+            JsValue::BigInt(bigint) => Inspected::Literal(format!("{bigint}n")),
+            JsValue::Rational(number) if *number == 0.0 && number.is_sign_negative() => {
+                Inspected::Literal("-0".to_string())
+            }
+            other => Inspected::Literal(other.display().to_string()),
+        })
+    }
+
+    fn object(
+        &mut self,
+        object: &JsObject,
+        depth: usize,
+        ctx: &mut Context,
+    ) -> JsResult<Inspected> {
+        if object.is_callable() {
+            let name = object.get(js_string!("name"), ctx)?;
+            let name = name
+                .as_string()
+                .map(JsString::to_std_string_lossy)
+                .filter(|name| !name.is_empty());
+            return Ok(Inspected::Function(name));
+        }
+        if JsError::from_opaque(object.clone().into())
+            .try_native(ctx)
+            .is_ok()
+        {
+            return error_text(object, ctx).map(Inspected::Error);
+        }
+        if let Ok(date) = JsDate::from_object(object.clone()) {
+            let text = match date.to_iso_string(ctx) {
+                Ok(iso) => iso.to_string(ctx)?.to_std_string_lossy(),
+                Err(_) => "Invalid Date".to_string(),
+            };
+            return Ok(Inspected::Special(text));
+        }
+        if let Ok(regexp) = JsRegExp::from_object(object.clone()) {
+            return Ok(Inspected::Special(regexp.to_string(ctx)?));
+        }
+        if self.parents.contains(object) {
+            return Ok(Inspected::Circular);
+        }
+        if depth > INSPECT_DEPTH && !is_empty(object, ctx)? {
+            let class = if object.is_array() {
+                Some("Array".to_string())
+            } else {
+                class_name(object, ctx)?
+            };
+            return Ok(Inspected::Elided(
+                class.unwrap_or_else(|| "Object".to_string()),
+            ));
+        }
+        self.parents.push(object.clone());
+        let inspected = self.contents(object, depth + 1, ctx);
+        self.parents.pop();
+        inspected
+    }
+
+    /// The items or properties of a container, inspected at `depth`.
+    fn contents(
+        &mut self,
+        object: &JsObject,
+        depth: usize,
+        ctx: &mut Context,
+    ) -> JsResult<Inspected> {
+        if let Ok(promise) = JsPromise::from_object(object.clone()) {
+            let (result, rejected) = match promise.state() {
+                PromiseState::Pending => (None, false),
+                PromiseState::Fulfilled(value) => (Some(value), false),
+                PromiseState::Rejected(reason) => (Some(reason), true),
+            };
+            let result = match result {
+                Some(value) => Some(Box::new(self.value(&value, depth, ctx)?)),
+                None => None,
+            };
+            return Ok(Inspected::Promise { result, rejected });
+        }
+        if object.is_array() {
+            let length = object.get(js_string!("length"), ctx)?.to_length(ctx)?;
+            let shown = length.min(INSPECT_MAX_ITEMS as u64);
+            let mut items = Vec::new();
+            for index in 0..shown {
+                let item = object.get(index, ctx)?;
+                items.push(self.value(&item, depth, ctx)?);
+            }
+            let more = (length - shown) as usize;
+            return Ok(Inspected::Array { items, more });
+        }
+        if let Ok(map) = JsMap::from_object(object.clone()) {
+            let size = map.get_size(ctx)?.to_length(ctx)? as usize;
+            let entries_iter = map.entries(ctx)?;
+            let mut entries = Vec::new();
+            while entries.len() < INSPECT_MAX_ITEMS {
+                let Some(entry) = next_item(entries_iter.next(ctx)?, ctx)? else {
+                    break;
+                };
+                let Some(pair) = entry.as_object() else {
+                    break;
+                };
+                let key = pair.get(0, ctx)?;
+                let value = pair.get(1, ctx)?;
+                entries.push((
+                    self.value(&key, depth, ctx)?,
+                    self.value(&value, depth, ctx)?,
+                ));
+            }
+            let more = size.saturating_sub(entries.len());
+            return Ok(Inspected::Map { entries, more });
+        }
+        if let Ok(set) = JsSet::from_object(object.clone()) {
+            let size = set.size()?;
+            let values = set.values(ctx)?;
+            let mut items = Vec::new();
+            while items.len() < INSPECT_MAX_ITEMS {
+                let Some(item) = next_item(values.next(ctx)?, ctx)? else {
+                    break;
+                };
+                items.push(self.value(&item, depth, ctx)?);
+            }
+            let more = size.saturating_sub(items.len());
+            return Ok(Inspected::Set { items, more });
+        }
+
+        let class = match object.prototype() {
+            None => Some("[Object: null prototype]".to_string()),
+            Some(_) => class_name(object, ctx)?,
+        };
+        let mut entries = Vec::new();
+        for key in object.own_property_keys(ctx)? {
+            let name = match &key {
+                PropertyKey::String(name) => name.to_std_string_lossy(),
+                PropertyKey::Index(index) => index.get().to_string(),
+                PropertyKey::Symbol(_) => continue,
+            };
+            // Proxies and other exotic objects keep no ordinary property storage; their
+            // properties are left out rather than running traps or getters.
+            let Some(descriptor) = object.borrow().properties().get(&key) else {
+                continue;
+            };
+            if !descriptor.expect_enumerable() {
+                continue;
+            }
+            let value = if descriptor.is_accessor_descriptor() {
+                let getter = descriptor.get().is_some_and(|get| !get.is_undefined());
+                let setter = descriptor.set().is_some_and(|set| !set.is_undefined());
+                Inspected::Special(
+                    match (getter, setter) {
+                        (true, true) => "[Getter/Setter]",
+                        (true, false) => "[Getter]",
+                        _ => "[Setter]",
+                    }
+                    .to_string(),
+                )
+            } else {
+                let value = descriptor.value().cloned().unwrap_or_default();
+                self.value(&value, depth, ctx)?
+            };
+            entries.push((name, value));
+        }
+        Ok(Inspected::Object { class, entries })
+    }
+}
+
+/// Whether `object` is an array or plain object with nothing to list, which prints as `[]`
+/// or `{}` at any depth.
+fn is_empty(object: &JsObject, ctx: &mut Context) -> JsResult<bool> {
+    if object.is_array() {
+        return Ok(object.get(js_string!("length"), ctx)?.to_length(ctx)? == 0);
+    }
+    let collection = JsMap::from_object(object.clone()).is_ok()
+        || JsSet::from_object(object.clone()).is_ok()
+        || JsPromise::from_object(object.clone()).is_ok();
+    Ok(!collection && object.own_property_keys(ctx)?.is_empty())
+}
+
+/// The `value` of an iterator result, or `None` once the iterator is done.
+fn next_item(result: JsValue, ctx: &mut Context) -> JsResult<Option<JsValue>> {
+    let Some(result) = result.as_object() else {
+        return Ok(None);
+    };
+    if result.get(js_string!("done"), ctx)?.to_boolean() {
+        return Ok(None);
+    }
+    result.get(js_string!("value"), ctx).map(Some)
+}
+
+/// The name of `object`'s constructor, unless it is plain `Object`.
+fn class_name(object: &JsObject, ctx: &mut Context) -> JsResult<Option<String>> {
+    let constructor = object.get(js_string!("constructor"), ctx)?;
+    let Some(constructor) = constructor.as_object() else {
+        return Ok(None);
+    };
+    let name = constructor.get(js_string!("name"), ctx)?;
+    Ok(name
+        .as_string()
+        .map(JsString::to_std_string_lossy)
+        .filter(|name| !name.is_empty() && name != "Object"))
+}
+
+/// An error's `stack`, or `Name: message` when it has none.
+fn error_text(error: &JsObject, ctx: &mut Context) -> JsResult<String> {
+    let stack = error.get(js_string!("stack"), ctx)?;
+    if let Some(stack) = stack.as_string() {
+        return Ok(stack.to_std_string_lossy());
+    }
+    let name = error.get(js_string!("name"), ctx)?.to_string(ctx)?;
+    let message = error.get(js_string!("message"), ctx)?.to_string(ctx)?;
+    Ok(
+        match (name.to_std_string_lossy(), message.to_std_string_lossy()) {
+            (name, message) if message.is_empty() => name,
+            (name, message) => format!("{name}: {message}"),
+        },
+    )
+}
@@ -1,113 +1,500 @@
-use anyhow::{Result, anyhow};
-use boa_engine::{Context, Source};
+mod console;
+mod diagnostics;
+mod inspect;
+mod memory;
+mod modules;
+mod timers;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct RuntimeDiagnostic {
-    pub message: String,
-}
+pub use console::{CapturedConsole, ConsoleSink, StdoutSink};
+pub use diagnostics::{DiagnosticKind, RuntimeDiagnostic, ScriptError, SourcePosition};
+pub use klumo_runtime_std::{HostFunction, OUTPUT_LIMIT_HINT};
+pub use memory::MemoryUsage;
+pub use modules::ImportCheck;
+
+use anyhow::{Context as _, Result, anyhow};
+use boa_engine::ast::scope::Scope;
+use boa_engine::builtins::promise::PromiseState;
+use boa_engine::interner::Interner;
+use boa_engine::object::builtins::JsPromise;
+use boa_engine::parser::Parser;
+use boa_engine::{
+    Context, JsError, JsNativeError, JsResult, JsString, JsValue, Module, NativeFunction, Source,
+};
+use klumo_runtime_std::{
+    CLEAR_TIMERS, CONSOLE_FORMAT, CONSOLE_TABLE, CONSOLE_WRITE, OutputBudget, RUN_TIMER,
+    StdBackend, TIMER_CANCEL, TIMER_SCHEDULE,
+};
+use modules::FileModuleLoader;
+use std::path::Path;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct EvalOutput {
     pub value: Option<String>,
+    /// Console lines printed during evaluation, newline-joined.
+    pub console_output: Option<String>,
     pub diagnostics: Vec<RuntimeDiagnostic>,
 }
 
+/// Caps on console output, counted in UTF-16 code units as JavaScript measures strings.
+/// `None` leaves a limit off. The total budget covers one evaluation, so a REPL session gets a
+/// fresh budget for every line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputLimits {
+    /// Longest single console entry; longer entries are cut with a marker.
+    pub max_entry_chars: Option<usize>,
+    /// Most output one evaluation may print; once reached, a marker is printed and further
+    /// console output is dropped.
+    pub max_total_chars: Option<usize>,
+}
+
+impl OutputLimits {
+    pub const DEFAULT_MAX_ENTRY_CHARS: usize = 16 * 1024;
+    pub const DEFAULT_MAX_TOTAL_CHARS: usize = 1024 * 1024;
+
+    /// The built-in limits: 16 KiB per entry and 1 MiB per evaluation.
+    pub fn standard() -> Self {
+        Self {
+            max_entry_chars: Some(Self::DEFAULT_MAX_ENTRY_CHARS),
+            max_total_chars: Some(Self::DEFAULT_MAX_TOTAL_CHARS),
+        }
+    }
+}
+
+/// How a source is evaluated: as a classic script or as an ES module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptGoal {
+    Script,
+    /// Uses `import`/`export` or top-level `await`, which only parse in a module.
+    Module,
+}
+
+/// `Module` when `source` only parses as a module; anything that parses as a script, or
+/// parses as neither, stays a script so its errors are reported as before.
+pub fn detect_goal(source: &str) -> ScriptGoal {
+    if parse_script(source).is_ok() || parse_module(source).is_err() {
+        ScriptGoal::Script
+    } else {
+        ScriptGoal::Module
+    }
+}
+
+/// The goal for `source` loaded as `source_name`: `.mjs` files are always modules and `.cjs`
+/// files always scripts, as in Node; anything else is left to [`detect_goal`].
+pub fn goal_for(source: &str, source_name: &str) -> ScriptGoal {
+    match Path::new(source_name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+    {
+        Some("mjs") => ScriptGoal::Module,
+        Some("cjs") => ScriptGoal::Script,
+        _ => detect_goal(source),
+    }
+}
+
+fn parse_script(source: &str) -> std::result::Result<(), String> {
+    let mut interner = Interner::default();
+    let mut parser = Parser::new(Source::from_bytes(source));
+    parser
+        .parse_script(&Scope::new_global(), &mut interner)
+        .map(drop)
+        .map_err(|err| err.to_string())
+}
+
+fn parse_module(source: &str) -> std::result::Result<(), String> {
+    let mut interner = Interner::default();
+    let mut parser = Parser::new(Source::from_bytes(source));
+    parser
+        .parse_module(&Scope::new_global(), &mut interner)
+        .map(drop)
+        .map_err(|err| err.to_string())
+}
+
+/// Parses `source` without executing it, as a classic script or, failing that, as a module.
+///
+/// Returns one diagnostic per parse failure; an empty list means the source parsed cleanly.
+pub fn check_syntax(source: &str) -> Vec<RuntimeDiagnostic> {
+    match parse_script(source) {
+        Ok(()) => Vec::new(),
+        Err(_) if parse_module(source).is_ok() => Vec::new(),
+        Err(err) => vec![RuntimeDiagnostic::syntax(&err)],
+    }
+}
+
 pub trait JsEngine {
     fn eval_script(&mut self, source: &str, source_name: &str) -> Result<EvalOutput>;
+
+    /// Exposes `function` to scripts as the global function `name`.
+    fn register_host_function(&mut self, name: &str, _function: HostFunction) -> Result<()> {
+        Err(anyhow!(
+            "the selected JavaScript engine cannot expose host functions such as {name}"
+        ))
+    }
+
+    /// Whether `eval_module` is implemented.
+    fn supports_modules(&self) -> bool {
+        false
+    }
+
+    /// Current memory figures; engines without heap accounting report the process only.
+    fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage::process()
+    }
+
+    /// Runs a full garbage collection now, e.g. between requests of a long-lived daemon.
+    /// Engines that cannot be asked to collect ignore it.
+    fn collect_garbage(&mut self) {}
+
+    /// Truncates console output past `limits` from the next evaluation on. Engines without
+    /// the console shim print nothing themselves and ignore it.
+    fn set_output_limits(&mut self, _limits: OutputLimits) -> Result<()> {
+        Ok(())
+    }
+
+    /// How long an evaluation keeps waiting for timers once its script has run. `None`
+    /// waits until none are left, as Node does before it exits, so an interval that is never
+    /// cleared keeps it running. With `Some(wait)`, timers falling due later are kept and run
+    /// by a later evaluation once due; `Some(Duration::ZERO)` only runs those already due.
+    /// Engines without timers ignore it.
+    fn set_timer_wait(&mut self, _wait: Option<Duration>) {}
+
+    /// How many timers the last evaluation left pending, i.e. those `set_timer_wait` did not
+    /// wait for.
+    fn pending_timers(&self) -> usize {
+        0
+    }
+
+    /// Drops every pending timer, so a later evaluation does not run them.
+    fn clear_timers(&mut self) {}
+
+    /// Runs `check` on every file a module imports before the file is parsed; an error
+    /// refuses the import. Engines without module support ignore it.
+    fn set_import_check(&mut self, _check: ImportCheck) {}
+
+    /// Evaluates `source` as an ES module. Modules have no completion value.
+    fn eval_module(&mut self, _source: &str, source_name: &str) -> Result<EvalOutput> {
+        Err(modules_unsupported(source_name))
+    }
+
+    /// Evaluates `source` with the goal `goal_for` picks for it. A module on an engine
+    /// without module support fails with an explanation instead of a `SyntaxError`.
+    fn evaluate(&mut self, source: &str, source_name: &str) -> Result<EvalOutput> {
+        self.evaluate_as(goal_for(source, source_name), source, source_name)
+    }
+
+    /// Evaluates `source` with `goal`, e.g. a module for `klumo run --module` even when it
+    /// would also parse as a script.
+    fn evaluate_as(
+        &mut self,
+        goal: ScriptGoal,
+        source: &str,
+        source_name: &str,
+    ) -> Result<EvalOutput> {
+        match goal {
+            ScriptGoal::Script => self.eval_script(source, source_name),
+            ScriptGoal::Module if self.supports_modules() => self.eval_module(source, source_name),
+            ScriptGoal::Module => Err(modules_unsupported(source_name)),
+        }
+    }
+}
+
+fn modules_unsupported(source_name: &str) -> anyhow::Error {
+    anyhow!(
+        "{source_name} is an ES module (it uses import/export or top-level await), but the selected JavaScript engine only runs classic scripts; use KLUMO_ENGINE=boa or rewrite it as a script"
+    )
 }
 
 pub struct BoaEngine {
     ctx: Context,
+    modules: Rc<FileModuleLoader>,
+    timer_wait: Option<Duration>,
 }
 
 impl BoaEngine {
-    pub fn new() -> Self {
+    /// Creates an engine with the standard library (`klumo-runtime-std`) installed, printing
+    /// console lines to stdout as they are logged. Fails when it cannot be installed, since
+    /// console output would otherwise be dropped silently.
+    pub fn new() -> Result<Self> {
+        Self::with_console_sink(StdoutSink)
+    }
+
+    /// Like [`BoaEngine::new`], but console lines go to `sink` instead of stdout.
+    pub fn with_console_sink(sink: impl ConsoleSink + 'static) -> Result<Self> {
+        let modules = Rc::new(FileModuleLoader::default());
+        let mut ctx = Context::builder()
+            .module_loader(modules.clone())
+            .host_hooks(&diagnostics::KlumoHooks)
+            .build()
+            .map_err(|err| anyhow!("failed to initialize the Boa engine: {err}"))?;
+        ctx.insert_data(console::ConsoleOutput::new(Box::new(sink)));
+        ctx.insert_data(timers::TimerQueue::default());
         let mut engine = Self {
-            ctx: Context::default(),
+            ctx,
+            modules,
+            timer_wait: None,
         };
-        engine.install_console_shim();
-        engine
+        klumo_runtime_std::install(&mut engine).context("failed to initialize the Boa engine")?;
+        diagnostics::install_stack_capture(&mut engine.ctx)
+            .map_err(|err| anyhow!("failed to initialize the Boa engine: {err}"))?;
+        Ok(engine)
     }
 
-    fn install_console_shim(&mut self) {
-        // Provide minimal console support for translated code.
-        let _ = self.ctx.eval(Source::from_bytes(
-            r#"
-globalThis.__klumo_console_logs = [];
-globalThis.console = globalThis.console || {};
-globalThis.console.log = (...args) => {
-  globalThis.__klumo_console_logs.push(args.map((v) => String(v)).join(" "));
-};
-globalThis.console.error = (...args) => {
-  globalThis.__klumo_console_logs.push(args.map((v) => String(v)).join(" "));
-};
-globalThis.__klumo_flush_console = () => {
-  const out = globalThis.__klumo_console_logs.join("\n");
-  globalThis.__klumo_console_logs = [];
-  return out;
-};
-"#,
-        ));
+    /// Caps how many iterations any single loop may run before it throws. Boa cannot be
+    /// interrupted from outside, so this is what eventually stops a runaway script whose
+    /// caller has stopped waiting for it.
+    pub fn set_loop_iteration_limit(&mut self, limit: u64) {
+        self.ctx
+            .runtime_limits_mut()
+            .set_loop_iteration_limit(limit);
     }
 
-    fn flush_console_logs(&mut self) {
-        let flushed = self.ctx.eval(Source::from_bytes(
-            "globalThis.__klumo_flush_console?.() ?? ''",
-        ));
-        let Ok(value) = flushed else {
-            return;
-        };
-        let Ok(text) = value.to_string(&mut self.ctx) else {
-            return;
-        };
-        let rendered = text.to_std_string_escaped();
-        if !rendered.is_empty() {
-            println!("{rendered}");
+    /// The error of an evaluation that threw `err`, as a [`ScriptError`]. Its console lines
+    /// were already printed and the rejections and timers it left are moot; all are dropped
+    /// so the next evaluation does not report or run them as its own.
+    fn failure(&mut self, err: &JsError, source_name: &str) -> anyhow::Error {
+        let diagnostic =
+            RuntimeDiagnostic::from_error(err, DiagnosticKind::Exception, &mut self.ctx);
+        console::take_output(&self.ctx);
+        diagnostics::take_unhandled_rejections(&mut self.ctx);
+        self.clear_timers();
+        ScriptError {
+            source_name: source_name.to_string(),
+            diagnostic,
+        }
+        .into()
+    }
+
+    /// Runs queued jobs, then each timer as it falls due, sleeping until then, until none
+    /// are left or the next one falls due after `timer_wait`. Stops early once `module` is
+    /// rejected, since that ends the evaluation.
+    fn run_event_loop(&mut self, module: Option<&JsPromise>) -> JsResult<()> {
+        let deadline = self.timer_wait.map(|wait| Instant::now() + wait);
+        loop {
+            self.ctx.run_jobs();
+            if module.is_some_and(|promise| matches!(promise.state(), PromiseState::Rejected(_))) {
+                return Ok(());
+            }
+            let Some((due, id)) = timers::next_due(&self.ctx, deadline) else {
+                return Ok(());
+            };
+            if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                thread::sleep(wait);
+            }
+            let run = self
+                .ctx
+                .global_object()
+                .get(JsString::from(RUN_TIMER), &mut self.ctx)?;
+            let Some(run) = run.as_callable() else {
+                return Err(JsNativeError::typ()
+                    .with_message(format!("timers stopped working: {RUN_TIMER} was replaced"))
+                    .into());
+            };
+            run.call(
+                &JsValue::undefined(),
+                &[JsValue::from(id as f64)],
+                &mut self.ctx,
+            )?;
         }
     }
+
+    /// The console output of a successful evaluation, which was already printed as it was
+    /// logged, and its diagnostics: promises rejected without a handler.
+    fn finish_evaluation(&mut self) -> (Option<String>, Vec<RuntimeDiagnostic>) {
+        let console_output = console::take_output(&self.ctx);
+        let diagnostics = diagnostics::take_unhandled_rejections(&mut self.ctx);
+        (console_output, diagnostics)
+    }
 }
 
-impl Default for BoaEngine {
-    fn default() -> Self {
-        Self::new()
+/// Boa's installer for `klumo-runtime-std`.
+impl StdBackend for BoaEngine {
+    fn register_shared(&mut self, name: &str, _arity: usize, function: HostFunction) -> Result<()> {
+        self.register_host_function(name, function)
+    }
+
+    fn register_engine_bound(&mut self, name: &str, arity: usize) -> Result<()> {
+        let native = match name {
+            CONSOLE_WRITE => console::console_write(),
+            CONSOLE_FORMAT => inspect::console_format(),
+            CONSOLE_TABLE => inspect::console_table(),
+            TIMER_SCHEDULE => timers::timer_schedule(),
+            TIMER_CANCEL => timers::timer_cancel(),
+            _ => return Err(anyhow!("the Boa engine does not implement {name}")),
+        };
+        self.ctx
+            .register_global_callable(JsString::from(name), arity, native)
+            .map_err(|err| anyhow!("failed registering {name}: {err}"))
+    }
+
+    fn run_setup(&mut self, source: &str, module: &str) -> Result<()> {
+        self.ctx
+            .eval(Source::from_bytes(source))
+            .map(drop)
+            .map_err(|err| anyhow!("failed evaluating {module} setup: {err}"))
+    }
+
+    fn eval_bool(&mut self, expression: &str) -> Result<bool> {
+        self.ctx
+            .eval(Source::from_bytes(expression))
+            .map(|value| value.to_boolean())
+            .map_err(|err| anyhow!("{err}"))
     }
 }
 
 impl JsEngine for BoaEngine {
+    fn register_host_function(&mut self, name: &str, function: HostFunction) -> Result<()> {
+        let native = NativeFunction::from_copy_closure(
+            move |_this: &JsValue, args: &[JsValue], ctx: &mut Context| -> JsResult<JsValue> {
+                let args = args
+                    .iter()
+                    .map(|arg| Ok(arg.to_string(ctx)?.to_std_string_lossy()))
+                    .collect::<JsResult<Vec<_>>>()?;
+                match function(&args) {
+                    Ok(Some(text)) => Ok(JsString::from(text.as_str()).into()),
+                    Ok(None) => Ok(JsValue::undefined()),
+                    Err(message) => Err(JsNativeError::error().with_message(message).into()),
+                }
+            },
+        );
+        self.ctx
+            .register_global_callable(JsString::from(name), 0, native)
+            .map_err(|err| anyhow!("failed registering host function {name}: {err}"))
+    }
+
+    fn set_output_limits(&mut self, limits: OutputLimits) -> Result<()> {
+        let budget = OutputBudget::new(
+            limits.max_entry_chars.unwrap_or(0),
+            limits.max_total_chars.unwrap_or(0),
+        );
+        console::set_budget(&self.ctx, budget);
+        Ok(())
+    }
+
+    fn set_timer_wait(&mut self, wait: Option<Duration>) {
+        self.timer_wait = wait;
+    }
+
+    fn pending_timers(&self) -> usize {
+        timers::pending(&self.ctx)
+    }
+
+    fn set_import_check(&mut self, check: ImportCheck) {
+        self.modules.set_check(check);
+    }
+
+    fn clear_timers(&mut self) {
+        timers::clear(&self.ctx);
+        let clear = self
+            .ctx
+            .global_object()
+            .get(JsString::from(CLEAR_TIMERS), &mut self.ctx);
+        if let Some(clear) = clear.ok().and_then(|clear| clear.as_callable().cloned()) {
+            // A script that replaced the global only keeps its own callbacks alive; their
+            // due times are already gone.
+            let _ = clear.call(&JsValue::undefined(), &[], &mut self.ctx);
+        }
+    }
+
     fn eval_script(&mut self, source: &str, source_name: &str) -> Result<EvalOutput> {
-        let result = self
+        let result = match self
             .ctx
             .eval(Source::from_bytes(source))
-            .map_err(|err| anyhow!("failed evaluating {source_name}: {err}"))?;
+            .and_then(|result| self.run_event_loop(None).map(|()| result))
+        {
+            Ok(result) => result,
+            Err(err) => return Err(self.failure(&err, source_name)),
+        };
 
-        self.flush_console_logs();
+        let (console_output, diagnostics) = self.finish_evaluation();
 
         if result.is_undefined() {
             return Ok(EvalOutput {
                 value: None,
-                diagnostics: Vec::new(),
+                console_output,
+                diagnostics,
             });
         }
 
         let rendered = result
             .to_string(&mut self.ctx)
             .map_err(|err| anyhow!("failed converting JS value to string: {err}"))?
-            .to_std_string_escaped();
+            .to_std_string_lossy();
 
         Ok(EvalOutput {
             value: Some(rendered),
-            diagnostics: Vec::new(),
+            console_output,
+            diagnostics,
+        })
+    }
+
+    fn supports_modules(&self) -> bool {
+        true
+    }
+
+    /// Collects the heap of the calling thread, which is where Boa keeps this context's
+    /// objects.
+    fn collect_garbage(&mut self) {
+        boa_engine::gc::force_collect();
+    }
+
+    /// Runs the module and its top-level `await`s to completion. When `source_name` is a file,
+    /// relative imports load the files next to it; see `FileModuleLoader`.
+    fn eval_module(&mut self, source: &str, source_name: &str) -> Result<EvalOutput> {
+        let path = Path::new(source_name)
+            .is_file()
+            .then(|| std::fs::canonicalize(source_name).ok())
+            .flatten();
+        let parsed = match &path {
+            Some(path) => Module::parse(
+                Source::from_bytes(source).with_path(path),
+                None,
+                &mut self.ctx,
+            ),
+            None => Module::parse(Source::from_bytes(source), None, &mut self.ctx),
+        };
+        if let (Some(path), Ok(module)) = (path, &parsed) {
+            self.modules.insert(path, module.clone());
+        }
+        let evaluated = parsed
+            .map(|module| module.load_link_evaluate(&mut self.ctx))
+            .and_then(|promise| {
+                self.run_event_loop(Some(&promise))?;
+                match promise.state() {
+                    PromiseState::Fulfilled(_) => Ok(()),
+                    PromiseState::Rejected(reason) => Err(JsError::from_opaque(reason)),
+                    PromiseState::Pending => Err(JsNativeError::error()
+                        .with_message("top-level await never settled")
+                        .into()),
+                }
+            });
+        if let Err(err) = evaluated {
+            return Err(self.failure(&err, source_name));
+        }
+
+        let (console_output, diagnostics) = self.finish_evaluation();
+        Ok(EvalOutput {
+            value: None,
+            console_output,
+            diagnostics,
         })
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{BoaEngine, JsEngine};
+    use super::{
+        BoaEngine, CapturedConsole, DiagnosticKind, EvalOutput, JsEngine, MemoryUsage,
+        OUTPUT_LIMIT_HINT, OutputLimits, RuntimeDiagnostic, ScriptError, ScriptGoal,
+        SourcePosition, check_syntax, detect_goal, goal_for,
+    };
+    use std::fs;
+    use std::time::{Duration, Instant};
 
     #[test]
     fn evaluates_expression() {
-        let mut engine = BoaEngine::new();
+        let mut engine = BoaEngine::new().expect("engine should initialize");
         let output = engine
             .eval_script("1 + 2", "<test>")
             .expect("eval should pass");
@@ -116,25 +503,358 @@ mod tests {
 
     #[test]
     fn suppresses_undefined() {
-        let mut engine = BoaEngine::new();
+        let mut engine = BoaEngine::new().expect("engine should initialize");
         let output = engine
             .eval_script("const a = 1;", "<test>")
             .expect("eval should pass");
         assert_eq!(output.value, None);
     }
 
+    #[test]
+    fn renders_non_ascii_text_as_utf8() {
+        let mut engine = BoaEngine::new().expect("engine should initialize");
+        let output = engine
+            .eval_script(
+                "console.log('héllo ✓', '日本'); '🦀 ' + 'a\\uD800b'",
+                "<test>",
+            )
+            .expect("eval should pass");
+        assert_eq!(output.console_output.as_deref(), Some("héllo ✓ 日本"));
+        assert_eq!(output.value.as_deref(), Some("🦀 a\u{FFFD}b"));
+    }
+
+    #[test]
+    fn captures_console_output() {
+        let mut engine = BoaEngine::new().expect("engine should initialize");
+        let output = engine
+            .eval_script("console.log('a', 1); console.error('b');", "<test>")
+            .expect("eval should pass");
+        assert_eq!(output.console_output.as_deref(), Some("a 1\nb"));
+    }
+
+    #[test]
+    fn console_inspects_values_instead_of_stringifying_them() {
+        let mut engine = BoaEngine::new().expect("engine should initialize");
+        let output = engine
+            .eval_script(
+                r#"
+class Point { constructor() { this.x = 1; } }
+const cyclic = { name: 'loop' };
+cyclic.self = cyclic;
+console.log({ a: 1, b: 'two', c: [1, { d: { e: { f: 1 } } }] });
+console.log(new Point(), [undefined, null, -0, 10n], cyclic);
+console.log(new Map([['k', 1]]), new Set([1]), function named() {}, () => {});
+console.log(Promise.resolve(3), Symbol('s'), /x/g, new Error('bad').message);
+console.log('%s is %d years', 'Ada', 36.5, 'extra');
+"#,
+                "<test>",
+            )
+            .expect("eval should pass");
+        assert_eq!(
+            output.console_output.as_deref(),
+            Some(
+                "{ a: 1, b: 'two', c: [ 1, { d: [Object] } ] }\n\
+                 Point { x: 1 } [ undefined, null, -0, 10n ] { name: 'loop', self: [Circular] }\n\
+                 Map(1) { 'k' => 1 } Set(1) { 1 } [Function: named] [Function (anonymous)]\n\
+                 Promise { 3 } Symbol(s) /x/g bad\n\
+                 Ada is 36.5 years extra"
+            )
+        );
+    }
+
+    #[test]
+    fn console_groups_counts_tables_and_asserts() {
+        let mut engine = BoaEngine::new().expect("engine should initialize");
+        let output = engine
+            .eval_script(
+                r#"
+console.group('outer');
+console.info('inside');
+console.count(); console.count();
+console.groupEnd();
+console.warn('back');
+console.assert(1 === 1, 'not printed');
+console.assert(false, 'expected %d', 2);
+console.timeEnd('never');
+console.table([{ a: 1, b: 'x' }, { a: 2 }]);
+console.table(5);
+"#,
+                "<test>",
+            )
+            .expect("eval should pass");
+        assert_eq!(
+            output.console_output.as_deref(),
+            Some(
+                "outer\n  inside\n  default: 1\n  default: 2\nback\n\
+                 Assertion failed: expected 2\n\
+                 Warning: No such label 'never' for console.timeEnd()\n\
+                 ┌─────────┬───┬─────┐\n\
+                 │ (index) │ a │ b   │\n\
+                 ├─────────┼───┼─────┤\n\
+                 │ 0       │ 1 │ 'x' │\n\
+                 │ 1       │ 2 │     │\n\
+                 └─────────┴───┴─────┘\n\
+                 5"
+            )
+        );
+    }
+
+    #[test]
+    fn console_lines_stream_before_the_script_finishes() {
+        let console = CapturedConsole::default();
+        let mut engine =
+            BoaEngine::with_console_sink(console.clone()).expect("engine should initialize");
+        engine
+            .eval_script(
+                "console.log('step', 1); console.error('step', 2); throw new Error('boom')",
+                "<test>",
+            )
+            .expect_err("script should fail");
+        // The lines reached the sink even though evaluation never returned normally.
+        assert_eq!(console.lines(), ["step 1", "step 2"]);
+
+        let next = engine.eval_script("1", "<test>").expect("eval should pass");
+        assert_eq!(next.console_output, None);
+    }
+
+    #[test]
+    fn output_limits_truncate_entries_and_stop_at_the_total() {
+        let mut engine = BoaEngine::new().expect("engine should initialize");
+        engine
+            .set_output_limits(OutputLimits {
+                max_entry_chars: Some(4),
+                max_total_chars: Some(10),
+            })
+            .expect("limits should apply");
+        let output = engine
+            .eval_script(
+                "console.log('abcdefgh'); console.log('1234'); console.log('dropped')",
+                "<test>",
+            )
+            .expect("eval should pass");
+        let console = output.console_output.expect("console output");
+        let lines: Vec<&str> = console.lines().collect();
+        assert_eq!(lines.len(), 3, "{console}");
+        assert!(lines[0].starts_with("abcd… [truncated 4 characters;"));
+        assert!(lines[0].ends_with(&format!("{OUTPUT_LIMIT_HINT}]")));
+        assert_eq!(lines[1], "1234");
+        assert!(lines[2].starts_with("dr… [output limit of 10 characters reached"));
+
+        // The total budget is per evaluation.
+        let next = engine
+            .eval_script("console.log('ok')", "<test>")
+            .expect("eval should pass");
+        assert_eq!(next.console_output.as_deref(), Some("ok"));
+    }
+
     #[test]
     fn maps_runtime_errors() {
-        let mut engine = BoaEngine::new();
+        let mut engine = BoaEngine::new().expect("engine should initialize");
         let err = engine
             .eval_script("throw new Error('boom')", "sample.js")
             .expect_err("expected eval error");
         assert!(err.to_string().contains("failed evaluating sample.js"));
     }
 
+    fn thrown(engine: &mut BoaEngine, source: &str) -> RuntimeDiagnostic {
+        let err = engine
+            .eval_script(source, "sample.js")
+            .expect_err("the script should throw");
+        err.downcast_ref::<ScriptError>()
+            .expect("thrown errors are script errors")
+            .diagnostic
+            .clone()
+    }
+
+    #[test]
+    fn thrown_errors_carry_name_message_stack_and_position() {
+        let mut engine =
+            BoaEngine::with_console_sink(CapturedConsole::default()).expect("engine should start");
+        let diagnostic = thrown(
+            &mut engine,
+            "function outer() { inner(); }\nfunction inner() { throw new RangeError('too far'); }\nouter();",
+        );
+        assert_eq!(diagnostic.kind, DiagnosticKind::Exception);
+        assert_eq!(diagnostic.name.as_deref(), Some("RangeError"));
+        assert_eq!(diagnostic.message, "too far");
+        assert_eq!(diagnostic.stack, ["inner", "outer", "<main>"]);
+
+        let diagnostic = thrown(
+            &mut engine,
+            "class AppError extends Error {}\nconst fail = () => { throw new AppError('custom'); };\nfail();",
+        );
+        assert_eq!(diagnostic.message, "custom");
+        assert_eq!(diagnostic.stack, ["AppError", "fail", "<main>"]);
+
+        let diagnostic = thrown(&mut engine, "\n\nlet = ;");
+        assert_eq!(diagnostic.name.as_deref(), Some("SyntaxError"));
+        assert_eq!(
+            diagnostic.position,
+            Some(SourcePosition { line: 3, column: 7 })
+        );
+
+        // Errors Boa raises itself have no recorded stack; plain values have no name.
+        let diagnostic = thrown(&mut engine, "null.x");
+        assert_eq!(diagnostic.name.as_deref(), Some("TypeError"));
+        assert!(diagnostic.stack.is_empty());
+        assert_eq!(thrown(&mut engine, "throw 'plain'").name, None);
+
+        // The wrapped constructors still behave like the originals.
+        let output = engine
+            .eval_script(
+                "const e = new TypeError('t');\n[e instanceof TypeError, e instanceof Error, e.constructor === TypeError, Error('x').stack.split('\\n')[0]].join()",
+                "sample.js",
+            )
+            .expect("eval should pass");
+        assert_eq!(output.value.as_deref(), Some("true,true,true,Error: x"));
+    }
+
+    #[test]
+    fn unhandled_rejections_are_reported_as_diagnostics() {
+        let mut engine =
+            BoaEngine::with_console_sink(CapturedConsole::default()).expect("engine should start");
+        let output = engine
+            .eval_script(
+                "function load() { Promise.reject(new Error('lost')); }\nload();\nPromise.reject(new Error('caught')).catch(() => {});\n1",
+                "sample.js",
+            )
+            .expect("eval should pass");
+        assert_eq!(output.value.as_deref(), Some("1"));
+        assert_eq!(output.diagnostics.len(), 1);
+        let diagnostic = &output.diagnostics[0];
+        assert_eq!(diagnostic.kind, DiagnosticKind::UnhandledRejection);
+        assert_eq!(diagnostic.to_string(), "Error: lost");
+        assert_eq!(diagnostic.stack, ["load", "<main>"]);
+
+        let output = engine
+            .eval_module(
+                "async function work() { await null; throw new Error('async'); }\nwork();\nexport {};",
+                "main.mjs",
+            )
+            .expect("module should run");
+        assert_eq!(output.diagnostics.len(), 1);
+        assert_eq!(output.diagnostics[0].message, "async");
+
+        // A failed evaluation takes its rejections with it.
+        thrown(
+            &mut engine,
+            "Promise.reject(new Error('moot'));\nthrow new Error('boom');",
+        );
+        let output = engine
+            .eval_script("2", "sample.js")
+            .expect("eval should pass");
+        assert!(output.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn timers_wait_in_real_time_and_die_with_a_failed_evaluation() {
+        let console = CapturedConsole::default();
+        let mut engine =
+            BoaEngine::with_console_sink(console.clone()).expect("engine should start");
+        let started = Instant::now();
+        let output = engine
+            .eval_script(
+                "setTimeout(() => console.log('waited'), 30); 'now'",
+                "<test>",
+            )
+            .expect("eval should pass");
+        assert!(started.elapsed() >= Duration::from_millis(30));
+        assert_eq!(output.value.as_deref(), Some("now"));
+        assert_eq!(output.console_output.as_deref(), Some("waited"));
+
+        engine
+            .eval_script(
+                "setTimeout(() => console.log('orphan'), 0);\nsetTimeout(() => { throw new Error('first'); }, 0);\nsetTimeout(() => console.log('after'), 5);",
+                "<test>",
+            )
+            .expect_err("the first timer throws");
+        let output = engine.eval_script("1", "<test>").expect("eval should pass");
+        assert_eq!(output.console_output, None);
+        assert_eq!(console.lines(), ["waited", "orphan"]);
+    }
+
+    #[test]
+    fn uncleared_intervals_do_not_block_an_evaluation_with_a_timer_wait() {
+        let console = CapturedConsole::default();
+        let mut engine =
+            BoaEngine::with_console_sink(console.clone()).expect("engine should start");
+        engine.set_timer_wait(Some(Duration::ZERO));
+        let started = Instant::now();
+        let output = engine
+            .eval_script(
+                "var ticks = 0;\nsetInterval(() => ticks++, 5);\nsetTimeout(() => console.log('later'), 10);\nqueueMicrotask(() => console.log('now'));",
+                "<test>",
+            )
+            .expect("eval should pass");
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(output.console_output.as_deref(), Some("now"));
+
+        // Pending timers are kept and run by the next evaluation once due.
+        std::thread::sleep(Duration::from_millis(20));
+        let output = engine.eval_script("1", "<test>").expect("eval should pass");
+        assert_eq!(output.console_output.as_deref(), Some("later"));
+        let output = engine
+            .eval_script("ticks", "<test>")
+            .expect("eval should pass");
+        assert_eq!(output.value.as_deref(), Some("1"));
+
+        engine.set_timer_wait(Some(Duration::from_millis(30)));
+        let started = Instant::now();
+        let output = engine
+            .eval_script("ticks = 0; 'waited'", "<test>")
+            .expect("eval should pass");
+        assert!(started.elapsed() >= Duration::from_millis(25));
+        assert_eq!(output.value.as_deref(), Some("waited"));
+        let output = engine
+            .eval_script("ticks", "<test>")
+            .expect("eval should pass");
+        assert!(
+            output.value.as_deref() != Some("0"),
+            "the interval ran while waiting"
+        );
+    }
+
+    #[test]
+    fn cleared_timers_do_not_run_with_a_later_evaluation() {
+        let console = CapturedConsole::default();
+        let mut engine =
+            BoaEngine::with_console_sink(console.clone()).expect("engine should start");
+        engine.set_timer_wait(Some(Duration::ZERO));
+        engine
+            .eval_script(
+                "setInterval(() => console.log('tick'), 1);\nsetTimeout(() => console.log('later'), 1);",
+                "<test>",
+            )
+            .expect("eval should pass");
+        assert_eq!(engine.pending_timers(), 2);
+
+        engine.clear_timers();
+        assert_eq!(engine.pending_timers(), 0);
+        std::thread::sleep(Duration::from_millis(5));
+        let output = engine
+            .eval_script("globalThis.__klumo_run_timer(1); 'done'", "<test>")
+            .expect("eval should pass");
+        assert_eq!(output.value.as_deref(), Some("done"));
+        assert_eq!(output.console_output, None);
+    }
+
+    #[test]
+    fn scheduling_natives_accept_any_delay() {
+        let mut engine = BoaEngine::new().expect("engine should start");
+        engine.set_timer_wait(Some(Duration::ZERO));
+        let output = engine
+            .eval_script(
+                "__klumo_timer_schedule(1, Infinity);\n__klumo_timer_schedule(2, 1e300);\n__klumo_timer_schedule(3, NaN);\n__klumo_timer_schedule(4, -Infinity);\n'x'",
+                "<test>",
+            )
+            .expect("eval should pass");
+        assert_eq!(output.value.as_deref(), Some("x"));
+        assert_eq!(engine.pending_timers(), 2);
+    }
+
     #[test]
     fn console_log_does_not_throw() {
-        let mut engine = BoaEngine::new();
+        let mut engine = BoaEngine::new().expect("engine should initialize");
         let output = engine
             .eval_script("console.log('hello'); 7", "<test>")
             .expect("eval should pass");
@@ -143,15 +863,252 @@ mod tests {
 
     #[test]
     fn console_logs_flush_on_undefined_result() {
-        let mut engine = BoaEngine::new();
+        let mut engine = BoaEngine::new().expect("engine should initialize");
         let output = engine
             .eval_script("console.log('hello from undefined');", "<test>")
             .expect("eval should pass");
         assert_eq!(output.value, None);
 
-        let length = engine
-            .eval_script("globalThis.__klumo_console_logs.length", "<test>")
+        assert_eq!(
+            output.console_output.as_deref(),
+            Some("hello from undefined")
+        );
+
+        let next = engine.eval_script("2", "<test>").expect("eval should pass");
+        assert_eq!(next.console_output, None);
+    }
+
+    #[test]
+    fn new_engine_passes_console_shim_self_check() {
+        let mut engine = BoaEngine::new().expect("engine should initialize");
+        klumo_runtime_std::check(&mut engine).expect("fresh shim should be healthy");
+
+        engine
+            .eval_script("delete globalThis.__klumo_console_write;", "<test>")
+            .expect("eval should pass");
+        assert!(klumo_runtime_std::check(&mut engine).is_err());
+    }
+
+    #[test]
+    fn overwritten_host_functions_do_not_lose_console_output() {
+        let console = CapturedConsole::default();
+        let mut engine =
+            BoaEngine::with_console_sink(console.clone()).expect("engine should initialize");
+        let output = engine
+            .eval_script(
+                "globalThis.__klumo_console_write = undefined; console.log('kept'); 1",
+                "<test>",
+            )
             .expect("eval should pass");
-        assert_eq!(length.value.as_deref(), Some("0"));
+        assert_eq!(output.value.as_deref(), Some("1"));
+        assert_eq!(output.console_output.as_deref(), Some("kept"));
+        assert!(output.diagnostics.is_empty());
+        assert_eq!(console.lines(), ["kept"]);
+    }
+
+    #[test]
+    fn check_syntax_accepts_valid_script() {
+        assert!(check_syntax("const a = 1; a + 1").is_empty());
+        assert!(check_syntax("import { a } from './a.js';\nexport const b = a;").is_empty());
+    }
+
+    #[test]
+    fn goal_follows_module_only_syntax() {
+        assert_eq!(detect_goal("const a = 1; a"), ScriptGoal::Script);
+        assert_eq!(detect_goal("const await = 1;"), ScriptGoal::Script);
+        assert_eq!(detect_goal("export const a = 1;"), ScriptGoal::Module);
+        assert_eq!(detect_goal("import x from 'y';"), ScriptGoal::Module);
+        assert_eq!(
+            detect_goal("const v = await Promise.resolve(1);"),
+            ScriptGoal::Module
+        );
+        assert_eq!(detect_goal("const = ;"), ScriptGoal::Script);
+        assert!(check_syntax("export default 1;").is_empty());
+        assert_eq!(goal_for("const a = 1;", "lib/a.mjs"), ScriptGoal::Module);
+        assert_eq!(goal_for("const a = 1;", "lib/a.js"), ScriptGoal::Script);
+        assert_eq!(goal_for("export {};", "lib/a.cjs"), ScriptGoal::Script);
+    }
+
+    #[test]
+    fn modules_run_strict_with_top_level_await() {
+        let mut engine = BoaEngine::new().expect("engine should initialize");
+        let output = engine
+            .eval_module(
+                "const v = await Promise.resolve(2);\nconsole.log(typeof this, v);\nexport const twice = v * 2;",
+                "main.mjs",
+            )
+            .expect("module should run");
+        assert_eq!(output.value, None);
+        assert_eq!(output.console_output.as_deref(), Some("undefined 2"));
+
+        let err = engine
+            .eval_module("undeclared = 1;", "main.mjs")
+            .expect_err("modules are strict");
+        assert!(err.to_string().contains("failed evaluating main.mjs"));
+
+        let err = engine
+            .eval_module("await Promise.reject(new Error('nope'));", "main.mjs")
+            .expect_err("rejection should fail the module");
+        assert!(err.to_string().contains("nope"));
+    }
+
+    #[test]
+    fn module_imports_resolve_relative_to_the_importing_file() {
+        let dir = tempfile::tempdir().expect("tempdir should work");
+        fs::create_dir(dir.path().join("lib")).expect("mkdir should work");
+        fs::write(
+            dir.path().join("lib/count.mjs"),
+            "export let count = 0;\nexport function bump() { count += 1; }\n",
+        )
+        .expect("write should work");
+        fs::write(
+            dir.path().join("lib/twice.mjs"),
+            "import { bump } from './count.mjs';\nbump();\nexport const twice = (n) => n * 2;\n",
+        )
+        .expect("write should work");
+        let main = dir.path().join("main.mjs");
+        let source = "import { twice } from './lib/twice.mjs';\nimport { count, bump } from './lib/count.mjs';\nbump();\nconsole.log(twice(21), count);\n";
+        fs::write(&main, source).expect("write should work");
+
+        let mut engine =
+            BoaEngine::with_console_sink(CapturedConsole::default()).expect("engine should start");
+        let output = engine
+            .evaluate(source, main.to_str().expect("path utf8"))
+            .expect("module graph should run");
+        // Both importers share one instance of count.mjs.
+        assert_eq!(output.console_output.as_deref(), Some("42 2"));
+
+        let err = engine
+            .evaluate(
+                "import { readFile } from 'node:fs';",
+                main.to_str().expect("path utf8"),
+            )
+            .expect_err("bare specifiers should fail");
+        assert!(err.to_string().contains("only relative file imports"));
+        let err = engine
+            .evaluate("import './missing.mjs';", main.to_str().expect("path utf8"))
+            .expect_err("missing files should fail");
+        assert!(
+            err.to_string()
+                .contains("cannot find module './missing.mjs'")
+        );
+        let err = engine
+            .evaluate("import './lib/count.mjs';", "<eval>")
+            .expect_err("sources without a file have nothing to resolve against");
+        assert!(err.to_string().contains("not a file"));
+    }
+
+    fn shout(args: &[String]) -> Result<Option<String>, String> {
+        match args.first() {
+            Some(text) if !text.is_empty() => Ok(Some(text.to_uppercase())),
+            Some(_) => Ok(None),
+            None => Err("shout needs text".to_string()),
+        }
+    }
+
+    #[test]
+    fn host_functions_return_strings_and_throw_errors() {
+        let mut engine = BoaEngine::new().expect("engine should initialize");
+        engine
+            .register_host_function("__shout", shout)
+            .expect("registration should work");
+        let out = engine
+            .eval_script("__shout('hi', 2) + typeof __shout('')", "host.js")
+            .expect("host call should work");
+        assert_eq!(out.value.as_deref(), Some("HIundefined"));
+
+        let out = engine
+            .eval_script(
+                "try { __shout(); } catch (e) { e instanceof Error ? e.message : 'no' }",
+                "host.js",
+            )
+            .expect("thrown error should be catchable");
+        assert_eq!(out.value.as_deref(), Some("shout needs text"));
+        assert!(
+            ScriptOnlyEngine
+                .register_host_function("__shout", shout)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn loop_iteration_limit_stops_runaway_loops() {
+        let mut engine = BoaEngine::new().expect("engine should initialize");
+        engine.set_loop_iteration_limit(1_000);
+        assert!(engine.eval_script("while (true) {}", "spin.js").is_err());
+        let out = engine
+            .eval_script(
+                "let n = 0; for (let i = 0; i < 500; i++) n++; n",
+                "count.js",
+            )
+            .expect("bounded loop should run");
+        assert_eq!(out.value.as_deref(), Some("500"));
+    }
+
+    #[test]
+    fn memory_usage_reads_proc_status_and_survives_collection() {
+        let usage = MemoryUsage::from_proc_status(
+            "Name:\tklumo\nVmHWM:\t   20480 kB\nVmRSS:\t   10240 kB\nThreads:\t1\n",
+        );
+        assert_eq!(usage.resident_bytes, Some(10_485_760));
+        assert_eq!(usage.peak_resident_bytes, Some(20_971_520));
+        assert_eq!(
+            usage.to_json(),
+            r#"{"heapBytes":null,"objects":null,"residentBytes":10485760,"peakResidentBytes":20971520}"#
+        );
+
+        let mut engine = BoaEngine::new().expect("engine should initialize");
+        engine
+            .eval_script(
+                "globalThis.kept = { n: 1 }; [1, 2, 3].map((n) => ({ n }));",
+                "gc.js",
+            )
+            .expect("script should run");
+        engine.collect_garbage();
+        let out = engine
+            .eval_script("kept.n", "after-gc.js")
+            .expect("reachable objects survive collection");
+        assert_eq!(out.value.as_deref(), Some("1"));
+        assert_eq!(engine.memory_usage().heap_bytes, None);
+    }
+
+    struct ScriptOnlyEngine;
+
+    impl JsEngine for ScriptOnlyEngine {
+        fn eval_script(&mut self, _source: &str, _source_name: &str) -> anyhow::Result<EvalOutput> {
+            Ok(EvalOutput {
+                value: Some("script".to_string()),
+                console_output: None,
+                diagnostics: Vec::new(),
+            })
+        }
+    }
+
+    #[test]
+    fn modules_on_script_only_engines_explain_the_failure() {
+        let mut engine = ScriptOnlyEngine;
+        let err = engine
+            .evaluate("export const a = 1;", "lib.mjs")
+            .expect_err("modules are unsupported");
+        assert!(
+            err.to_string()
+                .starts_with("lib.mjs is an ES module (it uses import/export or top-level await)")
+        );
+        let output = engine
+            .evaluate("1 + 1", "<test>")
+            .expect("scripts still run");
+        assert_eq!(output.value.as_deref(), Some("script"));
+    }
+
+    #[test]
+    fn check_syntax_reports_parse_errors() {
+        let diagnostics = check_syntax("const = ;");
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].to_string().starts_with("SyntaxError: "));
+        assert_eq!(diagnostics[0].name.as_deref(), Some("SyntaxError"));
+        assert_eq!(
+            diagnostics[0].position,
+            Some(SourcePosition { line: 1, column: 7 })
+        );
     }
 }
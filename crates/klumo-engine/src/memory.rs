@@ -0,0 +1,56 @@
+use std::fs;
+
+/// Memory figures for a running engine. Anything the engine or the platform cannot measure
+/// is `None`: Boa keeps its heap accounting private, the V8 scaffold has no isolate to read
+/// heap statistics from yet, and resident figures come from `/proc/self/status`, so they are
+/// only known on Linux.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// Bytes held by the engine's garbage-collected heap.
+    pub heap_bytes: Option<u64>,
+    /// Live objects on that heap.
+    pub objects: Option<u64>,
+    /// Resident memory of the whole process.
+    pub resident_bytes: Option<u64>,
+    /// Highest resident memory of the process so far.
+    pub peak_resident_bytes: Option<u64>,
+}
+
+impl MemoryUsage {
+    /// Resident figures of the current process, without engine heap figures.
+    pub fn process() -> Self {
+        fs::read_to_string("/proc/self/status")
+            .map(|status| Self::from_proc_status(&status))
+            .unwrap_or_default()
+    }
+
+    /// Reads `VmRSS` and `VmHWM` (the resident high-water mark) from the text of a Linux
+    /// `/proc/<pid>/status` file.
+    pub fn from_proc_status(status: &str) -> Self {
+        let kilobytes = |field: &str| {
+            status.lines().find_map(|line| {
+                let value = line.strip_prefix(field)?.strip_prefix(':')?;
+                let amount = value.trim().strip_suffix("kB")?.trim();
+                amount.parse::<u64>().ok().map(|kb| kb * 1024)
+            })
+        };
+        Self {
+            resident_bytes: kilobytes("VmRSS"),
+            peak_resident_bytes: kilobytes("VmHWM"),
+            ..Self::default()
+        }
+    }
+
+    /// `{ heapBytes, objects, residentBytes, peakResidentBytes }` as JSON, with `null` for
+    /// unknown figures; the shape `klumo.memoryUsage()` returns to scripts.
+    pub fn to_json(&self) -> String {
+        let field = |value: Option<u64>| value.map_or("null".to_string(), |v| v.to_string());
+        format!(
+            r#"{{"heapBytes":{},"objects":{},"residentBytes":{},"peakResidentBytes":{}}}"#,
+            field(self.heap_bytes),
+            field(self.objects),
+            field(self.resident_bytes),
+            field(self.peak_resident_bytes)
+        )
+    }
+}
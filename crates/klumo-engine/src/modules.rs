@@ -0,0 +1,96 @@
+use boa_engine::module::{ModuleLoader, Referrer};
+use boa_engine::{Context, JsNativeError, JsResult, JsString, Module, Source};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Vets an imported file before it runs: gets its path and contents and fails to refuse it.
+pub type ImportCheck = Rc<dyn Fn(&Path, &str) -> anyhow::Result<()>>;
+
+/// Resolves the `import`s of ES modules to JavaScript files on disk, relative to the file of
+/// the importing module. Files are run as they are, without translation; bare specifiers
+/// such as `node:fs` have nothing to resolve against and fail. Each file is parsed once per
+/// engine, so every importer shares one instance of it.
+#[derive(Default)]
+pub(crate) struct FileModuleLoader {
+    modules: RefCell<HashMap<PathBuf, Module>>,
+    check: RefCell<Option<ImportCheck>>,
+}
+
+impl FileModuleLoader {
+    /// Records `module` as the contents of `path`, so imports of it reuse it.
+    pub(crate) fn insert(&self, path: PathBuf, module: Module) {
+        self.modules.borrow_mut().insert(path, module);
+    }
+
+    /// Runs `check` on every file imported from now on.
+    pub(crate) fn set_check(&self, check: ImportCheck) {
+        *self.check.borrow_mut() = Some(check);
+    }
+
+    fn load(
+        &self,
+        referrer: Option<&Path>,
+        specifier: &str,
+        context: &mut Context,
+    ) -> JsResult<Module> {
+        if !is_relative(specifier) {
+            return Err(JsNativeError::typ()
+                .with_message(format!(
+                    "cannot import '{specifier}': only relative file imports (./ or ../) are supported"
+                ))
+                .into());
+        }
+        let Some(dir) = referrer.and_then(Path::parent) else {
+            return Err(JsNativeError::typ()
+                .with_message(format!(
+                    "cannot import '{specifier}' from a module that is not a file"
+                ))
+                .into());
+        };
+        let path = fs::canonicalize(dir.join(specifier)).map_err(|err| {
+            JsNativeError::typ().with_message(format!(
+                "cannot find module '{specifier}' imported from {}: {err}",
+                referrer.map_or_else(String::new, |path| path.display().to_string())
+            ))
+        })?;
+        if let Some(module) = self.modules.borrow().get(&path) {
+            return Ok(module.clone());
+        }
+        let text = fs::read_to_string(&path).map_err(|err| {
+            JsNativeError::typ()
+                .with_message(format!("failed reading module {}: {err}", path.display()))
+        })?;
+        let check = self.check.borrow().clone();
+        if let Some(check) = check {
+            check(&path, &text).map_err(|err| {
+                JsNativeError::typ().with_message(format!(
+                    "cannot import '{specifier}' ({}): {err:#}",
+                    path.display()
+                ))
+            })?;
+        }
+        let module = Module::parse(Source::from_bytes(&text).with_path(&path), None, context)?;
+        self.insert(path, module.clone());
+        Ok(module)
+    }
+}
+
+impl ModuleLoader for FileModuleLoader {
+    fn load_imported_module(
+        &self,
+        referrer: Referrer,
+        specifier: JsString,
+        finish_load: Box<dyn FnOnce(JsResult<Module>, &mut Context)>,
+        context: &mut Context,
+    ) {
+        let result = self.load(referrer.path(), &specifier.to_std_string_escaped(), context);
+        finish_load(result, context);
+    }
+}
+
+fn is_relative(specifier: &str) -> bool {
+    specifier.starts_with("./") || specifier.starts_with("../")
+}
@@ -0,0 +1,100 @@
+use boa_engine::gc::empty_trace;
+use boa_engine::{Context, Finalize, JsData, JsResult, JsValue, NativeFunction, Trace};
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// The timers of one context, kept as context data: the id of each pending timer by the
+/// time it falls due, ties broken by the order they were scheduled in. The callbacks stay
+/// with the `timers` script.
+#[derive(Debug, Default, JsData)]
+pub(crate) struct TimerQueue {
+    due: RefCell<BTreeMap<(Instant, u64), u64>>,
+    scheduled: Cell<u64>,
+}
+
+impl Finalize for TimerQueue {}
+
+// SAFETY: holds no garbage-collected values.
+unsafe impl Trace for TimerQueue {
+    empty_trace!();
+}
+
+/// Longest timer delay in milliseconds, 2^31 - 1 as in browsers and Node.
+const MAX_DELAY_MS: f64 = 2_147_483_647.0;
+
+/// The native behind `TIMER_SCHEDULE`: `(id, delay)` with the delay in milliseconds. Scripts
+/// can call it directly, so delays that are not numbers count as 0 and longer ones are cut
+/// to [`MAX_DELAY_MS`].
+pub(crate) fn timer_schedule() -> NativeFunction {
+    NativeFunction::from_fn_ptr(|_this, args, ctx| {
+        let id = argument(args, 0, ctx)? as u64;
+        let ms = argument(args, 1, ctx)?;
+        let ms = if ms.is_nan() {
+            0.0
+        } else {
+            ms.clamp(0.0, MAX_DELAY_MS)
+        };
+        let delay = Duration::try_from_secs_f64(ms / 1000.0).unwrap_or_default();
+        if let Some(queue) = ctx.get_data::<TimerQueue>() {
+            let order = queue.scheduled.get();
+            queue.scheduled.set(order + 1);
+            queue
+                .due
+                .borrow_mut()
+                .insert((due_after(Instant::now(), delay), order), id);
+        }
+        Ok(JsValue::undefined())
+    })
+}
+
+/// The native behind `TIMER_CANCEL`: `(id)`.
+pub(crate) fn timer_cancel() -> NativeFunction {
+    NativeFunction::from_fn_ptr(|_this, args, ctx| {
+        let id = argument(args, 0, ctx)? as u64;
+        if let Some(queue) = ctx.get_data::<TimerQueue>() {
+            queue.due.borrow_mut().retain(|_, pending| *pending != id);
+        }
+        Ok(JsValue::undefined())
+    })
+}
+
+/// `now + delay`, or the latest instant the platform can represent short of it.
+fn due_after(now: Instant, mut delay: Duration) -> Instant {
+    loop {
+        if let Some(due) = now.checked_add(delay) {
+            return due;
+        }
+        delay /= 2;
+    }
+}
+
+fn argument(args: &[JsValue], index: usize, ctx: &mut Context) -> JsResult<f64> {
+    args.get(index).cloned().unwrap_or_default().to_number(ctx)
+}
+
+/// Removes the timer that falls due first, unless that is after `deadline`, returning when
+/// it is due and its id.
+pub(crate) fn next_due(ctx: &Context, deadline: Option<Instant>) -> Option<(Instant, u64)> {
+    let queue = ctx.get_data::<TimerQueue>()?;
+    let mut due = queue.due.borrow_mut();
+    let entry = due.first_entry()?;
+    if deadline.is_some_and(|deadline| entry.key().0 > deadline) {
+        return None;
+    }
+    let ((due, _), id) = entry.remove_entry();
+    Some((due, id))
+}
+
+/// How many timers are pending.
+pub(crate) fn pending(ctx: &Context) -> usize {
+    ctx.get_data::<TimerQueue>()
+        .map_or(0, |queue| queue.due.borrow().len())
+}
+
+/// Drops the due times of every pending timer; the callbacks are dropped by `CLEAR_TIMERS`.
+pub(crate) fn clear(ctx: &Context) {
+    if let Some(queue) = ctx.get_data::<TimerQueue>() {
+        queue.due.borrow_mut().clear();
+    }
+}
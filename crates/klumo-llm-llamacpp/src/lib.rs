@@ -0,0 +1,280 @@
+use anyhow::{Context, Result, anyhow};
+use klumo_llm::{
+    Completion, LlmClient, LlmTranslateRequest, ProviderCallError, ProviderErrorKind, SizeLimits,
+    TlsOptions,
+};
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Client for the llama.cpp HTTP server (`llama-server`), which serves a single model.
+#[derive(Clone)]
+pub struct LlamaCppClient {
+    pub base_url: String,
+    pub timeout: Duration,
+    pub tls: TlsOptions,
+    pub limits: SizeLimits,
+}
+
+impl LlamaCppClient {
+    pub fn new(base_url: String) -> Self {
+        Self {
+            base_url,
+            timeout: Duration::from_secs(2),
+            tls: TlsOptions::default(),
+            limits: SizeLimits::default(),
+        }
+    }
+
+    pub fn with_tls(mut self, tls: TlsOptions) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    pub fn with_limits(mut self, limits: SizeLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Checks `/health`, which reports 503 while the server is still loading its model.
+    pub fn health(&self) -> Result<()> {
+        let client = self
+            .tls
+            .apply_to(Client::builder())?
+            .timeout(self.timeout)
+            .build()
+            .context("failed to build HTTP client")?;
+        let url = format!("{}/health", self.base_url.trim_end_matches('/'));
+        let response = client
+            .get(url)
+            .send()
+            .with_context(|| format!("failed reaching llama.cpp server at {}", self.base_url))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response
+                .text()
+                .unwrap_or_else(|_| "<unavailable>".to_string());
+            return Err(anyhow!("llama.cpp server is not ready ({status}): {body}"));
+        }
+        Ok(())
+    }
+
+    fn complete(&self, client: &Client, body: &CompletionRequest<'_>) -> Result<Completion> {
+        let url = format!("{}/completion", self.base_url.trim_end_matches('/'));
+        let response = client.post(&url).json(body).send().map_err(|err| {
+            ProviderCallError::new(
+                ProviderErrorKind::Network,
+                format!("failed calling llama.cpp server: {err}"),
+            )
+            .at(&url)
+        })?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response
+                .text()
+                .unwrap_or_else(|_| "<unavailable>".to_string());
+            return Err(ProviderCallError::http(
+                &url,
+                status.as_u16(),
+                format!("llama.cpp request failed ({status}): {body}"),
+            )
+            .into());
+        }
+
+        let parsed: CompletionResponse = response.json().map_err(|err| {
+            ProviderCallError::new(
+                ProviderErrorKind::Model,
+                format!("failed to decode llama.cpp response: {err}"),
+            )
+            .at(&url)
+        })?;
+        Ok(Completion {
+            truncated: parsed.stopped_limit || parsed.stop_type.as_deref() == Some("limit"),
+            text: parsed.content,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct CompletionRequest<'a> {
+    prompt: &'a str,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n_predict: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    /// Constrains sampling to a JSON object when structured output is requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    json_schema: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionResponse {
+    content: String,
+    /// `"limit"` when generation stopped at `n_predict` (current servers).
+    #[serde(default)]
+    stop_type: Option<String>,
+    /// Older servers report the same condition as a flag.
+    #[serde(default)]
+    stopped_limit: bool,
+}
+
+impl LlmClient for LlamaCppClient {
+    fn translate_to_js(&self, req: &LlmTranslateRequest, _model: &str) -> Result<String> {
+        self.limits.check_prompt(req)?;
+        let client = self
+            .tls
+            .apply_to(Client::builder())?
+            .timeout(Duration::from_secs(60))
+            .build()
+            .context("failed to build HTTP client")?;
+        let prompt = build_prompt(req);
+
+        // `/completion` continues raw text, so a cut-off answer resumes by appending it to the prompt.
+        self.limits.complete(|partials| {
+            let prompt = format!("{prompt}{}", partials.concat());
+            self.complete(
+                &client,
+                &CompletionRequest {
+                    prompt: &prompt,
+                    stream: false,
+                    temperature: req.temperature,
+                    top_p: req.top_p,
+                    n_predict: req.max_tokens,
+                    seed: req.seed,
+                    json_schema: req
+                        .structured_output
+                        .then(|| serde_json::json!({"type": "object"})),
+                },
+            )
+        })
+    }
+}
+
+/// Prompt parts joined into one text, ending where the model should start writing.
+fn build_prompt(req: &LlmTranslateRequest) -> String {
+    let mut prompt = req
+        .prompts
+        .parts(req)
+        .into_iter()
+        .map(|(_, content)| content)
+        .collect::<Vec<_>>()
+        .join("\n");
+    prompt.push('\n');
+    prompt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LlamaCppClient;
+    use klumo_llm::{LlmClient, LlmTranslateRequest, PromptTemplates};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    fn request() -> LlmTranslateRequest {
+        LlmTranslateRequest {
+            source_text: "print 1".to_string(),
+            source_id: "one.pseudo".to_string(),
+            language_hint: Some("pseudocode".to_string()),
+            scope_context: None,
+            repair_hint: None,
+            temperature: Some(0.0),
+            top_p: None,
+            max_tokens: Some(128),
+            seed: None,
+            structured_output: false,
+            explain: false,
+            explain_javascript: None,
+            prompts: PromptTemplates::default(),
+        }
+    }
+
+    /// Answers one connection per body, in order, and records the request bodies it saw.
+    fn serve(responses: Vec<(u16, &'static str)>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind should work");
+        let addr = listener.local_addr().expect("local addr should work");
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let seen_by_server = Arc::clone(&seen);
+        std::thread::spawn(move || {
+            for ((status, body), stream) in responses.into_iter().zip(listener.incoming()) {
+                let mut stream = stream.expect("accept should work");
+                let mut buf = [0_u8; 16384];
+                let n = stream.read(&mut buf).expect("read should work");
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                let request_body = request
+                    .split_once("\r\n\r\n")
+                    .map(|(_, body)| body.to_string())
+                    .unwrap_or_default();
+                seen_by_server.lock().expect("lock").push(request_body);
+                let response = format!(
+                    "HTTP/1.1 {status} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                stream
+                    .write_all(response.as_bytes())
+                    .expect("write should work");
+            }
+        });
+        (format!("http://{addr}"), seen)
+    }
+
+    #[test]
+    fn translates_through_completion_endpoint() {
+        let (base, seen) = serve(vec![(
+            200,
+            r#"{"content":"console.log(1);","stop":true,"stop_type":"eos"}"#,
+        )]);
+        let out = LlamaCppClient::new(base)
+            .translate_to_js(&request(), "default")
+            .expect("completion should work");
+        assert_eq!(out, "console.log(1);");
+
+        let body: serde_json::Value =
+            serde_json::from_str(&seen.lock().expect("lock")[0]).expect("json body");
+        assert_eq!(body["n_predict"], 128);
+        assert_eq!(body["stream"], false);
+        assert!(
+            body["prompt"]
+                .as_str()
+                .unwrap_or_default()
+                .contains("print 1")
+        );
+    }
+
+    #[test]
+    fn resumes_completions_cut_off_at_the_limit() {
+        let (base, seen) = serve(vec![
+            (200, r#"{"content":"console.","stop_type":"limit"}"#),
+            (200, r#"{"content":"log(1);","stopped_limit":false}"#),
+        ]);
+        let out = LlamaCppClient::new(base)
+            .translate_to_js(&request(), "default")
+            .expect("continuation should work");
+        assert_eq!(out, "console.log(1);");
+
+        let seen = seen.lock().expect("lock");
+        let second: serde_json::Value = serde_json::from_str(&seen[1]).expect("json body");
+        assert!(
+            second["prompt"]
+                .as_str()
+                .unwrap_or_default()
+                .ends_with("console.")
+        );
+    }
+
+    #[test]
+    fn health_reports_loading_servers() {
+        let (base, _) = serve(vec![(503, r#"{"error":{"message":"Loading model"}}"#)]);
+        let err = LlamaCppClient::new(base)
+            .health()
+            .expect_err("loading server is not ready");
+        assert!(err.to_string().contains("Loading model"));
+    }
+}
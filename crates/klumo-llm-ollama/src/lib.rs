@@ -1,13 +1,28 @@
 use anyhow::{Context, Result, anyhow};
-use klumo_llm::{LlmClient, LlmTranslateRequest};
-use reqwest::blocking::Client;
+use klumo_llm::{
+    CONTINUATION_PROMPT, Completion, LlmClient, LlmTranslateRequest, ProviderCallError,
+    ProviderErrorKind, SizeLimits, TlsOptions,
+};
+use reqwest::blocking::{Client, ClientBuilder};
+use reqwest::{StatusCode, Url};
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
+use std::io::{BufRead, BufReader};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// Upper bound on a warm-up call; loading a large model from disk can take minutes.
+const WARM_UP_TIMEOUT: Duration = Duration::from_secs(300);
 
 #[derive(Clone)]
 pub struct OllamaClient {
     pub base_url: String,
     pub timeout: Duration,
+    pub tls: TlsOptions,
+    pub limits: SizeLimits,
+    /// Ollama's `keep_alive`: how long the model stays loaded after a request, as a duration
+    /// such as `"30m"` or a number of seconds (`-1` keeps it loaded). `None` leaves the
+    /// server default, five minutes.
+    pub keep_alive: Option<String>,
 }
 
 impl OllamaClient {
@@ -15,11 +30,43 @@ impl OllamaClient {
         Ok(Self {
             base_url,
             timeout: Duration::from_secs(2),
+            tls: TlsOptions::default(),
+            limits: SizeLimits::default(),
+            keep_alive: None,
+        })
+    }
+
+    pub fn with_tls(mut self, tls: TlsOptions) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    pub fn with_limits(mut self, limits: SizeLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_keep_alive(mut self, keep_alive: Option<String>) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// Client builder with the TLS settings applied. A server on this machine is reached
+    /// directly even when `HTTP_PROXY` is set, since a corporate proxy cannot reach it.
+    fn client_builder(&self) -> Result<ClientBuilder> {
+        let builder = self.tls.apply_to(Client::builder())?;
+        Ok(if is_loopback(&self.base_url) {
+            builder.no_proxy()
+        } else {
+            builder
         })
     }
 
     pub fn is_reachable(&self) -> bool {
-        let client = match Client::builder().timeout(self.timeout).build() {
+        let client = match self
+            .client_builder()
+            .and_then(|builder| Ok(builder.timeout(self.timeout).build()?))
+        {
             Ok(c) => c,
             Err(_) => return false,
         };
@@ -31,6 +78,215 @@ impl OllamaClient {
             .map(|r| r.status().is_success())
             .unwrap_or(false)
     }
+
+    /// Names of the models pulled on the server, as reported by `/api/tags`.
+    pub fn list_models(&self) -> Result<Vec<String>> {
+        let client = self
+            .client_builder()?
+            .timeout(self.timeout)
+            .build()
+            .context("failed to build HTTP client")?;
+        let url = format!("{}/api/tags", self.base_url.trim_end_matches('/'));
+        let response = client
+            .get(url)
+            .send()
+            .with_context(|| format!("failed reaching Ollama at {}", self.base_url))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            return Err(anyhow!("Ollama model listing failed ({status})"));
+        }
+
+        let parsed: TagsResponse = response
+            .json()
+            .context("failed to decode Ollama model list")?;
+        Ok(parsed.models.into_iter().map(|model| model.name).collect())
+    }
+
+    /// Pulls `model` through `/api/pull`, reporting each streamed progress line to `on_progress`.
+    pub fn pull_model(
+        &self,
+        model: &str,
+        mut on_progress: impl FnMut(&PullProgress),
+    ) -> Result<()> {
+        // Pulls download gigabytes, so only the connect phase is bounded.
+        let client = self
+            .client_builder()?
+            .connect_timeout(self.timeout)
+            .timeout(None)
+            .build()
+            .context("failed to build HTTP client")?;
+        let url = format!("{}/api/pull", self.base_url.trim_end_matches('/'));
+        let response = client
+            .post(url)
+            .json(&PullRequest {
+                model,
+                stream: true,
+            })
+            .send()
+            .with_context(|| format!("failed reaching Ollama at {}", self.base_url))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response
+                .text()
+                .unwrap_or_else(|_| "<unavailable>".to_string());
+            return Err(anyhow!("Ollama pull of {model} failed ({status}): {body}"));
+        }
+
+        for line in BufReader::new(response).lines() {
+            let line = line.context("failed reading Ollama pull progress")?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let progress: PullProgress =
+                serde_json::from_str(&line).context("failed to decode Ollama pull progress")?;
+            if let Some(error) = &progress.error {
+                return Err(anyhow!("Ollama pull of {model} failed: {error}"));
+            }
+            on_progress(&progress);
+        }
+        Ok(())
+    }
+
+    /// Loads `model` into memory with an empty `/api/generate` call so the first translation
+    /// does not pay for it, and returns how long the load took.
+    pub fn warm_up(&self, model: &str) -> Result<Duration> {
+        let client = self
+            .client_builder()?
+            .connect_timeout(self.timeout)
+            .timeout(WARM_UP_TIMEOUT)
+            .build()
+            .context("failed to build HTTP client")?;
+        let url = format!("{}/api/generate", self.base_url.trim_end_matches('/'));
+        let started = Instant::now();
+        let response = client
+            .post(url)
+            .json(&WarmUpRequest {
+                model,
+                stream: false,
+                keep_alive: self.keep_alive_value(),
+            })
+            .send()
+            .with_context(|| format!("failed reaching Ollama at {}", self.base_url))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response
+                .text()
+                .unwrap_or_else(|_| "<unavailable>".to_string());
+            return Err(anyhow!(
+                "Ollama warm-up of {model} failed ({status}): {body}"
+            ));
+        }
+        Ok(started.elapsed())
+    }
+
+    /// Embedding of `text` by `model` (an embedding model such as `nomic-embed-text`), from
+    /// `/api/embeddings`.
+    pub fn embed(&self, model: &str, text: &str) -> Result<Vec<f32>> {
+        let client = self.http_client()?;
+        let url = format!("{}/api/embeddings", self.base_url.trim_end_matches('/'));
+        let response = client
+            .post(url)
+            .json(&EmbeddingsRequest {
+                model,
+                prompt: text,
+                keep_alive: self.keep_alive_value(),
+            })
+            .send()
+            .with_context(|| format!("failed reaching Ollama at {}", self.base_url))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response
+                .text()
+                .unwrap_or_else(|_| "<unavailable>".to_string());
+            return Err(anyhow!(
+                "Ollama embedding with {model} failed ({status}): {body}"
+            ));
+        }
+        let parsed: EmbeddingsResponse = response
+            .json()
+            .context("failed to decode Ollama embedding")?;
+        if parsed.embedding.is_empty() {
+            return Err(anyhow!(
+                "Ollama returned an empty embedding; is {model} an embedding model?"
+            ));
+        }
+        Ok(parsed.embedding)
+    }
+
+    fn keep_alive_value(&self) -> Option<serde_json::Value> {
+        self.keep_alive.as_deref().map(parse_keep_alive)
+    }
+}
+
+/// Ollama reads a bare number as seconds and anything else as a Go duration string.
+fn parse_keep_alive(raw: &str) -> serde_json::Value {
+    let raw = raw.trim();
+    match raw.parse::<i64>() {
+        Ok(seconds) => seconds.into(),
+        Err(_) => raw.into(),
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct WarmUpRequest<'a> {
+    model: &'a str,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+struct EmbeddingsRequest<'a> {
+    model: &'a str,
+    prompt: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingsResponse {
+    #[serde(default)]
+    embedding: Vec<f32>,
+}
+
+#[derive(Debug, Serialize)]
+struct PullRequest<'a> {
+    model: &'a str,
+    stream: bool,
+}
+
+/// One streamed line of `/api/pull` progress.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct PullProgress {
+    #[serde(default)]
+    pub status: String,
+    pub total: Option<u64>,
+    pub completed: Option<u64>,
+    pub error: Option<String>,
+}
+
+impl PullProgress {
+    /// Download progress of the current layer, when the line reports sizes.
+    pub fn percent(&self) -> Option<u64> {
+        match (self.completed, self.total) {
+            (Some(completed), Some(total)) if total > 0 => Some(completed * 100 / total),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TagsResponse {
+    models: Vec<TagEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TagEntry {
+    name: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -38,74 +294,546 @@ struct GenerateRequest<'a> {
     model: &'a str,
     prompt: &'a str,
     stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<&'static str>,
+    options: ModelOptions,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<serde_json::Value>,
+}
+
+/// Ollama's `options` object; unset fields fall back to the model's Modelfile defaults.
+#[derive(Debug, Default, Serialize)]
+struct ModelOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+}
+
+impl ModelOptions {
+    fn from_request(req: &LlmTranslateRequest) -> Self {
+        Self {
+            temperature: req.temperature,
+            top_p: req.top_p,
+            num_predict: req.max_tokens,
+            seed: req.seed,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 struct GenerateResponse {
     response: String,
+    /// `"length"` when generation stopped at `num_predict`.
+    done_reason: Option<String>,
 }
 
-impl LlmClient for OllamaClient {
-    fn translate_to_js(&self, req: &LlmTranslateRequest, model: &str) -> Result<String> {
-        let prompt = build_prompt(req);
-        let client = Client::builder()
+#[derive(Debug, Serialize)]
+struct ChatRequest<'a> {
+    model: &'a str,
+    messages: Vec<ChatMessage>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<&'static str>,
+    options: ModelOptions,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct ChatMessage {
+    role: String,
+    content: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponse {
+    message: ChatMessage,
+    done_reason: Option<String>,
+}
+
+/// Outcome of a `/api/chat` call; `Unsupported` means the server predates the chat API.
+enum ChatOutcome {
+    Completed(Completion),
+    Unsupported,
+}
+
+impl OllamaClient {
+    fn http_client(&self) -> Result<Client> {
+        self.client_builder()?
             .timeout(Duration::from_secs(30))
             .build()
-            .context("failed to build HTTP client")?;
+            .context("failed to build HTTP client")
+    }
+
+    fn chat(
+        &self,
+        client: &Client,
+        req: &LlmTranslateRequest,
+        messages: &[ChatMessage],
+        model: &str,
+    ) -> Result<ChatOutcome> {
+        let url = format!("{}/api/chat", self.base_url.trim_end_matches('/'));
+        let response = client
+            .post(&url)
+            .json(&ChatRequest {
+                model,
+                messages: messages.to_vec(),
+                stream: false,
+                format: req.structured_output.then_some("json"),
+                options: ModelOptions::from_request(req),
+                keep_alive: self.keep_alive_value(),
+            })
+            .send()
+            .map_err(|err| {
+                ProviderCallError::new(
+                    ProviderErrorKind::Network,
+                    format!("failed calling Ollama: {err}"),
+                )
+                .at(&url)
+            })?;
 
+        if response.status() == StatusCode::NOT_FOUND {
+            let body = response.text().unwrap_or_default();
+            // A missing model also yields 404, but with a JSON error naming the model.
+            if !body.contains("model") {
+                return Ok(ChatOutcome::Unsupported);
+            }
+            return Err(ProviderCallError::http(
+                &url,
+                404,
+                format!("Ollama request failed (404 Not Found): {body}"),
+            )
+            .into());
+        }
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response
+                .text()
+                .unwrap_or_else(|_| "<unavailable>".to_string());
+            return Err(ProviderCallError::http(
+                &url,
+                status.as_u16(),
+                format!("Ollama request failed ({status}): {body}"),
+            )
+            .into());
+        }
+
+        let parsed: ChatResponse = response.json().map_err(|err| {
+            ProviderCallError::new(
+                ProviderErrorKind::Model,
+                format!("failed to decode Ollama chat response: {err}"),
+            )
+            .at(&url)
+        })?;
+        Ok(ChatOutcome::Completed(Completion {
+            text: parsed.message.content,
+            truncated: parsed.done_reason.as_deref() == Some("length"),
+        }))
+    }
+
+    fn generate(
+        &self,
+        client: &Client,
+        req: &LlmTranslateRequest,
+        prompt: &str,
+        model: &str,
+    ) -> Result<Completion> {
         let url = format!("{}/api/generate", self.base_url.trim_end_matches('/'));
         let response = client
-            .post(url)
+            .post(&url)
             .json(&GenerateRequest {
                 model,
-                prompt: &prompt,
+                prompt,
                 stream: false,
+                format: req.structured_output.then_some("json"),
+                options: ModelOptions::from_request(req),
+                keep_alive: self.keep_alive_value(),
             })
             .send()
-            .context("failed calling Ollama")?;
+            .map_err(|err| {
+                ProviderCallError::new(
+                    ProviderErrorKind::Network,
+                    format!("failed calling Ollama: {err}"),
+                )
+                .at(&url)
+            })?;
 
         if !response.status().is_success() {
             let status = response.status();
             let body = response
                 .text()
                 .unwrap_or_else(|_| "<unavailable>".to_string());
-            return Err(anyhow!("Ollama request failed ({status}): {body}"));
+            return Err(ProviderCallError::http(
+                &url,
+                status.as_u16(),
+                format!("Ollama request failed ({status}): {body}"),
+            )
+            .into());
         }
 
-        let parsed: GenerateResponse = response
-            .json()
-            .context("failed to decode Ollama response")?;
+        let parsed: GenerateResponse = response.json().map_err(|err| {
+            ProviderCallError::new(
+                ProviderErrorKind::Model,
+                format!("failed to decode Ollama response: {err}"),
+            )
+            .at(&url)
+        })?;
 
-        Ok(parsed.response)
+        Ok(Completion {
+            text: parsed.response,
+            truncated: parsed.done_reason.as_deref() == Some("length"),
+        })
+    }
+}
+
+impl LlmClient for OllamaClient {
+    fn translate_to_js(&self, req: &LlmTranslateRequest, model: &str) -> Result<String> {
+        self.limits.check_prompt(req)?;
+        let client = self.http_client()?;
+        let messages = build_messages(req);
+        let mut chat_supported = true;
+        self.limits.complete(|partials| {
+            let messages = with_continuations(&messages, partials);
+            if chat_supported {
+                match self.chat(&client, req, &messages, model)? {
+                    ChatOutcome::Completed(completion) => return Ok(completion),
+                    ChatOutcome::Unsupported => chat_supported = false,
+                }
+            }
+            self.generate(&client, req, &flatten(&messages), model)
+        })
     }
 }
 
-fn build_prompt(req: &LlmTranslateRequest) -> String {
-    let hint = req
-        .language_hint
-        .as_ref()
-        .map_or("unknown".to_string(), ToString::to_string);
-    let repl_rule = if req.source_id == "<repl>" {
-        "Interactive REPL mode: output plain JavaScript script statements only. Do not emit import/export declarations.\n"
-    } else {
-        ""
+fn is_loopback(base_url: &str) -> bool {
+    let Ok(url) = Url::parse(base_url) else {
+        return false;
     };
-    let scope_context = req
-        .scope_context
-        .as_ref()
-        .map(|ctx| format!("Scope context:\n{ctx}\n"))
-        .unwrap_or_default();
+    let host = url.host_str().unwrap_or_default();
+    host == "localhost"
+        || host
+            .trim_matches(['[', ']'])
+            .parse::<IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Chat transcript for `/api/chat`: the system template is the system turn and every other
+/// prompt part (scope context, repair hint, input) gets its own user turn.
+fn build_messages(req: &LlmTranslateRequest) -> Vec<ChatMessage> {
+    req.prompts
+        .parts(req)
+        .into_iter()
+        .map(|(name, content)| ChatMessage {
+            role: if name == "system" { "system" } else { "user" }.to_string(),
+            content,
+        })
+        .collect()
+}
+
+/// Replays the partial replies of a cut-off answer, each followed by a request to continue.
+fn with_continuations(messages: &[ChatMessage], partials: &[String]) -> Vec<ChatMessage> {
+    let mut messages = messages.to_vec();
+    for partial in partials {
+        messages.push(ChatMessage {
+            role: "assistant".to_string(),
+            content: partial.clone(),
+        });
+        messages.push(ChatMessage {
+            role: "user".to_string(),
+            content: CONTINUATION_PROMPT.to_string(),
+        });
+    }
+    messages
+}
 
-    format!(
-        "You are a strict transpiler. Return only runnable modern JavaScript (Node-style ESM), no prose.\\n{}{}Source id: {}\\nLanguage hint: {}\\nINPUT START\\n{}\\nINPUT END",
-        repl_rule, scope_context, req.source_id, hint, req.source_text
-    )
+/// Single prompt for `/api/generate`, which has no notion of turns.
+fn flatten(messages: &[ChatMessage]) -> String {
+    messages
+        .iter()
+        .map(|message| message.content.as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 #[cfg(test)]
 mod tests {
-    use super::OllamaClient;
-    use klumo_llm::{LlmClient, LlmTranslateRequest};
+    use super::{ModelOptions, OllamaClient, WarmUpRequest, build_messages, is_loopback};
+    use klumo_llm::{LlmClient, LlmTranslateRequest, PromptTemplates};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    fn repl_request() -> LlmTranslateRequest {
+        LlmTranslateRequest {
+            source_text: "print x".to_string(),
+            source_id: "<repl>".to_string(),
+            language_hint: Some("python".to_string()),
+            scope_context: Some("let x = 1;".to_string()),
+            repair_hint: None,
+            temperature: Some(0.2),
+            top_p: None,
+            max_tokens: Some(256),
+            seed: None,
+            structured_output: false,
+            explain: false,
+            explain_javascript: None,
+            prompts: PromptTemplates::default(),
+        }
+    }
+
+    /// Serves canned responses keyed by request path, each used once and in order, and records
+    /// the paths it saw.
+    fn serve(
+        mut routes: Vec<(&'static str, u16, &'static str)>,
+    ) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind should work");
+        let addr = listener.local_addr().expect("local addr should work");
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let seen_by_server = Arc::clone(&seen);
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(routes.len()) {
+                let mut stream = stream.expect("accept should work");
+                let mut buf = [0_u8; 8192];
+                let n = stream.read(&mut buf).expect("read should work");
+                let head = String::from_utf8_lossy(&buf[..n]).to_string();
+                let path = head.split_whitespace().nth(1).unwrap_or("").to_string();
+                let (_, status, body) = routes
+                    .iter()
+                    .position(|(route, _, _)| *route == path)
+                    .map(|idx| routes.remove(idx))
+                    .unwrap_or(("", 500, "{}"));
+                seen_by_server.lock().expect("lock").push(path);
+                let response = format!(
+                    "HTTP/1.1 {status} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                stream
+                    .write_all(response.as_bytes())
+                    .expect("write should work");
+            }
+        });
+        (format!("http://{addr}"), seen)
+    }
+
+    #[test]
+    fn chat_messages_separate_system_rules_and_scope_context() {
+        let req = repl_request();
+        let messages = build_messages(&req);
+        let sentinel = klumo_llm::prompt_sentinel(&req);
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0].role, "system");
+        assert!(messages[0].content.contains("Interactive REPL mode"));
+        assert!(messages[1].content.contains("let x = 1;"));
+        assert!(messages[2].content.contains(&format!(
+            "INPUT-{sentinel} START\nprint x\nINPUT-{sentinel} END"
+        )));
+        assert!(!messages[2].content.contains("let x = 1;"));
+    }
+
+    #[test]
+    fn sampling_fields_map_to_ollama_options() {
+        let options = ModelOptions::from_request(&repl_request());
+        assert_eq!(
+            serde_json::to_value(options).expect("serialize should work"),
+            serde_json::json!({"temperature": 0.2_f32, "num_predict": 256})
+        );
+        let seeded = ModelOptions::from_request(&LlmTranslateRequest {
+            seed: Some(42),
+            ..repl_request()
+        });
+        assert_eq!(
+            serde_json::to_value(seeded).expect("serialize should work")["seed"],
+            42
+        );
+    }
+
+    #[test]
+    fn falls_back_to_generate_when_chat_endpoint_is_missing() {
+        let (base, seen) = serve(vec![
+            ("/api/chat", 404, "404 page not found"),
+            ("/api/generate", 200, r#"{"response":"console.log(x);"}"#),
+        ]);
+        let client = OllamaClient::new(base).expect("client should build");
+
+        let out = client
+            .translate_to_js(&repl_request(), "qwen2.5-coder:7b")
+            .expect("fallback should work");
+        assert_eq!(out, "console.log(x);");
+        assert_eq!(
+            *seen.lock().expect("lock"),
+            vec!["/api/chat".to_string(), "/api/generate".to_string()]
+        );
+    }
+
+    #[test]
+    fn continues_replies_cut_off_at_the_token_limit() {
+        let (base, seen) = serve(vec![
+            (
+                "/api/chat",
+                200,
+                r#"{"message":{"role":"assistant","content":"console.log("},"done_reason":"length"}"#,
+            ),
+            (
+                "/api/chat",
+                200,
+                r#"{"message":{"role":"assistant","content":"x);"},"done_reason":"stop"}"#,
+            ),
+        ]);
+        let client = OllamaClient::new(base).expect("client should build");
+
+        let out = client
+            .translate_to_js(&repl_request(), "qwen2.5-coder:7b")
+            .expect("continuation should work");
+        assert_eq!(out, "console.log(x);");
+        assert_eq!(seen.lock().expect("lock").len(), 2);
+    }
+
+    #[test]
+    fn lists_models_from_tags_endpoint() {
+        let (base, _) = serve(vec![(
+            "/api/tags",
+            200,
+            r#"{"models":[{"name":"qwen2.5-coder:7b","size":1},{"name":"llama3:latest"}]}"#,
+        )]);
+        let client = OllamaClient::new(base).expect("client should build");
+
+        let models = client.list_models().expect("listing should work");
+        assert_eq!(models, vec!["qwen2.5-coder:7b", "llama3:latest"]);
+    }
+
+    #[test]
+    fn embeds_text_through_the_embeddings_endpoint() {
+        let (base, seen) = serve(vec![
+            ("/api/embeddings", 200, r#"{"embedding":[0.5,-1.0,2.0]}"#),
+            ("/api/embeddings", 200, r#"{"embedding":[]}"#),
+        ]);
+        let client = OllamaClient::new(base).expect("client should build");
+
+        let embedding = client
+            .embed("nomic-embed-text", "print x")
+            .expect("embedding should work");
+        assert_eq!(embedding, vec![0.5, -1.0, 2.0]);
+        let err = client
+            .embed("qwen2.5-coder:7b", "print x")
+            .expect_err("an empty embedding should fail");
+        assert!(
+            err.to_string()
+                .contains("is qwen2.5-coder:7b an embedding model")
+        );
+        assert_eq!(seen.lock().expect("lock").len(), 2);
+    }
+
+    #[test]
+    fn pull_streams_progress_until_success() {
+        let (base, seen) = serve(vec![(
+            "/api/pull",
+            200,
+            "{\"status\":\"pulling manifest\"}\n{\"status\":\"downloading\",\"total\":200,\"completed\":50}\n{\"status\":\"success\"}\n",
+        )]);
+        let client = OllamaClient::new(base).expect("client should build");
+
+        let mut updates = Vec::new();
+        client
+            .pull_model("qwen2.5-coder:7b", |progress| {
+                updates.push(progress.clone())
+            })
+            .expect("pull should work");
+        assert_eq!(updates.len(), 3);
+        assert_eq!(updates[1].percent(), Some(25));
+        assert_eq!(updates[2].status, "success");
+        assert_eq!(*seen.lock().expect("lock"), vec!["/api/pull".to_string()]);
+
+        let (base, _) = serve(vec![(
+            "/api/pull",
+            200,
+            "{\"error\":\"pull model manifest: file does not exist\"}\n",
+        )]);
+        let err = OllamaClient::new(base)
+            .expect("client should build")
+            .pull_model("nope", |_| {})
+            .expect_err("pull error should surface");
+        assert!(err.to_string().contains("file does not exist"));
+    }
+
+    #[test]
+    fn keep_alive_numbers_are_seconds_and_other_values_durations() {
+        let body = |keep_alive: Option<&str>| {
+            let client = OllamaClient::new("http://127.0.0.1:1".to_string())
+                .expect("client should build")
+                .with_keep_alive(keep_alive.map(str::to_string));
+            serde_json::to_value(WarmUpRequest {
+                model: "m",
+                stream: false,
+                keep_alive: client.keep_alive_value(),
+            })
+            .expect("serialize should work")
+        };
+        assert_eq!(
+            body(Some("30m")),
+            serde_json::json!({"model": "m", "stream": false, "keep_alive": "30m"})
+        );
+        assert_eq!(body(Some(" -1 "))["keep_alive"], serde_json::json!(-1));
+        assert_eq!(body(Some("600"))["keep_alive"], serde_json::json!(600));
+        assert!(body(None).get("keep_alive").is_none());
+    }
+
+    #[test]
+    fn warm_up_loads_the_model_through_generate() {
+        let (base, seen) = serve(vec![(
+            "/api/generate",
+            200,
+            r#"{"model":"qwen2.5-coder:7b","response":"","done":true,"done_reason":"load"}"#,
+        )]);
+        let client = OllamaClient::new(base).expect("client should build");
+        client
+            .warm_up("qwen2.5-coder:7b")
+            .expect("warm-up should work");
+        assert_eq!(
+            *seen.lock().expect("lock"),
+            vec!["/api/generate".to_string()]
+        );
+
+        let (base, _) = serve(vec![(
+            "/api/generate",
+            404,
+            r#"{"error":"model 'nope' not found"}"#,
+        )]);
+        let err = OllamaClient::new(base)
+            .expect("client should build")
+            .warm_up("nope")
+            .expect_err("missing model should fail");
+        assert!(err.to_string().contains("not found"));
+    }
+
+    #[test]
+    fn loopback_servers_bypass_proxies() {
+        assert!(is_loopback("http://127.0.0.1:11434"));
+        assert!(is_loopback("http://localhost:11434/"));
+        assert!(is_loopback("http://[::1]:11434"));
+        assert!(!is_loopback("http://gpu-box.internal:11434"));
+        assert!(!is_loopback("not a url"));
+    }
+
+    #[test]
+    fn uses_chat_response_when_available() {
+        let (base, seen) = serve(vec![(
+            "/api/chat",
+            200,
+            r#"{"message":{"role":"assistant","content":"console.log(1);"}}"#,
+        )]);
+        let client = OllamaClient::new(base).expect("client should build");
+
+        let out = client
+            .translate_to_js(&repl_request(), "qwen2.5-coder:7b")
+            .expect("chat should work");
+        assert_eq!(out, "console.log(1);");
+        assert_eq!(*seen.lock().expect("lock"), vec!["/api/chat".to_string()]);
+    }
 
     #[test]
     #[ignore]
@@ -125,6 +853,15 @@ mod tests {
             source_id: "live.pseudo".to_string(),
             language_hint: Some("pseudocode".to_string()),
             scope_context: None,
+            repair_hint: None,
+            temperature: None,
+            top_p: None,
+            max_tokens: None,
+            seed: None,
+            structured_output: false,
+            explain: false,
+            explain_javascript: None,
+            prompts: PromptTemplates::default(),
         };
 
         let out = client
@@ -1,18 +1,67 @@
 use anyhow::{Context, Result, anyhow};
-use klumo_llm::{LlmClient, LlmTranslateRequest};
-use reqwest::blocking::Client;
+use klumo_llm::{
+    CONTINUATION_PROMPT, Completion, LlmClient, LlmTranslateRequest, ProviderCallError,
+    ProviderErrorKind, SizeLimits, TlsOptions, normalize_js_output,
+};
+use reqwest::StatusCode;
+use reqwest::blocking::{Client, Response};
+use reqwest::header::HeaderMap;
 use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
+/// Total time spent sleeping on 429 responses before a request gives up.
+pub const DEFAULT_RETRY_BUDGET: Duration = Duration::from_secs(60);
+
 #[derive(Clone)]
 pub struct OpenAiCompatibleClient {
     pub base_url: String,
     pub api_key: String,
+    pub retry_budget: Duration,
+    /// Print remaining-quota headers after each request.
+    pub verbose: bool,
+    pub tls: TlsOptions,
+    pub limits: SizeLimits,
+    /// Models the endpoint may fall back to, in order, when the requested one is unavailable.
+    /// Sent as OpenRouter's `models` list; other servers ignore it.
+    pub fallback_models: Vec<String>,
+    /// `x-ratelimit-remaining-requests` from the latest response, shared between clones; zero
+    /// after a 429.
+    remaining_requests: Arc<Mutex<Option<u64>>>,
 }
 
 impl OpenAiCompatibleClient {
     pub fn from_parts(base_url: String, api_key: String) -> Self {
-        Self { base_url, api_key }
+        Self {
+            base_url,
+            api_key,
+            retry_budget: DEFAULT_RETRY_BUDGET,
+            verbose: false,
+            tls: TlsOptions::default(),
+            limits: SizeLimits::default(),
+            fallback_models: Vec::new(),
+            remaining_requests: Arc::default(),
+        }
+    }
+
+    pub fn with_verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    pub fn with_tls(mut self, tls: TlsOptions) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    pub fn with_limits(mut self, limits: SizeLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_fallback_models(mut self, models: Vec<String>) -> Self {
+        self.fallback_models = models;
+        self
     }
 
     pub fn from_env() -> Result<Self> {
@@ -23,16 +72,71 @@ impl OpenAiCompatibleClient {
 
         Ok(Self::from_parts(base_url, api_key))
     }
+
+    /// Model ids served by the endpoint (`GET /models`), which also checks the credentials.
+    pub fn list_models(&self) -> Result<Vec<String>> {
+        let client = self
+            .tls
+            .apply_to(Client::builder())?
+            .timeout(Duration::from_secs(10))
+            .build()
+            .context("failed to build HTTP client")?;
+        let url = format!("{}/models", self.base_url.trim_end_matches('/'));
+        let response = client
+            .get(&url)
+            .bearer_auth(&self.api_key)
+            .send()
+            .with_context(|| format!("failed reaching {url}"))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = read_error_body(response);
+            return Err(anyhow!("model listing failed ({status}): {body}"));
+        }
+
+        let parsed: ModelList = response
+            .json()
+            .context("failed to decode OpenAI-compatible model list")?;
+        Ok(parsed.data.into_iter().map(|model| model.id).collect())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelList {
+    data: Vec<ModelEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelEntry {
+    id: String,
 }
 
 #[derive(Debug, Serialize)]
 struct ChatRequest {
     model: String,
-    temperature: f32,
+    /// `model` followed by the fallbacks, for OpenRouter-style routing.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    models: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<ResponseFormat>,
     messages: Vec<Message>,
 }
 
 #[derive(Debug, Serialize)]
+struct ResponseFormat {
+    #[serde(rename = "type")]
+    kind: &'static str,
+}
+
+#[derive(Debug, Clone, Serialize)]
 struct Message {
     role: String,
     content: String,
@@ -41,99 +145,716 @@ struct Message {
 #[derive(Debug, Deserialize)]
 struct ChatResponse {
     choices: Vec<Choice>,
+    /// Model that answered; differs from the requested one after a fallback.
+    #[serde(default)]
+    model: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct Choice {
     message: ChatMessage,
+    /// `"length"` when generation stopped at `max_tokens`.
+    finish_reason: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+/// Assistant message; code arrives in `content` or, from tool-calling servers, as call arguments.
+#[derive(Debug, Default, Deserialize)]
 struct ChatMessage {
-    content: String,
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ToolCall>,
+    /// Legacy single function call, still emitted by some compatible servers.
+    #[serde(default)]
+    function_call: Option<FunctionCall>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ToolCall {
+    function: FunctionCall,
+}
+
+#[derive(Debug, Deserialize)]
+struct FunctionCall {
+    #[serde(default)]
+    arguments: String,
+}
+
+/// Argument keys tool-calling models commonly put the generated program under.
+const CODE_ARGUMENT_KEYS: [&str; 4] = ["code", "source", "program", "content"];
+
+impl ChatMessage {
+    /// The message text, falling back to the arguments of the first tool or function call.
+    fn candidate(&self) -> Option<String> {
+        if let Some(content) = self.content.as_deref()
+            && !content.trim().is_empty()
+        {
+            return Some(content.to_string());
+        }
+        self.tool_calls
+            .iter()
+            .map(|call| &call.function)
+            .chain(self.function_call.as_ref())
+            .map(|function| unwrap_arguments(&function.arguments))
+            .find(|arguments| !arguments.trim().is_empty())
+    }
+}
+
+/// Pulls the code out of a JSON argument object. Objects with a `javascript` key are kept
+/// whole so the structured-output decoder still sees `notes` and `confidence`.
+fn unwrap_arguments(arguments: &str) -> String {
+    let Ok(serde_json::Value::Object(fields)) = serde_json::from_str(arguments) else {
+        return arguments.to_string();
+    };
+    if fields.contains_key("javascript") {
+        return arguments.to_string();
+    }
+    CODE_ARGUMENT_KEYS
+        .iter()
+        .find_map(|key| fields.get(*key).and_then(|value| value.as_str()))
+        .map(str::to_string)
+        .unwrap_or_else(|| arguments.to_string())
+}
+
+/// First choice that normalizes to JavaScript and finished on its own, else the first that
+/// normalizes, else the first with any text.
+fn best_choice(choices: Vec<Choice>) -> Option<Completion> {
+    let candidates: Vec<Completion> = choices
+        .into_iter()
+        .filter_map(|choice| {
+            Some(Completion {
+                text: choice.message.candidate()?,
+                truncated: choice.finish_reason.as_deref() == Some("length"),
+            })
+        })
+        .collect();
+    let usable = |candidate: &&Completion| normalize_js_output(&candidate.text).is_ok();
+    let pick = candidates
+        .iter()
+        .filter(usable)
+        .find(|candidate| !candidate.truncated)
+        .or_else(|| candidates.iter().find(usable))
+        .or(candidates.first())?;
+    Some(pick.clone())
 }
 
 impl LlmClient for OpenAiCompatibleClient {
     fn translate_to_js(&self, req: &LlmTranslateRequest, model: &str) -> Result<String> {
-        let prompt = build_prompt(req);
-        let body = ChatRequest {
-            model: model.to_string(),
-            temperature: 0.0,
-            messages: vec![
-                Message {
-                    role: "system".to_string(),
-                    content: "You convert arbitrary source text into executable JavaScript. Return code only."
-                        .to_string(),
-                },
-                Message {
-                    role: "user".to_string(),
-                    content: prompt,
-                },
-            ],
-        };
+        self.translate_reporting_model(req, model)
+            .map(|(raw, _)| raw)
+    }
 
-        let client = Client::builder()
+    fn translate_reporting_model(
+        &self,
+        req: &LlmTranslateRequest,
+        model: &str,
+    ) -> Result<(String, Option<String>)> {
+        self.limits.check_prompt(req)?;
+        let client = self
+            .tls
+            .apply_to(Client::builder())?
             .timeout(Duration::from_secs(45))
             .build()
             .context("failed to build HTTP client")?;
         let url = format!("{}/chat/completions", self.base_url.trim_end_matches('/'));
+        let messages = build_messages(req);
 
-        let response = client
-            .post(url)
-            .bearer_auth(&self.api_key)
-            .json(&body)
-            .send()
-            .context("failed calling OpenAI-compatible endpoint")?;
+        let mut served = None;
+        let raw = self.limits.complete(|partials| {
+            let body = self.chat_request(req, model, with_continuations(&messages, partials));
+            let (completion, answered_by) = self.send_chat(&client, &url, &body)?;
+            served = answered_by;
+            Ok(completion)
+        })?;
+        Ok((raw, served))
+    }
+
+    fn remaining_requests(&self) -> Option<u64> {
+        *self
+            .remaining_requests
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl OpenAiCompatibleClient {
+    fn chat_request(
+        &self,
+        req: &LlmTranslateRequest,
+        model: &str,
+        messages: Vec<Message>,
+    ) -> ChatRequest {
+        let models = if self.fallback_models.is_empty() {
+            Vec::new()
+        } else {
+            std::iter::once(model.to_string())
+                .chain(
+                    self.fallback_models
+                        .iter()
+                        .filter(|fallback| *fallback != model)
+                        .cloned(),
+                )
+                .collect()
+        };
+        ChatRequest {
+            model: model.to_string(),
+            models,
+            temperature: req.temperature,
+            top_p: req.top_p,
+            max_tokens: req.max_tokens,
+            seed: req.seed,
+            response_format: req.structured_output.then_some(ResponseFormat {
+                kind: "json_object",
+            }),
+            messages,
+        }
+    }
+
+    /// Sends one chat completion, retrying 429s within `retry_budget`. Returns the reply and
+    /// the model the server says produced it.
+    fn send_chat(
+        &self,
+        client: &Client,
+        url: &str,
+        body: &ChatRequest,
+    ) -> Result<(Completion, Option<String>)> {
+        let mut waited = Duration::ZERO;
+        let mut retries = 0u32;
+        let response = loop {
+            let response = client
+                .post(url)
+                .bearer_auth(&self.api_key)
+                .json(body)
+                .send()
+                .map_err(|err| {
+                    ProviderCallError::new(
+                        ProviderErrorKind::Network,
+                        format!("failed calling OpenAI-compatible endpoint: {err}"),
+                    )
+                    .at(url)
+                })?;
+            let limits = RateLimitInfo::from_headers(response.headers());
+            let throttled = response.status() == StatusCode::TOO_MANY_REQUESTS;
+            if let Some(remaining) = limits.remaining_requests.or(throttled.then_some(0)) {
+                *self
+                    .remaining_requests
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(remaining);
+            }
+            if !throttled {
+                if self.verbose
+                    && let Some(summary) = limits.summary()
+                {
+                    eprintln!("[klumo] openai rate limit: {summary}");
+                }
+                break response;
+            }
+
+            let body = read_error_body(response);
+            let delay = limits.retry_delay(retries);
+            let out_of_quota = body.contains("insufficient_quota");
+            if out_of_quota || waited + delay > self.retry_budget {
+                let mut err = ProviderCallError::http(
+                    url,
+                    429,
+                    format!(
+                        "OpenAI-compatible request was rate limited (429) after {retries} retries ({}s waited): {body}",
+                        waited.as_secs()
+                    ),
+                );
+                if out_of_quota {
+                    // Billing, not throttling: retrying later will not help.
+                    err.kind = ProviderErrorKind::Config;
+                }
+                return Err(err.into());
+            }
+            eprintln!(
+                "[klumo] openai rate limited, retrying in {:.1}s{}",
+                delay.as_secs_f32(),
+                limits
+                    .summary()
+                    .map(|summary| format!(" ({summary})"))
+                    .unwrap_or_default()
+            );
+            std::thread::sleep(delay);
+            waited += delay;
+            retries += 1;
+        };
 
         if !response.status().is_success() {
             let status = response.status();
-            let body = response
-                .text()
-                .unwrap_or_else(|_| "<unavailable>".to_string());
-            return Err(anyhow!(
-                "OpenAI-compatible request failed ({status}): {body}"
-            ));
+            let body = read_error_body(response);
+            return Err(ProviderCallError::http(
+                url,
+                status.as_u16(),
+                format!("OpenAI-compatible request failed ({status}): {body}"),
+            )
+            .into());
         }
 
-        let parsed: ChatResponse = response
-            .json()
-            .context("failed to decode OpenAI-compatible response")?;
-        let content = parsed
-            .choices
-            .first()
-            .map(|c| c.message.content.clone())
-            .ok_or_else(|| anyhow!("OpenAI-compatible response had no choices"))?;
-
-        Ok(content)
-    }
-}
-
-fn build_prompt(req: &LlmTranslateRequest) -> String {
-    let hint = req
-        .language_hint
-        .as_ref()
-        .map_or("unknown".to_string(), ToString::to_string);
-    let repl_rule = if req.source_id == "<repl>" {
-        "Interactive REPL mode: output plain JavaScript script statements only. Do not emit import/export declarations.\n"
-    } else {
-        ""
-    };
-    let scope_context = req
-        .scope_context
-        .as_ref()
-        .map(|ctx| format!("Scope context:\n{ctx}\n"))
-        .unwrap_or_default();
+        let parsed: ChatResponse = response.json().map_err(|err| {
+            ProviderCallError::new(
+                ProviderErrorKind::Model,
+                format!("failed to decode OpenAI-compatible response: {err}"),
+            )
+            .at(url)
+        })?;
+        let completion = best_choice(parsed.choices).ok_or_else(|| {
+            ProviderCallError::empty_output(
+                "OpenAI-compatible response had no choices with content",
+            )
+            .at(url)
+        })?;
+        Ok((completion, parsed.model))
+    }
+}
 
-    format!(
-        "You are a strict transpiler. Return only runnable modern JavaScript (Node-style ESM), no prose.\\n{}{}Source id: {}\\nLanguage hint: {}\\nINPUT START\\n{}\\nINPUT END",
-        repl_rule, scope_context, req.source_id, hint, req.source_text
-    )
+fn read_error_body(response: Response) -> String {
+    response
+        .text()
+        .unwrap_or_else(|_| "<unavailable>".to_string())
+}
+
+/// Rate-limit hints from `Retry-After` and the `x-ratelimit-*` response headers.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct RateLimitInfo {
+    retry_after: Option<Duration>,
+    reset_requests: Option<Duration>,
+    remaining_requests: Option<u64>,
+    remaining_tokens: Option<u64>,
+}
+
+impl RateLimitInfo {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+        };
+        let retry_after = header("retry-after-ms")
+            .and_then(|ms| ms.parse::<f64>().ok())
+            .and_then(|ms| header_delay(ms / 1000.0))
+            .or_else(|| {
+                header("retry-after")
+                    .and_then(|secs| secs.parse::<f64>().ok())
+                    .and_then(header_delay)
+            });
+        Self {
+            retry_after,
+            reset_requests: header("x-ratelimit-reset-requests").and_then(parse_reset_duration),
+            remaining_requests: header("x-ratelimit-remaining-requests")
+                .and_then(|v| v.parse().ok()),
+            remaining_tokens: header("x-ratelimit-remaining-tokens").and_then(|v| v.parse().ok()),
+        }
+    }
+
+    /// Server-provided delay when present, else exponential backoff from one second.
+    fn retry_delay(&self, retries: u32) -> Duration {
+        self.retry_after
+            .or(self.reset_requests)
+            .unwrap_or_else(|| Duration::from_secs(1 << retries.min(5)))
+    }
+
+    fn summary(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if let Some(requests) = self.remaining_requests {
+            parts.push(format!("remaining_requests={requests}"));
+        }
+        if let Some(tokens) = self.remaining_tokens {
+            parts.push(format!("remaining_tokens={tokens}"));
+        }
+        (!parts.is_empty()).then(|| parts.join(" "))
+    }
+}
+
+/// Longest delay a rate-limit header is taken to ask for. Anything longer exceeds every retry
+/// budget anyway, and the cap keeps adding delays from overflowing.
+const MAX_HEADER_DELAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A delay of `secs` from a header, capped at [`MAX_HEADER_DELAY`]; values that are not
+/// numbers of seconds (NaN, infinities) are ignored.
+fn header_delay(secs: f64) -> Option<Duration> {
+    if !secs.is_finite() {
+        return None;
+    }
+    Duration::try_from_secs_f64(secs.clamp(0.0, MAX_HEADER_DELAY.as_secs_f64())).ok()
+}
+
+/// Parses reset values such as `1s`, `6m0s`, `20ms`, or `1h2m3.5s`.
+fn parse_reset_duration(raw: &str) -> Option<Duration> {
+    let mut total = 0.0_f64;
+    let mut rest = raw.trim();
+    if rest.is_empty() {
+        return None;
+    }
+    while !rest.is_empty() {
+        let split = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .filter(|idx| *idx > 0)?;
+        let value: f64 = rest[..split].parse().ok()?;
+        rest = &rest[split..];
+        let (scale, unit_len) = if rest.starts_with("ms") {
+            (0.001, 2)
+        } else if rest.starts_with('h') {
+            (3600.0, 1)
+        } else if rest.starts_with('m') {
+            (60.0, 1)
+        } else if rest.starts_with('s') {
+            (1.0, 1)
+        } else {
+            return None;
+        };
+        total += value * scale;
+        rest = &rest[unit_len..];
+    }
+    header_delay(total)
+}
+
+/// System template as the system message; scope, repair, and input parts joined as the user message.
+fn build_messages(req: &LlmTranslateRequest) -> Vec<Message> {
+    let mut parts = req.prompts.parts(req).into_iter();
+    let system = parts.next().map(|(_, content)| content).unwrap_or_default();
+    let user = parts
+        .map(|(_, content)| content)
+        .collect::<Vec<_>>()
+        .join("\n");
+    vec![
+        Message {
+            role: "system".to_string(),
+            content: system,
+        },
+        Message {
+            role: "user".to_string(),
+            content: user,
+        },
+    ]
+}
+
+/// Replays the partial replies of a cut-off answer, each followed by a request to continue.
+fn with_continuations(messages: &[Message], partials: &[String]) -> Vec<Message> {
+    let mut messages = messages.to_vec();
+    for partial in partials {
+        messages.push(Message {
+            role: "assistant".to_string(),
+            content: partial.clone(),
+        });
+        messages.push(Message {
+            role: "user".to_string(),
+            content: CONTINUATION_PROMPT.to_string(),
+        });
+    }
+    messages
 }
 
 #[cfg(test)]
 mod tests {
-    use super::OpenAiCompatibleClient;
-    use klumo_llm::{LlmClient, LlmTranslateRequest};
+    use super::{
+        ChatResponse, MAX_HEADER_DELAY, OpenAiCompatibleClient, RateLimitInfo, best_choice,
+        parse_reset_duration,
+    };
+    use klumo_llm::{
+        LlmClient, LlmTranslateRequest, PromptTemplates, ProviderCallError, ProviderErrorKind,
+        SizeLimits, TlsOptions,
+    };
+    use reqwest::header::{HeaderMap, HeaderValue};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    fn request() -> LlmTranslateRequest {
+        LlmTranslateRequest {
+            source_text: "write hello".to_string(),
+            source_id: "hello.pseudo".to_string(),
+            language_hint: Some("pseudocode".to_string()),
+            scope_context: None,
+            repair_hint: None,
+            temperature: None,
+            top_p: None,
+            max_tokens: None,
+            seed: None,
+            structured_output: false,
+            explain: false,
+            explain_javascript: None,
+            prompts: PromptTemplates::default(),
+        }
+    }
+
+    /// Answers one connection per entry, in order, with `(status, extra headers, body)`.
+    fn serve(responses: Vec<(u16, &'static str, &'static str)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind should work");
+        let addr = listener.local_addr().expect("local addr should work");
+        std::thread::spawn(move || {
+            for ((status, headers, body), stream) in responses.into_iter().zip(listener.incoming())
+            {
+                let mut stream = stream.expect("accept should work");
+                let mut buf = [0_u8; 8192];
+                let _ = stream.read(&mut buf).expect("read should work");
+                let response = format!(
+                    "HTTP/1.1 {status} X\r\nContent-Type: application/json\r\n{headers}Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                stream
+                    .write_all(response.as_bytes())
+                    .expect("write should work");
+            }
+        });
+        format!("http://{addr}")
+    }
+
+    const OK_BODY: &str = r#"{"choices":[{"message":{"content":"console.log('hello');"}}]}"#;
+
+    #[test]
+    fn reset_durations_parse_openai_formats() {
+        assert_eq!(parse_reset_duration("1s"), Some(Duration::from_secs(1)));
+        assert_eq!(parse_reset_duration("6m0s"), Some(Duration::from_secs(360)));
+        assert_eq!(
+            parse_reset_duration("20ms"),
+            Some(Duration::from_millis(20))
+        );
+        assert_eq!(
+            parse_reset_duration("1h2m3.5s"),
+            Some(Duration::from_secs_f64(3723.5))
+        );
+        assert_eq!(parse_reset_duration("soon"), None);
+        assert_eq!(parse_reset_duration(""), None);
+        assert_eq!(parse_reset_duration("1.2.3s"), None);
+        assert_eq!(
+            parse_reset_duration(&format!("{}h", "9".repeat(400))),
+            None,
+            "overflowing to infinity"
+        );
+        assert_eq!(
+            parse_reset_duration("99999999999999999999h"),
+            Some(MAX_HEADER_DELAY)
+        );
+    }
+
+    #[test]
+    fn rate_limit_headers_prefer_retry_after() {
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", HeaderValue::from_static("2"));
+        headers.insert(
+            "x-ratelimit-reset-requests",
+            HeaderValue::from_static("30s"),
+        );
+        headers.insert(
+            "x-ratelimit-remaining-requests",
+            HeaderValue::from_static("0"),
+        );
+        headers.insert(
+            "x-ratelimit-remaining-tokens",
+            HeaderValue::from_static("1500"),
+        );
+
+        let info = RateLimitInfo::from_headers(&headers);
+        assert_eq!(info.retry_delay(0), Duration::from_secs(2));
+        assert_eq!(
+            info.summary().as_deref(),
+            Some("remaining_requests=0 remaining_tokens=1500")
+        );
+        assert_eq!(
+            RateLimitInfo::default().retry_delay(2),
+            Duration::from_secs(4)
+        );
+
+        for (name, value, expected) in [
+            ("retry-after", "inf", None),
+            ("retry-after", "NaN", None),
+            ("retry-after", "1e300", Some(MAX_HEADER_DELAY)),
+            ("retry-after", "-5", Some(Duration::ZERO)),
+            ("retry-after-ms", "1e308", Some(MAX_HEADER_DELAY)),
+        ] {
+            let mut headers = HeaderMap::new();
+            headers.insert(name, HeaderValue::from_static(value));
+            assert_eq!(
+                RateLimitInfo::from_headers(&headers).retry_after,
+                expected,
+                "{name}: {value}"
+            );
+        }
+    }
+
+    #[test]
+    fn retries_after_rate_limit_then_succeeds() {
+        let base_url = serve(vec![
+            (
+                429,
+                "Retry-After: 0\r\n",
+                r#"{"error":{"code":"rate_limit_exceeded"}}"#,
+            ),
+            (200, "x-ratelimit-remaining-requests: 9\r\n", OK_BODY),
+        ]);
+        let client = OpenAiCompatibleClient::from_parts(base_url, "key".to_string());
+        assert_eq!(client.remaining_requests(), None);
+        let out = client
+            .translate_to_js(&request(), "gpt-test")
+            .expect("second attempt should succeed");
+        assert_eq!(out, "console.log('hello');");
+        assert_eq!(client.remaining_requests(), Some(9));
+    }
+
+    #[test]
+    fn gives_up_when_retry_budget_is_exhausted() {
+        let base_url = serve(vec![(429, "Retry-After: 5\r\n", "slow down")]);
+        let mut client = OpenAiCompatibleClient::from_parts(base_url, "key".to_string());
+        client.retry_budget = Duration::from_secs(1);
+        let err = client
+            .translate_to_js(&request(), "gpt-test")
+            .expect_err("budget should be exceeded");
+        assert!(format!("{err:#}").contains("rate limited (429) after 0 retries"));
+        assert_eq!(client.remaining_requests(), Some(0));
+    }
+
+    #[test]
+    fn continues_replies_cut_off_at_the_token_limit() {
+        let base_url = serve(vec![
+            (
+                200,
+                "",
+                r#"{"choices":[{"message":{"content":"console.log("},"finish_reason":"length"}]}"#,
+            ),
+            (
+                200,
+                "",
+                r#"{"choices":[{"message":{"content":"'hello');"},"finish_reason":"stop"}]}"#,
+            ),
+        ]);
+        let client = OpenAiCompatibleClient::from_parts(base_url, "key".to_string());
+        let out = client
+            .translate_to_js(&request(), "gpt-test")
+            .expect("continuation should succeed");
+        assert_eq!(out, "console.log('hello');");
+    }
+
+    #[test]
+    fn fallback_models_are_sent_and_the_serving_model_reported() {
+        let client =
+            OpenAiCompatibleClient::from_parts("http://127.0.0.1:9".to_string(), "key".to_string())
+                .with_fallback_models(vec![
+                    "anthropic/claude-sonnet".to_string(),
+                    "openai/gpt-4.1".to_string(),
+                ]);
+        let body =
+            serde_json::to_value(client.chat_request(&request(), "openai/gpt-4.1", Vec::new()))
+                .expect("request should serialize");
+        assert_eq!(
+            body["models"],
+            serde_json::json!(["openai/gpt-4.1", "anthropic/claude-sonnet"])
+        );
+        let plain = OpenAiCompatibleClient::from_parts(String::new(), String::new());
+        let body = serde_json::to_value(plain.chat_request(&request(), "gpt-test", Vec::new()))
+            .expect("request should serialize");
+        assert!(body.get("models").is_none());
+
+        let base_url = serve(vec![(
+            200,
+            "",
+            r#"{"model":"anthropic/claude-sonnet","choices":[{"message":{"content":"console.log('hello');"}}]}"#,
+        )]);
+        let client = OpenAiCompatibleClient::from_parts(base_url, "key".to_string());
+        let (out, served) = client
+            .translate_reporting_model(&request(), "openai/gpt-4.1")
+            .expect("request should succeed");
+        assert_eq!(out, "console.log('hello');");
+        assert_eq!(served.as_deref(), Some("anthropic/claude-sonnet"));
+    }
+
+    fn pick(body: &str) -> Option<String> {
+        let parsed: ChatResponse = serde_json::from_str(body).expect("response should parse");
+        best_choice(parsed.choices).map(|completion| completion.text)
+    }
+
+    #[test]
+    fn unwraps_tool_and_function_call_arguments() {
+        assert_eq!(
+            pick(
+                r#"{"choices":[{"message":{"content":null,"tool_calls":[{"type":"function","function":{"name":"emit","arguments":"{\"code\":\"console.log(1);\"}"}}]},"finish_reason":"tool_calls"}]}"#
+            )
+            .as_deref(),
+            Some("console.log(1);")
+        );
+        assert_eq!(
+            pick(
+                r#"{"choices":[{"message":{"function_call":{"name":"emit","arguments":"console.log(2);"}}}]}"#
+            )
+            .as_deref(),
+            Some("console.log(2);")
+        );
+        let structured = r#"{\"javascript\":\"x\",\"notes\":\"n\"}"#;
+        let body = format!(
+            r#"{{"choices":[{{"message":{{"tool_calls":[{{"function":{{"arguments":"{structured}"}}}}]}}}}]}}"#
+        );
+        assert_eq!(
+            pick(&body).as_deref(),
+            Some(r#"{"javascript":"x","notes":"n"}"#)
+        );
+    }
+
+    #[test]
+    fn prefers_complete_usable_choices() {
+        assert_eq!(
+            pick(
+                r#"{"choices":[{"message":{"content":"   "}},{"message":{"content":"console.log("},"finish_reason":"length"},{"message":{"content":"console.log(3);"},"finish_reason":"stop"}]}"#
+            )
+            .as_deref(),
+            Some("console.log(3);")
+        );
+        assert_eq!(pick(r#"{"choices":[]}"#), None);
+    }
+
+    #[test]
+    fn oversized_prompts_fail_before_sending() {
+        let client =
+            OpenAiCompatibleClient::from_parts("http://127.0.0.1:9".to_string(), "key".to_string())
+                .with_limits(SizeLimits {
+                    max_prompt_bytes: 64,
+                    ..SizeLimits::default()
+                });
+        let err = client
+            .translate_to_js(&request(), "gpt-test")
+            .expect_err("prompt should be refused");
+        let call = err
+            .downcast_ref::<ProviderCallError>()
+            .expect("typed error");
+        assert_eq!(call.kind, ProviderErrorKind::Config);
+        assert!(call.endpoint.is_none());
+    }
+
+    #[test]
+    fn lists_models_and_reports_auth_failures() {
+        let base_url = serve(vec![(
+            200,
+            "",
+            r#"{"object":"list","data":[{"id":"gpt-4.1-mini","object":"model"}]}"#,
+        )]);
+        let client = OpenAiCompatibleClient::from_parts(base_url, "key".to_string());
+        assert_eq!(
+            client.list_models().expect("listing should work"),
+            vec!["gpt-4.1-mini"]
+        );
+
+        let base_url = serve(vec![(401, "", r#"{"error":"invalid api key"}"#)]);
+        let client = OpenAiCompatibleClient::from_parts(base_url, "bad".to_string());
+        let err = client.list_models().expect_err("401 should fail");
+        assert!(err.to_string().contains("401"));
+    }
+
+    #[test]
+    fn ca_bundle_is_loaded_before_the_request() {
+        let client = OpenAiCompatibleClient::from_parts(
+            "https://127.0.0.1:9".to_string(),
+            "key".to_string(),
+        )
+        .with_tls(TlsOptions {
+            ca_bundle: Some("/nonexistent/corp-ca.pem".into()),
+            accept_invalid_certs: false,
+        });
+        let err = client
+            .translate_to_js(&request(), "gpt-test")
+            .expect_err("missing bundle should fail");
+        assert!(format!("{err:#}").contains("failed reading CA bundle /nonexistent/corp-ca.pem"));
+    }
 
     #[test]
     #[ignore]
@@ -153,6 +874,15 @@ mod tests {
             source_id: "live.pseudo".to_string(),
             language_hint: Some("pseudocode".to_string()),
             scope_context: None,
+            repair_hint: None,
+            temperature: None,
+            top_p: None,
+            max_tokens: None,
+            seed: None,
+            structured_output: false,
+            explain: false,
+            explain_javascript: None,
+            prompts: PromptTemplates::default(),
         };
 
         let out = client
@@ -0,0 +1,385 @@
+use crate::{
+    LlmTranslateRequest, LlmTranslateResponse, ProviderDescriptor, ProviderSelection,
+    TranslationService, override_chain,
+};
+use anyhow::Result;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::{Pin, pin};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+/// Worker threads of the shared pool that runs blocking provider calls for async callers.
+pub const BLOCKING_POOL_THREADS: usize = 8;
+
+/// `TranslationService` for async callers such as the daemon and the web playground. Futures
+/// own their inputs and are `'static`, so any executor can spawn them; none of them needs a
+/// particular runtime.
+pub trait AsyncTranslationService {
+    fn candidate_chain(&self, selection: ProviderSelection) -> Vec<ProviderDescriptor>;
+
+    /// See `TranslationService::resolved_chain`.
+    fn resolved_chain(
+        &self,
+        selection: ProviderSelection,
+        model_override: Option<&str>,
+    ) -> Vec<ProviderDescriptor> {
+        override_chain(self.candidate_chain(selection), model_override)
+    }
+
+    fn prompt_fingerprint(&self) -> String;
+
+    fn translate_async(
+        &self,
+        selection: ProviderSelection,
+        req: LlmTranslateRequest,
+        model_override: Option<String>,
+    ) -> impl Future<Output = Result<LlmTranslateResponse>> + Send + 'static;
+
+    /// Results in request order. The default starts every translation before awaiting any.
+    fn translate_batch_async(
+        &self,
+        selection: ProviderSelection,
+        reqs: Vec<LlmTranslateRequest>,
+        model_override: Option<String>,
+    ) -> impl Future<Output = Vec<Result<LlmTranslateResponse>>> + Send + 'static {
+        let pending: Vec<_> = reqs
+            .into_iter()
+            .map(|req| self.translate_async(selection.clone(), req, model_override.clone()))
+            .collect();
+        async move {
+            let mut results = Vec::with_capacity(pending.len());
+            for translation in pending {
+                results.push(translation.await);
+            }
+            results
+        }
+    }
+}
+
+/// A shared blocking service, such as `Arc<ProviderRouter>`, serves async callers by running
+/// each call on the `BlockingPool` instead of a thread of its own.
+impl<S> AsyncTranslationService for Arc<S>
+where
+    S: TranslationService + Send + Sync + 'static,
+{
+    fn candidate_chain(&self, selection: ProviderSelection) -> Vec<ProviderDescriptor> {
+        TranslationService::candidate_chain(self.as_ref(), selection)
+    }
+
+    fn resolved_chain(
+        &self,
+        selection: ProviderSelection,
+        model_override: Option<&str>,
+    ) -> Vec<ProviderDescriptor> {
+        TranslationService::resolved_chain(self.as_ref(), selection, model_override)
+    }
+
+    fn prompt_fingerprint(&self) -> String {
+        TranslationService::prompt_fingerprint(self.as_ref())
+    }
+
+    fn translate_async(
+        &self,
+        selection: ProviderSelection,
+        req: LlmTranslateRequest,
+        model_override: Option<String>,
+    ) -> impl Future<Output = Result<LlmTranslateResponse>> + Send + 'static {
+        let service = Arc::clone(self);
+        BlockingPool::shared()
+            .run(move || service.translate(selection, &req, model_override.as_deref()))
+    }
+
+    fn translate_batch_async(
+        &self,
+        selection: ProviderSelection,
+        reqs: Vec<LlmTranslateRequest>,
+        model_override: Option<String>,
+    ) -> impl Future<Output = Vec<Result<LlmTranslateResponse>>> + Send + 'static {
+        let service = Arc::clone(self);
+        BlockingPool::shared()
+            .run(move || service.translate_batch(selection, &reqs, model_override.as_deref()))
+    }
+}
+
+/// Drives an async service from synchronous code, e.g. to hand it to `CompilerRouter`.
+pub struct Blocking<A>(pub A);
+
+impl<A: AsyncTranslationService> TranslationService for Blocking<A> {
+    fn candidate_chain(&self, selection: ProviderSelection) -> Vec<ProviderDescriptor> {
+        self.0.candidate_chain(selection)
+    }
+
+    fn resolved_chain(
+        &self,
+        selection: ProviderSelection,
+        model_override: Option<&str>,
+    ) -> Vec<ProviderDescriptor> {
+        self.0.resolved_chain(selection, model_override)
+    }
+
+    fn translate(
+        &self,
+        selection: ProviderSelection,
+        req: &LlmTranslateRequest,
+        model_override: Option<&str>,
+    ) -> Result<LlmTranslateResponse> {
+        block_on(
+            self.0
+                .translate_async(selection, req.clone(), model_override.map(str::to_string)),
+        )
+    }
+
+    fn translate_batch(
+        &self,
+        selection: ProviderSelection,
+        reqs: &[LlmTranslateRequest],
+        model_override: Option<&str>,
+    ) -> Vec<Result<LlmTranslateResponse>> {
+        block_on(self.0.translate_batch_async(
+            selection,
+            reqs.to_vec(),
+            model_override.map(str::to_string),
+        ))
+    }
+
+    fn prompt_fingerprint(&self) -> String {
+        self.0.prompt_fingerprint()
+    }
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Runs `future` to completion on the current thread, parking between polls.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        thread::park();
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A fixed set of threads for blocking work started from async code.
+pub struct BlockingPool {
+    jobs: Sender<Job>,
+}
+
+impl BlockingPool {
+    pub fn new(threads: usize) -> Self {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let queue = Arc::new(Mutex::new(queue));
+        for index in 0..threads.max(1) {
+            let queue = Arc::clone(&queue);
+            thread::Builder::new()
+                .name(format!("klumo-blocking-{index}"))
+                .spawn(move || work(&queue))
+                .expect("failed to spawn blocking pool thread");
+        }
+        Self { jobs }
+    }
+
+    /// The process-wide pool of `BLOCKING_POOL_THREADS` threads, started on first use.
+    pub fn shared() -> &'static Self {
+        static SHARED: OnceLock<BlockingPool> = OnceLock::new();
+        SHARED.get_or_init(|| Self::new(BLOCKING_POOL_THREADS))
+    }
+
+    /// Queues `job` at once; the returned future resolves to its output.
+    pub fn run<T, F>(&self, job: F) -> BlockingTask<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let state = Arc::new(Mutex::new(TaskState {
+            output: None,
+            waker: None,
+        }));
+        let shared = Arc::clone(&state);
+        let job: Job = Box::new(move || {
+            let output = panic::catch_unwind(AssertUnwindSafe(job));
+            let mut state = lock(&shared);
+            state.output = Some(output);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        });
+        // Workers never exit while the pool exists, so the queue is always open.
+        let _ = self.jobs.send(job);
+        BlockingTask { state }
+    }
+}
+
+fn work(queue: &Mutex<Receiver<Job>>) {
+    loop {
+        let job = queue
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .recv();
+        match job {
+            Ok(job) => job(),
+            Err(_) => return,
+        }
+    }
+}
+
+struct TaskState<T> {
+    output: Option<thread::Result<T>>,
+    waker: Option<Waker>,
+}
+
+fn lock<T>(state: &Mutex<TaskState<T>>) -> std::sync::MutexGuard<'_, TaskState<T>> {
+    state
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Output of a job queued on a `BlockingPool`. A panic in the job resumes in the awaiting task.
+pub struct BlockingTask<T> {
+    state: Arc<Mutex<TaskState<T>>>,
+}
+
+impl<T> Future for BlockingTask<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut state = lock(&self.state);
+        match state.output.take() {
+            Some(Ok(output)) => Poll::Ready(output),
+            Some(Err(payload)) => panic::resume_unwind(payload),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AsyncTranslationService, Blocking, BlockingPool, block_on};
+    use crate::{
+        LlmTranslateRequest, LlmTranslateResponse, PromptTemplates, Provider, ProviderDescriptor,
+        ProviderSelection, TranslationService,
+    };
+    use anyhow::{Result, anyhow};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct EchoService {
+        calls: AtomicUsize,
+    }
+
+    impl TranslationService for EchoService {
+        fn candidate_chain(&self, _selection: ProviderSelection) -> Vec<ProviderDescriptor> {
+            vec![ProviderDescriptor {
+                provider: Provider::OpenAiCompatible,
+                model: "echo".to_string(),
+            }]
+        }
+
+        fn translate(
+            &self,
+            _selection: ProviderSelection,
+            req: &LlmTranslateRequest,
+            model_override: Option<&str>,
+        ) -> Result<LlmTranslateResponse> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if req.source_text.is_empty() {
+                return Err(anyhow!("nothing to translate"));
+            }
+            std::thread::sleep(Duration::from_millis(10));
+            Ok(LlmTranslateResponse {
+                javascript: format!("console.log({:?});", req.source_text),
+                provider: Provider::OpenAiCompatible,
+                model: model_override.unwrap_or("echo").to_string(),
+                served_model: None,
+                notes: None,
+                confidence: None,
+                explanation: None,
+            })
+        }
+    }
+
+    fn req(source_text: &str) -> LlmTranslateRequest {
+        LlmTranslateRequest {
+            source_text: source_text.to_string(),
+            source_id: "snippet.pseudo".to_string(),
+            language_hint: None,
+            scope_context: None,
+            repair_hint: None,
+            temperature: None,
+            top_p: None,
+            max_tokens: None,
+            seed: None,
+            structured_output: false,
+            explain: false,
+            explain_javascript: None,
+            prompts: PromptTemplates::default(),
+        }
+    }
+
+    #[test]
+    fn shared_blocking_services_translate_asynchronously() {
+        let service = Arc::new(EchoService::default());
+        let pending: Vec<_> = ["a", "", "c"]
+            .into_iter()
+            .map(|text| service.translate_async(ProviderSelection::Auto, req(text), None))
+            .collect();
+        let results: Vec<Result<LlmTranslateResponse>> =
+            pending.into_iter().map(block_on).collect();
+        assert_eq!(
+            results[0].as_ref().expect("a").javascript,
+            "console.log(\"a\");"
+        );
+        assert!(results[1].is_err());
+        assert_eq!(
+            results[2].as_ref().expect("c").javascript,
+            "console.log(\"c\");"
+        );
+
+        let batch = block_on(service.translate_batch_async(
+            ProviderSelection::Auto,
+            vec![req("x"), req("y")],
+            Some("big".to_string()),
+        ));
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch[1].as_ref().expect("y").model, "big");
+        assert_eq!(service.calls.load(Ordering::SeqCst), 5);
+    }
+
+    #[test]
+    fn blocking_adapter_serves_sync_callers() {
+        let service = Blocking(Arc::new(EchoService::default()));
+        let response = service
+            .translate(ProviderSelection::Auto, &req("hi"), None)
+            .expect("translate should work");
+        assert_eq!(response.javascript, "console.log(\"hi\");");
+        assert_eq!(
+            service.candidate_chain(ProviderSelection::Auto)[0].model,
+            "echo"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "job failed")]
+    fn job_panics_resume_in_the_awaiting_task() {
+        let pool = BlockingPool::new(1);
+        block_on(pool.run(|| panic!("job failed")));
+    }
+}
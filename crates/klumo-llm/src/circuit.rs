@@ -0,0 +1,120 @@
+use crate::Provider;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Consecutive failures that open a provider's circuit by default.
+pub const DEFAULT_CIRCUIT_FAILURES: u32 = 3;
+/// Failures further apart than this do not count as consecutive.
+pub const DEFAULT_CIRCUIT_WINDOW: Duration = Duration::from_secs(300);
+/// How long an open circuit skips its provider.
+pub const DEFAULT_CIRCUIT_COOLDOWN: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Default)]
+struct CircuitState {
+    /// Times of the current run of consecutive failures, oldest first.
+    failures: Vec<Instant>,
+    open_until: Option<Instant>,
+    /// The cooldown passed and one trial call is allowed; its failure reopens the circuit.
+    half_open: bool,
+}
+
+/// Skips a provider for `cooldown` after `failures` consecutive failures within `window`, so a
+/// dead endpoint does not add its timeout to every later compile of a long session.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    pub failures: u32,
+    pub window: Duration,
+    pub cooldown: Duration,
+    states: Mutex<HashMap<Provider, CircuitState>>,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_CIRCUIT_FAILURES,
+            DEFAULT_CIRCUIT_WINDOW,
+            DEFAULT_CIRCUIT_COOLDOWN,
+        )
+    }
+}
+
+impl CircuitBreaker {
+    pub fn new(failures: u32, window: Duration, cooldown: Duration) -> Self {
+        Self {
+            failures: failures.max(1),
+            window,
+            cooldown,
+            states: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Time left before `provider` is tried again, or `None` when calls may go through.
+    pub fn open_for(&self, provider: &Provider) -> Option<Duration> {
+        let mut states = self.lock();
+        let state = states.get_mut(provider)?;
+        let open_until = state.open_until?;
+        let now = Instant::now();
+        if now < open_until {
+            return Some(open_until - now);
+        }
+        state.open_until = None;
+        state.half_open = true;
+        None
+    }
+
+    pub fn record_success(&self, provider: &Provider) {
+        self.lock().remove(provider);
+    }
+
+    /// Counts a failure; returns `true` when it opened the circuit.
+    pub fn record_failure(&self, provider: &Provider) -> bool {
+        let mut states = self.lock();
+        let state = states.entry(provider.clone()).or_default();
+        let now = Instant::now();
+        state
+            .failures
+            .retain(|at| now.duration_since(*at) <= self.window);
+        state.failures.push(now);
+        if state.half_open || state.failures.len() >= self.failures as usize {
+            state.failures.clear();
+            state.half_open = false;
+            state.open_until = Some(now + self.cooldown);
+            return true;
+        }
+        false
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Provider, CircuitState>> {
+        self.states
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CircuitBreaker;
+    use crate::Provider;
+    use std::time::Duration;
+
+    #[test]
+    fn opens_after_consecutive_failures_and_half_opens_after_cooldown() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60), Duration::from_secs(60));
+        assert!(!breaker.record_failure(&Provider::Ollama));
+        breaker.record_success(&Provider::Ollama);
+        assert!(!breaker.record_failure(&Provider::Ollama));
+        assert!(breaker.record_failure(&Provider::Ollama));
+        assert!(breaker.open_for(&Provider::Ollama).is_some());
+        assert!(breaker.open_for(&Provider::OpenAiCompatible).is_none());
+
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60), Duration::ZERO);
+        breaker.record_failure(&Provider::Ollama);
+        assert!(breaker.record_failure(&Provider::Ollama));
+        assert!(breaker.open_for(&Provider::Ollama).is_none());
+        assert!(
+            breaker.record_failure(&Provider::Ollama),
+            "a failed trial call reopens at once"
+        );
+    }
+}
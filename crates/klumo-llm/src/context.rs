@@ -0,0 +1,357 @@
+/// Source lines from the end of the previous chunk repeated as context for the next one.
+pub const CHUNK_OVERLAP_LINES: usize = 5;
+
+/// Lines of already generated JavaScript shown to the model when translating a later chunk.
+const PREVIOUS_JS_TAIL_LINES: usize = 40;
+
+/// Rough token count for `text` (about four bytes per token for code and English). Good
+/// enough to decide when a source must be split; it is not a tokenizer.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.len().div_ceil(4)
+}
+
+/// Source tokens that fit in one request for a model with `context_window` tokens, leaving
+/// room for the prompt template and the generated JavaScript.
+pub fn chunk_budget(context_window: usize) -> usize {
+    (context_window / 3).max(1)
+}
+
+/// One piece of a source too large to translate in a single request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceChunk {
+    /// Zero-based position of this chunk.
+    pub index: usize,
+    pub total: usize,
+    /// Lines to translate.
+    pub text: String,
+    /// Trailing lines of the previous chunk, shown as context only.
+    pub overlap: String,
+}
+
+impl SourceChunk {
+    /// Scope context for this chunk: the caller's own context, a continuation instruction, the
+    /// tail of the JavaScript generated so far, and the overlapping source lines.
+    pub fn scope_context(&self, source_id: &str, base: Option<&str>, previous_js: &str) -> String {
+        let mut sections: Vec<String> = base.map(str::to_string).into_iter().collect();
+        sections.push(format!(
+            "This input is part {} of {} of {source_id}. Translate only the INPUT lines. The program continues from the JavaScript below: reuse its bindings and do not redeclare or repeat them.",
+            self.index + 1,
+            self.total
+        ));
+        if !previous_js.trim().is_empty() {
+            let lines: Vec<&str> = previous_js.lines().collect();
+            let tail = lines[lines.len().saturating_sub(PREVIOUS_JS_TAIL_LINES)..].join("\n");
+            sections.push(format!(
+                "JavaScript generated for the previous parts (last lines):\n{tail}"
+            ));
+        }
+        if !self.overlap.is_empty() {
+            sections.push(format!(
+                "Source lines just before this part, already translated:\n{}",
+                self.overlap
+            ));
+        }
+        sections.join("\n\n")
+    }
+}
+
+/// Splits `source` into chunks of at most about `budget` tokens. Cuts prefer the start of a
+/// top-level item (see [`item_starts`]) so blocks stay whole; a single line larger than the
+/// budget becomes its own chunk. Sources within budget come back as one chunk.
+pub fn split_source(source: &str, budget: usize) -> Vec<SourceChunk> {
+    let lines: Vec<&str> = source.split_inclusive('\n').collect();
+    let starts = item_starts(&lines);
+    let mut pieces: Vec<String> = Vec::new();
+    let mut start = 0;
+    let mut current_tokens = 0;
+
+    for (index, line) in lines.iter().enumerate() {
+        let line_tokens = estimate_tokens(line);
+        if index > start && current_tokens + line_tokens > budget {
+            let cut = if starts[index] {
+                index
+            } else {
+                (start + 1..index)
+                    .rev()
+                    .find(|&candidate| starts[candidate])
+                    .unwrap_or(index)
+            };
+            pieces.push(lines[start..cut].concat());
+            start = cut;
+            current_tokens = lines[start..index]
+                .iter()
+                .map(|line| estimate_tokens(line))
+                .sum();
+        }
+        current_tokens += line_tokens;
+    }
+    if start < lines.len() || pieces.is_empty() {
+        pieces.push(lines[start..].concat());
+    }
+    into_chunks(pieces)
+}
+
+/// Splits `source` into top-level units, about one per function or class: an item followed by
+/// lines that are not items of their own (indented, or inside its brackets) is a block and a
+/// unit of its own, runs of one-line statements between blocks form one unit, and blank lines
+/// and lines closing a block (`}`, `end`, `else`, ...) stay with the unit above them. Unlike
+/// [`split_source`] the cuts depend only on the source's shape, not on a budget, so editing
+/// one unit leaves the text of the others unchanged.
+pub fn split_units(source: &str) -> Vec<SourceChunk> {
+    let lines: Vec<&str> = source.split_inclusive('\n').collect();
+    let starts = item_starts(&lines);
+    let mut pieces: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut in_block = false;
+    for (index, line) in lines.iter().enumerate() {
+        if starts[index] && !line.trim().is_empty() {
+            let opens_block = (index + 1..lines.len())
+                .find(|&next| !lines[next].trim().is_empty())
+                .is_some_and(|next| !starts[next]);
+            if !current.is_empty() && (in_block || opens_block) {
+                pieces.push(std::mem::take(&mut current));
+            }
+            in_block = opens_block;
+        }
+        current.push_str(line);
+    }
+    if !current.is_empty() || pieces.is_empty() {
+        pieces.push(current);
+    }
+    into_chunks(pieces)
+}
+
+/// [`split_units`], with every unit over `budget` tokens cut further by [`split_source`] so
+/// that no unit outgrows a request.
+pub fn split_units_within(source: &str, budget: usize) -> Vec<SourceChunk> {
+    let pieces = split_units(source)
+        .into_iter()
+        .flat_map(|unit| {
+            if estimate_tokens(&unit.text) > budget {
+                split_source(&unit.text, budget)
+                    .into_iter()
+                    .map(|piece| piece.text)
+                    .collect()
+            } else {
+                vec![unit.text]
+            }
+        })
+        .collect();
+    into_chunks(pieces)
+}
+
+/// An unindented line that still belongs to the block above it.
+fn closes_block(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    let word = trimmed
+        .split(|ch: char| !ch.is_alphanumeric())
+        .next()
+        .unwrap_or_default();
+    trimmed.starts_with(['}', ')', ']'])
+        || matches!(
+            word,
+            "end" | "else" | "elif" | "elsif" | "except" | "catch" | "finally"
+        )
+}
+
+/// Numbers `pieces` and gives each the trailing lines of the one before as overlap.
+fn into_chunks(pieces: Vec<String>) -> Vec<SourceChunk> {
+    let total = pieces.len();
+    let mut chunks = Vec::with_capacity(total);
+    let mut previous = "";
+    for (index, text) in pieces.iter().enumerate() {
+        let previous_lines: Vec<&str> = previous.lines().collect();
+        let overlap =
+            previous_lines[previous_lines.len().saturating_sub(CHUNK_OVERLAP_LINES)..].join("\n");
+        chunks.push(SourceChunk {
+            index,
+            total,
+            text: text.clone(),
+            overlap,
+        });
+        previous = text;
+    }
+    chunks
+}
+
+/// Marks the lines a chunk may start at: blank or unindented lines at the top level, that is
+/// outside every bracket and block comment, except lines closing a block (`}`, `end`,
+/// `else`, ...). Brackets are counted past string literals and comments, so a brace in a
+/// string or an unindented line inside a multi-line literal or call is never a cut.
+fn item_starts(lines: &[&str]) -> Vec<bool> {
+    let mut depth = 0;
+    let mut in_comment = false;
+    lines
+        .iter()
+        .map(|line| {
+            let start = depth == 0
+                && !in_comment
+                && (line.trim().is_empty()
+                    || (!line.starts_with([' ', '\t']) && !closes_block(line)));
+            scan_brackets(line, &mut depth, &mut in_comment);
+            start
+        })
+        .collect()
+}
+
+/// Updates the bracket `depth` and whether a `/* */` comment is open after `line`. String
+/// literals end at the line's end, which keeps a stray quote (a Rust lifetime, an apostrophe)
+/// from hiding the rest of the file; `//` comments and `#` followed by a space end the line.
+fn scan_brackets(line: &str, depth: &mut usize, in_comment: &mut bool) {
+    let mut chars = line.chars().peekable();
+    let mut quote = None;
+    while let Some(ch) = chars.next() {
+        if *in_comment {
+            if ch == '*' && chars.next_if_eq(&'/').is_some() {
+                *in_comment = false;
+            }
+            continue;
+        }
+        if let Some(open) = quote {
+            if ch == '\\' {
+                chars.next();
+            } else if ch == open {
+                quote = None;
+            }
+            continue;
+        }
+        match ch {
+            '"' | '\'' | '`' => quote = Some(ch),
+            '/' if chars.peek() == Some(&'/') => break,
+            '/' if chars.next_if_eq(&'*').is_some() => *in_comment = true,
+            '#' if chars.peek().is_none_or(|next| next.is_whitespace()) => break,
+            '(' | '[' | '{' => *depth += 1,
+            ')' | ']' | '}' => *depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{chunk_budget, estimate_tokens, split_source, split_units, split_units_within};
+
+    #[test]
+    fn estimates_about_four_bytes_per_token() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+        assert_eq!(chunk_budget(8192), 2730);
+    }
+
+    #[test]
+    fn small_sources_stay_whole() {
+        let chunks = split_source("print 1\nprint 2\n", 100);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].text, "print 1\nprint 2\n");
+        assert!(chunks[0].overlap.is_empty());
+    }
+
+    #[test]
+    fn splits_at_top_level_lines_with_overlap() {
+        let source = "def a():\n    return 1111\n    return 2222\ndef b():\n    return 3333\n    return 4444\n";
+        let chunks = split_source(source, 12);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(
+            chunks[0].text,
+            "def a():\n    return 1111\n    return 2222\n"
+        );
+        assert_eq!(
+            chunks[1].text,
+            "def b():\n    return 3333\n    return 4444\n"
+        );
+        assert_eq!(chunks[1].total, 2);
+        assert!(chunks[1].overlap.ends_with("    return 2222"));
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|chunk| chunk.text.as_str())
+                .collect::<String>(),
+            source
+        );
+    }
+
+    #[test]
+    fn closing_braces_and_bracketed_lines_are_not_cuts() {
+        let source = "function a() {\n  return 1;\n}\nfunction b() {\n  return 2222222222;\n}\n";
+        let texts: Vec<String> = split_source(source, 18)
+            .into_iter()
+            .map(|chunk| chunk.text)
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                "function a() {\n  return 1;\n}\n",
+                "function b() {\n  return 2222222222;\n}\n",
+            ]
+        );
+
+        let source = "function f() {\nreturn \"{\";\n}\nconst t = [\n// ]\n1,\n];\nf();\n";
+        let texts: Vec<String> = split_units(source)
+            .into_iter()
+            .map(|unit| unit.text)
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                "function f() {\nreturn \"{\";\n}\n",
+                "const t = [\n// ]\n1,\n];\n",
+                "f();\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn units_follow_top_level_blocks() {
+        let source = "x = 1\ny = 2\n\ndef a():\n    return x\n\nfunction b() {\n  return y;\n}\nprint a()\nprint b()\n";
+        let units = split_units(source);
+        let texts: Vec<&str> = units.iter().map(|unit| unit.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "x = 1\ny = 2\n\n",
+                "def a():\n    return x\n\n",
+                "function b() {\n  return y;\n}\n",
+                "print a()\nprint b()\n",
+            ]
+        );
+        assert_eq!(units[3].total, 4);
+        assert!(units[3].overlap.ends_with('}'));
+
+        // Editing one block leaves the other units as they were.
+        let edited = split_units(&source.replace("return x", "return x * 2"));
+        assert_eq!(edited.len(), 4);
+        assert_ne!(edited[1].text, units[1].text);
+        assert_eq!(edited[2].text, units[2].text);
+        assert_eq!(split_units("")[0].text, "");
+    }
+
+    #[test]
+    fn oversized_units_are_split_to_the_budget() {
+        let source = "x = 1\n\ndef a():\n    return 1111\n    return 2222\n    return 3333\n";
+        let units = split_units_within(source, 8);
+        let texts: Vec<&str> = units.iter().map(|unit| unit.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "x = 1\n\n",
+                "def a():\n    return 1111\n",
+                "    return 2222\n    return 3333\n"
+            ]
+        );
+        assert_eq!(units[2].index, 2);
+        assert_eq!(units[2].total, 3);
+        assert_eq!(split_units_within(source, 100), split_units(source));
+    }
+
+    #[test]
+    fn chunk_context_names_part_and_previous_output() {
+        let chunks = split_source("a = 1\nb = 2\n", 2);
+        assert_eq!(chunks.len(), 2);
+        let context = chunks[1].scope_context("big.pseudo", Some("let x = 0;"), "let a = 1;");
+        assert!(context.starts_with("let x = 0;"));
+        assert!(context.contains("part 2 of 2 of big.pseudo"));
+        assert!(context.contains("let a = 1;"));
+        assert!(context.contains("already translated:\na = 1"));
+    }
+}
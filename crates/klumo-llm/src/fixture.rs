@@ -0,0 +1,56 @@
+use crate::LlmTranslateRequest;
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+
+/// Translations stored on disk as `<dir>/<hash>.js`, keyed by the request's source hash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixtureStore {
+    pub dir: PathBuf,
+    /// Translate misses through the `Auto` chain and save the result (`KLUMO_RECORD_FIXTURES=1`).
+    pub record: bool,
+}
+
+impl FixtureStore {
+    /// Hashes the inputs that shape a translation: source text, language hint, scope context,
+    /// and repair hint. `source_id` is left out so fixtures survive checkout location changes.
+    pub fn key(req: &LlmTranslateRequest) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(req.source_text.as_bytes());
+        for (label, part) in [
+            ("lang", &req.language_hint),
+            ("scope", &req.scope_context),
+            ("repair", &req.repair_hint),
+        ] {
+            if let Some(part) = part {
+                hasher.update(format!("\n--{label}--\n").as_bytes());
+                hasher.update(part.as_bytes());
+            }
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    pub fn path_for(&self, req: &LlmTranslateRequest) -> PathBuf {
+        self.dir.join(format!("{}.js", Self::key(req)))
+    }
+
+    pub fn load(&self, req: &LlmTranslateRequest) -> Result<Option<String>> {
+        let path = self.path_for(req);
+        if !path.exists() {
+            return Ok(None);
+        }
+        fs::read_to_string(&path)
+            .map(Some)
+            .with_context(|| format!("failed reading fixture {}", path.display()))
+    }
+
+    pub fn save(&self, req: &LlmTranslateRequest, javascript: &str) -> Result<PathBuf> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("failed creating fixture dir {}", self.dir.display()))?;
+        let path = self.path_for(req);
+        fs::write(&path, javascript)
+            .with_context(|| format!("failed writing fixture {}", path.display()))?;
+        Ok(path)
+    }
+}
@@ -1,47 +1,424 @@
+mod async_service;
+mod circuit;
+mod context;
+mod fixture;
+mod limits;
+mod metrics;
+mod normalize;
+mod observer;
+mod probe_cache;
+mod prompt;
+mod response_cache;
+mod usage;
+
 use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
 use std::error::Error as StdError;
 use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub use async_service::{
+    AsyncTranslationService, BLOCKING_POOL_THREADS, Blocking, BlockingPool, BlockingTask, block_on,
+};
+pub use circuit::{
+    CircuitBreaker, DEFAULT_CIRCUIT_COOLDOWN, DEFAULT_CIRCUIT_FAILURES, DEFAULT_CIRCUIT_WINDOW,
+};
+pub use context::{
+    CHUNK_OVERLAP_LINES, SourceChunk, chunk_budget, estimate_tokens, split_source, split_units,
+    split_units_within,
+};
+pub use fixture::FixtureStore;
+pub use limits::{CONTINUATION_PROMPT, Completion, SizeLimits};
+pub use metrics::{CallOutcome, ProviderMetricsStore, ProviderStats};
+pub use observer::{LlmExchange, LlmLogDir, LlmObserver};
+pub use probe_cache::{CachedProbe, DEFAULT_PROBE_TTL};
+pub use prompt::{PROMPT_TEMPLATE_NAMES, PromptTemplates, TranslationExample, prompt_sentinel};
+pub use response_cache::{ResponseCache, normalize_source};
+pub use usage::{ProviderUsage, UsageEvent, UsageLog, UsageOutcome};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Provider {
     Ollama,
     OpenAiCompatible,
+    /// A llama.cpp HTTP server (`llama-server`).
+    LlamaCpp,
+    /// A named OpenAI-compatible endpoint declared in configuration.
+    Custom(String),
+    /// Recorded translations read from a local directory; never touches the network.
+    Fixture,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+impl Provider {
+    fn fallback_label(&self) -> &str {
+        match self {
+            Provider::Ollama => "Ollama",
+            Provider::OpenAiCompatible => "OpenAI-compatible",
+            Provider::LlamaCpp => "llama.cpp",
+            Provider::Custom(name) => name,
+            Provider::Fixture => "Fixture",
+        }
+    }
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Provider::Ollama => f.write_str("ollama"),
+            Provider::OpenAiCompatible => f.write_str("openai-compatible"),
+            Provider::LlamaCpp => f.write_str("llamacpp"),
+            Provider::Custom(name) => write!(f, "custom:{name}"),
+            Provider::Fixture => f.write_str("fixture"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ProviderSelection {
     Auto,
     Ollama,
     OpenAiCompatible,
+    LlamaCpp,
+    Fixture,
+    /// The custom provider declared under this name.
+    Custom(String),
 }
 
+/// Model name reported for fixture translations.
+pub const FIXTURE_MODEL: &str = "fixture";
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ProviderDescriptor {
     pub provider: Provider,
     pub model: String,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Sampling knobs forwarded to the provider; `None` leaves the provider's own default.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SamplingOptions {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    /// Sampling seed, for providers that accept one (Ollama, OpenAI-compatible, llama.cpp).
+    pub seed: Option<u64>,
+}
+
+impl SamplingOptions {
+    pub fn is_unset(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// A fixed `name=value` rendering of every knob, unset ones left empty, for hashing into
+    /// cache keys. Floats print in their shortest round-trip form.
+    pub fn fingerprint(&self) -> String {
+        fn field<T: fmt::Display>(value: Option<T>) -> String {
+            value.map(|value| value.to_string()).unwrap_or_default()
+        }
+        format!(
+            "temperature={};top_p={};max_tokens={};seed={}",
+            field(self.temperature),
+            field(self.top_p),
+            field(self.max_tokens),
+            field(self.seed)
+        )
+    }
+}
+
+/// TLS settings provider clients apply when building their HTTP client. Proxies come from
+/// `HTTPS_PROXY`/`HTTP_PROXY`/`NO_PROXY`, which the HTTP client reads itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsOptions {
+    /// PEM bundle of extra root certificates, e.g. a TLS-intercepting proxy's CA.
+    pub ca_bundle: Option<PathBuf>,
+    /// Skip certificate verification entirely.
+    pub accept_invalid_certs: bool,
+}
+
+#[cfg(feature = "reqwest")]
+impl TlsOptions {
+    /// Adds the CA bundle's certificates and the verification setting to `builder`.
+    pub fn apply_to(
+        &self,
+        mut builder: reqwest::blocking::ClientBuilder,
+    ) -> Result<reqwest::blocking::ClientBuilder> {
+        use anyhow::Context;
+
+        if let Some(path) = &self.ca_bundle {
+            let pem = std::fs::read(path)
+                .with_context(|| format!("failed reading CA bundle {}", path.display()))?;
+            for cert in reqwest::Certificate::from_pem_bundle(&pem)
+                .with_context(|| format!("failed parsing CA bundle {}", path.display()))?
+            {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+        Ok(builder.danger_accept_invalid_certs(self.accept_invalid_certs))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct LlmTranslateRequest {
     pub source_text: String,
     pub source_id: String,
     pub language_hint: Option<String>,
     pub scope_context: Option<String>,
+    /// Set when re-prompting after rejected output: why the previous answer was refused.
+    pub repair_hint: Option<String>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub seed: Option<u64>,
+    /// Ask the provider for a JSON object (see [`STRUCTURED_OUTPUT_INSTRUCTION`]) instead of bare code.
+    pub structured_output: bool,
+    /// Also ask for an [`Explanation`] of the translation (see [`EXPLAINED_OUTPUT_INSTRUCTION`]);
+    /// implies `structured_output`.
+    pub explain: bool,
+    /// With `explain`: JavaScript an earlier translation of the source produced. The provider
+    /// is asked to explain it (see [`EXPLAIN_EXISTING_INSTRUCTION`]) rather than translate anew.
+    pub explain_javascript: Option<String>,
+    /// Templates clients render the prompt from; `ProviderRouter` fills in its own set.
+    pub prompts: PromptTemplates,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Prompt suffix used when `LlmTranslateRequest::structured_output` is set.
+pub const STRUCTURED_OUTPUT_INSTRUCTION: &str = "Respond with a single JSON object: {\"javascript\": \"<runnable JavaScript>\", \"notes\": \"<short explanation of assumptions>\", \"confidence\": <number from 0 to 1>}. Do not wrap it in markdown.";
+
+/// Prompt suffix used in place of [`STRUCTURED_OUTPUT_INSTRUCTION`] when
+/// `LlmTranslateRequest::explain` is set.
+pub const EXPLAINED_OUTPUT_INSTRUCTION: &str = "Respond with a single JSON object: {\"javascript\": \"<runnable JavaScript>\", \"notes\": \"<short explanation of assumptions>\", \"confidence\": <number from 0 to 1>, \"explanation\": {\"rationale\": \"<two or three sentences on how the source was translated>\", \"mappings\": [{\"source\": \"<construct in the source>\", \"generated\": \"<the JavaScript it became>\"}]}}. List each distinctive source construct once in mappings. Do not wrap it in markdown.";
+
+/// Prompt suffix used in place of [`EXPLAINED_OUTPUT_INSTRUCTION`] when
+/// `LlmTranslateRequest::explain_javascript` is set; that JavaScript follows it between markers.
+pub const EXPLAIN_EXISTING_INSTRUCTION: &str = "The source was already translated to the JavaScript between the JS markers below. Do not translate it again: explain that translation. Respond with a single JSON object: {\"javascript\": \"<the given JavaScript, unchanged>\", \"explanation\": {\"rationale\": \"<two or three sentences on how the source was translated>\", \"mappings\": [{\"source\": \"<construct in the source>\", \"generated\": \"<the JavaScript it became>\"}]}}. List each distinctive source construct once in mappings. Do not wrap it in markdown.";
+
+/// A translator's account of one translation: why it came out the way it did, and which
+/// generated construct each original construct became.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Explanation {
+    pub rationale: String,
+    #[serde(default)]
+    pub mappings: Vec<ConstructMapping>,
+}
+
+impl Explanation {
+    pub fn is_empty(&self) -> bool {
+        self.rationale.trim().is_empty() && self.mappings.is_empty()
+    }
+}
+
+/// One row of an [`Explanation`]'s mapping table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConstructMapping {
+    pub source: String,
+    pub generated: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct LlmTranslateResponse {
     pub javascript: String,
     pub provider: Provider,
     pub model: String,
+    /// Model the endpoint reports answering with, when it differs from the requested `model`.
+    pub served_model: Option<String>,
+    pub notes: Option<String>,
+    pub confidence: Option<f32>,
+    pub explanation: Option<Explanation>,
+}
+
+/// Provider output after fence stripping and structured-response decoding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NormalizedOutput {
+    pub javascript: String,
+    pub notes: Option<String>,
+    pub confidence: Option<f32>,
+    pub explanation: Option<Explanation>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StructuredOutput {
+    javascript: String,
+    notes: Option<String>,
+    confidence: Option<f32>,
+    /// Kept loose so a malformed explanation only loses the explanation, not the code.
+    explanation: Option<serde_json::Value>,
+}
+
+/// Machine-readable category of a failed provider attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProviderErrorKind {
+    /// Missing credentials, unknown provider or model, missing fixture.
+    Config,
+    /// The endpoint could not be reached, timed out, throttled, or failed server-side.
+    Network,
+    /// The model answered, but not with usable JavaScript.
+    Model,
+    /// A client error that carried no classification.
+    Other,
+}
+
+impl ProviderErrorKind {
+    /// Classifies an HTTP error status returned by a provider endpoint.
+    pub fn from_status(status: u16) -> Self {
+        match status {
+            401 | 403 | 404 => ProviderErrorKind::Config,
+            408 | 429 | 500..=599 => ProviderErrorKind::Network,
+            _ => ProviderErrorKind::Model,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ProviderErrorKind::Config => "config",
+            ProviderErrorKind::Network => "network",
+            ProviderErrorKind::Model => "model",
+            ProviderErrorKind::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for ProviderErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Error returned by `LlmClient` implementations that carries the details routing reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProviderCallError {
+    pub kind: ProviderErrorKind,
+    pub status: Option<u16>,
+    pub endpoint: Option<String>,
+    pub message: String,
+    /// The model answered with nothing usable at all, as opposed to unusable text.
+    pub empty_output: bool,
+}
+
+impl ProviderCallError {
+    pub fn new(kind: ProviderErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            status: None,
+            endpoint: None,
+            message: message.into(),
+            empty_output: false,
+        }
+    }
+
+    /// A `Model` error for a blank or missing answer.
+    pub fn empty_output(message: impl Into<String>) -> Self {
+        Self {
+            empty_output: true,
+            ..Self::new(ProviderErrorKind::Model, message)
+        }
+    }
+
+    /// A non-success HTTP response, classified by status.
+    pub fn http(endpoint: &str, status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status: Some(status),
+            ..Self::new(ProviderErrorKind::from_status(status), message).at(endpoint)
+        }
+    }
+
+    pub fn at(mut self, endpoint: &str) -> Self {
+        self.endpoint = Some(endpoint.to_string());
+        self
+    }
+}
+
+impl fmt::Display for ProviderCallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl StdError for ProviderCallError {}
+
+/// What went wrong in one attempt; finer than the `ProviderErrorKind` used for exit codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttemptErrorKind {
+    /// No response: the endpoint was unreachable or the connection failed.
+    Network,
+    /// The endpoint answered with a non-success status.
+    HttpStatus,
+    /// The model answered, but not with JavaScript Klumo could extract.
+    Normalization,
+    /// The model answered with nothing.
+    EmptyOutput,
+    /// Missing credentials, unknown provider, or an oversized prompt; nothing was sent.
+    Config,
+    /// Skipped because the provider's circuit breaker is open.
+    CircuitOpen,
+    Other,
+}
+
+impl AttemptErrorKind {
+    pub fn classify(call: Option<&ProviderCallError>) -> Self {
+        let Some(call) = call else {
+            return Self::Other;
+        };
+        if call.status.is_some() {
+            return Self::HttpStatus;
+        }
+        match call.kind {
+            ProviderErrorKind::Network => Self::Network,
+            ProviderErrorKind::Config => Self::Config,
+            ProviderErrorKind::Model if call.empty_output => Self::EmptyOutput,
+            ProviderErrorKind::Model => Self::Normalization,
+            ProviderErrorKind::Other => Self::Other,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Network => "network",
+            Self::HttpStatus => "http_status",
+            Self::Normalization => "normalization",
+            Self::EmptyOutput => "empty_output",
+            Self::Config => "config",
+            Self::CircuitOpen => "circuit_open",
+            Self::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for AttemptErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ProviderAttempt {
     pub provider: Provider,
+    /// Model the attempt asked for.
+    pub model: String,
     pub stage: &'static str,
     pub error: String,
     pub note: Option<String>,
+    pub kind: ProviderErrorKind,
+    pub error_kind: AttemptErrorKind,
+    pub status: Option<u16>,
+    pub endpoint: Option<String>,
+    /// Time spent on the attempt, including retries inside the client.
+    pub elapsed: Duration,
+}
+
+impl ProviderAttempt {
+    /// `error_kind`, plus HTTP status and time taken when a request reached an endpoint.
+    fn summary(&self) -> String {
+        let mut summary = self.error_kind.to_string();
+        if let Some(status) = self.status {
+            summary.push_str(&format!(" {status}"));
+        }
+        if self.endpoint.is_some() {
+            summary.push_str(&format!(" after {}ms", self.elapsed.as_millis()));
+        }
+        summary
+    }
 }
 
 #[derive(Debug)]
@@ -49,22 +426,52 @@ pub struct ProviderRoutingError {
     pub attempts: Vec<ProviderAttempt>,
 }
 
+impl ProviderRoutingError {
+    /// The kind of the last attempt, i.e. the failure that ended routing.
+    pub fn kind(&self) -> ProviderErrorKind {
+        self.attempts
+            .last()
+            .map_or(ProviderErrorKind::Other, |attempt| attempt.kind)
+    }
+
+    /// Whether no provider could even be asked: every attempt failed to connect, lacked
+    /// configuration such as an API key, or was skipped by an open circuit.
+    pub fn nothing_reachable(&self) -> bool {
+        !self.attempts.is_empty()
+            && self.attempts.iter().all(|attempt| {
+                matches!(
+                    attempt.error_kind,
+                    AttemptErrorKind::Network
+                        | AttemptErrorKind::Config
+                        | AttemptErrorKind::CircuitOpen
+                )
+            })
+    }
+}
+
 impl fmt::Display for ProviderRoutingError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(f, "LLM routing failed:")?;
-        for attempt in &self.attempts {
-            let provider = match attempt.provider {
-                Provider::Ollama => "ollama",
-                Provider::OpenAiCompatible => "openai-compatible",
-            };
+        let count = self.attempts.len();
+        writeln!(
+            f,
+            "LLM routing failed after {count} attempt{}:",
+            if count == 1 { "" } else { "s" }
+        )?;
+        for (index, attempt) in self.attempts.iter().enumerate() {
+            writeln!(
+                f,
+                "  {}. {} {} [{}]: {}",
+                index + 1,
+                attempt.provider,
+                attempt.model,
+                attempt.summary(),
+                attempt.error
+            )?;
+            if let Some(endpoint) = &attempt.endpoint {
+                writeln!(f, "     endpoint: {endpoint}")?;
+            }
             if let Some(note) = &attempt.note {
-                writeln!(
-                    f,
-                    "- {} ({}) {} [{}]",
-                    provider, attempt.stage, attempt.error, note
-                )?;
-            } else {
-                writeln!(f, "- {} ({}) {}", provider, attempt.stage, attempt.error)?;
+                writeln!(f, "     {note}")?;
             }
         }
         Ok(())
@@ -75,10 +482,66 @@ impl StdError for ProviderRoutingError {}
 
 pub trait LlmClient {
     fn translate_to_js(&self, req: &LlmTranslateRequest, model: &str) -> Result<String>;
+
+    /// The raw answer and, for endpoints that route between several models (an OpenRouter
+    /// fallback list), the model that actually served it.
+    fn translate_reporting_model(
+        &self,
+        req: &LlmTranslateRequest,
+        model: &str,
+    ) -> Result<(String, Option<String>)> {
+        self.translate_to_js(req, model).map(|raw| (raw, None))
+    }
+
+    /// Requests the endpoint's rate-limit headers said remain in the current window, as of the
+    /// latest response; `None` when it has not said.
+    fn remaining_requests(&self) -> Option<u64> {
+        None
+    }
 }
 
 pub trait ReachabilityProbe {
     fn ollama_reachable(&self) -> bool;
+
+    /// Models pulled on the Ollama server, or `None` when the probe cannot list them.
+    fn installed_ollama_models(&self) -> Option<Vec<String>> {
+        None
+    }
+
+    /// Downloads `model` onto the Ollama server. Probes that cannot pull report an error.
+    fn pull_ollama_model(&self, model: &str) -> Result<()> {
+        Err(anyhow!(
+            "cannot pull {model}: pulling is not supported by this probe"
+        ))
+    }
+}
+
+fn model_matches(installed: &str, wanted: &str) -> bool {
+    installed == wanted
+        || installed
+            .strip_prefix(wanted)
+            .is_some_and(|tag| tag.starts_with(':'))
+}
+
+/// Picks the Ollama model to use given what is installed: the configured model when it is
+/// pulled, else the first installed match from `preferences` (bare names match any tag).
+/// Returns `None` when neither is installed; an arbitrary installed model is no stand-in for a
+/// coding model, so callers keep the configured one and let auto-pull or the missing-model
+/// error take over.
+pub fn pick_ollama_model(
+    installed: &[String],
+    configured: &str,
+    preferences: &[String],
+) -> Option<String> {
+    if installed.iter().any(|name| model_matches(name, configured)) {
+        return Some(configured.to_string());
+    }
+    preferences.iter().find_map(|wanted| {
+        installed
+            .iter()
+            .find(|name| model_matches(name, wanted))
+            .cloned()
+    })
 }
 
 pub trait TranslationService {
@@ -89,33 +552,209 @@ pub trait TranslationService {
         req: &LlmTranslateRequest,
         model_override: Option<&str>,
     ) -> Result<LlmTranslateResponse>;
+
+    /// Like [`translate`](Self::translate), but only `provider` uses `model`; the other
+    /// providers of the chain keep their own models. The default gives `model` to every provider.
+    fn translate_for_provider(
+        &self,
+        selection: ProviderSelection,
+        req: &LlmTranslateRequest,
+        provider: &Provider,
+        model: &str,
+    ) -> Result<LlmTranslateResponse> {
+        let _ = provider;
+        self.translate(selection, req, Some(model))
+    }
+
+    /// The distinct (provider, model) pairs a translation with `model_override` could be
+    /// answered by, in the order they are tried. Providers that ignore the override keep their
+    /// own model.
+    fn resolved_chain(
+        &self,
+        selection: ProviderSelection,
+        model_override: Option<&str>,
+    ) -> Vec<ProviderDescriptor> {
+        override_chain(self.candidate_chain(selection), model_override)
+    }
+
+    /// Translates every request in `reqs`, returning one result per request in the same order.
+    /// The default sends them one after another.
+    fn translate_batch(
+        &self,
+        selection: ProviderSelection,
+        reqs: &[LlmTranslateRequest],
+        model_override: Option<&str>,
+    ) -> Vec<Result<LlmTranslateResponse>> {
+        reqs.iter()
+            .map(|req| self.translate(selection.clone(), req, model_override))
+            .collect()
+    }
+
+    /// Identifies the prompt templates in use so cached translations are invalidated when they change.
+    fn prompt_fingerprint(&self) -> String {
+        PromptTemplates::default().fingerprint()
+    }
+
+    /// How many translations for `selection` may be in flight at once, whether in one
+    /// [`translate_batch`](Self::translate_batch) or from several threads. The default is one.
+    fn max_concurrency(&self, _selection: ProviderSelection) -> usize {
+        1
+    }
+
+    /// The lowercase name of the language `source_text` is written in, for sources whose
+    /// extension and content do not tell; `None` when the service cannot say. The default
+    /// never asks.
+    fn classify_language(
+        &self,
+        _selection: ProviderSelection,
+        _source_text: &str,
+    ) -> Option<String> {
+        None
+    }
+}
+
+/// How much of a source [`TranslationService::classify_language`] shows a provider.
+pub const CLASSIFY_SAMPLE_BYTES: usize = 2_000;
+
+/// A language name from a classifier's answer: its first line without quotes, fences or a
+/// trailing period, if that is a short name at all.
+fn language_name(answer: &str) -> Option<String> {
+    let name = answer
+        .lines()
+        .map(|line| {
+            line.trim()
+                .trim_matches(|c| matches!(c, '`' | '"' | '\'' | '.' | '*'))
+        })
+        .find(|line| !line.is_empty())?
+        .to_ascii_lowercase();
+    let plausible = name.len() <= 32
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '#' | '-' | '_' | ' '));
+    (plausible && name != "unknown").then_some(name)
 }
 
-pub fn normalize_js_output(raw: &str) -> Result<String> {
-    let trimmed = raw.trim();
+/// `chain` with every model replaced by `model_override`, without repeated pairs.
+pub(crate) fn override_chain(
+    chain: Vec<ProviderDescriptor>,
+    model_override: Option<&str>,
+) -> Vec<ProviderDescriptor> {
+    dedup_chain(chain.into_iter().map(|mut entry| {
+        if let Some(model) = model_override {
+            entry.model = model.to_string();
+        }
+        entry
+    }))
+}
+
+fn dedup_chain(entries: impl IntoIterator<Item = ProviderDescriptor>) -> Vec<ProviderDescriptor> {
+    let mut chain: Vec<ProviderDescriptor> = Vec::new();
+    for entry in entries {
+        if !chain.contains(&entry) {
+            chain.push(entry);
+        }
+    }
+    chain
+}
+
+/// Most requests in flight at once when a `ProviderRouter` chain starts at a remote endpoint.
+pub const BATCH_CONCURRENCY: usize = 4;
+
+/// Strips markdown fences, surrounding prose and invisible characters outside string and
+/// template literals and, when the output is a structured JSON response, unpacks its
+/// `javascript`, `notes`, `confidence` and `explanation` fields.
+pub fn normalize_js_output(raw: &str) -> Result<NormalizedOutput> {
+    let trimmed = raw.trim_matches(normalize::is_blank);
     if trimmed.is_empty() {
         return Err(anyhow!("LLM returned empty output"));
     }
 
-    if let Some(block) = extract_fenced_code(trimmed) {
-        if block.trim().is_empty() {
+    if let Some(structured) = parse_structured(trimmed) {
+        return structured;
+    }
+
+    let body = match normalize::select_fenced_code(trimmed) {
+        Some(block) if block.trim_matches(normalize::is_blank).is_empty() => {
             return Err(anyhow!("LLM returned empty fenced output"));
         }
-        return Ok(block.trim().to_string());
+        Some(block) => block,
+        None => normalize::strip_prose(trimmed),
+    };
+    let body = normalize::strip_invisible(&body).trim().to_string();
+
+    if let Some(structured) = parse_structured(&body) {
+        return structured;
+    }
+
+    Ok(NormalizedOutput {
+        javascript: body,
+        notes: None,
+        confidence: None,
+        explanation: None,
+    })
+}
+
+fn parse_structured(text: &str) -> Option<Result<NormalizedOutput>> {
+    if !text.starts_with('{') {
+        return None;
     }
+    let structured = serde_json::from_str::<StructuredOutput>(text).ok()?;
+    Some(normalize_js_output(&structured.javascript).map(|inner| {
+        NormalizedOutput {
+            javascript: inner.javascript,
+            notes: structured.notes.filter(|notes| !notes.trim().is_empty()),
+            confidence: structured.confidence.map(|value| value.clamp(0.0, 1.0)),
+            explanation: structured
+                .explanation
+                .and_then(|value| serde_json::from_value::<Explanation>(value).ok())
+                .filter(|explanation| !explanation.is_empty()),
+        }
+    }))
+}
+
+/// Ollama answers 404 when the requested model has not been pulled.
+fn is_missing_model<T>(result: &Result<T>) -> bool {
+    result.as_ref().is_err_and(|err| {
+        err.downcast_ref::<ProviderCallError>()
+            .is_some_and(|call| call.status == Some(404))
+    })
+}
+
+/// Whether Ollama's tag list confirms `model` is missing. A 404 for an installed model, or a
+/// list that cannot be read, is no reason to start a download.
+fn ollama_model_absent(probe: &impl ReachabilityProbe, model: &str) -> bool {
+    probe
+        .installed_ollama_models()
+        .is_some_and(|installed| !installed.iter().any(|name| model_matches(name, model)))
+}
+
+/// What to do about an Ollama model that is not installed, appended to the 404 Ollama sent.
+fn missing_ollama_model_hint(model: &str, auto_pull: bool) -> String {
+    let pull = if auto_pull {
+        format!("it was not pulled, so run `ollama pull {model}`")
+    } else {
+        format!("run `ollama pull {model}` or set `ollama_auto_pull`")
+    };
+    format!(
+        " ({model} is not installed in Ollama: {pull}, or add an installed coding model to `ollama_model_preferences`; `klumo models` lists them)"
+    )
+}
 
-    Ok(trimmed.to_string())
+/// A configured llama.cpp server. `model` is only reported; the server serves whatever it loaded.
+pub struct LlamaCppProvider {
+    pub client: Box<dyn LlmClient + Send + Sync>,
+    pub model: String,
 }
 
-fn extract_fenced_code(input: &str) -> Option<String> {
-    let start = input.find("```")?;
-    let remainder = &input[start + 3..];
-    let body_start = remainder.find('\n')? + 1;
-    let body = &remainder[body_start..];
-    let end = body.find("```")?;
-    Some(body[..end].to_string())
+/// An additional OpenAI-compatible endpoint routed alongside the built-in providers.
+pub struct CustomProvider<C: LlmClient> {
+    pub name: String,
+    pub client: C,
+    pub model: String,
 }
 
+/// Walks the provider chain for each translation. Shared through an `Arc`, it also serves
+/// async callers as an `AsyncTranslationService`.
 pub struct ProviderRouter<O, P, R>
 where
     O: LlmClient,
@@ -127,48 +766,385 @@ where
     pub reachability: R,
     pub ollama_model: String,
     pub openai_model: String,
+    /// Tried after Ollama in `ProviderSelection::Auto` when configured.
+    pub llamacpp: Option<LlamaCppProvider>,
+    /// Tried in declaration order after Ollama and before the built-in OpenAI endpoint in
+    /// `ProviderSelection::Auto`.
+    pub custom: Vec<CustomProvider<P>>,
+    /// Coding models to fall back to, in order, when `ollama_model` is not pulled locally.
+    pub ollama_model_preferences: Vec<String>,
+    /// Backing store for `ProviderSelection::Fixture`.
+    pub fixtures: Option<FixtureStore>,
+    /// Prompt templates handed to every client call.
+    pub prompts: PromptTemplates,
+    /// Pull the model through the probe and retry once when Ollama answers 404 and its tag
+    /// list confirms the model is not installed. The probe may still ask before pulling.
+    pub ollama_auto_pull: bool,
+    /// Records the outcome and latency of every provider call for `klumo stats`.
+    pub metrics: Option<ProviderMetricsStore>,
+    /// Sees every prompt and raw response, e.g. to dump them with `--llm-log`.
+    pub observer: Option<Arc<dyn LlmObserver>>,
+    /// Skips providers whose network calls keep failing instead of waiting out their timeouts.
+    pub circuit: Option<CircuitBreaker>,
+    /// Answers keyed on the normalized prompt, reused across reformatted inputs.
+    pub response_cache: Option<ResponseCache>,
+    /// Opt-in local usage telemetry for `klumo stats --llm`.
+    pub usage: Option<UsageLog>,
 }
 
 impl<O, P, R> ProviderRouter<O, P, R>
 where
-    O: LlmClient,
-    P: LlmClient,
-    R: ReachabilityProbe,
+    O: LlmClient + Sync,
+    P: LlmClient + Sync,
+    R: ReachabilityProbe + Sync,
 {
-    fn call_provider(
+    /// The client that calls `provider`; fixtures are served by [`Self::call_fixture`].
+    /// Tries each entry of `chain` in turn; batches resolve the chain once and share it.
+    fn translate_along(
         &self,
-        provider: Provider,
+        chain: &[ProviderDescriptor],
         req: &LlmTranslateRequest,
         model_override: Option<&str>,
     ) -> Result<LlmTranslateResponse> {
-        match provider {
-            Provider::Ollama => {
-                let model = model_override.unwrap_or(&self.ollama_model);
-                let output = self.ollama.translate_to_js(req, model)?;
-                Ok(LlmTranslateResponse {
-                    javascript: normalize_js_output(&output)?,
-                    provider,
-                    model: model.to_string(),
-                })
+        let mut attempts = Vec::new();
+
+        for (index, entry) in chain.iter().enumerate() {
+            let note = chain.get(index + 1).map(|next| {
+                format!(
+                    "{} failed, falling back to {}",
+                    entry.provider.fallback_label(),
+                    next.provider.fallback_label()
+                )
+            });
+            // Fixture replay is local; when recording it goes through the breaker per provider.
+            let circuit = self
+                .circuit
+                .as_ref()
+                .filter(|_| entry.provider != Provider::Fixture);
+            if let Some(remaining) = circuit.and_then(|circuit| circuit.open_for(&entry.provider)) {
+                attempts.push(ProviderAttempt {
+                    provider: entry.provider.clone(),
+                    model: model_override.unwrap_or(&entry.model).to_string(),
+                    stage: "circuit",
+                    error: format!(
+                        "skipped after repeated network failures; retrying in {}s",
+                        remaining.as_secs().max(1)
+                    ),
+                    note,
+                    kind: ProviderErrorKind::Network,
+                    error_kind: AttemptErrorKind::CircuitOpen,
+                    status: None,
+                    endpoint: None,
+                    elapsed: Duration::ZERO,
+                });
+                continue;
+            }
+
+            let started = Instant::now();
+            match self.call_provider(entry, req, model_override) {
+                Ok(response) => {
+                    if let Some(circuit) = circuit {
+                        circuit.record_success(&entry.provider);
+                    }
+                    return Ok(response);
+                }
+                Err(err) => {
+                    let call_error = err.downcast_ref::<ProviderCallError>();
+                    if let Some(circuit) = circuit
+                        && call_error.is_some_and(|e| e.kind == ProviderErrorKind::Network)
+                        && circuit.record_failure(&entry.provider)
+                    {
+                        eprintln!(
+                            "[klumo] warning: {} failed {} times in a row ({}); skipping it for {}s",
+                            entry.provider,
+                            circuit.failures,
+                            err,
+                            circuit.cooldown.as_secs()
+                        );
+                    }
+                    attempts.push(ProviderAttempt {
+                        provider: entry.provider.clone(),
+                        model: model_override.unwrap_or(&entry.model).to_string(),
+                        stage: "translate",
+                        error: err.to_string(),
+                        note,
+                        kind: call_error.map_or(ProviderErrorKind::Other, |e| e.kind),
+                        error_kind: AttemptErrorKind::classify(call_error),
+                        status: call_error.and_then(|e| e.status),
+                        endpoint: call_error.and_then(|e| e.endpoint.clone()),
+                        elapsed: started.elapsed(),
+                    })
+                }
+            }
+        }
+
+        Err(ProviderRoutingError { attempts }.into())
+    }
+
+    /// Up to `BATCH_CONCURRENCY` when a hosted OpenAI-compatible endpoint leads `chain`, and
+    /// no more than the requests its rate-limit headers last said remain (one after a 429).
+    /// Local servers (Ollama, llama.cpp) work through one request at a time anyway, so they
+    /// get one and reuse the client's kept-alive connection.
+    fn chain_concurrency(&self, chain: &[ProviderDescriptor]) -> usize {
+        let Some(first) = chain.first().filter(|first| {
+            matches!(
+                first.provider,
+                Provider::OpenAiCompatible | Provider::Custom(_)
+            )
+        }) else {
+            return 1;
+        };
+        let remaining = self
+            .client(&first.provider)
+            .ok()
+            .and_then(|client| client.remaining_requests());
+        remaining.map_or(BATCH_CONCURRENCY, |remaining| {
+            usize::try_from(remaining)
+                .unwrap_or(usize::MAX)
+                .clamp(1, BATCH_CONCURRENCY)
+        })
+    }
+
+    fn client(&self, provider: &Provider) -> Result<&dyn LlmClient> {
+        Ok(match provider {
+            Provider::Fixture => {
+                return Err(ProviderCallError::new(
+                    ProviderErrorKind::Config,
+                    "the fixture provider replays recordings and has no client",
+                )
+                .into());
             }
-            Provider::OpenAiCompatible => {
-                let model = model_override.unwrap_or(&self.openai_model);
-                let output = self.openai.translate_to_js(req, model)?;
-                Ok(LlmTranslateResponse {
-                    javascript: normalize_js_output(&output)?,
-                    provider,
-                    model: model.to_string(),
-                })
+            Provider::Ollama => &self.ollama,
+            Provider::OpenAiCompatible => &self.openai,
+            Provider::LlamaCpp => self
+                .llamacpp
+                .as_ref()
+                .ok_or_else(|| {
+                    ProviderCallError::new(
+                        ProviderErrorKind::Config,
+                        "llama.cpp provider has no llamacpp_url configured",
+                    )
+                })?
+                .client
+                .as_ref(),
+            Provider::Custom(name) => {
+                &self
+                    .custom
+                    .iter()
+                    .find(|entry| &entry.name == name)
+                    .ok_or_else(|| {
+                        ProviderCallError::new(
+                            ProviderErrorKind::Config,
+                            format!("unknown provider '{provider}'"),
+                        )
+                    })?
+                    .client
             }
+        })
+    }
+
+    fn call_provider(
+        &self,
+        candidate: &ProviderDescriptor,
+        req: &LlmTranslateRequest,
+        model_override: Option<&str>,
+    ) -> Result<LlmTranslateResponse> {
+        if candidate.provider == Provider::Fixture {
+            return self.call_fixture(req, model_override);
+        }
+        let client = self.client(&candidate.provider)?;
+        let model = model_override.unwrap_or(&candidate.model);
+        let req = LlmTranslateRequest {
+            prompts: self.prompts.clone(),
+            ..req.clone()
+        };
+        let cache_key = self
+            .response_cache
+            .as_ref()
+            .map(|cache| (cache, cache.key(&candidate.provider, model, &req)));
+        if let Some((cache, key)) = &cache_key
+            && let Some(cached) = cache.get(key, &candidate.provider, model)
+        {
+            self.record_usage(&candidate.provider, model, UsageOutcome::CacheHit, None);
+            return Ok(cached);
+        }
+        let started = Instant::now();
+        let mut answer = client.translate_reporting_model(&req, model);
+        if candidate.provider == Provider::Ollama
+            && self.ollama_auto_pull
+            && is_missing_model(&answer)
+            && ollama_model_absent(&self.reachability, model)
+            && self.reachability.pull_ollama_model(model).is_ok()
+        {
+            answer = client.translate_reporting_model(&req, model);
+        }
+        if candidate.provider == Provider::Ollama
+            && is_missing_model(&answer)
+            && let Err(err) = &mut answer
+            && let Some(call) = err.downcast_mut::<ProviderCallError>()
+        {
+            call.message
+                .push_str(&missing_ollama_model_hint(model, self.ollama_auto_pull));
+        }
+        let (raw, served_model) = match answer {
+            Ok((raw, served)) => (Ok(raw), served.filter(|served| served != model)),
+            Err(err) => (Err(err), None),
+        };
+        if let Some(observer) = &self.observer {
+            observer.on_exchange(&LlmExchange {
+                provider: &candidate.provider,
+                model,
+                request: &req,
+                prompt: req.prompts.parts(&req),
+                response: raw.as_deref().map_err(|err| format!("{err:#}")),
+                latency: started.elapsed(),
+            });
+        }
+        let output = raw.map(|raw| {
+            normalize_js_output(&raw).map_err(|err| {
+                if raw.trim_matches(normalize::is_blank).is_empty() {
+                    ProviderCallError::empty_output(err.to_string())
+                } else {
+                    ProviderCallError::new(ProviderErrorKind::Model, err.to_string())
+                }
+            })
+        });
+        let outcome = match &output {
+            Ok(Ok(_)) => CallOutcome::Success,
+            Ok(Err(_)) => CallOutcome::NormalizationFailure,
+            Err(_) => CallOutcome::ProviderFailure,
+        };
+        if let Some(metrics) = &self.metrics {
+            // Metrics are best effort; a read-only home must not fail the translation.
+            let _ = metrics.record(&candidate.provider, outcome, started.elapsed());
+        }
+        self.record_usage(
+            &candidate.provider,
+            model,
+            outcome.into(),
+            Some(started.elapsed()),
+        );
+        let output = output??;
+        let response = LlmTranslateResponse {
+            javascript: output.javascript,
+            provider: candidate.provider.clone(),
+            model: model.to_string(),
+            served_model,
+            notes: output.notes,
+            confidence: output.confidence,
+            explanation: output.explanation,
+        };
+        if let Some((cache, key)) = &cache_key {
+            // Best effort, like metrics: a read-only home must not fail the translation.
+            let _ = cache.put(key, &response);
         }
+        Ok(response)
+    }
+
+    /// The custom provider `name`; its model is empty when none is declared under that name,
+    /// and calling it fails.
+    fn custom_descriptor(&self, name: String) -> ProviderDescriptor {
+        let model = self
+            .custom
+            .iter()
+            .find(|entry| entry.name == name)
+            .map_or_else(String::new, |entry| entry.model.clone());
+        ProviderDescriptor {
+            provider: Provider::Custom(name),
+            model,
+        }
+    }
+
+    /// Serves a recorded translation, or records one through the `Auto` chain when the store
+    /// allows it.
+    fn record_usage(
+        &self,
+        provider: &Provider,
+        model: &str,
+        outcome: UsageOutcome,
+        latency: Option<Duration>,
+    ) {
+        if let Some(usage) = &self.usage {
+            // Best effort, like metrics: a read-only home must not fail the translation.
+            let _ = usage.record(&UsageEvent::new(provider, model, outcome, latency));
+        }
+    }
+
+    fn call_fixture(
+        &self,
+        req: &LlmTranslateRequest,
+        model_override: Option<&str>,
+    ) -> Result<LlmTranslateResponse> {
+        let store = self.fixtures.as_ref().ok_or_else(|| {
+            ProviderCallError::new(
+                ProviderErrorKind::Config,
+                "fixture provider has no fixture directory configured",
+            )
+        })?;
+        if let Some(javascript) = store.load(req)? {
+            return Ok(LlmTranslateResponse {
+                javascript,
+                provider: Provider::Fixture,
+                model: FIXTURE_MODEL.to_string(),
+                served_model: None,
+                notes: None,
+                confidence: None,
+                explanation: None,
+            });
+        }
+        if !store.record {
+            return Err(ProviderCallError::new(
+                ProviderErrorKind::Config,
+                format!(
+                    "no fixture for {} at {} (set KLUMO_RECORD_FIXTURES=1 to record one)",
+                    req.source_id,
+                    store.path_for(req).display()
+                ),
+            )
+            .into());
+        }
+        let response = self.translate(ProviderSelection::Auto, req, model_override)?;
+        store.save(req, &response.javascript)?;
+        Ok(response)
+    }
+
+    /// The configured Ollama model, swapped for an installed one when the probe can list models.
+    fn discovered_ollama_model(&self) -> String {
+        self.reachability
+            .installed_ollama_models()
+            .and_then(|installed| {
+                pick_ollama_model(
+                    &installed,
+                    &self.ollama_model,
+                    &self.ollama_model_preferences,
+                )
+            })
+            .unwrap_or_else(|| self.ollama_model.clone())
+    }
+
+    fn llamacpp_descriptor(&self) -> ProviderDescriptor {
+        ProviderDescriptor {
+            provider: Provider::LlamaCpp,
+            model: self
+                .llamacpp
+                .as_ref()
+                .map_or_else(String::new, |entry| entry.model.clone()),
+        }
+    }
+
+    fn custom_descriptors(&self) -> impl Iterator<Item = ProviderDescriptor> + '_ {
+        self.custom.iter().map(|entry| ProviderDescriptor {
+            provider: Provider::Custom(entry.name.clone()),
+            model: entry.model.clone(),
+        })
     }
 }
 
 impl<O, P, R> TranslationService for ProviderRouter<O, P, R>
 where
-    O: LlmClient,
-    P: LlmClient,
-    R: ReachabilityProbe,
+    O: LlmClient + Sync,
+    P: LlmClient + Sync,
+    R: ReachabilityProbe + Sync,
 {
     fn candidate_chain(&self, selection: ProviderSelection) -> Vec<ProviderDescriptor> {
         match selection {
@@ -180,69 +1156,189 @@ where
                 provider: Provider::OpenAiCompatible,
                 model: self.openai_model.clone(),
             }],
+            ProviderSelection::LlamaCpp => vec![self.llamacpp_descriptor()],
+            ProviderSelection::Custom(name) => vec![self.custom_descriptor(name)],
+            ProviderSelection::Fixture => vec![ProviderDescriptor {
+                provider: Provider::Fixture,
+                model: FIXTURE_MODEL.to_string(),
+            }],
             ProviderSelection::Auto => {
+                let mut chain = Vec::new();
                 if self.reachability.ollama_reachable() {
-                    vec![
-                        ProviderDescriptor {
-                            provider: Provider::Ollama,
-                            model: self.ollama_model.clone(),
-                        },
-                        ProviderDescriptor {
-                            provider: Provider::OpenAiCompatible,
-                            model: self.openai_model.clone(),
-                        },
-                    ]
-                } else {
-                    vec![ProviderDescriptor {
-                        provider: Provider::OpenAiCompatible,
-                        model: self.openai_model.clone(),
-                    }]
+                    chain.push(ProviderDescriptor {
+                        provider: Provider::Ollama,
+                        model: self.discovered_ollama_model(),
+                    });
+                }
+                if self.llamacpp.is_some() {
+                    chain.push(self.llamacpp_descriptor());
                 }
+                chain.extend(self.custom_descriptors());
+                chain.push(ProviderDescriptor {
+                    provider: Provider::OpenAiCompatible,
+                    model: self.openai_model.clone(),
+                });
+                chain
             }
         }
     }
 
-    fn translate(
+    fn resolved_chain(
         &self,
         selection: ProviderSelection,
-        req: &LlmTranslateRequest,
         model_override: Option<&str>,
-    ) -> Result<LlmTranslateResponse> {
-        let chain = self.candidate_chain(selection);
-        let mut attempts = Vec::new();
+    ) -> Vec<ProviderDescriptor> {
+        dedup_chain(
+            self.candidate_chain(selection)
+                .into_iter()
+                .map(|mut entry| {
+                    // Fixtures always answer as `FIXTURE_MODEL`.
+                    if let Some(model) = model_override
+                        && entry.provider != Provider::Fixture
+                    {
+                        entry.model = model.to_string();
+                    }
+                    entry
+                }),
+        )
+    }
 
-        let total = chain.len();
-        for (index, entry) in chain.into_iter().enumerate() {
-            match self.call_provider(entry.provider, req, model_override) {
-                Ok(response) => return Ok(response),
-                Err(err) => {
-                    let note = if index + 1 < total && matches!(entry.provider, Provider::Ollama) {
-                        Some("Ollama failed, falling back to OpenAI-compatible".to_string())
-                    } else {
-                        None
-                    };
+    /// Asks the first provider of the chain that answers, with
+    /// [`PromptTemplates::language_classifier`] and the start of the source. Providers behind
+    /// an open circuit and fixture replay are skipped; the call is not logged or metered.
+    fn classify_language(&self, selection: ProviderSelection, source_text: &str) -> Option<String> {
+        let mut end = source_text.len().min(CLASSIFY_SAMPLE_BYTES);
+        while !source_text.is_char_boundary(end) {
+            end -= 1;
+        }
+        let req = LlmTranslateRequest {
+            source_text: source_text[..end].to_string(),
+            source_id: "<classify>".to_string(),
+            language_hint: None,
+            scope_context: None,
+            repair_hint: None,
+            temperature: Some(0.0),
+            top_p: None,
+            max_tokens: Some(8),
+            seed: None,
+            structured_output: false,
+            explain: false,
+            explain_javascript: None,
+            prompts: PromptTemplates::language_classifier(),
+        };
+        self.candidate_chain(selection)
+            .iter()
+            .filter(|entry| entry.provider != Provider::Fixture)
+            .filter(|entry| {
+                self.circuit
+                    .as_ref()
+                    .is_none_or(|circuit| circuit.open_for(&entry.provider).is_none())
+            })
+            .find_map(|entry| {
+                let client = self.client(&entry.provider).ok()?;
+                language_name(&client.translate_to_js(&req, &entry.model).ok()?)
+            })
+    }
 
-                    attempts.push(ProviderAttempt {
-                        provider: entry.provider,
-                        stage: "translate",
-                        error: err.to_string(),
-                        note,
-                    })
+    fn translate(
+        &self,
+        selection: ProviderSelection,
+        req: &LlmTranslateRequest,
+        model_override: Option<&str>,
+    ) -> Result<LlmTranslateResponse> {
+        self.translate_along(&self.candidate_chain(selection), req, model_override)
+    }
+
+    fn translate_for_provider(
+        &self,
+        selection: ProviderSelection,
+        req: &LlmTranslateRequest,
+        provider: &Provider,
+        model: &str,
+    ) -> Result<LlmTranslateResponse> {
+        let chain: Vec<ProviderDescriptor> = self
+            .candidate_chain(selection)
+            .into_iter()
+            .map(|mut entry| {
+                if entry.provider == *provider {
+                    entry.model = model.to_string();
                 }
-            }
+                entry
+            })
+            .collect();
+        self.translate_along(&chain, req, None)
+    }
+
+    /// Batches run up to [`max_concurrency`](TranslationService::max_concurrency) requests
+    /// in parallel.
+    fn translate_batch(
+        &self,
+        selection: ProviderSelection,
+        reqs: &[LlmTranslateRequest],
+        model_override: Option<&str>,
+    ) -> Vec<Result<LlmTranslateResponse>> {
+        let chain = self.candidate_chain(selection);
+        let workers = self.chain_concurrency(&chain).min(reqs.len());
+        if workers < 2 {
+            return reqs
+                .iter()
+                .map(|req| self.translate_along(&chain, req, model_override))
+                .collect();
         }
 
-        Err(ProviderRoutingError { attempts }.into())
+        let next = AtomicUsize::new(0);
+        let results: Mutex<Vec<Option<Result<LlmTranslateResponse>>>> =
+            Mutex::new(reqs.iter().map(|_| None).collect());
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| {
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(req) = reqs.get(index) else {
+                            break;
+                        };
+                        let result = self.translate_along(&chain, req, model_override);
+                        results
+                            .lock()
+                            .unwrap_or_else(|poisoned| poisoned.into_inner())[index] = Some(result);
+                    }
+                });
+            }
+        });
+        results
+            .into_inner()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .into_iter()
+            .map(|result| result.expect("every batched request is translated"))
+            .collect()
+    }
+
+    fn prompt_fingerprint(&self) -> String {
+        self.prompts.fingerprint()
+    }
+
+    fn max_concurrency(&self, selection: ProviderSelection) -> usize {
+        self.chain_concurrency(&self.candidate_chain(selection))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::{
-        LlmClient, LlmTranslateRequest, Provider, ProviderDescriptor, ProviderRouter,
-        ProviderSelection, ReachabilityProbe, TranslationService, normalize_js_output,
+        AsyncTranslationService, AttemptErrorKind, CLASSIFY_SAMPLE_BYTES, CachedProbe,
+        CircuitBreaker, ConstructMapping, CustomProvider, FIXTURE_MODEL, FixtureStore,
+        LlamaCppProvider, LlmClient, LlmLogDir, LlmTranslateRequest, PromptTemplates, Provider,
+        ProviderCallError, ProviderDescriptor, ProviderErrorKind, ProviderMetricsStore,
+        ProviderRouter, ProviderRoutingError, ProviderSelection, ReachabilityProbe, ResponseCache,
+        TranslationService, UsageLog, UsageOutcome, block_on, language_name, normalize_js_output,
+        pick_ollama_model,
     };
     use anyhow::{Result, anyhow};
+    use std::cell::Cell;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
 
     struct StubClient {
         fail: bool,
@@ -266,19 +1362,227 @@ mod tests {
         }
     }
 
+    struct ListingProbe(Vec<&'static str>);
+
+    impl ReachabilityProbe for ListingProbe {
+        fn ollama_reachable(&self) -> bool {
+            true
+        }
+
+        fn installed_ollama_models(&self) -> Option<Vec<String>> {
+            Some(self.0.iter().map(ToString::to_string).collect())
+        }
+    }
+
     fn req() -> LlmTranslateRequest {
         LlmTranslateRequest {
             source_text: "write 1".to_string(),
             source_id: "sample.pseudo".to_string(),
             language_hint: Some("pseudo".to_string()),
             scope_context: None,
+            repair_hint: None,
+            temperature: None,
+            top_p: None,
+            max_tokens: None,
+            seed: None,
+            structured_output: false,
+            explain: false,
+            explain_javascript: None,
+            prompts: PromptTemplates::default(),
+        }
+    }
+
+    /// Echoes the source id after a short delay and tracks how many calls overlap.
+    #[derive(Default)]
+    struct OverlapClient {
+        active: AtomicUsize,
+        peak: AtomicUsize,
+        remaining: Option<u64>,
+    }
+
+    impl LlmClient for OverlapClient {
+        fn translate_to_js(&self, req: &LlmTranslateRequest, _model: &str) -> Result<String> {
+            let active = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(active, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(20));
+            self.active.fetch_sub(1, Ordering::SeqCst);
+            Ok(format!("console.log({:?})", req.source_id))
+        }
+
+        fn remaining_requests(&self) -> Option<u64> {
+            self.remaining
+        }
+    }
+
+    /// A router over `ollama` and `openai` using `ollama-model` and `openai-model`, with every
+    /// optional feature off; tests turn features on with struct update syntax.
+    fn test_router<O, P, R>(ollama: O, openai: P, reachability: R) -> ProviderRouter<O, P, R>
+    where
+        O: LlmClient,
+        P: LlmClient,
+        R: ReachabilityProbe,
+    {
+        ProviderRouter {
+            ollama,
+            openai,
+            reachability,
+            ollama_model: "ollama-model".to_string(),
+            openai_model: "openai-model".to_string(),
+            custom: Vec::new(),
+            ollama_model_preferences: Vec::new(),
+            fixtures: None,
+            prompts: PromptTemplates::default(),
+            metrics: None,
+            observer: None,
+            circuit: None,
+            response_cache: None,
+            usage: None,
+            ollama_auto_pull: false,
+            llamacpp: None,
+        }
+    }
+
+    fn overlap_router(
+        ollama_reachable: bool,
+    ) -> ProviderRouter<OverlapClient, OverlapClient, Probe> {
+        test_router(
+            OverlapClient::default(),
+            OverlapClient::default(),
+            Probe(ollama_reachable),
+        )
+    }
+
+    #[test]
+    fn shared_router_serves_async_callers() {
+        let router = Arc::new(overlap_router(false));
+        let pending: Vec<_> = (0..3)
+            .map(|index| {
+                router.translate_async(
+                    ProviderSelection::OpenAiCompatible,
+                    LlmTranslateRequest {
+                        source_id: format!("part{index}.pseudo"),
+                        ..req()
+                    },
+                    None,
+                )
+            })
+            .collect();
+        let outputs: Vec<String> = pending
+            .into_iter()
+            .map(|translation| {
+                block_on(translation)
+                    .expect("translation should work")
+                    .javascript
+            })
+            .collect();
+        assert_eq!(
+            outputs,
+            vec![
+                "console.log(\"part0.pseudo\")",
+                "console.log(\"part1.pseudo\")",
+                "console.log(\"part2.pseudo\")"
+            ]
+        );
+        assert!(
+            router.openai.peak.load(Ordering::SeqCst) > 1,
+            "calls ran on pool threads at the same time"
+        );
+    }
+
+    #[test]
+    fn batches_run_in_parallel_for_remote_endpoints_and_keep_order() {
+        let reqs: Vec<LlmTranslateRequest> = (0..6)
+            .map(|index| LlmTranslateRequest {
+                source_id: format!("part{index}.pseudo"),
+                ..req()
+            })
+            .collect();
+
+        let remote = overlap_router(false);
+        let results = remote.translate_batch(ProviderSelection::Auto, &reqs, None);
+        let outputs: Vec<String> = results
+            .into_iter()
+            .map(|result| result.expect("batch entry should translate").javascript)
+            .collect();
+        assert_eq!(outputs[0], "console.log(\"part0.pseudo\")");
+        assert_eq!(outputs[5], "console.log(\"part5.pseudo\")");
+        assert!(remote.openai.peak.load(Ordering::SeqCst) > 1);
+
+        let local = overlap_router(true);
+        let results = local.translate_batch(ProviderSelection::Auto, &reqs, None);
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(local.ollama.peak.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn batches_stay_within_the_requests_rate_limits_leave() {
+        let reqs: Vec<LlmTranslateRequest> = (0..6)
+            .map(|index| LlmTranslateRequest {
+                source_id: format!("part{index}.pseudo"),
+                ..req()
+            })
+            .collect();
+        for (remaining, expected) in [(Some(2), 2), (Some(0), 1), (Some(u64::MAX), 4)] {
+            let mut router = overlap_router(false);
+            router.openai.remaining = remaining;
+            assert_eq!(router.max_concurrency(ProviderSelection::Auto), expected);
+            let results = router.translate_batch(ProviderSelection::Auto, &reqs, None);
+            assert!(results.iter().all(Result::is_ok));
+            assert!(router.openai.peak.load(Ordering::SeqCst) <= expected);
         }
     }
 
     #[test]
     fn strips_fence() {
         let out = normalize_js_output("```js\n1+1\n```").expect("normalize should pass");
-        assert_eq!(out, "1+1");
+        assert_eq!(out.javascript, "1+1");
+    }
+
+    #[test]
+    fn unpacks_structured_json_output() {
+        let out = normalize_js_output(
+            "{\"javascript\": \"```js\\nconsole.log(1)\\n```\", \"notes\": \"assumed stdout\", \"confidence\": 1.4}",
+        )
+        .expect("normalize should pass");
+        assert_eq!(out.javascript, "console.log(1)");
+        assert_eq!(out.notes.as_deref(), Some("assumed stdout"));
+        assert_eq!(out.confidence, Some(1.0));
+
+        let fenced = normalize_js_output(
+            "```json\n{\"javascript\": \"1 + 1\", \"notes\": \"\", \"confidence\": 0.5}\n```",
+        )
+        .expect("normalize should pass");
+        assert_eq!(fenced.javascript, "1 + 1");
+        assert_eq!(fenced.notes, None);
+        assert_eq!(fenced.confidence, Some(0.5));
+
+        let block = normalize_js_output("{ let x = 1; }").expect("normalize should pass");
+        assert_eq!(block.javascript, "{ let x = 1; }");
+        assert_eq!(block.notes, None);
+    }
+
+    #[test]
+    fn unpacks_explanations_and_drops_malformed_ones() {
+        let out = normalize_js_output(
+            "{\"javascript\": \"console.log(1)\", \"explanation\": {\"rationale\": \"print maps to console.log\", \"mappings\": [{\"source\": \"print 1\", \"generated\": \"console.log(1)\"}]}}",
+        )
+        .expect("normalize should pass");
+        let explanation = out.explanation.expect("explanation should parse");
+        assert_eq!(explanation.rationale, "print maps to console.log");
+        assert_eq!(
+            explanation.mappings,
+            vec![ConstructMapping {
+                source: "print 1".to_string(),
+                generated: "console.log(1)".to_string(),
+            }]
+        );
+
+        let malformed = normalize_js_output(
+            "{\"javascript\": \"console.log(1)\", \"explanation\": \"it prints\"}",
+        )
+        .expect("normalize should pass");
+        assert_eq!(malformed.javascript, "console.log(1)");
+        assert_eq!(malformed.explanation, None);
     }
 
     #[test]
@@ -289,19 +1593,17 @@ mod tests {
 
     #[test]
     fn auto_prefers_ollama_when_reachable() {
-        let router = ProviderRouter {
-            ollama: StubClient {
+        let router = test_router(
+            StubClient {
                 fail: false,
                 output: "1".to_string(),
             },
-            openai: StubClient {
+            StubClient {
                 fail: false,
                 output: "2".to_string(),
             },
-            reachability: Probe(true),
-            ollama_model: "ollama-model".to_string(),
-            openai_model: "openai-model".to_string(),
-        };
+            Probe(true),
+        );
 
         let chain = router.candidate_chain(ProviderSelection::Auto);
         assert_eq!(chain[0].provider, Provider::Ollama);
@@ -309,42 +1611,45 @@ mod tests {
 
     #[test]
     fn auto_falls_back_to_openai_on_ollama_failure() {
-        let router = ProviderRouter {
-            ollama: StubClient {
+        let router = test_router(
+            StubClient {
                 fail: true,
                 output: String::new(),
             },
-            openai: StubClient {
+            StubClient {
                 fail: false,
                 output: "3".to_string(),
             },
-            reachability: Probe(true),
-            ollama_model: "ollama-model".to_string(),
-            openai_model: "openai-model".to_string(),
-        };
+            Probe(true),
+        );
 
         let response = router
             .translate(ProviderSelection::Auto, &req(), None)
             .expect("fallback should work");
         assert_eq!(response.provider, Provider::OpenAiCompatible);
         assert_eq!(response.javascript, "3");
+
+        // A model meant for Ollama is not passed on to the fallback.
+        let response = router
+            .translate_for_provider(ProviderSelection::Auto, &req(), &Provider::Ollama, "tiny")
+            .expect("fallback should work");
+        assert_eq!(response.provider, Provider::OpenAiCompatible);
+        assert_eq!(response.model, "openai-model");
     }
 
     #[test]
     fn explicit_provider_bypasses_auto() {
-        let router = ProviderRouter {
-            ollama: StubClient {
+        let router = test_router(
+            StubClient {
                 fail: false,
                 output: "1".to_string(),
             },
-            openai: StubClient {
+            StubClient {
                 fail: false,
                 output: "9".to_string(),
             },
-            reachability: Probe(true),
-            ollama_model: "ollama-model".to_string(),
-            openai_model: "openai-model".to_string(),
-        };
+            Probe(true),
+        );
 
         let chain = router.candidate_chain(ProviderSelection::OpenAiCompatible);
         assert_eq!(
@@ -364,19 +1669,17 @@ mod tests {
 
     #[test]
     fn routing_error_is_human_readable_with_fallback_note() {
-        let router = ProviderRouter {
-            ollama: StubClient {
+        let router = test_router(
+            StubClient {
                 fail: true,
                 output: String::new(),
             },
-            openai: StubClient {
+            StubClient {
                 fail: true,
                 output: String::new(),
             },
-            reachability: Probe(true),
-            ollama_model: "ollama-model".to_string(),
-            openai_model: "openai-model".to_string(),
-        };
+            Probe(true),
+        );
 
         let err = router
             .translate(ProviderSelection::Auto, &req(), None)
@@ -385,5 +1688,783 @@ mod tests {
         assert!(rendered.contains("LLM routing failed"));
         assert!(rendered.contains("ollama"));
         assert!(rendered.contains("falling back to OpenAI-compatible"));
+        assert!(rendered.contains("[other]"));
+    }
+
+    struct HttpFailingClient;
+
+    impl LlmClient for HttpFailingClient {
+        fn translate_to_js(&self, _req: &LlmTranslateRequest, _model: &str) -> Result<String> {
+            Err(ProviderCallError::http("http://llm.test/v1", 503, "unavailable").into())
+        }
+    }
+
+    struct PullableOllama(Arc<AtomicBool>);
+
+    impl LlmClient for PullableOllama {
+        fn translate_to_js(&self, _req: &LlmTranslateRequest, model: &str) -> Result<String> {
+            if !self.0.load(Ordering::SeqCst) {
+                let message = format!("model '{model}' not found");
+                return Err(
+                    ProviderCallError::http("http://ollama.test/api/chat", 404, message).into(),
+                );
+            }
+            Ok("pulled()".to_string())
+        }
+    }
+
+    struct PullingProbe(Arc<AtomicBool>, Mutex<Vec<String>>);
+
+    impl ReachabilityProbe for PullingProbe {
+        fn ollama_reachable(&self) -> bool {
+            true
+        }
+
+        fn installed_ollama_models(&self) -> Option<Vec<String>> {
+            Some(vec!["llama3:latest".to_string()])
+        }
+
+        fn pull_ollama_model(&self, model: &str) -> Result<()> {
+            self.1.lock().expect("lock").push(model.to_string());
+            self.0.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn missing_ollama_model_is_pulled_once_when_auto_pull_is_on() {
+        let pulled = Arc::new(AtomicBool::new(false));
+        let mut router = ProviderRouter {
+            ollama_model: "qwen2.5-coder:7b".to_string(),
+            ..test_router(
+                PullableOllama(Arc::clone(&pulled)),
+                StubClient {
+                    fail: true,
+                    output: String::new(),
+                },
+                PullingProbe(Arc::clone(&pulled), Mutex::new(Vec::new())),
+            )
+        };
+
+        let err = router
+            .translate(ProviderSelection::Ollama, &req(), None)
+            .expect_err("missing model should fail without auto-pull");
+        assert!(err.to_string().contains("[http_status 404 after "));
+        assert!(
+            err.to_string()
+                .contains("`ollama pull qwen2.5-coder:7b` or set `ollama_auto_pull`")
+        );
+        assert!(router.reachability.1.lock().expect("lock").is_empty());
+
+        router.ollama_auto_pull = true;
+        let response = router
+            .translate(ProviderSelection::Ollama, &req(), None)
+            .expect("pulled model should answer");
+        assert_eq!(response.javascript, "pulled()");
+        assert_eq!(
+            *router.reachability.1.lock().expect("lock"),
+            vec!["qwen2.5-coder:7b"]
+        );
+
+        pulled.store(false, Ordering::SeqCst);
+        router.ollama_model = "llama3:latest".to_string();
+        router
+            .translate(ProviderSelection::Ollama, &req(), None)
+            .expect_err("a 404 for an installed model should not pull");
+        assert_eq!(router.reachability.1.lock().expect("lock").len(), 1);
+    }
+
+    #[test]
+    fn response_cache_serves_reformatted_sources_without_a_call() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let mut router = ProviderRouter {
+            response_cache: Some(ResponseCache::new(dir.path().to_path_buf())),
+            ..test_router(
+                StubClient {
+                    fail: true,
+                    output: String::new(),
+                },
+                StubClient {
+                    fail: false,
+                    output: "console.log(1);".to_string(),
+                },
+                Probe(false),
+            )
+        };
+        router
+            .translate(ProviderSelection::OpenAiCompatible, &req(), None)
+            .expect("first call should reach the provider");
+
+        router.openai.fail = true;
+        let reformatted = LlmTranslateRequest {
+            source_text: "\n\nwrite   1   \n".to_string(),
+            source_id: "copy.pseudo".to_string(),
+            ..req()
+        };
+        let cached = router
+            .translate(ProviderSelection::OpenAiCompatible, &reformatted, None)
+            .expect("reformatted source should be served from the cache");
+        assert_eq!(cached.javascript, "console.log(1);");
+        assert_eq!(cached.model, "openai-model");
+
+        let changed = LlmTranslateRequest {
+            source_text: "print two".to_string(),
+            ..req()
+        };
+        assert!(
+            router
+                .translate(ProviderSelection::OpenAiCompatible, &changed, None)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn llm_log_dumps_prompt_and_raw_response_per_exchange() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let router = ProviderRouter {
+            observer: Some(Arc::new(LlmLogDir::new(dir.path().join("llm-log")))),
+            ..test_router(
+                StubClient {
+                    fail: true,
+                    output: String::new(),
+                },
+                StubClient {
+                    fail: false,
+                    output: "```js\nok()\n```".to_string(),
+                },
+                Probe(true),
+            )
+        };
+
+        router
+            .translate(ProviderSelection::Auto, &req(), None)
+            .expect("openai should answer");
+
+        let mut logs: Vec<_> = std::fs::read_dir(dir.path().join("llm-log"))
+            .expect("log dir should exist")
+            .map(|entry| entry.expect("entry").path())
+            .collect();
+        logs.sort();
+        assert_eq!(logs.len(), 2);
+        assert!(logs[0].to_string_lossy().ends_with("-0000-ollama.txt"));
+        let failed = std::fs::read_to_string(&logs[0]).expect("read log");
+        assert!(failed.contains("--- error ---\nstub failure"));
+        let answered = std::fs::read_to_string(&logs[1]).expect("read log");
+        assert!(answered.starts_with("provider: openai-compatible\nmodel: openai-model\n"));
+        assert!(answered.contains("--- prompt: input ---"));
+        assert!(answered.contains("--- response ---\n```js\nok()\n```"));
+    }
+
+    /// Names the language when asked with the classifier prompt.
+    struct ClassifierClient;
+
+    impl LlmClient for ClassifierClient {
+        fn translate_to_js(&self, req: &LlmTranslateRequest, _model: &str) -> Result<String> {
+            assert!(req.source_text.len() <= CLASSIFY_SAMPLE_BYTES);
+            let asked = req
+                .prompts
+                .render("system", req)
+                .starts_with("You identify programming languages");
+            Ok(if asked { "`Python`." } else { "translated()" }.to_string())
+        }
+    }
+
+    #[test]
+    fn classify_language_asks_the_chain_and_reads_a_bare_name() {
+        let router = test_router(
+            StubClient {
+                fail: true,
+                output: String::new(),
+            },
+            ClassifierClient,
+            Probe(true),
+        );
+        let source = "print('hi')\n".repeat(500);
+        assert_eq!(
+            router.classify_language(ProviderSelection::Auto, &source),
+            Some("python".to_string())
+        );
+        assert_eq!(
+            router.classify_language(ProviderSelection::Fixture, &source),
+            None
+        );
+
+        assert_eq!(language_name("```\nruby\n```"), Some("ruby".to_string()));
+        assert_eq!(language_name("Unknown."), None);
+        assert_eq!(
+            language_name("This looks like Python code to me, probably version 3"),
+            None
+        );
+    }
+
+    /// Answers with the first model of its list, as an OpenRouter fallback would.
+    struct RoutingClient(&'static str);
+
+    impl LlmClient for RoutingClient {
+        fn translate_to_js(&self, _req: &LlmTranslateRequest, _model: &str) -> Result<String> {
+            Ok("routed()".to_string())
+        }
+
+        fn translate_reporting_model(
+            &self,
+            req: &LlmTranslateRequest,
+            model: &str,
+        ) -> Result<(String, Option<String>)> {
+            Ok((self.translate_to_js(req, model)?, Some(self.0.to_string())))
+        }
+    }
+
+    #[test]
+    fn response_records_the_model_that_served_a_fallback() {
+        let router = |served: &'static str| ProviderRouter {
+            openai_model: "openai/gpt-4.1".to_string(),
+            ..test_router(
+                StubClient {
+                    fail: true,
+                    output: String::new(),
+                },
+                RoutingClient(served),
+                Probe(false),
+            )
+        };
+
+        let response = router("anthropic/claude-sonnet")
+            .translate(ProviderSelection::OpenAiCompatible, &req(), None)
+            .expect("fallback should answer");
+        assert_eq!(response.model, "openai/gpt-4.1");
+        assert_eq!(
+            response.served_model.as_deref(),
+            Some("anthropic/claude-sonnet")
+        );
+
+        let response = router("openai/gpt-4.1")
+            .translate(ProviderSelection::OpenAiCompatible, &req(), None)
+            .expect("primary should answer");
+        assert_eq!(response.served_model, None);
+    }
+
+    struct CountingProbe(Cell<usize>);
+
+    impl ReachabilityProbe for CountingProbe {
+        fn ollama_reachable(&self) -> bool {
+            self.0.set(self.0.get() + 1);
+            true
+        }
+
+        fn installed_ollama_models(&self) -> Option<Vec<String>> {
+            self.0.set(self.0.get() + 1);
+            Some(vec!["llama3:latest".to_string()])
+        }
+
+        fn pull_ollama_model(&self, _model: &str) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn cached_probe_reuses_answers_within_ttl() {
+        let probe = CachedProbe::new(CountingProbe(Cell::new(0)), Duration::from_secs(60));
+        assert!(probe.ollama_reachable());
+        assert!(probe.ollama_reachable());
+        assert_eq!(probe.installed_ollama_models().map(|m| m.len()), Some(1));
+        assert_eq!(probe.installed_ollama_models().map(|m| m.len()), Some(1));
+        assert_eq!(probe.inner.0.get(), 2);
+
+        probe.pull_ollama_model("qwen2.5-coder:7b").expect("pull");
+        probe.installed_ollama_models();
+        assert_eq!(
+            probe.inner.0.get(),
+            3,
+            "a pull should refresh the model list"
+        );
+
+        let uncached = CachedProbe::new(CountingProbe(Cell::new(0)), Duration::ZERO);
+        uncached.ollama_reachable();
+        uncached.ollama_reachable();
+        assert_eq!(uncached.inner.0.get(), 2);
+        uncached.installed_ollama_models();
+        uncached.installed_ollama_models();
+        assert_eq!(
+            uncached.inner.0.get(),
+            3,
+            "the model list should outlive the ttl"
+        );
+    }
+
+    #[test]
+    fn routing_error_carries_attempt_diagnostics() {
+        assert_eq!(
+            ProviderErrorKind::from_status(401),
+            ProviderErrorKind::Config
+        );
+        assert_eq!(
+            ProviderErrorKind::from_status(429),
+            ProviderErrorKind::Network
+        );
+        assert_eq!(
+            ProviderErrorKind::from_status(422),
+            ProviderErrorKind::Model
+        );
+
+        let router = test_router(
+            StubClient {
+                fail: false,
+                output: "   ".to_string(),
+            },
+            HttpFailingClient,
+            Probe(true),
+        );
+
+        let err = router
+            .translate(ProviderSelection::Auto, &req(), None)
+            .expect_err("expected routing failure");
+        let routing = err
+            .downcast_ref::<ProviderRoutingError>()
+            .expect("routing error");
+        assert_eq!(routing.attempts[0].kind, ProviderErrorKind::Model);
+        assert_eq!(
+            routing.attempts[0].error_kind,
+            AttemptErrorKind::EmptyOutput
+        );
+        assert_eq!(routing.attempts[0].model, "ollama-model");
+        let last = &routing.attempts[1];
+        assert_eq!(last.kind, ProviderErrorKind::Network);
+        assert_eq!(last.error_kind, AttemptErrorKind::HttpStatus);
+        assert_eq!(last.model, "openai-model");
+        assert_eq!(last.status, Some(503));
+        assert_eq!(last.endpoint.as_deref(), Some("http://llm.test/v1"));
+        assert_eq!(routing.kind(), ProviderErrorKind::Network);
+        assert!(!routing.nothing_reachable(), "both endpoints answered");
+        let rendered = err.to_string();
+        assert!(rendered.starts_with("LLM routing failed after 2 attempts:\n"));
+        assert!(rendered.contains(
+            "  1. ollama ollama-model [empty_output]: LLM returned empty output\n     Ollama failed, falling back to OpenAI-compatible\n"
+        ));
+        assert!(rendered.contains("  2. openai-compatible openai-model [http_status 503 after "));
+        assert!(rendered.ends_with("ms]: unavailable\n     endpoint: http://llm.test/v1\n"));
+
+        assert_eq!(
+            AttemptErrorKind::classify(Some(&ProviderCallError::new(
+                ProviderErrorKind::Model,
+                "no code"
+            ))),
+            AttemptErrorKind::Normalization
+        );
+        assert_eq!(
+            AttemptErrorKind::classify(Some(&ProviderCallError::new(
+                ProviderErrorKind::Network,
+                "connection refused"
+            ))),
+            AttemptErrorKind::Network
+        );
+    }
+
+    struct MissingKeyClient;
+
+    impl LlmClient for MissingKeyClient {
+        fn translate_to_js(&self, _req: &LlmTranslateRequest, _model: &str) -> Result<String> {
+            Err(
+                ProviderCallError::new(ProviderErrorKind::Config, "OPENAI_API_KEY is required")
+                    .into(),
+            )
+        }
+    }
+
+    #[test]
+    fn routing_without_a_usable_provider_is_nothing_reachable() {
+        let router = test_router(
+            StubClient {
+                fail: false,
+                output: "never()".to_string(),
+            },
+            MissingKeyClient,
+            Probe(false),
+        );
+
+        let err = router
+            .translate(ProviderSelection::Auto, &req(), None)
+            .expect_err("expected routing failure");
+        let routing = err
+            .downcast_ref::<ProviderRoutingError>()
+            .expect("routing error");
+        assert_eq!(routing.attempts.len(), 1);
+        assert!(routing.nothing_reachable());
+    }
+
+    #[test]
+    fn circuit_skips_provider_after_repeated_network_failures() {
+        let router = ProviderRouter {
+            circuit: Some(CircuitBreaker::new(
+                2,
+                Duration::from_secs(60),
+                Duration::from_secs(60),
+            )),
+            ..test_router(
+                StubClient {
+                    fail: false,
+                    output: "   ".to_string(),
+                },
+                HttpFailingClient,
+                Probe(true),
+            )
+        };
+
+        let stages = |router: &ProviderRouter<StubClient, HttpFailingClient, Probe>| {
+            let err = router
+                .translate(ProviderSelection::Auto, &req(), None)
+                .expect_err("expected routing failure");
+            let routing = err
+                .downcast_ref::<ProviderRoutingError>()
+                .expect("routing error");
+            routing
+                .attempts
+                .iter()
+                .map(|attempt| attempt.stage)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(stages(&router), ["translate", "translate"]);
+        assert_eq!(stages(&router), ["translate", "translate"]);
+        // OpenAI failed twice on the network and is skipped; Ollama's bad output does not count.
+        assert_eq!(stages(&router), ["translate", "circuit"]);
+    }
+
+    #[test]
+    fn auto_chain_routes_through_custom_providers_in_order() {
+        let router = ProviderRouter {
+            custom: vec![
+                CustomProvider {
+                    name: "corp".to_string(),
+                    client: StubClient {
+                        fail: true,
+                        output: String::new(),
+                    },
+                    model: "corp-model".to_string(),
+                },
+                CustomProvider {
+                    name: "lab".to_string(),
+                    client: StubClient {
+                        fail: false,
+                        output: "7".to_string(),
+                    },
+                    model: "lab-model".to_string(),
+                },
+            ],
+            ..test_router(
+                StubClient {
+                    fail: true,
+                    output: String::new(),
+                },
+                StubClient {
+                    fail: true,
+                    output: String::new(),
+                },
+                Probe(false),
+            )
+        };
+
+        let providers: Vec<Provider> = router
+            .candidate_chain(ProviderSelection::Auto)
+            .into_iter()
+            .map(|entry| entry.provider)
+            .collect();
+        assert_eq!(
+            providers,
+            vec![
+                Provider::Custom("corp".to_string()),
+                Provider::Custom("lab".to_string()),
+                Provider::OpenAiCompatible,
+            ]
+        );
+
+        let response = router
+            .translate(ProviderSelection::Auto, &req(), None)
+            .expect("lab provider should answer");
+        assert_eq!(response.provider, Provider::Custom("lab".to_string()));
+        assert_eq!(response.model, "lab-model");
+        assert_eq!(response.javascript, "7");
+        assert_eq!(response.provider.to_string(), "custom:lab");
+
+        let chain = router.candidate_chain(ProviderSelection::Custom("lab".to_string()));
+        assert_eq!(
+            chain,
+            vec![ProviderDescriptor {
+                provider: Provider::Custom("lab".to_string()),
+                model: "lab-model".to_string(),
+            }]
+        );
+        let err = router
+            .translate(ProviderSelection::Custom("nope".to_string()), &req(), None)
+            .expect_err("undeclared provider should fail");
+        assert!(format!("{err:#}").contains("unknown provider 'custom:nope'"));
+    }
+
+    #[test]
+    fn llamacpp_is_routed_after_ollama_when_configured() {
+        let router = ProviderRouter {
+            custom: vec![CustomProvider {
+                name: "corp".to_string(),
+                client: StubClient {
+                    fail: true,
+                    output: String::new(),
+                },
+                model: "corp-model".to_string(),
+            }],
+            llamacpp: Some(LlamaCppProvider {
+                client: Box::new(StubClient {
+                    fail: false,
+                    output: "8".to_string(),
+                }),
+                model: "default".to_string(),
+            }),
+            ..test_router(
+                StubClient {
+                    fail: true,
+                    output: String::new(),
+                },
+                StubClient {
+                    fail: true,
+                    output: String::new(),
+                },
+                Probe(true),
+            )
+        };
+
+        let providers: Vec<Provider> = router
+            .candidate_chain(ProviderSelection::Auto)
+            .into_iter()
+            .map(|entry| entry.provider)
+            .collect();
+        assert_eq!(
+            providers,
+            vec![
+                Provider::Ollama,
+                Provider::LlamaCpp,
+                Provider::Custom("corp".to_string()),
+                Provider::OpenAiCompatible,
+            ]
+        );
+
+        let response = router
+            .translate(ProviderSelection::LlamaCpp, &req(), None)
+            .expect("llama.cpp should answer");
+        assert_eq!(response.provider, Provider::LlamaCpp);
+        assert_eq!(response.model, "default");
+        assert_eq!(response.javascript, "8");
+    }
+
+    #[test]
+    fn picks_installed_model_by_preference() {
+        let installed = vec!["llama3:latest".to_string(), "codellama:13b".to_string()];
+        let preferences = vec!["qwen2.5-coder".to_string(), "codellama".to_string()];
+
+        assert_eq!(
+            pick_ollama_model(&installed, "llama3", &preferences).as_deref(),
+            Some("llama3")
+        );
+        assert_eq!(
+            pick_ollama_model(&installed, "qwen2.5-coder:7b", &preferences).as_deref(),
+            Some("codellama:13b")
+        );
+        assert_eq!(pick_ollama_model(&installed, "qwen2.5-coder:7b", &[]), None);
+        assert_eq!(
+            pick_ollama_model(&[], "qwen2.5-coder:7b", &preferences),
+            None
+        );
+    }
+
+    #[test]
+    fn auto_uses_discovered_ollama_model_when_configured_one_is_missing() {
+        let router = ProviderRouter {
+            ollama_model: "qwen2.5-coder:7b".to_string(),
+            ollama_model_preferences: vec!["deepseek-coder".to_string()],
+            ..test_router(
+                StubClient {
+                    fail: false,
+                    output: "1".to_string(),
+                },
+                StubClient {
+                    fail: false,
+                    output: "2".to_string(),
+                },
+                ListingProbe(vec!["deepseek-coder:6.7b"]),
+            )
+        };
+
+        let response = router
+            .translate(ProviderSelection::Auto, &req(), None)
+            .expect("discovered model should answer");
+        assert_eq!(response.provider, Provider::Ollama);
+        assert_eq!(response.model, "deepseek-coder:6.7b");
+    }
+
+    #[test]
+    fn fixture_provider_replays_and_records_translations() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let mut router = ProviderRouter {
+            fixtures: Some(FixtureStore {
+                dir: dir.path().to_path_buf(),
+                record: false,
+            }),
+            ..test_router(
+                StubClient {
+                    fail: true,
+                    output: String::new(),
+                },
+                StubClient {
+                    fail: false,
+                    output: "recorded()".to_string(),
+                },
+                Probe(false),
+            )
+        };
+
+        let err = router
+            .translate(ProviderSelection::Fixture, &req(), None)
+            .expect_err("missing fixture should fail");
+        assert!(err.to_string().contains("KLUMO_RECORD_FIXTURES=1"));
+        assert_eq!(
+            router.resolved_chain(ProviderSelection::Fixture, Some("big")),
+            vec![ProviderDescriptor {
+                provider: Provider::Fixture,
+                model: FIXTURE_MODEL.to_string(),
+            }],
+            "fixtures ignore model overrides"
+        );
+
+        router.fixtures.as_mut().expect("store").record = true;
+        let recorded = router
+            .translate(ProviderSelection::Fixture, &req(), None)
+            .expect("recording should call the auto chain");
+        assert_eq!(recorded.provider, Provider::OpenAiCompatible);
+
+        router.openai.fail = true;
+        let replayed = router
+            .translate(ProviderSelection::Fixture, &req(), None)
+            .expect("fixture should replay without network");
+        assert_eq!(replayed.provider, Provider::Fixture);
+        assert_eq!(replayed.javascript, "recorded()");
+    }
+
+    #[test]
+    fn router_records_provider_metrics_per_attempt() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let store = ProviderMetricsStore {
+            path: dir.path().join("metrics").join("providers.json"),
+        };
+        let mut router = ProviderRouter {
+            metrics: Some(store.clone()),
+            ..test_router(
+                StubClient {
+                    fail: false,
+                    output: "```js\n```".to_string(),
+                },
+                StubClient {
+                    fail: false,
+                    output: "ok()".to_string(),
+                },
+                Probe(true),
+            )
+        };
+
+        router
+            .translate(ProviderSelection::Auto, &req(), None)
+            .expect("openai should answer after ollama's empty output");
+        router.openai.fail = true;
+        router
+            .translate(ProviderSelection::OpenAiCompatible, &req(), None)
+            .expect_err("openai failure should surface");
+
+        let stats = store.load().expect("metrics should load");
+        let ollama = &stats["ollama"];
+        assert_eq!(ollama.calls, 1);
+        assert_eq!(ollama.normalization_failure_rate(), 1.0);
+        assert_eq!(ollama.mean_latency_ms(), None);
+        let openai = &stats["openai-compatible"];
+        assert_eq!(openai.calls, 2);
+        assert_eq!(openai.successes, 1);
+        assert_eq!(openai.success_rate(), 0.5);
+        assert!(openai.mean_latency_ms().is_some());
+
+        store.reset().expect("reset should succeed");
+        assert!(store.load().expect("metrics should load").is_empty());
+    }
+
+    #[test]
+    fn sampling_fingerprint_names_every_knob() {
+        let sampling = super::SamplingOptions {
+            temperature: Some(0.2),
+            max_tokens: Some(512),
+            ..super::SamplingOptions::default()
+        };
+        assert_eq!(
+            sampling.fingerprint(),
+            "temperature=0.2;top_p=;max_tokens=512;seed="
+        );
+    }
+
+    #[test]
+    fn corrupt_metrics_are_moved_aside_before_recording() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let store = ProviderMetricsStore {
+            path: dir.path().join("providers.json"),
+        };
+        std::fs::write(&store.path, "{ not json").expect("write should work");
+
+        store
+            .record(
+                &Provider::Ollama,
+                super::CallOutcome::Success,
+                Duration::from_millis(5),
+            )
+            .expect("record should start over");
+        assert_eq!(
+            store.load().expect("metrics should load")["ollama"].calls,
+            1
+        );
+        let backup = std::fs::read_to_string(dir.path().join("providers.json.corrupt"))
+            .expect("the corrupt file should be kept");
+        assert_eq!(backup, "{ not json");
+    }
+
+    #[test]
+    fn router_logs_usage_for_calls_and_response_cache_hits() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let usage = UsageLog {
+            path: dir.path().join("metrics.jsonl"),
+        };
+        let router = ProviderRouter {
+            response_cache: Some(ResponseCache::new(dir.path().join("responses"))),
+            usage: Some(usage.clone()),
+            ..test_router(
+                StubClient {
+                    fail: true,
+                    output: String::new(),
+                },
+                StubClient {
+                    fail: false,
+                    output: "ok()".to_string(),
+                },
+                Probe(true),
+            )
+        };
+
+        for _ in 0..2 {
+            router
+                .translate(ProviderSelection::OpenAiCompatible, &req(), None)
+                .expect("openai should answer");
+        }
+        let events = usage.load().expect("usage should load");
+        let outcomes: Vec<_> = events.iter().map(|event| event.outcome).collect();
+        assert_eq!(
+            outcomes,
+            vec![UsageOutcome::Success, UsageOutcome::CacheHit]
+        );
+        assert!(events[0].latency_ms.is_some());
+        assert_eq!(events[1].model, "openai-model");
+        assert!(
+            events
+                .iter()
+                .all(|event| event.provider == "openai-compatible")
+        );
     }
 }
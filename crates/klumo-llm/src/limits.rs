@@ -0,0 +1,190 @@
+use crate::{LlmTranslateRequest, ProviderCallError, ProviderErrorKind};
+use anyhow::Result;
+
+/// User turn sent after a reply that stopped at the token limit.
+pub const CONTINUATION_PROMPT: &str = "Your previous answer was cut off. Continue exactly where it stopped. Do not repeat anything you already wrote and do not add commentary.";
+
+/// Size caps provider clients enforce around a translation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeLimits {
+    /// Rendered prompt size above which a request is refused before it is sent.
+    pub max_prompt_bytes: usize,
+    /// Combined size of a reply and its continuations above which the translation fails.
+    pub max_response_bytes: usize,
+    /// Continuation requests sent after replies that stopped at the token limit.
+    pub max_continuations: u32,
+}
+
+impl Default for SizeLimits {
+    fn default() -> Self {
+        Self {
+            max_prompt_bytes: 256 * 1024,
+            max_response_bytes: 1024 * 1024,
+            max_continuations: 3,
+        }
+    }
+}
+
+/// One provider reply and whether generation stopped at the token limit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub text: String,
+    pub truncated: bool,
+}
+
+impl SizeLimits {
+    /// Refuses prompts over `max_prompt_bytes`; resending an oversized prompt cannot succeed.
+    pub fn check_prompt(&self, req: &LlmTranslateRequest) -> Result<(), ProviderCallError> {
+        let size: usize = req
+            .prompts
+            .parts(req)
+            .iter()
+            .map(|(_, content)| content.len())
+            .sum();
+        if size <= self.max_prompt_bytes {
+            return Ok(());
+        }
+        Err(ProviderCallError::new(
+            ProviderErrorKind::Config,
+            format!(
+                "prompt for {} is {size} bytes, over the {} byte limit; split the source into smaller modules and build them separately, or raise max_prompt_bytes",
+                req.source_id, self.max_prompt_bytes
+            ),
+        ))
+    }
+
+    /// Calls `request` until a reply finishes on its own, passing the partial replies received
+    /// so far so the client can replay them before asking to continue. Returns the joined text.
+    pub fn complete(
+        &self,
+        mut request: impl FnMut(&[String]) -> Result<Completion>,
+    ) -> Result<String> {
+        let mut partials: Vec<String> = Vec::new();
+        loop {
+            let completion = request(&partials)?;
+            partials.push(completion.text);
+            let size: usize = partials.iter().map(String::len).sum();
+            if size > self.max_response_bytes {
+                return Err(ProviderCallError::new(
+                    ProviderErrorKind::Model,
+                    format!(
+                        "response is {size} bytes, over the {} byte limit (max_response_bytes)",
+                        self.max_response_bytes
+                    ),
+                )
+                .into());
+            }
+            if !completion.truncated {
+                return Ok(partials.concat());
+            }
+            if partials.len() > self.max_continuations as usize {
+                return Err(ProviderCallError::new(
+                    ProviderErrorKind::Model,
+                    format!(
+                        "response was still cut off at the token limit after {} continuation requests; raise max_tokens or split the source",
+                        self.max_continuations
+                    ),
+                )
+                .into());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Completion, SizeLimits};
+    use crate::{LlmTranslateRequest, PromptTemplates, ProviderCallError, ProviderErrorKind};
+
+    fn request(source_text: &str) -> LlmTranslateRequest {
+        LlmTranslateRequest {
+            source_text: source_text.to_string(),
+            source_id: "big.pseudo".to_string(),
+            language_hint: None,
+            scope_context: None,
+            repair_hint: None,
+            temperature: None,
+            top_p: None,
+            max_tokens: None,
+            seed: None,
+            structured_output: false,
+            explain: false,
+            explain_javascript: None,
+            prompts: PromptTemplates::default(),
+        }
+    }
+
+    #[test]
+    fn oversized_prompts_fail_with_guidance() {
+        let limits = SizeLimits {
+            max_prompt_bytes: 4096,
+            ..SizeLimits::default()
+        };
+        assert!(limits.check_prompt(&request("print 1")).is_ok());
+
+        let err = limits
+            .check_prompt(&request(&"x".repeat(5000)))
+            .expect_err("prompt should be refused");
+        assert_eq!(err.kind, ProviderErrorKind::Config);
+        assert!(err.message.contains("big.pseudo"));
+        assert!(err.message.contains("max_prompt_bytes"));
+    }
+
+    #[test]
+    fn truncated_replies_are_continued_and_joined() {
+        let limits = SizeLimits::default();
+        let mut seen = Vec::new();
+        let out = limits
+            .complete(|partials| {
+                seen.push(partials.to_vec());
+                Ok(Completion {
+                    text: if partials.is_empty() {
+                        "console.log(".to_string()
+                    } else {
+                        "1);".to_string()
+                    },
+                    truncated: partials.is_empty(),
+                })
+            })
+            .expect("continuation should finish");
+        assert_eq!(out, "console.log(1);");
+        assert_eq!(seen, vec![vec![], vec!["console.log(".to_string()]]);
+    }
+
+    #[test]
+    fn endless_or_oversized_replies_fail() {
+        let limits = SizeLimits {
+            max_continuations: 2,
+            ..SizeLimits::default()
+        };
+        let mut calls = 0;
+        let err = limits
+            .complete(|_| {
+                calls += 1;
+                Ok(Completion {
+                    text: "x".to_string(),
+                    truncated: true,
+                })
+            })
+            .expect_err("should give up");
+        assert_eq!(calls, 3);
+        assert!(err.to_string().contains("after 2 continuation requests"));
+
+        let limits = SizeLimits {
+            max_response_bytes: 4,
+            ..SizeLimits::default()
+        };
+        let err = limits
+            .complete(|_| {
+                Ok(Completion {
+                    text: "console.log(1);".to_string(),
+                    truncated: false,
+                })
+            })
+            .expect_err("should be too large");
+        let call = err
+            .downcast_ref::<ProviderCallError>()
+            .expect("typed error");
+        assert_eq!(call.kind, ProviderErrorKind::Model);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::Provider;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How a single provider call ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallOutcome {
+    Success,
+    /// The provider answered but its output could not be normalized into JavaScript.
+    NormalizationFailure,
+    /// The request itself failed (network, auth, rate limit, ...).
+    ProviderFailure,
+}
+
+/// Running totals for one provider.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProviderStats {
+    pub calls: u64,
+    pub successes: u64,
+    pub normalization_failures: u64,
+    /// Summed over successful calls only, so failures that time out early do not skew it.
+    pub success_latency_ms: u64,
+}
+
+impl ProviderStats {
+    pub fn record(&mut self, outcome: CallOutcome, latency: Duration) {
+        self.calls += 1;
+        match outcome {
+            CallOutcome::Success => {
+                self.successes += 1;
+                self.success_latency_ms += latency.as_millis() as u64;
+            }
+            CallOutcome::NormalizationFailure => self.normalization_failures += 1,
+            CallOutcome::ProviderFailure => {}
+        }
+    }
+
+    pub fn success_rate(&self) -> f64 {
+        ratio(self.successes, self.calls)
+    }
+
+    pub fn normalization_failure_rate(&self) -> f64 {
+        ratio(self.normalization_failures, self.calls)
+    }
+
+    pub fn mean_latency_ms(&self) -> Option<u64> {
+        (self.successes > 0).then(|| self.success_latency_ms / self.successes)
+    }
+}
+
+fn ratio(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 / whole as f64
+    }
+}
+
+static RECORD_LOCK: Mutex<()> = Mutex::new(());
+
+/// Per-provider call statistics persisted as a single JSON file, keyed by provider name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProviderMetricsStore {
+    pub path: PathBuf,
+}
+
+impl ProviderMetricsStore {
+    pub fn default_path() -> Result<PathBuf> {
+        let home = dirs::home_dir().context("failed to resolve home directory")?;
+        Ok(home.join(".klumo").join("metrics").join("providers.json"))
+    }
+
+    /// Reads the recorded totals. A missing file yields an empty scoreboard.
+    pub fn load(&self) -> Result<BTreeMap<String, ProviderStats>> {
+        let raw = match fs::read_to_string(&self.path) {
+            Ok(raw) => raw,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed reading {}", self.path.display()));
+            }
+        };
+        serde_json::from_str(&raw)
+            .with_context(|| format!("failed parsing {}", self.path.display()))
+    }
+
+    pub fn record(
+        &self,
+        provider: &Provider,
+        outcome: CallOutcome,
+        latency: Duration,
+    ) -> Result<()> {
+        // Batched translations record from several threads; keep each read-modify-write whole.
+        let _guard = RECORD_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut stats = match self.load() {
+            Ok(stats) => stats,
+            Err(err) if self.path.exists() => {
+                // Keep the unreadable totals for inspection rather than writing over them.
+                let backup = self.path.with_extension("json.corrupt");
+                fs::rename(&self.path, &backup)
+                    .with_context(|| format!("failed moving aside {}", self.path.display()))?;
+                eprintln!(
+                    "[klumo] warning: {err:#}; moved it to {} and started new provider metrics",
+                    backup.display()
+                );
+                BTreeMap::new()
+            }
+            Err(err) => return Err(err),
+        };
+        stats
+            .entry(provider.to_string())
+            .or_default()
+            .record(outcome, latency);
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed creating {}", parent.display()))?;
+        }
+        fs::write(&self.path, serde_json::to_string_pretty(&stats)?)
+            .with_context(|| format!("failed writing {}", self.path.display()))
+    }
+
+    pub fn reset(&self) -> Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                Err(err).with_context(|| format!("failed removing {}", self.path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,237 @@
+/// Invisible characters models sometimes emit around or inside code: byte order marks and
+/// zero-width spaces/joiners. Left in place outside a literal they turn into syntax errors.
+const INVISIBLE_CHARS: [char; 5] = ['\u{feff}', '\u{200b}', '\u{200c}', '\u{200d}', '\u{2060}'];
+
+/// Fence tags whose blocks are shell commands or sample output rather than the program.
+const NON_CODE_FENCES: [&str; 10] = [
+    "bash",
+    "sh",
+    "shell",
+    "zsh",
+    "console",
+    "text",
+    "txt",
+    "plaintext",
+    "output",
+    "diff",
+];
+
+/// Lowercased line openings that mark an introduction rather than code.
+const PROSE_OPENINGS: [&str; 12] = [
+    "here is",
+    "here's",
+    "here\u{2019}s",
+    "sure",
+    "certainly",
+    "below is",
+    "the following",
+    "this is the",
+    "translated",
+    "javascript:",
+    "output:",
+    "result:",
+];
+
+/// Whitespace or one of [`INVISIBLE_CHARS`], for trimming a whole answer.
+pub(crate) fn is_blank(ch: char) -> bool {
+    ch.is_whitespace() || INVISIBLE_CHARS.contains(&ch)
+}
+
+/// `code` without [`INVISIBLE_CHARS`], except inside string and template literals, where a
+/// zero-width joiner can be part of the text (an emoji sequence) and must survive. Literals
+/// are tracked through escapes, `${...}` substitutions and comments; a quoted string ends at
+/// the end of its line at the latest, so a stray quote cannot protect the rest of the code.
+pub(crate) fn strip_invisible(code: &str) -> String {
+    let mut out = String::with_capacity(code.len());
+    // What is open, innermost last: a quote, `{` for a template substitution, `/` for a line
+    // comment or `*` for a block comment.
+    let mut open: Vec<char> = Vec::new();
+    let mut chars = code.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match open.last().copied() {
+            Some(quote @ ('"' | '\'' | '`')) => {
+                out.push(ch);
+                match ch {
+                    '\\' => out.extend(chars.next()),
+                    '$' if quote == '`' && chars.peek() == Some(&'{') => {
+                        out.extend(chars.next());
+                        open.push('{');
+                    }
+                    '\n' if quote != '`' => {
+                        open.pop();
+                    }
+                    _ if ch == quote => {
+                        open.pop();
+                    }
+                    _ => {}
+                }
+            }
+            Some(comment @ ('/' | '*')) => {
+                if INVISIBLE_CHARS.contains(&ch) {
+                    continue;
+                }
+                out.push(ch);
+                let closes = match comment {
+                    '/' => ch == '\n',
+                    _ => ch == '*' && chars.peek() == Some(&'/'),
+                };
+                if closes {
+                    out.extend(chars.next_if_eq(&'/'));
+                    open.pop();
+                }
+            }
+            _ => {
+                if INVISIBLE_CHARS.contains(&ch) {
+                    continue;
+                }
+                out.push(ch);
+                match ch {
+                    '"' | '\'' | '`' => open.push(ch),
+                    '/' if matches!(chars.peek(), Some('/' | '*')) => {
+                        let kind = chars.next().expect("peeked");
+                        out.push(kind);
+                        open.push(kind);
+                    }
+                    '{' if !open.is_empty() => open.push('{'),
+                    '}' if !open.is_empty() => {
+                        open.pop();
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    out
+}
+
+struct FencedBlock<'a> {
+    tag: String,
+    body: &'a str,
+}
+
+/// Code from the fenced blocks in `text`, ignoring any prose around them. Shell and output
+/// blocks are skipped when another block remains. Several code blocks are concatenated in
+/// order, except that blocks repeated inside a later one are dropped and, when the blocks
+/// declare the same top-level names (a draft followed by a revision), only the largest is
+/// kept. `None` when `text` has no fence.
+pub(crate) fn select_fenced_code(text: &str) -> Option<String> {
+    let blocks = fenced_blocks(text);
+    let first = blocks.first()?.body;
+    let code: Vec<&str> = blocks
+        .iter()
+        .filter(|block| !NON_CODE_FENCES.contains(&block.tag.as_str()))
+        .map(|block| block.body.trim())
+        .filter(|body| !body.is_empty())
+        .collect();
+    if code.is_empty() {
+        return Some(first.to_string());
+    }
+
+    let mut kept: Vec<&str> = Vec::new();
+    for body in code {
+        if kept.iter().any(|existing| existing.contains(body)) {
+            continue;
+        }
+        kept.retain(|existing| !body.contains(existing));
+        kept.push(body);
+    }
+    if redeclares_names(&kept) {
+        return kept
+            .iter()
+            .max_by_key(|body| body.len())
+            .map(|body| body.to_string());
+    }
+    Some(kept.join("\n\n"))
+}
+
+/// Every ```` ``` ```` block in `text`. An unterminated last fence runs to the end of the text,
+/// which is what a truncated answer looks like.
+fn fenced_blocks(text: &str) -> Vec<FencedBlock<'_>> {
+    let mut blocks = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("```") {
+        let after = &rest[start + 3..];
+        let Some(newline) = after.find('\n') else {
+            break;
+        };
+        let tag = after[..newline].trim().to_ascii_lowercase();
+        let body = &after[newline + 1..];
+        match body.find("```") {
+            Some(end) => {
+                blocks.push(FencedBlock {
+                    tag,
+                    body: &body[..end],
+                });
+                rest = &body[end + 3..];
+            }
+            None => {
+                blocks.push(FencedBlock { tag, body });
+                break;
+            }
+        }
+    }
+    blocks
+}
+
+fn redeclares_names(blocks: &[&str]) -> bool {
+    let mut seen: Vec<&str> = Vec::new();
+    for block in blocks {
+        let names = top_level_names(block);
+        if names.iter().any(|name| seen.contains(name)) {
+            return true;
+        }
+        seen.extend(names);
+    }
+    false
+}
+
+/// Names declared by unindented `function`, `class`, `const`, `let` and `var` lines.
+fn top_level_names(code: &str) -> Vec<&str> {
+    code.lines()
+        .filter(|line| !line.starts_with([' ', '\t']))
+        .filter_map(|line| {
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let line = line.strip_prefix("async ").unwrap_or(line);
+            let rest = [
+                "function* ",
+                "function ",
+                "class ",
+                "const ",
+                "let ",
+                "var ",
+            ]
+            .iter()
+            .find_map(|keyword| line.strip_prefix(keyword))?;
+            let end = rest
+                .find(|ch: char| !(ch.is_alphanumeric() || ch == '_' || ch == '$'))
+                .unwrap_or(rest.len());
+            (end > 0).then(|| &rest[..end])
+        })
+        .collect()
+}
+
+/// Drops introduction lines such as "Here is the JavaScript:" from unfenced output, along with
+/// stray fence markers left by a half-fenced answer.
+pub(crate) fn strip_prose(text: &str) -> String {
+    let mut lines: Vec<&str> = text
+        .lines()
+        .filter(|line| !line.trim().starts_with("```"))
+        .collect();
+    let code_start = lines
+        .iter()
+        .position(|line| !line.trim().is_empty() && !is_prose_line(line))
+        .unwrap_or(0);
+    lines.drain(..code_start);
+    lines.join("\n").trim().to_string()
+}
+
+fn is_prose_line(line: &str) -> bool {
+    let line = line.trim_matches(is_blank);
+    let lower = line.to_lowercase();
+    let opens_prose = PROSE_OPENINGS.iter().any(|opening| {
+        lower.strip_prefix(opening).is_some_and(|rest| {
+            !rest.starts_with(|ch: char| ch.is_alphanumeric() || ch == '_' || ch == '$')
+        })
+    });
+    opens_prose && !line.ends_with([';', '{', '}'])
+}
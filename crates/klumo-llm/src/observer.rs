@@ -0,0 +1,90 @@
+use crate::{LlmTranslateRequest, Provider};
+use anyhow::{Context, Result};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// One provider call as seen by an [`LlmObserver`]: the rendered prompt and the raw response
+/// before normalization.
+#[derive(Debug)]
+pub struct LlmExchange<'a> {
+    pub provider: &'a Provider,
+    pub model: &'a str,
+    pub request: &'a LlmTranslateRequest,
+    /// Rendered prompt parts, in the order clients send them.
+    pub prompt: Vec<(&'static str, String)>,
+    /// Raw provider output, or the client error.
+    pub response: std::result::Result<&'a str, String>,
+    pub latency: Duration,
+}
+
+/// Tap on LLM traffic, called by `ProviderRouter` after every provider call.
+pub trait LlmObserver: Send + Sync {
+    fn on_exchange(&self, exchange: &LlmExchange<'_>);
+}
+
+/// Writes each exchange to `<dir>/<unix millis>-<seq>-<provider>.txt` (`--llm-log <dir>`).
+#[derive(Debug)]
+pub struct LlmLogDir {
+    pub dir: PathBuf,
+    seq: AtomicU64,
+}
+
+impl LlmLogDir {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            seq: AtomicU64::new(0),
+        }
+    }
+
+    pub fn write(&self, exchange: &LlmExchange<'_>) -> Result<PathBuf> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("failed creating LLM log dir {}", self.dir.display()))?;
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis())
+            .unwrap_or_default();
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        let provider: String = exchange
+            .provider
+            .to_string()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+        let path = self.dir.join(format!("{millis}-{seq:04}-{provider}.txt"));
+        fs::write(&path, render_exchange(exchange))
+            .with_context(|| format!("failed writing LLM log {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+impl LlmObserver for LlmLogDir {
+    fn on_exchange(&self, exchange: &LlmExchange<'_>) {
+        if let Err(err) = self.write(exchange) {
+            eprintln!("[klumo] warning: {err:#}");
+        }
+    }
+}
+
+fn render_exchange(exchange: &LlmExchange<'_>) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "provider: {}", exchange.provider);
+    let _ = writeln!(out, "model: {}", exchange.model);
+    let _ = writeln!(out, "source: {}", exchange.request.source_id);
+    let _ = writeln!(out, "latency_ms: {}", exchange.latency.as_millis());
+    for (name, content) in &exchange.prompt {
+        let _ = writeln!(out, "\n--- prompt: {name} ---\n{content}");
+    }
+    match &exchange.response {
+        Ok(raw) => {
+            let _ = writeln!(out, "\n--- response ---\n{raw}");
+        }
+        Err(error) => {
+            let _ = writeln!(out, "\n--- error ---\n{error}");
+        }
+    }
+    out
+}
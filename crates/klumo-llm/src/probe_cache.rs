@@ -0,0 +1,72 @@
+use crate::ReachabilityProbe;
+use anyhow::Result;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Default lifetime of a cached probe answer.
+pub const DEFAULT_PROBE_TTL: Duration = Duration::from_secs(10);
+
+/// Wraps a [`ReachabilityProbe`] so repeated compiles within `ttl` reuse the last reachability
+/// answer instead of making another network round-trip. The installed model list is kept for
+/// the rest of the process once listed, until a pull changes it.
+pub struct CachedProbe<P> {
+    pub inner: P,
+    pub ttl: Duration,
+    reachable: Mutex<Option<(Instant, bool)>>,
+    models: Mutex<Option<Vec<String>>>,
+}
+
+impl<P> CachedProbe<P> {
+    pub fn new(inner: P, ttl: Duration) -> Self {
+        Self {
+            inner,
+            ttl,
+            reachable: Mutex::new(None),
+            models: Mutex::new(None),
+        }
+    }
+}
+
+fn cached<T: Clone>(
+    slot: &Mutex<Option<(Instant, T)>>,
+    ttl: Duration,
+    probe: impl FnOnce() -> T,
+) -> T {
+    let mut slot = slot.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some((at, value)) = slot.as_ref()
+        && at.elapsed() < ttl
+    {
+        return value.clone();
+    }
+    let value = probe();
+    *slot = Some((Instant::now(), value.clone()));
+    value
+}
+
+impl<P: ReachabilityProbe> ReachabilityProbe for CachedProbe<P> {
+    fn ollama_reachable(&self) -> bool {
+        cached(&self.reachable, self.ttl, || self.inner.ollama_reachable())
+    }
+
+    /// A failed listing is not kept, so the next resolution asks again.
+    fn installed_ollama_models(&self) -> Option<Vec<String>> {
+        let mut models = self
+            .models
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if models.is_none() {
+            *models = self.inner.installed_ollama_models();
+        }
+        models.clone()
+    }
+
+    /// A pull changes what is installed, so the model list is probed again afterwards.
+    fn pull_ollama_model(&self, model: &str) -> Result<()> {
+        let result = self.inner.pull_ollama_model(model);
+        *self
+            .models
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+        result
+    }
+}
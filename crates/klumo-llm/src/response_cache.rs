@@ -0,0 +1,437 @@
+use crate::{Explanation, LlmTranslateRequest, LlmTranslateResponse, Provider, SamplingOptions};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedResponse {
+    javascript: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    served_model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    notes: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    confidence: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    explanation: Option<Explanation>,
+}
+
+/// Provider answers keyed on the normalized prompt, one `<key>.json` per entry. Unlike the
+/// compile cache it ignores whitespace, full-line comments and the source's file name, so a
+/// reformatted or copied snippet reuses the earlier answer instead of calling the provider.
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    root: PathBuf,
+}
+
+impl ResponseCache {
+    pub fn default_root() -> Result<PathBuf> {
+        let home = dirs::home_dir().context("failed to resolve home directory")?;
+        Ok(home.join(".klumo").join("responses"))
+    }
+
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Hash of the prompt `req` renders once its source is normalized, with the provider, model
+    /// and sampling settings that shape the answer.
+    pub fn key(&self, provider: &Provider, model: &str, req: &LlmTranslateRequest) -> String {
+        let normalized = LlmTranslateRequest {
+            source_text: normalize_source(&req.source_text, &language_of(req)),
+            source_id: source_id_shape(&req.source_id),
+            ..req.clone()
+        };
+        let mut hasher = Sha256::new();
+        for field in [provider.to_string().as_str(), model] {
+            hasher.update(field.as_bytes());
+            hasher.update(b"\0");
+        }
+        for (name, body) in req.prompts.parts(&normalized) {
+            hasher.update(name.as_bytes());
+            hasher.update(b"\0");
+            hasher.update(body.as_bytes());
+            hasher.update(b"\0");
+        }
+        let sampling = SamplingOptions {
+            temperature: req.temperature,
+            top_p: req.top_p,
+            max_tokens: req.max_tokens,
+            seed: req.seed,
+        };
+        hasher.update(sampling.fingerprint().as_bytes());
+        hasher.update(format!(";structured_output={}", req.structured_output).as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.root.join(format!("{key}.json"))
+    }
+
+    /// The answer stored under `key`, attributed to `provider` and `model`. Unreadable entries
+    /// count as misses.
+    pub fn get(&self, key: &str, provider: &Provider, model: &str) -> Option<LlmTranslateResponse> {
+        let raw = fs::read_to_string(self.entry_path(key)).ok()?;
+        let cached: CachedResponse = serde_json::from_str(&raw).ok()?;
+        Some(LlmTranslateResponse {
+            javascript: cached.javascript,
+            provider: provider.clone(),
+            model: model.to_string(),
+            served_model: cached.served_model,
+            notes: cached.notes,
+            confidence: cached.confidence,
+            explanation: cached.explanation,
+        })
+    }
+
+    pub fn put(&self, key: &str, response: &LlmTranslateResponse) -> Result<()> {
+        fs::create_dir_all(&self.root)
+            .with_context(|| format!("failed creating {}", self.root.display()))?;
+        let cached = CachedResponse {
+            javascript: response.javascript.clone(),
+            served_model: response.served_model.clone(),
+            notes: response.notes.clone(),
+            confidence: response.confidence,
+            explanation: response.explanation.clone(),
+        };
+        let raw =
+            serde_json::to_string_pretty(&cached).context("failed serializing cached response")?;
+        let path = self.entry_path(key);
+        fs::write(&path, raw).with_context(|| format!("failed writing {}", path.display()))
+    }
+}
+
+impl Default for ResponseCache {
+    fn default() -> Self {
+        let root = Self::default_root().unwrap_or_else(|_| PathBuf::from(".klumo-responses"));
+        Self { root }
+    }
+}
+
+/// Only the extension of a file name reaches the key; ids without one, such as `<repl>`, are
+/// kept whole because the prompt treats them specially.
+fn source_id_shape(source_id: &str) -> String {
+    match source_id.rsplit_once('.') {
+        Some((_, ext)) if !ext.contains(['/', '\\']) => format!("*.{ext}"),
+        _ => source_id.to_string(),
+    }
+}
+
+/// Drops blank lines, comments and trailing whitespace, and collapses runs of spaces, all
+/// outside string and template literals, which are kept byte for byte even across lines.
+/// Leading indentation is kept since it is significant in languages such as Python. Only the
+/// comments of `language` (a language hint or file extension) are dropped: `//` and `/* */`
+/// in C-like languages, `#` in scripting languages, `--` in Lua, SQL and Haskell, and none
+/// when the language is unknown. A comment starts a line or follows whitespace, and `#` or
+/// `--` need whitespace after them, so `#include`, `--flag` and `${#x}` stay code.
+pub fn normalize_source(source: &str, language: &str) -> String {
+    let syntax = CommentSyntax::of(language);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    // Whether the current line began, or has so far stayed, inside a literal; such lines are
+    // string content and never dropped or trimmed.
+    let mut literal_line = false;
+    let mut quote: Option<&'static str> = None;
+    let mut block_comment = false;
+    let mut line_comment = false;
+    let mut at = 0;
+    while at < source.len() {
+        let rest = &source[at..];
+        let c = rest.chars().next().expect("not at the end");
+        if c == '\n' || rest.starts_with("\r\n") {
+            let literal = literal_line || quote.is_some();
+            let code = if literal {
+                line.as_str()
+            } else {
+                line.trim_end()
+            };
+            if literal || !code.trim().is_empty() {
+                lines.push(code.to_string());
+            }
+            line.clear();
+            literal_line = quote.is_some();
+            line_comment = false;
+            at += if c == '\n' { 1 } else { 2 };
+            continue;
+        }
+        if line_comment {
+            at += c.len_utf8();
+            continue;
+        }
+        if block_comment {
+            if rest.starts_with("*/") {
+                block_comment = false;
+                at += 2;
+            } else {
+                at += c.len_utf8();
+            }
+            continue;
+        }
+        if let Some(close) = quote {
+            if c == '\\' {
+                let escaped = rest[1..].chars().next().map_or(0, char::len_utf8);
+                line.push_str(&rest[..1 + escaped]);
+                at += 1 + escaped;
+                continue;
+            }
+            if rest.starts_with(close) {
+                line.push_str(close);
+                quote = None;
+                at += close.len();
+                continue;
+            }
+            line.push(c);
+            at += c.len_utf8();
+            continue;
+        }
+
+        let boundary = line.chars().last().is_none_or(char::is_whitespace);
+        if boundary && syntax.comment_at(rest, lines.is_empty() && line.is_empty()) {
+            if rest.starts_with("/*") {
+                block_comment = true;
+                at += 2;
+            } else {
+                line_comment = true;
+            }
+            continue;
+        }
+        if let Some(open) = ["\"\"\"", "'''", "\"", "'", "`"]
+            .into_iter()
+            .find(|open| rest.starts_with(open))
+        {
+            line.push_str(open);
+            quote = Some(open);
+            at += open.len();
+            continue;
+        }
+        if c == ' ' || c == '\t' {
+            let indentation = line.chars().all(char::is_whitespace);
+            if indentation {
+                line.push(c);
+            } else if !line.ends_with(' ') {
+                line.push(' ');
+            }
+        } else {
+            line.push(c);
+        }
+        at += c.len_utf8();
+    }
+    let literal = literal_line || quote.is_some();
+    let code = if literal {
+        line.as_str()
+    } else {
+        line.trim_end()
+    };
+    if literal || !code.trim().is_empty() {
+        lines.push(code.to_string());
+    }
+    lines.join("\n")
+}
+
+/// The language [`normalize_source`] assumes for `req`: its hint, else its file extension.
+fn language_of(req: &LlmTranslateRequest) -> String {
+    match &req.language_hint {
+        Some(hint) => hint.clone(),
+        None => req
+            .source_id
+            .rsplit_once('.')
+            .map_or_else(String::new, |(_, ext)| ext.to_string()),
+    }
+}
+
+/// The comment markers of one language.
+#[derive(Debug, Clone, Copy, Default)]
+struct CommentSyntax {
+    /// `//` and `/* */`.
+    slash: bool,
+    /// `#`, and a `#!` line at the very start.
+    hash: bool,
+    /// `--`.
+    dash: bool,
+}
+
+impl CommentSyntax {
+    fn of(language: &str) -> Self {
+        let language = language
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let slash = matches!(
+            language.as_str(),
+            "javascript"
+                | "js"
+                | "mjs"
+                | "cjs"
+                | "jsx"
+                | "typescript"
+                | "ts"
+                | "mts"
+                | "cts"
+                | "tsx"
+                | "java"
+                | "c"
+                | "h"
+                | "cpp"
+                | "c++"
+                | "cc"
+                | "cxx"
+                | "hpp"
+                | "csharp"
+                | "c#"
+                | "cs"
+                | "go"
+                | "golang"
+                | "rust"
+                | "rs"
+                | "swift"
+                | "kotlin"
+                | "kt"
+                | "scala"
+                | "dart"
+                | "php"
+                | "groovy"
+                | "zig"
+                | "solidity"
+                | "sol"
+        );
+        let hash = matches!(
+            language.as_str(),
+            "python"
+                | "py"
+                | "ruby"
+                | "rb"
+                | "shell"
+                | "sh"
+                | "bash"
+                | "zsh"
+                | "perl"
+                | "pl"
+                | "r"
+                | "yaml"
+                | "yml"
+                | "toml"
+                | "powershell"
+                | "ps1"
+                | "elixir"
+                | "ex"
+                | "exs"
+                | "julia"
+                | "jl"
+                | "nim"
+                | "crystal"
+                | "cr"
+                | "coffeescript"
+                | "coffee"
+                | "tcl"
+                | "php"
+        );
+        let dash = matches!(
+            language.as_str(),
+            "lua" | "sql" | "haskell" | "hs" | "ada" | "adb" | "elm" | "vhdl" | "applescript"
+        );
+        Self { slash, hash, dash }
+    }
+
+    /// Whether a comment starts at `rest`; `file_start` allows a `#!` line.
+    fn comment_at(&self, rest: &str, file_start: bool) -> bool {
+        let spaced = |marker: &str| {
+            rest.strip_prefix(marker)
+                .is_some_and(|after| after.is_empty() || after.starts_with(char::is_whitespace))
+        };
+        (self.slash && (rest.starts_with("//") || rest.starts_with("/*")))
+            || (self.hash && (spaced("#") || (file_start && rest.starts_with("#!"))))
+            || (self.dash && spaced("--"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ResponseCache, normalize_source};
+    use crate::{LlmTranslateRequest, PromptTemplates, Provider};
+
+    fn req(source_text: &str, source_id: &str) -> LlmTranslateRequest {
+        LlmTranslateRequest {
+            source_text: source_text.to_string(),
+            source_id: source_id.to_string(),
+            language_hint: Some("python".to_string()),
+            scope_context: None,
+            repair_hint: None,
+            temperature: None,
+            top_p: None,
+            max_tokens: None,
+            seed: None,
+            structured_output: false,
+            explain: false,
+            explain_javascript: None,
+            prompts: PromptTemplates::default(),
+        }
+    }
+
+    #[test]
+    fn normalization_ignores_formatting_and_comments_but_not_strings_or_indentation() {
+        let python = |source: &str| normalize_source(source, "python");
+        let original = "# add numbers\nfor i in range(3):\n    print(i,  'a  b')\n";
+        let reformatted = "#!/usr/bin/env python\n\nfor i   in range(3):   \n    # show it\n    print(i, 'a  b')  # trailing\n\n";
+        assert_eq!(python(original), python(reformatted));
+        assert_eq!(python(original), "for i in range(3):\n    print(i, 'a  b')");
+        assert_ne!(python("print('a  b')"), python("print('a b')"));
+        assert_ne!(
+            python("if x:\n    y()\nz()"),
+            python("if x:\n    y()\n    z()")
+        );
+        assert_eq!(
+            normalize_source("/* header\n still */\nx = 1; // one", "c"),
+            "x = 1;"
+        );
+        assert_eq!(normalize_source("#include <a.h>", "c"), "#include <a.h>");
+        assert_eq!(
+            normalize_source("-- pick all\nSELECT *  FROM t", "sql"),
+            "SELECT * FROM t"
+        );
+        assert_eq!(
+            normalize_source("# keep\n// keep", "pseudocode"),
+            "# keep\n// keep"
+        );
+    }
+
+    #[test]
+    fn normalization_never_drops_code_that_only_looks_like_a_comment() {
+        let js = |source: &str| normalize_source(source, "javascript");
+        assert_ne!(js("let counter = 1;\n-- counter;"), js("let counter = 1;"));
+        assert_ne!(js("x = a --b"), js("x = a"));
+        for marker in ["# one", "// one", "/* one */", "-- one"] {
+            let template = format!("const s = `a\n{marker}\n  b`;");
+            assert_eq!(js(&template), template);
+            assert_ne!(js(&template), js("const s = `a\n  b`;"));
+            let python = format!("s = \"\"\"a\n{marker}\n\n  b\"\"\"");
+            assert_eq!(normalize_source(&python, "python"), python);
+        }
+        assert_eq!(js("s = 'it\\'s // not' // but this"), "s = 'it\\'s // not'");
+        assert_ne!(
+            normalize_source("echo ${#x}", "bash"),
+            normalize_source("echo ${", "bash")
+        );
+    }
+
+    #[test]
+    fn keys_match_across_reformatting_and_file_names_only() {
+        let cache = ResponseCache::new(std::env::temp_dir());
+        let key = |text: &str, id: &str, model: &str| {
+            cache.key(&Provider::OpenAiCompatible, model, &req(text, id))
+        };
+        let base = key("print(1)", "a.py", "gpt");
+        assert_eq!(base, key("# say one\nprint(1)   \n\n", "b.py", "gpt"));
+        assert_eq!(base, key("print(1)", "nested/dir/c.py", "gpt"));
+        assert_ne!(base, key("print(1)", "a.rb", "gpt"));
+        assert_ne!(base, key("print(2)", "a.py", "gpt"));
+        assert_ne!(base, key("print(1)", "a.py", "other"));
+        assert_ne!(base, key("print(1)", "<repl>", "gpt"));
+    }
+}
@@ -0,0 +1,249 @@
+use crate::{CallOutcome, Provider};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// What a usage event records: a provider call's outcome, or a translation served from a cache
+/// without calling the provider.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageOutcome {
+    Success,
+    NormalizationFailure,
+    ProviderFailure,
+    CacheHit,
+}
+
+impl From<CallOutcome> for UsageOutcome {
+    fn from(outcome: CallOutcome) -> Self {
+        match outcome {
+            CallOutcome::Success => Self::Success,
+            CallOutcome::NormalizationFailure => Self::NormalizationFailure,
+            CallOutcome::ProviderFailure => Self::ProviderFailure,
+        }
+    }
+}
+
+/// One line of the usage log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageEvent {
+    /// Milliseconds since the Unix epoch.
+    pub at_ms: u64,
+    pub provider: String,
+    pub model: String,
+    pub outcome: UsageOutcome,
+    /// Wall time of the provider call; absent for cache hits.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+}
+
+impl UsageEvent {
+    pub fn new(
+        provider: &Provider,
+        model: &str,
+        outcome: UsageOutcome,
+        latency: Option<Duration>,
+    ) -> Self {
+        Self {
+            at_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since| since.as_millis() as u64)
+                .unwrap_or_default(),
+            provider: provider.to_string(),
+            model: model.to_string(),
+            outcome,
+            latency_ms: latency.map(|latency| latency.as_millis() as u64),
+        }
+    }
+}
+
+static APPEND_LOCK: Mutex<()> = Mutex::new(());
+
+/// Opt-in, local-only usage telemetry: one JSON event per line, appended as translations run
+/// and aggregated on read by `klumo stats --llm`. Nothing is ever sent anywhere.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsageLog {
+    pub path: PathBuf,
+}
+
+impl UsageLog {
+    pub fn default_path() -> Result<PathBuf> {
+        let home = dirs::home_dir().context("failed to resolve home directory")?;
+        Ok(home.join(".klumo").join("metrics.jsonl"))
+    }
+
+    pub fn record(&self, event: &UsageEvent) -> Result<()> {
+        let mut line = serde_json::to_string(event)?;
+        line.push('\n');
+        // Batched translations record from several threads; keep lines from interleaving.
+        let _guard = APPEND_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed creating {}", parent.display()))?;
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .with_context(|| format!("failed writing {}", self.path.display()))
+    }
+
+    /// Every recorded event. A missing file yields none and unreadable lines, such as one cut
+    /// short by a crash, are skipped.
+    pub fn load(&self) -> Result<Vec<UsageEvent>> {
+        let raw = match fs::read_to_string(&self.path) {
+            Ok(raw) => raw,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed reading {}", self.path.display()));
+            }
+        };
+        Ok(raw
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+
+    pub fn reset(&self) -> Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                Err(err).with_context(|| format!("failed removing {}", self.path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Usage aggregated for one provider.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProviderUsage {
+    pub calls: u64,
+    pub failures: u64,
+    pub cache_hits: u64,
+    /// Latencies of successful calls, sorted ascending.
+    pub latencies_ms: Vec<u64>,
+}
+
+impl ProviderUsage {
+    /// Totals per provider name over `events`.
+    pub fn aggregate(events: &[UsageEvent]) -> BTreeMap<String, ProviderUsage> {
+        let mut usage: BTreeMap<String, ProviderUsage> = BTreeMap::new();
+        for event in events {
+            let entry = usage.entry(event.provider.clone()).or_default();
+            match event.outcome {
+                UsageOutcome::CacheHit => entry.cache_hits += 1,
+                UsageOutcome::Success => {
+                    entry.calls += 1;
+                    entry.latencies_ms.extend(event.latency_ms);
+                }
+                UsageOutcome::NormalizationFailure | UsageOutcome::ProviderFailure => {
+                    entry.calls += 1;
+                    entry.failures += 1;
+                }
+            }
+        }
+        for entry in usage.values_mut() {
+            entry.latencies_ms.sort_unstable();
+        }
+        usage
+    }
+
+    /// Nearest-rank percentile of successful call latency, `None` before any success.
+    pub fn latency_percentile(&self, percentile: u8) -> Option<u64> {
+        if self.latencies_ms.is_empty() {
+            return None;
+        }
+        let rank = (usize::from(percentile.min(100)) * self.latencies_ms.len()).div_ceil(100);
+        Some(self.latencies_ms[rank.saturating_sub(1)])
+    }
+
+    pub fn failure_rate(&self) -> f64 {
+        ratio(self.failures, self.calls)
+    }
+
+    /// Share of translations served from a cache rather than by calling the provider.
+    pub fn cache_hit_ratio(&self) -> f64 {
+        ratio(self.cache_hits, self.cache_hits + self.calls)
+    }
+}
+
+fn ratio(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 / whole as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ProviderUsage, UsageEvent, UsageLog, UsageOutcome};
+    use crate::Provider;
+    use std::time::Duration;
+
+    fn event(outcome: UsageOutcome, latency_ms: Option<u64>) -> UsageEvent {
+        UsageEvent::new(
+            &Provider::Ollama,
+            "qwen2.5-coder:7b",
+            outcome,
+            latency_ms.map(Duration::from_millis),
+        )
+    }
+
+    #[test]
+    fn aggregates_percentiles_failures_and_cache_hits() {
+        let mut events: Vec<UsageEvent> = (1..=10)
+            .rev()
+            .map(|n| event(UsageOutcome::Success, Some(n * 100)))
+            .collect();
+        events.push(event(UsageOutcome::ProviderFailure, Some(5_000)));
+        events.push(event(UsageOutcome::NormalizationFailure, Some(700)));
+        events.extend((0..4).map(|_| event(UsageOutcome::CacheHit, None)));
+
+        let usage = ProviderUsage::aggregate(&events);
+        let ollama = &usage["ollama"];
+        assert_eq!(ollama.calls, 12);
+        assert_eq!(ollama.failures, 2);
+        assert_eq!(ollama.cache_hits, 4);
+        assert_eq!(ollama.latency_percentile(50), Some(500));
+        assert_eq!(ollama.latency_percentile(90), Some(900));
+        assert_eq!(ollama.latency_percentile(99), Some(1_000));
+        assert_eq!(ollama.cache_hit_ratio(), 0.25);
+        assert_eq!(ProviderUsage::default().latency_percentile(50), None);
+    }
+
+    #[test]
+    fn log_appends_lines_and_skips_torn_ones() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let log = UsageLog {
+            path: dir.path().join("nested").join("metrics.jsonl"),
+        };
+        assert!(log.load().expect("missing log loads").is_empty());
+
+        log.record(&event(UsageOutcome::Success, Some(42)))
+            .expect("record should work");
+        log.record(&event(UsageOutcome::CacheHit, None))
+            .expect("record should work");
+        let mut raw = std::fs::read_to_string(&log.path).expect("read log");
+        assert_eq!(raw.lines().count(), 2);
+        assert!(raw.contains("\"outcome\":\"cache_hit\""));
+        raw.push_str("{\"at_ms\":1,\"provi");
+        std::fs::write(&log.path, raw).expect("write log");
+
+        let events = log.load().expect("log should load");
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].latency_ms, Some(42));
+        assert_eq!(events[1].latency_ms, None);
+
+        log.reset().expect("reset should work");
+        assert!(log.load().expect("reset log loads").is_empty());
+    }
+}
@@ -0,0 +1,132 @@
+//! Model answers seen in practice and the JavaScript `normalize_js_output` recovers from them.
+
+use insta::assert_snapshot;
+use klumo_llm::normalize_js_output;
+
+fn normalize(raw: &str) -> String {
+    normalize_js_output(raw)
+        .expect("normalize should pass")
+        .javascript
+}
+
+#[test]
+fn leading_introduction_before_fence() {
+    assert_snapshot!(normalize(
+        "Here is the JavaScript:\n\n```javascript\nconst total = [1, 2, 3].reduce((a, b) => a + b, 0);\nconsole.log(total);\n```"
+    ), @r"
+    const total = [1, 2, 3].reduce((a, b) => a + b, 0);
+    console.log(total);
+    ");
+}
+
+#[test]
+fn trailing_prose_after_closing_fence() {
+    assert_snapshot!(normalize(
+        "```js\nfor (let i = 0; i < 3; i++) {\n  console.log(i);\n}\n```\n\nThis loop prints 0, 1 and 2. Note that `range(3)` maps to a counted `for` loop."
+    ), @r"
+    for (let i = 0; i < 3; i++) {
+      console.log(i);
+    }
+    ");
+}
+
+#[test]
+fn unfenced_answer_with_introduction() {
+    assert_snapshot!(normalize(
+        "Sure! Here's the translated code:\n\nfunction greet(name) {\n  return `Hello, ${name}!`;\n}\nconsole.log(greet(\"klumo\"));"
+    ), @r#"
+    function greet(name) {
+      return `Hello, ${name}!`;
+    }
+    console.log(greet("klumo"));
+    "#);
+}
+
+#[test]
+fn byte_order_mark_and_zero_width_characters() {
+    assert_snapshot!(normalize(
+        "\u{feff}```js\nconst x\u{200b} = 1;\nconsole.log(x\u{200d});\n```\u{2060}"
+    ), @r"
+    const x = 1;
+    console.log(x);
+    ");
+}
+
+#[test]
+fn zero_width_characters_inside_literals_are_kept() {
+    let javascript = normalize(
+        "```js\nconst family = \"\u{1f468}\u{200d}\u{1f469}\";\u{200b}\nconst tag = `x\u{200b}${family\u{200b}.length}`; // it's \u{200b}\nconsole.log('\\'\u{200c}', tag);\n```",
+    );
+    assert_eq!(
+        javascript,
+        "const family = \"\u{1f468}\u{200d}\u{1f469}\";\nconst tag = `x\u{200b}${family.length}`; // it's \nconsole.log('\\'\u{200c}', tag);"
+    );
+}
+
+#[test]
+fn helper_and_usage_blocks_are_concatenated() {
+    assert_snapshot!(normalize(
+        "First, the helper:\n\n```js\nfunction square(n) {\n  return n * n;\n}\n```\n\nThen the main program:\n\n```js\nconsole.log(square(4));\n```\n\nRun it with `klumo run`."
+    ), @r"
+    function square(n) {
+      return n * n;
+    }
+
+    console.log(square(4));
+    ");
+}
+
+#[test]
+fn revised_block_replaces_draft() {
+    assert_snapshot!(normalize(
+        "A direct translation:\n\n```js\nfunction fib(n) {\n  return n < 2 ? n : fib(n - 1) + fib(n - 2);\n}\n```\n\nA faster version that avoids recursion:\n\n```js\nfunction fib(n) {\n  let [a, b] = [0, 1];\n  for (let i = 0; i < n; i++) [a, b] = [b, a + b];\n  return a;\n}\nconsole.log(fib(10));\n```"
+    ), @r"
+    function fib(n) {
+      let [a, b] = [0, 1];
+      for (let i = 0; i < n; i++) [a, b] = [b, a + b];
+      return a;
+    }
+    console.log(fib(10));
+    ");
+}
+
+#[test]
+fn repeated_snippet_is_not_duplicated() {
+    assert_snapshot!(normalize(
+        "The key line is:\n\n```js\nconsole.log(\"done\");\n```\n\nFull program:\n\n```js\nconst items = [\"a\", \"b\"];\nitems.forEach((item) => console.log(item));\nconsole.log(\"done\");\n```"
+    ), @r#"
+    const items = ["a", "b"];
+    items.forEach((item) => console.log(item));
+    console.log("done");
+    "#);
+}
+
+#[test]
+fn shell_and_output_blocks_are_skipped() {
+    assert_snapshot!(normalize(
+        "```javascript\nconsole.log(6 * 7);\n```\n\nRun it:\n\n```bash\nklumo run answer.js\n```\n\nOutput:\n\n```text\n42\n```"
+    ), @"console.log(6 * 7);");
+}
+
+#[test]
+fn truncated_answer_without_closing_fence() {
+    assert_snapshot!(normalize(
+        "```js\nconst names = [\"ada\", \"grace\"];\nconsole.log(names.join(\", \"));\n"
+    ), @r#"
+    const names = ["ada", "grace"];
+    console.log(names.join(", "));
+    "#);
+}
+
+#[test]
+fn stray_closing_fence_without_opening() {
+    assert_snapshot!(normalize("JavaScript:\nconsole.log(1);\n```"), @"console.log(1);");
+}
+
+#[test]
+fn code_that_resembles_prose_is_kept() {
+    assert_snapshot!(normalize("sureCount = 2;\nconsole.log(sureCount);"), @r"
+    sureCount = 2;
+    console.log(sureCount);
+    ");
+}
@@ -0,0 +1,243 @@
+use crate::{HostBinding, HostFunctionSpec, StdModule};
+use std::sync::OnceLock;
+use std::time::Instant;
+
+/// Host function receiving each console line, already formatted and indented. Engine-bound:
+/// the engine applies its [`OutputBudget`], keeps the line for the evaluation's output and
+/// hands it to its embedder as soon as the script prints it.
+pub const CONSOLE_WRITE: &str = "__klumo_console_write";
+
+/// Ends every truncation marker, pointing at the ways to get the full output.
+pub const OUTPUT_LIMIT_HINT: &str = "use --no-output-limit or redirect output to a file";
+
+/// Console output limits, counted in UTF-16 code units as JavaScript measures strings; `0`
+/// leaves a limit off. The total covers what was written since the last [`reset`], which
+/// engines call once per evaluation.
+///
+/// [`reset`]: OutputBudget::reset
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputBudget {
+    max_entry: usize,
+    max_total: usize,
+    used: usize,
+}
+
+impl OutputBudget {
+    pub fn new(max_entry_chars: usize, max_total_chars: usize) -> Self {
+        Self {
+            max_entry: max_entry_chars,
+            max_total: max_total_chars,
+            used: 0,
+        }
+    }
+
+    /// `line` as it may be printed: cut with a marker past the entry limit or the rest of the
+    /// total, or `None` once the total is spent. Markers do not count against the budget;
+    /// only the script's own text does.
+    pub fn admit(&mut self, line: &str) -> Option<String> {
+        if self.max_total > 0 && self.used >= self.max_total {
+            return None;
+        }
+        let length = utf16_len(line);
+        let (mut line, mut length) = (line.to_string(), length);
+        let mut marker = String::new();
+        if self.max_entry > 0 && length > self.max_entry {
+            let kept = cut(&line, self.max_entry);
+            let dropped = length - utf16_len(kept);
+            marker = format!(
+                "… [truncated {dropped} character{}; {OUTPUT_LIMIT_HINT}]",
+                if dropped == 1 { "" } else { "s" }
+            );
+            line = kept.to_string();
+            length = utf16_len(&line);
+        }
+        if self.max_total > 0 && self.used + length > self.max_total {
+            line = cut(&line, self.max_total - self.used).to_string();
+            marker = format!(
+                "… [output limit of {} characters reached, further console output is dropped; {OUTPUT_LIMIT_HINT}]",
+                self.max_total
+            );
+            self.used = self.max_total;
+        } else {
+            self.used += length;
+        }
+        line.push_str(&marker);
+        Some(line)
+    }
+
+    /// Starts a fresh total.
+    pub fn reset(&mut self) {
+        self.used = 0;
+    }
+}
+
+fn utf16_len(text: &str) -> usize {
+    text.chars().map(char::len_utf16).sum()
+}
+
+/// The longest prefix of `text` within `units` UTF-16 code units. Cuts never split a
+/// surrogate pair, which would print as U+FFFD.
+fn cut(text: &str, units: usize) -> &str {
+    let mut used = 0;
+    for (index, c) in text.char_indices() {
+        used += c.len_utf16();
+        if used > units {
+            return &text[..index];
+        }
+    }
+    text
+}
+
+/// Host function turning `console.log` arguments into one line of text with
+/// [`format_log_line`](crate::format_log_line). Engine-bound: only the engine can walk its
+/// values into [`Inspected`](crate::Inspected) trees.
+pub const CONSOLE_FORMAT: &str = "__klumo_console_format";
+
+/// Host function rendering `console.table(data, columns)` with
+/// [`render_table`](crate::render_table), or returning `undefined` when `data` has no rows.
+/// Engine-bound like [`CONSOLE_FORMAT`].
+pub const CONSOLE_TABLE: &str = "__klumo_console_table";
+
+/// Host function returning milliseconds on a monotonic clock, for `console.time`.
+pub const CONSOLE_NOW: &str = "__klumo_console_now";
+
+fn monotonic_ms(_args: &[String]) -> std::result::Result<Option<String>, String> {
+    static START: OnceLock<Instant> = OnceLock::new();
+    let elapsed = START.get_or_init(Instant::now).elapsed();
+    Ok(Some(format!("{:.3}", elapsed.as_secs_f64() * 1000.0)))
+}
+
+/// The `console` global. Values are formatted natively through [`CONSOLE_FORMAT`] and
+/// [`CONSOLE_TABLE`], and every line goes straight to [`CONSOLE_WRITE`], so nothing is
+/// buffered on the JavaScript side. The script only keeps the bookkeeping every backend
+/// shares: group indentation, counters and timers. It holds on to the host functions it was
+/// installed with, so scripts that overwrite those globals cannot divert or lose output.
+pub(crate) const CONSOLE: StdModule = StdModule {
+    name: "console",
+    host_functions: &[
+        HostFunctionSpec {
+            name: CONSOLE_WRITE,
+            arity: 1,
+            binding: HostBinding::Engine,
+        },
+        HostFunctionSpec {
+            name: CONSOLE_FORMAT,
+            arity: 0,
+            binding: HostBinding::Engine,
+        },
+        HostFunctionSpec {
+            name: CONSOLE_TABLE,
+            arity: 2,
+            binding: HostBinding::Engine,
+        },
+        HostFunctionSpec {
+            name: CONSOLE_NOW,
+            arity: 0,
+            binding: HostBinding::Shared(monotonic_ms),
+        },
+    ],
+    source: r#"
+globalThis.console = globalThis.console || {};
+(() => {
+  const write = globalThis.__klumo_console_write;
+  const format = globalThis.__klumo_console_format;
+  const table = globalThis.__klumo_console_table;
+  const clock = globalThis.__klumo_console_now;
+  const now = () => Number(clock());
+  const state = { indent: "", counts: new Map(), timers: new Map() };
+  const emit = (text) =>
+    write(state.indent ? state.indent + text.split("\n").join("\n" + state.indent) : text);
+  const log = (...args) => emit(format(...args));
+  const label = (value) => (value === undefined ? "default" : `${value}`);
+  const elapsed = (name) => `${name}: ${(now() - state.timers.get(name)).toFixed(3)}ms`;
+  Object.assign(globalThis.console, {
+    log,
+    info: log,
+    debug: log,
+    warn: log,
+    error: log,
+    table: (data, columns) => {
+      const text = table(data, columns);
+      if (text === undefined) log(data);
+      else emit(text);
+    },
+    group: (...args) => {
+      if (args.length) log(...args);
+      state.indent += "  ";
+    },
+    groupCollapsed: (...args) => globalThis.console.group(...args),
+    groupEnd: () => {
+      state.indent = state.indent.slice(2);
+    },
+    count: (name) => {
+      const key = label(name);
+      const count = (state.counts.get(key) || 0) + 1;
+      state.counts.set(key, count);
+      log(`${key}: ${count}`);
+    },
+    countReset: (name) => {
+      const key = label(name);
+      if (state.counts.has(key)) state.counts.set(key, 0);
+      else log(`Count for '${key}' does not exist`);
+    },
+    time: (name) => {
+      const key = label(name);
+      if (state.timers.has(key)) log(`Warning: Label '${key}' already exists for console.time()`);
+      else state.timers.set(key, now());
+    },
+    timeLog: (name, ...data) => {
+      const key = label(name);
+      if (state.timers.has(key)) log(elapsed(key), ...data);
+      else log(`Warning: No such label '${key}' for console.timeLog()`);
+    },
+    timeEnd: (name) => {
+      const key = label(name);
+      if (!state.timers.has(key)) {
+        log(`Warning: No such label '${key}' for console.timeEnd()`);
+        return;
+      }
+      log(elapsed(key));
+      state.timers.delete(key);
+    },
+    assert: (condition, ...data) => {
+      if (condition) return;
+      if (typeof data[0] === "string") log(`Assertion failed: ${data[0]}`, ...data.slice(1));
+      else log("Assertion failed", ...data);
+    },
+  });
+})();
+"#,
+    check: "typeof globalThis.console?.log === 'function' && typeof globalThis.__klumo_console_write === 'function' && typeof globalThis.__klumo_console_format === 'function' && typeof globalThis.__klumo_console_table === 'function' && typeof globalThis.__klumo_console_now === 'function'",
+};
+
+#[cfg(test)]
+mod tests {
+    use super::{OUTPUT_LIMIT_HINT, OutputBudget};
+
+    #[test]
+    fn budget_cuts_entries_and_stops_at_the_total() {
+        let mut budget = OutputBudget::new(4, 6);
+        assert_eq!(
+            budget.admit("abcdef").as_deref(),
+            Some(format!("abcd… [truncated 2 characters; {OUTPUT_LIMIT_HINT}]").as_str())
+        );
+        // A surrogate pair counts as two units and is never split.
+        assert_eq!(
+            budget.admit("x🦀").as_deref(),
+            Some(
+                format!(
+                    "x… [output limit of 6 characters reached, further console output is dropped; {OUTPUT_LIMIT_HINT}]"
+                )
+                .as_str()
+            )
+        );
+        assert_eq!(budget.admit("dropped"), None);
+
+        budget.reset();
+        assert_eq!(budget.admit("ok").as_deref(), Some("ok"));
+        assert_eq!(
+            OutputBudget::default().admit("unlimited").as_deref(),
+            Some("unlimited")
+        );
+    }
+}
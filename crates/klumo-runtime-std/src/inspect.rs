@@ -0,0 +1,496 @@
+/// Nesting levels console output expands; objects deeper than that print as `[Object]`.
+pub const INSPECT_DEPTH: usize = 2;
+
+/// Items of an array, map or set console output lists before `... N more items`.
+pub const INSPECT_MAX_ITEMS: usize = 100;
+
+/// Width past which an inspected object is spread over one line per entry.
+const LINE_WIDTH: usize = 72;
+
+/// A JavaScript value as console output shows it. Engines build it from their own values
+/// (see `CONSOLE_FORMAT`), so every backend prints objects the same way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Inspected {
+    /// A string: bare at the top level of a line, quoted inside objects.
+    Text(String),
+    /// Any other primitive, as JavaScript renders it (`1`, `-0`, `true`, `undefined`,
+    /// `10n`, `Symbol(a)`).
+    Literal(String),
+    /// A function and its name, if it has one.
+    Function(Option<String>),
+    /// An array's first items and how many more it has.
+    Array {
+        items: Vec<Inspected>,
+        more: usize,
+    },
+    /// An object's own enumerable string-keyed properties; `class` names a constructor
+    /// other than `Object`, or says the object has no prototype.
+    Object {
+        class: Option<String>,
+        entries: Vec<(String, Inspected)>,
+    },
+    Map {
+        entries: Vec<(Inspected, Inspected)>,
+        more: usize,
+    },
+    Set {
+        items: Vec<Inspected>,
+        more: usize,
+    },
+    /// An error, as its `stack` or `Name: message`.
+    Error(String),
+    /// A promise and, once it settled, its result.
+    Promise {
+        result: Option<Box<Inspected>>,
+        rejected: bool,
+    },
+    /// A value printed as fixed text: a date, a regular expression, or an accessor property
+    /// (`[Getter]`).
+    Special(String),
+    /// An object nested deeper than [`INSPECT_DEPTH`], printed as `[<class>]`.
+    Elided(String),
+    /// An object that contains itself, at the point it recurs.
+    Circular,
+}
+
+/// One console line from the arguments of `console.log`: a leading string with `%s`, `%d`,
+/// `%i`, `%f`, `%o`, `%O`, `%c` or `%%` consumes arguments like Node's `util.format`, and
+/// the rest are inspected and joined with spaces.
+pub fn format_log_line(args: &[Inspected]) -> String {
+    let mut rest = args.iter();
+    let mut parts = Vec::with_capacity(args.len());
+    if let Some(Inspected::Text(template)) = args.first()
+        && template.contains('%')
+    {
+        rest.next();
+        parts.push(apply_format(template, &mut rest));
+    }
+    parts.extend(rest.map(top_level));
+    parts.join(" ")
+}
+
+/// `value` as `console.log` prints it on its own.
+fn top_level(value: &Inspected) -> String {
+    match value {
+        Inspected::Text(text) => text.clone(),
+        Inspected::Error(text) => text.clone(),
+        other => inspect(other, 0),
+    }
+}
+
+fn apply_format<'a>(template: &str, args: &mut impl Iterator<Item = &'a Inspected>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let Some(&spec) = chars.peek() else {
+            out.push('%');
+            break;
+        };
+        if spec == '%' {
+            chars.next();
+            out.push('%');
+            continue;
+        }
+        if !"sdifoOc".contains(spec) {
+            out.push('%');
+            continue;
+        }
+        let Some(arg) = args.next() else {
+            out.push('%');
+            continue;
+        };
+        chars.next();
+        match spec {
+            's' => out.push_str(&top_level(arg)),
+            'd' | 'i' | 'f' => {
+                let number = number_of(arg);
+                let number = if spec == 'i' { number.trunc() } else { number };
+                out.push_str(&js_number(number));
+            }
+            'o' | 'O' => out.push_str(&inspect(arg, 0)),
+            _ => {} // %c styles the browser console and prints nothing here.
+        }
+    }
+    out
+}
+
+fn number_of(value: &Inspected) -> f64 {
+    match value {
+        Inspected::Text(text) | Inspected::Literal(text) => {
+            let text = text.trim().trim_end_matches('n');
+            match text {
+                "" | "null" | "false" => 0.0,
+                "true" => 1.0,
+                "Infinity" => f64::INFINITY,
+                "-Infinity" => f64::NEG_INFINITY,
+                _ => text.parse().unwrap_or(f64::NAN),
+            }
+        }
+        _ => f64::NAN,
+    }
+}
+
+/// `number` as JavaScript prints it, for the numbers `%d`/`%i`/`%f` produce.
+fn js_number(number: f64) -> String {
+    match number {
+        n if n.is_nan() => "NaN".to_string(),
+        f64::INFINITY => "Infinity".to_string(),
+        f64::NEG_INFINITY => "-Infinity".to_string(),
+        n => n.to_string(),
+    }
+}
+
+/// `value` nested in an object printed at `indent` columns.
+fn inspect(value: &Inspected, indent: usize) -> String {
+    match value {
+        Inspected::Text(text) => quote(text),
+        Inspected::Literal(text) | Inspected::Special(text) => text.clone(),
+        Inspected::Function(Some(name)) => format!("[Function: {name}]"),
+        Inspected::Function(None) => "[Function (anonymous)]".to_string(),
+        Inspected::Error(text) => format!("[{}]", text.lines().next().unwrap_or_default()),
+        Inspected::Elided(class) => format!("[{class}]"),
+        Inspected::Circular => "[Circular]".to_string(),
+        Inspected::Promise { result, rejected } => {
+            let state = match result {
+                None => "<pending>".to_string(),
+                Some(result) if *rejected => format!("<rejected> {}", inspect(result, indent + 2)),
+                Some(result) => inspect(result, indent + 2),
+            };
+            format!("Promise {{ {state} }}")
+        }
+        Inspected::Array { items, more } => {
+            let parts = listed(items.iter().map(|item| inspect(item, indent + 2)), *more);
+            wrap("[", &parts, "]", indent)
+        }
+        Inspected::Object { class, entries } => {
+            let parts: Vec<String> = entries
+                .iter()
+                .map(|(key, value)| {
+                    format!("{}: {}", property_key(key), inspect(value, indent + 2))
+                })
+                .collect();
+            let body = wrap("{", &parts, "}", indent);
+            match class {
+                Some(class) => format!("{class} {body}"),
+                None => body,
+            }
+        }
+        Inspected::Map { entries, more } => {
+            let parts = listed(
+                entries.iter().map(|(key, value)| {
+                    format!(
+                        "{} => {}",
+                        inspect(key, indent + 2),
+                        inspect(value, indent + 2)
+                    )
+                }),
+                *more,
+            );
+            format!(
+                "Map({}) {}",
+                entries.len() + more,
+                wrap("{", &parts, "}", indent)
+            )
+        }
+        Inspected::Set { items, more } => {
+            let parts = listed(items.iter().map(|item| inspect(item, indent + 2)), *more);
+            format!(
+                "Set({}) {}",
+                items.len() + more,
+                wrap("{", &parts, "}", indent)
+            )
+        }
+    }
+}
+
+fn listed(parts: impl Iterator<Item = String>, more: usize) -> Vec<String> {
+    let mut parts: Vec<String> = parts.collect();
+    match more {
+        0 => {}
+        1 => parts.push("... 1 more item".to_string()),
+        more => parts.push(format!("... {more} more items")),
+    }
+    parts
+}
+
+/// `{ a, b }` on one line when it fits, otherwise one part per line indented past `indent`.
+fn wrap(open: &str, parts: &[String], close: &str, indent: usize) -> String {
+    if parts.is_empty() {
+        return format!("{open}{close}");
+    }
+    let single = format!("{open} {} {close}", parts.join(", "));
+    if indent + single.chars().count() <= LINE_WIDTH && !single.contains('\n') {
+        return single;
+    }
+    let pad = " ".repeat(indent + 2);
+    format!(
+        "{open}\n{pad}{}\n{}{close}",
+        parts.join(&format!(",\n{pad}")),
+        " ".repeat(indent)
+    )
+}
+
+/// Single quotes unless the text contains some and no double quotes, like Node.
+fn quote(text: &str) -> String {
+    let delimiter = if text.contains('\'') && !text.contains('"') {
+        '"'
+    } else {
+        '\''
+    };
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push(delimiter);
+    for c in text.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\\' => out.push_str("\\\\"),
+            c if c == delimiter => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push(delimiter);
+    out
+}
+
+/// Keys that are not identifiers print quoted.
+fn property_key(key: &str) -> String {
+    let mut chars = key.chars();
+    let identifier = chars
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$');
+    if identifier || (!key.is_empty() && key.chars().all(|c| c.is_ascii_digit())) {
+        key.to_string()
+    } else {
+        quote(key)
+    }
+}
+
+/// `console.table(data, columns)`: a box-drawn table with one row per array item or object
+/// property of `data` and one column per property of the rows (a `Values` column for rows
+/// that are not objects), limited to `columns` when given. `None` when `data` has no rows to
+/// tabulate, in which case `console.table` logs it instead.
+pub fn render_table(data: &Inspected, columns: Option<&[String]>) -> Option<String> {
+    let rows: Vec<(String, &Inspected)> = match data {
+        Inspected::Array { items, .. } => items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| (index.to_string(), item))
+            .collect(),
+        Inspected::Object { entries, .. } => entries
+            .iter()
+            .map(|(key, value)| (key.clone(), value))
+            .collect(),
+        _ => return None,
+    };
+
+    let fields = |row: &Inspected| -> Option<Vec<(String, Inspected)>> {
+        match row {
+            Inspected::Object { entries, .. } => Some(entries.clone()),
+            Inspected::Array { items, .. } => Some(
+                items
+                    .iter()
+                    .enumerate()
+                    .map(|(index, item)| (index.to_string(), item.clone()))
+                    .collect(),
+            ),
+            _ => None,
+        }
+    };
+    let mut names: Vec<String> = match columns {
+        Some(columns) => columns.to_vec(),
+        None => Vec::new(),
+    };
+    let mut has_values = false;
+    for (_, row) in &rows {
+        match fields(row) {
+            Some(entries) if columns.is_none() => {
+                for (key, _) in entries {
+                    if !names.contains(&key) {
+                        names.push(key);
+                    }
+                }
+            }
+            Some(_) => {}
+            None => has_values = true,
+        }
+    }
+
+    let mut header = vec!["(index)".to_string()];
+    header.extend(names.iter().cloned());
+    if has_values {
+        header.push("Values".to_string());
+    }
+    let body: Vec<Vec<String>> = rows
+        .iter()
+        .map(|(index, row)| {
+            let entries = fields(row);
+            let mut cells = vec![index.clone()];
+            cells.extend(names.iter().map(|name| {
+                entries
+                    .as_ref()
+                    .and_then(|entries| entries.iter().find(|(key, _)| key == name))
+                    .map(|(_, value)| inspect(value, 0))
+                    .unwrap_or_default()
+            }));
+            if has_values {
+                cells.push(match entries {
+                    Some(_) => String::new(),
+                    None => inspect(row, 0),
+                });
+            }
+            cells
+        })
+        .collect();
+
+    let widths: Vec<usize> = (0..header.len())
+        .map(|column| {
+            body.iter()
+                .chain([&header])
+                .map(|cells| cells[column].chars().count())
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+    let rule = |left: &str, middle: &str, right: &str| {
+        let segments: Vec<String> = widths.iter().map(|width| "─".repeat(width + 2)).collect();
+        format!("{left}{}{right}", segments.join(middle))
+    };
+    let line = |cells: &[String]| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!(" {cell}{} ", " ".repeat(width - cell.chars().count())))
+            .collect();
+        format!("│{}│", padded.join("│"))
+    };
+    let mut lines = vec![rule("┌", "┬", "┐"), line(&header), rule("├", "┼", "┤")];
+    lines.extend(body.iter().map(|cells| line(cells)));
+    lines.push(rule("└", "┴", "┘"));
+    Some(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Inspected, format_log_line, render_table};
+
+    fn text(value: &str) -> Inspected {
+        Inspected::Text(value.to_string())
+    }
+
+    fn literal(value: &str) -> Inspected {
+        Inspected::Literal(value.to_string())
+    }
+
+    fn object(entries: &[(&str, Inspected)]) -> Inspected {
+        Inspected::Object {
+            class: None,
+            entries: entries
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.clone()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn objects_print_like_node() {
+        let user = object(&[
+            ("name", text("Ada")),
+            (
+                "tags",
+                Inspected::Array {
+                    items: vec![text("math"), literal("1")],
+                    more: 0,
+                },
+            ),
+            ("first-seen", Inspected::Elided("Object".to_string())),
+            ("greet", Inspected::Function(Some("greet".to_string()))),
+        ]);
+        assert_eq!(
+            format_log_line(&[text("user"), user]),
+            "user {\n  name: 'Ada',\n  tags: [ 'math', 1 ],\n  'first-seen': [Object],\n  greet: [Function: greet]\n}"
+        );
+        assert_eq!(
+            format_log_line(&[
+                Inspected::Object {
+                    class: Some("Point".to_string()),
+                    entries: vec![("x".to_string(), literal("1"))],
+                },
+                Inspected::Map {
+                    entries: vec![(text("a"), literal("1"))],
+                    more: 0,
+                },
+                Inspected::Set {
+                    items: vec![literal("1")],
+                    more: 2,
+                },
+                Inspected::Array {
+                    items: Vec::new(),
+                    more: 0,
+                },
+                object(&[("self", Inspected::Circular)]),
+                text("it's"),
+            ]),
+            "Point { x: 1 } Map(1) { 'a' => 1 } Set(3) { 1, ... 2 more items } [] { self: [Circular] } it's"
+        );
+        assert_eq!(
+            format_log_line(&[object(&[("quote", text("it's"))])]),
+            "{ quote: \"it's\" }"
+        );
+    }
+
+    #[test]
+    fn format_specifiers_consume_arguments() {
+        assert_eq!(
+            format_log_line(&[
+                text("%s has %d items (%i%%) %o%c"),
+                text("cart"),
+                text("3"),
+                literal("42.9"),
+                object(&[("a", literal("1"))]),
+                text("color: red"),
+                literal("true"),
+            ]),
+            "cart has 3 items (42%) { a: 1 } true"
+        );
+        assert_eq!(format_log_line(&[text("100% %s")]), "100% %s");
+        assert_eq!(format_log_line(&[text("%d"), text("x")]), "NaN");
+    }
+
+    #[test]
+    fn tables_have_one_column_per_property() {
+        let rows = Inspected::Array {
+            items: vec![
+                object(&[("name", text("a")), ("size", literal("1"))]),
+                object(&[("name", text("bb")), ("extra", literal("true"))]),
+                literal("7"),
+            ],
+            more: 0,
+        };
+        assert_eq!(
+            render_table(&rows, None).expect("arrays tabulate"),
+            "┌─────────┬──────┬──────┬───────┬────────┐\n\
+             │ (index) │ name │ size │ extra │ Values │\n\
+             ├─────────┼──────┼──────┼───────┼────────┤\n\
+             │ 0       │ 'a'  │ 1    │       │        │\n\
+             │ 1       │ 'bb' │      │ true  │        │\n\
+             │ 2       │      │      │       │ 7      │\n\
+             └─────────┴──────┴──────┴───────┴────────┘"
+        );
+        assert_eq!(
+            render_table(&rows, Some(&["size".to_string()]))
+                .expect("arrays tabulate")
+                .lines()
+                .nth(1),
+            Some("│ (index) │ size │ Values │")
+        );
+        assert_eq!(render_table(&literal("1"), None), None);
+    }
+}
@@ -0,0 +1,215 @@
+mod console;
+mod inspect;
+mod timers;
+
+pub use console::{
+    CONSOLE_FORMAT, CONSOLE_NOW, CONSOLE_TABLE, CONSOLE_WRITE, OUTPUT_LIMIT_HINT, OutputBudget,
+};
+pub use inspect::{INSPECT_DEPTH, INSPECT_MAX_ITEMS, Inspected, format_log_line, render_table};
+pub use timers::{CLEAR_TIMERS, RUN_TIMER, TIMER_CANCEL, TIMER_SCHEDULE};
+
+use anyhow::{Result, anyhow, bail};
+
+/// A host capability callable from scripts. It receives its arguments converted to strings
+/// and returns a string, `None` for `undefined`, or a message thrown as a JavaScript `Error`.
+pub type HostFunction = fn(&[String]) -> std::result::Result<Option<String>, String>;
+
+/// Who implements a host function the standard library calls.
+#[derive(Debug, Clone, Copy)]
+pub enum HostBinding {
+    /// Implemented here, once, with the engine-neutral [`HostFunction`] signature; backends
+    /// only register it.
+    Shared(HostFunction),
+    /// Needs state only the engine or its embedder has, such as where console lines go, so
+    /// every backend implements it under the spec's name.
+    Engine,
+}
+
+/// A global function the JavaScript side of a module calls into the host through.
+#[derive(Debug, Clone, Copy)]
+pub struct HostFunctionSpec {
+    pub name: &'static str,
+    pub arity: usize,
+    pub binding: HostBinding,
+}
+
+/// One part of the standard library: the host functions it needs, the script defining its
+/// globals on top of them, and an expression that is `true` while it is usable.
+#[derive(Debug, Clone, Copy)]
+pub struct StdModule {
+    pub name: &'static str,
+    pub host_functions: &'static [HostFunctionSpec],
+    pub source: &'static str,
+    pub check: &'static str,
+}
+
+/// Every module, in install order.
+pub const MODULES: &[StdModule] = &[console::CONSOLE, timers::TIMERS];
+
+/// What a JavaScript engine implements to receive the standard library. [`install`] walks
+/// [`MODULES`] through it, so a module added here reaches every backend, and a backend
+/// missing one of its engine-bound host functions fails to start instead of running without
+/// the API.
+pub trait StdBackend {
+    /// Exposes `function` to scripts as the global function `name`.
+    fn register_shared(&mut self, name: &str, arity: usize, function: HostFunction) -> Result<()>;
+
+    /// Exposes the backend's own implementation of the engine-bound host function `name`.
+    /// Names the backend does not implement must fail.
+    fn register_engine_bound(&mut self, name: &str, arity: usize) -> Result<()>;
+
+    /// Runs a module's setup script at global scope.
+    fn run_setup(&mut self, source: &str, module: &str) -> Result<()>;
+
+    /// Evaluates `expression` and converts the result to a boolean.
+    fn eval_bool(&mut self, expression: &str) -> Result<bool>;
+}
+
+/// Registers each module's host functions, runs its setup script, then checks every module.
+pub fn install(backend: &mut dyn StdBackend) -> Result<()> {
+    for module in MODULES {
+        for spec in module.host_functions {
+            match spec.binding {
+                HostBinding::Shared(function) => {
+                    backend.register_shared(spec.name, spec.arity, function)
+                }
+                HostBinding::Engine => backend.register_engine_bound(spec.name, spec.arity),
+            }
+            .map_err(|err| {
+                anyhow!(
+                    "failed installing host function {} for the {} module: {err:#}",
+                    spec.name,
+                    module.name
+                )
+            })?;
+        }
+        backend
+            .run_setup(module.source, module.name)
+            .map_err(|err| anyhow!("failed installing the {} module: {err:#}", module.name))?;
+    }
+    check(backend)
+}
+
+/// Fails naming the first module whose check is not `true`, e.g. because a script replaced
+/// its globals.
+pub fn check(backend: &mut dyn StdBackend) -> Result<()> {
+    for module in MODULES {
+        let healthy = backend
+            .eval_bool(module.check)
+            .map_err(|err| anyhow!("{} self-check failed: {err:#}", module.name))?;
+        if !healthy {
+            bail!(
+                "{} self-check failed: its globals or host functions are missing",
+                module.name
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HostBinding, HostFunction, MODULES, StdBackend, check, install};
+    use anyhow::{Result, bail};
+
+    /// Records what `install` asks for; only implements the engine-bound functions in `knows`.
+    #[derive(Default)]
+    struct Recorder {
+        knows: Vec<&'static str>,
+        calls: Vec<String>,
+        healthy: bool,
+    }
+
+    impl StdBackend for Recorder {
+        fn register_shared(
+            &mut self,
+            name: &str,
+            _arity: usize,
+            _function: HostFunction,
+        ) -> Result<()> {
+            self.calls.push(format!("shared {name}"));
+            Ok(())
+        }
+
+        fn register_engine_bound(&mut self, name: &str, _arity: usize) -> Result<()> {
+            if !self.knows.contains(&name) {
+                bail!("no implementation of {name}");
+            }
+            self.calls.push(format!("engine {name}"));
+            Ok(())
+        }
+
+        fn run_setup(&mut self, _source: &str, module: &str) -> Result<()> {
+            self.calls.push(format!("setup {module}"));
+            Ok(())
+        }
+
+        fn eval_bool(&mut self, _expression: &str) -> Result<bool> {
+            Ok(self.healthy)
+        }
+    }
+
+    fn engine_bound() -> Vec<&'static str> {
+        MODULES
+            .iter()
+            .flat_map(|module| module.host_functions)
+            .filter(|spec| matches!(spec.binding, HostBinding::Engine))
+            .map(|spec| spec.name)
+            .collect()
+    }
+
+    #[test]
+    fn host_functions_are_registered_before_their_module_runs() {
+        let mut backend = Recorder {
+            knows: engine_bound(),
+            healthy: true,
+            ..Recorder::default()
+        };
+        install(&mut backend).expect("install should succeed");
+        assert_eq!(
+            backend.calls,
+            vec![
+                "engine __klumo_console_write",
+                "engine __klumo_console_format",
+                "engine __klumo_console_table",
+                "shared __klumo_console_now",
+                "setup console",
+                "engine __klumo_timer_schedule",
+                "engine __klumo_timer_cancel",
+                "setup timers"
+            ]
+        );
+    }
+
+    #[test]
+    fn backends_missing_a_host_function_fail_to_install() {
+        let mut backend = Recorder {
+            healthy: true,
+            ..Recorder::default()
+        };
+        let err = install(&mut backend).expect_err("console writer is missing");
+        assert!(err.to_string().contains("__klumo_console_write"));
+
+        let mut unhealthy = Recorder::default();
+        assert!(
+            check(&mut unhealthy)
+                .expect_err("check should fail")
+                .to_string()
+                .contains("console self-check failed")
+        );
+    }
+
+    #[test]
+    fn modules_use_the_host_functions_they_declare() {
+        for module in MODULES {
+            for spec in module.host_functions {
+                assert!(
+                    module.source.contains(spec.name) && module.check.contains(spec.name),
+                    "{} declares {} without calling and checking it",
+                    module.name,
+                    spec.name
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use crate::{HostBinding, HostFunctionSpec, StdModule};
+
+/// Host function scheduling timer `id` to fall due after `delay` milliseconds. Engine-bound:
+/// the engine's event loop keeps the due times and calls [`RUN_TIMER`] with each id in turn.
+pub const TIMER_SCHEDULE: &str = "__klumo_timer_schedule";
+
+/// Host function dropping timer `id` from the engine's event loop. Engine-bound like
+/// [`TIMER_SCHEDULE`].
+pub const TIMER_CANCEL: &str = "__klumo_timer_cancel";
+
+/// Global function the engine calls with the id of a timer that fell due. It runs the
+/// callback, reschedules intervals, and ignores ids that were cleared in the meantime.
+pub const RUN_TIMER: &str = "__klumo_run_timer";
+
+/// Global function the engine calls to drop every timer callback, along with the due times
+/// it drops itself, e.g. when a pooled engine moves on to an unrelated request.
+pub const CLEAR_TIMERS: &str = "__klumo_clear_timers";
+
+/// `setTimeout`, `setInterval`, `clearTimeout`, `clearInterval` and `queueMicrotask`. The
+/// callbacks stay on the JavaScript side; engines only order timer ids by due time, so
+/// every backend shares the argument handling and interval bookkeeping. Microtasks are
+/// promise jobs, so they run in order with `then` callbacks.
+pub(crate) const TIMERS: StdModule = StdModule {
+    name: "timers",
+    host_functions: &[
+        HostFunctionSpec {
+            name: TIMER_SCHEDULE,
+            arity: 2,
+            binding: HostBinding::Engine,
+        },
+        HostFunctionSpec {
+            name: TIMER_CANCEL,
+            arity: 1,
+            binding: HostBinding::Engine,
+        },
+    ],
+    source: r#"
+(() => {
+  const schedule = globalThis.__klumo_timer_schedule;
+  const cancel = globalThis.__klumo_timer_cancel;
+  const resolved = Promise.resolve();
+  const timers = new Map();
+  let nextId = 1;
+  const callable = (callback) => {
+    if (typeof callback !== "function") {
+      throw new TypeError(`The "callback" argument must be of type function. Received ${typeof callback}`);
+    }
+  };
+  // Like Node, delays that are not numbers from 1 to 2^31 - 1 become 1.
+  const delayOf = (delay) => {
+    const ms = Number(delay);
+    return ms >= 1 && ms <= 0x7fffffff ? ms : 1;
+  };
+  const start = (callback, delay, args, repeat) => {
+    callable(callback);
+    const id = nextId++;
+    const ms = delayOf(delay);
+    timers.set(id, { callback, args, interval: repeat ? ms : undefined });
+    schedule(id, ms);
+    return id;
+  };
+  const clear = (id) => {
+    if (timers.delete(Number(id))) cancel(Number(id));
+  };
+  Object.assign(globalThis, {
+    setTimeout: (callback, delay, ...args) => start(callback, delay, args, false),
+    setInterval: (callback, delay, ...args) => start(callback, delay, args, true),
+    clearTimeout: clear,
+    clearInterval: clear,
+    queueMicrotask: (callback) => {
+      callable(callback);
+      resolved.then(() => callback());
+    },
+  });
+  globalThis.__klumo_run_timer = (id) => {
+    const timer = timers.get(id);
+    if (!timer) return;
+    if (timer.interval === undefined) {
+      timers.delete(id);
+      timer.callback(...timer.args);
+      return;
+    }
+    timer.callback(...timer.args);
+    if (timers.has(id)) schedule(id, timer.interval);
+  };
+  globalThis.__klumo_clear_timers = () => timers.clear();
+})();
+"#,
+    check: "typeof globalThis.setTimeout === 'function' && typeof globalThis.setInterval === 'function' && typeof globalThis.clearTimeout === 'function' && typeof globalThis.queueMicrotask === 'function' && typeof globalThis.__klumo_timer_schedule === 'function' && typeof globalThis.__klumo_timer_cancel === 'function' && typeof globalThis.__klumo_run_timer === 'function' && typeof globalThis.__klumo_clear_timers === 'function'",
+};